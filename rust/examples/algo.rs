@@ -10,8 +10,7 @@ use hftbacktest::{
         btreemarketdepth::BTreeMarketDepth,
         hashmapmarketdepth::HashMapMarketDepth,
         MarketDepth,
-        INVALID_MAX,
-        INVALID_MIN,
+        Tick,
     },
     get_precision,
     ty::{OrdType, Side, TimeInForce},
@@ -42,7 +41,9 @@ where
         let depth = hbt.depth(0);
         let position = hbt.position(0);
 
-        if depth.best_bid_tick() == INVALID_MIN || depth.best_ask_tick() == INVALID_MAX {
+        if depth.best_bid_tick() == MD::Tick::INVALID_MIN
+            || depth.best_ask_tick() == MD::Tick::INVALID_MAX
+        {
             // Market depth is incomplete.
             continue;
         }
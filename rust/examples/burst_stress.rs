@@ -0,0 +1,96 @@
+use std::time::Instant;
+
+use hftbacktest::{
+    depth::{MarketDepth, INVALID_MAX, INVALID_MIN},
+    testing::{Scenario, TradeSide},
+    ty::{OrdType, Status, TimeInForce},
+    Interface,
+};
+
+const TICK_SIZE: f32 = 0.1;
+
+fn main() {
+    tracing_subscriber::fmt::init();
+
+    // Each burst packs thousands of depth updates into a millisecond.
+    let mut scenario = Scenario::new(TICK_SIZE, 1.0)
+        .feed_latency(100_000)
+        .order_latency(200_000, 200_000)
+        .book(
+            &[(99.9, 10.0), (99.8, 10.0), (99.7, 10.0)],
+            &[(100.0, 10.0), (100.1, 10.0), (100.2, 10.0)],
+        );
+    for _ in 0..100 {
+        scenario = scenario
+            .advance(10_000_000)
+            .stuff(99.9, 100.0, 1.0, 20, 5_000, 1_000_000)
+            .flip(99.9, 100.0, 10.0, 2_000, 1_000_000)
+            .trade(TradeSide::Sell, 99.9, 5.0)
+            .trade(TradeSide::Buy, 100.0, 5.0);
+    }
+    let num_rows = scenario.rows().len();
+    let mut hbt = scenario.backtest().unwrap();
+
+    // Requotes at the best on every change of the best, which is the worst case under the
+    // rapid best flips.
+    let start = Instant::now();
+    let mut num_wakeups = 0;
+    let mut num_orders = 0;
+    let mut order_id = 0;
+    let mut quoted = (INVALID_MIN, INVALID_MAX);
+    while hbt.wait_next_feed(true, 1_000_000_000).unwrap() {
+        num_wakeups += 1;
+        let depth = hbt.depth(0);
+        let best = (depth.best_bid_tick(), depth.best_ask_tick());
+        if best == quoted || best.0 == INVALID_MIN || best.1 == INVALID_MAX {
+            continue;
+        }
+        let (bid, ask) = (depth.best_bid(), depth.best_ask());
+        let working: Vec<i64> = hbt
+            .orders(0)
+            .values()
+            .filter(|order| order.status == Status::New && order.req == Status::None)
+            .map(|order| order.order_id)
+            .collect();
+        for order_id in working {
+            hbt.cancel(0, order_id, false).unwrap();
+        }
+        order_id += 1;
+        hbt.submit_buy_order(
+            0,
+            order_id,
+            bid,
+            1.0,
+            TimeInForce::GTX,
+            OrdType::Limit,
+            false,
+        )
+        .unwrap();
+        order_id += 1;
+        hbt.submit_sell_order(
+            0,
+            order_id,
+            ask,
+            1.0,
+            TimeInForce::GTX,
+            OrdType::Limit,
+            false,
+        )
+        .unwrap();
+        num_orders += 2;
+        quoted = best;
+        hbt.clear_inactive_orders(Some(0));
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let state_values = hbt.state_values(0);
+    hbt.close().unwrap();
+
+    println!("rows: {num_rows}");
+    println!("wakeups: {num_wakeups}");
+    println!("orders: {num_orders}");
+    println!("trades: {}", state_values.trade_num);
+    println!("position: {}", state_values.position);
+    println!("elapsed: {elapsed:.3} s");
+    println!("throughput: {:.0} rows/s", num_rows as f64 / elapsed);
+    println!("wakeup rate: {:.0} wakeups/s", num_wakeups as f64 / elapsed);
+}
@@ -2,7 +2,7 @@ use hftbacktest::{
     backtest::{
         assettype::LinearAsset,
         backtest::MultiAssetMultiExchangeBacktest,
-        models::{IntpOrderLatency, PowerProbQueueFunc3, ProbQueueModel, QueuePos},
+        models::{IntpOrderLatency, OutOfRange, PowerProbQueueFunc3, ProbQueueModel, QueuePos},
         reader::read_npz,
         BtAssetBuilder,
         BtBuilder,
@@ -17,7 +17,8 @@ mod algo;
 use algo::gridtrading;
 
 fn prepare_backtest() -> MultiAssetMultiExchangeBacktest<QueuePos, HashMapMarketDepth> {
-    let latency_model = IntpOrderLatency::new(read_npz("latency_20240215.npz").unwrap());
+    let latency_model =
+        IntpOrderLatency::new(read_npz("latency_20240215.npz").unwrap(), OutOfRange::Clamp);
     let asset_type = LinearAsset::new(1.0);
     let queue_model = ProbQueueModel::new(PowerProbQueueFunc3::new(3.0));
 
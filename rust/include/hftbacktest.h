@@ -0,0 +1,125 @@
+/*
+ * The C ABI of the backtester, exported by the library built with the `ffi` feature, such as by
+ * `cargo rustc --profile release-ffi --features ffi --crate-type cdylib`, whose profile unwinds
+ * a panic so that it can be caught. See `src/ffi.rs` for the details, including the JSON
+ * configuration of `hbt_create`.
+ *
+ * The functions that can fail return -1 on failure, whose reason is then returned by
+ * `hbt_last_error`. Those that return a flag return 1 for true and 0 for false, and the others
+ * return 0 on success. A panic is caught at the boundary and fails the function in the same way.
+ */
+#ifndef HFTBACKTEST_H
+#define HFTBACKTEST_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define HBT_BUY 1
+#define HBT_SELL -1
+
+#define HBT_GTC 0
+#define HBT_GTX 1
+#define HBT_FOK 2
+#define HBT_IOC 3
+
+#define HBT_LIMIT 0
+#define HBT_MARKET 1
+#define HBT_STOP_MARKET 2
+#define HBT_STOP_LIMIT 3
+#define HBT_TAKE_PROFIT_MARKET 4
+#define HBT_TAKE_PROFIT_LIMIT 5
+
+#define HBT_NONE 0
+#define HBT_NEW 1
+#define HBT_EXPIRED 2
+#define HBT_FILLED 3
+#define HBT_CANCELED 4
+#define HBT_PARTIALLY_FILLED 5
+#define HBT_REJECTED 6
+#define HBT_TOO_MANY_OPEN_ORDERS 7
+#define HBT_REPLACED 8
+
+typedef struct HftBacktest HftBacktest;
+
+typedef struct {
+    int64_t order_id;
+    float price;
+    float qty;
+    /* The trigger price of a stop or take-profit order, ignored for the other order types. */
+    float trigger_price;
+    int8_t side;
+    uint8_t time_in_force;
+    uint8_t order_type;
+} HbtOrderRequest;
+
+typedef struct {
+    int64_t best_bid_tick;
+    int64_t best_ask_tick;
+    float best_bid;
+    float best_ask;
+    float best_bid_qty;
+    float best_ask_qty;
+    float tick_size;
+    float lot_size;
+} HbtDepth;
+
+typedef struct {
+    double position;
+    double balance;
+    double fee;
+    int32_t trade_num;
+    double trade_qty;
+    double trade_amount;
+} HbtStateValues;
+
+typedef struct {
+    int64_t order_id;
+    int64_t exch_timestamp;
+    int64_t local_timestamp;
+    float price;
+    float qty;
+    float leaves_qty;
+    float exec_price;
+    float exec_qty;
+    int8_t side;
+    uint8_t order_type;
+    uint8_t time_in_force;
+    uint8_t status;
+    uint8_t req;
+    bool maker;
+} HbtOrder;
+
+const char *hbt_last_error(void);
+
+HftBacktest *hbt_create(const char *config);
+void hbt_free(HftBacktest *hbt);
+
+/* Returns -1 on failure. */
+int64_t hbt_current_timestamp(const HftBacktest *hbt);
+int32_t hbt_elapse(HftBacktest *hbt, int64_t duration);
+int32_t hbt_elapse_bt(HftBacktest *hbt, int64_t duration);
+int32_t hbt_wait_next_feed(HftBacktest *hbt, bool include_order_resp, int64_t timeout);
+
+int32_t hbt_submit_order(HftBacktest *hbt, size_t asset_no, const HbtOrderRequest *req, bool wait);
+int32_t hbt_cancel(HftBacktest *hbt, size_t asset_no, int64_t order_id, bool wait);
+/* Clears the orders that have ended of all the assets if `asset_no` is negative. */
+int32_t hbt_clear_inactive_orders(HftBacktest *hbt, int64_t asset_no);
+
+int32_t hbt_depth(const HftBacktest *hbt, size_t asset_no, HbtDepth *out);
+/* Reads `levels` ticks of the side from the best price, where `side` is `HBT_BUY` for the bid. */
+int32_t hbt_depth_levels(
+    const HftBacktest *hbt, size_t asset_no, int8_t side, float *qty_out, size_t levels);
+int32_t hbt_state_values(const HftBacktest *hbt, size_t asset_no, HbtStateValues *out);
+/* Returns the number of the orders, which can exceed `capacity`. */
+ptrdiff_t hbt_orders(const HftBacktest *hbt, size_t asset_no, HbtOrder *out, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
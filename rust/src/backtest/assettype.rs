@@ -1,9 +1,45 @@
+/// Converts the prices and quantities of an asset into values in its settlement currency, on
+/// which the state accounting is based. Implementing this for a custom instrument lets it be
+/// backtested without changing the accounting.
 pub trait AssetType {
+    /// Returns the amount in the settlement currency exchanged by trading `qty` at `exec_price`,
+    /// on which the fee and the trading amount are based.
     fn amount(&self, exec_price: f32, qty: f32) -> f64;
+
+    /// Returns the change of the balance by buying `qty` at `exec_price`, whose negative is that
+    /// by selling. By default, the amount is debited.
+    fn balance_change(&self, exec_price: f32, qty: f32) -> f64 {
+        -self.amount(exec_price, qty)
+    }
+
+    /// Returns the equity in the settlement currency, marking the position to `price`.
     fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64;
+
+    /// Returns the contract multiplier, the quantity of the underlying, or the value for a quanto
+    /// contract, that one contract represents.
+    fn contract_size(&self) -> f64;
+
+    /// Returns the notional value in the settlement currency of `qty` at `price`, regardless of
+    /// the side.
+    fn notional(&self, price: f32, qty: f64) -> f64 {
+        self.amount(price, qty.abs() as f32).abs()
+    }
+
+    /// Returns the PnL in the settlement currency of the position `qty`, positive for a long
+    /// position and negative for a short position, opened at `entry_price` and marked to `price`.
+    fn pnl(&self, entry_price: f32, price: f32, qty: f64) -> f64 {
+        let balance = self.balance_change(entry_price, qty.abs() as f32) * qty.signum();
+        self.equity(price, balance, qty, 0.0)
+    }
+
+    /// Returns the fee in the settlement currency charged at `fee_rate` for trading `qty` at
+    /// `exec_price`. A negative rate is a rebate.
+    fn fee(&self, exec_price: f32, qty: f32, fee_rate: f64) -> f64 {
+        self.amount(exec_price, qty) * fee_rate
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct LinearAsset {
     contract_size: f64,
 }
@@ -22,9 +58,13 @@ impl AssetType for LinearAsset {
     fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64 {
         balance + self.contract_size * position * price as f64 - fee
     }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct InverseAsset {
     contract_size: f64,
 }
@@ -40,7 +80,122 @@ impl AssetType for InverseAsset {
         self.contract_size * qty as f64 / exec_price as f64
     }
 
+    /// A long position in the inverse contract is a short position in the settlement currency,
+    /// so buying credits the amount, which is offset by the position's value when it's marked,
+    /// and the balance stays that of the settlement currency, including the deposits.
+    fn balance_change(&self, exec_price: f32, qty: f32) -> f64 {
+        self.amount(exec_price, qty)
+    }
+
     fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64 {
-        -balance - self.contract_size * position / price as f64 - fee
+        balance - self.contract_size * position / price as f64 - fee
+    }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
+    }
+}
+
+/// A quanto contract, whose price is quoted in one currency but which is settled in another at a
+/// fixed rate, such as an ETHUSD perpetual margined in BTC. The multiplier is the value in the
+/// settlement currency of one point of the price per contract, so the PnL is linear in the price
+/// as for [`LinearAsset`], but it is accounted for in the settlement currency without converting
+/// at the settlement currency's price.
+#[derive(Clone, Debug)]
+pub struct QuantoAsset {
+    multiplier: f64,
+}
+
+impl QuantoAsset {
+    pub fn new(multiplier: f64) -> Self {
+        Self { multiplier }
+    }
+
+    /// Converts a value in the settlement currency, such as the equity, into the quote currency
+    /// at the settlement currency's price.
+    pub fn to_quote(&self, value: f64, settlement_price: f64) -> f64 {
+        value * settlement_price
+    }
+}
+
+impl AssetType for QuantoAsset {
+    fn amount(&self, exec_price: f32, qty: f32) -> f64 {
+        self.multiplier * exec_price as f64 * qty as f64
+    }
+
+    fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64 {
+        balance + self.multiplier * position * price as f64 - fee
+    }
+
+    fn contract_size(&self) -> f64 {
+        self.multiplier
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum OptionKind {
+    Call,
+    Put,
+}
+
+/// An option whose premium is quoted as the price of the order book, such as options on
+/// Deribit-style crypto venues. PnL is accounted for in premium terms, so the equity is marked to
+/// the option price, not to the underlying price. Use [`PricingModel`] to compute the theoretical
+/// value and the greeks from the underlying price.
+///
+/// [`PricingModel`]: crate::backtest::models::PricingModel
+#[derive(Clone, Debug)]
+pub struct OptionAsset {
+    pub underlying: String,
+    pub kind: OptionKind,
+    pub strike: f64,
+    /// Expiry timestamp in nanoseconds since the Unix epoch.
+    pub expiry: i64,
+    contract_size: f64,
+}
+
+impl OptionAsset {
+    pub fn new(
+        underlying: &str,
+        kind: OptionKind,
+        strike: f64,
+        expiry: i64,
+        contract_size: f64,
+    ) -> Self {
+        Self {
+            underlying: underlying.to_string(),
+            kind,
+            strike,
+            expiry,
+            contract_size,
+        }
+    }
+
+    /// Returns the time to expiry in years, which is zero once the option expires.
+    pub fn time_to_expiry(&self, timestamp: i64) -> f64 {
+        (self.expiry - timestamp).max(0) as f64 / 31_536_000_000_000_000.0
+    }
+
+    /// Returns the intrinsic value per unit of the underlying, which is also the settlement value
+    /// at expiry.
+    pub fn intrinsic_value(&self, underlying_price: f64) -> f64 {
+        match self.kind {
+            OptionKind::Call => (underlying_price - self.strike).max(0.0),
+            OptionKind::Put => (self.strike - underlying_price).max(0.0),
+        }
+    }
+}
+
+impl AssetType for OptionAsset {
+    fn amount(&self, exec_price: f32, qty: f32) -> f64 {
+        self.contract_size * exec_price as f64 * qty as f64
+    }
+
+    fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64 {
+        balance + self.contract_size * position * price as f64 - fee
+    }
+
+    fn contract_size(&self) -> f64 {
+        self.contract_size
     }
 }
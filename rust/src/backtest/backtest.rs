@@ -1,23 +1,91 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    io::Error as IoError,
+    marker::PhantomData,
+    rc::Rc,
+    time::Instant,
+};
 
 use crate::{
     backtest::{
-        evs::{EventSet, EventType},
-        proc::{LocalProcessor, Processor},
+        evs::{EventSet, EventType, TieBreak},
+        fingerprint::{Config, Fingerprint},
+        proc::{CustomProcessor, LocalProcessor, Processor},
+        profile::ModifyPriorityStats,
         reader::{UNTIL_END_OF_DATA, WAIT_ORDER_RESPONSE_NONE},
         state::StateValues,
+        stats::Termination,
+        ComputeTime,
         Error,
+        StalenessAction,
+        StalenessWatchdog,
     },
     depth::MarketDepth,
-    ty::{OrdType, Order, Event, Side, TimeInForce},
+    schedule::Schedule,
+    ty::{
+        Adjustment,
+        OrdType,
+        Order,
+        Event,
+        ExternalEvent,
+        ResponsePriority,
+        Side,
+        TimeInForce,
+    },
     Interface,
 };
 
+/// Which events make `goto_` return early.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Wake {
+    Never,
+    Feed,
+    FeedOrOrderResponse,
+}
+
+struct EarlyStop<T> {
+    interval: i64,
+    next_ts: i64,
+    predicate: Box<dyn FnMut(&T) -> bool>,
+}
+
+#[derive(Clone)]
+struct Staleness {
+    watchdog: StalenessWatchdog,
+    // The timestamp by which the next feed row is due, or `None` if the watchdog isn't armed.
+    deadline: Option<i64>,
+}
+
+impl Staleness {
+    /// Arms the watchdog by the feed row at the timestamp.
+    fn arm(&mut self, timestamp: i64) {
+        self.deadline = Some(timestamp + self.watchdog.threshold);
+    }
+}
+
 pub struct MultiAssetMultiExchangeBacktest<Q, MD> {
     cur_ts: i64,
     evs: EventSet,
+    tie_break: TieBreak,
+    response_priority: Option<ResponsePriority>,
+    compute_time: ComputeTime,
+    wakeup: Option<Instant>,
+    wakeup_interval: Option<i64>,
+    last_wakeup: i64,
+    early_stop: Option<EarlyStop<Self>>,
+    stopped_at: Option<i64>,
+    schedule: Option<Schedule>,
+    pending_external_events: VecDeque<ExternalEvent>,
+    external_events: Vec<ExternalEvent>,
+    pending_adjustments: VecDeque<Adjustment>,
+    adjustments: Vec<Adjustment>,
+    // The other assets sharing the exchange of each asset.
+    shared_exch: Vec<Vec<usize>>,
+    staleness: Vec<Option<Staleness>>,
     local: Vec<Box<dyn LocalProcessor<Q, MD>>>,
     exch: Vec<Box<dyn Processor>>,
+    config: Config,
     _q_marker: PhantomData<Q>,
 }
 
@@ -33,13 +101,332 @@ where
         }
         Self {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
+            evs: EventSet::new(num_assets, TieBreak::default(), None),
+            tie_break: TieBreak::default(),
+            response_priority: None,
+            compute_time: ComputeTime::default(),
+            wakeup: None,
+            wakeup_interval: None,
+            last_wakeup: i64::MIN,
+            early_stop: None,
+            stopped_at: None,
+            schedule: None,
+            pending_external_events: VecDeque::new(),
+            external_events: Vec::new(),
+            pending_adjustments: VecDeque::new(),
+            adjustments: Vec::new(),
+            shared_exch: vec![Vec::new(); num_assets],
+            staleness: vec![None; num_assets],
             local,
             exch,
+            config: Default::default(),
             _q_marker: Default::default(),
         }
     }
 
+    /// Sets the tie-breaking policy for events of the same asset that share the same timestamp.
+    /// See [`TieBreak`].
+    pub fn with_tie_break(self, tie_break: TieBreak) -> Self {
+        Self {
+            evs: EventSet::new(self.local.len(), tie_break, self.response_priority),
+            tie_break,
+            ..self
+        }
+    }
+
+    /// Sets whether the order responses or the market data updates are delivered to the strategy
+    /// first when they share the same local timestamp. If it is not set, it follows the
+    /// [`TieBreak`]. See [`ResponsePriority`].
+    pub fn with_response_priority(self, response_priority: ResponsePriority) -> Self {
+        Self {
+            evs: EventSet::new(self.local.len(), self.tie_break, Some(response_priority)),
+            response_priority: Some(response_priority),
+            ..self
+        }
+    }
+
+    /// Sets how the strategy's compute time is charged to the backtest clock. See
+    /// [`ComputeTime`].
+    pub fn with_compute_time(self, compute_time: ComputeTime) -> Self {
+        Self {
+            compute_time,
+            ..self
+        }
+    }
+
+    /// Coalesces the wakeups of [`wait_next_feed`](Interface::wait_next_feed) to at most once
+    /// per `interval`. A feed that arrives sooner than `interval` after the previous wakeup
+    /// doesn't wake the strategy immediately; instead, the strategy wakes once the interval has
+    /// passed, with all the events up to then applied, such as the depth updates and the trades
+    /// accumulated in [`trade`](Interface::trade). This speeds up backtests of strategies that
+    /// don't need to react to every event and models the batching of a real event loop.
+    pub fn with_wakeup_interval(self, interval: i64) -> Self {
+        Self {
+            wakeup_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sets the early-stop predicate, which is evaluated every `interval` during elapse. If the
+    /// predicate returns `true`, such as when the drawdown exceeds a threshold, the backtest stops
+    /// as if it reached the end of the data, and [`termination`](Self::termination) reports that
+    /// it stopped early.
+    pub fn with_early_stop<F>(self, interval: i64, predicate: F) -> Self
+    where
+        F: FnMut(&Self) -> bool + 'static,
+    {
+        Self {
+            early_stop: Some(EarlyStop {
+                interval,
+                next_ts: i64::MIN,
+                predicate: Box::new(predicate),
+            }),
+            ..self
+        }
+    }
+
+    /// Sets the schedule that gates order submission. See [`Schedule`].
+    pub fn with_schedule(self, schedule: Schedule) -> Self {
+        Self {
+            schedule: Some(schedule),
+            ..self
+        }
+    }
+
+    /// Sets the external events, such as an economic calendar, to be injected into the backtest.
+    /// Each event is delivered to the strategy through [`Interface::external_events`] once the
+    /// local time reaches its timestamp. See
+    /// [`read_external_events`](crate::backtest::reader::read_external_events).
+    pub fn with_external_events(self, mut events: Vec<ExternalEvent>) -> Self {
+        events.sort_by_key(|event| event.timestamp);
+        Self {
+            pending_external_events: events.into(),
+            ..self
+        }
+    }
+
+    /// Sets the manual adjustments of the assets' positions and balances, such as a starting
+    /// inventory or an over-the-counter block trade in the middle of the run. Each adjustment is
+    /// applied to the asset's state once the local time reaches its timestamp, without being
+    /// counted as a trade, and is reported through [`Interface::adjustments`], so that it can be
+    /// told apart from the fills, for instance by
+    /// [`FillJournal`](crate::backtest::stats::FillJournal).
+    pub fn with_adjustments(self, mut adjustments: Vec<Adjustment>) -> Self {
+        adjustments.sort_by_key(|adjustment| adjustment.timestamp);
+        Self {
+            pending_adjustments: adjustments.into(),
+            ..self
+        }
+    }
+
+    /// Sets the assets that share one exchange, which are the strategy instances built by
+    /// [`BtAssetBuilder::build_shared`](crate::backtest::BtAssetBuilder::build_shared), so that
+    /// the responses the exchange sends to any of them are scheduled at once.
+    pub fn with_shared_exchange(mut self, asset_nos: Vec<usize>) -> Self {
+        for &asset_no in asset_nos.iter() {
+            self.shared_exch[asset_no] = asset_nos
+                .iter()
+                .copied()
+                .filter(|other| *other != asset_no)
+                .collect();
+        }
+        self
+    }
+
+    /// Sets the [`StalenessWatchdog`] of the asset, which pulls the strategy's resting orders
+    /// when the asset's feed has a gap longer than its threshold.
+    pub fn with_staleness_watchdog(mut self, asset_no: usize, watchdog: StalenessWatchdog) -> Self {
+        self.staleness[asset_no] = Some(Staleness {
+            watchdog,
+            deadline: None,
+        });
+        self
+    }
+
+    /// Sets the hook invoked on every fill of the strategy's orders, with the asset number, the
+    /// filled order, and the asset's state values after the fill is applied, so that custom
+    /// accounting, such as tax lots or per-strategy books, can be kept alongside the backtest.
+    pub fn with_fill_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(usize, &Order<Q>, &StateValues) + 'static,
+        Q: 'static,
+    {
+        let hook = Rc::new(RefCell::new(hook));
+        for (asset_no, local) in self.local.iter_mut().enumerate() {
+            let hook = hook.clone();
+            local.set_fill_hook(Box::new(move |order, state_values| {
+                (hook.borrow_mut())(asset_no, order, state_values)
+            }));
+        }
+        self
+    }
+
+    /// Adds a processor that consumes the feed data of the asset on the local timeline, right
+    /// after the local processor applies each row, so that its derived state is kept in sync with
+    /// the market depth at every wakeup of the strategy. The processors of an asset run in order
+    /// of addition. See [`CustomProcessor`].
+    pub fn with_processor<P>(mut self, asset_no: usize, processor: P) -> Self
+    where
+        P: CustomProcessor<MD> + 'static,
+    {
+        self.local
+            .get_mut(asset_no)
+            .unwrap()
+            .add_processor(Box::new(processor));
+        self
+    }
+
+    pub fn termination(&self) -> Termination {
+        match self.stopped_at {
+            Some(timestamp) => Termination::EarlyStopped(timestamp),
+            None => Termination::Completed,
+        }
+    }
+
+    pub(crate) fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Computes the [`Fingerprint`] of the configuration the backtest was built with by
+    /// [`BtBuilder`](crate::backtest::BtBuilder), reading the data files to compute their
+    /// checksums, so it fails if a data file has been removed since. A backtest constructed
+    /// directly only has the crate version in its fingerprint.
+    pub fn fingerprint(&self) -> Result<Fingerprint, IoError> {
+        self.config.fingerprint()
+    }
+
+    /// Returns the number of trades of the asset that have exceeded the length of the trade
+    /// buffer, which are either dropped or buffered beyond the preallocated length, depending on
+    /// the [`TradeBuffer`](crate::backtest::TradeBuffer).
+    pub fn trade_overflow(&self, asset_no: usize) -> u64 {
+        self.local
+            .get(asset_no)
+            .map(|local| local.trade_overflow())
+            .unwrap_or(0)
+    }
+
+    /// Returns the fills of the asset gained and lost by the queue position resets of the
+    /// modified orders, compared to keeping their queue positions, as its exchange applies the
+    /// [`ModifyPolicy`](crate::backtest::profile::ModifyPolicy). For an exchange shared by
+    /// several assets, they are those of all the sharing assets. See [`ModifyPriorityStats`].
+    pub fn modify_priority_stats(&self, asset_no: usize) -> ModifyPriorityStats {
+        self.exch
+            .get(asset_no)
+            .map(|exch| exch.modify_priority_stats())
+            .unwrap_or_default()
+    }
+
+    /// Sets the one-cancels-other group that the orders of the asset submitted afterward join,
+    /// until it is reset with `None`. When an order of the group is filled, even partially, the
+    /// exchange cancels the other orders of the group at the fill's timestamp, and their
+    /// cancellations are delivered after the fill. An order that arrives at the exchange after
+    /// its group has been triggered is expired. Group ids must not be reused.
+    ///
+    /// This enables bracket-style exits, such as a take-profit and a stop-loss order on the same
+    /// position, without waiting for the fill to round-trip to the strategy.
+    pub fn set_oco_group(&mut self, asset_no: usize, group: Option<i64>) {
+        self.local.get_mut(asset_no).unwrap().set_oco_group(group);
+    }
+
+    /// Sets the trigger price of the trigger orders of the asset submitted afterward, such as
+    /// [`OrdType::StopMarket`] and [`OrdType::TakeProfitLimit`], until it is reset with `None`.
+    /// Submitting a trigger order without a trigger price fails with
+    /// [`Error::InvalidOrderRequest`].
+    ///
+    /// A trigger order rests untriggered at the exchange, which expires it if it would be
+    /// triggered immediately, until a trade price or the opposite best price reaches the trigger
+    /// price. It then becomes a market order, taking the opposite best, or a limit order at its
+    /// price, and follows the normal matching path.
+    pub fn set_trigger_price(&mut self, asset_no: usize, trigger_price: Option<f32>) {
+        self.local
+            .get_mut(asset_no)
+            .unwrap()
+            .set_trigger_price(trigger_price);
+    }
+
+    fn check_early_stop(&mut self) -> bool {
+        let mut early_stop = match self.early_stop.take() {
+            Some(early_stop) => early_stop,
+            None => return false,
+        };
+        let mut stop = false;
+        if early_stop.next_ts == i64::MIN {
+            early_stop.next_ts = self.cur_ts + early_stop.interval;
+        } else if self.cur_ts >= early_stop.next_ts {
+            early_stop.next_ts = self.cur_ts + early_stop.interval;
+            stop = (early_stop.predicate)(self);
+        }
+        self.early_stop = Some(early_stop);
+        if stop {
+            self.stopped_at = Some(self.cur_ts);
+        }
+        stop
+    }
+
+    /// Delivers the external events and applies the adjustments up to the current timestamp.
+    fn deliver_pending_events(&mut self) {
+        while let Some(event) = self.pending_external_events.front() {
+            if event.timestamp > self.cur_ts {
+                break;
+            }
+            let event = self.pending_external_events.pop_front().unwrap();
+            self.external_events.push(event);
+        }
+        while let Some(adjustment) = self.pending_adjustments.front() {
+            if adjustment.timestamp > self.cur_ts {
+                break;
+            }
+            let adjustment = self.pending_adjustments.pop_front().unwrap();
+            if let Some(local) = self.local.get_mut(adjustment.asset_no) {
+                local.apply_adjustment(&adjustment);
+            }
+            self.adjustments.push(adjustment);
+        }
+    }
+
+    /// Initializes the event set on the first wakeup. Returns `false` if the backtest has ended.
+    fn start(&mut self) -> Result<bool, Error> {
+        if self.stopped_at.is_some() {
+            return Ok(false);
+        }
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn finish_wakeup(&mut self, result: bool) -> Result<bool, Error> {
+        if result && self.check_early_stop() {
+            return Ok(false);
+        }
+        if !matches!(self.compute_time, ComputeTime::None) {
+            self.wakeup = Some(Instant::now());
+        }
+        Ok(result)
+    }
+
+    fn charge_compute_time(&mut self) -> Result<bool, Error> {
+        if let Some(wakeup) = self.wakeup.take() {
+            let compute_time = match self.compute_time {
+                ComputeTime::None => 0,
+                ComputeTime::Constant(compute_time) => compute_time,
+                ComputeTime::Measured => wakeup.elapsed().as_nanos() as i64,
+            };
+            if compute_time > 0 {
+                return self.goto(self.cur_ts + compute_time, WAIT_ORDER_RESPONSE_NONE);
+            }
+        }
+        Ok(true)
+    }
+
     fn initialize_evs(&mut self) -> Result<(), Error> {
         for (asset_no, local) in self.local.iter_mut().enumerate() {
             match local.initialize_data() {
@@ -67,11 +454,80 @@ where
     }
 
     pub fn goto(&mut self, timestamp: i64, wait_order_response: i64) -> Result<bool, Error> {
+        self.goto_(timestamp, wait_order_response, Wake::Never)
+    }
+
+    /// Schedules the responses to the other assets sharing the exchange of the asset, as of when
+    /// their locals receive them.
+    fn update_shared_local_orders(&mut self, asset_no: usize) {
+        for &other in self.shared_exch[asset_no].iter() {
+            self.evs
+                .update_local_order(other, self.local[other].frontmost_recv_order_timestamp());
+        }
+    }
+
+    /// Fires the staleness watchdogs whose deadlines are before `until`, returning whether any
+    /// has fired, in which case the events scheduled by it may precede the next event.
+    fn fire_staleness_watchdogs(&mut self, until: i64) -> Result<bool, Error> {
+        let mut fired = false;
+        for asset_no in 0..self.staleness.len() {
+            let Some(staleness) = self.staleness[asset_no].as_mut() else {
+                continue;
+            };
+            let Some(deadline) = staleness.deadline.filter(|deadline| *deadline < until) else {
+                continue;
+            };
+            staleness.deadline = None;
+            fired = true;
+            match staleness.watchdog.action {
+                StalenessAction::Cancel => {
+                    let local = self.local.get_mut(asset_no).unwrap();
+                    let mut order_ids: Vec<i64> = local
+                        .orders()
+                        .values()
+                        .filter(|order| order.cancellable())
+                        .map(|order| order.order_id)
+                        .collect();
+                    order_ids.sort();
+                    for order_id in order_ids {
+                        local.cancel(order_id, deadline)?;
+                    }
+                    self.evs
+                        .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+                }
+                StalenessAction::Expire => {
+                    let exch = self.exch.get_mut(asset_no).unwrap();
+                    exch.expire_all_orders(deadline)?;
+                    self.evs.update_local_order(
+                        asset_no,
+                        self.local[asset_no].frontmost_recv_order_timestamp(),
+                    );
+                    self.update_shared_local_orders(asset_no);
+                }
+            }
+        }
+        Ok(fired)
+    }
+
+    /// Processes the events up to `timestamp`, but returns right after the first event that
+    /// `wake` selects, with the current timestamp set to that event's timestamp.
+    fn goto_(
+        &mut self,
+        timestamp: i64,
+        wait_order_response: i64,
+        wake: Wake,
+    ) -> Result<bool, Error> {
         loop {
             match self.evs.next() {
                 Some(ev) => {
+                    if self
+                        .fire_staleness_watchdogs(ev.timestamp.min(timestamp.saturating_add(1)))?
+                    {
+                        continue;
+                    }
                     if ev.timestamp > timestamp {
                         self.cur_ts = timestamp;
+                        self.deliver_pending_events();
                         return Ok(true);
                     }
                     match ev.ty {
@@ -80,14 +536,25 @@ where
                             match local.process_data() {
                                 Ok((next_ts, _)) => {
                                     self.evs.update_local_data(ev.asset_no, next_ts);
+                                    if let Some(staleness) = &mut self.staleness[ev.asset_no] {
+                                        staleness.arm(ev.timestamp);
+                                    }
                                 }
                                 Err(Error::EndOfData) => {
                                     self.evs.invalidate_local_data(ev.asset_no);
+                                    if let Some(staleness) = &mut self.staleness[ev.asset_no] {
+                                        staleness.deadline = None;
+                                    }
                                 }
                                 Err(e) => {
                                     return Err(e);
                                 }
                             }
+                            if wake != Wake::Never {
+                                self.cur_ts = ev.timestamp;
+                                self.deliver_pending_events();
+                                return Ok(true);
+                            }
                         }
                         EventType::LocalOrder => {
                             let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
@@ -96,6 +563,11 @@ where
                                 ev.asset_no,
                                 local.frontmost_recv_order_timestamp(),
                             );
+                            if wake == Wake::FeedOrOrderResponse {
+                                self.cur_ts = ev.timestamp;
+                                self.deliver_pending_events();
+                                return Ok(true);
+                            }
                         }
                         EventType::ExchData => {
                             let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
@@ -112,8 +584,9 @@ where
                             }
                             self.evs.update_local_order(
                                 ev.asset_no,
-                                exch.frontmost_send_order_timestamp(),
+                                self.local[ev.asset_no].frontmost_recv_order_timestamp(),
                             );
+                            self.update_shared_local_orders(ev.asset_no);
                         }
                         EventType::ExchOrder => {
                             let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
@@ -122,6 +595,13 @@ where
                                 ev.asset_no,
                                 exch.frontmost_recv_order_timestamp(),
                             );
+                            // The responses can arrive at the same time as the next market data,
+                            // so they are scheduled right away to be ordered against it.
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                self.local[ev.asset_no].frontmost_recv_order_timestamp(),
+                            );
+                            self.update_shared_local_orders(ev.asset_no);
                         }
                     }
                 }
@@ -156,7 +636,7 @@ where
         &self.local.get(asset_no).unwrap().depth()
     }
 
-    fn trade(&self, asset_no: usize) -> &Vec<Event> {
+    fn trade(&self, asset_no: usize) -> &[Event] {
         let local = self.local.get(asset_no).unwrap();
         local.trade()
     }
@@ -175,10 +655,37 @@ where
         }
     }
 
+    fn external_events(&self) -> &Vec<ExternalEvent> {
+        &self.external_events
+    }
+
+    fn clear_external_events(&mut self) {
+        self.external_events.clear();
+    }
+
+    fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+
     fn orders(&self, asset_no: usize) -> &HashMap<i64, Order<Q>> {
         &self.local.get(asset_no).unwrap().orders()
     }
 
+    fn order_entry_latency(&self, asset_no: usize, order_id: i64) -> Option<i64> {
+        self.local
+            .get(asset_no)
+            .unwrap()
+            .order_entry_latency(order_id)
+    }
+
+    fn order_budget(&self, _asset_no: usize) -> Option<u32> {
+        None
+    }
+
+    fn features(&self, asset_no: usize) -> &[f64] {
+        self.local.get(asset_no).unwrap().features()
+    }
+
     fn submit_buy_order(
         &mut self,
         asset_no: usize,
@@ -189,6 +696,14 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        if let Some(schedule) = self.schedule.as_ref() {
+            if !schedule.is_active(self.cur_ts) {
+                return Err(Error::OutsideSchedule);
+            }
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
             order_id,
@@ -218,6 +733,14 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        if let Some(schedule) = self.schedule.as_ref() {
+            if !schedule.is_active(self.cur_ts) {
+                return Err(Error::OutsideSchedule);
+            }
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
             order_id,
@@ -238,6 +761,9 @@ where
     }
 
     fn cancel(&mut self, asset_no: usize, order_id: i64, wait: bool) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.cancel(order_id, self.cur_ts)?;
         self.evs
@@ -249,6 +775,49 @@ where
         Ok(true)
     }
 
+    fn reduce_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.reduce(order_id, qty, self.cur_ts)?;
+        self.evs
+            .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+
+        if wait {
+            return self.goto(UNTIL_END_OF_DATA, order_id);
+        }
+        Ok(true)
+    }
+
+    fn modify_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.modify(order_id, price, qty, self.cur_ts)?;
+        self.evs
+            .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+
+        if wait {
+            return self.goto(UNTIL_END_OF_DATA, order_id);
+        }
+        Ok(true)
+    }
+
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
         match asset_no {
             Some(asset_no) => {
@@ -266,18 +835,36 @@ where
     }
 
     fn elapse(&mut self, duration: i64) -> Result<bool, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(false);
-                }
+        if !self.start()? {
+            return Ok(false);
+        }
+        let result = self.goto(self.cur_ts + duration, WAIT_ORDER_RESPONSE_NONE)?;
+        self.finish_wakeup(result)
+    }
+
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, Self::Error> {
+        if !self.start()? {
+            return Ok(false);
+        }
+        let deadline = self.cur_ts + timeout;
+        let wake = if include_order_resp {
+            Wake::FeedOrOrderResponse
+        } else {
+            Wake::Feed
+        };
+        let mut result = self.goto_(deadline, WAIT_ORDER_RESPONSE_NONE, wake)?;
+        if let Some(interval) = self.wakeup_interval {
+            let next_wakeup = self.last_wakeup.saturating_add(interval).min(deadline);
+            if result && self.cur_ts < next_wakeup {
+                result = self.goto(next_wakeup, WAIT_ORDER_RESPONSE_NONE)?;
             }
         }
-        self.goto(self.cur_ts + duration, WAIT_ORDER_RESPONSE_NONE)
+        self.last_wakeup = self.cur_ts;
+        self.finish_wakeup(result)
     }
 
     fn elapse_bt(&mut self, duration: i64) -> Result<bool, Self::Error> {
@@ -292,8 +879,25 @@ where
 pub struct MultiAssetSingleExchangeBacktest<Q, MD, Local, Exchange> {
     cur_ts: i64,
     evs: EventSet,
+    tie_break: TieBreak,
+    response_priority: Option<ResponsePriority>,
+    compute_time: ComputeTime,
+    wakeup: Option<Instant>,
+    wakeup_interval: Option<i64>,
+    last_wakeup: i64,
+    early_stop: Option<EarlyStop<Self>>,
+    stopped_at: Option<i64>,
+    schedule: Option<Schedule>,
+    pending_external_events: VecDeque<ExternalEvent>,
+    external_events: Vec<ExternalEvent>,
+    pending_adjustments: VecDeque<Adjustment>,
+    adjustments: Vec<Adjustment>,
+    // The other assets sharing the exchange of each asset.
+    shared_exch: Vec<Vec<usize>>,
+    staleness: Vec<Option<Staleness>>,
     local: Vec<Local>,
     exch: Vec<Exchange>,
+    config: Config,
     _q_marker: PhantomData<Q>,
     _md_marker: PhantomData<MD>,
 }
@@ -312,14 +916,336 @@ where
         }
         Self {
             cur_ts: i64::MAX,
-            evs: EventSet::new(num_assets),
+            evs: EventSet::new(num_assets, TieBreak::default(), None),
+            tie_break: TieBreak::default(),
+            response_priority: None,
+            compute_time: ComputeTime::default(),
+            wakeup: None,
+            wakeup_interval: None,
+            last_wakeup: i64::MIN,
+            early_stop: None,
+            stopped_at: None,
+            schedule: None,
+            pending_external_events: VecDeque::new(),
+            external_events: Vec::new(),
+            pending_adjustments: VecDeque::new(),
+            adjustments: Vec::new(),
+            shared_exch: vec![Vec::new(); num_assets],
+            staleness: vec![None; num_assets],
             local,
             exch,
+            config: Default::default(),
             _q_marker: Default::default(),
             _md_marker: Default::default(),
         }
     }
 
+    /// Sets the tie-breaking policy for events of the same asset that share the same timestamp.
+    /// See [`TieBreak`].
+    pub fn with_tie_break(self, tie_break: TieBreak) -> Self {
+        Self {
+            evs: EventSet::new(self.local.len(), tie_break, self.response_priority),
+            tie_break,
+            ..self
+        }
+    }
+
+    /// Sets whether the order responses or the market data updates are delivered to the strategy
+    /// first when they share the same local timestamp. If it is not set, it follows the
+    /// [`TieBreak`]. See [`ResponsePriority`].
+    pub fn with_response_priority(self, response_priority: ResponsePriority) -> Self {
+        Self {
+            evs: EventSet::new(self.local.len(), self.tie_break, Some(response_priority)),
+            response_priority: Some(response_priority),
+            ..self
+        }
+    }
+
+    /// Sets how the strategy's compute time is charged to the backtest clock. See
+    /// [`ComputeTime`].
+    pub fn with_compute_time(self, compute_time: ComputeTime) -> Self {
+        Self {
+            compute_time,
+            ..self
+        }
+    }
+
+    /// Coalesces the wakeups of [`wait_next_feed`](Interface::wait_next_feed) to at most once
+    /// per `interval`. A feed that arrives sooner than `interval` after the previous wakeup
+    /// doesn't wake the strategy immediately; instead, the strategy wakes once the interval has
+    /// passed, with all the events up to then applied, such as the depth updates and the trades
+    /// accumulated in [`trade`](Interface::trade). This speeds up backtests of strategies that
+    /// don't need to react to every event and models the batching of a real event loop.
+    pub fn with_wakeup_interval(self, interval: i64) -> Self {
+        Self {
+            wakeup_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sets the early-stop predicate, which is evaluated every `interval` during elapse. If the
+    /// predicate returns `true`, such as when the drawdown exceeds a threshold, the backtest stops
+    /// as if it reached the end of the data, and [`termination`](Self::termination) reports that
+    /// it stopped early.
+    pub fn with_early_stop<F>(self, interval: i64, predicate: F) -> Self
+    where
+        F: FnMut(&Self) -> bool + 'static,
+    {
+        Self {
+            early_stop: Some(EarlyStop {
+                interval,
+                next_ts: i64::MIN,
+                predicate: Box::new(predicate),
+            }),
+            ..self
+        }
+    }
+
+    /// Sets the schedule that gates order submission. See [`Schedule`].
+    pub fn with_schedule(self, schedule: Schedule) -> Self {
+        Self {
+            schedule: Some(schedule),
+            ..self
+        }
+    }
+
+    /// Sets the external events, such as an economic calendar, to be injected into the backtest.
+    /// Each event is delivered to the strategy through [`Interface::external_events`] once the
+    /// local time reaches its timestamp. See
+    /// [`read_external_events`](crate::backtest::reader::read_external_events).
+    pub fn with_external_events(self, mut events: Vec<ExternalEvent>) -> Self {
+        events.sort_by_key(|event| event.timestamp);
+        Self {
+            pending_external_events: events.into(),
+            ..self
+        }
+    }
+
+    /// Sets the manual adjustments of the assets' positions and balances, such as a starting
+    /// inventory or an over-the-counter block trade in the middle of the run. Each adjustment is
+    /// applied to the asset's state once the local time reaches its timestamp, without being
+    /// counted as a trade, and is reported through [`Interface::adjustments`], so that it can be
+    /// told apart from the fills, for instance by
+    /// [`FillJournal`](crate::backtest::stats::FillJournal).
+    pub fn with_adjustments(self, mut adjustments: Vec<Adjustment>) -> Self {
+        adjustments.sort_by_key(|adjustment| adjustment.timestamp);
+        Self {
+            pending_adjustments: adjustments.into(),
+            ..self
+        }
+    }
+
+    /// Sets the assets that share one exchange, which are the strategy instances built by
+    /// [`BtAssetBuilder::build_shared`](crate::backtest::BtAssetBuilder::build_shared), so that
+    /// the responses the exchange sends to any of them are scheduled at once.
+    pub fn with_shared_exchange(mut self, asset_nos: Vec<usize>) -> Self {
+        for &asset_no in asset_nos.iter() {
+            self.shared_exch[asset_no] = asset_nos
+                .iter()
+                .copied()
+                .filter(|other| *other != asset_no)
+                .collect();
+        }
+        self
+    }
+
+    /// Sets the [`StalenessWatchdog`] of the asset, which pulls the strategy's resting orders
+    /// when the asset's feed has a gap longer than its threshold.
+    pub fn with_staleness_watchdog(mut self, asset_no: usize, watchdog: StalenessWatchdog) -> Self {
+        self.staleness[asset_no] = Some(Staleness {
+            watchdog,
+            deadline: None,
+        });
+        self
+    }
+
+    /// Sets the hook invoked on every fill of the strategy's orders, with the asset number, the
+    /// filled order, and the asset's state values after the fill is applied, so that custom
+    /// accounting, such as tax lots or per-strategy books, can be kept alongside the backtest.
+    pub fn with_fill_hook<F>(mut self, hook: F) -> Self
+    where
+        F: FnMut(usize, &Order<Q>, &StateValues) + 'static,
+        Q: 'static,
+    {
+        let hook = Rc::new(RefCell::new(hook));
+        for (asset_no, local) in self.local.iter_mut().enumerate() {
+            let hook = hook.clone();
+            local.set_fill_hook(Box::new(move |order, state_values| {
+                (hook.borrow_mut())(asset_no, order, state_values)
+            }));
+        }
+        self
+    }
+
+    /// Adds a processor that consumes the feed data of the asset on the local timeline, right
+    /// after the local processor applies each row, so that its derived state is kept in sync with
+    /// the market depth at every wakeup of the strategy. The processors of an asset run in order
+    /// of addition. See [`CustomProcessor`].
+    pub fn with_processor<P>(mut self, asset_no: usize, processor: P) -> Self
+    where
+        P: CustomProcessor<MD> + 'static,
+    {
+        self.local
+            .get_mut(asset_no)
+            .unwrap()
+            .add_processor(Box::new(processor));
+        self
+    }
+
+    pub fn termination(&self) -> Termination {
+        match self.stopped_at {
+            Some(timestamp) => Termination::EarlyStopped(timestamp),
+            None => Termination::Completed,
+        }
+    }
+
+    /// Sets the configuration the backtest was built with, such as the settings and the data
+    /// files of its processors, which are constructed directly and so can't be recorded by
+    /// themselves.
+    pub fn with_config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
+    /// Computes the [`Fingerprint`] of the configuration set by
+    /// [`with_config`](Self::with_config), reading the data files to compute their checksums, so
+    /// it fails if a data file has been removed since. Without the configuration set, it only has
+    /// the crate version.
+    pub fn fingerprint(&self) -> Result<Fingerprint, IoError> {
+        self.config.fingerprint()
+    }
+
+    /// Returns the number of trades of the asset that have exceeded the length of the trade
+    /// buffer, which are either dropped or buffered beyond the preallocated length, depending on
+    /// the [`TradeBuffer`](crate::backtest::TradeBuffer).
+    pub fn trade_overflow(&self, asset_no: usize) -> u64 {
+        self.local
+            .get(asset_no)
+            .map(|local| local.trade_overflow())
+            .unwrap_or(0)
+    }
+
+    /// Returns the fills of the asset gained and lost by the queue position resets of the
+    /// modified orders, compared to keeping their queue positions, as its exchange applies the
+    /// [`ModifyPolicy`](crate::backtest::profile::ModifyPolicy). For an exchange shared by
+    /// several assets, they are those of all the sharing assets. See [`ModifyPriorityStats`].
+    pub fn modify_priority_stats(&self, asset_no: usize) -> ModifyPriorityStats {
+        self.exch
+            .get(asset_no)
+            .map(|exch| exch.modify_priority_stats())
+            .unwrap_or_default()
+    }
+
+    /// Sets the one-cancels-other group that the orders of the asset submitted afterward join,
+    /// until it is reset with `None`. When an order of the group is filled, even partially, the
+    /// exchange cancels the other orders of the group at the fill's timestamp, and their
+    /// cancellations are delivered after the fill. An order that arrives at the exchange after
+    /// its group has been triggered is expired. Group ids must not be reused.
+    ///
+    /// This enables bracket-style exits, such as a take-profit and a stop-loss order on the same
+    /// position, without waiting for the fill to round-trip to the strategy.
+    pub fn set_oco_group(&mut self, asset_no: usize, group: Option<i64>) {
+        self.local.get_mut(asset_no).unwrap().set_oco_group(group);
+    }
+
+    /// Sets the trigger price of the trigger orders of the asset submitted afterward, such as
+    /// [`OrdType::StopMarket`] and [`OrdType::TakeProfitLimit`], until it is reset with `None`.
+    /// Submitting a trigger order without a trigger price fails with
+    /// [`Error::InvalidOrderRequest`].
+    ///
+    /// A trigger order rests untriggered at the exchange, which expires it if it would be
+    /// triggered immediately, until a trade price or the opposite best price reaches the trigger
+    /// price. It then becomes a market order, taking the opposite best, or a limit order at its
+    /// price, and follows the normal matching path.
+    pub fn set_trigger_price(&mut self, asset_no: usize, trigger_price: Option<f32>) {
+        self.local
+            .get_mut(asset_no)
+            .unwrap()
+            .set_trigger_price(trigger_price);
+    }
+
+    fn check_early_stop(&mut self) -> bool {
+        let mut early_stop = match self.early_stop.take() {
+            Some(early_stop) => early_stop,
+            None => return false,
+        };
+        let mut stop = false;
+        if early_stop.next_ts == i64::MIN {
+            early_stop.next_ts = self.cur_ts + early_stop.interval;
+        } else if self.cur_ts >= early_stop.next_ts {
+            early_stop.next_ts = self.cur_ts + early_stop.interval;
+            stop = (early_stop.predicate)(self);
+        }
+        self.early_stop = Some(early_stop);
+        if stop {
+            self.stopped_at = Some(self.cur_ts);
+        }
+        stop
+    }
+
+    /// Delivers the external events and applies the adjustments up to the current timestamp.
+    fn deliver_pending_events(&mut self) {
+        while let Some(event) = self.pending_external_events.front() {
+            if event.timestamp > self.cur_ts {
+                break;
+            }
+            let event = self.pending_external_events.pop_front().unwrap();
+            self.external_events.push(event);
+        }
+        while let Some(adjustment) = self.pending_adjustments.front() {
+            if adjustment.timestamp > self.cur_ts {
+                break;
+            }
+            let adjustment = self.pending_adjustments.pop_front().unwrap();
+            if let Some(local) = self.local.get_mut(adjustment.asset_no) {
+                local.apply_adjustment(&adjustment);
+            }
+            self.adjustments.push(adjustment);
+        }
+    }
+
+    /// Initializes the event set on the first wakeup. Returns `false` if the backtest has ended.
+    fn start(&mut self) -> Result<bool, Error> {
+        if self.stopped_at.is_some() {
+            return Ok(false);
+        }
+        if self.cur_ts == i64::MAX {
+            self.initialize_evs()?;
+            match self.evs.next() {
+                Some(ev) => {
+                    self.cur_ts = ev.timestamp;
+                }
+                None => {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn finish_wakeup(&mut self, result: bool) -> Result<bool, Error> {
+        if result && self.check_early_stop() {
+            return Ok(false);
+        }
+        if !matches!(self.compute_time, ComputeTime::None) {
+            self.wakeup = Some(Instant::now());
+        }
+        Ok(result)
+    }
+
+    fn charge_compute_time(&mut self) -> Result<bool, Error> {
+        if let Some(wakeup) = self.wakeup.take() {
+            let compute_time = match self.compute_time {
+                ComputeTime::None => 0,
+                ComputeTime::Constant(compute_time) => compute_time,
+                ComputeTime::Measured => wakeup.elapsed().as_nanos() as i64,
+            };
+            if compute_time > 0 {
+                return self.goto(self.cur_ts + compute_time, WAIT_ORDER_RESPONSE_NONE);
+            }
+        }
+        Ok(true)
+    }
+
     fn initialize_evs(&mut self) -> Result<(), Error> {
         for (asset_no, local) in self.local.iter_mut().enumerate() {
             match local.initialize_data() {
@@ -347,11 +1273,80 @@ where
     }
 
     pub fn goto(&mut self, timestamp: i64, wait_order_response: i64) -> Result<bool, Error> {
+        self.goto_(timestamp, wait_order_response, Wake::Never)
+    }
+
+    /// Schedules the responses to the other assets sharing the exchange of the asset, as of when
+    /// their locals receive them.
+    fn update_shared_local_orders(&mut self, asset_no: usize) {
+        for &other in self.shared_exch[asset_no].iter() {
+            self.evs
+                .update_local_order(other, self.local[other].frontmost_recv_order_timestamp());
+        }
+    }
+
+    /// Fires the staleness watchdogs whose deadlines are before `until`, returning whether any
+    /// has fired, in which case the events scheduled by it may precede the next event.
+    fn fire_staleness_watchdogs(&mut self, until: i64) -> Result<bool, Error> {
+        let mut fired = false;
+        for asset_no in 0..self.staleness.len() {
+            let Some(staleness) = self.staleness[asset_no].as_mut() else {
+                continue;
+            };
+            let Some(deadline) = staleness.deadline.filter(|deadline| *deadline < until) else {
+                continue;
+            };
+            staleness.deadline = None;
+            fired = true;
+            match staleness.watchdog.action {
+                StalenessAction::Cancel => {
+                    let local = self.local.get_mut(asset_no).unwrap();
+                    let mut order_ids: Vec<i64> = local
+                        .orders()
+                        .values()
+                        .filter(|order| order.cancellable())
+                        .map(|order| order.order_id)
+                        .collect();
+                    order_ids.sort();
+                    for order_id in order_ids {
+                        local.cancel(order_id, deadline)?;
+                    }
+                    self.evs
+                        .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+                }
+                StalenessAction::Expire => {
+                    let exch = self.exch.get_mut(asset_no).unwrap();
+                    exch.expire_all_orders(deadline)?;
+                    self.evs.update_local_order(
+                        asset_no,
+                        self.local[asset_no].frontmost_recv_order_timestamp(),
+                    );
+                    self.update_shared_local_orders(asset_no);
+                }
+            }
+        }
+        Ok(fired)
+    }
+
+    /// Processes the events up to `timestamp`, but returns right after the first event that
+    /// `wake` selects, with the current timestamp set to that event's timestamp.
+    fn goto_(
+        &mut self,
+        timestamp: i64,
+        wait_order_response: i64,
+        wake: Wake,
+    ) -> Result<bool, Error> {
         loop {
             match self.evs.next() {
                 Some(ev) => {
+                    if self
+                        .fire_staleness_watchdogs(ev.timestamp.min(timestamp.saturating_add(1)))?
+                    {
+                        continue;
+                    }
                     if ev.timestamp > timestamp {
                         self.cur_ts = timestamp;
+                        self.deliver_pending_events();
                         return Ok(true);
                     }
                     match ev.ty {
@@ -360,14 +1355,25 @@ where
                             match local.process_data() {
                                 Ok((next_ts, _)) => {
                                     self.evs.update_local_data(ev.asset_no, next_ts);
+                                    if let Some(staleness) = &mut self.staleness[ev.asset_no] {
+                                        staleness.arm(ev.timestamp);
+                                    }
                                 }
                                 Err(Error::EndOfData) => {
                                     self.evs.invalidate_local_data(ev.asset_no);
+                                    if let Some(staleness) = &mut self.staleness[ev.asset_no] {
+                                        staleness.deadline = None;
+                                    }
                                 }
                                 Err(e) => {
                                     return Err(e);
                                 }
                             }
+                            if wake != Wake::Never {
+                                self.cur_ts = ev.timestamp;
+                                self.deliver_pending_events();
+                                return Ok(true);
+                            }
                         }
                         EventType::LocalOrder => {
                             let local = unsafe { self.local.get_unchecked_mut(ev.asset_no) };
@@ -376,6 +1382,11 @@ where
                                 ev.asset_no,
                                 local.frontmost_recv_order_timestamp(),
                             );
+                            if wake == Wake::FeedOrOrderResponse {
+                                self.cur_ts = ev.timestamp;
+                                self.deliver_pending_events();
+                                return Ok(true);
+                            }
                         }
                         EventType::ExchData => {
                             let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
@@ -392,8 +1403,9 @@ where
                             }
                             self.evs.update_local_order(
                                 ev.asset_no,
-                                exch.frontmost_send_order_timestamp(),
+                                self.local[ev.asset_no].frontmost_recv_order_timestamp(),
                             );
+                            self.update_shared_local_orders(ev.asset_no);
                         }
                         EventType::ExchOrder => {
                             let exch = unsafe { self.exch.get_unchecked_mut(ev.asset_no) };
@@ -402,6 +1414,13 @@ where
                                 ev.asset_no,
                                 exch.frontmost_recv_order_timestamp(),
                             );
+                            // The responses can arrive at the same time as the next market data,
+                            // so they are scheduled right away to be ordered against it.
+                            self.evs.update_local_order(
+                                ev.asset_no,
+                                self.local[ev.asset_no].frontmost_recv_order_timestamp(),
+                            );
+                            self.update_shared_local_orders(ev.asset_no);
                         }
                     }
                 }
@@ -439,7 +1458,7 @@ where
         &self.local.get(asset_no).unwrap().depth()
     }
 
-    fn trade(&self, asset_no: usize) -> &Vec<Event> {
+    fn trade(&self, asset_no: usize) -> &[Event] {
         let local = self.local.get(asset_no).unwrap();
         local.trade()
     }
@@ -458,10 +1477,37 @@ where
         }
     }
 
+    fn external_events(&self) -> &Vec<ExternalEvent> {
+        &self.external_events
+    }
+
+    fn clear_external_events(&mut self) {
+        self.external_events.clear();
+    }
+
+    fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+
     fn orders(&self, asset_no: usize) -> &HashMap<i64, Order<Q>> {
         &self.local.get(asset_no).unwrap().orders()
     }
 
+    fn order_entry_latency(&self, asset_no: usize, order_id: i64) -> Option<i64> {
+        self.local
+            .get(asset_no)
+            .unwrap()
+            .order_entry_latency(order_id)
+    }
+
+    fn order_budget(&self, _asset_no: usize) -> Option<u32> {
+        None
+    }
+
+    fn features(&self, asset_no: usize) -> &[f64] {
+        self.local.get(asset_no).unwrap().features()
+    }
+
     fn submit_buy_order(
         &mut self,
         asset_no: usize,
@@ -472,6 +1518,14 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        if let Some(schedule) = self.schedule.as_ref() {
+            if !schedule.is_active(self.cur_ts) {
+                return Err(Error::OutsideSchedule);
+            }
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
             order_id,
@@ -501,6 +1555,14 @@ where
         order_type: OrdType,
         wait: bool,
     ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        if let Some(schedule) = self.schedule.as_ref() {
+            if !schedule.is_active(self.cur_ts) {
+                return Err(Error::OutsideSchedule);
+            }
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.submit_order(
             order_id,
@@ -521,6 +1583,9 @@ where
     }
 
     fn cancel(&mut self, asset_no: usize, order_id: i64, wait: bool) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
         let local = self.local.get_mut(asset_no).unwrap();
         local.cancel(order_id, self.cur_ts)?;
         self.evs
@@ -532,6 +1597,49 @@ where
         Ok(true)
     }
 
+    fn reduce_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.reduce(order_id, qty, self.cur_ts)?;
+        self.evs
+            .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+
+        if wait {
+            return self.goto(UNTIL_END_OF_DATA, order_id);
+        }
+        Ok(true)
+    }
+
+    fn modify_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error> {
+        if !self.charge_compute_time()? {
+            return Ok(false);
+        }
+        let local = self.local.get_mut(asset_no).unwrap();
+        local.modify(order_id, price, qty, self.cur_ts)?;
+        self.evs
+            .update_exch_order(asset_no, local.frontmost_send_order_timestamp());
+
+        if wait {
+            return self.goto(UNTIL_END_OF_DATA, order_id);
+        }
+        Ok(true)
+    }
+
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
         match asset_no {
             Some(asset_no) => {
@@ -549,18 +1657,36 @@ where
     }
 
     fn elapse(&mut self, duration: i64) -> Result<bool, Self::Error> {
-        if self.cur_ts == i64::MAX {
-            self.initialize_evs()?;
-            match self.evs.next() {
-                Some(ev) => {
-                    self.cur_ts = ev.timestamp;
-                }
-                None => {
-                    return Ok(false);
-                }
+        if !self.start()? {
+            return Ok(false);
+        }
+        let result = self.goto(self.cur_ts + duration, WAIT_ORDER_RESPONSE_NONE)?;
+        self.finish_wakeup(result)
+    }
+
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, Self::Error> {
+        if !self.start()? {
+            return Ok(false);
+        }
+        let deadline = self.cur_ts + timeout;
+        let wake = if include_order_resp {
+            Wake::FeedOrOrderResponse
+        } else {
+            Wake::Feed
+        };
+        let mut result = self.goto_(deadline, WAIT_ORDER_RESPONSE_NONE, wake)?;
+        if let Some(interval) = self.wakeup_interval {
+            let next_wakeup = self.last_wakeup.saturating_add(interval).min(deadline);
+            if result && self.cur_ts < next_wakeup {
+                result = self.goto(next_wakeup, WAIT_ORDER_RESPONSE_NONE)?;
             }
         }
-        self.goto(self.cur_ts + duration, WAIT_ORDER_RESPONSE_NONE)
+        self.last_wakeup = self.cur_ts;
+        self.finish_wakeup(result)
     }
 
     fn elapse_bt(&mut self, duration: i64) -> Result<bool, Self::Error> {
@@ -0,0 +1,353 @@
+use crate::{
+    backtest::{
+        assettype::AssetType,
+        models::FlatFee,
+        reader::{
+            Reader,
+            EXCH_ASK_DEPTH_CLEAR_EVENT,
+            EXCH_ASK_DEPTH_EVENT,
+            EXCH_ASK_DEPTH_SNAPSHOT_EVENT,
+            EXCH_BID_DEPTH_CLEAR_EVENT,
+            EXCH_BID_DEPTH_EVENT,
+            EXCH_BID_DEPTH_SNAPSHOT_EVENT,
+            EXCH_BUY_TRADE_EVENT,
+            EXCH_EVENT,
+            EXCH_SELL_TRADE_EVENT,
+        },
+        state::State,
+        Error,
+    },
+    depth::{MarketDepth, Tick, INVALID_MAX, INVALID_MIN},
+    ty::{Event, OrdType, Order, Side, Status, TimeInForce, BUY, SELL},
+};
+
+/// Quotes produced by a stateless quoting rule. Each side is given as `(price, qty)`, and `None`
+/// means no quote on that side.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Quote {
+    pub bid: Option<(f32, f32)>,
+    pub ask: Option<(f32, f32)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Resting {
+    price_tick: i64,
+    qty: f32,
+}
+
+struct Book<AT>
+where
+    AT: AssetType,
+{
+    bid: Option<Resting>,
+    ask: Option<Resting>,
+    pending: Option<(i64, Quote)>,
+    state: State<AT>,
+}
+
+/// Replays the market data once while evaluating many parameterizations of a stateless quoting
+/// rule simultaneously. The market depth is shared, and each parameterization has its own quotes
+/// and state.
+///
+/// This is intended for coarse parameter scans before running full-fidelity backtests, so the
+/// fill model is simplified:
+/// * The quoting rule is evaluated every `interval` against the exchange-side market depth, and
+///   the resulting quotes replace the resting quotes after `latency`.
+/// * A resting quote is fully filled at its price only when the market trades through it or the
+///   opposite best price crosses it, since the queue position is not modeled.
+/// * A quote crossing the opposite best price when it replaces the resting quote is fully filled
+///   at the opposite best price as a taker.
+pub struct BatchBacktest<P, AT, MD>
+where
+    AT: AssetType,
+    MD: MarketDepth,
+{
+    reader: Reader<Event>,
+    depth: MD,
+    params: Vec<P>,
+    books: Vec<Book<AT>>,
+    interval: i64,
+    latency: i64,
+    // The mid price as of when the book last had both sides.
+    last_mid: f32,
+}
+
+impl<P, AT, MD> BatchBacktest<P, AT, MD>
+where
+    AT: AssetType + Clone,
+    MD: MarketDepth,
+{
+    pub fn new(
+        reader: Reader<Event>,
+        depth: MD,
+        asset_type: AT,
+        params: Vec<P>,
+        interval: i64,
+        latency: i64,
+    ) -> Self {
+        if interval <= 0 {
+            panic!();
+        }
+        let books = params
+            .iter()
+            .map(|_| Book {
+                bid: None,
+                ask: None,
+                pending: None,
+                state: State::new(asset_type.clone()),
+            })
+            .collect();
+        Self {
+            reader,
+            depth,
+            params,
+            books,
+            interval,
+            latency,
+            last_mid: f32::NAN,
+        }
+    }
+
+    /// Sets the maker and taker fee rates applied to all parameterizations.
+    pub fn fee(mut self, maker_fee: f64, taker_fee: f64) -> Self {
+        for book in self.books.iter_mut() {
+            book.state.fee_model = Box::new(FlatFee::new(maker_fee, taker_fee));
+        }
+        self
+    }
+
+    /// Replays the data until the end, evaluating `rule` with each parameterization, the market
+    /// depth, and the parameterization's position.
+    pub fn run<F>(&mut self, rule: F) -> Result<(), Error>
+    where
+        F: Fn(&P, &MD, f64) -> Quote,
+    {
+        let mut next_eval_ts = i64::MIN;
+        loop {
+            let data = match self.reader.next() {
+                Ok(data) => data,
+                Err(Error::EndOfData) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            for row_num in 0..data.len() {
+                let row = &data[row_num];
+                if row.ev & EXCH_EVENT != EXCH_EVENT {
+                    continue;
+                }
+
+                if next_eval_ts == i64::MIN {
+                    next_eval_ts = row.exch_ts;
+                }
+                while row.exch_ts >= next_eval_ts {
+                    self.evaluate(&rule, next_eval_ts);
+                    next_eval_ts += self.interval;
+                }
+                self.activate(row.exch_ts);
+                self.process(row);
+                let best_bid_tick = self.depth.best_bid_tick().to_i64();
+                let best_ask_tick = self.depth.best_ask_tick().to_i64();
+                if best_bid_tick != INVALID_MIN && best_ask_tick != INVALID_MAX {
+                    self.last_mid = (self.depth.best_bid() + self.depth.best_ask()) / 2.0;
+                }
+            }
+            self.reader.release(data);
+        }
+    }
+
+    /// Returns the states of the parameterizations in the order of the given parameters.
+    pub fn states(&self) -> impl Iterator<Item = &State<AT>> {
+        self.books.iter().map(|book| &book.state)
+    }
+
+    /// Returns the equities of the parameterizations, marked at the mid price as of when the
+    /// book last had both sides, which is `NaN` if the book has never had both sides.
+    pub fn equities(&self) -> Vec<f64> {
+        self.books
+            .iter()
+            .map(|book| book.state.equity(self.last_mid))
+            .collect()
+    }
+
+    pub fn params(&self) -> &[P] {
+        &self.params
+    }
+
+    pub fn depth(&self) -> &MD {
+        &self.depth
+    }
+
+    fn evaluate<F>(&mut self, rule: &F, timestamp: i64)
+    where
+        F: Fn(&P, &MD, f64) -> Quote,
+    {
+        for (params, book) in self.params.iter().zip(self.books.iter_mut()) {
+            let quote = rule(params, &self.depth, book.state.position);
+            book.pending = Some((timestamp + self.latency, quote));
+        }
+    }
+
+    fn activate(&mut self, timestamp: i64) {
+        let tick_size = self.depth.tick_size();
+        let best_bid_tick = self.depth.best_bid_tick().to_i64();
+        let best_ask_tick = self.depth.best_ask_tick().to_i64();
+        for book in self.books.iter_mut() {
+            let quote = match book.pending {
+                Some((activation_ts, quote)) if activation_ts <= timestamp => quote,
+                _ => continue,
+            };
+            book.pending = None;
+
+            book.bid = quote.bid.map(|(price, qty)| Resting {
+                price_tick: i64::from_price(price, tick_size),
+                qty,
+            });
+            if let Some(bid) = book.bid {
+                if best_ask_tick != INVALID_MAX && bid.price_tick >= best_ask_tick {
+                    fill(
+                        &mut book.state,
+                        Side::Buy,
+                        best_ask_tick,
+                        tick_size,
+                        bid.qty,
+                        false,
+                    );
+                    book.bid = None;
+                }
+            }
+
+            book.ask = quote.ask.map(|(price, qty)| Resting {
+                price_tick: i64::from_price(price, tick_size),
+                qty,
+            });
+            if let Some(ask) = book.ask {
+                if best_bid_tick != INVALID_MIN && ask.price_tick <= best_bid_tick {
+                    fill(
+                        &mut book.state,
+                        Side::Sell,
+                        best_bid_tick,
+                        tick_size,
+                        ask.qty,
+                        false,
+                    );
+                    book.ask = None;
+                }
+            }
+        }
+    }
+
+    fn process(&mut self, row: &Event) {
+        let tick_size = self.depth.tick_size();
+        if row.ev & EXCH_BID_DEPTH_CLEAR_EVENT == EXCH_BID_DEPTH_CLEAR_EVENT {
+            self.depth.clear_depth(BUY, row.px);
+        } else if row.ev & EXCH_ASK_DEPTH_CLEAR_EVENT == EXCH_ASK_DEPTH_CLEAR_EVENT {
+            self.depth.clear_depth(SELL, row.px);
+        } else if row.ev & EXCH_BID_DEPTH_EVENT == EXCH_BID_DEPTH_EVENT
+            || row.ev & EXCH_BID_DEPTH_SNAPSHOT_EVENT == EXCH_BID_DEPTH_SNAPSHOT_EVENT
+        {
+            let (_, _, best_bid_tick, _, _, _) =
+                self.depth.update_bid_depth(row.px, row.qty, row.exch_ts);
+            let best_bid_tick = best_bid_tick.to_i64();
+            // The best bid crossing a resting ask fills it.
+            for book in self.books.iter_mut() {
+                if let Some(ask) = book.ask {
+                    if best_bid_tick != INVALID_MIN && ask.price_tick <= best_bid_tick {
+                        fill(
+                            &mut book.state,
+                            Side::Sell,
+                            ask.price_tick,
+                            tick_size,
+                            ask.qty,
+                            true,
+                        );
+                        book.ask = None;
+                    }
+                }
+            }
+        } else if row.ev & EXCH_ASK_DEPTH_EVENT == EXCH_ASK_DEPTH_EVENT
+            || row.ev & EXCH_ASK_DEPTH_SNAPSHOT_EVENT == EXCH_ASK_DEPTH_SNAPSHOT_EVENT
+        {
+            let (_, _, best_ask_tick, _, _, _) =
+                self.depth.update_ask_depth(row.px, row.qty, row.exch_ts);
+            let best_ask_tick = best_ask_tick.to_i64();
+            // The best ask crossing a resting bid fills it.
+            for book in self.books.iter_mut() {
+                if let Some(bid) = book.bid {
+                    if best_ask_tick != INVALID_MAX && bid.price_tick >= best_ask_tick {
+                        fill(
+                            &mut book.state,
+                            Side::Buy,
+                            bid.price_tick,
+                            tick_size,
+                            bid.qty,
+                            true,
+                        );
+                        book.bid = None;
+                    }
+                }
+            }
+        } else if row.ev & EXCH_BUY_TRADE_EVENT == EXCH_BUY_TRADE_EVENT {
+            // A buy trade through a resting ask fills it.
+            let price_tick = i64::from_price(row.px, tick_size);
+            for book in self.books.iter_mut() {
+                if let Some(ask) = book.ask {
+                    if ask.price_tick < price_tick {
+                        fill(
+                            &mut book.state,
+                            Side::Sell,
+                            ask.price_tick,
+                            tick_size,
+                            ask.qty,
+                            true,
+                        );
+                        book.ask = None;
+                    }
+                }
+            }
+        } else if row.ev & EXCH_SELL_TRADE_EVENT == EXCH_SELL_TRADE_EVENT {
+            // A sell trade through a resting bid fills it.
+            let price_tick = i64::from_price(row.px, tick_size);
+            for book in self.books.iter_mut() {
+                if let Some(bid) = book.bid {
+                    if bid.price_tick > price_tick {
+                        fill(
+                            &mut book.state,
+                            Side::Buy,
+                            bid.price_tick,
+                            tick_size,
+                            bid.qty,
+                            true,
+                        );
+                        book.bid = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn fill<AT>(
+    state: &mut State<AT>,
+    side: Side,
+    exec_price_tick: i64,
+    tick_size: f32,
+    qty: f32,
+    maker: bool,
+) where
+    AT: AssetType,
+{
+    let mut order = Order::<()>::new(
+        0,
+        exec_price_tick,
+        tick_size,
+        qty,
+        side,
+        OrdType::Limit,
+        TimeInForce::GTC,
+    );
+    order.exec_price_tick = exec_price_tick;
+    order.exec_qty = qty;
+    order.leaves_qty = 0.0;
+    order.maker = maker;
+    order.status = Status::Filled;
+    state.apply_fill(&order);
+}
@@ -0,0 +1,222 @@
+use std::{
+    fs,
+    io::{Error as IoError, ErrorKind},
+    path::Path,
+};
+
+use chrono::{Duration, NaiveDate};
+
+use crate::{
+    backtest::reader::{read_data, LOCAL_EVENT},
+    ty::Event,
+};
+
+/// An ordered list of data files built from a pattern, so that a long backtest doesn't need to
+/// list the files one by one.
+///
+/// The pattern supports the following expansions, which can be combined:
+/// * `{20240201..20240215}`: the calendar dates from the first to the last, inclusive, in
+///   `YYYYMMDD` format.
+/// * `{1..12}`, `{01..12}`: the integers from the first to the last, inclusive, zero-padded to
+///   the width of the first if it starts with `0`.
+/// * `{a,b,c}`: each of the alternatives.
+/// * `*` and `?` in the file name: the existing files that match, in lexicographical order.
+///
+/// For example, `SOLUSDT_{20240201..20240215}.npz` expands to `SOLUSDT_20240201.npz` through
+/// `SOLUSDT_20240215.npz`.
+#[derive(Clone, Debug)]
+pub struct Catalog {
+    files: Vec<String>,
+}
+
+impl Catalog {
+    /// Expands the pattern and verifies that all the expanded files exist.
+    pub fn new(pattern: &str) -> Result<Self, IoError> {
+        Self::from_files(expand(pattern)?)
+    }
+
+    /// Constructs a catalog from the given files, verifying that they exist.
+    pub fn from_files(files: Vec<String>) -> Result<Self, IoError> {
+        if files.is_empty() {
+            return Err(IoError::new(ErrorKind::NotFound, "no data file"));
+        }
+        for file in files.iter() {
+            if !Path::new(file).is_file() {
+                return Err(IoError::new(
+                    ErrorKind::NotFound,
+                    format!("data file not found: {}", file),
+                ));
+            }
+        }
+        Ok(Self { files })
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    pub fn into_files(self) -> Vec<String> {
+        self.files
+    }
+
+    /// Reads the files and returns the covered time range as the first and the last local
+    /// timestamps. Returns an error if the files are not in chronological order, that is, if a
+    /// file contains a local event earlier than the last local event of the preceding file.
+    pub fn time_range(&self) -> Result<(i64, i64), IoError> {
+        let mut range: Option<(i64, i64)> = None;
+        let mut prev_file = "";
+        for file in self.files.iter() {
+            let data = read_data::<Event>(file)?;
+            let mut file_range: Option<(i64, i64)> = None;
+            for i in 0..data.len() {
+                let row = &data[i];
+                if row.ev & LOCAL_EVENT == LOCAL_EVENT {
+                    file_range = Some(match file_range {
+                        Some((start, end)) => (start.min(row.local_ts), end.max(row.local_ts)),
+                        None => (row.local_ts, row.local_ts),
+                    });
+                }
+            }
+            let Some((file_start, file_end)) = file_range else {
+                continue;
+            };
+            range = Some(match range {
+                Some((start, end)) => {
+                    if file_start < end {
+                        return Err(IoError::new(
+                            ErrorKind::InvalidData,
+                            format!("{} overlaps or precedes {}", file, prev_file),
+                        ));
+                    }
+                    (start, file_end)
+                }
+                None => (file_start, file_end),
+            });
+            prev_file = file;
+        }
+        range.ok_or_else(|| IoError::new(ErrorKind::InvalidData, "no local event"))
+    }
+}
+
+/// Expands the pattern into the list of file paths in order. See [`Catalog`] for the syntax.
+/// Brace expansions don't check whether the files exist, while wildcards match only the
+/// existing files.
+pub fn expand(pattern: &str) -> Result<Vec<String>, IoError> {
+    let mut expanded = Vec::new();
+    for path in expand_braces(pattern)? {
+        if path.contains(['*', '?']) {
+            expanded.extend(expand_wildcard(&path)?);
+        } else {
+            expanded.push(path);
+        }
+    }
+    Ok(expanded)
+}
+
+fn invalid_pattern(pattern: &str) -> IoError {
+    IoError::new(
+        ErrorKind::InvalidInput,
+        format!("invalid pattern: {}", pattern),
+    )
+}
+
+fn expand_braces(pattern: &str) -> Result<Vec<String>, IoError> {
+    let Some(open) = pattern.find('{') else {
+        if pattern.contains('}') {
+            return Err(invalid_pattern(pattern));
+        }
+        return Ok(vec![pattern.to_string()]);
+    };
+    let close = open
+        + pattern[open..]
+            .find('}')
+            .ok_or_else(|| invalid_pattern(pattern))?;
+    let prefix = &pattern[..open];
+    let body = &pattern[(open + 1)..close];
+    let rest = expand_braces(&pattern[(close + 1)..])?;
+
+    let items = if let Some((first, last)) = body.split_once("..") {
+        expand_range(first.trim(), last.trim()).ok_or_else(|| invalid_pattern(pattern))?
+    } else {
+        body.split(',').map(|item| item.to_string()).collect()
+    };
+
+    let mut expanded = Vec::with_capacity(items.len() * rest.len());
+    for item in items {
+        for suffix in rest.iter() {
+            expanded.push(format!("{}{}{}", prefix, item, suffix));
+        }
+    }
+    Ok(expanded)
+}
+
+fn expand_range(first: &str, last: &str) -> Option<Vec<String>> {
+    if first.len() == 8 && last.len() == 8 {
+        if let (Ok(first), Ok(last)) = (
+            NaiveDate::parse_from_str(first, "%Y%m%d"),
+            NaiveDate::parse_from_str(last, "%Y%m%d"),
+        ) {
+            let mut dates = Vec::new();
+            let mut date = first;
+            while date <= last {
+                dates.push(date.format("%Y%m%d").to_string());
+                date += Duration::days(1);
+            }
+            return Some(dates);
+        }
+    }
+    let width = if first.starts_with('0') {
+        first.len()
+    } else {
+        0
+    };
+    let first = first.parse::<i64>().ok()?;
+    let last = last.parse::<i64>().ok()?;
+    Some(
+        (first..=last)
+            .map(|i| format!("{:0width$}", i, width = width))
+            .collect(),
+    )
+}
+
+fn expand_wildcard(pattern: &str) -> Result<Vec<String>, IoError> {
+    let path = Path::new(pattern);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| invalid_pattern(pattern))?;
+    if dir.is_some_and(|dir| dir.to_string_lossy().contains(['*', '?'])) {
+        return Err(invalid_pattern(pattern));
+    }
+
+    let mut matched = Vec::new();
+    for entry in fs::read_dir(dir.unwrap_or(Path::new(".")))? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        if wildcard_match(name.as_bytes(), file_name.as_bytes()) {
+            matched.push(match dir {
+                Some(dir) => dir.join(file_name).to_string_lossy().to_string(),
+                None => file_name.to_string(),
+            });
+        }
+    }
+    matched.sort();
+    Ok(matched)
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            wildcard_match(&pattern[1..], name)
+                || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
@@ -0,0 +1,292 @@
+//! A compact delta encoding of the event rows for recorded market data.
+//!
+//! The file consists of a 32-byte header, `HBTD`, the version, 3 reserved bytes, the tick size
+//! and the quantity unit as little-endian `f64`, and the number of rows as little-endian `u64`,
+//! followed by the length of the [`DataHeader`] as little-endian `u32` and the header in JSON,
+//! and then the encoded rows. Version 1 files, which have no [`DataHeader`], are still read. Each
+//! row starts with a control byte whose bits indicate which
+//! fields are the same as the previous row's and are omitted. The other fields are
+//! variable-length integers: the event flags, the exchange timestamp and the local timestamp as
+//! zigzag deltas from the previous row, the price as the zigzag delta in ticks from the previous
+//! price on the same side, and the quantity in quantity units.
+//!
+//! Prices and quantities are quantized to the tick size and the quantity unit, so the quantity
+//! unit should be the finest quantity increment of the data, which may be finer than the lot
+//! size.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Error as IoError, ErrorKind, Read, Write},
+    mem::size_of,
+};
+
+use crate::{
+    backtest::{
+        header::DataHeader,
+        reader::{aligned_vec, read_npz, Data},
+    },
+    ty::{Event, BUY, SELL},
+};
+
+pub const MAGIC: &[u8; 4] = b"HBTD";
+pub const VERSION: u8 = 2;
+const HEADER_LEN: usize = 32;
+
+const SAME_EV: u8 = 1;
+const SAME_EXCH_TS: u8 = 1 << 1;
+const SAME_LOCAL_TS: u8 = 1 << 2;
+const SAME_PX: u8 = 1 << 3;
+const SAME_QTY: u8 = 1 << 4;
+
+fn side_slot(ev: i64) -> usize {
+    if ev & BUY == BUY {
+        0
+    } else if ev & SELL == SELL {
+        1
+    } else {
+        2
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_zigzag(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Result<u64, IoError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *buf
+            .get(*pos)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "truncated row"))?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+        if shift > 63 {
+            return Err(IoError::new(ErrorKind::InvalidData, "invalid varint"));
+        }
+    }
+}
+
+fn read_zigzag(buf: &[u8], pos: &mut usize) -> Result<i64, IoError> {
+    let value = read_varint(buf, pos)?;
+    Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+}
+
+/// Encodes the event rows into the delta format. Prices are quantized to the tick size of the
+/// header.
+pub fn encode(rows: &[Event], header: &DataHeader, qty_unit: f64) -> Vec<u8> {
+    let tick_size = header.tick_size;
+    let json = header.to_json();
+    let mut buf = Vec::with_capacity(HEADER_LEN + 4 + json.len() + rows.len() * 8);
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    buf.extend_from_slice(&[0; 3]);
+    buf.extend_from_slice(&tick_size.to_le_bytes());
+    buf.extend_from_slice(&qty_unit.to_le_bytes());
+    buf.extend_from_slice(&(rows.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    buf.extend_from_slice(json.as_bytes());
+
+    let mut prev_ev = 0;
+    let mut prev_exch_ts = 0;
+    let mut prev_local_ts = 0;
+    let mut prev_px_tick = [0i64; 3];
+    let mut prev_qty = 0;
+    for row in rows {
+        let slot = side_slot(row.ev);
+        let px_tick = (row.px as f64 / tick_size).round() as i64;
+        let qty = (row.qty as f64 / qty_unit).round() as i64;
+
+        let mut ctrl = 0;
+        if row.ev == prev_ev {
+            ctrl |= SAME_EV;
+        }
+        if row.exch_ts == prev_exch_ts {
+            ctrl |= SAME_EXCH_TS;
+        }
+        if row.local_ts == prev_local_ts {
+            ctrl |= SAME_LOCAL_TS;
+        }
+        if px_tick == prev_px_tick[slot] {
+            ctrl |= SAME_PX;
+        }
+        if qty == prev_qty {
+            ctrl |= SAME_QTY;
+        }
+        buf.push(ctrl);
+        if ctrl & SAME_EV == 0 {
+            write_varint(&mut buf, row.ev as u64);
+        }
+        if ctrl & SAME_EXCH_TS == 0 {
+            write_zigzag(&mut buf, row.exch_ts.wrapping_sub(prev_exch_ts));
+        }
+        if ctrl & SAME_LOCAL_TS == 0 {
+            write_zigzag(&mut buf, row.local_ts.wrapping_sub(prev_local_ts));
+        }
+        if ctrl & SAME_PX == 0 {
+            write_zigzag(&mut buf, px_tick - prev_px_tick[slot]);
+        }
+        if ctrl & SAME_QTY == 0 {
+            write_zigzag(&mut buf, qty);
+        }
+
+        prev_ev = row.ev;
+        prev_exch_ts = row.exch_ts;
+        prev_local_ts = row.local_ts;
+        prev_px_tick[slot] = px_tick;
+        prev_qty = qty;
+    }
+    buf
+}
+
+fn data_header_len(buf: &[u8]) -> Result<usize, IoError> {
+    if buf.len() < HEADER_LEN || &buf[0..4] != MAGIC {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "not a delta-encoded data file",
+        ));
+    }
+    match buf[4] {
+        1 => Ok(0),
+        2 if buf.len() >= HEADER_LEN + 4 => {
+            Ok(u32::from_le_bytes(buf[HEADER_LEN..(HEADER_LEN + 4)].try_into().unwrap()) as usize)
+        }
+        2 => Err(IoError::new(ErrorKind::UnexpectedEof, "truncated header")),
+        version => Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("unsupported delta format version {}", version),
+        )),
+    }
+}
+
+/// Parses the [`DataHeader`] from the beginning of the delta format. Returns `None` for
+/// version 1, which has no header.
+pub fn decode_header(buf: &[u8]) -> Result<Option<DataHeader>, IoError> {
+    let len = data_header_len(buf)?;
+    if buf[4] == 1 {
+        return Ok(None);
+    }
+    let json = buf
+        .get((HEADER_LEN + 4)..(HEADER_LEN + 4 + len))
+        .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "truncated header"))?;
+    let json = std::str::from_utf8(json)
+        .map_err(|_| IoError::new(ErrorKind::InvalidData, "invalid data header"))?;
+    DataHeader::from_json(json).map(Some)
+}
+
+/// Decodes the delta format into a buffer of event rows.
+pub fn decode(buf: &[u8]) -> Result<Box<[u8]>, IoError> {
+    let data_header_len = data_header_len(buf)?;
+    let tick_size = f64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let qty_unit = f64::from_le_bytes(buf[16..24].try_into().unwrap());
+    let num_rows = u64::from_le_bytes(buf[24..32].try_into().unwrap()) as usize;
+
+    let row_size = size_of::<Event>();
+    let mut out = aligned_vec(num_rows * row_size);
+    let mut pos = match buf[4] {
+        1 => HEADER_LEN,
+        _ => HEADER_LEN + 4 + data_header_len,
+    };
+    let mut ev = 0;
+    let mut exch_ts = 0i64;
+    let mut local_ts = 0i64;
+    let mut px_tick = [0i64; 3];
+    let mut qty = 0;
+    for i in 0..num_rows {
+        let ctrl = *buf
+            .get(pos)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "truncated row"))?;
+        pos += 1;
+        if ctrl & SAME_EV == 0 {
+            ev = read_varint(buf, &mut pos)? as i64;
+        }
+        if ctrl & SAME_EXCH_TS == 0 {
+            exch_ts = exch_ts.wrapping_add(read_zigzag(buf, &mut pos)?);
+        }
+        if ctrl & SAME_LOCAL_TS == 0 {
+            local_ts = local_ts.wrapping_add(read_zigzag(buf, &mut pos)?);
+        }
+        let slot = side_slot(ev);
+        if ctrl & SAME_PX == 0 {
+            px_tick[slot] += read_zigzag(buf, &mut pos)?;
+        }
+        if ctrl & SAME_QTY == 0 {
+            qty = read_zigzag(buf, &mut pos)?;
+        }
+        let row = Event {
+            ev,
+            exch_ts,
+            local_ts,
+            px: (px_tick[slot] as f64 * tick_size) as f32,
+            qty: (qty as f64 * qty_unit) as f32,
+        };
+        unsafe {
+            std::ptr::write(out[i * row_size..].as_mut_ptr() as *mut Event, row);
+        }
+    }
+    Ok(out)
+}
+
+/// Writes the event rows to a file in the delta format.
+pub fn write_delta(
+    filepath: &str,
+    rows: &[Event],
+    header: &DataHeader,
+    qty_unit: f64,
+) -> Result<(), IoError> {
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    writer.write_all(&encode(rows, header, qty_unit))?;
+    writer.flush()
+}
+
+/// Reads a file in the delta format.
+pub fn read_delta<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
+    if size_of::<D>() != size_of::<Event>() {
+        return Err(IoError::new(
+            ErrorKind::InvalidInput,
+            "the delta format contains event rows only",
+        ));
+    }
+    let mut buf = Vec::new();
+    File::open(filepath)?.read_to_end(&mut buf)?;
+    Ok(Data::from_buf(decode(&buf)?, 0))
+}
+
+/// Reads only the [`DataHeader`] of a file in the delta format.
+pub fn read_delta_header(filepath: &str) -> Result<Option<DataHeader>, IoError> {
+    let mut file = File::open(filepath)?;
+    let mut buf = vec![0; HEADER_LEN];
+    file.read_exact(&mut buf)?;
+    if buf[4] != 1 {
+        buf.resize(HEADER_LEN + 4, 0);
+        file.read_exact(&mut buf[HEADER_LEN..])?;
+        let len = data_header_len(&buf)?;
+        buf.resize(HEADER_LEN + 4 + len, 0);
+        file.read_exact(&mut buf[(HEADER_LEN + 4)..])?;
+    }
+    decode_header(&buf)
+}
+
+/// Converts an `.npz` data file into the delta format.
+pub fn convert_npz(
+    src: &str,
+    dst: &str,
+    header: &DataHeader,
+    qty_unit: f64,
+) -> Result<(), IoError> {
+    let data = read_npz::<Event>(src)?;
+    let rows: Vec<Event> = (0..data.len()).map(|i| data[i].clone()).collect();
+    write_delta(dst, &rows, header, qty_unit)
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::backtest::stats::{Fill, Record};
+
+/// The output of a backtest run to be compared by [`diff_runs`], typically from
+/// [`Recorder`](crate::backtest::stats::Recorder) and
+/// [`FillJournal`](crate::backtest::stats::FillJournal).
+#[derive(Clone, Copy, Debug)]
+pub struct RunOutput<'a> {
+    pub records: &'a [Record],
+    pub fills: &'a [Fill],
+}
+
+/// A fill that differs between two runs. The fills are matched by the order id and the order of
+/// the fills of the order.
+#[derive(Clone, Copy, Debug)]
+pub enum FillDiff {
+    /// The fill occurs only in the left run.
+    Left(Fill),
+    /// The fill occurs only in the right run.
+    Right(Fill),
+    /// The fill occurs in both runs with a different timestamp, side, price, quantity or
+    /// liquidity.
+    Changed(Fill, Fill),
+}
+
+impl FillDiff {
+    pub fn order_id(&self) -> i64 {
+        match self {
+            FillDiff::Left(fill) | FillDiff::Right(fill) | FillDiff::Changed(fill, _) => {
+                fill.order_id
+            }
+        }
+    }
+
+    /// Returns the earlier timestamp of the fill in either run.
+    pub fn timestamp(&self) -> i64 {
+        match self {
+            FillDiff::Left(fill) | FillDiff::Right(fill) => fill.timestamp,
+            FillDiff::Changed(left, right) => left.timestamp.min(right.timestamp),
+        }
+    }
+}
+
+/// Where two backtest runs diverge, produced by [`diff_runs`].
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// The timestamp at which the runs first diverge, which is the earlier of the first
+    /// differing record and the first differing fill.
+    pub timestamp: i64,
+    /// The first differing records of the left and the right run, at the same index. A record is
+    /// `None` if the run has ended by then.
+    pub records: Option<(Option<Record>, Option<Record>)>,
+    /// The differing fills, in order of their timestamps.
+    pub fills: Vec<FillDiff>,
+    /// The ids of the orders whose fills differ, in order of their first differing fills.
+    pub order_ids: Vec<i64>,
+}
+
+/// Compares the outputs of two backtest runs, such as before and after a change to the engine,
+/// and returns where they first diverge, or `None` if they are the same. The floating-point
+/// values of the records are compared within `tolerance`, and the other values, including the
+/// timestamps, exactly.
+pub fn diff_runs(left: RunOutput, right: RunOutput, tolerance: f64) -> Option<Divergence> {
+    let records = first_record_diff(left.records, right.records, tolerance);
+    let fills = diff_fills(left.fills, right.fills, tolerance);
+    if records.is_none() && fills.is_empty() {
+        return None;
+    }
+
+    let record_ts = records.map(|(left, right)| match (left, right) {
+        (Some(left), Some(right)) => left.timestamp.min(right.timestamp),
+        (Some(record), None) | (None, Some(record)) => record.timestamp,
+        (None, None) => unreachable!(),
+    });
+    let fill_ts = fills.first().map(|fill| fill.timestamp());
+    let timestamp = match (record_ts, fill_ts) {
+        (Some(record_ts), Some(fill_ts)) => record_ts.min(fill_ts),
+        (Some(ts), None) | (None, Some(ts)) => ts,
+        (None, None) => unreachable!(),
+    };
+
+    let mut order_ids: Vec<i64> = Vec::new();
+    for fill in fills.iter() {
+        if !order_ids.contains(&fill.order_id()) {
+            order_ids.push(fill.order_id());
+        }
+    }
+    Some(Divergence {
+        timestamp,
+        records,
+        fills,
+        order_ids,
+    })
+}
+
+fn first_record_diff(
+    left: &[Record],
+    right: &[Record],
+    tolerance: f64,
+) -> Option<(Option<Record>, Option<Record>)> {
+    for i in 0..left.len().max(right.len()) {
+        let (l, r) = (left.get(i), right.get(i));
+        let same = match (l, r) {
+            (Some(l), Some(r)) => {
+                l.timestamp == r.timestamp
+                    && l.trade_num == r.trade_num
+                    && within(l.price as f64, r.price as f64, tolerance)
+                    && within(l.position, r.position, tolerance)
+                    && within(l.balance, r.balance, tolerance)
+                    && within(l.fee, r.fee, tolerance)
+                    && within(l.trade_qty, r.trade_qty, tolerance)
+                    && within(l.trade_amount, r.trade_amount, tolerance)
+                    && within(l.equity, r.equity, tolerance)
+            }
+            _ => false,
+        };
+        if !same {
+            return Some((l.copied(), r.copied()));
+        }
+    }
+    None
+}
+
+fn diff_fills(left: &[Fill], right: &[Fill], tolerance: f64) -> Vec<FillDiff> {
+    // key: (order_id, the number of the preceding fills of the order), value: the fill
+    let mut right_fills: HashMap<(i64, usize), Fill> = HashMap::new();
+    let mut count: HashMap<i64, usize> = HashMap::new();
+    for fill in right {
+        let n = count.entry(fill.order_id).or_default();
+        right_fills.insert((fill.order_id, *n), *fill);
+        *n += 1;
+    }
+
+    let mut diffs = Vec::new();
+    count.clear();
+    for fill in left {
+        let n = count.entry(fill.order_id).or_default();
+        match right_fills.remove(&(fill.order_id, *n)) {
+            Some(other) => {
+                let same = fill.timestamp == other.timestamp
+                    && fill.exch_timestamp == other.exch_timestamp
+                    && fill.side == other.side
+                    && fill.maker == other.maker
+                    && within(fill.price as f64, other.price as f64, tolerance)
+                    && within(fill.qty as f64, other.qty as f64, tolerance);
+                if !same {
+                    diffs.push(FillDiff::Changed(*fill, other));
+                }
+            }
+            None => diffs.push(FillDiff::Left(*fill)),
+        }
+        *n += 1;
+    }
+    let mut right_only: Vec<((i64, usize), Fill)> = right_fills.into_iter().collect();
+    right_only.sort_by_key(|(key, _)| *key);
+    diffs.extend(
+        right_only
+            .into_iter()
+            .map(|(_, fill)| FillDiff::Right(fill)),
+    );
+    diffs.sort_by_key(|diff| (diff.timestamp(), diff.order_id()));
+    diffs
+}
+
+fn within(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance || (a.is_nan() && b.is_nan())
+}
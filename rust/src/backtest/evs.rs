@@ -1,3 +1,5 @@
+use crate::ty::ResponsePriority;
+
 #[derive(Clone, Copy)]
 #[repr(C, align(32))]
 pub struct Event {
@@ -14,44 +16,78 @@ pub enum EventType {
     ExchOrder,
 }
 
+/// Decides which event is processed first when events of the same asset share the same
+/// timestamp. In either case, local-side events are processed before exchange-side events. The
+/// order on the local side, between the order responses and the market data delivered to the
+/// strategy, can be set separately by [`ResponsePriority`].
+#[derive(Eq, PartialEq, Clone, Copy, Debug, Default)]
+pub enum TieBreak {
+    /// Market data events are processed before order events at equal timestamps, so an order
+    /// arriving at the exchange sees the market state updated by the market event first.
+    #[default]
+    MarketFirst,
+    /// Order events are processed before market data events at equal timestamps, so an order
+    /// arriving at the exchange is processed against the market state prior to the market event.
+    OrderFirst,
+}
+
 pub struct EventSet {
     ev: Vec<Event>,
     invalid: usize,
     num_assets: usize,
+    // Slot offsets within each asset's four slots. Since the earliest slot wins at equal
+    // timestamps, these determine the tie-breaking order.
+    local_data: usize,
+    local_order: usize,
+    exch_data: usize,
+    exch_order: usize,
 }
 
 impl EventSet {
-    pub fn new(num_assets: usize) -> Self {
+    /// Constructs an event set. The local side follows `response_priority` if it is set, or
+    /// `tie_break` otherwise.
+    pub fn new(
+        num_assets: usize,
+        tie_break: TieBreak,
+        response_priority: Option<ResponsePriority>,
+    ) -> Self {
         if num_assets == 0 {
             panic!();
         }
+        let local_order_first = match response_priority {
+            Some(ResponsePriority::MarketDataFirst) => false,
+            Some(ResponsePriority::OrderResponseFirst) => true,
+            None => tie_break == TieBreak::OrderFirst,
+        };
+        let local = if local_order_first {
+            [EventType::LocalOrder, EventType::LocalData]
+        } else {
+            [EventType::LocalData, EventType::LocalOrder]
+        };
+        let exch = match tie_break {
+            TieBreak::MarketFirst => [EventType::ExchData, EventType::ExchOrder],
+            TieBreak::OrderFirst => [EventType::ExchOrder, EventType::ExchData],
+        };
+        let order = [local[0], local[1], exch[0], exch[1]];
+        let offset = |ty: EventType| order.iter().position(|item| *item == ty).unwrap();
         let mut ev = Vec::new();
         for asset_no in 0..num_assets {
-            ev.push(Event {
-                timestamp: i64::MAX,
-                asset_no,
-                ty: EventType::LocalData,
-            });
-            ev.push(Event {
-                timestamp: i64::MAX,
-                asset_no,
-                ty: EventType::LocalOrder,
-            });
-            ev.push(Event {
-                timestamp: i64::MAX,
-                asset_no,
-                ty: EventType::ExchData,
-            });
-            ev.push(Event {
-                timestamp: i64::MAX,
-                asset_no,
-                ty: EventType::ExchOrder,
-            });
+            for ty in order {
+                ev.push(Event {
+                    timestamp: i64::MAX,
+                    asset_no,
+                    ty,
+                });
+            }
         }
         Self {
             ev,
             invalid: 0,
             num_assets,
+            local_data: offset(EventType::LocalData),
+            local_order: offset(EventType::LocalOrder),
+            exch_data: offset(EventType::ExchData),
+            exch_order: offset(EventType::ExchOrder),
         }
     }
 
@@ -74,19 +110,19 @@ impl EventSet {
     }
 
     pub fn update_local_data(&mut self, asset_no: usize, timestamp: i64) {
-        self.update(4 * asset_no, timestamp);
+        self.update(4 * asset_no + self.local_data, timestamp);
     }
 
     pub fn update_local_order(&mut self, asset_no: usize, timestamp: i64) {
-        self.update(4 * asset_no + 1, timestamp);
+        self.update(4 * asset_no + self.local_order, timestamp);
     }
 
     pub fn update_exch_data(&mut self, asset_no: usize, timestamp: i64) {
-        self.update(4 * asset_no + 2, timestamp);
+        self.update(4 * asset_no + self.exch_data, timestamp);
     }
 
     pub fn update_exch_order(&mut self, asset_no: usize, timestamp: i64) {
-        self.update(4 * asset_no + 3, timestamp);
+        self.update(4 * asset_no + self.exch_order, timestamp);
     }
 
     fn invalidate(&mut self, evst_no: usize) {
@@ -96,10 +132,10 @@ impl EventSet {
     }
 
     pub fn invalidate_local_data(&mut self, asset_no: usize) {
-        self.invalidate(4 * asset_no);
+        self.invalidate(4 * asset_no + self.local_data);
     }
 
     pub fn invalidate_exch_data(&mut self, asset_no: usize) {
-        self.invalidate(4 * asset_no + 2);
+        self.invalidate(4 * asset_no + self.exch_data);
     }
 }
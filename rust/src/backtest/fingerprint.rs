@@ -0,0 +1,143 @@
+use std::{
+    fmt::{Debug, Write},
+    fs::File,
+    io::{Error as IoError, Read},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// The values longer than this, such as those of the models holding data, are kept as their
+/// digests.
+const MAX_VALUE_LEN: usize = 256;
+
+#[derive(Clone, Debug)]
+enum Value {
+    Text(String),
+    /// A data file, whose checksum is computed when the fingerprint is, so that building a
+    /// backtest doesn't read the whole data twice. The label is the path, or the URL of a
+    /// downloaded file.
+    File {
+        label: String,
+        path: String,
+    },
+}
+
+/// The configuration of an asset or a backtest, recorded as it's built, from which the
+/// [`Fingerprint`] is computed.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    entries: Vec<(String, Value)>,
+}
+
+impl Config {
+    /// Records the value by its `Debug` representation.
+    pub fn add(&mut self, key: &str, value: impl Debug) {
+        let mut text = format!("{value:?}");
+        if text.len() > MAX_VALUE_LEN {
+            text = format!("sha256:{}", digest(text.as_bytes()));
+        }
+        self.entries.push((key.to_string(), Value::Text(text)));
+    }
+
+    pub fn add_file(&mut self, key: &str, label: &str, path: &str) {
+        self.entries.push((
+            key.to_string(),
+            Value::File {
+                label: label.to_string(),
+                path: path.to_string(),
+            },
+        ));
+    }
+
+    pub fn append(&mut self, other: &Config) {
+        self.entries.extend(other.entries.iter().cloned());
+    }
+
+    /// Appends the entries of the other configuration with their keys prefixed.
+    pub fn extend(&mut self, prefix: &str, other: &Config) {
+        self.entries.extend(
+            other
+                .entries
+                .iter()
+                .map(|(key, value)| (format!("{prefix}.{key}"), value.clone())),
+        );
+    }
+
+    /// Computes the fingerprint, reading the data files to compute their checksums.
+    pub fn fingerprint(&self) -> Result<Fingerprint, IoError> {
+        let mut entries = vec![(
+            "hftbacktest".to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        )];
+        for (key, value) in &self.entries {
+            let value = match value {
+                Value::Text(text) => text.clone(),
+                Value::File { label, path } => {
+                    format!("{label} sha256:{}", file_digest(path)?)
+                }
+            };
+            entries.push((key.clone(), value));
+        }
+        let mut hasher = Sha256::new();
+        for (key, value) in &entries {
+            hasher.update(key.as_bytes());
+            hasher.update(b"=");
+            hasher.update(value.as_bytes());
+            hasher.update(b"\n");
+        }
+        Ok(Fingerprint {
+            digest: hex(&hasher.finalize()),
+            entries,
+        })
+    }
+}
+
+/// Identifies exactly what produced the results of a backtest: the crate version, the models and
+/// their parameters, the settings of the assets and the backtest, and the data with their
+/// checksums. It is obtained from
+/// [`MultiAssetMultiExchangeBacktest::fingerprint`](crate::backtest::backtest::MultiAssetMultiExchangeBacktest::fingerprint)
+/// and embedded into the outputs by [`Recorder::fingerprint`](crate::backtest::stats::Recorder::fingerprint).
+///
+/// The models are recorded by their `Debug` representations, so a custom model should derive
+/// `Debug` over all of its parameters to be told apart.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Fingerprint {
+    /// The SHA-256 digest of the entries in hex.
+    pub digest: String,
+    /// The configuration entries in the order in which they are recorded, keyed such as
+    /// `asset[0].latency_model` and `asset[0].data`, the latter once per data file.
+    pub entries: Vec<(String, String)>,
+}
+
+impl Fingerprint {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+pub(crate) fn digest(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn file_digest(path: &str) -> Result<String, IoError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut tmp = String::with_capacity(bytes.len() * 2);
+    for c in bytes {
+        write!(&mut tmp, "{:02x}", c).unwrap();
+    }
+    tmp
+}
@@ -0,0 +1,121 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use serde::{Deserialize, Serialize};
+
+/// The current version of the [`DataHeader`].
+pub const HEADER_VERSION: u32 = 1;
+
+/// The columns of the event rows, [`Event`](crate::ty::Event), in order.
+pub const EVENT_COLUMNS: [&str; 5] = ["ev", "exch_ts", "local_ts", "px", "qty"];
+
+/// The name of the entry that holds the [`DataHeader`] in an `.npz` archive. Since it is not an
+/// array, NumPy still loads the archive.
+pub const NPZ_HEADER_ENTRY: &str = "header.json";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampUnit {
+    #[default]
+    #[serde(rename = "ns")]
+    Nanosecond,
+    #[serde(rename = "us")]
+    Microsecond,
+    #[serde(rename = "ms")]
+    Millisecond,
+}
+
+/// Describes the content of a data file. It is embedded in the `.hbtd` format and optionally in
+/// an `.npz` archive as [`NPZ_HEADER_ENTRY`], and is validated against the asset configuration
+/// when the backtest is built, so that data recorded with a different tick size or in a
+/// different timestamp unit is rejected before the backtest runs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataHeader {
+    pub version: u32,
+    #[serde(default)]
+    pub symbol: String,
+    #[serde(default)]
+    pub exchange: String,
+    pub tick_size: f64,
+    #[serde(default)]
+    pub timestamp_unit: TimestampUnit,
+    pub columns: Vec<String>,
+}
+
+impl DataHeader {
+    pub fn new(tick_size: f64) -> Self {
+        Self {
+            version: HEADER_VERSION,
+            symbol: String::new(),
+            exchange: String::new(),
+            tick_size,
+            timestamp_unit: TimestampUnit::Nanosecond,
+            columns: EVENT_COLUMNS.iter().map(|col| col.to_string()).collect(),
+        }
+    }
+
+    pub fn symbol(self, symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            ..self
+        }
+    }
+
+    pub fn exchange(self, exchange: &str) -> Self {
+        Self {
+            exchange: exchange.to_string(),
+            ..self
+        }
+    }
+
+    pub fn timestamp_unit(self, timestamp_unit: TimestampUnit) -> Self {
+        Self {
+            timestamp_unit,
+            ..self
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(s: &str) -> Result<Self, IoError> {
+        serde_json::from_str(s).map_err(|error| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("invalid data header: {}", error),
+            )
+        })
+    }
+
+    /// Validates the header against the asset's tick size. The timestamps must be in
+    /// nanoseconds, which the backtester assumes, and the columns must match [`EVENT_COLUMNS`].
+    pub fn validate(&self, tick_size: f32) -> Result<(), IoError> {
+        let invalid = |msg: String| Err(IoError::new(ErrorKind::InvalidData, msg));
+        if self.version > HEADER_VERSION {
+            return invalid(format!("unsupported data header version {}", self.version));
+        }
+        if (self.tick_size as f32 - tick_size).abs() > tick_size.abs() * 1e-6 {
+            return invalid(format!(
+                "tick size mismatch: the data has {} but the asset has {}",
+                self.tick_size, tick_size
+            ));
+        }
+        if self.timestamp_unit != TimestampUnit::Nanosecond {
+            return invalid(format!(
+                "timestamp unit mismatch: the data is in {:?} but nanoseconds are expected",
+                self.timestamp_unit
+            ));
+        }
+        if self
+            .columns
+            .iter()
+            .map(|col| col.as_str())
+            .ne(EVENT_COLUMNS)
+        {
+            return invalid(format!(
+                "column mismatch: the data has {:?} but {:?} are expected",
+                self.columns, EVENT_COLUMNS
+            ));
+        }
+        Ok(())
+    }
+}
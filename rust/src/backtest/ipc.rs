@@ -0,0 +1,113 @@
+use std::{fs::File, io::Error as IoError, sync::Arc};
+
+use arrow::{
+    array::{
+        ArrayRef,
+        BooleanArray,
+        Float32Array,
+        Float64Array,
+        Int32Array,
+        Int64Array,
+        Int8Array,
+    },
+    datatypes::{DataType, Field, Schema},
+    error::ArrowError,
+    ipc::writer::FileWriter,
+    record_batch::RecordBatch,
+};
+
+use crate::backtest::stats::{Fill, Record};
+
+fn arrow_error(error: ArrowError) -> IoError {
+    IoError::other(error.to_string())
+}
+
+fn write(path: &str, schema: Schema, columns: Vec<ArrayRef>) -> Result<(), IoError> {
+    let schema = Arc::new(schema);
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(arrow_error)?;
+    let file = File::create(path)?;
+    let mut writer = FileWriter::try_new(file, &schema).map_err(arrow_error)?;
+    writer.write(&batch).map_err(arrow_error)?;
+    writer.finish().map_err(arrow_error)?;
+    Ok(())
+}
+
+/// Writes the records into an Arrow IPC (Feather V2) file, which pandas and polars can read
+/// without conversion.
+pub fn write_records(path: &str, records: &[Record]) -> Result<(), IoError> {
+    let schema = Schema::new(vec![
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("price", DataType::Float32, false),
+        Field::new("position", DataType::Float64, false),
+        Field::new("balance", DataType::Float64, false),
+        Field::new("fee", DataType::Float64, false),
+        Field::new("trade_num", DataType::Int32, false),
+        Field::new("trade_qty", DataType::Float64, false),
+        Field::new("trade_amount", DataType::Float64, false),
+        Field::new("equity", DataType::Float64, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            records.iter().map(|r| r.timestamp),
+        )),
+        Arc::new(Float32Array::from_iter_values(
+            records.iter().map(|r| r.price),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.position),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.balance),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.fee),
+        )),
+        Arc::new(Int32Array::from_iter_values(
+            records.iter().map(|r| r.trade_num),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.trade_qty),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.trade_amount),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            records.iter().map(|r| r.equity),
+        )),
+    ];
+    write(path, schema, columns)
+}
+
+/// Writes the fills into an Arrow IPC (Feather V2) file, which pandas and polars can read
+/// without conversion. The side is written as `1` for buy and `-1` for sell.
+pub fn write_fills(path: &str, fills: &[Fill]) -> Result<(), IoError> {
+    let schema = Schema::new(vec![
+        Field::new("timestamp", DataType::Int64, false),
+        Field::new("exch_timestamp", DataType::Int64, false),
+        Field::new("order_id", DataType::Int64, false),
+        Field::new("side", DataType::Int8, false),
+        Field::new("price", DataType::Float32, false),
+        Field::new("qty", DataType::Float32, false),
+        Field::new("maker", DataType::Boolean, false),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from_iter_values(
+            fills.iter().map(|f| f.timestamp),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            fills.iter().map(|f| f.exch_timestamp),
+        )),
+        Arc::new(Int64Array::from_iter_values(
+            fills.iter().map(|f| f.order_id),
+        )),
+        Arc::new(Int8Array::from_iter_values(
+            fills.iter().map(|f| f.side as i8),
+        )),
+        Arc::new(Float32Array::from_iter_values(
+            fills.iter().map(|f| f.price),
+        )),
+        Arc::new(Float32Array::from_iter_values(fills.iter().map(|f| f.qty))),
+        Arc::new(BooleanArray::from_iter(fills.iter().map(|f| Some(f.maker)))),
+    ];
+    write(path, schema, columns)
+}
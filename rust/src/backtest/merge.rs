@@ -0,0 +1,117 @@
+use std::{fs, io::Error as IoError, path::Path};
+
+use crate::{
+    backtest::reader::{read_data, write_npy},
+    ty::Event,
+};
+
+/// An event tagged with the asset number of the venue it came from.
+#[derive(Clone, Debug)]
+pub struct TaggedEvent {
+    pub asset_no: usize,
+    pub event: Event,
+}
+
+/// Merges the per-venue event streams into a single stream interleaved on local timestamps, and
+/// writes them back as per-asset files split at common time boundaries, which can be fed to the
+/// multi-exchange backtest as each asset's data.
+///
+/// Each venue is assigned the asset number in the order it is added. The order of the events
+/// within a venue is preserved; only the events of different venues are interleaved.
+#[derive(Clone, Debug, Default)]
+pub struct Merger {
+    venues: Vec<Vec<String>>,
+}
+
+impl Merger {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds a venue with its data files in chronological order.
+    pub fn venue(mut self, files: Vec<String>) -> Self {
+        self.venues.push(files);
+        self
+    }
+
+    fn read_venue(files: &[String]) -> Result<Vec<Event>, IoError> {
+        let mut events = Vec::new();
+        for file in files {
+            let data = read_data::<Event>(file)?;
+            events.extend((0..data.len()).map(|i| data[i].clone()));
+        }
+        Ok(events)
+    }
+
+    /// Reads all the venues and returns the merged stream.
+    pub fn merge(&self) -> Result<Vec<TaggedEvent>, IoError> {
+        let venues = self
+            .venues
+            .iter()
+            .map(|files| Self::read_venue(files))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut merged = Vec::with_capacity(venues.iter().map(|events| events.len()).sum());
+        let mut pos = vec![0; venues.len()];
+        loop {
+            // The venue with the earliest next event is taken, the lower asset number first on a
+            // tie.
+            let next = venues
+                .iter()
+                .enumerate()
+                .filter(|(asset_no, events)| pos[*asset_no] < events.len())
+                .min_by_key(|(asset_no, events)| events[pos[*asset_no]].local_ts);
+            let Some((asset_no, events)) = next else {
+                break;
+            };
+            merged.push(TaggedEvent {
+                asset_no,
+                event: events[pos[asset_no]].clone(),
+            });
+            pos[asset_no] += 1;
+        }
+        Ok(merged)
+    }
+
+    /// Merges the venues and writes each asset's events to `.npy` files in the directory, split
+    /// every `interval` of local time starting from the earliest event across all venues, so that
+    /// the periods are common to all assets. The files are named `{asset_no}_{start}.npy`, where
+    /// `start` is the start of the period. A period without any event of an asset produces no
+    /// file for that asset. Returns each asset's file paths in order.
+    pub fn write(&self, dir: &str, interval: i64) -> Result<Vec<Vec<String>>, IoError> {
+        fs::create_dir_all(dir)?;
+        let merged = self.merge()?;
+        let mut files = vec![Vec::new(); self.venues.len()];
+        let Some(origin) = merged.first().map(|tagged| tagged.event.local_ts) else {
+            return Ok(files);
+        };
+
+        let mut chunks: Vec<Vec<Event>> = vec![Vec::new(); self.venues.len()];
+        let mut chunk_start = origin;
+        let mut flush = |chunks: &mut Vec<Vec<Event>>, chunk_start: i64| {
+            for (asset_no, chunk) in chunks.iter_mut().enumerate() {
+                if chunk.is_empty() {
+                    continue;
+                }
+                let filepath = Path::new(dir)
+                    .join(format!("{}_{}.npy", asset_no, chunk_start))
+                    .to_string_lossy()
+                    .to_string();
+                write_npy(&filepath, chunk)?;
+                files[asset_no].push(filepath);
+                chunk.clear();
+            }
+            Ok::<_, IoError>(())
+        };
+        for tagged in merged {
+            // An event earlier than the current period, which occurs if a venue's events are not
+            // ordered by local timestamp, stays in the current period to preserve the order.
+            if tagged.event.local_ts >= chunk_start + interval {
+                flush(&mut chunks, chunk_start)?;
+                chunk_start += (tagged.event.local_ts - chunk_start) / interval * interval;
+            }
+            chunks[tagged.asset_no].push(tagged.event);
+        }
+        flush(&mut chunks, chunk_start)?;
+        Ok(files)
+    }
+}
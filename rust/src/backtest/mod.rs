@@ -1,30 +1,74 @@
 pub mod assettype;
 pub mod backtest;
+pub mod batch;
+pub mod catalog;
+pub mod delta;
+pub mod diff;
+pub mod fingerprint;
+pub mod header;
+#[cfg(feature = "arrow-ipc")]
+pub mod ipc;
+pub mod merge;
 pub mod models;
 pub mod order;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub mod proc;
+pub mod profile;
 pub mod reader;
+pub mod rebuild;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "report")]
+pub mod report;
 pub mod state;
+pub mod stats;
+pub mod stream;
+pub mod walkforward;
 
 mod evs;
+mod prefetch;
+#[cfg(any(feature = "hftbacktest-py", feature = "ffi"))]
+pub(crate) mod spec;
 
-use std::{io::Error as IoError, marker::PhantomData};
+use std::{
+    any::type_name,
+    fmt::Debug,
+    io::{Error as IoError, ErrorKind},
+    marker::PhantomData,
+};
 
+pub use evs::TieBreak;
+#[cfg(feature = "remote")]
+use remote::RemoteFile;
 use thiserror::Error;
 
 use crate::{
     backtest::{
         assettype::AssetType,
         backtest::MultiAssetMultiExchangeBacktest,
-        models::{LatencyModel, QueueModel},
+        catalog::Catalog,
+        fingerprint::{digest, Config},
+        models::{FeeModel, LatencyModel, LatencyOffset, QueueModel},
         order::OrderBus,
-        proc::{Local, LocalProcessor, NoPartialFillExchange, Processor},
-        reader::{Cache, Reader},
+        proc::{
+            L3Exchange,
+            Local,
+            LocalProcessor,
+            NoPartialFillExchange,
+            PartialFillExchange,
+            Processor,
+            ReferenceExchange,
+            SharedExchangeHandle,
+        },
+        profile::{GtxPolicy, ModifyPolicy, OrderRateLimit, OrderRateLimiter, VenueProfile},
+        reader::{read_data, Cache, Data, Reader},
         state::State,
     },
-    depth::hashmapmarketdepth::HashMapMarketDepth,
-    error::BuildError,
-    ty::Event,
+    depth::{hashmapmarketdepth::HashMapMarketDepth, l3marketdepth::L3MarketDepth, MarketDepth},
+    error::{BuildError, InvalidTransition},
+    feature::FeatureSet,
+    ty::{Adjustment, Event, ExternalEvent, L3Event, ResponsePriority},
 };
 
 #[derive(Error, Debug)]
@@ -39,42 +83,297 @@ pub enum Error {
     InvalidOrderRequest,
     #[error("order status is invalid to proceed the request")]
     InvalidOrderStatus,
+    #[error("order submission is not allowed by the schedule")]
+    OutsideSchedule,
+    #[error("{0}")]
+    InvalidStatusTransition(#[from] InvalidTransition),
     #[error("end of data")]
     EndOfData,
     #[error("data error: {0}")]
     DataError(#[from] IoError),
+    #[error("order latency at {0} is outside the range of the latency data")]
+    LatencyOutOfRange(i64),
+    #[error("the asset is reference only and doesn't accept orders")]
+    ReferenceOnly,
+}
+
+/// Models the strategy's compute time, which is charged to the backtest clock once per wakeup
+/// before the first order request, so that an order decided after heavy computation is
+/// timestamped later rather than as if the decision were instantaneous.
+#[derive(Clone, Copy, Debug, Default)]
+pub enum ComputeTime {
+    /// No compute time is charged.
+    #[default]
+    None,
+    /// Charges a fixed compute time per wakeup, in the same unit as the timestamps.
+    Constant(i64),
+    /// Charges the measured wall-clock time, in nanoseconds, from the wakeup to the first order
+    /// request.
+    Measured,
 }
 
-pub enum DataSource {
+/// How the local processor buffers the market trades delivered through
+/// [`Interface::trade`](crate::Interface::trade) until they are cleared by
+/// [`Interface::clear_last_trades`](crate::Interface::clear_last_trades). The buffer's capacity is
+/// kept across the clears, so it's allocated only when it grows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TradeBuffer {
+    /// Doesn't buffer the trades.
+    Disabled,
+    /// Buffers all the trades, preallocating for the given number of trades and growing beyond
+    /// it during bursts. The trades beyond the preallocated length are counted as overflow.
+    Unbounded(usize),
+    /// Buffers up to the given number of the most recent trades, dropping the oldest one when
+    /// full. The dropped trades are counted as overflow.
+    Ring(usize),
+}
+
+impl Default for TradeBuffer {
+    fn default() -> Self {
+        TradeBuffer::Unbounded(1000)
+    }
+}
+
+/// What the exchange does with the open orders when it rolls over to the next data file and the
+/// file begins with a re-snapshot of the book, such as at a daily session boundary. Since venues
+/// differ, such as those with sessions versus those trading 24/7, it's set per asset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RolloverPolicy {
+    /// Keeps the open orders with their queue positions as they are.
+    #[default]
+    Keep,
+    /// Keeps the open orders and re-derives their queue positions from the new snapshot by the
+    /// queue model, as if they were placed after the snapshot.
+    Requeue,
+    /// Expires the open orders before the new snapshot is applied.
+    Expire,
+}
+
+/// What the [`StalenessWatchdog`] does with the strategy's resting orders when it fires.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StalenessAction {
+    /// The local cancels the orders, as the bot's own watchdog does in production, so the cancels
+    /// reach the exchange after the order entry latency and the orders can still be filled in
+    /// the meantime.
+    #[default]
+    Cancel,
+    /// The exchange expires the orders at once, as a venue's cancel-on-disconnect does. With an
+    /// exchange shared by several strategy instances, the orders of all the instances expire.
+    Expire,
+}
+
+/// Pulls the strategy's resting orders of an asset when its recorded feed has a gap longer than
+/// the threshold, to model the production safety behavior of quoting only on a fresh feed. The
+/// watchdog fires once per gap, at the local timestamp of the last feed row plus the threshold,
+/// and is rearmed by the next feed row. It isn't armed until the first feed row, and the end of
+/// the data isn't regarded as a gap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StalenessWatchdog {
+    /// The longest gap tolerated between the feed rows, in the same unit as the timestamps.
+    pub threshold: i64,
+    pub action: StalenessAction,
+}
+
+/// The exchange processor that simulates the order matching.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExchangeKind {
+    /// Fills the entire leaves quantity of an order at once. See [`NoPartialFillExchange`].
+    #[default]
+    NoPartialFillExchange,
+    /// Fills a taker order level by level against the depth, consuming the taken quantity, and a
+    /// maker order by up to the traded quantity once its queue position is reached, with a
+    /// response per fill. See [`PartialFillExchange`].
+    PartialFillExchange,
+    /// Matches the orders against the book of individual orders built from the market-by-order
+    /// data set by [`BtAssetBuilder::l3_data`], with the exact queue positions. The queue model
+    /// is unused. See [`L3Exchange`].
+    L3Exchange,
+    /// Doesn't match orders, for an asset that the strategy only reads signals from, such as
+    /// the depth, the trades, and the features, while trading other assets with full fidelity.
+    /// The asset's data is processed only by the local, so lighter data than the full depth, such
+    /// as the trades only or the best bid and offer with [`BtAssetBuilder::bbo_feed`], is enough,
+    /// and the order submissions are rejected with [`Error::ReferenceOnly`]. The queue model is
+    /// unused. See [`ReferenceExchange`].
+    ReferenceOnly,
+}
+
+pub enum DataSource<D = Event> {
     File(String),
-    Array,
+    /// The files expanded from a pattern such as `SOLUSDT_{20240201..20240215}.npz`. See
+    /// [`Catalog`] for the syntax.
+    Glob(String),
+    Catalog(Catalog),
+    /// A file referenced by an `s3://`, `gs://`, or `https://` URL, which is downloaded into the
+    /// local cache. A `File` with such a URL is fetched the same way, without checksum
+    /// validation.
+    #[cfg(feature = "remote")]
+    Remote(RemoteFile),
+    /// A file referenced by an `s3://`, `gs://`, or `https://` URL, which, unlike
+    /// [`Remote`](Self::Remote), is downloaded when the backtest reaches it instead of when the
+    /// asset is built, or ahead of time in the background with
+    /// [`BtAssetBuilder::prefetch`]. Its [`DataHeader`](header::DataHeader), if any, isn't
+    /// validated.
+    #[cfg(feature = "remote")]
+    Url(String),
+    /// A Parquet file of the event schema, whose row groups are loaded one at a time as the
+    /// backtest progresses, so that a large file doesn't have to be converted to `.npz` first or
+    /// read into memory at once. See [`read_parquet`](parquet::read_parquet).
+    #[cfg(feature = "parquet")]
+    Parquet(String),
+    /// An `.npy` file, which is memory-mapped instead of read into memory, so that the backtests
+    /// on the same machine share the pages of the file and the loading doesn't have to copy it.
+    /// See [`mmap_data`](reader::mmap_data).
+    #[cfg(feature = "mmap")]
+    MmapFile(String),
+    /// The rows in memory.
+    Array(Data<D>),
 }
 
-pub struct BtAsset<Q> {
-    local: Box<dyn LocalProcessor<Q, HashMapMarketDepth>>,
-    exch: Box<dyn Processor>,
+/// Adds the data sources to the reader, keeping the first error in `data_error` to be reported
+/// when the asset is built.
+fn add_data<D>(
+    reader: &mut Reader<D>,
+    data: Vec<DataSource<D>>,
+    data_error: &mut Option<IoError>,
+    config: &mut Config,
+    key: &str,
+) where
+    D: Sized + Clone,
+{
+    for item in data {
+        match item {
+            #[cfg(feature = "remote")]
+            DataSource::File(filename) if RemoteFile::is_remote(&filename) => {
+                add_remote_file(reader, RemoteFile::new(&filename), data_error, config, key);
+            }
+            DataSource::File(filename) => {
+                config.add_file(key, &filename, &filename);
+                reader.add_file(filename);
+            }
+            DataSource::Glob(pattern) => match Catalog::new(&pattern) {
+                Ok(catalog) => {
+                    for filename in catalog.into_files() {
+                        config.add_file(key, &filename, &filename);
+                        reader.add_file(filename);
+                    }
+                }
+                Err(error) => {
+                    data_error.get_or_insert(error);
+                }
+            },
+            DataSource::Catalog(catalog) => {
+                for filename in catalog.into_files() {
+                    config.add_file(key, &filename, &filename);
+                    reader.add_file(filename);
+                }
+            }
+            #[cfg(feature = "remote")]
+            DataSource::Remote(remote) => {
+                add_remote_file(reader, remote, data_error, config, key);
+            }
+            #[cfg(feature = "remote")]
+            DataSource::Url(url) => {
+                // It isn't downloaded yet, so only the URL is recorded.
+                config.add(key, &url);
+                reader.add_file(url);
+            }
+            #[cfg(feature = "parquet")]
+            DataSource::Parquet(filename) => match parquet::row_group_keys(&filename) {
+                Ok(keys) => {
+                    config.add_file(key, &filename, &filename);
+                    for key in keys {
+                        reader.add_file(key);
+                    }
+                }
+                Err(error) => {
+                    data_error.get_or_insert(error);
+                }
+            },
+            #[cfg(feature = "mmap")]
+            DataSource::MmapFile(filename) => {
+                config.add_file(key, &filename, &filename);
+                reader.add_mmap_file(filename);
+            }
+            DataSource::Array(data) => {
+                config.add(
+                    key,
+                    format_args!("{} rows sha256:{}", data.len(), digest(data.as_bytes())),
+                );
+                reader.add_data(data);
+            }
+        }
+    }
 }
 
-pub struct BtAssetBuilder<Q, LM, AT, QM, F>
-where
-    F: Fn() -> HashMapMarketDepth,
+#[cfg(feature = "remote")]
+fn add_remote_file<D>(
+    reader: &mut Reader<D>,
+    remote: RemoteFile,
+    data_error: &mut Option<IoError>,
+    config: &mut Config,
+    key: &str,
+) where
+    D: Sized + Clone,
 {
+    match remote.fetch() {
+        Ok(filename) => {
+            config.add_file(key, remote.url(), &filename);
+            reader.add_file(filename)
+        }
+        Err(error) => {
+            data_error.get_or_insert(error);
+        }
+    }
+}
+
+pub struct BtAsset<Q, MD = HashMapMarketDepth> {
+    local: Box<dyn LocalProcessor<Q, MD>>,
+    exch: Box<dyn Processor>,
+    staleness_watchdog: Option<StalenessWatchdog>,
+    config: Config,
+}
+
+/// Builds an asset for the backtest. The local and the exchange each keep their own market depth
+/// created by the function set by [`depth`](Self::depth), such as [`HashMapMarketDepth`] or
+/// [`RoiVectorMarketDepth`](crate::depth::roivectormarketdepth::RoiVectorMarketDepth), which the
+/// queue model works on too.
+pub struct BtAssetBuilder<Q, LM, AT, QM, F> {
     latency_model: Option<LM>,
     asset_type: Option<AT>,
     queue_model: Option<QM>,
     depth_func: Option<F>,
+    latency_offset: LatencyOffset,
+    trade_aggregation: Option<i64>,
+    trade_buffer: TradeBuffer,
+    rollover_policy: RolloverPolicy,
+    exch_kind: ExchangeKind,
+    fee_model: Option<Box<dyn Fn() -> Box<dyn FeeModel>>>,
+    fee_model_config: Option<String>,
+    gtx_policy: GtxPolicy,
+    modify_policy: ModifyPolicy,
+    order_rate_limits: Vec<OrderRateLimit>,
+    max_open_orders: Option<usize>,
+    staleness_watchdog: Option<StalenessWatchdog>,
+    features: FeatureSet,
+    bbo_feed: bool,
     reader: Reader<Event>,
+    l3_reader: Reader<L3Event>,
+    data_error: Option<IoError>,
+    unknown_profile: Option<String>,
+    // The data sources, recorded for the fingerprint.
+    data_config: Config,
     _q_marker: PhantomData<Q>,
 }
 
-impl<Q, LM, AT, QM, F> BtAssetBuilder<Q, LM, AT, QM, F>
+impl<Q, LM, AT, QM, F, MD> BtAssetBuilder<Q, LM, AT, QM, F>
 where
-    F: Fn() -> HashMapMarketDepth,
-    AT: AssetType + Clone + 'static,
-    Local<AT, Q, LM, HashMapMarketDepth>: LocalProcessor<Q, HashMapMarketDepth>,
+    F: Fn() -> MD,
+    MD: MarketDepth + 'static,
+    AT: AssetType + Clone + Debug + 'static,
+    Local<AT, Q, LM, MD>: LocalProcessor<Q, MD>,
     Q: Clone + Default + 'static,
-    QM: QueueModel<Q> + 'static,
-    LM: LatencyModel + Clone + 'static,
+    QM: QueueModel<Q, MD> + Debug + 'static,
+    LM: LatencyModel + Clone + Debug + 'static,
 {
     pub fn new() -> Self {
         let cache = Cache::new();
@@ -85,20 +384,95 @@ where
             asset_type: None,
             queue_model: None,
             depth_func: None,
+            latency_offset: Default::default(),
+            trade_aggregation: None,
+            trade_buffer: Default::default(),
+            rollover_policy: Default::default(),
+            exch_kind: Default::default(),
+            fee_model: None,
+            fee_model_config: None,
+            gtx_policy: Default::default(),
+            modify_policy: Default::default(),
+            order_rate_limits: Vec::new(),
+            max_open_orders: None,
+            staleness_watchdog: None,
+            features: FeatureSet::new(),
+            bbo_feed: false,
             reader,
+            l3_reader: Reader::new(Cache::new()),
+            data_error: None,
+            unknown_profile: None,
+            data_config: Default::default(),
             _q_marker: Default::default(),
         }
     }
 
     pub fn data(mut self, data: Vec<DataSource>) -> Self {
-        for item in data {
-            match item {
-                DataSource::File(filename) => {
-                    self.reader.add_file(filename);
-                }
-                DataSource::Array => {
-                    todo!();
-                }
+        add_data(
+            &mut self.reader,
+            data,
+            &mut self.data_error,
+            &mut self.data_config,
+            "data",
+        );
+        self
+    }
+
+    /// Sets the market-by-order data, from which [`ExchangeKind::L3Exchange`] builds the book of
+    /// individual orders. The local still processes the market-by-price data set by
+    /// [`data`](Self::data), which can be derived from the market-by-order data by
+    /// [`depth_from_l3`](rebuild::depth_from_l3).
+    pub fn l3_data(mut self, data: Vec<DataSource<L3Event>>) -> Self {
+        add_data(
+            &mut self.l3_reader,
+            data,
+            &mut self.data_error,
+            &mut self.data_config,
+            "l3_data",
+        );
+        self
+    }
+
+    /// Loads the data files up to `depth` files ahead of the backtest in a background thread,
+    /// downloading the files referenced by [`DataSource::Url`] and decompressing them, so that
+    /// long multi-day backtests don't stall on I/O at every file boundary. Each prefetched file
+    /// is held in memory until it's read. By default, the files are loaded when they're reached.
+    pub fn prefetch(mut self, depth: usize) -> Self {
+        self.reader.prefetch(depth);
+        self.l3_reader.prefetch(depth);
+        self
+    }
+
+    /// Starts the asset from the depth snapshot written by
+    /// [`write_depth_snapshot`](reader::write_depth_snapshot), at its timestamp, instead of
+    /// building the book by replaying the data from the beginning, for the iterative research on
+    /// a late window of the data. The rows of the data at or before the timestamp, by the local
+    /// timestamp, are skipped, so the data should be the same as that of the run in which the
+    /// snapshot was taken, and should start at or before the timestamp.
+    ///
+    /// Both the local and the exchange start from the local depth of the snapshot, and the
+    /// exchange catches up by the rows received by the local after the timestamp, whose exchange
+    /// timestamps may precede it. [`ExchangeKind::L3Exchange`] still builds its book from the
+    /// whole market-by-order data.
+    pub fn initial_snapshot(mut self, filepath: &str) -> Self {
+        let snapshot = read_data::<Event>(filepath).and_then(|data| {
+            match data.as_slice().iter().map(|row| row.local_ts).max() {
+                Some(timestamp) => Ok((data, timestamp)),
+                None => Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("{filepath}: the depth snapshot is empty"),
+                )),
+            }
+        });
+        match snapshot {
+            Ok((data, timestamp)) => {
+                self.data_config
+                    .add_file("initial_snapshot", filepath, filepath);
+                self.reader
+                    .initial_snapshot(data, timestamp, |row| row.local_ts);
+            }
+            Err(error) => {
+                self.data_error.get_or_insert(error);
             }
         }
         self
@@ -132,14 +506,277 @@ where
         }
     }
 
-    pub fn build(self) -> Result<BtAsset<Q>, BuildError> {
+    /// Sets the fixed additional latencies applied on the local side per event class. By default,
+    /// no offsets are applied.
+    pub fn latency_offset(self, latency_offset: LatencyOffset) -> Self {
+        Self {
+            latency_offset,
+            ..self
+        }
+    }
+
+    /// Aggregates the consecutive trade prints of the same aggressor side within `window` of the
+    /// first print into one trade per price before feeding them to the queue model, for the feeds
+    /// that report one logical aggressor trade as many prints. The aggregated trade takes effect
+    /// at the first print's timestamp. By default, each print is fed as it is.
+    pub fn trade_aggregation(self, window: i64) -> Self {
+        Self {
+            trade_aggregation: Some(window),
+            ..self
+        }
+    }
+
+    /// Sets how the trades are buffered until they are cleared. The default is
+    /// [`TradeBuffer::Unbounded`] preallocated for 1000 trades. See [`TradeBuffer`].
+    pub fn trade_buffer(self, trade_buffer: TradeBuffer) -> Self {
+        Self {
+            trade_buffer,
+            ..self
+        }
+    }
+
+    /// Sets the features that the local processor maintains on every event of the asset, which
+    /// the strategy reads by [`Interface::features`](crate::Interface::features). See
+    /// [`FeatureSet`].
+    pub fn features(self, features: FeatureSet) -> Self {
+        Self { features, ..self }
+    }
+
+    /// Sets whether the depth events of the data are of the best bid and offer, each of which
+    /// replaces the best level of its side in the local's depth, so that a feed that doesn't
+    /// remove the previous best level when the best price moves builds the correct top of the
+    /// book. This applies only to the local, so it's meant for an asset of
+    /// [`ExchangeKind::ReferenceOnly`]. The default is `false`.
+    pub fn bbo_feed(self, bbo_feed: bool) -> Self {
+        Self { bbo_feed, ..self }
+    }
+
+    /// Sets what the exchange does with the open orders when the book is re-snapshotted at the
+    /// start of the next data file. The default is [`RolloverPolicy::Keep`].
+    pub fn rollover_policy(self, rollover_policy: RolloverPolicy) -> Self {
+        Self {
+            rollover_policy,
+            ..self
+        }
+    }
+
+    /// Sets the exchange processor. The default is [`ExchangeKind::NoPartialFillExchange`].
+    pub fn exchange(self, exch_kind: ExchangeKind) -> Self {
+        Self { exch_kind, ..self }
+    }
+
+    /// Sets the fee model, which the local and the exchange each get a copy of. The default
+    /// charges no fees. See [`FeeModel`].
+    pub fn fee_model<FM>(self, fee_model: FM) -> Self
+    where
+        FM: FeeModel + Clone + Debug + 'static,
+    {
+        Self {
+            fee_model_config: Some(format!("{fee_model:?}")),
+            fee_model: Some(Box::new(move || Box::new(fee_model.clone()))),
+            ..self
+        }
+    }
+
+    /// Sets what the exchange does with a post-only order that would take liquidity. The default
+    /// is [`GtxPolicy::Expire`].
+    pub fn gtx_policy(self, gtx_policy: GtxPolicy) -> Self {
+        Self { gtx_policy, ..self }
+    }
+
+    /// Sets whether a modified order keeps its queue position. The default is
+    /// [`ModifyPolicy::ResetPriority`].
+    pub fn modify_policy(self, modify_policy: ModifyPolicy) -> Self {
+        Self {
+            modify_policy,
+            ..self
+        }
+    }
+
+    /// Adds a limit of `max` order submissions per `window` at the exchange, beyond which the
+    /// submissions are rejected. By default, the submissions aren't limited.
+    pub fn order_rate_limit(mut self, max: u32, window: i64) -> Self {
+        self.order_rate_limits.push(OrderRateLimit { max, window });
+        self
+    }
+
+    /// Sets the cap of the open orders at the exchange, beyond which the new orders are rejected
+    /// with [`Status::TooManyOpenOrders`](crate::ty::Status::TooManyOpenOrders), so that a
+    /// strategy laddering deeper than the venue allows finds out in the backtest. By default, the
+    /// open orders aren't capped.
+    pub fn max_open_orders(self, max_open_orders: usize) -> Self {
+        Self {
+            max_open_orders: Some(max_open_orders),
+            ..self
+        }
+    }
+
+    /// Sets the [`StalenessWatchdog`] that pulls the strategy's resting orders when the feed of
+    /// the asset has a gap longer than its threshold. By default, there is no watchdog.
+    pub fn staleness_watchdog(self, watchdog: StalenessWatchdog) -> Self {
+        Self {
+            staleness_watchdog: Some(watchdog),
+            ..self
+        }
+    }
+
+    /// Applies the built-in [`VenueProfile`] of the name, such as `binancefutures-2024` or
+    /// `bybit-linear`, which sets the fee model, the GTX policy, the modify policy, the order
+    /// rate limits, and the cap of the open orders of the venue. The settings made after this override those of the profile.
+    /// The profile's latencies are set separately by
+    /// [`latency_model`](Self::latency_model)`(profile.latency())`. [`build`](Self::build) fails
+    /// with [`BuildError::ProfileNotFound`] if there is no profile of the name.
+    pub fn venue_profile(self, name: &str) -> Self {
+        match VenueProfile::get(name) {
+            Some(profile) => self.venue(&profile),
+            None => Self {
+                unknown_profile: Some(name.to_string()),
+                ..self
+            },
+        }
+    }
+
+    /// Applies the venue profile, which can be a built-in profile customized or your own. See
+    /// [`venue_profile`](Self::venue_profile).
+    pub fn venue(self, profile: &VenueProfile) -> Self {
+        Self {
+            gtx_policy: profile.gtx_policy,
+            modify_policy: profile.modify_policy,
+            order_rate_limits: profile.order_rate_limits.clone(),
+            max_open_orders: profile.max_open_orders,
+            ..self.fee_model(profile.fee_model())
+        }
+    }
+
+    pub fn build(mut self) -> Result<BtAsset<Q, MD>, BuildError> {
+        self.check_errors()?;
+        let ob_local_to_exch = OrderBus::new();
+        let ob_exch_to_local = OrderBus::new();
+
+        let local = self.build_local(ob_local_to_exch.clone(), ob_exch_to_local.clone())?;
+        let config = self.config();
+        let staleness_watchdog = self.staleness_watchdog;
+        let exch = self.build_exch(ob_exch_to_local, ob_local_to_exch)?;
+        Ok(BtAsset {
+            local,
+            exch,
+            staleness_watchdog,
+            config,
+        })
+    }
+
+    /// Builds the asset for `num_strategies` strategy instances that trade it independently and
+    /// concurrently, to study their interaction, such as the self-competition between two of your
+    /// own strategies. Each returned asset has its own local, with its own state and order id
+    /// namespace, and they share the market data and the exchange, where they match against the
+    /// same depth. Add them to the backtest by [`BtBuilder::add_shared`].
+    ///
+    /// The queue position of each order is still estimated from the market depth alone, without
+    /// regard to the other instances' orders at the same price.
+    pub fn build_shared(
+        mut self,
+        num_strategies: usize,
+    ) -> Result<Vec<BtAsset<Q, MD>>, BuildError> {
+        self.check_errors()?;
+        let buses: Vec<(OrderBus<Q>, OrderBus<Q>)> = (0..num_strategies)
+            .map(|_| (OrderBus::new(), OrderBus::new()))
+            .collect();
+        let locals = buses
+            .iter()
+            .map(|(orders_to, orders_from)| {
+                self.build_local(orders_to.clone(), orders_from.clone())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let config = self.config();
+        let staleness_watchdog = self.staleness_watchdog;
+
         let ob_local_to_exch = OrderBus::new();
         let ob_exch_to_local = OrderBus::new();
+        let exch = self.build_exch(ob_exch_to_local.clone(), ob_local_to_exch.clone())?;
+        let handles = SharedExchangeHandle::new(exch, ob_local_to_exch, ob_exch_to_local, buses);
+        Ok(locals
+            .into_iter()
+            .zip(handles)
+            .map(|(local, handle)| BtAsset {
+                local,
+                exch: Box::new(handle),
+                staleness_watchdog,
+                config: config.clone(),
+            })
+            .collect())
+    }
+
+    fn check_errors(&mut self) -> Result<(), BuildError> {
+        if let Some(name) = self.unknown_profile.take() {
+            return Err(BuildError::ProfileNotFound(name));
+        }
+        if let Some(error) = self.data_error.take() {
+            return Err(BuildError::Error(error.into()));
+        }
+        Ok(())
+    }
 
+    fn config(&self) -> Config {
+        let mut config = Config::default();
+        config.add("exchange", self.exch_kind);
+        if let Some(latency_model) = &self.latency_model {
+            config.add("latency_model", latency_model);
+        }
+        if let Some(asset_type) = &self.asset_type {
+            config.add("asset_type", asset_type);
+        }
+        if let Some(queue_model) = &self.queue_model {
+            config.add("queue_model", queue_model);
+        }
+        if let Some(create_depth) = &self.depth_func {
+            let depth = create_depth();
+            config.add(
+                "depth",
+                format_args!(
+                    "{} tick_size={} lot_size={}",
+                    type_name::<MD>(),
+                    depth.tick_size(),
+                    depth.lot_size()
+                ),
+            );
+        }
+        if let Some(fee_model) = &self.fee_model_config {
+            config.add("fee_model", format_args!("{fee_model}"));
+        }
+        config.add("latency_offset", self.latency_offset);
+        config.add("trade_aggregation", self.trade_aggregation);
+        config.add("trade_buffer", self.trade_buffer);
+        config.add("rollover_policy", self.rollover_policy);
+        config.add("gtx_policy", self.gtx_policy);
+        config.add("modify_policy", self.modify_policy);
+        config.add("order_rate_limits", &self.order_rate_limits);
+        config.add("max_open_orders", self.max_open_orders);
+        config.add("staleness_watchdog", self.staleness_watchdog);
+        config.add("bbo_feed", self.bbo_feed);
+        config.append(&self.data_config);
+        config
+    }
+
+    fn new_state(&self, asset_type: AT) -> State<AT> {
+        let mut state = State::new(asset_type);
+        if let Some(create_fee_model) = self.fee_model.as_ref() {
+            state.fee_model = create_fee_model();
+        }
+        state
+    }
+
+    fn build_local(
+        &self,
+        orders_to: OrderBus<Q>,
+        orders_from: OrderBus<Q>,
+    ) -> Result<Box<dyn LocalProcessor<Q, MD>>, BuildError> {
         let create_depth = self
             .depth_func
             .as_ref()
             .ok_or(BuildError::BuilderIncomplete("depth"))?;
+        self.reader
+            .validate(create_depth().tick_size())
+            .map_err(|error| BuildError::Error(error.into()))?;
         let order_latency = self
             .latency_model
             .clone()
@@ -149,70 +786,247 @@ where
             .clone()
             .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
 
-        let local = Local::new(
+        let mut local = Local::new(
             self.reader.clone(),
             create_depth(),
-            State::new(asset_type),
+            self.new_state(asset_type),
             order_latency,
-            1000,
-            ob_local_to_exch.clone(),
-            ob_exch_to_local.clone(),
+            self.trade_buffer,
+            orders_to,
+            orders_from,
         );
+        local.latency_offset = self.latency_offset;
+        local.features = self.features.clone();
+        local.reference_only = self.exch_kind == ExchangeKind::ReferenceOnly;
+        local.bbo_feed = self.bbo_feed;
+        Ok(Box::new(local))
+    }
 
+    fn build_exch(
+        self,
+        orders_to: OrderBus<Q>,
+        orders_from: OrderBus<Q>,
+    ) -> Result<Box<dyn Processor>, BuildError> {
+        let create_depth = self
+            .depth_func
+            .as_ref()
+            .ok_or(BuildError::BuilderIncomplete("depth"))?;
         let order_latency = self
             .latency_model
             .clone()
             .ok_or(BuildError::BuilderIncomplete("order_latency"))?;
-        let queue_model = self
-            .queue_model
-            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
         let asset_type = self
             .asset_type
             .clone()
             .ok_or(BuildError::BuilderIncomplete("asset_type"))?;
-        let exch = NoPartialFillExchange::new(
-            self.reader.clone(),
-            create_depth(),
-            State::new(asset_type),
-            order_latency,
-            queue_model,
-            ob_exch_to_local,
-            ob_local_to_exch,
-        );
-
-        Ok(BtAsset {
-            local: Box::new(local),
-            exch: Box::new(exch),
-        })
+        let state = self.new_state(asset_type);
+        let depth = create_depth();
+        let queue_model = self
+            .queue_model
+            .ok_or(BuildError::BuilderIncomplete("queue_model"))?;
+        let exch: Box<dyn Processor> = match self.exch_kind {
+            ExchangeKind::NoPartialFillExchange => {
+                let mut exch = NoPartialFillExchange::new(
+                    self.reader.clone(),
+                    depth,
+                    state,
+                    order_latency,
+                    queue_model,
+                    orders_to,
+                    orders_from,
+                );
+                exch.trade_aggregation = self.trade_aggregation;
+                exch.rollover_policy = self.rollover_policy;
+                exch.gtx_policy = self.gtx_policy;
+                exch.modify_policy = self.modify_policy;
+                exch.order_rate_limiter = OrderRateLimiter::new(self.order_rate_limits.clone());
+                exch.max_open_orders = self.max_open_orders;
+                Box::new(exch)
+            }
+            ExchangeKind::PartialFillExchange => {
+                let mut exch = PartialFillExchange::new(
+                    self.reader.clone(),
+                    depth,
+                    state,
+                    order_latency,
+                    queue_model,
+                    orders_to,
+                    orders_from,
+                );
+                exch.trade_aggregation = self.trade_aggregation;
+                exch.rollover_policy = self.rollover_policy;
+                exch.gtx_policy = self.gtx_policy;
+                exch.modify_policy = self.modify_policy;
+                exch.order_rate_limiter = OrderRateLimiter::new(self.order_rate_limits.clone());
+                exch.max_open_orders = self.max_open_orders;
+                Box::new(exch)
+            }
+            ExchangeKind::L3Exchange => {
+                let mut exch = L3Exchange::new(
+                    self.l3_reader.clone(),
+                    L3MarketDepth::new(depth.tick_size(), depth.lot_size()),
+                    state,
+                    order_latency,
+                    orders_to,
+                    orders_from,
+                );
+                exch.gtx_policy = self.gtx_policy;
+                exch.modify_policy = self.modify_policy;
+                exch.order_rate_limiter = OrderRateLimiter::new(self.order_rate_limits.clone());
+                exch.max_open_orders = self.max_open_orders;
+                Box::new(exch)
+            }
+            ExchangeKind::ReferenceOnly => Box::new(ReferenceExchange),
+        };
+        Ok(exch)
     }
 }
 
-pub struct BtBuilder<Q> {
-    local: Vec<Box<dyn LocalProcessor<Q, HashMapMarketDepth>>>,
+pub struct BtBuilder<Q, MD = HashMapMarketDepth> {
+    local: Vec<Box<dyn LocalProcessor<Q, MD>>>,
     exch: Vec<Box<dyn Processor>>,
+    tie_break: TieBreak,
+    response_priority: Option<ResponsePriority>,
+    compute_time: ComputeTime,
+    wakeup_interval: Option<i64>,
+    external_events: Vec<ExternalEvent>,
+    adjustments: Vec<Adjustment>,
+    shared: Vec<Vec<usize>>,
+    staleness_watchdogs: Vec<Option<StalenessWatchdog>>,
+    asset_configs: Vec<Config>,
 }
 
-impl<Q> BtBuilder<Q>
+impl<Q, MD> BtBuilder<Q, MD>
 where
     Q: Clone,
+    MD: MarketDepth,
 {
     pub fn new() -> Self {
         Self {
             local: vec![],
             exch: vec![],
+            tie_break: TieBreak::default(),
+            response_priority: None,
+            compute_time: ComputeTime::default(),
+            wakeup_interval: None,
+            external_events: Vec::new(),
+            adjustments: Vec::new(),
+            shared: Vec::new(),
+            staleness_watchdogs: Vec::new(),
+            asset_configs: Vec::new(),
         }
     }
 
-    pub fn add(self, asset: BtAsset<Q>) -> Self {
+    pub fn add(self, asset: BtAsset<Q, MD>) -> Self {
         let mut s = Self { ..self };
         s.local.push(asset.local);
         s.exch.push(asset.exch);
+        s.staleness_watchdogs.push(asset.staleness_watchdog);
+        s.asset_configs.push(asset.config);
         s
     }
 
-    pub fn build(
-        self,
-    ) -> Result<MultiAssetMultiExchangeBacktest<Q, HashMapMarketDepth>, BuildError> {
-        Ok(MultiAssetMultiExchangeBacktest::new(self.local, self.exch))
+    /// Adds the strategy instances of an asset that share its exchange, built by
+    /// [`BtAssetBuilder::build_shared`], as consecutive assets.
+    pub fn add_shared(self, assets: Vec<BtAsset<Q, MD>>) -> Self {
+        let mut s = Self { ..self };
+        let asset_nos = (s.local.len()..s.local.len() + assets.len()).collect();
+        for asset in assets {
+            s.local.push(asset.local);
+            s.exch.push(asset.exch);
+            s.staleness_watchdogs.push(asset.staleness_watchdog);
+            s.asset_configs.push(asset.config);
+        }
+        s.shared.push(asset_nos);
+        s
+    }
+
+    /// Sets the tie-breaking policy for events of the same asset that share the same timestamp.
+    /// The default is [`TieBreak::MarketFirst`].
+    pub fn tie_break(self, tie_break: TieBreak) -> Self {
+        Self { tie_break, ..self }
+    }
+
+    /// Sets whether the order responses or the market data updates are delivered to the strategy
+    /// first at the same local timestamp. By default, it follows the [`TieBreak`].
+    pub fn response_priority(self, response_priority: ResponsePriority) -> Self {
+        Self {
+            response_priority: Some(response_priority),
+            ..self
+        }
+    }
+
+    /// Sets how the strategy's compute time is charged to the backtest clock. By default, no
+    /// compute time is charged.
+    pub fn compute_time(self, compute_time: ComputeTime) -> Self {
+        Self {
+            compute_time,
+            ..self
+        }
+    }
+
+    /// Coalesces the strategy wakeups to at most once per `interval`. See
+    /// [`MultiAssetMultiExchangeBacktest::with_wakeup_interval`].
+    pub fn wakeup_interval(self, interval: i64) -> Self {
+        Self {
+            wakeup_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sets the external events, such as an economic calendar, to be injected into the backtest.
+    pub fn external_events(self, external_events: Vec<ExternalEvent>) -> Self {
+        Self {
+            external_events,
+            ..self
+        }
+    }
+
+    /// Sets the manual adjustments of the assets' positions and balances. See
+    /// [`MultiAssetMultiExchangeBacktest::with_adjustments`].
+    pub fn adjustments(self, adjustments: Vec<Adjustment>) -> Self {
+        Self {
+            adjustments,
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<MultiAssetMultiExchangeBacktest<Q, MD>, BuildError> {
+        let config = self.config();
+        let mut hbt = MultiAssetMultiExchangeBacktest::new(self.local, self.exch)
+            .with_tie_break(self.tie_break)
+            .with_compute_time(self.compute_time)
+            .with_external_events(self.external_events)
+            .with_adjustments(self.adjustments);
+        if let Some(response_priority) = self.response_priority {
+            hbt = hbt.with_response_priority(response_priority);
+        }
+        if let Some(interval) = self.wakeup_interval {
+            hbt = hbt.with_wakeup_interval(interval);
+        }
+        for asset_nos in self.shared {
+            hbt = hbt.with_shared_exchange(asset_nos);
+        }
+        for (asset_no, watchdog) in self.staleness_watchdogs.into_iter().enumerate() {
+            if let Some(watchdog) = watchdog {
+                hbt = hbt.with_staleness_watchdog(asset_no, watchdog);
+            }
+        }
+        Ok(hbt.with_config(config))
+    }
+
+    fn config(&self) -> Config {
+        let mut config = Config::default();
+        for (asset_no, asset_config) in self.asset_configs.iter().enumerate() {
+            config.extend(&format!("asset[{asset_no}]"), asset_config);
+        }
+        config.add("tie_break", self.tie_break);
+        config.add("response_priority", self.response_priority);
+        config.add("compute_time", self.compute_time);
+        config.add("wakeup_interval", self.wakeup_interval);
+        config.add("external_events", &self.external_events);
+        config.add("adjustments", &self.adjustments);
+        config.add("shared", &self.shared);
+        config
     }
 }
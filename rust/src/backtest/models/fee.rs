@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+
+/// Provides the fee rate of each fill, so that maker rebates and volume-tiered fee schedules can
+/// be simulated. The fee itself is computed from the rate by the asset type. See
+/// [`AssetType::fee`](crate::backtest::assettype::AssetType::fee).
+pub trait FeeModel {
+    /// Returns the fee rate of a fill of `amount` in the settlement currency at `timestamp`. A
+    /// negative rate is a rebate. It is called once per fill in order of the fills, so the model
+    /// can track the traded volume, which includes the fill only after its rate is determined.
+    fn fee_rate(&mut self, timestamp: i64, maker: bool, amount: f64) -> f64;
+}
+
+/// Charges fixed maker and taker fee rates. A negative maker fee rate is a rebate. The default
+/// charges no fees.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlatFee {
+    maker_fee: f64,
+    taker_fee: f64,
+}
+
+impl FlatFee {
+    pub fn new(maker_fee: f64, taker_fee: f64) -> Self {
+        Self {
+            maker_fee,
+            taker_fee,
+        }
+    }
+}
+
+impl FeeModel for FlatFee {
+    fn fee_rate(&mut self, _timestamp: i64, maker: bool, _amount: f64) -> f64 {
+        if maker {
+            self.maker_fee
+        } else {
+            self.taker_fee
+        }
+    }
+}
+
+/// Charges the maker and taker fee rates of the highest tier reached by the traded volume over a
+/// rolling window, such as the 30-day volume on which most venues base their fee tiers. The
+/// volume is the sum of the fill amounts in the settlement currency.
+#[derive(Clone, Debug)]
+pub struct TieredFee {
+    window: i64,
+    // (min_volume, maker_fee, taker_fee) in ascending order of min_volume.
+    tiers: Vec<(f64, f64, f64)>,
+    // The timestamps and amounts of the fills within the window.
+    fills: VecDeque<(i64, f64)>,
+    volume: f64,
+}
+
+impl TieredFee {
+    /// Constructs a tiered fee model over the rolling `window`, whose base tier charges
+    /// `maker_fee` and `taker_fee`.
+    pub fn new(window: i64, maker_fee: f64, taker_fee: f64) -> Self {
+        Self {
+            window,
+            tiers: vec![(0.0, maker_fee, taker_fee)],
+            fills: VecDeque::new(),
+            volume: 0.0,
+        }
+    }
+
+    /// Adds a tier that charges `maker_fee` and `taker_fee` once the volume reaches
+    /// `min_volume`.
+    pub fn tier(mut self, min_volume: f64, maker_fee: f64, taker_fee: f64) -> Self {
+        self.tiers.push((min_volume, maker_fee, taker_fee));
+        self.tiers.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self
+    }
+
+    /// Returns the traded volume over the window as of the last fill.
+    pub fn volume(&self) -> f64 {
+        self.volume
+    }
+}
+
+impl FeeModel for TieredFee {
+    fn fee_rate(&mut self, timestamp: i64, maker: bool, amount: f64) -> f64 {
+        while let Some(&(ts, fill_amount)) = self.fills.front() {
+            if ts > timestamp - self.window {
+                break;
+            }
+            self.volume -= fill_amount;
+            self.fills.pop_front();
+        }
+        if self.fills.is_empty() {
+            // Prevents the accumulation of the rounding errors.
+            self.volume = 0.0;
+        }
+
+        let (_, maker_fee, taker_fee) = self
+            .tiers
+            .iter()
+            .rev()
+            .find(|(min_volume, _, _)| self.volume >= *min_volume)
+            .unwrap_or(&self.tiers[0]);
+        let fee_rate = if maker { *maker_fee } else { *taker_fee };
+
+        self.fills.push_back((timestamp, amount.abs()));
+        self.volume += amount.abs();
+        fee_rate
+    }
+}
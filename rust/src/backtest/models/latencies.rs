@@ -1,11 +1,35 @@
-use crate::{backtest::reader::Data, ty::Order};
+use std::fmt::{Debug, Formatter};
+
+use serde::Deserialize;
+
+use crate::{
+    backtest::{fingerprint::digest, reader::Data, Error},
+    ty::{Order, Status},
+};
 
 pub trait LatencyModel {
-    fn entry<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> i64;
-    fn response<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> i64;
+    fn entry<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error>;
+    fn response<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error>;
+
+    /// Positions the model at the start timestamp of the backtest, before any order is
+    /// submitted. Models without a state ignore it.
+    fn seek(&mut self, _timestamp: i64) {}
 }
 
-#[derive(Clone)]
+/// Fixed additional latencies applied on the local side per event class, on top of the feed's
+/// local timestamps and the order latency model, since a real stack processes each message type
+/// with a different delay.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatencyOffset {
+    /// Added to the local timestamp of depth events, including clear and snapshot events.
+    pub depth: i64,
+    /// Added to the local timestamp of trade events.
+    pub trade: i64,
+    /// Added to the local receipt timestamp of order responses.
+    pub order_response: i64,
+}
+
+#[derive(Clone, Debug)]
 pub struct ConstantLatency {
     entry_latency: i64,
     response_latency: i64,
@@ -21,12 +45,58 @@ impl ConstantLatency {
 }
 
 impl LatencyModel for ConstantLatency {
-    fn entry<Q: Clone>(&mut self, _timestamp: i64, _order: &Order<Q>) -> i64 {
-        self.entry_latency
+    fn entry<Q: Clone>(&mut self, _timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        Ok(self.entry_latency)
+    }
+
+    fn response<Q: Clone>(&mut self, _timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        Ok(self.response_latency)
+    }
+}
+
+/// Delivers the passive fills with a separate response latency model, since live, the fills of
+/// resting orders are reported by the user data stream, while the other responses, including the
+/// fills of aggressive orders, are returned in the response to the order request. The entry
+/// latency and all the other responses follow the `taker` model.
+///
+/// Only the responses for the same order are kept in the order they are made by the exchange, so a
+/// response, such as a cancel reject, can't overtake an earlier fill of the same order even if its
+/// latency is shorter; it's received with the fill instead. A response for another order can
+/// still arrive before a fill made earlier, as live, where the request responses and the user
+/// data stream aren't ordered with each other.
+#[derive(Clone, Debug)]
+pub struct MakerTakerLatency<LT, LM> {
+    taker: LT,
+    maker: LM,
+}
+
+impl<LT, LM> MakerTakerLatency<LT, LM> {
+    pub fn new(taker: LT, maker: LM) -> Self {
+        Self { taker, maker }
+    }
+}
+
+impl<LT, LM> LatencyModel for MakerTakerLatency<LT, LM>
+where
+    LT: LatencyModel,
+    LM: LatencyModel,
+{
+    fn entry<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error> {
+        self.taker.entry(timestamp, order)
+    }
+
+    fn response<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error> {
+        let fill = order.status == Status::Filled || order.status == Status::PartiallyFilled;
+        if fill && order.maker {
+            self.maker.response(timestamp, order)
+        } else {
+            self.taker.response(timestamp, order)
+        }
     }
 
-    fn response<Q: Clone>(&mut self, _timestamp: i64, _order: &Order<Q>) -> i64 {
-        self.response_latency
+    fn seek(&mut self, timestamp: i64) {
+        self.taker.seek(timestamp);
+        self.maker.seek(timestamp);
     }
 }
 
@@ -39,21 +109,52 @@ pub struct OrderLatencyRow {
     pub reserved: i64,
 }
 
+/// How [`IntpOrderLatency`] handles a request outside the time range of the recorded latencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutOfRange {
+    /// Uses the latency of the nearest row, the first row before the range and the last row
+    /// after it.
+    #[default]
+    Clamp,
+    /// Extends the interpolation between the first two rows before the range and between the
+    /// last two rows after it. The extrapolated latency doesn't go below zero.
+    Extrapolate,
+    /// Fails the request with [`Error::LatencyOutOfRange`], which stops the backtest.
+    Error,
+}
+
+/// Interpolates the order latency between the recorded rows, which are ordered by the request
+/// timestamp. The entry latency is interpolated on the request timestamps and the response
+/// latency on the exchange timestamps of the rows other than the rejections.
 #[derive(Clone)]
 pub struct IntpOrderLatency {
     entry_rn: usize,
     resp_rn: usize,
+    out_of_range: OutOfRange,
     data: Data<OrderLatencyRow>,
 }
 
+impl Debug for IntpOrderLatency {
+    /// Shows the latency data by its checksum rather than by its rows.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IntpOrderLatency")
+            .field("out_of_range", &self.out_of_range)
+            .field("rows", &self.data.len())
+            .field("sha256", &digest(self.data.as_bytes()))
+            .finish()
+    }
+}
+
 impl IntpOrderLatency {
-    pub fn new(data: Data<OrderLatencyRow>) -> Self {
+    pub fn new(data: Data<OrderLatencyRow>, out_of_range: OutOfRange) -> Self {
         if data.len() == 0 {
             panic!();
         }
         Self {
             entry_rn: 0,
             resp_rn: 0,
+            out_of_range,
             data,
         }
     }
@@ -61,95 +162,225 @@ impl IntpOrderLatency {
     fn intp(&self, x: i64, x1: i64, y1: i64, x2: i64, y2: i64) -> i64 {
         (((y2 - y1) as f64) / ((x2 - x1) as f64) * ((x - x1) as f64)) as i64 + y1
     }
-}
 
-impl LatencyModel for IntpOrderLatency {
-    fn entry<Q: Clone>(&mut self, timestamp: i64, _order: &Order<Q>) -> i64 {
-        let first_row = &self.data[0];
-        if timestamp < first_row.req_timestamp {
-            return first_row.exch_timestamp - first_row.req_timestamp;
+    /// Finds the last row that has the key at or before the timestamp, scanning forward from the
+    /// cursor since the requests mostly come in time order, or otherwise by binary search on the
+    /// request timestamps, or by a forward scan from the first row on the exchange timestamps,
+    /// which aren't ordered through the rejections. The timestamp must be within the range of
+    /// the keys.
+    fn locate(&self, cursor: usize, timestamp: i64, key: Key) -> usize {
+        let last = self.data.len() - 1;
+        let mut rn = if cursor <= last
+            && key.has(&self.data[cursor])
+            && key.of(&self.data[cursor]) <= timestamp
+        {
+            cursor
+        } else {
+            match key {
+                Key::Request => {
+                    return self.data.first_at(timestamp + 1, req_timestamp).max(1) - 1;
+                }
+                Key::Exchange => match self.next(None, key) {
+                    Some(rn) => rn,
+                    None => return 0,
+                },
+            }
+        };
+        while let Some(next) = self.next(Some(rn), key) {
+            if key.of(&self.data[next]) > timestamp {
+                break;
+            }
+            rn = next;
         }
+        rn
+    }
 
-        let last_row = &self.data[self.data.len() - 1];
-        if timestamp >= last_row.req_timestamp {
-            return last_row.exch_timestamp - last_row.req_timestamp;
+    /// Returns the first row that has the key after the row, or from the first row if `None`.
+    fn next(&self, rn: Option<usize>, key: Key) -> Option<usize> {
+        let start = rn.map_or(0, |rn| rn + 1);
+        (start..self.data.len()).find(|&next| key.has(&self.data[next]))
+    }
+
+    /// Returns the last row that has the key before the row.
+    fn prev(&self, rn: usize, key: Key) -> Option<usize> {
+        (0..rn).rev().find(|&prev| key.has(&self.data[prev]))
+    }
+
+    /// Returns the rows of the segment to interpolate in, or the latency of the row to clamp to,
+    /// or an error, depending on where the timestamp is in the range of the keys.
+    fn segment(
+        &self,
+        cursor: usize,
+        timestamp: i64,
+        key: Key,
+    ) -> Result<Result<(usize, usize), i64>, Error> {
+        let (Some(first), Some(last)) = (self.next(None, key), self.prev(self.data.len(), key))
+        else {
+            // All the requests are rejected, so there is no response latency, which isn't
+            // looked up since no request reaches the exchange.
+            return match self.out_of_range {
+                OutOfRange::Error => Err(Error::LatencyOutOfRange(timestamp)),
+                _ => Ok(Err(0)),
+            };
+        };
+        let first_row = &self.data[first];
+        let last_row = &self.data[last];
+        let before = timestamp < key.of(first_row);
+        let after = timestamp > key.of(last_row);
+        if before || after {
+            return match self.out_of_range {
+                OutOfRange::Error => Err(Error::LatencyOutOfRange(timestamp)),
+                OutOfRange::Extrapolate if first < last => Ok(Ok(if before {
+                    (first, self.next(Some(first), key).unwrap())
+                } else {
+                    (self.prev(last, key).unwrap(), last)
+                })),
+                _ => Ok(Err(key.latency(if before { first_row } else { last_row }))),
+            };
         }
+        let rn = self.locate(cursor, timestamp, key);
+        if rn == last {
+            return Ok(Err(key.latency(last_row)));
+        }
+        Ok(Ok((rn, self.next(Some(rn), key).unwrap())))
+    }
 
-        for row_num in self.entry_rn..(self.data.len() - 1) {
-            let req_local_timestamp = self.data[row_num].req_timestamp;
-            let next_req_local_timestamp = self.data[row_num + 1].req_timestamp;
-            if req_local_timestamp <= timestamp && timestamp < next_req_local_timestamp {
-                self.entry_rn = row_num;
-
-                let exch_timestamp = self.data[row_num].exch_timestamp;
-                let next_exch_timestamp = self.data[row_num + 1].exch_timestamp;
-
-                // The exchange may reject an order request due to technical issues such
-                // congestion, this is particularly common in crypto markets. A timestamp of
-                // zero on the exchange represents the occurrence of those kinds of errors at
-                // that time.
-                if exch_timestamp <= 0 || next_exch_timestamp <= 0 {
-                    let resp_timestamp = self.data[row_num].resp_timestamp;
-                    let next_resp_timestamp = self.data[row_num + 1].resp_timestamp;
-                    let lat1 = resp_timestamp - req_local_timestamp;
-                    let lat2 = next_resp_timestamp - next_req_local_timestamp;
-
-                    // Negative latency indicates that the order is rejected for technical
-                    // reasons, and its value represents the latency that the local experiences
-                    // when receiving the rejection notification
-                    return -self.intp(
-                        timestamp,
-                        req_local_timestamp,
-                        lat1,
-                        next_req_local_timestamp,
-                        lat2,
-                    );
-                }
+    fn entry_at(&self, rn: usize, next: usize, timestamp: i64, extrapolated: bool) -> i64 {
+        let req_local_timestamp = self.data[rn].req_timestamp;
+        let next_req_local_timestamp = self.data[next].req_timestamp;
+        let exch_timestamp = self.data[rn].exch_timestamp;
+        let next_exch_timestamp = self.data[next].exch_timestamp;
 
-                let lat1 = exch_timestamp - req_local_timestamp;
-                let lat2 = next_exch_timestamp - next_req_local_timestamp;
-                return self.intp(
-                    timestamp,
-                    req_local_timestamp,
-                    lat1,
-                    next_req_local_timestamp,
-                    lat2,
-                );
-            }
+        // The exchange may reject an order request due to technical issues such
+        // congestion, this is particularly common in crypto markets. A timestamp of
+        // zero on the exchange represents the occurrence of those kinds of errors at
+        // that time.
+        if exch_timestamp <= 0 || next_exch_timestamp <= 0 {
+            let resp_timestamp = self.data[rn].resp_timestamp;
+            let next_resp_timestamp = self.data[next].resp_timestamp;
+            let lat1 = resp_timestamp - req_local_timestamp;
+            let lat2 = next_resp_timestamp - next_req_local_timestamp;
+
+            // Negative latency indicates that the order is rejected for technical
+            // reasons, and its value represents the latency that the local experiences
+            // when receiving the rejection notification
+            let lat = self.intp(
+                timestamp,
+                req_local_timestamp,
+                lat1,
+                next_req_local_timestamp,
+                lat2,
+            );
+            return -(if extrapolated { lat.max(0) } else { lat });
+        }
+
+        let lat1 = exch_timestamp - req_local_timestamp;
+        let lat2 = next_exch_timestamp - next_req_local_timestamp;
+        let lat = self.intp(
+            timestamp,
+            req_local_timestamp,
+            lat1,
+            next_req_local_timestamp,
+            lat2,
+        );
+        if extrapolated {
+            lat.max(0)
+        } else {
+            lat
         }
-        return -1;
     }
 
-    fn response<Q: Clone>(&mut self, timestamp: i64, _order: &Order<Q>) -> i64 {
-        let first_row = &self.data[0];
-        if timestamp < first_row.exch_timestamp {
-            return first_row.resp_timestamp - first_row.exch_timestamp;
+    fn response_at(&self, rn: usize, next: usize, timestamp: i64) -> i64 {
+        let exch_timestamp = self.data[rn].exch_timestamp;
+        let next_exch_timestamp = self.data[next].exch_timestamp;
+        let resp_local_timestamp = self.data[rn].resp_timestamp;
+        let next_resp_local_timestamp = self.data[next].resp_timestamp;
+
+        let lat1 = resp_local_timestamp - exch_timestamp;
+        let lat2 = next_resp_local_timestamp - next_exch_timestamp;
+
+        let lat = self.intp(timestamp, exch_timestamp, lat1, next_exch_timestamp, lat2);
+        if lat < 0 {
+            return -1;
         }
+        lat
+    }
+}
+
+fn req_timestamp(row: &OrderLatencyRow) -> i64 {
+    row.req_timestamp
+}
+
+/// The timestamp of the rows by which the latency is looked up.
+#[derive(Clone, Copy, Debug)]
+enum Key {
+    /// The request timestamp, which every row has, for the entry latency.
+    Request,
+    /// The exchange timestamp for the response latency, which the rows of the rejections don't
+    /// have, as their exchange timestamps are zero or negative.
+    Exchange,
+}
 
-        let last_row = &self.data[self.data.len() - 1];
-        if timestamp >= last_row.exch_timestamp {
-            return last_row.resp_timestamp - last_row.exch_timestamp;
+impl Key {
+    fn of(self, row: &OrderLatencyRow) -> i64 {
+        match self {
+            Key::Request => row.req_timestamp,
+            Key::Exchange => row.exch_timestamp,
         }
+    }
 
-        for row_num in self.resp_rn..(self.data.len() - 1) {
-            let exch_timestamp = self.data[row_num].exch_timestamp;
-            let next_exch_timestamp = self.data[row_num + 1].exch_timestamp;
-            if exch_timestamp <= timestamp && timestamp < next_exch_timestamp {
-                self.resp_rn = row_num;
+    fn has(self, row: &OrderLatencyRow) -> bool {
+        match self {
+            Key::Request => true,
+            Key::Exchange => row.exch_timestamp > 0,
+        }
+    }
 
-                let resp_local_timestamp = self.data[row_num].resp_timestamp;
-                let next_resp_local_timestamp = self.data[row_num + 1].resp_timestamp;
+    fn latency(self, row: &OrderLatencyRow) -> i64 {
+        match self {
+            // The entry latency of a rejection is the negative of its round trip, as it's
+            // interpolated in `entry_at`.
+            Key::Request if row.exch_timestamp <= 0 => -(row.resp_timestamp - row.req_timestamp),
+            Key::Request => row.exch_timestamp - row.req_timestamp,
+            Key::Exchange => row.resp_timestamp - row.exch_timestamp,
+        }
+    }
+}
 
-                let lat1 = resp_local_timestamp - exch_timestamp;
-                let lat2 = next_resp_local_timestamp - next_exch_timestamp;
+impl LatencyModel for IntpOrderLatency {
+    fn entry<Q: Clone>(&mut self, timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        match self.segment(self.entry_rn, timestamp, Key::Request)? {
+            Ok((rn, next)) => {
+                let extrapolated = timestamp < self.data[rn].req_timestamp
+                    || timestamp >= self.data[next].req_timestamp;
+                if !extrapolated {
+                    self.entry_rn = rn;
+                }
+                Ok(self.entry_at(rn, next, timestamp, extrapolated))
+            }
+            Err(latency) => Ok(latency),
+        }
+    }
 
-                let lat = self.intp(timestamp, exch_timestamp, lat1, next_exch_timestamp, lat2);
-                if lat < 0 {
-                    return -1;
+    fn response<Q: Clone>(&mut self, timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        match self.segment(self.resp_rn, timestamp, Key::Exchange)? {
+            Ok((rn, next)) => {
+                let extrapolated = timestamp < self.data[rn].exch_timestamp
+                    || timestamp >= self.data[next].exch_timestamp;
+                if !extrapolated {
+                    self.resp_rn = rn;
                 }
-                return lat;
+                let lat = self.response_at(rn, next, timestamp);
+                Ok(if extrapolated { lat.max(0) } else { lat })
             }
+            Err(latency) => Ok(latency),
         }
-        return -1;
+    }
+
+    fn seek(&mut self, timestamp: i64) {
+        // Starts the forward scans from the rows at the start, rather than from the first row,
+        // which may be far before the start if the latency data covers a longer period.
+        self.entry_rn = self.locate(usize::MAX, timestamp, Key::Request);
+        self.resp_rn = self.locate(usize::MAX, timestamp, Key::Exchange);
     }
 }
@@ -1,5 +1,28 @@
+mod fee;
 mod latencies;
+mod pricing;
 mod queue;
 
-pub use latencies::{ConstantLatency, IntpOrderLatency, LatencyModel};
-pub use queue::{PowerProbQueueFunc3, ProbQueueModel, QueueModel, QueuePos, RiskAdverseQueueModel};
+pub use fee::{FeeModel, FlatFee, TieredFee};
+pub use latencies::{
+    ConstantLatency,
+    IntpOrderLatency,
+    LatencyModel,
+    LatencyOffset,
+    MakerTakerLatency,
+    OrderLatencyRow,
+    OutOfRange,
+};
+pub use pricing::{BlackScholes, Greeks, PricingModel};
+pub use queue::{
+    LogProbQueueFunc,
+    LogProbQueueFunc2,
+    PowerProbQueueFunc,
+    PowerProbQueueFunc2,
+    PowerProbQueueFunc3,
+    ProbQueueModel,
+    Probability,
+    QueueModel,
+    QueuePos,
+    RiskAdverseQueueModel,
+};
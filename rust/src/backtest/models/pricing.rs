@@ -0,0 +1,141 @@
+use std::{f64::consts::PI, ops::Mul};
+
+use crate::backtest::assettype::{AssetType, OptionAsset, OptionKind};
+
+/// Sensitivities of an option value. Vega is per one volatility point (1%) and theta is per day.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Greeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+}
+
+impl Mul<f64> for Greeks {
+    type Output = Greeks;
+
+    fn mul(self, rhs: f64) -> Self::Output {
+        Greeks {
+            delta: self.delta * rhs,
+            gamma: self.gamma * rhs,
+            vega: self.vega * rhs,
+            theta: self.theta * rhs,
+        }
+    }
+}
+
+/// Provides the theoretical value and the greeks of an option, per unit of the underlying, in the
+/// same unit as the underlying price.
+pub trait PricingModel {
+    fn value(&self, option: &OptionAsset, underlying_price: f64, timestamp: i64) -> f64;
+    fn greeks(&self, option: &OptionAsset, underlying_price: f64, timestamp: i64) -> Greeks;
+
+    /// Returns the greeks of the position, which accounts for the contract size.
+    fn position_greeks(
+        &self,
+        option: &OptionAsset,
+        underlying_price: f64,
+        timestamp: i64,
+        position: f64,
+    ) -> Greeks {
+        self.greeks(option, underlying_price, timestamp) * (position * option.contract_size())
+    }
+}
+
+/// Black-Scholes model with a constant volatility and risk-free rate, both annualized.
+#[derive(Clone, Debug)]
+pub struct BlackScholes {
+    volatility: f64,
+    rate: f64,
+}
+
+impl BlackScholes {
+    pub fn new(volatility: f64, rate: f64) -> Self {
+        Self { volatility, rate }
+    }
+
+    fn d1_d2(&self, option: &OptionAsset, underlying_price: f64, t: f64) -> (f64, f64) {
+        let vol_sqrt_t = self.volatility * t.sqrt();
+        let d1 = ((underlying_price / option.strike).ln()
+            + (self.rate + 0.5 * self.volatility * self.volatility) * t)
+            / vol_sqrt_t;
+        (d1, d1 - vol_sqrt_t)
+    }
+}
+
+impl PricingModel for BlackScholes {
+    fn value(&self, option: &OptionAsset, underlying_price: f64, timestamp: i64) -> f64 {
+        let t = option.time_to_expiry(timestamp);
+        if t <= 0.0 || self.volatility <= 0.0 {
+            return option.intrinsic_value(underlying_price);
+        }
+        let (d1, d2) = self.d1_d2(option, underlying_price, t);
+        let discount = (-self.rate * t).exp();
+        match option.kind {
+            OptionKind::Call => {
+                underlying_price * norm_cdf(d1) - option.strike * discount * norm_cdf(d2)
+            }
+            OptionKind::Put => {
+                option.strike * discount * norm_cdf(-d2) - underlying_price * norm_cdf(-d1)
+            }
+        }
+    }
+
+    fn greeks(&self, option: &OptionAsset, underlying_price: f64, timestamp: i64) -> Greeks {
+        let t = option.time_to_expiry(timestamp);
+        if t <= 0.0 || self.volatility <= 0.0 {
+            let itm = option.intrinsic_value(underlying_price) > 0.0;
+            let delta = match (option.kind, itm) {
+                (OptionKind::Call, true) => 1.0,
+                (OptionKind::Put, true) => -1.0,
+                _ => 0.0,
+            };
+            return Greeks {
+                delta,
+                ..Default::default()
+            };
+        }
+        let (d1, d2) = self.d1_d2(option, underlying_price, t);
+        let discount = (-self.rate * t).exp();
+        let sqrt_t = t.sqrt();
+        let gamma = norm_pdf(d1) / (underlying_price * self.volatility * sqrt_t);
+        let vega = underlying_price * norm_pdf(d1) * sqrt_t / 100.0;
+        let decay = -underlying_price * norm_pdf(d1) * self.volatility / (2.0 * sqrt_t);
+        let (delta, theta) = match option.kind {
+            OptionKind::Call => (
+                norm_cdf(d1),
+                decay - self.rate * option.strike * discount * norm_cdf(d2),
+            ),
+            OptionKind::Put => (
+                norm_cdf(d1) - 1.0,
+                decay + self.rate * option.strike * discount * norm_cdf(-d2),
+            ),
+        };
+        Greeks {
+            delta,
+            gamma,
+            vega,
+            theta: theta / 365.0,
+        }
+    }
+}
+
+fn norm_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * PI).sqrt()
+}
+
+// Uses the approximation of the error function by Abramowitz and Stegun, formula 7.1.26, whose
+// maximum error is 1.5e-7.
+fn norm_cdf(x: f64) -> f64 {
+    let z = x.abs() / 2f64.sqrt();
+    let t = 1.0 / (1.0 + 0.3275911 * z);
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-z * z).exp();
+    if x >= 0.0 {
+        0.5 * (1.0 + erf)
+    } else {
+        0.5 * (1.0 - erf)
+    }
+}
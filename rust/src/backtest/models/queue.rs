@@ -1,18 +1,22 @@
 use crate::{
-    depth::hashmapmarketdepth::HashMapMarketDepth,
+    depth::{MarketDepth, Tick},
     ty::{Order, Side},
 };
 
-pub trait QueueModel<Q>
+/// Estimates the queue position of the orders resting at the exchange from the changes of the
+/// market depth of type `MD` and the trades.
+pub trait QueueModel<Q, MD>
 where
     Q: Clone,
+    MD: MarketDepth,
 {
-    fn new_order(&self, order: &mut Order<Q>, depth: &HashMapMarketDepth);
-    fn trade(&self, order: &mut Order<Q>, qty: f32, depth: &HashMapMarketDepth);
-    fn depth(&self, order: &mut Order<Q>, prev_qty: f32, new_qty: f32, depth: &HashMapMarketDepth);
-    fn is_filled(&self, order: &Order<Q>, depth: &HashMapMarketDepth) -> bool;
+    fn new_order(&self, order: &mut Order<Q>, depth: &MD);
+    fn trade(&self, order: &mut Order<Q>, qty: f32, depth: &MD);
+    fn depth(&self, order: &mut Order<Q>, prev_qty: f32, new_qty: f32, depth: &MD);
+    fn is_filled(&self, order: &Order<Q>, depth: &MD) -> bool;
 }
 
+#[derive(Debug)]
 pub struct RiskAdverseQueueModel(());
 
 impl RiskAdverseQueueModel {
@@ -21,31 +25,28 @@ impl RiskAdverseQueueModel {
     }
 }
 
-impl QueueModel<f32> for RiskAdverseQueueModel {
-    fn new_order(&self, order: &mut Order<f32>, depth: &HashMapMarketDepth) {
+impl<MD> QueueModel<f32, MD> for RiskAdverseQueueModel
+where
+    MD: MarketDepth,
+{
+    fn new_order(&self, order: &mut Order<f32>, depth: &MD) {
         if order.side == Side::Buy {
-            order.q = *depth.bid_depth.get(&order.price_tick).unwrap_or(&0.0);
+            order.q = depth.bid_qty_at_tick(MD::Tick::from_i64(order.price_tick));
         } else {
-            order.q = *depth.ask_depth.get(&order.price_tick).unwrap_or(&0.0);
+            order.q = depth.ask_qty_at_tick(MD::Tick::from_i64(order.price_tick));
         }
     }
 
-    fn trade(&self, order: &mut Order<f32>, qty: f32, _depth: &HashMapMarketDepth) {
+    fn trade(&self, order: &mut Order<f32>, qty: f32, _depth: &MD) {
         order.q -= qty;
     }
 
-    fn depth(
-        &self,
-        order: &mut Order<f32>,
-        _prev_qty: f32,
-        new_qty: f32,
-        _depth: &HashMapMarketDepth,
-    ) {
+    fn depth(&self, order: &mut Order<f32>, _prev_qty: f32, new_qty: f32, _depth: &MD) {
         order.q = order.q.min(new_qty);
     }
 
-    fn is_filled(&self, order: &Order<f32>, depth: &HashMapMarketDepth) -> bool {
-        (order.q / depth.lot_size).round() < 0.0
+    fn is_filled(&self, order: &Order<f32>, depth: &MD) -> bool {
+        (order.q / depth.lot_size()).round() < 0.0
     }
 }
 
@@ -68,6 +69,7 @@ pub trait Probability {
     fn prob(&self, front: f32, back: f32) -> f32;
 }
 
+#[derive(Debug)]
 pub struct ProbQueueModel<P>
 where
     P: Probability,
@@ -91,30 +93,25 @@ where
 /// decreases. The advancement in queue position depends on the probability based on the relative queue position. To
 /// avoid double counting the quantity decrease caused by trades, all trade quantities occurring at the level before
 /// the book quantity changes will be subtracted from the book quantity changes.
-impl<P> QueueModel<QueuePos> for ProbQueueModel<P>
+impl<P, MD> QueueModel<QueuePos, MD> for ProbQueueModel<P>
 where
     P: Probability,
+    MD: MarketDepth,
 {
-    fn new_order(&self, order: &mut Order<QueuePos>, depth: &HashMapMarketDepth) {
+    fn new_order(&self, order: &mut Order<QueuePos>, depth: &MD) {
         if order.side == Side::Buy {
-            order.q.front = *depth.bid_depth.get(&order.price_tick).unwrap_or(&0.0);
+            order.q.front = depth.bid_qty_at_tick(MD::Tick::from_i64(order.price_tick));
         } else {
-            order.q.front = *depth.ask_depth.get(&order.price_tick).unwrap_or(&0.0);
+            order.q.front = depth.ask_qty_at_tick(MD::Tick::from_i64(order.price_tick));
         }
     }
 
-    fn trade(&self, order: &mut Order<QueuePos>, qty: f32, _depth: &HashMapMarketDepth) {
+    fn trade(&self, order: &mut Order<QueuePos>, qty: f32, _depth: &MD) {
         order.q.front -= qty;
         order.q.cum_trade_qty += qty;
     }
 
-    fn depth(
-        &self,
-        order: &mut Order<QueuePos>,
-        prev_qty: f32,
-        new_qty: f32,
-        _depth: &HashMapMarketDepth,
-    ) {
+    fn depth(&self, order: &mut Order<QueuePos>, prev_qty: f32, new_qty: f32, _depth: &MD) {
         let mut chg = prev_qty - new_qty;
         // In order to avoid duplicate order queue position adjustment, subtract queue position
         // change by trades.
@@ -139,11 +136,12 @@ where
         order.q.front = est_front.min(new_qty);
     }
 
-    fn is_filled(&self, order: &Order<QueuePos>, depth: &HashMapMarketDepth) -> bool {
-        (order.q.front / depth.lot_size).round() < 0.0
+    fn is_filled(&self, order: &Order<QueuePos>, depth: &MD) -> bool {
+        (order.q.front / depth.lot_size()).round() < 0.0
     }
 }
 
+#[derive(Debug)]
 pub struct PowerProbQueueFunc {
     n: f32,
 }
@@ -164,6 +162,7 @@ impl Probability for PowerProbQueueFunc {
     }
 }
 
+#[derive(Debug, Default)]
 pub struct LogProbQueueFunc(());
 
 impl LogProbQueueFunc {
@@ -182,6 +181,7 @@ impl Probability for LogProbQueueFunc {
     }
 }
 
+#[derive(Debug, Default)]
 pub struct LogProbQueueFunc2(());
 
 impl LogProbQueueFunc2 {
@@ -200,6 +200,7 @@ impl Probability for LogProbQueueFunc2 {
     }
 }
 
+#[derive(Debug)]
 pub struct PowerProbQueueFunc2 {
     n: f32,
 }
@@ -220,6 +221,7 @@ impl Probability for PowerProbQueueFunc2 {
     }
 }
 
+#[derive(Debug)]
 pub struct PowerProbQueueFunc3 {
     n: f32,
 }
@@ -34,9 +34,26 @@ where
             .unwrap_or(i64::MAX)
     }
 
+    /// Appends the order to be received at `timestamp`. The responses for the same order are
+    /// received in the order they are appended, so a response whose timestamp is earlier than
+    /// that of a pending response for the same order is received at the same time as that one
+    /// instead. This keeps, for example, a cancel reject from overtaking the fill that caused it
+    /// when the response latency varies. The other orders are received at their own timestamps.
     pub fn append(&mut self, order: Order<Q>, timestamp: i64) {
+        let mut order_list = self.order_list.borrow_mut();
+        let timestamp = if self.contains_key(order.order_id) {
+            order_list
+                .iter()
+                .filter(|(pending, _)| pending.order_id == order.order_id)
+                .map(|(_, pending_ts)| *pending_ts)
+                .fold(timestamp, i64::max)
+        } else {
+            timestamp
+        };
         *self.orders.borrow_mut().entry(order.order_id).or_insert(0) += 1;
-        self.order_list.borrow_mut().push((order, timestamp));
+        // Keeps the list sorted by the timestamp, after the ones with the same timestamp.
+        let index = order_list.partition_point(|(_, pending_ts)| *pending_ts <= timestamp);
+        order_list.insert(index, (order, timestamp));
     }
 
     pub fn get_head_timestamp(&self) -> Option<i64> {
@@ -0,0 +1,153 @@
+use std::{
+    fs::File,
+    io::{Error as IoError, ErrorKind},
+    mem::size_of,
+};
+
+use ::parquet::{
+    arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ProjectionMask},
+    errors::ParquetError,
+};
+use arrow::{
+    array::{Array, ArrayRef, AsArray},
+    compute::cast,
+    datatypes::{DataType, Float32Type, Int64Type},
+    error::ArrowError,
+    record_batch::RecordBatch,
+};
+
+use crate::{
+    backtest::reader::{aligned_vec, Data},
+    ty::Event,
+};
+
+/// The columns of the event schema, in order of the fields of [`Event`].
+const COLUMNS: [&str; 5] = ["ev", "exch_ts", "local_ts", "px", "qty"];
+
+// Separates the path and the row group number in the key of a row group, which can't be part of
+// a valid path.
+const ROW_GROUP_SEPARATOR: char = '\0';
+
+fn parquet_error(error: ParquetError) -> IoError {
+    IoError::new(ErrorKind::InvalidData, error.to_string())
+}
+
+fn arrow_error(error: ArrowError) -> IoError {
+    IoError::new(ErrorKind::InvalidData, error.to_string())
+}
+
+fn open(filepath: &str) -> Result<ParquetRecordBatchReaderBuilder<File>, IoError> {
+    let builder =
+        ParquetRecordBatchReaderBuilder::try_new(File::open(filepath)?).map_err(parquet_error)?;
+    for column in COLUMNS {
+        builder.schema().index_of(column).map_err(|_| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("{filepath}: column `{column}` of the event schema is missing"),
+            )
+        })?;
+    }
+    Ok(builder)
+}
+
+/// Returns the keys by which the [`Reader`](crate::backtest::reader::Reader) loads the row groups
+/// of the Parquet file one at a time, each as a separate chunk of the data, as the backtest
+/// progresses.
+pub(crate) fn row_group_keys(filepath: &str) -> Result<Vec<String>, IoError> {
+    let builder = open(filepath)?;
+    Ok((0..builder.metadata().num_row_groups())
+        .map(|row_group| format!("{filepath}{ROW_GROUP_SEPARATOR}{row_group}"))
+        .collect())
+}
+
+/// Splits the key of a row group into the path and the row group number.
+pub(crate) fn split_key(key: &str) -> Option<(&str, usize)> {
+    let (filepath, row_group) = key.split_once(ROW_GROUP_SEPARATOR)?;
+    if filepath.is_empty() {
+        return None;
+    }
+    Some((filepath, row_group.parse().ok()?))
+}
+
+fn column(batch: &RecordBatch, name: &str, data_type: &DataType) -> Result<ArrayRef, IoError> {
+    let array = batch.column_by_name(name).unwrap();
+    if array.null_count() > 0 {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("column `{name}` has nulls"),
+        ));
+    }
+    cast(array, data_type).map_err(arrow_error)
+}
+
+/// Reads the rows of the event schema, which consists of the columns `ev`, `exch_ts`,
+/// `local_ts`, `px`, and `qty`, from the row group of a Parquet file, or from all the row groups
+/// if `row_group` is `None`. The other columns are ignored, and the columns of other numeric
+/// types are cast to the types of the fields of [`Event`].
+pub fn read_parquet<D: Sized>(
+    filepath: &str,
+    row_group: Option<usize>,
+) -> Result<Data<D>, IoError> {
+    if size_of::<D>() != size_of::<Event>() {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "Parquet data is supported only for the event schema",
+        ));
+    }
+    let builder = open(filepath)?;
+    let row_groups: Vec<usize> = match row_group {
+        Some(row_group) if row_group >= builder.metadata().num_row_groups() => {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("{filepath}: row group {row_group} doesn't exist"),
+            ));
+        }
+        Some(row_group) => vec![row_group],
+        None => (0..builder.metadata().num_row_groups()).collect(),
+    };
+    let num_rows = row_groups
+        .iter()
+        .map(|row_group| builder.metadata().row_group(*row_group).num_rows() as usize)
+        .sum::<usize>();
+    let indices = COLUMNS
+        .iter()
+        .map(|column| builder.schema().index_of(column).unwrap())
+        .collect::<Vec<_>>();
+    let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+    let reader = builder
+        .with_row_groups(row_groups)
+        .with_projection(mask)
+        .build()
+        .map_err(parquet_error)?;
+
+    let mut rows = Vec::with_capacity(num_rows);
+    for batch in reader {
+        let batch = batch.map_err(arrow_error)?;
+        let ev = column(&batch, "ev", &DataType::Int64)?;
+        let exch_ts = column(&batch, "exch_ts", &DataType::Int64)?;
+        let local_ts = column(&batch, "local_ts", &DataType::Int64)?;
+        let px = column(&batch, "px", &DataType::Float32)?;
+        let qty = column(&batch, "qty", &DataType::Float32)?;
+        let ev = ev.as_primitive::<Int64Type>().values();
+        let exch_ts = exch_ts.as_primitive::<Int64Type>().values();
+        let local_ts = local_ts.as_primitive::<Int64Type>().values();
+        let px = px.as_primitive::<Float32Type>().values();
+        let qty = qty.as_primitive::<Float32Type>().values();
+        for i in 0..batch.num_rows() {
+            rows.push(Event {
+                ev: ev[i],
+                exch_ts: exch_ts[i],
+                local_ts: local_ts[i],
+                px: px[i],
+                qty: qty[i],
+            });
+        }
+    }
+
+    let size = size_of::<Event>() * rows.len();
+    let mut buf = aligned_vec(size);
+    unsafe {
+        std::ptr::copy_nonoverlapping(rows.as_ptr() as *const u8, buf.as_mut_ptr(), size);
+    }
+    Ok(Data::from_buf(buf, 0))
+}
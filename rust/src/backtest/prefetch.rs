@@ -0,0 +1,73 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Error as IoError,
+    sync::mpsc::{sync_channel, Receiver},
+    thread,
+};
+
+/// The raw buffer of a loaded data file and the length of its header.
+pub(crate) type RawData = (Box<[u8]>, usize);
+
+/// Loads a data file into its raw buffer.
+pub(crate) type Load = fn(&str) -> Result<RawData, IoError>;
+
+/// Loads the data files of a [`Reader`](crate::backtest::reader::Reader) in order in a background
+/// thread, up to `depth` files ahead of the file being read, so that downloading from an object
+/// store and decompressing the next file overlap with the backtest running on the current one.
+#[derive(Debug)]
+pub(crate) struct Prefetcher {
+    rx: Receiver<(String, Result<RawData, IoError>)>,
+    // The files to be received, in order.
+    pending: VecDeque<String>,
+    // The files received while waiting for another one.
+    ready: HashMap<String, Result<RawData, IoError>>,
+}
+
+impl Prefetcher {
+    pub fn start(files: Vec<String>, depth: usize, load: Load) -> Self {
+        // The data in memory is already in the cache.
+        let files: VecDeque<String> = files
+            .into_iter()
+            .filter(|file| !file.starts_with('\0'))
+            .collect();
+        let pending = files.clone();
+        let (tx, rx) = sync_channel(depth.saturating_sub(1));
+        thread::Builder::new()
+            .name("hbt-prefetch".to_string())
+            .spawn(move || {
+                for file in files {
+                    let result = load(&file);
+                    // The reader has been dropped.
+                    if tx.send((file, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+            .unwrap();
+        Self {
+            rx,
+            pending,
+            ready: HashMap::new(),
+        }
+    }
+
+    /// Waits for the file to be loaded. Returns `None` if the file isn't going to be loaded, as it
+    /// has already been taken or isn't in the list, in which case it has to be read directly.
+    pub fn take(&mut self, file: &str) -> Option<Result<RawData, IoError>> {
+        if let Some(result) = self.ready.remove(file) {
+            return Some(result);
+        }
+        if !self.pending.iter().any(|pending| pending == file) {
+            return None;
+        }
+        while let Some(pending) = self.pending.pop_front() {
+            let (loaded, result) = self.rx.recv().ok()?;
+            debug_assert_eq!(loaded, pending);
+            if loaded == file {
+                return Some(result);
+            }
+            self.ready.insert(loaded, result);
+        }
+        None
+    }
+}
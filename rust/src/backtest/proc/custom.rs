@@ -0,0 +1,30 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{depth::MarketDepth, ty::Event};
+
+/// A processor that consumes the feed data of an asset on the local timeline alongside the
+/// backtest's local processor, so that derived state, such as a signal or a feature, can be
+/// computed inside the engine, in sync with the market depth the strategy sees, instead of in the
+/// strategy's body. It's registered by
+/// [`MultiAssetMultiExchangeBacktest::with_processor`](crate::backtest::backtest::MultiAssetMultiExchangeBacktest::with_processor).
+///
+/// The backtest owns the processor, so the strategy reads its state through a shared handle,
+/// which is an `Rc<RefCell<P>>` that the strategy keeps a clone of.
+pub trait CustomProcessor<MD>
+where
+    MD: MarketDepth,
+{
+    /// Processes the row of the feed data at its local timestamp, right after the local processor
+    /// has applied it to the market depth and the trades.
+    fn process(&mut self, row: &Event, depth: &MD);
+}
+
+impl<P, MD> CustomProcessor<MD> for Rc<RefCell<P>>
+where
+    P: CustomProcessor<MD>,
+    MD: MarketDepth,
+{
+    fn process(&mut self, row: &Event, depth: &MD) {
+        self.borrow_mut().process(row, depth)
+    }
+}
@@ -0,0 +1,641 @@
+use std::{collections::HashMap, mem};
+
+use crate::{
+    backtest::{
+        assettype::AssetType,
+        models::LatencyModel,
+        order::OrderBus,
+        proc::proc::{OcoGroups, Processor},
+        profile::{GtxPolicy, ModifyPolicy, OrderRateLimiter},
+        reader::{event_type, Data, Reader, EXCH_EVENT},
+        state::State,
+        Error,
+    },
+    depth::{l3marketdepth::L3MarketDepth, INVALID_MAX, INVALID_MIN},
+    ty::{
+        L3Event,
+        Order,
+        Side,
+        Status,
+        TimeInForce,
+        ADD_ORDER_EVENT,
+        BUY,
+        CANCEL_ORDER_EVENT,
+        DEPTH_CLEAR_EVENT,
+        FILL_EVENT,
+        MODIFY_ORDER_EVENT,
+        SELL,
+    },
+};
+
+/// Market-by-order exchange processor, which matches the orders against the book of individual
+/// orders built from market-by-order data, [`L3Event`], and so knows the exact queue position of
+/// each resting order instead of estimating it by a
+/// [`QueueModel`](crate::backtest::models::QueueModel).
+///
+/// A resting order joins the end of the queue at its price, behind the orders in the book at the
+/// time. When the feed executes an order in the book, the resting orders that would have been
+/// executed first, at a better price or ahead in the queue at the same price, are filled instead
+/// by up to the executed quantity. Likewise, an order added to the book that crosses the resting
+/// orders fills them by up to its quantity. A taker order is filled level by level against the
+/// orders in the book, which are removed from the book as taken, and the rest of a GTC order
+/// rests at its price.
+///
+/// Since the book is replayed from the feed, the strategy's orders don't change the feed's own
+/// executions, which still occur as recorded. Trigger orders aren't supported and expire.
+pub struct L3Exchange<AT, Q, LM>
+where
+    AT: AssetType,
+    Q: Clone + Default,
+    LM: LatencyModel,
+{
+    reader: Reader<L3Event>,
+    data: Data<L3Event>,
+    row_num: usize,
+
+    // key: order_id, value: Order<Q>
+    orders: HashMap<i64, Order<Q>>,
+    // key: order_id, value: the time priority of the order in the queue at its price
+    priorities: HashMap<i64, u64>,
+
+    orders_to: OrderBus<Q>,
+    orders_from: OrderBus<Q>,
+
+    depth: L3MarketDepth,
+    state: State<AT>,
+    order_latency: LM,
+
+    oco_groups: OcoGroups,
+
+    /// What to do with a post-only order that would take liquidity. See [`GtxPolicy`].
+    pub gtx_policy: GtxPolicy,
+    /// Whether a modified order keeps its time priority. See [`ModifyPolicy`].
+    pub modify_policy: ModifyPolicy,
+    /// Rejects the order submissions beyond the venue's rate limits. By default, they aren't
+    /// limited.
+    pub order_rate_limiter: OrderRateLimiter,
+    /// Rejects the new orders with [`Status::TooManyOpenOrders`] once the open orders reach the
+    /// venue's cap. By default, they aren't capped.
+    pub max_open_orders: Option<usize>,
+}
+
+impl<AT, Q, LM> L3Exchange<AT, Q, LM>
+where
+    AT: AssetType,
+    Q: Clone + Default,
+    LM: LatencyModel,
+{
+    pub fn new(
+        reader: Reader<L3Event>,
+        depth: L3MarketDepth,
+        state: State<AT>,
+        order_latency: LM,
+        orders_to: OrderBus<Q>,
+        orders_from: OrderBus<Q>,
+    ) -> Self {
+        Self {
+            reader,
+            data: Data::empty(),
+            row_num: 0,
+            orders: Default::default(),
+            priorities: Default::default(),
+            orders_to,
+            orders_from,
+            depth,
+            state,
+            order_latency,
+            oco_groups: Default::default(),
+            gtx_policy: Default::default(),
+            modify_policy: Default::default(),
+            order_rate_limiter: Default::default(),
+            max_open_orders: None,
+        }
+    }
+
+    fn process_recv_order_(
+        &mut self,
+        mut order: Order<Q>,
+        recv_timestamp: i64,
+        wait_resp: i64,
+        next_timestamp: i64,
+    ) -> Result<i64, Error> {
+        let order_id = order.order_id;
+        let resp_timestamp = if order.req == Status::New {
+            order.req = Status::None;
+            self.ack_new(order, recv_timestamp)?
+        } else if order.req == Status::Canceled {
+            order.req = Status::None;
+            self.ack_cancel(order, recv_timestamp)?
+        } else if order.req == Status::Replaced {
+            order.req = Status::None;
+            self.ack_modify(order, recv_timestamp)?
+        } else {
+            return Err(Error::InvalidOrderRequest);
+        };
+
+        // Checks if the local waits for the orders' response.
+        if wait_resp == order_id {
+            // If next_timestamp is valid, chooses the earlier timestamp.
+            return if next_timestamp > 0 {
+                Ok(next_timestamp.min(resp_timestamp))
+            } else {
+                Ok(resp_timestamp)
+            };
+        }
+        // Bypass next_timestamp
+        Ok(next_timestamp)
+    }
+
+    fn respond(
+        &mut self,
+        mut order: Order<Q>,
+        status: Status,
+        timestamp: i64,
+    ) -> Result<i64, Error> {
+        order.set_status(status)?;
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order)?;
+        self.orders_to.append(order, local_recv_timestamp);
+        Ok(local_recv_timestamp)
+    }
+
+    fn fill(
+        &mut self,
+        order: &mut Order<Q>,
+        timestamp: i64,
+        maker: bool,
+        exec_price_tick: i64,
+        exec_qty: f32,
+    ) -> Result<i64, Error> {
+        if order.terminal() {
+            return Err(Error::InvalidOrderStatus);
+        }
+        if self.oco_groups.is_triggered(order) {
+            // Another order of the group has been filled first, so this order is canceled
+            // instead.
+            order.set_status(Status::Canceled)?;
+            return self.respond(order.clone(), Status::Canceled, timestamp);
+        }
+
+        order.maker = maker;
+        order.exec_price_tick = exec_price_tick;
+        order.exec_qty = exec_qty;
+        order.leaves_qty -= exec_qty;
+        if (order.leaves_qty / self.depth.depth().lot_size).round() > 0f32 {
+            order.set_status(Status::PartiallyFilled)?;
+        } else {
+            order.set_status(Status::Filled)?;
+        }
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp =
+            order.exch_timestamp + self.order_latency.response(timestamp, order)?;
+
+        self.state.apply_fill(order);
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+        self.oco_groups.on_fill(order, timestamp);
+        Ok(local_recv_timestamp)
+    }
+
+    /// Cancels the orders whose one-cancels-other groups have been triggered by fills. The
+    /// cancellations are made at the timestamp of the triggering fill, and are delivered after it.
+    fn cancel_oco_orders(&mut self) -> Result<(), Error> {
+        for (order_id, timestamp) in self.oco_groups.take_pending() {
+            if let Some(order) = self.orders.remove(&order_id) {
+                self.priorities.remove(&order_id);
+                self.respond(order, Status::Canceled, timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the resting orders of the side that would be executed before the order in the book
+    /// at the price tick with the priority, in order of execution, by up to the quantity.
+    fn fill_ahead_of(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        priority: u64,
+        qty: f32,
+        timestamp: i64,
+    ) -> Result<(), Error> {
+        let mut ahead: Vec<(i64, u64, i64)> = self
+            .orders
+            .values()
+            .filter(|order| order.side == side)
+            .map(|order| {
+                (
+                    order.price_tick,
+                    self.priorities[&order.order_id],
+                    order.order_id,
+                )
+            })
+            .filter(|&(order_price_tick, order_priority, _)| {
+                if order_price_tick == price_tick {
+                    order_priority < priority
+                } else if side == Side::Buy {
+                    order_price_tick > price_tick
+                } else {
+                    order_price_tick < price_tick
+                }
+            })
+            .collect();
+        if side == Side::Buy {
+            ahead.sort_by_key(|&(order_price_tick, order_priority, _)| {
+                (-order_price_tick, order_priority)
+            });
+        } else {
+            ahead.sort_by_key(|&(order_price_tick, order_priority, _)| {
+                (order_price_tick, order_priority)
+            });
+        }
+
+        let lot_size = self.depth.depth().lot_size;
+        let mut qty = qty;
+        for (_, _, order_id) in ahead {
+            if (qty / lot_size).round() <= 0f32 {
+                break;
+            }
+            let mut order = self.orders.remove(&order_id).unwrap();
+            let exec_qty = qty.min(order.leaves_qty);
+            qty -= exec_qty;
+            let price_tick = order.price_tick;
+            self.fill(&mut order, timestamp, true, price_tick, exec_qty)?;
+            if order.terminal() {
+                self.priorities.remove(&order_id);
+            } else {
+                self.orders.insert(order_id, order);
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the orders in the book at the opposite best prices up to the order's price, level by
+    /// level, until the order is filled, with a fill per level. The taken quantity is removed
+    /// from the book.
+    fn take(&mut self, order: &mut Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        let mut local_recv_timestamp = 0;
+        while !order.terminal() {
+            let depth = self.depth.depth();
+            let (best_tick, level_qty, book_side) = if order.side == Side::Buy {
+                let best_tick = depth.best_ask_tick;
+                if best_tick == INVALID_MAX || best_tick > order.price_tick {
+                    break;
+                }
+                (best_tick, depth.ask_depth[&best_tick], Side::Sell)
+            } else {
+                let best_tick = depth.best_bid_tick;
+                if best_tick == INVALID_MIN || best_tick < order.price_tick {
+                    break;
+                }
+                (best_tick, depth.bid_depth[&best_tick], Side::Buy)
+            };
+            let exec_qty = order.leaves_qty.min(level_qty);
+
+            // Removes the taken quantity from the queue in order of priority.
+            let queue: Vec<(i64, f32)> = self
+                .depth
+                .queue(book_side, best_tick)
+                .map(|book_order| (book_order.order_id, book_order.qty))
+                .collect();
+            let mut qty = exec_qty;
+            for (order_id, book_qty) in queue {
+                let taken_qty = book_qty.min(qty);
+                self.depth.fill_order(order_id, taken_qty, timestamp);
+                qty -= taken_qty;
+                if qty <= 0f32 {
+                    break;
+                }
+            }
+
+            local_recv_timestamp = self.fill(order, timestamp, false, best_tick, exec_qty)?;
+        }
+        Ok(local_recv_timestamp)
+    }
+
+    /// Checks whether the market depth up to the order's price covers the order's entire quantity.
+    fn is_fully_marketable(&self, order: &Order<Q>) -> bool {
+        let depth = self.depth.depth();
+        let mut cum_qty = 0f32;
+        let order_lots = (order.leaves_qty / depth.lot_size).round();
+        if order.side == Side::Buy {
+            for t in depth.best_ask_tick..=order.price_tick {
+                if let Some(qty) = depth.ask_depth.get(&t) {
+                    cum_qty += qty;
+                    if (cum_qty / depth.lot_size).round() >= order_lots {
+                        return true;
+                    }
+                }
+            }
+        } else {
+            for t in (order.price_tick..=depth.best_bid_tick).rev() {
+                if let Some(qty) = depth.bid_depth.get(&t) {
+                    cum_qty += qty;
+                    if (cum_qty / depth.lot_size).round() >= order_lots {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    fn ack_new(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        if self.orders.contains_key(&order.order_id) {
+            return Err(Error::OrderAlreadyExist);
+        }
+
+        if !self.order_rate_limiter.acquire(timestamp) {
+            // The order rate limit is exceeded.
+            return self.respond(order, Status::Rejected, timestamp);
+        }
+
+        if let Some(max_open_orders) = self.max_open_orders {
+            if self.orders.len() >= max_open_orders {
+                return self.respond(order, Status::TooManyOpenOrders, timestamp);
+            }
+        }
+
+        if self.oco_groups.is_triggered(&order) || order.order_type.is_trigger() {
+            // The order's group has already been triggered, or the order is a trigger order,
+            // which isn't supported.
+            return self.respond(order, Status::Expired, timestamp);
+        }
+
+        let depth = self.depth.depth();
+        let marketable = if order.side == Side::Buy {
+            order.price_tick >= depth.best_ask_tick
+        } else {
+            order.price_tick <= depth.best_bid_tick
+        };
+        let mut local_recv_timestamp = 0;
+        if marketable {
+            // A post-only order is refused by the GTX policy rather than taking the market, and a
+            // fill-or-kill order expires unless its entire quantity is available up to its price.
+            if order.time_in_force == TimeInForce::GTX {
+                let status = self.gtx_policy.status();
+                return self.respond(order, status, timestamp);
+            }
+            if order.time_in_force == TimeInForce::FOK && !self.is_fully_marketable(&order) {
+                return self.respond(order, Status::Expired, timestamp);
+            }
+            local_recv_timestamp = self.take(&mut order, timestamp)?;
+            if order.terminal() {
+                return Ok(local_recv_timestamp);
+            }
+        }
+        if matches!(order.time_in_force, TimeInForce::IOC | TimeInForce::FOK) {
+            // The order must be executed immediately, so the rest expires instead of resting.
+            return self.respond(order, Status::Expired, timestamp);
+        }
+
+        // The order joins the end of the queue at its price.
+        let priority = self.depth.take_priority();
+        self.priorities.insert(order.order_id, priority);
+        if order.status != Status::PartiallyFilled {
+            // The exchange accepts this order.
+            local_recv_timestamp = self.respond(order.clone(), Status::New, timestamp)?;
+            order.set_status(Status::New)?;
+            order.exch_timestamp = timestamp;
+        }
+        self.oco_groups.insert(&order);
+        self.orders.insert(order.order_id, order);
+        Ok(local_recv_timestamp)
+    }
+
+    fn ack_cancel(&mut self, order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        match self.orders.remove(&order.order_id) {
+            Some(exch_order) => {
+                self.priorities.remove(&order.order_id);
+                self.oco_groups.remove(&exch_order);
+                self.respond(exch_order, Status::Canceled, timestamp)
+            }
+            None => {
+                // The order is already gone, such as filled while the cancel was in flight, so
+                // the cancel is rejected.
+                self.reject_request(order, timestamp)
+            }
+        }
+    }
+
+    fn ack_modify(&mut self, order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        if !self.order_rate_limiter.acquire(timestamp) {
+            // The order rate limit is exceeded, so the modification is rejected and the order
+            // remains as it is.
+            return self.reject_request(order, timestamp);
+        }
+        let Some(mut exch_order) = self.orders.remove(&order.order_id) else {
+            // The order is already gone, such as filled while the modification was in flight.
+            return self.reject_request(order, timestamp);
+        };
+        let lot_size = self.depth.depth().lot_size;
+        let exec_qty = exch_order.qty - exch_order.leaves_qty;
+        if ((order.qty - exec_qty) / lot_size).round() <= 0f32 {
+            // The executed quantity can't be modified.
+            self.orders.insert(exch_order.order_id, exch_order);
+            return self.reject_request(order, timestamp);
+        }
+
+        let (prev_price_tick, prev_qty) = (exch_order.price_tick, exch_order.qty);
+        exch_order.price_tick = order.price_tick;
+        exch_order.qty = order.qty;
+        exch_order.leaves_qty = order.qty - exec_qty;
+        // The response carries no fill.
+        exch_order.exec_qty = 0.0;
+        let reset = self.modify_policy.resets_priority(
+            prev_price_tick,
+            prev_qty,
+            exch_order.price_tick,
+            exch_order.qty,
+        );
+
+        let depth = self.depth.depth();
+        let marketable = if exch_order.side == Side::Buy {
+            exch_order.price_tick >= depth.best_ask_tick
+        } else {
+            exch_order.price_tick <= depth.best_bid_tick
+        };
+        let mut local_recv_timestamp = 0;
+        if marketable {
+            self.priorities.remove(&exch_order.order_id);
+            if exch_order.time_in_force == TimeInForce::GTX {
+                // The resting order can't be rejected, so it's canceled instead.
+                let status = match self.gtx_policy.status() {
+                    Status::Rejected => Status::Canceled,
+                    status => status,
+                };
+                self.oco_groups.remove(&exch_order);
+                return self.respond(exch_order, status, timestamp);
+            }
+            local_recv_timestamp = self.take(&mut exch_order, timestamp)?;
+            if exch_order.terminal() {
+                return Ok(local_recv_timestamp);
+            }
+        }
+        if reset || marketable {
+            // The order goes to the end of the queue at its price.
+            let priority = self.depth.take_priority();
+            self.priorities.insert(exch_order.order_id, priority);
+        }
+        if local_recv_timestamp == 0 {
+            // The modification is accepted without taking the market, whose fills would carry
+            // the modified order otherwise.
+            let status = exch_order.status;
+            local_recv_timestamp = self.respond(exch_order.clone(), status, timestamp)?;
+            exch_order.exch_timestamp = timestamp;
+        }
+        self.orders.insert(exch_order.order_id, exch_order);
+        Ok(local_recv_timestamp)
+    }
+
+    /// Rejects the request for the order, which remains as it is.
+    fn reject_request(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        order.req = Status::Rejected;
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order)?;
+        self.orders_to.append(order, local_recv_timestamp);
+        Ok(local_recv_timestamp)
+    }
+
+    /// Fills the resting orders of the opposite side crossed by the order in the book, by up to
+    /// its quantity.
+    fn on_book_order(&mut self, order_id: i64, timestamp: i64) -> Result<(), Error> {
+        let Some(book_order) = self.depth.orders().get(&order_id) else {
+            return Ok(());
+        };
+        let (price_tick, qty) = (book_order.price_tick, book_order.qty);
+        if book_order.side == Side::Buy {
+            self.fill_ahead_of(Side::Sell, price_tick, u64::MAX, qty, timestamp)
+        } else {
+            self.fill_ahead_of(Side::Buy, price_tick, u64::MAX, qty, timestamp)
+        }
+    }
+}
+
+impl<AT, Q, LM> Processor for L3Exchange<AT, Q, LM>
+where
+    AT: AssetType,
+    Q: Clone + Default,
+    LM: LatencyModel,
+{
+    fn initialize_data(&mut self) -> Result<i64, Error> {
+        self.data = self.reader.next()?;
+        for rn in 0..self.data.len() {
+            if self.data[rn].ev & EXCH_EVENT == EXCH_EVENT {
+                self.row_num = rn;
+                self.order_latency.seek(self.data[rn].exch_ts);
+                return Ok(self.data[rn].local_ts);
+            }
+        }
+        Err(Error::EndOfData)
+    }
+
+    fn process_data(&mut self) -> Result<(i64, i64), Error> {
+        let row = self.data[self.row_num].clone();
+        let side = if row.ev & BUY == BUY {
+            Side::Buy
+        } else if row.ev & SELL == SELL {
+            Side::Sell
+        } else {
+            Side::Unsupported
+        };
+        match event_type(row.ev) {
+            DEPTH_CLEAR_EVENT => {
+                self.depth.clear();
+            }
+            ADD_ORDER_EVENT if side != Side::Unsupported => {
+                let added = self
+                    .depth
+                    .add_order(row.order_id, side, row.px, row.qty, row.exch_ts)
+                    .is_some();
+                if added && !self.orders.is_empty() {
+                    self.on_book_order(row.order_id, row.exch_ts)?;
+                }
+            }
+            CANCEL_ORDER_EVENT => {
+                self.depth.delete_order(row.order_id, row.exch_ts);
+            }
+            MODIFY_ORDER_EVENT => {
+                let modified = self
+                    .depth
+                    .modify_order(row.order_id, row.px, row.qty, row.exch_ts)
+                    .is_some();
+                if modified && !self.orders.is_empty() {
+                    self.on_book_order(row.order_id, row.exch_ts)?;
+                }
+            }
+            FILL_EVENT => {
+                if let Some(book_order) = self.depth.fill_order(row.order_id, row.qty, row.exch_ts)
+                {
+                    if !self.orders.is_empty() {
+                        self.fill_ahead_of(
+                            book_order.side,
+                            book_order.price_tick,
+                            book_order.priority,
+                            row.qty.min(book_order.qty),
+                            row.exch_ts,
+                        )?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.cancel_oco_orders()?;
+
+        // Checks
+        let mut next_ts = 0;
+        for rn in (self.row_num + 1)..self.data.len() {
+            if self.data[rn].ev & EXCH_EVENT == EXCH_EVENT {
+                self.row_num = rn;
+                next_ts = self.data[rn].exch_ts;
+                break;
+            }
+        }
+
+        if next_ts <= 0 {
+            let next_data = self.reader.next()?;
+            let next_row = &next_data[0];
+            next_ts = next_row.exch_ts;
+            let data = mem::replace(&mut self.data, next_data);
+            self.reader.release(data);
+            self.row_num = 0;
+        }
+        Ok((next_ts, i64::MAX))
+    }
+
+    fn process_recv_order(&mut self, timestamp: i64, wait_resp: i64) -> Result<i64, Error> {
+        // Processes the order part.
+        let mut next_timestamp = i64::MAX;
+        while self.orders_from.len() > 0 {
+            let recv_timestamp = self.orders_from.get_head_timestamp().unwrap();
+            if timestamp == recv_timestamp {
+                let order = self.orders_from.remove(0);
+                next_timestamp =
+                    self.process_recv_order_(order, recv_timestamp, wait_resp, next_timestamp)?;
+                self.cancel_oco_orders()?;
+            } else {
+                assert!(recv_timestamp > timestamp);
+                break;
+            }
+        }
+        Ok(next_timestamp)
+    }
+
+    fn frontmost_recv_order_timestamp(&self) -> i64 {
+        self.orders_from.frontmost_timestamp()
+    }
+
+    fn frontmost_send_order_timestamp(&self) -> i64 {
+        self.orders_to.frontmost_timestamp()
+    }
+
+    fn expire_all_orders(&mut self, timestamp: i64) -> Result<(), Error> {
+        let mut orders: Vec<Order<Q>> = self.orders.drain().map(|(_, order)| order).collect();
+        orders.sort_by_key(|order| order.order_id);
+        self.priorities.clear();
+        for order in orders {
+            self.oco_groups.remove(&order);
+            self.respond(order, Status::Expired, timestamp)?;
+        }
+        Ok(())
+    }
+}
@@ -3,12 +3,17 @@ use std::{
     mem,
 };
 
+use tracing::warn;
+
 use crate::{
     backtest::{
         assettype::AssetType,
-        models::LatencyModel,
+        models::{LatencyModel, LatencyOffset},
         order::OrderBus,
-        proc::proc::{LocalProcessor, Processor},
+        proc::{
+            proc::{LocalProcessor, Processor},
+            CustomProcessor,
+        },
         reader::{
             Data,
             Reader,
@@ -21,11 +26,27 @@ use crate::{
             LOCAL_EVENT,
             LOCAL_TRADE_EVENT,
         },
-        state::{State, StateValues},
+        state::{FillHook, State, StateValues},
         Error,
+        TradeBuffer,
+    },
+    depth::{MarketDepth, Tick},
+    feature::FeatureSet,
+    ty::{
+        Adjustment,
+        OrdType,
+        Order,
+        Event,
+        Side,
+        Status,
+        TimeInForce,
+        BUY,
+        DEPTH_CLEAR_EVENT,
+        DEPTH_EVENT,
+        DEPTH_SNAPSHOT_EVENT,
+        SELL,
+        TRADE_EVENT,
     },
-    depth::MarketDepth,
-    ty::{OrdType, Order, Event, Side, Status, TimeInForce, BUY, SELL},
 };
 
 pub struct Local<AT, Q, LM, MD>
@@ -44,9 +65,32 @@ where
     pub depth: MD,
     pub state: State<AT>,
     pub order_latency: LM,
-    pub trades: Vec<Event>,
+    pub latency_offset: LatencyOffset,
+    // The effective local timestamp of the row to be processed next.
+    data_ts: i64,
+    // In the ring mode, the trades before `trade_head` have been dropped, and are removed all at
+    // once when as many as the buffer's length have accumulated, not shifting on every trade.
+    trades: Vec<Event>,
+    trade_head: usize,
+    trade_buffer: TradeBuffer,
+    // The number of trades that have exceeded the buffer's length.
+    pub trade_overflow: u64,
     pub last_order_entry_latency: Option<i64>,
     pub last_roundtrip_order_latency: Option<i64>,
+    // The entry latency of each order, taken from the first response to the order.
+    pub order_entry_latencies: HashMap<i64, i64>,
+    // Invoked on every fill with the filled order and the state after the fill is applied.
+    fill_hook: Option<FillHook<Q>>,
+    // The one-cancels-other group that the submitted orders join.
+    oco_group: Option<i64>,
+    // The trigger price of the submitted trigger orders.
+    trigger_price: Option<f32>,
+    processors: Vec<Box<dyn CustomProcessor<MD>>>,
+    pub features: FeatureSet,
+    // Rejects the order submissions, as the asset has no exchange to match them.
+    pub reference_only: bool,
+    // Each depth event replaces the best level of its side.
+    pub bbo_feed: bool,
 }
 
 impl<AT, Q, LM, MD> Local<AT, Q, LM, MD>
@@ -61,7 +105,7 @@ where
         depth: MD,
         state: State<AT>,
         order_latency: LM,
-        trade_len: usize,
+        trade_buffer: TradeBuffer,
         orders_to: OrderBus<Q>,
         orders_from: OrderBus<Q>,
     ) -> Self {
@@ -75,9 +119,26 @@ where
             depth,
             state,
             order_latency,
-            trades: Vec::with_capacity(trade_len),
+            latency_offset: Default::default(),
+            data_ts: 0,
+            trades: Vec::with_capacity(match trade_buffer {
+                TradeBuffer::Disabled => 0,
+                TradeBuffer::Unbounded(len) => len,
+                TradeBuffer::Ring(len) => 2 * len,
+            }),
+            trade_head: 0,
+            trade_buffer,
+            trade_overflow: 0,
             last_order_entry_latency: None,
             last_roundtrip_order_latency: None,
+            order_entry_latencies: Default::default(),
+            fill_hook: None,
+            oco_group: None,
+            trigger_price: None,
+            processors: Vec::new(),
+            features: FeatureSet::new(),
+            reference_only: false,
+            bbo_feed: false,
         }
     }
 
@@ -88,8 +149,41 @@ where
         _wait_resp: i64,
         next_timestamp: i64,
     ) -> Result<i64, Error> {
-        if order.status == Status::Filled {
+        if order.req == Status::Rejected {
+            // The cancel or the modification is rejected, such as since the exchange no longer
+            // has the order, whose final status has been delivered by the preceding response.
+            // Only the request is marked as rejected, unless the order id has been reused by a
+            // new order meanwhile.
+            if let Some(local_order) = self.orders.get_mut(&order.order_id) {
+                if local_order.req != Status::New {
+                    local_order.req = Status::Rejected;
+                }
+            }
+            return Ok(next_timestamp);
+        }
+        if let Some(local_order) = self.orders.get(&order.order_id) {
+            // A duplicate or late response, such as the one overtaken by a fill, is normal with
+            // latency, so it's skipped, keeping the last valid state, as the live bot does.
+            if let Err(error) = local_order.status.transition(order.status) {
+                warn!(
+                    %error,
+                    order_id = order.order_id,
+                    "Skips an invalid order status transition."
+                );
+                return Ok(next_timestamp);
+            }
+        }
+        // Each fill response, including a partial fill, carries the quantity of that fill.
+        if (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+            && order.exec_qty > 0.0
+        {
             self.state.apply_fill(&order);
+            if self.fill_hook.is_some() {
+                let state_values = self.state_values();
+                if let Some(hook) = self.fill_hook.as_mut() {
+                    hook(&order, &state_values);
+                }
+            }
         }
         // Applies the received order response to the local orders.
         match self.orders.entry(order.order_id) {
@@ -107,6 +201,54 @@ where
 
     pub fn clear_last_trades(&mut self) {
         self.trades.clear();
+        self.trade_head = 0;
+    }
+
+    /// Sends the modification of the order to the price and the quantity, which should be less
+    /// than `max_qty` if given.
+    fn replace(
+        &mut self,
+        order_id: i64,
+        price_tick: i64,
+        qty: f32,
+        max_qty: Option<f32>,
+        current_timestamp: i64,
+    ) -> Result<(), Error> {
+        let order = self.orders.get_mut(&order_id).ok_or(Error::OrderNotFound)?;
+
+        if order.req != Status::None && order.req != Status::Rejected {
+            return Err(Error::OrderRequestInProcess);
+        }
+
+        if !order.active() {
+            return Err(Error::InvalidOrderStatus);
+        }
+
+        let exec_qty = order.qty - order.leaves_qty;
+        if max_qty.is_some_and(|max_qty| qty >= max_qty) || qty <= exec_qty {
+            return Err(Error::InvalidOrderRequest);
+        }
+
+        // The local order keeps its price and quantity until the exchange accepts the
+        // modification.
+        order.req = Status::Replaced;
+        order.local_timestamp = current_timestamp;
+        let mut req_order = order.clone();
+        req_order.price_tick = price_tick;
+        req_order.qty = qty;
+        let exch_recv_timestamp =
+            current_timestamp + self.order_latency.entry(current_timestamp, &req_order)?;
+
+        self.orders_to.append(req_order, exch_recv_timestamp);
+        Ok(())
+    }
+
+    fn effective_local_timestamp(&self, row: &Event) -> i64 {
+        if row.ev & 0xff == TRADE_EVENT {
+            row.local_ts + self.latency_offset.trade
+        } else {
+            row.local_ts + self.latency_offset.depth
+        }
     }
 }
 
@@ -127,11 +269,20 @@ where
         time_in_force: TimeInForce,
         current_timestamp: i64,
     ) -> Result<(), Error> {
+        if self.reference_only {
+            return Err(Error::ReferenceOnly);
+        }
         if self.orders.contains_key(&order_id) {
             return Err(Error::OrderAlreadyExist);
         }
 
-        let price_tick = (price / self.depth.tick_size()).round() as i32;
+        let trigger_price_tick = if order_type.is_trigger() {
+            let trigger_price = self.trigger_price.ok_or(Error::InvalidOrderRequest)?;
+            i64::from_price(trigger_price, self.depth.tick_size())
+        } else {
+            0
+        };
+        let price_tick = i64::from_price(price, self.depth.tick_size());
         let mut order = Order::new(
             order_id,
             price_tick,
@@ -142,8 +293,11 @@ where
             time_in_force,
         );
         order.req = Status::New;
+        order.local_timestamp = current_timestamp;
+        order.oco_group = self.oco_group;
+        order.trigger_price_tick = trigger_price_tick;
         let exch_recv_timestamp =
-            current_timestamp + self.order_latency.entry(current_timestamp, &order);
+            current_timestamp + self.order_latency.entry(current_timestamp, &order)?;
 
         self.orders_to.append(order.clone(), exch_recv_timestamp);
         self.orders.insert(order.order_id, order);
@@ -153,24 +307,52 @@ where
     fn cancel(&mut self, order_id: i64, current_timestamp: i64) -> Result<(), Error> {
         let order = self.orders.get_mut(&order_id).ok_or(Error::OrderNotFound)?;
 
-        if order.req != Status::None {
+        // A rejected request doesn't hold the order.
+        if order.req != Status::None && order.req != Status::Rejected {
             return Err(Error::OrderRequestInProcess);
         }
 
+        if !order.active() {
+            return Err(Error::InvalidOrderStatus);
+        }
+
         order.req = Status::Canceled;
+        order.local_timestamp = current_timestamp;
         let exch_recv_timestamp =
-            current_timestamp + self.order_latency.entry(current_timestamp, order);
+            current_timestamp + self.order_latency.entry(current_timestamp, order)?;
 
         self.orders_to.append(order.clone(), exch_recv_timestamp);
         Ok(())
     }
 
+    fn reduce(&mut self, order_id: i64, qty: f32, current_timestamp: i64) -> Result<(), Error> {
+        let order = self.orders.get(&order_id).ok_or(Error::OrderNotFound)?;
+        let price_tick = order.price_tick;
+        self.replace(
+            order_id,
+            price_tick,
+            qty,
+            Some(order.qty),
+            current_timestamp,
+        )
+    }
+
+    fn modify(
+        &mut self,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        current_timestamp: i64,
+    ) -> Result<(), Error> {
+        let price_tick = i64::from_price(price, self.depth.tick_size());
+        self.replace(order_id, price_tick, qty, None, current_timestamp)
+    }
+
     fn clear_inactive_orders(&mut self) {
-        self.orders.retain(|_, order| {
-            order.status != Status::Expired
-                && order.status != Status::Filled
-                && order.status != Status::Canceled
-        })
+        self.orders.retain(|_, order| !order.terminal());
+        let orders = &self.orders;
+        self.order_entry_latencies
+            .retain(|order_id, _| orders.contains_key(order_id));
     }
 
     fn position(&self) -> f64 {
@@ -196,12 +378,46 @@ where
         &self.orders
     }
 
-    fn trade(&self) -> &Vec<Event> {
-        &self.trades
+    fn order_entry_latency(&self, order_id: i64) -> Option<i64> {
+        self.order_entry_latencies.get(&order_id).copied()
+    }
+
+    fn trade(&self) -> &[Event] {
+        &self.trades[self.trade_head..]
+    }
+
+    fn features(&self) -> &[f64] {
+        self.features.values()
     }
 
     fn clear_last_trades(&mut self) {
         self.trades.clear();
+        self.trade_head = 0;
+    }
+
+    fn trade_overflow(&self) -> u64 {
+        self.trade_overflow
+    }
+
+    fn set_fill_hook(&mut self, hook: FillHook<Q>) {
+        self.fill_hook = Some(hook);
+    }
+
+    fn set_oco_group(&mut self, group: Option<i64>) {
+        self.oco_group = group;
+    }
+
+    fn set_trigger_price(&mut self, trigger_price: Option<f32>) {
+        self.trigger_price = trigger_price;
+    }
+
+    fn apply_adjustment(&mut self, adjustment: &Adjustment) {
+        self.state
+            .apply_adjustment(adjustment.position, adjustment.balance);
+    }
+
+    fn add_processor(&mut self, processor: Box<dyn CustomProcessor<MD>>) {
+        self.processors.push(processor);
     }
 }
 
@@ -217,7 +433,9 @@ where
         for rn in 0..self.data.len() {
             if self.data[rn].ev & LOCAL_EVENT == LOCAL_EVENT {
                 self.row_num = rn;
-                return Ok(self.data[rn].local_ts);
+                self.data_ts = self.effective_local_timestamp(&self.data[rn]);
+                self.order_latency.seek(self.data_ts);
+                return Ok(self.data_ts);
             }
         }
         Err(Error::EndOfData)
@@ -233,25 +451,63 @@ where
         } else if row.ev & LOCAL_BID_DEPTH_EVENT == LOCAL_BID_DEPTH_EVENT
             || row.ev & LOCAL_BID_DEPTH_SNAPSHOT_EVENT == LOCAL_BID_DEPTH_SNAPSHOT_EVENT
         {
-            self.depth.update_bid_depth(row.px, row.qty, row.local_ts);
+            if self.bbo_feed && self.depth.best_bid_tick() != MD::Tick::INVALID_MIN {
+                let best_bid = self.depth.best_bid();
+                self.depth.clear_depth(BUY, best_bid);
+            }
+            self.depth.update_bid_depth(row.px, row.qty, row.exch_ts);
         } else if row.ev & LOCAL_ASK_DEPTH_EVENT == LOCAL_ASK_DEPTH_EVENT
             || row.ev & LOCAL_ASK_DEPTH_SNAPSHOT_EVENT == LOCAL_ASK_DEPTH_SNAPSHOT_EVENT
         {
-            self.depth.update_ask_depth(row.px, row.qty, row.local_ts);
+            if self.bbo_feed && self.depth.best_ask_tick() != MD::Tick::INVALID_MAX {
+                let best_ask = self.depth.best_ask();
+                self.depth.clear_depth(SELL, best_ask);
+            }
+            self.depth.update_ask_depth(row.px, row.qty, row.exch_ts);
         }
         // Processes a trade event
         else if row.ev & LOCAL_TRADE_EVENT == LOCAL_TRADE_EVENT {
-            if self.trades.capacity() > 0 {
-                self.trades.push(row.clone());
+            self.features.on_trade(row);
+            match self.trade_buffer {
+                TradeBuffer::Disabled => {}
+                TradeBuffer::Unbounded(len) => {
+                    if self.trades.len() >= len {
+                        self.trade_overflow += 1;
+                    }
+                    self.trades.push(row.clone());
+                }
+                TradeBuffer::Ring(0) => {
+                    self.trade_overflow += 1;
+                }
+                TradeBuffer::Ring(len) => {
+                    if self.trades.len() - self.trade_head >= len {
+                        self.trade_overflow += 1;
+                        self.trade_head += 1;
+                        if self.trade_head >= len {
+                            self.trades.drain(..self.trade_head);
+                            self.trade_head = 0;
+                        }
+                    }
+                    self.trades.push(row.clone());
+                }
             }
         }
+        if matches!(
+            row.ev & 0xff,
+            DEPTH_EVENT | DEPTH_CLEAR_EVENT | DEPTH_SNAPSHOT_EVENT
+        ) {
+            self.features.on_depth(&self.depth);
+        }
+        for processor in self.processors.iter_mut() {
+            processor.process(row, &self.depth);
+        }
 
         // Checks
         let mut next_ts = 0;
         for rn in (self.row_num + 1)..self.data.len() {
             if self.data[rn].ev & LOCAL_EVENT == LOCAL_EVENT {
                 self.row_num = rn;
-                next_ts = self.data[rn].local_ts;
+                next_ts = self.effective_local_timestamp(&self.data[rn]);
                 break;
             }
         }
@@ -259,11 +515,16 @@ where
         if next_ts <= 0 {
             let next_data = self.reader.next()?;
             let next_row = &next_data[0];
-            next_ts = next_row.local_ts;
+            next_ts = self.effective_local_timestamp(next_row);
             let data = mem::replace(&mut self.data, next_data);
             self.reader.release(data);
             self.row_num = 0;
         }
+
+        // The feed is processed in order, so a row with a smaller offset cannot overtake the
+        // preceding row.
+        next_ts = next_ts.max(self.data_ts);
+        self.data_ts = next_ts;
         Ok((next_ts, i64::MAX))
     }
 
@@ -271,10 +532,14 @@ where
         // Processes the order part.
         let mut next_timestamp = i64::MAX;
         while self.orders_from.len() > 0 {
-            let recv_timestamp = self.orders_from.get_head_timestamp().unwrap();
+            let recv_timestamp = self.orders_from.get_head_timestamp().unwrap()
+                + self.latency_offset.order_response;
             if timestamp == recv_timestamp {
                 let order = self.orders_from.remove(0);
                 self.last_order_entry_latency = Some(order.exch_timestamp - order.local_timestamp);
+                self.order_entry_latencies
+                    .entry(order.order_id)
+                    .or_insert(order.exch_timestamp - order.local_timestamp);
                 self.last_roundtrip_order_latency = Some(recv_timestamp - order.local_timestamp);
                 next_timestamp =
                     self.process_recv_order_(order, recv_timestamp, wait_resp, next_timestamp)?;
@@ -287,7 +552,9 @@ where
     }
 
     fn frontmost_recv_order_timestamp(&self) -> i64 {
-        self.orders_from.frontmost_timestamp()
+        self.orders_from
+            .frontmost_timestamp()
+            .saturating_add(self.latency_offset.order_response)
     }
 
     fn frontmost_send_order_timestamp(&self) -> i64 {
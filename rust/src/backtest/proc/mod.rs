@@ -1,8 +1,17 @@
+mod custom;
+mod l3exchange;
 mod local;
 mod nopartialfillexchange;
 mod partialfillexchange;
 mod proc;
+mod referenceexchange;
+mod shared;
 
+pub use custom::CustomProcessor;
+pub use l3exchange::L3Exchange;
 pub use local::Local;
 pub use nopartialfillexchange::NoPartialFillExchange;
+pub use partialfillexchange::PartialFillExchange;
 pub use proc::{LocalProcessor, Processor};
+pub use referenceexchange::ReferenceExchange;
+pub use shared::SharedExchangeHandle;
@@ -1,6 +1,6 @@
 use std::{
     cell::RefCell,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     mem,
     rc::Rc,
 };
@@ -10,7 +10,17 @@ use crate::{
         assettype::AssetType,
         models::{LatencyModel, QueueModel},
         order::OrderBus,
-        proc::proc::Processor,
+        proc::proc::{
+            aggregate_trades,
+            is_snapshot_row,
+            is_triggered_at,
+            ModifyShadows,
+            OcoGroups,
+            Processor,
+            Rollover,
+            TriggerOrders,
+        },
+        profile::{GtxPolicy, ModifyPolicy, ModifyPriorityStats, OrderRateLimiter},
         reader::{
             Data,
             Reader,
@@ -26,17 +36,19 @@ use crate::{
         },
         state::State,
         Error,
+        RolloverPolicy,
     },
-    depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth as _, INVALID_MAX, INVALID_MIN},
-    ty::{Order, Event, Side, Status, TimeInForce, BUY, SELL},
+    depth::{MarketDepth, Tick, INVALID_MAX, INVALID_MIN},
+    ty::{Event, OrdType, Order, Side, Status, TimeInForce, BUY, SELL},
 };
 
-pub struct NoPartialFillExchange<AT, Q, LM, QM>
+pub struct NoPartialFillExchange<AT, Q, LM, QM, MD>
 where
     AT: AssetType,
     Q: Clone + Default,
     LM: LatencyModel,
-    QM: QueueModel<Q>,
+    QM: QueueModel<Q, MD>,
+    MD: MarketDepth,
 {
     reader: Reader<Event>,
     data: Data<Event>,
@@ -45,30 +57,57 @@ where
     // key: order_id, value: Order<Q>
     orders: Rc<RefCell<HashMap<i64, Order<Q>>>>,
     // key: order's price tick, value: order_ids
-    buy_orders: HashMap<i32, HashSet<i64>>,
-    sell_orders: HashMap<i32, HashSet<i64>>,
+    buy_orders: HashMap<i64, HashSet<i64>>,
+    sell_orders: HashMap<i64, HashSet<i64>>,
 
     orders_to: OrderBus<Q>,
     orders_from: OrderBus<Q>,
 
-    depth: HashMapMarketDepth,
+    depth: MD,
     state: State<AT>,
     order_latency: LM,
     queue_model: QM,
 
     filled_orders: Vec<i64>,
+
+    /// The window within which the consecutive trade prints of the same aggressor side are
+    /// aggregated before being fed to the queue model. See [`aggregate_trades`].
+    pub trade_aggregation: Option<i64>,
+    // The row numbers of the trade prints that have been aggregated into an earlier trade.
+    aggregated_rows: VecDeque<usize>,
+
+    /// What to do with the open orders when the book is re-snapshotted at the start of the next
+    /// data file. See [`RolloverPolicy`].
+    pub rollover_policy: RolloverPolicy,
+    rollover: Rollover,
+
+    /// What to do with a post-only order that would take liquidity. See [`GtxPolicy`].
+    pub gtx_policy: GtxPolicy,
+    /// Whether a modified order keeps its queue position. See [`ModifyPolicy`].
+    pub modify_policy: ModifyPolicy,
+    /// Rejects the order submissions and modifications beyond the venue's rate limits. By
+    /// default, they aren't limited.
+    pub order_rate_limiter: OrderRateLimiter,
+    /// Rejects the new orders with [`Status::TooManyOpenOrders`] once the open orders, including
+    /// the untriggered trigger orders, reach the venue's cap. By default, they aren't capped.
+    pub max_open_orders: Option<usize>,
+
+    oco_groups: OcoGroups,
+    trigger_orders: TriggerOrders<Q>,
+    modify_shadows: ModifyShadows<Q>,
 }
 
-impl<AT, Q, LM, QM> NoPartialFillExchange<AT, Q, LM, QM>
+impl<AT, Q, LM, QM, MD> NoPartialFillExchange<AT, Q, LM, QM, MD>
 where
     AT: AssetType,
     Q: Clone + Default,
     LM: LatencyModel,
-    QM: QueueModel<Q>,
+    QM: QueueModel<Q, MD>,
+    MD: MarketDepth,
 {
     pub fn new(
         reader: Reader<Event>,
-        depth: HashMapMarketDepth,
+        depth: MD,
         state: State<AT>,
         order_latency: LM,
         queue_model: QM,
@@ -89,6 +128,17 @@ where
             order_latency,
             queue_model,
             filled_orders: Default::default(),
+            trade_aggregation: None,
+            aggregated_rows: VecDeque::new(),
+            rollover_policy: RolloverPolicy::Keep,
+            rollover: Rollover::None,
+            gtx_policy: Default::default(),
+            modify_policy: Default::default(),
+            order_rate_limiter: Default::default(),
+            max_open_orders: None,
+            oco_groups: Default::default(),
+            trigger_orders: Default::default(),
+            modify_shadows: Default::default(),
         }
     }
 
@@ -121,6 +171,21 @@ where
             order.req = Status::None;
             let resp_timestamp = self.ack_cancel(order, recv_timestamp)?;
 
+            // Checks if the local waits for the orders' response.
+            if wait_resp == order_id {
+                // If next_timestamp is valid, chooses the earlier timestamp.
+                return if next_timestamp > 0 {
+                    Ok(next_timestamp.min(resp_timestamp))
+                } else {
+                    Ok(resp_timestamp)
+                };
+            }
+        }
+        // Processes a modify order.
+        else if order.req == Status::Replaced {
+            order.req = Status::None;
+            let resp_timestamp = self.ack_modify(order, recv_timestamp)?;
+
             // Checks if the local waits for the orders' response.
             if wait_resp == order_id {
                 // If next_timestamp is valid, chooses the earlier timestamp.
@@ -141,7 +206,7 @@ where
     fn check_if_sell_filled(
         &mut self,
         order: &mut Order<Q>,
-        price_tick: i32,
+        price_tick: i64,
         qty: f32,
         timestamp: i64,
     ) -> Result<i64, Error> {
@@ -162,7 +227,7 @@ where
     fn check_if_buy_filled(
         &mut self,
         order: &mut Order<Q>,
-        price_tick: i32,
+        price_tick: i64,
         qty: f32,
         timestamp: i64,
     ) -> Result<i64, Error> {
@@ -185,14 +250,19 @@ where
         order: &mut Order<Q>,
         timestamp: i64,
         maker: bool,
-        exec_price_tick: i32,
+        exec_price_tick: i64,
     ) -> Result<i64, Error> {
-        if order.status == Status::Expired
-            || order.status == Status::Canceled
-            || order.status == Status::Filled
-        {
+        if order.terminal() {
             return Err(Error::InvalidOrderStatus);
         }
+        if self.oco_groups.is_triggered(order) {
+            // Another order of the group has been filled first, so this order is canceled
+            // instead.
+            return self.cancel_oco_order(order, timestamp);
+        }
+
+        self.modify_shadows
+            .on_fill(order.order_id, order.leaves_qty, timestamp);
 
         order.maker = maker;
         if maker {
@@ -203,16 +273,241 @@ where
 
         order.exec_qty = order.leaves_qty;
         order.leaves_qty = 0.0;
-        order.status = Status::Filled;
+        order.set_status(Status::Filled)?;
         order.exch_timestamp = timestamp;
         let local_recv_timestamp =
-            order.exch_timestamp + self.order_latency.response(timestamp, &order);
+            order.exch_timestamp + self.order_latency.response(timestamp, &order)?;
 
         self.state.apply_fill(order);
         self.orders_to.append(order.clone(), local_recv_timestamp);
+        self.oco_groups.on_fill(order, timestamp);
         Ok(local_recv_timestamp)
     }
 
+    /// Cancels the orders whose one-cancels-other groups have been triggered by fills. The
+    /// cancellations are made at the timestamp of the triggering fill, and are delivered after it.
+    fn cancel_oco_orders(&mut self) -> Result<(), Error> {
+        for (order_id, timestamp) in self.oco_groups.take_pending() {
+            let order = self.orders.borrow_mut().remove(&order_id);
+            if let Some(mut order) = order {
+                if order.side == Side::Buy {
+                    self.buy_orders
+                        .get_mut(&order.price_tick)
+                        .unwrap()
+                        .remove(&order_id);
+                } else {
+                    self.sell_orders
+                        .get_mut(&order.price_tick)
+                        .unwrap()
+                        .remove(&order_id);
+                }
+                self.cancel_oco_order(&mut order, timestamp)?;
+            } else if let Some(mut order) = self.trigger_orders.remove(order_id) {
+                self.cancel_oco_order(&mut order, timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cancel_oco_order(&mut self, order: &mut Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        self.modify_shadows.on_leave(order.order_id);
+        order.set_status(Status::Canceled)?;
+        order.exch_timestamp = timestamp;
+        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, order)?;
+        self.orders_to.append(order.clone(), local_recv_timestamp);
+        Ok(local_recv_timestamp)
+    }
+
+    /// Triggers the trigger orders of the side whose trigger prices have been reached by the
+    /// price. A triggered order becomes a market order, taking the opposite best, or a limit order
+    /// at its price, and follows the normal matching path as a new order. A market order expires
+    /// if the opposite side of the book is empty.
+    fn activate_trigger_orders(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        timestamp: i64,
+    ) -> Result<(), Error> {
+        if price_tick == INVALID_MIN || price_tick == INVALID_MAX {
+            return Ok(());
+        }
+        for mut order in self.trigger_orders.take_triggered(side, price_tick) {
+            // The order rejoins its one-cancels-other group when it is accepted again.
+            self.oco_groups.remove(&order);
+            if matches!(
+                order.order_type,
+                OrdType::StopMarket | OrdType::TakeProfitMarket
+            ) {
+                order.order_type = OrdType::Market;
+                let best_tick = if side == Side::Buy {
+                    self.depth.best_ask_tick().to_i64()
+                } else {
+                    self.depth.best_bid_tick().to_i64()
+                };
+                if best_tick == INVALID_MIN || best_tick == INVALID_MAX {
+                    // There is nothing to take, and the market order must not rest at the price
+                    // it was submitted with.
+                    order.set_status(Status::Expired)?;
+                    order.exch_timestamp = timestamp;
+                    let local_recv_timestamp =
+                        timestamp + self.order_latency.response(timestamp, &order)?;
+                    self.orders_to.append(order, local_recv_timestamp);
+                    continue;
+                }
+                order.price_tick = best_tick;
+            } else {
+                order.order_type = OrdType::Limit;
+            }
+            self.accept_new(order, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the rollover policy as the rows at the start of the new data file are processed.
+    /// The open orders are expired before the re-snapshot of the book, or requeued after it.
+    fn on_rollover_row(&mut self) -> Result<(), Error> {
+        let row = &self.data[self.row_num];
+        if row.ev & EXCH_EVENT != EXCH_EVENT {
+            return Ok(());
+        }
+        let timestamp = row.exch_ts;
+        let snapshot = is_snapshot_row(row.ev);
+        match (self.rollover, snapshot) {
+            (Rollover::RolledOver, true) => {
+                self.rollover = Rollover::Resnapshotting;
+                if self.rollover_policy == RolloverPolicy::Expire {
+                    self.expire_orders(timestamp)?;
+                }
+            }
+            (Rollover::Resnapshotting, false) => {
+                self.rollover = Rollover::None;
+                if self.rollover_policy == RolloverPolicy::Requeue {
+                    let mut orders = self.orders.borrow_mut();
+                    for order in orders.values_mut() {
+                        self.queue_model.new_order(order, &self.depth);
+                    }
+                    self.modify_shadows.requeue(&self.queue_model, &self.depth);
+                }
+            }
+            // The new data file continues the book without a re-snapshot.
+            (Rollover::RolledOver, false) => {
+                self.rollover = Rollover::None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn expire_orders(&mut self, timestamp: i64) -> Result<(), Error> {
+        let mut orders: Vec<Order<Q>> = self
+            .orders
+            .borrow_mut()
+            .drain()
+            .map(|(_, order)| order)
+            .collect();
+        orders.sort_by_key(|order| order.order_id);
+        orders.extend(self.trigger_orders.drain());
+        self.buy_orders.clear();
+        self.sell_orders.clear();
+        for mut order in orders {
+            self.modify_shadows.on_leave(order.order_id);
+            order.set_status(Status::Expired)?;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order, local_recv_timestamp);
+        }
+        Ok(())
+    }
+
+    /// Returns the trade of the current row, or the trades aggregated from the current row if the
+    /// trade aggregation is set.
+    fn trades(&mut self, trade_ev: i64) -> Vec<(i64, f32)> {
+        let row = &self.data[self.row_num];
+        match self.trade_aggregation {
+            None => vec![(i64::from_price(row.px, self.depth.tick_size()), row.qty)],
+            Some(window) => {
+                let (trades, aggregated_rows) = aggregate_trades(
+                    &self.data,
+                    self.row_num,
+                    trade_ev,
+                    self.depth.tick_size(),
+                    window,
+                );
+                self.aggregated_rows.extend(aggregated_rows);
+                trades
+            }
+        }
+    }
+
+    fn on_buy_trade(&mut self, price_tick: i64, qty: f32, timestamp: i64) -> Result<(), Error> {
+        if !self.modify_shadows.is_empty() {
+            self.modify_shadows.on_trade(
+                Side::Sell,
+                price_tick,
+                qty,
+                timestamp,
+                &self.queue_model,
+                &self.depth,
+            );
+        }
+        let orders = self.orders.clone();
+        let mut orders_borrowed = orders.borrow_mut();
+        if self.depth.best_bid_tick().to_i64() == INVALID_MIN
+            || (orders_borrowed.len() as i64) < price_tick - self.depth.best_bid_tick().to_i64()
+        {
+            for (_, order) in orders_borrowed.iter_mut() {
+                if order.side == Side::Sell {
+                    self.check_if_sell_filled(order, price_tick, qty, timestamp)?;
+                }
+            }
+        } else {
+            for t in (self.depth.best_bid_tick().to_i64() + 1)..=price_tick {
+                if let Some(order_ids) = self.sell_orders.get(&t) {
+                    for order_id in order_ids.clone().iter() {
+                        let order = orders_borrowed.get_mut(&order_id).unwrap();
+                        self.check_if_sell_filled(order, price_tick, qty, timestamp)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn on_sell_trade(&mut self, price_tick: i64, qty: f32, timestamp: i64) -> Result<(), Error> {
+        if !self.modify_shadows.is_empty() {
+            self.modify_shadows.on_trade(
+                Side::Buy,
+                price_tick,
+                qty,
+                timestamp,
+                &self.queue_model,
+                &self.depth,
+            );
+        }
+        let orders = self.orders.clone();
+        let mut orders_borrowed = orders.borrow_mut();
+        if self.depth.best_ask_tick().to_i64() == INVALID_MAX
+            || (orders_borrowed.len() as i64) < self.depth.best_ask_tick().to_i64() - price_tick
+        {
+            for (_, order) in orders_borrowed.iter_mut() {
+                if order.side == Side::Buy {
+                    self.check_if_buy_filled(order, price_tick, qty, timestamp)?;
+                }
+            }
+        } else {
+            for t in (price_tick..self.depth.best_ask_tick().to_i64()).rev() {
+                if let Some(order_ids) = self.buy_orders.get(&t) {
+                    for order_id in order_ids.clone().iter() {
+                        let order = orders_borrowed.get_mut(&order_id).unwrap();
+                        self.check_if_buy_filled(order, price_tick, qty, timestamp)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn remove_filled_orders(&mut self) {
         if self.filled_orders.len() > 0 {
             let mut orders = self.orders.borrow_mut();
@@ -233,7 +528,17 @@ where
         }
     }
 
-    fn on_bid_qty_chg(&mut self, price_tick: i32, prev_qty: f32, new_qty: f32) {
+    fn on_bid_qty_chg(&mut self, price_tick: i64, prev_qty: f32, new_qty: f32) {
+        if !self.modify_shadows.is_empty() {
+            self.modify_shadows.on_qty_chg(
+                Side::Buy,
+                price_tick,
+                prev_qty,
+                new_qty,
+                &self.queue_model,
+                &self.depth,
+            );
+        }
         let orders = self.orders.clone();
         if let Some(order_ids) = self.buy_orders.get(&price_tick) {
             for order_id in order_ids.iter() {
@@ -245,7 +550,17 @@ where
         }
     }
 
-    fn on_ask_qty_chg(&mut self, price_tick: i32, prev_qty: f32, new_qty: f32) {
+    fn on_ask_qty_chg(&mut self, price_tick: i64, prev_qty: f32, new_qty: f32) {
+        if !self.modify_shadows.is_empty() {
+            self.modify_shadows.on_qty_chg(
+                Side::Sell,
+                price_tick,
+                prev_qty,
+                new_qty,
+                &self.queue_model,
+                &self.depth,
+            );
+        }
         let orders = self.orders.clone();
         if let Some(order_ids) = self.sell_orders.get(&price_tick) {
             for order_id in order_ids.iter() {
@@ -259,17 +574,19 @@ where
 
     fn on_best_bid_update(
         &mut self,
-        prev_best_tick: i32,
-        new_best_tick: i32,
+        prev_best_tick: i64,
+        new_best_tick: i64,
         timestamp: i64,
     ) -> Result<(), Error> {
+        self.modify_shadows
+            .on_cross(Side::Sell, new_best_tick, timestamp);
         // If the best has been significantly updated compared to the previous best, it would be
         // better to iterate orders dict instead of order price ladder.
         {
             let orders = self.orders.clone();
             let mut orders_borrowed = orders.borrow_mut();
             if prev_best_tick == INVALID_MIN
-                || (orders_borrowed.len() as i32) < new_best_tick - prev_best_tick
+                || (orders_borrowed.len() as i64) < new_best_tick - prev_best_tick
             {
                 for (_, order) in orders_borrowed.iter_mut() {
                     if order.side == Side::Sell && order.price_tick <= new_best_tick {
@@ -295,17 +612,19 @@ where
 
     fn on_best_ask_update(
         &mut self,
-        prev_best_tick: i32,
-        new_best_tick: i32,
+        prev_best_tick: i64,
+        new_best_tick: i64,
         timestamp: i64,
     ) -> Result<(), Error> {
+        self.modify_shadows
+            .on_cross(Side::Buy, new_best_tick, timestamp);
         // If the best has been significantly updated compared to the previous best, it would be
         // better to iterate orders dict instead of order price ladder.
         {
             let orders = self.orders.clone();
             let mut orders_borrowed = orders.borrow_mut();
             if prev_best_tick == INVALID_MAX
-                || (orders_borrowed.len() as i32) < prev_best_tick - new_best_tick
+                || (orders_borrowed.len() as i64) < prev_best_tick - new_best_tick
             {
                 for (_, order) in orders_borrowed.iter_mut() {
                     if order.side == Side::Buy && order.price_tick >= new_best_tick {
@@ -329,30 +648,152 @@ where
         Ok(())
     }
 
+    /// Checks whether the market depth up to the order's price covers the order's entire quantity.
+    fn is_fully_marketable(&self, order: &Order<Q>) -> bool {
+        let mut cum_qty = 0f32;
+        let order_lots = (order.leaves_qty / self.depth.lot_size()).round();
+        if order.side == Side::Buy {
+            for t in self.depth.best_ask_tick().to_i64()..=order.price_tick {
+                let qty = self.depth.ask_qty_at_tick(MD::Tick::from_i64(t));
+                if qty > 0.0 {
+                    cum_qty += qty;
+                    if (cum_qty / self.depth.lot_size()).round() >= order_lots {
+                        return true;
+                    }
+                }
+            }
+        } else {
+            for t in (order.price_tick..=self.depth.best_bid_tick().to_i64()).rev() {
+                let qty = self.depth.bid_qty_at_tick(MD::Tick::from_i64(t));
+                if qty > 0.0 {
+                    cum_qty += qty;
+                    if (cum_qty / self.depth.lot_size()).round() >= order_lots {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Acknowledges the order submitted by the local, which is subject to the order rate limit
+    /// and the open order cap.
     fn ack_new(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
         if self.orders.borrow().contains_key(&order.order_id) {
             return Err(Error::OrderAlreadyExist);
         }
 
+        if !self.order_rate_limiter.acquire(timestamp) {
+            // The order rate limit is exceeded.
+            order.set_status(Status::Rejected)?;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(local_recv_timestamp);
+        }
+
+        if let Some(max_open_orders) = self.max_open_orders {
+            if self.orders.borrow().len() + self.trigger_orders.len() >= max_open_orders {
+                order.set_status(Status::TooManyOpenOrders)?;
+                order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order)?;
+                self.orders_to.append(order, local_recv_timestamp);
+                return Ok(local_recv_timestamp);
+            }
+        }
+
+        self.accept_new(order, timestamp)
+    }
+
+    /// Accepts the new order, which has passed the order rate limit and the open order cap, or
+    /// the triggered order, which already passed them when it was submitted.
+    fn accept_new(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        if self.oco_groups.is_triggered(&order) {
+            // The order's group has already been triggered.
+            order.set_status(Status::Expired)?;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(local_recv_timestamp);
+        }
+
+        if order.order_type.is_trigger() {
+            // The order rests untriggered unless it would be triggered immediately by the
+            // opposite best, in which case it expires.
+            let best_tick = if order.side == Side::Buy {
+                self.depth.best_ask_tick().to_i64()
+            } else {
+                self.depth.best_bid_tick().to_i64()
+            };
+            let status = if best_tick != INVALID_MIN
+                && best_tick != INVALID_MAX
+                && is_triggered_at(&order, best_tick)
+            {
+                Status::Expired
+            } else {
+                Status::New
+            };
+            order.set_status(status)?;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order.clone(), local_recv_timestamp);
+            if order.status == Status::New {
+                self.oco_groups.insert(&order);
+                self.trigger_orders.insert(order);
+            }
+            return Ok(local_recv_timestamp);
+        }
+
         if order.side == Side::Buy {
             // Checks if the buy order price is greater than or equal to the current best ask.
-            if order.price_tick >= self.depth.best_ask_tick {
-                if order.time_in_force == TimeInForce::GTX {
-                    order.status = Status::Expired;
+            if order.price_tick >= self.depth.best_ask_tick().to_i64() {
+                // A post-only order is refused by the GTX policy rather than taking the market,
+                // and a fill-or-kill order expires unless its entire quantity is available up to
+                // its price.
+                let expire = match order.time_in_force {
+                    TimeInForce::GTX => true,
+                    TimeInForce::FOK => !self.is_fully_marketable(&order),
+                    _ => false,
+                };
+                if expire {
+                    let status = if order.time_in_force == TimeInForce::GTX {
+                        self.gtx_policy.status()
+                    } else {
+                        Status::Expired
+                    };
+                    order.set_status(status)?;
 
                     order.exch_timestamp = timestamp;
                     let local_recv_timestamp =
-                        timestamp + self.order_latency.response(timestamp, &order);
+                        timestamp + self.order_latency.response(timestamp, &order)?;
                     self.orders_to.append(order.clone(), local_recv_timestamp);
                     Ok(local_recv_timestamp)
                 } else {
                     // Takes the market.
-                    self.fill(&mut order, timestamp, false, self.depth.best_ask_tick)
+                    self.fill(
+                        &mut order,
+                        timestamp,
+                        false,
+                        self.depth.best_ask_tick().to_i64(),
+                    )
                 }
+            } else if matches!(order.time_in_force, TimeInForce::IOC | TimeInForce::FOK) {
+                // The order must be executed immediately, so it expires instead of resting.
+                order.set_status(Status::Expired)?;
+
+                order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order)?;
+                self.orders_to.append(order.clone(), local_recv_timestamp);
+                Ok(local_recv_timestamp)
             } else {
                 // Initializes the order's queue position.
                 self.queue_model.new_order(&mut order, &self.depth);
-                order.status = Status::New;
+                order.set_status(Status::New)?;
                 // The exchange accepts this order.
                 self.buy_orders
                     .entry(order.price_tick)
@@ -361,32 +802,60 @@ where
 
                 order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &order);
+                    timestamp + self.order_latency.response(timestamp, &order)?;
                 self.orders_to.append(order.clone(), local_recv_timestamp);
 
+                self.oco_groups.insert(&order);
                 self.orders.borrow_mut().insert(order.order_id, order);
 
                 Ok(local_recv_timestamp)
             }
         } else {
             // Checks if the sell order price is less than or equal to the current best bid.
-            if order.price_tick <= self.depth.best_bid_tick {
-                if order.time_in_force == TimeInForce::GTX {
-                    order.status = Status::Expired;
+            if order.price_tick <= self.depth.best_bid_tick().to_i64() {
+                // A post-only order is refused by the GTX policy rather than taking the market,
+                // and a fill-or-kill order expires unless its entire quantity is available up to
+                // its price.
+                let expire = match order.time_in_force {
+                    TimeInForce::GTX => true,
+                    TimeInForce::FOK => !self.is_fully_marketable(&order),
+                    _ => false,
+                };
+                if expire {
+                    let status = if order.time_in_force == TimeInForce::GTX {
+                        self.gtx_policy.status()
+                    } else {
+                        Status::Expired
+                    };
+                    order.set_status(status)?;
 
                     order.exch_timestamp = timestamp;
                     let local_recv_timestamp =
-                        timestamp + self.order_latency.response(timestamp, &order);
+                        timestamp + self.order_latency.response(timestamp, &order)?;
                     self.orders_to.append(order.clone(), local_recv_timestamp);
                     Ok(local_recv_timestamp)
                 } else {
                     // Takes the market.
-                    self.fill(&mut order, timestamp, false, self.depth.best_bid_tick)
+                    self.fill(
+                        &mut order,
+                        timestamp,
+                        false,
+                        self.depth.best_bid_tick().to_i64(),
+                    )
                 }
+            } else if matches!(order.time_in_force, TimeInForce::IOC | TimeInForce::FOK) {
+                // The order must be executed immediately, so it expires instead of resting.
+                order.set_status(Status::Expired)?;
+
+                order.exch_timestamp = timestamp;
+                let local_recv_timestamp =
+                    timestamp + self.order_latency.response(timestamp, &order)?;
+                self.orders_to.append(order.clone(), local_recv_timestamp);
+                Ok(local_recv_timestamp)
             } else {
                 // Initializes the order's queue position.
                 self.queue_model.new_order(&mut order, &self.depth);
-                order.status = Status::New;
+                order.set_status(Status::New)?;
                 // The exchange accepts this order.
                 self.sell_orders
                     .entry(order.price_tick)
@@ -395,9 +864,10 @@ where
 
                 order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &order);
+                    timestamp + self.order_latency.response(timestamp, &order)?;
                 self.orders_to.append(order.clone(), local_recv_timestamp);
 
+                self.oco_groups.insert(&order);
                 self.orders.borrow_mut().insert(order.order_id, order);
 
                 Ok(local_recv_timestamp)
@@ -406,18 +876,31 @@ where
     }
 
     fn ack_cancel(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        if let Some(mut exch_order) = self.trigger_orders.remove(order.order_id) {
+            // The order hasn't been triggered yet.
+            self.oco_groups.remove(&exch_order);
+            exch_order.set_status(Status::Canceled)?;
+            exch_order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &exch_order)?;
+            self.orders_to.append(exch_order, local_recv_timestamp);
+            return Ok(local_recv_timestamp);
+        }
+
         let exch_order = {
             let mut order_borrowed = self.orders.borrow_mut();
             order_borrowed.remove(&order.order_id)
         };
 
         if exch_order.is_none() {
-            order.status = Status::Expired;
+            // The order is already gone, such as filled while the cancel was in flight, so the
+            // cancel is rejected. The response follows the order's last response on the bus, so
+            // the local sees the fill first and then the reject.
+            order.req = Status::Rejected;
             order.exch_timestamp = timestamp;
-            let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &order);
-            // It can overwrite another existing order on the local side if order_id is the same.
-            // So, commented out.
-            // self.orders_to.append(order.copy(), local_recv_timestamp)
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order, local_recv_timestamp);
             return Ok(local_recv_timestamp);
         }
 
@@ -435,29 +918,57 @@ where
                 .remove(&exch_order.order_id);
         }
 
+        self.oco_groups.remove(&exch_order);
+        self.modify_shadows.on_leave(exch_order.order_id);
+
         // Make the response.
-        exch_order.status = Status::Canceled;
+        exch_order.set_status(Status::Canceled)?;
         exch_order.exch_timestamp = timestamp;
-        let local_recv_timestamp = timestamp + self.order_latency.response(timestamp, &exch_order);
+        let local_recv_timestamp =
+            timestamp + self.order_latency.response(timestamp, &exch_order)?;
         self.orders_to
             .append(exch_order.clone(), local_recv_timestamp);
         Ok(local_recv_timestamp)
     }
 
     fn ack_modify(&mut self, mut order: Order<Q>, timestamp: i64) -> Result<i64, Error> {
+        if !self.order_rate_limiter.acquire(timestamp) {
+            // The order rate limit is exceeded, so the modification is rejected and the order
+            // remains as it is.
+            order.req = Status::Rejected;
+            order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &order)?;
+            self.orders_to.append(order, local_recv_timestamp);
+            return Ok(local_recv_timestamp);
+        }
+
+        if let Some(mut exch_order) = self.trigger_orders.remove(order.order_id) {
+            // The order hasn't been triggered yet, so it's modified in place.
+            exch_order.price_tick = order.price_tick;
+            exch_order.qty = order.qty;
+            exch_order.leaves_qty = order.qty;
+            exch_order.exch_timestamp = timestamp;
+            let local_recv_timestamp =
+                timestamp + self.order_latency.response(timestamp, &exch_order)?;
+            self.orders_to
+                .append(exch_order.clone(), local_recv_timestamp);
+            self.trigger_orders.insert(exch_order);
+            return Ok(local_recv_timestamp);
+        }
+
         let mut exch_order = {
             let mut order_borrowed = self.orders.borrow_mut();
             let exch_order = order_borrowed.remove(&order.order_id);
 
-            // The order can be already deleted due to fill or expiration.
+            // The order can be already deleted due to fill or expiration, so the modification is
+            // rejected, as the cancel is.
             if exch_order.is_none() {
-                order.status = Status::Expired;
+                order.req = Status::Rejected;
                 order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &order);
-                // It can overwrite another existing order on the local side if order_id is the
-                // same. So, commented out.
-                // self.orders_to.append(order.copy(), local_recv_timestamp)
+                    timestamp + self.order_latency.response(timestamp, &order)?;
+                self.orders_to.append(order, local_recv_timestamp);
                 return Ok(local_recv_timestamp);
             }
 
@@ -465,32 +976,50 @@ where
         };
 
         let prev_price_tick = exch_order.price_tick;
+        let prev_qty = exch_order.qty;
         exch_order.price_tick = order.price_tick;
         // No partial fill occurs.
         exch_order.qty = order.qty;
-        // The initialization of the order queue position may not occur when the modified quantity
-        // is smaller than the previous quantity, depending on the exchanges. It may need to
-        // implement exchange-specific specialization.
-        let init_q_pos = true;
+        exch_order.leaves_qty = order.qty;
+        // Whether the queue position is initialized depends on the exchange. See
+        // [`ModifyPolicy`].
+        let init_q_pos = self.modify_policy.resets_priority(
+            prev_price_tick,
+            prev_qty,
+            exch_order.price_tick,
+            exch_order.qty,
+        );
+        self.modify_shadows
+            .on_modify(&exch_order, prev_price_tick, init_q_pos);
 
         if exch_order.side == Side::Buy {
             // Check if the buy order price is greater than or equal to the current best ask.
-            if exch_order.price_tick >= self.depth.best_ask_tick {
+            if exch_order.price_tick >= self.depth.best_ask_tick().to_i64() {
                 self.buy_orders
                     .get_mut(&prev_price_tick)
                     .unwrap()
                     .remove(&exch_order.order_id);
 
                 if exch_order.time_in_force == TimeInForce::GTX {
-                    exch_order.status = Status::Expired;
+                    // The resting order can't be rejected, so it's canceled instead.
+                    let status = match self.gtx_policy.status() {
+                        Status::Rejected => Status::Canceled,
+                        status => status,
+                    };
+                    exch_order.set_status(status)?;
                 } else {
                     // Take the market.
-                    return self.fill(&mut exch_order, timestamp, false, self.depth.best_ask_tick);
+                    return self.fill(
+                        &mut exch_order,
+                        timestamp,
+                        false,
+                        self.depth.best_ask_tick().to_i64(),
+                    );
                 }
 
                 exch_order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &exch_order);
+                    timestamp + self.order_latency.response(timestamp, &exch_order)?;
                 self.orders_to
                     .append(exch_order.clone(), local_recv_timestamp);
                 Ok(local_recv_timestamp)
@@ -510,11 +1039,11 @@ where
                     // Initialize the order's queue position.
                     self.queue_model.new_order(&mut exch_order, &self.depth);
                 }
-                exch_order.status = Status::New;
+                exch_order.set_status(Status::New)?;
 
                 exch_order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &exch_order);
+                    timestamp + self.order_latency.response(timestamp, &exch_order)?;
                 self.orders_to
                     .append(exch_order.clone(), local_recv_timestamp);
 
@@ -525,22 +1054,32 @@ where
             }
         } else {
             // Check if the sell order price is less than or equal to the current best bid.
-            if exch_order.price_tick <= self.depth.best_bid_tick {
+            if exch_order.price_tick <= self.depth.best_bid_tick().to_i64() {
                 self.sell_orders
                     .get_mut(&prev_price_tick)
                     .unwrap()
                     .remove(&exch_order.order_id);
 
                 if exch_order.time_in_force == TimeInForce::GTX {
-                    exch_order.status = Status::Expired;
+                    // The resting order can't be rejected, so it's canceled instead.
+                    let status = match self.gtx_policy.status() {
+                        Status::Rejected => Status::Canceled,
+                        status => status,
+                    };
+                    exch_order.set_status(status)?;
                 } else {
                     // Take the market.
-                    return self.fill(&mut exch_order, timestamp, false, self.depth.best_bid_tick);
+                    return self.fill(
+                        &mut exch_order,
+                        timestamp,
+                        false,
+                        self.depth.best_bid_tick().to_i64(),
+                    );
                 }
 
                 exch_order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &exch_order);
+                    timestamp + self.order_latency.response(timestamp, &exch_order)?;
                 self.orders_to
                     .append(exch_order.clone(), local_recv_timestamp);
                 Ok(local_recv_timestamp)
@@ -560,11 +1099,11 @@ where
                     // Initialize the order's queue position.
                     self.queue_model.new_order(&mut exch_order, &self.depth);
                 }
-                exch_order.status = Status::New;
+                exch_order.set_status(Status::New)?;
 
                 exch_order.exch_timestamp = timestamp;
                 let local_recv_timestamp =
-                    timestamp + self.order_latency.response(timestamp, &exch_order);
+                    timestamp + self.order_latency.response(timestamp, &exch_order)?;
                 self.orders_to
                     .append(exch_order.clone(), local_recv_timestamp);
 
@@ -575,29 +1114,26 @@ where
             }
         }
     }
-}
 
-impl<AT, Q, LM, QM> Processor for NoPartialFillExchange<AT, Q, LM, QM>
-where
-    Q: Clone + Default,
-    AT: AssetType,
-    LM: LatencyModel,
-    QM: QueueModel<Q>,
-{
-    fn initialize_data(&mut self) -> Result<i64, Error> {
-        self.data = self.reader.next()?;
-        for rn in 0..self.data.len() {
-            if self.data[rn].ev & EXCH_EVENT == EXCH_EVENT {
-                self.row_num = rn;
-                return Ok(self.data[rn].local_ts);
-            }
-        }
-        Err(Error::EndOfData)
+    /// Applies a market event received live instead of read from the data, so that the exchange
+    /// can run on a live feed. See [`PaperConnector`](crate::connector::paper::PaperConnector).
+    #[cfg(feature = "live")]
+    pub(crate) fn apply_row(&mut self, row: Event) -> Result<(), Error> {
+        self.data = Data::from_rows(&[row]);
+        self.row_num = 0;
+        self.process_row()
     }
 
-    fn process_data(&mut self) -> Result<(i64, i64), Error> {
+    /// Processes the current row of the data.
+    fn process_row(&mut self) -> Result<(), Error> {
         let row_num = self.row_num;
-        if self.data[row_num].ev & EXCH_BID_DEPTH_CLEAR_EVENT == EXCH_BID_DEPTH_CLEAR_EVENT {
+        if self.rollover != Rollover::None {
+            self.on_rollover_row()?;
+        }
+        if self.aggregated_rows.front() == Some(&row_num) {
+            // The trade print has already been aggregated into an earlier trade.
+            self.aggregated_rows.pop_front();
+        } else if self.data[row_num].ev & EXCH_BID_DEPTH_CLEAR_EVENT == EXCH_BID_DEPTH_CLEAR_EVENT {
             self.depth.clear_depth(BUY, self.data[row_num].px);
         } else if self.data[row_num].ev & EXCH_ASK_DEPTH_CLEAR_EVENT == EXCH_ASK_DEPTH_CLEAR_EVENT {
             self.depth.clear_depth(SELL, self.data[row_num].px);
@@ -611,9 +1147,13 @@ where
                     self.data[row_num].qty,
                     self.data[row_num].exch_ts,
                 );
-            self.on_bid_qty_chg(price_tick, prev_qty, new_qty);
+            self.on_bid_qty_chg(price_tick.to_i64(), prev_qty, new_qty);
             if best_bid_tick > prev_best_bid_tick {
-                self.on_best_bid_update(prev_best_bid_tick, best_bid_tick, timestamp)?;
+                self.on_best_bid_update(
+                    prev_best_bid_tick.to_i64(),
+                    best_bid_tick.to_i64(),
+                    timestamp,
+                )?;
             }
         } else if self.data[row_num].ev & EXCH_ASK_DEPTH_EVENT == EXCH_ASK_DEPTH_EVENT
             || self.data[row_num].ev & EXCH_ASK_DEPTH_SNAPSHOT_EVENT
@@ -625,83 +1165,77 @@ where
                     self.data[row_num].qty,
                     self.data[row_num].exch_ts,
                 );
-            self.on_ask_qty_chg(price_tick, prev_qty, new_qty);
+            self.on_ask_qty_chg(price_tick.to_i64(), prev_qty, new_qty);
             if best_ask_tick < prev_best_ask_tick {
-                self.on_best_ask_update(prev_best_ask_tick, best_ask_tick, timestamp)?;
+                self.on_best_ask_update(
+                    prev_best_ask_tick.to_i64(),
+                    best_ask_tick.to_i64(),
+                    timestamp,
+                )?;
             }
         } else if self.data[row_num].ev & EXCH_BUY_TRADE_EVENT == EXCH_BUY_TRADE_EVENT {
-            let price_tick = (self.data[row_num].px / self.depth.tick_size).round() as i32;
-            let qty = self.data[row_num].qty;
-            {
-                let orders = self.orders.clone();
-                let mut orders_borrowed = orders.borrow_mut();
-                if self.depth.best_bid_tick == INVALID_MIN
-                    || (orders_borrowed.len() as i32) < price_tick - self.depth.best_bid_tick
-                {
-                    for (_, order) in orders_borrowed.iter_mut() {
-                        if order.side == Side::Sell {
-                            self.check_if_sell_filled(
-                                order,
-                                price_tick,
-                                qty,
-                                self.data[row_num].exch_ts,
-                            )?;
-                        }
-                    }
-                } else {
-                    for t in (self.depth.best_bid_tick + 1)..=price_tick {
-                        if let Some(order_ids) = self.sell_orders.get(&t) {
-                            for order_id in order_ids.clone().iter() {
-                                let order = orders_borrowed.get_mut(&order_id).unwrap();
-                                self.check_if_sell_filled(
-                                    order,
-                                    price_tick,
-                                    qty,
-                                    self.data[row_num].exch_ts,
-                                )?;
-                            }
-                        }
-                    }
-                }
+            let timestamp = self.data[row_num].exch_ts;
+            for (price_tick, qty) in self.trades(EXCH_BUY_TRADE_EVENT) {
+                self.on_buy_trade(price_tick, qty, timestamp)?;
+                self.remove_filled_orders();
             }
-            self.remove_filled_orders();
         } else if self.data[row_num].ev & EXCH_SELL_TRADE_EVENT == EXCH_SELL_TRADE_EVENT {
-            let price_tick = (self.data[row_num].px / self.depth.tick_size).round() as i32;
-            let qty = self.data[row_num].qty;
+            let timestamp = self.data[row_num].exch_ts;
+            for (price_tick, qty) in self.trades(EXCH_SELL_TRADE_EVENT) {
+                self.on_sell_trade(price_tick, qty, timestamp)?;
+                self.remove_filled_orders();
+            }
+        }
+        if !self.trigger_orders.is_empty() {
+            let row = &self.data[row_num];
+            let (ev, timestamp) = (row.ev, row.exch_ts);
+            if ev & EXCH_BUY_TRADE_EVENT == EXCH_BUY_TRADE_EVENT
+                || ev & EXCH_SELL_TRADE_EVENT == EXCH_SELL_TRADE_EVENT
             {
-                let orders = self.orders.clone();
-                let mut orders_borrowed = orders.borrow_mut();
-                if self.depth.best_ask_tick == INVALID_MAX
-                    || (orders_borrowed.len() as i32) < self.depth.best_ask_tick - price_tick
-                {
-                    for (_, order) in orders_borrowed.iter_mut() {
-                        if order.side == Side::Buy {
-                            self.check_if_buy_filled(
-                                order,
-                                price_tick,
-                                qty,
-                                self.data[row_num].exch_ts,
-                            )?;
-                        }
-                    }
-                } else {
-                    for t in (price_tick..self.depth.best_ask_tick).rev() {
-                        if let Some(order_ids) = self.buy_orders.get(&t) {
-                            for order_id in order_ids.clone().iter() {
-                                let order = orders_borrowed.get_mut(&order_id).unwrap();
-                                self.check_if_buy_filled(
-                                    order,
-                                    price_tick,
-                                    qty,
-                                    self.data[row_num].exch_ts,
-                                )?;
-                            }
-                        }
-                    }
-                }
+                let trade_tick = i64::from_price(row.px, self.depth.tick_size());
+                self.activate_trigger_orders(Side::Buy, trade_tick, timestamp)?;
+                self.activate_trigger_orders(Side::Sell, trade_tick, timestamp)?;
+            }
+            // The best prices are transient while the book is being re-snapshotted.
+            if !is_snapshot_row(ev) {
+                self.activate_trigger_orders(
+                    Side::Buy,
+                    self.depth.best_ask_tick().to_i64(),
+                    timestamp,
+                )?;
+                self.activate_trigger_orders(
+                    Side::Sell,
+                    self.depth.best_bid_tick().to_i64(),
+                    timestamp,
+                )?;
+            }
+        }
+        self.cancel_oco_orders()
+    }
+}
+
+impl<AT, Q, LM, QM, MD> Processor for NoPartialFillExchange<AT, Q, LM, QM, MD>
+where
+    Q: Clone + Default,
+    AT: AssetType,
+    LM: LatencyModel,
+    QM: QueueModel<Q, MD>,
+    MD: MarketDepth,
+{
+    fn initialize_data(&mut self) -> Result<i64, Error> {
+        self.data = self.reader.next()?;
+        for rn in 0..self.data.len() {
+            if self.data[rn].ev & EXCH_EVENT == EXCH_EVENT {
+                self.row_num = rn;
+                self.order_latency.seek(self.data[rn].exch_ts);
+                return Ok(self.data[rn].local_ts);
             }
-            self.remove_filled_orders();
         }
+        Err(Error::EndOfData)
+    }
+
+    fn process_data(&mut self) -> Result<(i64, i64), Error> {
+        self.process_row()?;
 
         // Checks
         let mut next_ts = 0;
@@ -720,6 +1254,9 @@ where
             let data = mem::replace(&mut self.data, next_data);
             self.reader.release(data);
             self.row_num = 0;
+            if self.rollover_policy != RolloverPolicy::Keep {
+                self.rollover = Rollover::RolledOver;
+            }
         }
         Ok((next_ts, i64::MAX))
     }
@@ -733,6 +1270,7 @@ where
                 let order = self.orders_from.remove(0);
                 next_timestamp =
                     self.process_recv_order_(order, recv_timestamp, wait_resp, next_timestamp)?;
+                self.cancel_oco_orders()?;
             } else {
                 assert!(recv_timestamp > timestamp);
                 break;
@@ -748,4 +1286,12 @@ where
     fn frontmost_send_order_timestamp(&self) -> i64 {
         self.orders_to.frontmost_timestamp()
     }
+
+    fn expire_all_orders(&mut self, timestamp: i64) -> Result<(), Error> {
+        self.expire_orders(timestamp)
+    }
+
+    fn modify_priority_stats(&self) -> ModifyPriorityStats {
+        self.modify_shadows.stats()
+    }
 }
@@ -1,9 +1,26 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem};
 
 use crate::{
-    backtest::{state::StateValues, Error},
-    depth::MarketDepth,
-    ty::{OrdType, Order, Event, Side, TimeInForce},
+    backtest::{
+        models::QueueModel,
+        proc::CustomProcessor,
+        profile::ModifyPriorityStats,
+        reader::{Data, EXCH_EVENT},
+        state::{FillHook, StateValues},
+        Error,
+    },
+    depth::{MarketDepth, Tick},
+    ty::{
+        Adjustment,
+        OrdType,
+        Order,
+        Event,
+        Side,
+        TimeInForce,
+        DEPTH_CLEAR_EVENT,
+        DEPTH_SNAPSHOT_EVENT,
+        TRADE_EVENT,
+    },
 };
 
 pub trait LocalProcessor<Q, MD>: Processor
@@ -22,13 +39,46 @@ where
         current_timestamp: i64,
     ) -> Result<(), Error>;
     fn cancel(&mut self, order_id: i64, current_timestamp: i64) -> Result<(), Error>;
+    /// Reduces the quantity of the order to `qty` without canceling it. `qty` is the new total
+    /// quantity, which should be less than the order's quantity and greater than its executed
+    /// quantity.
+    fn reduce(&mut self, order_id: i64, qty: f32, current_timestamp: i64) -> Result<(), Error>;
+    /// Modifies the price and the quantity of the order without canceling it. `qty` is the new
+    /// total quantity, which should be greater than the order's executed quantity.
+    fn modify(
+        &mut self,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        current_timestamp: i64,
+    ) -> Result<(), Error>;
     fn clear_inactive_orders(&mut self);
     fn position(&self) -> f64;
     fn state_values(&self) -> StateValues;
     fn depth(&self) -> &MD;
     fn orders(&self) -> &HashMap<i64, Order<Q>>;
-    fn trade(&self) -> &Vec<Event>;
+    fn order_entry_latency(&self, order_id: i64) -> Option<i64>;
+    fn trade(&self) -> &[Event];
+    /// Returns the latest values of the features. See
+    /// [`FeatureSet`](crate::feature::FeatureSet).
+    fn features(&self) -> &[f64];
     fn clear_last_trades(&mut self);
+    /// Returns the number of trades that have exceeded the length of the trade buffer. See
+    /// [`TradeBuffer`](crate::backtest::TradeBuffer).
+    fn trade_overflow(&self) -> u64;
+    /// Sets the hook invoked on every fill, after the fill is applied to the state, with the
+    /// filled order and the resulting state values.
+    fn set_fill_hook(&mut self, hook: FillHook<Q>);
+    /// Sets the one-cancels-other group that the orders submitted afterward join, or `None` to
+    /// stop grouping them.
+    fn set_oco_group(&mut self, group: Option<i64>);
+    /// Sets the trigger price of the trigger orders submitted afterward, or `None` to unset it.
+    fn set_trigger_price(&mut self, trigger_price: Option<f32>);
+    /// Applies a manual adjustment of the position and the balance.
+    fn apply_adjustment(&mut self, adjustment: &Adjustment);
+    /// Adds a processor that consumes every row of the feed data after it's applied, in order of
+    /// addition. See [`CustomProcessor`].
+    fn add_processor(&mut self, processor: Box<dyn CustomProcessor<MD>>);
 }
 
 pub trait Processor {
@@ -37,4 +87,372 @@ pub trait Processor {
     fn process_recv_order(&mut self, timestamp: i64, wait_resp: i64) -> Result<i64, Error>;
     fn frontmost_recv_order_timestamp(&self) -> i64;
     fn frontmost_send_order_timestamp(&self) -> i64;
+
+    /// Expires all the open orders at the timestamp, as the venue does when it cancels the orders
+    /// of a stale connection. Only the exchange processors hold the open orders to expire.
+    fn expire_all_orders(&mut self, _timestamp: i64) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Returns the fills gained and lost by the queue position resets of the modified orders.
+    /// Only [`NoPartialFillExchange`](crate::backtest::proc::NoPartialFillExchange) tracks them,
+    /// as it applies the [`ModifyPolicy`](crate::backtest::profile::ModifyPolicy).
+    fn modify_priority_stats(&self) -> ModifyPriorityStats {
+        Default::default()
+    }
+}
+
+/// The progress of the rollover to the next data file, for applying the
+/// [`RolloverPolicy`](crate::backtest::RolloverPolicy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Rollover {
+    None,
+    /// Rolled over to the next data file, whose first exchange row is yet to be seen.
+    RolledOver,
+    /// The book is being re-snapshotted by the rows at the start of the new data file.
+    Resnapshotting,
+}
+
+/// Tracks the one-cancels-other groups of the orders accepted by an exchange.
+#[derive(Default)]
+pub(super) struct OcoGroups {
+    // key: group, value: the ids of the group's orders resting in the book.
+    orders: HashMap<i64, Vec<i64>>,
+    // key: group, value: the id of the order whose fill has triggered the group.
+    triggered: HashMap<i64, i64>,
+    // The ids of the orders to be canceled as their groups have been triggered, with the
+    // timestamp of the triggering fill.
+    pending: Vec<(i64, i64)>,
+}
+
+impl OcoGroups {
+    /// Adds the order to its group as it rests in the book.
+    pub fn insert<Q: Clone>(&mut self, order: &Order<Q>) {
+        if let Some(group) = order.oco_group {
+            self.orders.entry(group).or_default().push(order.order_id);
+        }
+    }
+
+    /// Removes the order from its group as it leaves the book other than by a fill.
+    pub fn remove<Q: Clone>(&mut self, order: &Order<Q>) {
+        if let Some(group) = order.oco_group {
+            if let Some(order_ids) = self.orders.get_mut(&group) {
+                order_ids.retain(|order_id| *order_id != order.order_id);
+                if order_ids.is_empty() {
+                    self.orders.remove(&group);
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the order's group has been triggered by the fill of another order.
+    pub fn is_triggered<Q: Clone>(&self, order: &Order<Q>) -> bool {
+        order
+            .oco_group
+            .and_then(|group| self.triggered.get(&group))
+            .is_some_and(|order_id| *order_id != order.order_id)
+    }
+
+    /// Triggers the order's group on its first fill, scheduling the cancellation of the other
+    /// orders of the group.
+    pub fn on_fill<Q: Clone>(&mut self, order: &Order<Q>, timestamp: i64) {
+        let Some(group) = order.oco_group else {
+            return;
+        };
+        if self.triggered.contains_key(&group) {
+            return;
+        }
+        self.triggered.insert(group, order.order_id);
+        for order_id in self.orders.remove(&group).unwrap_or_default() {
+            if order_id != order.order_id {
+                self.pending.push((order_id, timestamp));
+            }
+        }
+    }
+
+    /// Takes the orders to be canceled, with the timestamp of the fill that triggered them.
+    pub fn take_pending(&mut self) -> Vec<(i64, i64)> {
+        mem::take(&mut self.pending)
+    }
+}
+
+/// Holds the trigger orders accepted by an exchange, such as stop and take-profit orders, until
+/// they are triggered.
+#[derive(Default)]
+pub(super) struct TriggerOrders<Q: Clone> {
+    // key: order_id, value: Order<Q>
+    orders: HashMap<i64, Order<Q>>,
+}
+
+impl<Q: Clone> TriggerOrders<Q> {
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+
+    pub fn insert(&mut self, order: Order<Q>) {
+        self.orders.insert(order.order_id, order);
+    }
+
+    pub fn remove(&mut self, order_id: i64) -> Option<Order<Q>> {
+        self.orders.remove(&order_id)
+    }
+
+    /// Removes all the orders, in order of their order ids.
+    pub fn drain(&mut self) -> Vec<Order<Q>> {
+        let mut orders: Vec<Order<Q>> = self.orders.drain().map(|(_, order)| order).collect();
+        orders.sort_by_key(|order| order.order_id);
+        orders
+    }
+
+    /// Removes the orders of the side that are triggered at the price, in order of their order
+    /// ids.
+    pub fn take_triggered(&mut self, side: Side, price_tick: i64) -> Vec<Order<Q>> {
+        let mut order_ids: Vec<i64> = self
+            .orders
+            .values()
+            .filter(|order| order.side == side && is_triggered_at(order, price_tick))
+            .map(|order| order.order_id)
+            .collect();
+        order_ids.sort_unstable();
+        order_ids
+            .into_iter()
+            .filter_map(|order_id| self.orders.remove(&order_id))
+            .collect()
+    }
+}
+
+struct Shadow<Q: Clone> {
+    order: Order<Q>,
+    // The timestamp at which the shadow would have been filled.
+    filled_at: Option<i64>,
+}
+
+/// Runs the shadows of the orders whose queue positions have been reset by modifications without
+/// changing their prices. A shadow keeps the queue position the order had before the reset and is
+/// advanced by the same trades and depth changes as the order, so that the fills of both can be
+/// compared once the order leaves the book. See [`ModifyPriorityStats`].
+#[derive(Default)]
+pub(super) struct ModifyShadows<Q: Clone> {
+    // key: order_id, value: the shadow of the order
+    shadows: HashMap<i64, Shadow<Q>>,
+    stats: ModifyPriorityStats,
+}
+
+impl<Q: Clone> ModifyShadows<Q> {
+    pub fn stats(&self) -> ModifyPriorityStats {
+        self.stats
+    }
+
+    /// Applies the modification of the order, which still has the queue position it had before
+    /// the modification. `reset` tells whether the modification resets the queue position.
+    pub fn on_modify(&mut self, order: &Order<Q>, prev_price_tick: i64, reset: bool) {
+        if order.price_tick != prev_price_tick {
+            // Both policies reset the queue position.
+            self.on_leave(order.order_id);
+            return;
+        }
+        if reset {
+            self.stats.resets += 1;
+        }
+        if let Some(shadow) = self.shadows.get_mut(&order.order_id) {
+            shadow.order.qty = order.qty;
+            shadow.order.leaves_qty = order.leaves_qty;
+        } else if reset {
+            self.shadows.insert(
+                order.order_id,
+                Shadow {
+                    order: order.clone(),
+                    filled_at: None,
+                },
+            );
+        }
+    }
+
+    /// Advances the shadows of the orders of the side by the trade at the price. This should be
+    /// done before the orders themselves are advanced, so that a shadow filled by the same trade
+    /// as its order is seen filled at the same time.
+    pub fn on_trade<MD, QM>(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        qty: f32,
+        timestamp: i64,
+        queue_model: &QM,
+        depth: &MD,
+    ) where
+        MD: MarketDepth,
+        QM: QueueModel<Q, MD>,
+    {
+        for shadow in self.shadows.values_mut() {
+            let order = &mut shadow.order;
+            if shadow.filled_at.is_some() || order.side != side {
+                continue;
+            }
+            let crossed = match side {
+                Side::Buy => order.price_tick > price_tick,
+                _ => order.price_tick < price_tick,
+            };
+            if crossed {
+                shadow.filled_at = Some(timestamp);
+            } else if order.price_tick == price_tick {
+                queue_model.trade(order, qty, depth);
+                if queue_model.is_filled(order, depth) {
+                    shadow.filled_at = Some(timestamp);
+                }
+            }
+        }
+    }
+
+    /// Advances the shadows of the orders of the side by the quantity change at the price.
+    pub fn on_qty_chg<MD, QM>(
+        &mut self,
+        side: Side,
+        price_tick: i64,
+        prev_qty: f32,
+        new_qty: f32,
+        queue_model: &QM,
+        depth: &MD,
+    ) where
+        MD: MarketDepth,
+        QM: QueueModel<Q, MD>,
+    {
+        for shadow in self.shadows.values_mut() {
+            let order = &mut shadow.order;
+            if shadow.filled_at.is_none() && order.side == side && order.price_tick == price_tick {
+                queue_model.depth(order, prev_qty, new_qty, depth);
+            }
+        }
+    }
+
+    /// Fills the shadows of the orders of the side whose prices have been crossed by the
+    /// opposite best price.
+    pub fn on_cross(&mut self, side: Side, best_tick: i64, timestamp: i64) {
+        for shadow in self.shadows.values_mut() {
+            let order = &shadow.order;
+            if shadow.filled_at.is_some() || order.side != side {
+                continue;
+            }
+            let crossed = match side {
+                Side::Buy => order.price_tick >= best_tick,
+                _ => order.price_tick <= best_tick,
+            };
+            if crossed {
+                shadow.filled_at = Some(timestamp);
+            }
+        }
+    }
+
+    /// Resets the queue positions of the shadows, as the orders are requeued.
+    pub fn requeue<MD, QM>(&mut self, queue_model: &QM, depth: &MD)
+    where
+        MD: MarketDepth,
+        QM: QueueModel<Q, MD>,
+    {
+        for shadow in self.shadows.values_mut() {
+            if shadow.filled_at.is_none() {
+                queue_model.new_order(&mut shadow.order, depth);
+            }
+        }
+    }
+
+    /// Compares the fill of the order with its shadow.
+    pub fn on_fill(&mut self, order_id: i64, qty: f32, timestamp: i64) {
+        let Some(shadow) = self.shadows.remove(&order_id) else {
+            return;
+        };
+        match shadow.filled_at {
+            Some(filled_at) => {
+                self.stats.filled += 1;
+                self.stats.fill_delay += timestamp - filled_at;
+            }
+            None => {
+                self.stats.gained += 1;
+                self.stats.gained_qty += qty as f64;
+            }
+        }
+    }
+
+    /// Compares the order leaving the book unfilled with its shadow.
+    pub fn on_leave(&mut self, order_id: i64) {
+        let Some(shadow) = self.shadows.remove(&order_id) else {
+            return;
+        };
+        if shadow.filled_at.is_some() {
+            self.stats.lost += 1;
+            self.stats.lost_qty += shadow.order.leaves_qty as f64;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shadows.is_empty()
+    }
+}
+
+/// Returns `true` if the trigger order is triggered at the price.
+pub(super) fn is_triggered_at<Q: Clone>(order: &Order<Q>, price_tick: i64) -> bool {
+    match (order.order_type, order.side) {
+        (OrdType::StopMarket | OrdType::StopLimit, Side::Buy)
+        | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Sell) => {
+            price_tick >= order.trigger_price_tick
+        }
+        (OrdType::StopMarket | OrdType::StopLimit, Side::Sell)
+        | (OrdType::TakeProfitMarket | OrdType::TakeProfitLimit, Side::Buy) => {
+            price_tick <= order.trigger_price_tick
+        }
+        _ => false,
+    }
+}
+
+/// Returns `true` if the row clears the depth or is a depth snapshot.
+pub(super) fn is_snapshot_row(ev: i64) -> bool {
+    matches!(ev & 0xff, DEPTH_CLEAR_EVENT | DEPTH_SNAPSHOT_EVENT)
+}
+
+/// Aggregates the trade prints of the same aggressor side, starting from the print at `row_num`,
+/// whose exchange timestamps are within `window` of the first print, so that one logical trade
+/// reported as many prints is fed to the queue model once per price. The other events in between,
+/// such as depth updates, are skipped over, but a trade print of the other side ends the
+/// aggregation, as does the end of the data chunk.
+///
+/// Returns the aggregated trades as `(price_tick, qty)` in order of their first prints, and the
+/// row numbers of the aggregated prints following the first, which should be skipped when they
+/// are reached.
+pub(super) fn aggregate_trades(
+    data: &Data<Event>,
+    row_num: usize,
+    trade_ev: i64,
+    tick_size: f32,
+    window: i64,
+) -> (Vec<(i64, f32)>, Vec<usize>) {
+    let first_ts = data[row_num].exch_ts;
+    let mut trades: Vec<(i64, f32)> = Vec::new();
+    let mut aggregated = Vec::new();
+    for rn in row_num..data.len() {
+        let row = &data[rn];
+        if row.ev & EXCH_EVENT != EXCH_EVENT {
+            continue;
+        }
+        if row.exch_ts - first_ts > window {
+            break;
+        }
+        if row.ev & TRADE_EVENT != TRADE_EVENT {
+            continue;
+        }
+        if row.ev & trade_ev != trade_ev {
+            break;
+        }
+        let price_tick = i64::from_price(row.px, tick_size);
+        match trades.iter_mut().find(|(t, _)| *t == price_tick) {
+            Some((_, qty)) => *qty += row.qty,
+            None => trades.push((price_tick, row.qty)),
+        }
+        if rn != row_num {
+            aggregated.push(rn);
+        }
+    }
+    (trades, aggregated)
 }
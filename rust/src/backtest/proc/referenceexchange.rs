@@ -0,0 +1,31 @@
+use crate::backtest::{proc::Processor, Error};
+
+/// The exchange processor of a reference-only asset, which doesn't match orders. It reads no
+/// data and never receives an order, as the local of the asset rejects the submissions with
+/// [`Error::ReferenceOnly`], so the asset's data is processed only by the local, which is all
+/// that the strategy needs from an asset it only reads signals from. See
+/// [`ExchangeKind::ReferenceOnly`](crate::backtest::ExchangeKind::ReferenceOnly).
+#[derive(Default)]
+pub struct ReferenceExchange;
+
+impl Processor for ReferenceExchange {
+    fn initialize_data(&mut self) -> Result<i64, Error> {
+        Err(Error::EndOfData)
+    }
+
+    fn process_data(&mut self) -> Result<(i64, i64), Error> {
+        Err(Error::EndOfData)
+    }
+
+    fn process_recv_order(&mut self, _timestamp: i64, _wait_resp: i64) -> Result<i64, Error> {
+        Ok(i64::MAX)
+    }
+
+    fn frontmost_recv_order_timestamp(&self) -> i64 {
+        i64::MAX
+    }
+
+    fn frontmost_send_order_timestamp(&self) -> i64 {
+        i64::MAX
+    }
+}
@@ -0,0 +1,210 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    backtest::{
+        order::OrderBus,
+        proc::Processor,
+        profile::ModifyPriorityStats,
+        reader::{WAIT_ORDER_RESPONSE_ANY, WAIT_ORDER_RESPONSE_NONE},
+        Error,
+    },
+    ty::Status,
+};
+
+struct Slot<Q>
+where
+    Q: Clone,
+{
+    // The orders sent by the slot's local.
+    orders_from: OrderBus<Q>,
+    // The responses to the slot's local.
+    orders_to: OrderBus<Q>,
+}
+
+/// An exchange shared by the strategy instances trading the same asset, which keeps the order ids
+/// and the one-cancels-other groups of each instance in their own namespaces by mapping them to
+/// unique ids in the exchange.
+struct SharedExchange<Q>
+where
+    Q: Clone,
+{
+    exch: Box<dyn Processor>,
+    // The buses of the underlying exchange.
+    exch_orders_from: OrderBus<Q>,
+    exch_orders_to: OrderBus<Q>,
+    slots: Vec<Slot<Q>>,
+    // key: (slot, order_id), value: the order id in the exchange
+    order_ids: HashMap<(usize, i64), i64>,
+    // key: the order id in the exchange, value: (slot, order_id)
+    slot_order_ids: HashMap<i64, (usize, i64)>,
+    // key: (slot, group), value: the group in the exchange
+    groups: HashMap<(usize, i64), i64>,
+    // key: the group in the exchange, value: the group of the slot
+    slot_groups: HashMap<i64, i64>,
+    next_id: i64,
+}
+
+impl<Q> SharedExchange<Q>
+where
+    Q: Clone,
+{
+    /// Forwards the orders of the slot received at `timestamp` to the underlying exchange.
+    fn forward(&mut self, slot: usize, timestamp: i64) {
+        let orders_from = &mut self.slots[slot].orders_from;
+        while orders_from.get_head_timestamp() == Some(timestamp) {
+            let mut order = orders_from.remove(0);
+            let key = (slot, order.order_id);
+            let order_id = match self.order_ids.get(&key) {
+                // A new order may reuse the id of an inactive order.
+                Some(order_id) if order.req != Status::New => *order_id,
+                _ => {
+                    self.next_id += 1;
+                    self.order_ids.insert(key, self.next_id);
+                    self.slot_order_ids.insert(self.next_id, key);
+                    self.next_id
+                }
+            };
+            order.order_id = order_id;
+            if let Some(group) = order.oco_group {
+                let next_id = &mut self.next_id;
+                let exch_group = *self.groups.entry((slot, group)).or_insert_with(|| {
+                    *next_id += 1;
+                    *next_id
+                });
+                self.slot_groups.insert(exch_group, group);
+                order.oco_group = Some(exch_group);
+            }
+            self.exch_orders_from.append(order, timestamp);
+        }
+    }
+
+    /// Routes the responses of the underlying exchange to the slots they belong to.
+    fn route(&mut self) {
+        while self.exch_orders_to.len() > 0 {
+            let timestamp = self.exch_orders_to.get_head_timestamp().unwrap();
+            let mut order = self.exch_orders_to.remove(0);
+            let (slot, order_id) = self.slot_order_ids[&order.order_id];
+            order.order_id = order_id;
+            order.oco_group = order
+                .oco_group
+                .map(|exch_group| self.slot_groups[&exch_group]);
+            self.slots[slot].orders_to.append(order, timestamp);
+        }
+    }
+
+    /// Returns the order id in the exchange to wait for the response to.
+    fn wait_resp(&self, slot: usize, wait_resp: i64) -> i64 {
+        match self.order_ids.get(&(slot, wait_resp)) {
+            Some(order_id) => *order_id,
+            None if wait_resp == WAIT_ORDER_RESPONSE_ANY => WAIT_ORDER_RESPONSE_ANY,
+            None => WAIT_ORDER_RESPONSE_NONE,
+        }
+    }
+}
+
+/// A handle to an exchange shared by several strategy instances trading the same asset, each of
+/// which has its own local. The first handle drives the market data of the exchange, and each
+/// handle passes the orders of its instance to the exchange and the responses back. See
+/// [`BtAssetBuilder::build_shared`](crate::backtest::BtAssetBuilder::build_shared).
+pub struct SharedExchangeHandle<Q>
+where
+    Q: Clone,
+{
+    exch: Rc<RefCell<SharedExchange<Q>>>,
+    slot: usize,
+}
+
+impl<Q> SharedExchangeHandle<Q>
+where
+    Q: Clone,
+{
+    /// Shares the exchange among the locals connected by the given buses, which are the buses of
+    /// the locals' orders and of the responses to them, returning a handle per local. The
+    /// exchange must be connected to `exch_orders_from` and `exch_orders_to`.
+    pub fn new(
+        exch: Box<dyn Processor>,
+        exch_orders_from: OrderBus<Q>,
+        exch_orders_to: OrderBus<Q>,
+        buses: Vec<(OrderBus<Q>, OrderBus<Q>)>,
+    ) -> Vec<Self> {
+        let num_slots = buses.len();
+        let exch = Rc::new(RefCell::new(SharedExchange {
+            exch,
+            exch_orders_from,
+            exch_orders_to,
+            slots: buses
+                .into_iter()
+                .map(|(orders_from, orders_to)| Slot {
+                    orders_from,
+                    orders_to,
+                })
+                .collect(),
+            order_ids: HashMap::new(),
+            slot_order_ids: HashMap::new(),
+            groups: HashMap::new(),
+            slot_groups: HashMap::new(),
+            next_id: 0,
+        }));
+        (0..num_slots)
+            .map(|slot| Self {
+                exch: exch.clone(),
+                slot,
+            })
+            .collect()
+    }
+}
+
+impl<Q> Processor for SharedExchangeHandle<Q>
+where
+    Q: Clone,
+{
+    fn initialize_data(&mut self) -> Result<i64, Error> {
+        if self.slot == 0 {
+            self.exch.borrow_mut().exch.initialize_data()
+        } else {
+            Err(Error::EndOfData)
+        }
+    }
+
+    fn process_data(&mut self) -> Result<(i64, i64), Error> {
+        if self.slot != 0 {
+            return Err(Error::EndOfData);
+        }
+        let mut exch = self.exch.borrow_mut();
+        let result = exch.exch.process_data();
+        exch.route();
+        result
+    }
+
+    fn process_recv_order(&mut self, timestamp: i64, wait_resp: i64) -> Result<i64, Error> {
+        let mut exch = self.exch.borrow_mut();
+        exch.forward(self.slot, timestamp);
+        let wait_resp = exch.wait_resp(self.slot, wait_resp);
+        let result = exch.exch.process_recv_order(timestamp, wait_resp);
+        exch.route();
+        result
+    }
+
+    fn frontmost_recv_order_timestamp(&self) -> i64 {
+        self.exch.borrow().slots[self.slot]
+            .orders_from
+            .frontmost_timestamp()
+    }
+
+    fn frontmost_send_order_timestamp(&self) -> i64 {
+        self.exch.borrow().slots[self.slot]
+            .orders_to
+            .frontmost_timestamp()
+    }
+
+    fn expire_all_orders(&mut self, timestamp: i64) -> Result<(), Error> {
+        let mut exch = self.exch.borrow_mut();
+        let result = exch.exch.expire_all_orders(timestamp);
+        exch.route();
+        result
+    }
+
+    fn modify_priority_stats(&self) -> ModifyPriorityStats {
+        self.exch.borrow().exch.modify_priority_stats()
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::VecDeque;
+
+use crate::{
+    backtest::models::{ConstantLatency, FlatFee},
+    ty::Status,
+};
+
+/// What the exchange does with a post-only (GTX) order that would take liquidity on arrival.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GtxPolicy {
+    /// Expires the order, as Binance does.
+    #[default]
+    Expire,
+    /// Cancels the order, as Bybit does.
+    Cancel,
+    /// Rejects the order.
+    Reject,
+}
+
+impl GtxPolicy {
+    /// Returns the status of the post-only order that would take liquidity.
+    pub fn status(&self) -> Status {
+        match self {
+            GtxPolicy::Expire => Status::Expired,
+            GtxPolicy::Cancel => Status::Canceled,
+            GtxPolicy::Reject => Status::Rejected,
+        }
+    }
+}
+
+/// Whether a modified order keeps its queue position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ModifyPolicy {
+    /// The modified order always goes to the back of the queue.
+    #[default]
+    ResetPriority,
+    /// The modified order keeps its queue position if only its quantity is decreased, and goes
+    /// to the back of the queue otherwise.
+    KeepPriorityOnQtyDecrease,
+}
+
+impl ModifyPolicy {
+    /// Returns `true` if the queue position is reset by the modification.
+    pub fn resets_priority(
+        &self,
+        prev_price_tick: i64,
+        prev_qty: f32,
+        price_tick: i64,
+        qty: f32,
+    ) -> bool {
+        match self {
+            ModifyPolicy::ResetPriority => true,
+            ModifyPolicy::KeepPriorityOnQtyDecrease => {
+                prev_price_tick != price_tick || qty > prev_qty
+            }
+        }
+    }
+}
+
+/// The fills gained and lost by the queue position resets of the [`ModifyPolicy`], compared to a
+/// hypothetical policy under which the modified orders keep their queue positions as long as
+/// their prices are unchanged. It tells whether amending the orders or canceling and resubmitting
+/// them serves the strategy better on the venue.
+///
+/// Once a modification resets the queue position of an order without changing its price, the
+/// exchange runs a shadow of the order that keeps the previous queue position, through the same
+/// trades and depth changes as the order itself, until the order leaves the book. A modification
+/// that changes the price resets the queue position under both policies, so it ends the
+/// comparison of the order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModifyPriorityStats {
+    /// The number of the modifications that have reset the queue positions of the orders without
+    /// changing their prices.
+    pub resets: u64,
+    /// The number of the orders filled under both policies.
+    pub filled: u64,
+    /// The sum of how much later, in nanoseconds, the orders filled under both policies have been
+    /// filled than they would have been with the kept queue positions.
+    pub fill_delay: i64,
+    /// The number of the orders filled, which wouldn't have been filled yet with the kept queue
+    /// positions.
+    pub gained: u64,
+    /// The quantity of the gained fills.
+    pub gained_qty: f64,
+    /// The number of the orders that have left the book unfilled, such as by cancellation, which
+    /// would have been filled with the kept queue positions.
+    pub lost: u64,
+    /// The quantity of the lost fills.
+    pub lost_qty: f64,
+}
+
+impl ModifyPriorityStats {
+    /// Returns the net number of the fills gained by the queue position resets, which is negative
+    /// if more fills have been lost than gained.
+    pub fn net_fills(&self) -> i64 {
+        self.gained as i64 - self.lost as i64
+    }
+}
+
+/// A limit of the number of order submissions, including modifications, within a sliding window
+/// in nanoseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OrderRateLimit {
+    pub max: u32,
+    pub window: i64,
+}
+
+/// Rejects the order submissions beyond the [`OrderRateLimit`]s at the exchange, as a venue
+/// rejects the requests beyond its rate limits.
+#[derive(Clone, Debug, Default)]
+pub struct OrderRateLimiter {
+    limits: Vec<OrderRateLimit>,
+    // The timestamps of the accepted submissions within the largest window, in ascending order.
+    sent: VecDeque<i64>,
+}
+
+impl OrderRateLimiter {
+    pub fn new(limits: Vec<OrderRateLimit>) -> Self {
+        Self {
+            limits,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Counts the submission at `timestamp` if every limit allows it, returning whether it's
+    /// accepted.
+    pub fn acquire(&mut self, timestamp: i64) -> bool {
+        let Some(max_window) = self.limits.iter().map(|limit| limit.window).max() else {
+            return true;
+        };
+        while self
+            .sent
+            .front()
+            .is_some_and(|ts| *ts <= timestamp - max_window)
+        {
+            self.sent.pop_front();
+        }
+        let exceeded = self.limits.iter().any(|limit| {
+            let num_sent = self
+                .sent
+                .iter()
+                .rev()
+                .take_while(|ts| **ts > timestamp - limit.window)
+                .count();
+            num_sent >= limit.max as usize
+        });
+        if exceeded {
+            return false;
+        }
+        self.sent.push_back(timestamp);
+        true
+    }
+}
+
+/// The names of the built-in venue profiles.
+pub const VENUE_PROFILES: [&str; 2] = ["binancefutures-2024", "bybit-linear"];
+
+/// A named bundle of the venue's trading rules and typical latencies, which
+/// [`BtAssetBuilder::venue_profile`](crate::backtest::BtAssetBuilder::venue_profile) applies so
+/// that a backtest starts from realistic settings rather than the raw defaults, which charge no
+/// fees and impose no limits.
+///
+/// The built-in profiles, listed in [`VENUE_PROFILES`], carry the base-tier fees and the default
+/// limits published by the venues as of 2024, and the latencies of a bot hosted in the same cloud
+/// region as the venue. They are starting points, to be replaced by your account's fee tier and
+/// your measured latencies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VenueProfile {
+    pub name: String,
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+    /// The order entry latency in nanoseconds.
+    pub entry_latency: i64,
+    /// The order response latency in nanoseconds.
+    pub response_latency: i64,
+    pub gtx_policy: GtxPolicy,
+    pub modify_policy: ModifyPolicy,
+    pub order_rate_limits: Vec<OrderRateLimit>,
+    /// The cap of the open orders per symbol.
+    pub max_open_orders: Option<usize>,
+}
+
+impl VenueProfile {
+    /// Returns the built-in profile of the name.
+    ///
+    /// * `binancefutures-2024`: Binance USDⓈ-M Futures, with the maker and taker fees of 0.02%
+    ///   and 0.05%, 10 ms latencies, post-only orders that would take are expired, modified
+    ///   orders lose their queue positions, the order rate limits of 300 per 10 seconds and 1200
+    ///   per minute, and up to 200 open orders.
+    /// * `bybit-linear`: Bybit USDT perpetuals, with the maker and taker fees of 0.02% and
+    ///   0.055%, 10 ms latencies, post-only orders that would take are canceled, modified orders
+    ///   keep their queue positions if only their quantities are decreased, the order rate limit
+    ///   of 10 per second, and up to 500 open orders.
+    pub fn get(name: &str) -> Option<Self> {
+        match name {
+            "binancefutures-2024" => Some(Self {
+                name: name.to_string(),
+                maker_fee: 0.0002,
+                taker_fee: 0.0005,
+                entry_latency: 10_000_000,
+                response_latency: 10_000_000,
+                gtx_policy: GtxPolicy::Expire,
+                modify_policy: ModifyPolicy::ResetPriority,
+                order_rate_limits: vec![
+                    OrderRateLimit {
+                        max: 300,
+                        window: 10_000_000_000,
+                    },
+                    OrderRateLimit {
+                        max: 1200,
+                        window: 60_000_000_000,
+                    },
+                ],
+                max_open_orders: Some(200),
+            }),
+            "bybit-linear" => Some(Self {
+                name: name.to_string(),
+                maker_fee: 0.0002,
+                taker_fee: 0.00055,
+                entry_latency: 10_000_000,
+                response_latency: 10_000_000,
+                gtx_policy: GtxPolicy::Cancel,
+                modify_policy: ModifyPolicy::KeepPriorityOnQtyDecrease,
+                order_rate_limits: vec![OrderRateLimit {
+                    max: 10,
+                    window: 1_000_000_000,
+                }],
+                max_open_orders: Some(500),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the fee model of the profile's fees.
+    pub fn fee_model(&self) -> FlatFee {
+        FlatFee::new(self.maker_fee, self.taker_fee)
+    }
+
+    /// Returns the latency model of the profile's latencies. Since the latency model is a type
+    /// parameter of the builder, it isn't applied by
+    /// [`BtAssetBuilder::venue_profile`](crate::backtest::BtAssetBuilder::venue_profile) and
+    /// should be set by [`BtAssetBuilder::latency_model`](crate::backtest::BtAssetBuilder::latency_model)
+    /// if the backtest uses [`ConstantLatency`].
+    pub fn latency(&self) -> ConstantLatency {
+        ConstantLatency::new(self.entry_latency, self.response_latency)
+    }
+}
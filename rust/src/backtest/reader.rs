@@ -1,21 +1,44 @@
 use std::{
     cell::{Cell, RefCell},
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{Error as IoError, ErrorKind, Read},
+    io::{BufRead, BufReader, BufWriter, Error as IoError, ErrorKind, Read, Write},
     marker::PhantomData,
-    mem::{forget, size_of},
-    ops::Index,
+    mem::{forget, size_of, size_of_val},
+    ops::{Deref, Index},
     rc::Rc,
 };
 
 use crate::{
-    backtest::Error,
-    ty::{BUY, DEPTH_CLEAR_EVENT, DEPTH_EVENT, DEPTH_SNAPSHOT_EVENT, SELL, TRADE_EVENT},
+    backtest::{
+        delta::{read_delta, read_delta_header},
+        header::{DataHeader, NPZ_HEADER_ENTRY},
+        prefetch::{Prefetcher, RawData},
+        Error,
+    },
+    depth::ApplySnapshot,
+    schedule::parse_time,
+    ty::{
+        Event,
+        ExternalEvent,
+        ADD_ORDER_EVENT,
+        BUY,
+        CANCEL_ORDER_EVENT,
+        DEPTH_CLEAR_EVENT,
+        DEPTH_EVENT,
+        DEPTH_SNAPSHOT_EVENT,
+        FILL_EVENT,
+        MODIFY_ORDER_EVENT,
+        SELL,
+        TRADE_EVENT,
+    },
 };
 
 pub const EXCH_EVENT: i64 = 1 << 31;
 pub const LOCAL_EVENT: i64 = 1 << 30;
+/// Flags a row that is not recorded but inferred, such as by
+/// [`rebuild_depth`](crate::backtest::rebuild::rebuild_depth).
+pub const SYNTHETIC_EVENT: i64 = 1 << 27;
 
 pub const LOCAL_BID_DEPTH_EVENT: i64 = DEPTH_EVENT | BUY | LOCAL_EVENT;
 pub const LOCAL_ASK_DEPTH_EVENT: i64 = DEPTH_EVENT | SELL | LOCAL_EVENT;
@@ -39,14 +62,57 @@ pub const EXCH_TRADE_EVENT: i64 = TRADE_EVENT | EXCH_EVENT;
 pub const EXCH_BUY_TRADE_EVENT: i64 = TRADE_EVENT | BUY | EXCH_EVENT;
 pub const EXCH_SELL_TRADE_EVENT: i64 = TRADE_EVENT | SELL | EXCH_EVENT;
 
+// The market-by-order events, whose event types share bits, so that they must be told apart by
+// the whole event type rather than by the bits.
+pub const EXCH_BID_ADD_ORDER_EVENT: i64 = ADD_ORDER_EVENT | BUY | EXCH_EVENT;
+pub const EXCH_ASK_ADD_ORDER_EVENT: i64 = ADD_ORDER_EVENT | SELL | EXCH_EVENT;
+pub const EXCH_BID_CANCEL_ORDER_EVENT: i64 = CANCEL_ORDER_EVENT | BUY | EXCH_EVENT;
+pub const EXCH_ASK_CANCEL_ORDER_EVENT: i64 = CANCEL_ORDER_EVENT | SELL | EXCH_EVENT;
+pub const EXCH_BID_MODIFY_ORDER_EVENT: i64 = MODIFY_ORDER_EVENT | BUY | EXCH_EVENT;
+pub const EXCH_ASK_MODIFY_ORDER_EVENT: i64 = MODIFY_ORDER_EVENT | SELL | EXCH_EVENT;
+pub const EXCH_BID_FILL_EVENT: i64 = FILL_EVENT | BUY | EXCH_EVENT;
+pub const EXCH_ASK_FILL_EVENT: i64 = FILL_EVENT | SELL | EXCH_EVENT;
+
+/// Returns the event type of the event flags, such as [`DEPTH_EVENT`] or [`ADD_ORDER_EVENT`],
+/// without the side and the processor flags.
+pub fn event_type(ev: i64) -> i64 {
+    ev & 0xff
+}
+
 pub const WAIT_ORDER_RESPONSE_NONE: i64 = -1;
 pub const WAIT_ORDER_RESPONSE_ANY: i64 = -2;
 
 pub const UNTIL_END_OF_DATA: i64 = i64::MAX;
 
+/// The buffer of [`Data`], either in heap memory or mapped from a file.
+#[derive(Debug)]
+enum Buffer {
+    Heap(Box<[u8]>),
+    #[cfg(feature = "mmap")]
+    Mmap(memmap2::Mmap),
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Buffer::Heap(Default::default())
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Buffer::Heap(buf) => buf,
+            #[cfg(feature = "mmap")]
+            Buffer::Mmap(mmap) => mmap,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Data<D> {
-    buf: Rc<Box<[u8]>>,
+    buf: Rc<Buffer>,
     header_len: usize,
     _d_marker: PhantomData<D>,
 }
@@ -60,6 +126,32 @@ where
         (self.buf.len() - self.header_len) / size
     }
 
+    pub(crate) fn from_buf(buf: Box<[u8]>, header_len: usize) -> Self {
+        Self {
+            buf: Rc::new(Buffer::Heap(buf)),
+            header_len,
+            _d_marker: Default::default(),
+        }
+    }
+
+    /// Constructs the data from the rows in memory.
+    pub fn from_rows(rows: &[D]) -> Self {
+        let size = size_of_val(rows);
+        let mut buf = aligned_vec(size);
+        unsafe {
+            std::ptr::copy_nonoverlapping(rows.as_ptr() as *const u8, buf.as_mut_ptr(), size);
+        }
+        Self::from_buf(buf, 0)
+    }
+
+    /// Returns the data without the rows before `row`, sharing the buffer.
+    fn skip(self, row: usize) -> Self {
+        Self {
+            header_len: self.header_len + row.min(self.len()) * size_of::<D>(),
+            ..self
+        }
+    }
+
     pub fn empty() -> Self {
         Self {
             buf: Default::default(),
@@ -67,6 +159,58 @@ where
             _d_marker: Default::default(),
         }
     }
+
+    /// Returns the raw buffer and the header length of the data that has just been read.
+    fn into_raw(self) -> RawData {
+        let copy = |buf: &[u8]| {
+            let mut copy = aligned_vec(buf.len());
+            copy.copy_from_slice(buf);
+            copy
+        };
+        let buf = match Rc::try_unwrap(self.buf) {
+            Ok(Buffer::Heap(buf)) => buf,
+            #[cfg(feature = "mmap")]
+            Ok(Buffer::Mmap(mmap)) => copy(&mmap),
+            Err(buf) => copy(&buf),
+        };
+        (buf, self.header_len)
+    }
+
+    /// Returns the bytes of the rows.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.buf[self.header_len..]
+    }
+
+    /// Returns the rows as a slice, for random access.
+    pub fn as_slice(&self) -> &[D] {
+        let len = self.len();
+        if len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.buf[self.header_len..].as_ptr() as *const D, len) }
+    }
+
+    /// Returns the row number of the first row whose timestamp, taken by `key`, is at or after
+    /// `timestamp`, or the number of rows if there is no such row, by binary search. The rows
+    /// must be ordered by the key, such as [`Event::local_ts`] for the feed data.
+    pub fn first_at<F>(&self, timestamp: i64, key: F) -> usize
+    where
+        F: Fn(&D) -> i64,
+    {
+        self.as_slice().partition_point(|row| key(row) < timestamp)
+    }
+
+    /// Returns the rows whose timestamps, taken by `key`, are in the range from `start`,
+    /// inclusive, to `end`, exclusive. The rows must be ordered by the key. See
+    /// [`Data::first_at`].
+    pub fn time_range<F>(&self, start: i64, end: i64, key: F) -> &[D]
+    where
+        F: Fn(&D) -> i64,
+    {
+        let from = self.first_at(start, &key);
+        let to = self.first_at(end, &key).max(from);
+        &self.as_slice()[from..to]
+    }
 }
 
 impl<D> Index<usize> for Data<D>
@@ -138,6 +282,14 @@ where
     file_list: Vec<String>,
     cache: Cache<D>,
     data_num: usize,
+    prefetch_depth: usize,
+    // Shared by the clones of the reader, which read the same files.
+    prefetcher: Rc<RefCell<Option<Prefetcher>>>,
+    // The files that are memory-mapped instead of read.
+    mmap_files: HashSet<String>,
+    // The timestamp at or before which the rows following the initial snapshot are skipped, and
+    // the key of the timestamp.
+    start: Option<(i64, fn(&D) -> i64)>,
 }
 
 impl<D> Reader<D>
@@ -149,33 +301,107 @@ where
             file_list: Vec::new(),
             cache,
             data_num: 0,
+            prefetch_depth: 0,
+            prefetcher: Default::default(),
+            mmap_files: HashSet::new(),
+            start: None,
         }
     }
 
+    /// Loads the files up to `depth` files ahead of the file being read in a background thread,
+    /// including downloading the files referenced by URLs and decompressing them, so that the
+    /// backtest doesn't wait for I/O between the files. The prefetch starts when the first file is
+    /// read. A `depth` of zero disables it, which is the default.
+    pub fn prefetch(&mut self, depth: usize) {
+        self.prefetch_depth = depth;
+    }
+
     pub fn add_file(&mut self, filepath: String) {
         self.file_list.push(filepath);
     }
 
+    /// Adds a file that is memory-mapped instead of read into memory. See [`mmap_data`].
+    #[cfg(feature = "mmap")]
+    pub fn add_mmap_file(&mut self, filepath: String) {
+        self.mmap_files.insert(filepath.clone());
+        self.file_list.push(filepath);
+    }
+
+    /// Adds the data in memory, which is read in order with the files.
+    pub fn add_data(&mut self, data: Data<D>) {
+        // The key can't be a valid file path.
+        let key = format!("\0data{}", self.file_list.len());
+        self.cache.insert(key.clone(), data);
+        // Holds a reference so that the data, which can't be read again unlike a file, stays in
+        // the cache until all the processors sharing the cache have read it.
+        self.cache.get(&key);
+        self.file_list.push(key);
+    }
+
+    /// Reads the snapshot first, and then the data from the rows after `timestamp`, taken by
+    /// `key`, skipping the rows at or before it, whose changes are already in the snapshot.
+    pub fn initial_snapshot(&mut self, snapshot: Data<D>, timestamp: i64, key: fn(&D) -> i64) {
+        self.cache.insert(SNAPSHOT_KEY.to_string(), snapshot);
+        // Holds a reference like the data in memory.
+        self.cache.get(SNAPSHOT_KEY);
+        self.file_list.insert(0, SNAPSHOT_KEY.to_string());
+        self.start = Some((timestamp, key));
+    }
+
     pub fn release(&mut self, data: Data<D>) {
         self.cache.remove(data);
     }
 
+    /// Validates the [`DataHeader`] of each file that has one against the asset's tick size.
+    pub fn validate(&self, tick_size: f32) -> Result<(), IoError> {
+        for filepath in self.file_list.iter() {
+            // The files referenced by URLs aren't downloaded until they're read.
+            #[cfg(feature = "remote")]
+            if crate::backtest::remote::RemoteFile::is_remote(filepath) {
+                continue;
+            }
+            if let Some(header) = read_data_header(filepath)? {
+                header.validate(tick_size).map_err(|error| {
+                    IoError::new(error.kind(), format!("{}: {}", filepath, error))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn next(&mut self) -> Result<Data<D>, Error> {
+        loop {
+            let data = self.next_data()?;
+            let filepath = &self.file_list[self.data_num - 1];
+            let Some((timestamp, key)) = self.start.filter(|_| filepath != SNAPSHOT_KEY) else {
+                return Ok(data);
+            };
+            // The rows are skipped as the local would have processed them by the timestamp.
+            match data.as_slice().iter().position(|row| key(row) > timestamp) {
+                Some(row) => {
+                    // The following data has no rows to skip.
+                    self.start = None;
+                    return Ok(data.skip(row));
+                }
+                None => self.release(data),
+            }
+        }
+    }
+
+    fn next_data(&mut self) -> Result<Data<D>, Error> {
         if self.data_num < self.file_list.len() {
             let filepath = self.file_list.get(self.data_num).unwrap();
             if !self.cache.contains(filepath) {
-                if filepath.ends_with(".npy") {
-                    let data = read_npy(filepath)?;
-                    self.cache.insert(filepath.to_string(), data);
-                } else if filepath.ends_with(".npz") {
-                    let data = read_npz(filepath)?;
-                    self.cache.insert(filepath.to_string(), data);
-                } else {
-                    return Err(Error::DataError(IoError::new(
-                        ErrorKind::InvalidData,
-                        "unsupported data type",
-                    )));
-                }
+                let data = match self.prefetched(self.data_num) {
+                    Some(result) => {
+                        let (buf, header_len) = result?;
+                        Data::from_buf(buf, header_len)
+                    }
+                    #[cfg(feature = "mmap")]
+                    None if self.mmap_files.contains(filepath) => mmap_data(filepath)?,
+                    None => read_data(filepath)?,
+                };
+                self.cache.insert(filepath.to_string(), data);
             }
             let data = self.cache.get(filepath);
             self.data_num += 1;
@@ -184,12 +410,36 @@ where
             Err(Error::EndOfData)
         }
     }
+
+    fn prefetched(&self, data_num: usize) -> Option<Result<RawData, IoError>> {
+        if self.prefetch_depth == 0 {
+            return None;
+        }
+        let mut prefetcher = self.prefetcher.borrow_mut();
+        let prefetcher = prefetcher.get_or_insert_with(|| {
+            // The mapped files are loaded by the page cache on access instead.
+            let files = self.file_list[data_num..]
+                .iter()
+                .filter(|file| !self.mmap_files.contains(*file))
+                .cloned()
+                .collect();
+            Prefetcher::start(files, self.prefetch_depth, read_raw::<D>)
+        });
+        prefetcher.take(&self.file_list[data_num])
+    }
+}
+
+// The key of the initial snapshot, which can't be a valid file path.
+const SNAPSHOT_KEY: &str = "\0snapshot";
+
+fn read_raw<D: Sized>(filepath: &str) -> Result<RawData, IoError> {
+    read_data::<D>(filepath).map(Data::into_raw)
 }
 
 #[repr(C, align(64))]
 struct Align64([u8; 64]);
 
-fn aligned_vec(size: usize) -> Box<[u8]> {
+pub(crate) fn aligned_vec(size: usize) -> Box<[u8]> {
     let capacity = (size / size_of::<Align64>()) + 1;
     let mut aligned: Vec<Align64> = Vec::with_capacity(capacity);
 
@@ -203,6 +453,98 @@ fn aligned_vec(size: usize) -> Box<[u8]> {
     }
 }
 
+/// Reads a data file in the format given by its extension, `.npy`, `.npz`, or `.hbtd`, or
+/// `.parquet` with the `parquet` feature. With the `remote` feature, a file referenced by a URL is
+/// downloaded into the local cache first. See [`RemoteFile`](crate::backtest::remote::RemoteFile).
+pub fn read_data<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
+    #[cfg(feature = "remote")]
+    if crate::backtest::remote::RemoteFile::is_remote(filepath) {
+        let filepath = crate::backtest::remote::RemoteFile::new(filepath).fetch()?;
+        return read_data(&filepath);
+    }
+    #[cfg(feature = "parquet")]
+    if let Some((filepath, row_group)) = crate::backtest::parquet::split_key(filepath) {
+        return crate::backtest::parquet::read_parquet(filepath, Some(row_group));
+    }
+    #[cfg(feature = "parquet")]
+    if filepath.ends_with(".parquet") {
+        return crate::backtest::parquet::read_parquet(filepath, None);
+    }
+    if filepath.ends_with(".npy") {
+        read_npy(filepath)
+    } else if filepath.ends_with(".npz") {
+        read_npz(filepath)
+    } else if filepath.ends_with(".hbtd") {
+        read_delta(filepath)
+    } else {
+        Err(IoError::new(
+            ErrorKind::InvalidData,
+            "unsupported data type",
+        ))
+    }
+}
+
+/// Writes the event rows to an `.npy` file.
+pub fn write_npy(filepath: &str, rows: &[Event]) -> Result<(), IoError> {
+    let mut header = format!(
+        "{{'descr': [('ev', '<i8'), ('exch_ts', '<i8'), ('local_ts', '<i8'), ('px', '<f4'), \
+         ('qty', '<f4')], 'fortran_order': False, 'shape': ({},), }}",
+        rows.len()
+    );
+    // The total header length is padded to a multiple of 64 bytes and ends with a newline.
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut writer = BufWriter::new(File::create(filepath)?);
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    let bytes =
+        unsafe { std::slice::from_raw_parts(rows.as_ptr() as *const u8, size_of_val(rows)) };
+    writer.write_all(bytes)?;
+    writer.flush()
+}
+
+/// Writes the levels of the depth at `timestamp` to an `.npy` file as depth snapshot rows, from
+/// which a later backtest can start at the timestamp without replaying the data before it. See
+/// [`BtAssetBuilder::initial_snapshot`](crate::backtest::BtAssetBuilder::initial_snapshot).
+pub fn write_depth_snapshot<MD>(filepath: &str, depth: &MD, timestamp: i64) -> Result<(), IoError>
+where
+    MD: ApplySnapshot,
+{
+    write_npy(filepath, &depth.snapshot(timestamp))
+}
+
+/// Reads only the [`DataHeader`] of a data file, if it has one.
+pub fn read_data_header(filepath: &str) -> Result<Option<DataHeader>, IoError> {
+    if filepath.ends_with(".hbtd") {
+        read_delta_header(filepath)
+    } else if filepath.ends_with(".npz") {
+        let mut archive = zip::ZipArchive::new(File::open(filepath)?)?;
+        let mut file = match archive.by_name(NPZ_HEADER_ENTRY) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let mut json = String::new();
+        file.read_to_string(&mut json)?;
+        DataHeader::from_json(&json).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Appends the [`DataHeader`] to an `.npz` archive as [`NPZ_HEADER_ENTRY`], after the array.
+pub fn write_npz_header(filepath: &str, header: &DataHeader) -> Result<(), IoError> {
+    let file = File::options().read(true).write(true).open(filepath)?;
+    let mut archive = zip::ZipWriter::new_append(file)?;
+    archive.start_file(NPZ_HEADER_ENTRY, Default::default())?;
+    archive.write_all(header.to_json().as_bytes())?;
+    archive.finish()?;
+    Ok(())
+}
+
 pub fn read_npy<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
     let mut file = File::open(filepath)?;
 
@@ -218,9 +560,64 @@ pub fn read_npy<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
     let header_len = u16::from_le_bytes(buf[8..10].try_into().unwrap()) as usize;
     // let header = String::from_utf8(buf[10..(10 + header_len)].to_vec()).unwrap().to_string().trim().to_string();
 
+    Ok(Data::from_buf(buf, 10 + header_len))
+}
+
+/// Maps an `.npy` file, or the array of an `.npz` archive stored without compression, into memory
+/// instead of reading it, so that the backtests on the same machine share the file's pages in the
+/// page cache and the rows are loaded only when they're accessed.
+///
+/// The rows must be aligned to `D` in the file, which is the case for the `.npy` files written by
+/// NumPy or by [`write_npy`], but usually not for the arrays in the archives written by
+/// `numpy.savez`, which aren't padded. Such an archive has to be converted to `.npy` first.
+///
+/// The file must not be modified while it's mapped, as the rows are read from the file itself.
+#[cfg(feature = "mmap")]
+pub fn mmap_data<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
+    let file = File::open(filepath)?;
+    let (offset, len) = if filepath.ends_with(".npz") {
+        let mut archive = zip::ZipArchive::new(&file)?;
+        let entry = archive.by_index_raw(0)?;
+        if entry.compression() != zip::CompressionMethod::Stored {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("{filepath}: a compressed array cannot be memory-mapped"),
+            ));
+        }
+        (entry.data_start(), Some(entry.size() as usize))
+    } else if filepath.ends_with(".npy") {
+        (0, None)
+    } else {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "unsupported data type",
+        ));
+    };
+    let mut options = memmap2::MmapOptions::new();
+    options.offset(offset);
+    if let Some(len) = len {
+        options.len(len);
+    }
+    let mmap = unsafe { options.map(&file)? };
+    let header_len = match mmap.get(8..10) {
+        Some(header_len) => 10 + u16::from_le_bytes(header_len.try_into().unwrap()) as usize,
+        None => 0,
+    };
+    if header_len == 0 || header_len > mmap.len() {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("{filepath}: invalid npy header"),
+        ));
+    }
+    if !(mmap.as_ptr() as usize + header_len).is_multiple_of(align_of::<D>()) {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("{filepath}: the rows are not aligned to be memory-mapped, use `.npy` instead"),
+        ));
+    }
     Ok(Data {
-        buf: Rc::new(buf),
-        header_len: 10 + header_len,
+        buf: Rc::new(Buffer::Mmap(mmap)),
+        header_len,
         _d_marker: Default::default(),
     })
 }
@@ -241,9 +638,40 @@ pub fn read_npz<D: Sized>(filepath: &str) -> Result<Data<D>, IoError> {
     let header_len = u16::from_le_bytes(buf[8..10].try_into().unwrap()) as usize;
     // let header = String::from_utf8(buf[10..(10 + header_len)].to_vec()).unwrap().to_string().trim().to_string();
 
-    Ok(Data {
-        buf: Rc::new(buf),
-        header_len: 10 + header_len,
-        _d_marker: Default::default(),
-    })
+    Ok(Data::from_buf(buf, 10 + header_len))
+}
+
+/// Reads the external events, such as an economic calendar, from a file. Each line consists of
+/// comma-separated local timestamp, either in RFC 3339 or in nanoseconds since the Unix epoch,
+/// name, and optional value, which defaults to `NaN`. Empty lines and lines starting with `#` are
+/// ignored. The returned events are sorted by timestamp.
+pub fn read_external_events(filepath: &str) -> Result<Vec<ExternalEvent>, IoError> {
+    let file = File::open(filepath)?;
+    let mut events = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = line.splitn(3, ',');
+        let timestamp = parse_time(tokens.next().unwrap_or(""))?;
+        let name = tokens.next().unwrap_or("").trim().to_string();
+        let value = match tokens.next() {
+            Some(value) => value.trim().parse::<f64>().map_err(|_| {
+                IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid value: {}", value.trim()),
+                )
+            })?,
+            None => f64::NAN,
+        };
+        events.push(ExternalEvent {
+            timestamp,
+            name,
+            value,
+        });
+    }
+    events.sort_by_key(|event| event.timestamp);
+    Ok(events)
 }
@@ -0,0 +1,380 @@
+use std::{collections::BTreeMap, io::Error as IoError};
+
+use crate::{
+    backtest::reader::{
+        event_type,
+        read_data,
+        write_npy,
+        EXCH_EVENT,
+        LOCAL_EVENT,
+        SYNTHETIC_EVENT,
+    },
+    depth::{l3marketdepth::L3MarketDepth, Tick, INVALID_MAX, INVALID_MIN},
+    ty::{
+        Event,
+        L3Event,
+        Side,
+        ADD_ORDER_EVENT,
+        BUY,
+        CANCEL_ORDER_EVENT,
+        DEPTH_CLEAR_EVENT,
+        DEPTH_EVENT,
+        DEPTH_SNAPSHOT_EVENT,
+        FILL_EVENT,
+        MODIFY_ORDER_EVENT,
+        SELL,
+        TRADE_EVENT,
+    },
+};
+
+/// How the level changes between two snapshots are timed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The level changes are emitted at the snapshot's timestamp. Since the changes are known
+    /// only when the snapshot is received, this introduces no look-ahead.
+    #[default]
+    AtSnapshot,
+    /// The level changes are spread evenly from the later of the previous snapshot and the last
+    /// trade to the snapshot, and are flagged as synthetic. This produces a smoother book but the
+    /// changes are revealed earlier than they would have been observed.
+    Linear,
+}
+
+struct Book {
+    tick_size: f64,
+    bids: BTreeMap<i64, f32>,
+    asks: BTreeMap<i64, f32>,
+}
+
+impl Book {
+    fn tick(&self, px: f32) -> i64 {
+        (px as f64 / self.tick_size).round() as i64
+    }
+
+    fn px(&self, tick: i64) -> f32 {
+        (tick as f64 * self.tick_size) as f32
+    }
+
+    fn side(&mut self, side: i64) -> &mut BTreeMap<i64, f32> {
+        if side == BUY {
+            &mut self.bids
+        } else {
+            &mut self.asks
+        }
+    }
+
+    fn update(&mut self, side: i64, px: f32, qty: f32) {
+        let tick = self.tick(px);
+        if qty > 0.0 {
+            self.side(side).insert(tick, qty);
+        } else {
+            self.side(side).remove(&tick);
+        }
+    }
+}
+
+fn ev_type(ev: i64) -> i64 {
+    ev & 0xff
+}
+
+fn ev_side(ev: i64) -> i64 {
+    ev & (BUY | SELL)
+}
+
+/// Reconstructs a depth event stream from periodic depth snapshots and trades, for data in which
+/// the full depth updates weren't recorded.
+///
+/// The consecutive depth snapshot rows with the same exchange timestamp form a snapshot. The
+/// first snapshot is emitted as it is, preceded by depth clear rows, and each following
+/// snapshot is replaced by the depth update rows that turn the rebuilt book into the snapshot,
+/// so that the book matches every snapshot when it is received. Between snapshots, each trade
+/// depletes the traded level by its quantity and removes the levels it traded through. Depth
+/// clear rows are dropped since the rebuilt stream has its own, and all the other rows are
+/// passed through, with depth update rows also applied to the rebuilt book.
+///
+/// The rows whose timing or quantity is inferred, the trade-implied depletions and the
+/// interpolated level changes, are flagged with [`SYNTHETIC_EVENT`].
+pub fn rebuild_depth(rows: &[Event], tick_size: f64, interpolation: Interpolation) -> Vec<Event> {
+    let mut book = Book {
+        tick_size,
+        bids: Default::default(),
+        asks: Default::default(),
+    };
+    let mut out = Vec::with_capacity(rows.len());
+    let mut initialized = false;
+    // The earliest time from which the level changes of the next snapshot can be interpolated.
+    let mut interp_from = None;
+
+    let mut i = 0;
+    while i < rows.len() {
+        let row = &rows[i];
+        match ev_type(row.ev) {
+            DEPTH_SNAPSHOT_EVENT => {
+                let mut j = i;
+                while j < rows.len()
+                    && ev_type(rows[j].ev) == DEPTH_SNAPSHOT_EVENT
+                    && rows[j].exch_ts == row.exch_ts
+                {
+                    j += 1;
+                }
+                let snapshot = &rows[i..j];
+                if initialized {
+                    emit_diff(&mut book, snapshot, interp_from, interpolation, &mut out);
+                } else {
+                    emit_initial(&mut book, snapshot, &mut out);
+                    initialized = true;
+                }
+                interp_from = Some((row.exch_ts, row.local_ts));
+                i = j;
+                continue;
+            }
+            DEPTH_CLEAR_EVENT => {}
+            DEPTH_EVENT => {
+                book.update(ev_side(row.ev), row.px, row.qty);
+                out.push(row.clone());
+            }
+            TRADE_EVENT => {
+                out.push(row.clone());
+                if initialized {
+                    emit_trade_depletion(&mut book, row, &mut out);
+                    interp_from = Some((row.exch_ts, row.local_ts));
+                }
+            }
+            _ => {
+                out.push(row.clone());
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+fn emit_initial(book: &mut Book, snapshot: &[Event], out: &mut Vec<Event>) {
+    let flags = snapshot[0].ev & (EXCH_EVENT | LOCAL_EVENT);
+    for side in [BUY, SELL] {
+        let worst = snapshot
+            .iter()
+            .filter(|row| ev_side(row.ev) == side)
+            .map(|row| row.px)
+            .reduce(|a, b| if side == BUY { a.min(b) } else { a.max(b) });
+        if let Some(worst) = worst {
+            out.push(Event {
+                ev: DEPTH_CLEAR_EVENT | side | flags,
+                exch_ts: snapshot[0].exch_ts,
+                local_ts: snapshot[0].local_ts,
+                px: worst,
+                qty: 0.0,
+            });
+        }
+    }
+    for row in snapshot {
+        book.update(ev_side(row.ev), row.px, row.qty);
+        out.push(row.clone());
+    }
+}
+
+fn emit_diff(
+    book: &mut Book,
+    snapshot: &[Event],
+    interp_from: Option<(i64, i64)>,
+    interpolation: Interpolation,
+    out: &mut Vec<Event>,
+) {
+    let flags = snapshot[0].ev & (EXCH_EVENT | LOCAL_EVENT);
+    let mut changes = Vec::new();
+    for side in [BUY, SELL] {
+        let mut target = BTreeMap::new();
+        for row in snapshot.iter().filter(|row| ev_side(row.ev) == side) {
+            if row.qty > 0.0 {
+                target.insert(book.tick(row.px), row.qty);
+            }
+        }
+        let current = book.side(side).clone();
+        for (tick, _) in current
+            .iter()
+            .filter(|(tick, _)| !target.contains_key(tick))
+        {
+            changes.push((side, *tick, 0.0));
+        }
+        for (tick, qty) in target.iter() {
+            if current.get(tick) != Some(qty) {
+                changes.push((side, *tick, *qty));
+            }
+        }
+        *book.side(side) = target;
+    }
+
+    // Removals go first so that the interpolated book doesn't cross in between.
+    changes.sort_by_key(|(_, _, qty)| *qty > 0.0);
+
+    let (exch_ts, local_ts) = (snapshot[0].exch_ts, snapshot[0].local_ts);
+    let n = changes.len() as i64;
+    for (k, (side, tick, qty)) in changes.into_iter().enumerate() {
+        let k = k as i64;
+        let (ev, exch_ts, local_ts) = match (interpolation, interp_from) {
+            (Interpolation::Linear, Some((from_exch_ts, from_local_ts))) => (
+                DEPTH_EVENT | side | flags | SYNTHETIC_EVENT,
+                from_exch_ts + (exch_ts - from_exch_ts) * (k + 1) / n,
+                from_local_ts + (local_ts - from_local_ts) * (k + 1) / n,
+            ),
+            _ => (DEPTH_EVENT | side | flags, exch_ts, local_ts),
+        };
+        out.push(Event {
+            ev,
+            exch_ts,
+            local_ts,
+            px: book.px(tick),
+            qty,
+        });
+    }
+}
+
+fn emit_trade_depletion(book: &mut Book, trade: &Event, out: &mut Vec<Event>) {
+    let flags = trade.ev & (EXCH_EVENT | LOCAL_EVENT);
+    let trade_tick = book.tick(trade.px);
+    // A buy trade is initiated by a buyer and takes the asks.
+    let (side, levels): (i64, Vec<(i64, f32)>) = if ev_side(trade.ev) == BUY {
+        (
+            SELL,
+            book.asks
+                .range(..=trade_tick)
+                .map(|(tick, qty)| (*tick, *qty))
+                .collect(),
+        )
+    } else if ev_side(trade.ev) == SELL {
+        (
+            BUY,
+            book.bids
+                .range(trade_tick..)
+                .map(|(tick, qty)| (*tick, *qty))
+                .collect(),
+        )
+    } else {
+        return;
+    };
+    for (tick, qty) in levels {
+        let qty = if tick == trade_tick {
+            (qty - trade.qty).max(0.0)
+        } else {
+            0.0
+        };
+        let px = book.px(tick);
+        book.update(side, px, qty);
+        out.push(Event {
+            ev: DEPTH_EVENT | side | flags | SYNTHETIC_EVENT,
+            exch_ts: trade.exch_ts,
+            local_ts: trade.local_ts,
+            px,
+            qty,
+        });
+    }
+}
+
+/// Rebuilds the depth of a data file using [`rebuild_depth`] and writes the result to an `.npy`
+/// file.
+pub fn rebuild_depth_file(
+    src: &str,
+    dst: &str,
+    tick_size: f64,
+    interpolation: Interpolation,
+) -> Result<(), IoError> {
+    let data = read_data::<Event>(src)?;
+    let rows: Vec<Event> = (0..data.len()).map(|i| data[i].clone()).collect();
+    write_npy(dst, &rebuild_depth(&rows, tick_size, interpolation))
+}
+
+/// Derives the market-by-price event stream from market-by-order data, such as for the local
+/// processing the market data alongside [`L3Exchange`](crate::backtest::proc::L3Exchange).
+///
+/// Each message that changes a level is replaced by a depth update row with the level's new
+/// quantity, and a fill is preceded by a trade row whose side is the aggressor's, the opposite of
+/// the executed order's side. A depth clear row clears both sides. The rows keep the processor
+/// flags and the timestamps of the messages.
+pub fn depth_from_l3(rows: &[L3Event], tick_size: f32, lot_size: f32) -> Vec<Event> {
+    let mut book = L3MarketDepth::new(tick_size, lot_size);
+    let mut out = Vec::with_capacity(rows.len());
+    for row in rows {
+        let flags = row.ev & (EXCH_EVENT | LOCAL_EVENT);
+        let side = if row.ev & BUY == BUY {
+            Side::Buy
+        } else {
+            Side::Sell
+        };
+        let level = |book: &L3MarketDepth, side: Side, price_tick: i64| {
+            let (side, levels) = if side == Side::Buy {
+                (BUY, &book.depth().bid_depth)
+            } else {
+                (SELL, &book.depth().ask_depth)
+            };
+            Event {
+                ev: DEPTH_EVENT | side | flags,
+                exch_ts: row.exch_ts,
+                local_ts: row.local_ts,
+                px: price_tick as f32 * tick_size,
+                qty: levels.get(&price_tick).copied().unwrap_or(0.0),
+            }
+        };
+        match event_type(row.ev) {
+            DEPTH_CLEAR_EVENT => {
+                let depth = book.depth();
+                if depth.best_bid_tick != INVALID_MIN {
+                    out.push(Event {
+                        ev: DEPTH_CLEAR_EVENT | BUY | flags,
+                        exch_ts: row.exch_ts,
+                        local_ts: row.local_ts,
+                        px: depth.low_bid_tick as f32 * tick_size,
+                        qty: 0.0,
+                    });
+                }
+                if depth.best_ask_tick != INVALID_MAX {
+                    out.push(Event {
+                        ev: DEPTH_CLEAR_EVENT | SELL | flags,
+                        exch_ts: row.exch_ts,
+                        local_ts: row.local_ts,
+                        px: depth.high_ask_tick as f32 * tick_size,
+                        qty: 0.0,
+                    });
+                }
+                book.clear();
+            }
+            ADD_ORDER_EVENT => {
+                if let Some((price_tick, _, _)) =
+                    book.add_order(row.order_id, side, row.px, row.qty, row.exch_ts)
+                {
+                    out.push(level(&book, side, price_tick));
+                }
+            }
+            CANCEL_ORDER_EVENT => {
+                if let Some(order) = book.delete_order(row.order_id, row.exch_ts) {
+                    out.push(level(&book, order.side, order.price_tick));
+                }
+            }
+            MODIFY_ORDER_EVENT => {
+                if let Some((prev, _, _)) =
+                    book.modify_order(row.order_id, row.px, row.qty, row.exch_ts)
+                {
+                    let price_tick = i64::from_price(row.px, tick_size);
+                    if price_tick != prev.price_tick {
+                        out.push(level(&book, prev.side, prev.price_tick));
+                    }
+                    out.push(level(&book, prev.side, price_tick));
+                }
+            }
+            FILL_EVENT => {
+                if let Some(order) = book.fill_order(row.order_id, row.qty, row.exch_ts) {
+                    let aggressor = if order.side == Side::Buy { SELL } else { BUY };
+                    out.push(Event {
+                        ev: TRADE_EVENT | aggressor | flags,
+                        exch_ts: row.exch_ts,
+                        local_ts: row.local_ts,
+                        px: row.px,
+                        qty: row.qty,
+                    });
+                    out.push(level(&book, order.side, order.price_tick));
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
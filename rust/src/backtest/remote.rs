@@ -0,0 +1,337 @@
+use std::{
+    env,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{BufWriter, Error as IoError, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION},
+    Url,
+};
+use sha2::{Digest, Sha256};
+
+/// A data file in an object store or on a web server, referenced by an `s3://bucket/key`,
+/// `gs://bucket/key`, `https://`, or `http://` URL. The file is downloaded once into the local cache directory and
+/// read from there afterwards.
+///
+/// The S3 requests are signed with the `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, and optional
+/// `AWS_SESSION_TOKEN` environment variables if they are set, and are sent unsigned otherwise.
+/// The region is taken from `AWS_REGION`, defaulting to `us-east-1`, and `AWS_ENDPOINT_URL` can
+/// point to an S3-compatible store, which is then accessed in path style. The Google Cloud Storage
+/// requests carry the OAuth 2.0 access token in `GOOGLE_OAUTH_ACCESS_TOKEN` if it's set.
+#[derive(Clone, Debug)]
+pub struct RemoteFile {
+    url: String,
+    sha256: Option<String>,
+    cache_dir: Option<PathBuf>,
+}
+
+impl RemoteFile {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            sha256: None,
+            cache_dir: None,
+        }
+    }
+
+    /// Sets the expected SHA-256 checksum of the file in hex. Both a downloaded file and a cached
+    /// file are verified against it.
+    pub fn sha256(self, sha256: &str) -> Self {
+        Self {
+            sha256: Some(sha256.to_ascii_lowercase()),
+            ..self
+        }
+    }
+
+    /// Sets the local cache directory. By default, it is `HFTBACKTEST_CACHE_DIR` if set, or the
+    /// `hftbacktest` directory in the temporary directory.
+    pub fn cache_dir(self, cache_dir: &str) -> Self {
+        Self {
+            cache_dir: Some(PathBuf::from(cache_dir)),
+            ..self
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns `true` if the path is a URL that can be fetched as a [`RemoteFile`].
+    pub fn is_remote(path: &str) -> bool {
+        path.starts_with("s3://")
+            || path.starts_with("gs://")
+            || path.starts_with("https://")
+            || path.starts_with("http://")
+    }
+
+    /// Returns the path of the cached file, which keeps the file name of the URL so that the
+    /// data format is still identified by the extension.
+    pub fn cache_path(&self) -> PathBuf {
+        let cache_dir = self.cache_dir.clone().unwrap_or_else(|| {
+            env::var("HFTBACKTEST_CACHE_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| env::temp_dir().join("hftbacktest"))
+        });
+        let name = self
+            .url
+            .split(['?', '#'])
+            .next()
+            .and_then(|url| url.rsplit('/').next())
+            .unwrap_or("");
+        let digest = hex(&Sha256::digest(self.url.as_bytes()));
+        cache_dir.join(format!("{}_{}", &digest[..16], name))
+    }
+
+    /// Downloads the file into the cache unless it is already cached and returns the local path.
+    /// The download is streamed to a temporary file, which is moved into place only after its
+    /// length and checksum are verified.
+    pub fn fetch(&self) -> Result<String, IoError> {
+        let path = self.cache_path();
+        if path.is_file() {
+            match &self.sha256 {
+                Some(expected) if &file_sha256(&path)? != expected => {
+                    fs::remove_file(&path)?;
+                }
+                _ => return Ok(path.to_string_lossy().to_string()),
+            }
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let part = path.with_extension("part");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let result = runtime.block_on(self.download(&part));
+        let checksum = match result {
+            Ok(checksum) => checksum,
+            Err(error) => {
+                let _ = fs::remove_file(&part);
+                return Err(error);
+            }
+        };
+        if let Some(expected) = &self.sha256 {
+            if &checksum != expected {
+                let _ = fs::remove_file(&part);
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "checksum mismatch for {}: expected {}, got {}",
+                        self.url, expected, checksum
+                    ),
+                ));
+            }
+        }
+        fs::rename(&part, &path)?;
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    async fn download(&self, part: &Path) -> Result<String, IoError> {
+        let (url, headers) = self.request()?;
+        let mut resp = reqwest::Client::new()
+            .get(url)
+            .headers(headers)
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status())
+            .map_err(IoError::other)?;
+        let content_length = resp.content_length();
+
+        let mut writer = BufWriter::new(File::create(part)?);
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = resp.chunk().await.map_err(IoError::other)? {
+            hasher.update(&chunk);
+            writer.write_all(&chunk)?;
+            size += chunk.len() as u64;
+        }
+        writer.flush()?;
+
+        if let Some(content_length) = content_length {
+            if size != content_length {
+                return Err(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    format!(
+                        "incomplete download of {}: {} of {} bytes",
+                        self.url, size, content_length
+                    ),
+                ));
+            }
+        }
+        Ok(hex(&hasher.finalize()))
+    }
+
+    fn request(&self) -> Result<(Url, HeaderMap), IoError> {
+        let invalid = || {
+            IoError::new(
+                ErrorKind::InvalidInput,
+                format!("invalid url: {}", self.url),
+            )
+        };
+        if let Some(location) = self.url.strip_prefix("gs://") {
+            let (bucket, key) = location.split_once('/').ok_or_else(invalid)?;
+            let url = format!(
+                "https://storage.googleapis.com/{}/{}",
+                bucket,
+                uri_encode(key)
+            );
+            let url = Url::parse(&url).map_err(|_| invalid())?;
+            let mut headers = HeaderMap::new();
+            if let Ok(token) = env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                headers.insert(
+                    AUTHORIZATION,
+                    format!("Bearer {token}").parse().map_err(|_| invalid())?,
+                );
+            }
+            return Ok((url, headers));
+        }
+        let Some(location) = self.url.strip_prefix("s3://") else {
+            return Ok((
+                Url::parse(&self.url).map_err(|_| invalid())?,
+                HeaderMap::new(),
+            ));
+        };
+        let (bucket, key) = location.split_once('/').ok_or_else(invalid)?;
+        let region = env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let url = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => format!(
+                "{}/{}/{}",
+                endpoint.trim_end_matches('/'),
+                bucket,
+                uri_encode(key)
+            ),
+            Err(_) => format!(
+                "https://{}.s3.{}.amazonaws.com/{}",
+                bucket,
+                region,
+                uri_encode(key)
+            ),
+        };
+        let url = Url::parse(&url).map_err(|_| invalid())?;
+
+        let mut headers = HeaderMap::new();
+        if let (Ok(access_key), Ok(secret_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            let session_token = env::var("AWS_SESSION_TOKEN").ok();
+            sign_s3(
+                &url,
+                &region,
+                &access_key,
+                &secret_key,
+                session_token.as_deref(),
+                &mut headers,
+            );
+        }
+        Ok((url, headers))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut tmp = String::with_capacity(bytes.len() * 2);
+    for c in bytes {
+        write!(&mut tmp, "{:02x}", c).unwrap();
+    }
+    tmp
+}
+
+fn file_sha256(path: &Path) -> Result<String, IoError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn uri_encode(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for c in key.bytes() {
+        if c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b'.' | b'~' | b'/') {
+            encoded.push(c as char);
+        } else {
+            write!(&mut encoded, "%{:02X}", c).unwrap();
+        }
+    }
+    encoded
+}
+
+fn hmac_sha256(key: &[u8], s: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(s.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Signs a GET request to S3 with AWS Signature Version 4.
+fn sign_s3(
+    url: &Url,
+    region: &str,
+    access_key: &str,
+    secret_key: &str,
+    session_token: Option<&str>,
+    headers: &mut HeaderMap,
+) {
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_string(),
+    };
+
+    let mut canonical_headers = format!(
+        "host:{}\nx-amz-content-sha256:UNSIGNED-PAYLOAD\nx-amz-date:{}\n",
+        host, amz_date
+    );
+    let mut signed_headers = "host;x-amz-content-sha256;x-amz-date".to_string();
+    if let Some(token) = session_token {
+        canonical_headers.push_str(&format!("x-amz-security-token:{}\n", token));
+        signed_headers.push_str(";x-amz-security-token");
+    }
+    let canonical_request = format!(
+        "GET\n{}\n{}\n{}\n{}\nUNSIGNED-PAYLOAD",
+        url.path(),
+        url.query().unwrap_or(""),
+        canonical_headers,
+        signed_headers
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), &date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, "s3");
+    let key = hmac_sha256(&key, "aws4_request");
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+
+    headers.insert("x-amz-date", amz_date.parse().unwrap());
+    headers.insert("x-amz-content-sha256", "UNSIGNED-PAYLOAD".parse().unwrap());
+    if let Some(token) = session_token {
+        headers.insert("x-amz-security-token", token.parse().unwrap());
+    }
+    headers.insert(
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            access_key, scope, signed_headers, signature
+        )
+        .parse()
+        .unwrap(),
+    );
+}
@@ -0,0 +1,259 @@
+use std::{
+    fs::File,
+    io::{Error as IoError, Write},
+    ops::Range,
+};
+
+use plotters::prelude::*;
+
+use crate::backtest::stats::{BacktestReport, Record};
+
+const WIDTH: u32 = 1200;
+const HEIGHT: u32 = 320;
+
+fn draw_error<E: std::error::Error + Send + Sync>(error: DrawingAreaErrorKind<E>) -> IoError {
+    IoError::other(error.to_string())
+}
+
+fn value_range(values: impl Iterator<Item = f64>) -> Range<f64> {
+    let (min, max) = values.fold((f64::MAX, f64::MIN), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if min > max {
+        0.0..1.0
+    } else if min == max {
+        (min - 1.0)..(max + 1.0)
+    } else {
+        let margin = (max - min) * 0.05;
+        (min - margin)..(max + margin)
+    }
+}
+
+// The x-axis is the elapsed time in hours since the first record, assuming nanosecond
+// timestamps.
+fn elapsed_hours(records: &[Record], timestamp: i64) -> f64 {
+    let start = records.first().map(|record| record.timestamp).unwrap_or(0);
+    (timestamp - start) as f64 / 3_600_000_000_000.0
+}
+
+fn time_range(records: &[Record]) -> Range<f64> {
+    match records.last() {
+        Some(last) if elapsed_hours(records, last.timestamp) > 0.0 => {
+            0.0..elapsed_hours(records, last.timestamp)
+        }
+        _ => 0.0..1.0,
+    }
+}
+
+fn line_chart(
+    caption: &str,
+    records: &[Record],
+    series: &[(f64, f64)],
+    color: &RGBColor,
+) -> Result<String, IoError> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).map_err(draw_error)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption(caption, ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(80)
+            .build_cartesian_2d(
+                time_range(records),
+                value_range(series.iter().map(|(_, value)| *value)),
+            )
+            .map_err(draw_error)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_desc("Elapsed (hours)")
+            .draw()
+            .map_err(draw_error)?;
+        chart
+            .draw_series(LineSeries::new(series.iter().copied(), color))
+            .map_err(draw_error)?;
+        root.present().map_err(draw_error)?;
+    }
+    Ok(svg)
+}
+
+fn price_chart(records: &[Record]) -> Result<String, IoError> {
+    let mut svg = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).map_err(draw_error)?;
+        let mut chart = ChartBuilder::on(&root)
+            .caption("Price and fills", ("sans-serif", 18))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(80)
+            .build_cartesian_2d(
+                time_range(records),
+                value_range(records.iter().map(|record| record.price as f64)),
+            )
+            .map_err(draw_error)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_desc("Elapsed (hours)")
+            .draw()
+            .map_err(draw_error)?;
+        chart
+            .draw_series(LineSeries::new(
+                records.iter().map(|record| {
+                    (
+                        elapsed_hours(records, record.timestamp),
+                        record.price as f64,
+                    )
+                }),
+                &BLACK,
+            ))
+            .map_err(draw_error)?;
+
+        // Marks the records at which trades occurred, by the direction of the position change.
+        let fills = records.windows(2).filter_map(|w| {
+            if w[1].trade_num > w[0].trade_num {
+                let color = if w[1].position >= w[0].position {
+                    GREEN
+                } else {
+                    RED
+                };
+                Some(Circle::new(
+                    (elapsed_hours(records, w[1].timestamp), w[1].price as f64),
+                    3,
+                    color.filled(),
+                ))
+            } else {
+                None
+            }
+        });
+        chart.draw_series(fills).map_err(draw_error)?;
+        root.present().map_err(draw_error)?;
+    }
+    Ok(svg)
+}
+
+fn fmt_value(value: f64) -> String {
+    if value.is_nan() {
+        "-".to_string()
+    } else {
+        format!("{:.4}", value)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders the report and the recorded series into a standalone HTML file, which includes the
+/// equity curve, the drawdown, the position over time, and the fills on the price, followed by
+/// the configuration entries of the report's fingerprint, if any.
+pub fn write_html(
+    path: &str,
+    title: &str,
+    records: &[Record],
+    report: &BacktestReport,
+) -> Result<(), IoError> {
+    let equity: Vec<(f64, f64)> = records
+        .iter()
+        .map(|record| (elapsed_hours(records, record.timestamp), record.equity))
+        .collect();
+    let mut peak = f64::MIN;
+    let drawdown: Vec<(f64, f64)> = records
+        .iter()
+        .map(|record| {
+            peak = peak.max(record.equity);
+            (
+                elapsed_hours(records, record.timestamp),
+                record.equity - peak,
+            )
+        })
+        .collect();
+    let position: Vec<(f64, f64)> = records
+        .iter()
+        .map(|record| (elapsed_hours(records, record.timestamp), record.position))
+        .collect();
+
+    let mut summary = vec![
+        ("Start", report.start.to_string()),
+        ("End", report.end.to_string()),
+        ("PnL", fmt_value(report.pnl)),
+        ("Fee", fmt_value(report.fee)),
+        ("Trades", report.trade_num.to_string()),
+        ("Trading amount", fmt_value(report.trade_amount)),
+        ("Sharpe ratio", fmt_value(report.sharpe)),
+        ("Sortino ratio", fmt_value(report.sortino)),
+        ("Max drawdown", fmt_value(report.max_drawdown)),
+        ("Turnover", fmt_value(report.turnover)),
+        ("PnL per trade", fmt_value(report.pnl_per_trade)),
+        ("Average trade quantity", fmt_value(report.avg_trade_qty)),
+        ("Average trade amount", fmt_value(report.avg_trade_amount)),
+        ("Termination", format!("{:?}", report.termination)),
+    ];
+    if let Some(fingerprint) = &report.fingerprint {
+        summary.push(("Fingerprint", fingerprint.digest.clone()));
+    }
+    if let Some(ci) = report.sharpe_ci {
+        summary.push((
+            "Sharpe ratio CI",
+            format!("{} ~ {}", fmt_value(ci.lower), fmt_value(ci.upper)),
+        ));
+    }
+    if let Some(ci) = report.max_drawdown_ci {
+        summary.push((
+            "Max drawdown CI",
+            format!("{} ~ {}", fmt_value(ci.lower), fmt_value(ci.upper)),
+        ));
+    }
+    if let Some(slippage) = report.slippage {
+        summary.push(("Fills", slippage.num_fills.to_string()));
+        summary.push(("Slippage vs arrival", fmt_value(slippage.vs_arrival)));
+        summary.push(("Slippage vs decision", fmt_value(slippage.vs_decision)));
+        summary.push((
+            "Maker slippage vs arrival",
+            fmt_value(slippage.maker_vs_arrival),
+        ));
+        summary.push((
+            "Taker slippage vs arrival",
+            fmt_value(slippage.taker_vs_arrival),
+        ));
+    }
+
+    let mut file = File::create(path)?;
+    writeln!(
+        file,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+        <style>body {{ font-family: sans-serif; }} td {{ padding: 2px 12px; }}</style>\n\
+        </head>\n<body>\n<h1>{title}</h1>\n<table>"
+    )?;
+    for (name, value) in summary {
+        writeln!(file, "<tr><td>{name}</td><td>{value}</td></tr>")?;
+    }
+    writeln!(file, "</table>")?;
+    for svg in [
+        line_chart("Equity", records, &equity, &BLUE)?,
+        line_chart("Drawdown", records, &drawdown, &RED)?,
+        line_chart("Position", records, &position, &MAGENTA)?,
+        price_chart(records)?,
+    ] {
+        writeln!(file, "<div>{svg}</div>")?;
+    }
+    if let Some(fingerprint) = &report.fingerprint {
+        writeln!(file, "<h2>Configuration</h2>\n<table>")?;
+        for (key, value) in &fingerprint.entries {
+            writeln!(
+                file,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(key),
+                escape(value)
+            )?;
+        }
+        writeln!(file, "</table>")?;
+    }
+    writeln!(file, "</body>\n</html>")?;
+    Ok(())
+}
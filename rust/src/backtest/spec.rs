@@ -0,0 +1,316 @@
+use serde::Deserialize;
+
+use crate::{
+    backtest::{
+        assettype::{AssetType, InverseAsset, LinearAsset},
+        models::{
+            ConstantLatency,
+            FlatFee,
+            IntpOrderLatency,
+            LatencyModel,
+            LogProbQueueFunc,
+            LogProbQueueFunc2,
+            OutOfRange,
+            PowerProbQueueFunc,
+            PowerProbQueueFunc2,
+            PowerProbQueueFunc3,
+            ProbQueueModel,
+            Probability,
+            QueuePos,
+        },
+        reader::read_data,
+        BtAsset,
+        BtAssetBuilder,
+        DataSource,
+        Error,
+        ExchangeKind,
+    },
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    error::BuildError,
+    ty::{OrdType, Order, TimeInForce},
+};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LatencySpec {
+    Constant {
+        entry_latency: i64,
+        response_latency: i64,
+    },
+    /// [`IntpOrderLatency`] with the latency data file, which handles a request outside the time
+    /// range of the data by `out_of_range`.
+    Intp {
+        filepath: String,
+        #[serde(default)]
+        out_of_range: OutOfRange,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AssetTypeSpec {
+    Linear { contract_size: f64 },
+    Inverse { contract_size: f64 },
+}
+
+/// The probability function of [`ProbQueueModel`].
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum QueueModelSpec {
+    PowerProb { n: f32 },
+    PowerProb2 { n: f32 },
+    PowerProb3 { n: f32 },
+    LogProb,
+    LogProb2,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExchangeSpec {
+    #[default]
+    NoPartialFill,
+    PartialFill,
+    ReferenceOnly,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub(crate) struct FeeSpec {
+    pub maker_fee: f64,
+    pub taker_fee: f64,
+}
+
+/// Describes an asset of the backtest by its settings, so that the bindings to the other
+/// languages can build it at runtime, with [`HashMapMarketDepth`] and [`ProbQueueModel`].
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct AssetSpec {
+    pub data: Vec<String>,
+    #[serde(default)]
+    pub initial_snapshot: Option<String>,
+    pub latency: Option<LatencySpec>,
+    pub asset_type: Option<AssetTypeSpec>,
+    pub queue_model: Option<QueueModelSpec>,
+    pub tick_size: Option<f32>,
+    #[serde(default = "default_lot_size")]
+    pub lot_size: f32,
+    #[serde(default)]
+    pub exchange: ExchangeSpec,
+    #[serde(default)]
+    pub bbo_feed: bool,
+    /// The fee overrides that of the venue profile.
+    #[serde(default)]
+    pub fee: Option<FeeSpec>,
+    #[serde(default)]
+    pub venue_profile: Option<String>,
+}
+
+fn default_lot_size() -> f32 {
+    1.0
+}
+
+impl Default for AssetSpec {
+    fn default() -> Self {
+        Self {
+            data: Vec::new(),
+            initial_snapshot: None,
+            latency: None,
+            asset_type: None,
+            queue_model: None,
+            tick_size: None,
+            lot_size: default_lot_size(),
+            exchange: Default::default(),
+            bbo_feed: false,
+            fee: None,
+            venue_profile: None,
+        }
+    }
+}
+
+impl AssetSpec {
+    /// Builds the asset, reading its data.
+    pub fn build(&self) -> Result<BtAsset<QueuePos, HashMapMarketDepth>, BuildError> {
+        let latency = match self.latency.clone() {
+            Some(LatencySpec::Constant {
+                entry_latency,
+                response_latency,
+            }) => Latency::Constant(ConstantLatency::new(entry_latency, response_latency)),
+            Some(LatencySpec::Intp {
+                filepath,
+                out_of_range,
+            }) => {
+                let data = read_data(&filepath).map_err(|error| BuildError::Error(error.into()))?;
+                Latency::Intp(IntpOrderLatency::new(data, out_of_range))
+            }
+            None => return Err(BuildError::BuilderIncomplete("order_latency")),
+        };
+        let asset_type = match self.asset_type {
+            Some(AssetTypeSpec::Linear { contract_size }) => {
+                Asset::Linear(LinearAsset::new(contract_size))
+            }
+            Some(AssetTypeSpec::Inverse { contract_size }) => {
+                Asset::Inverse(InverseAsset::new(contract_size))
+            }
+            None => return Err(BuildError::BuilderIncomplete("asset_type")),
+        };
+        let prob_func = match self.queue_model {
+            Some(QueueModelSpec::PowerProb { n }) => ProbFunc::Power(PowerProbQueueFunc::new(n)),
+            Some(QueueModelSpec::PowerProb2 { n }) => ProbFunc::Power2(PowerProbQueueFunc2::new(n)),
+            Some(QueueModelSpec::PowerProb3 { n }) => ProbFunc::Power3(PowerProbQueueFunc3::new(n)),
+            Some(QueueModelSpec::LogProb) => ProbFunc::Log(LogProbQueueFunc::new()),
+            Some(QueueModelSpec::LogProb2) => ProbFunc::Log2(LogProbQueueFunc2::new()),
+            None => return Err(BuildError::BuilderIncomplete("queue_model")),
+        };
+        let tick_size = self
+            .tick_size
+            .ok_or(BuildError::BuilderIncomplete("depth"))?;
+        let lot_size = self.lot_size;
+        let exch_kind = match self.exchange {
+            ExchangeSpec::NoPartialFill => ExchangeKind::NoPartialFillExchange,
+            ExchangeSpec::PartialFill => ExchangeKind::PartialFillExchange,
+            ExchangeSpec::ReferenceOnly => ExchangeKind::ReferenceOnly,
+        };
+
+        let mut builder = BtAssetBuilder::new();
+        if let Some(name) = &self.venue_profile {
+            builder = builder.venue_profile(name);
+        }
+        if let Some(fee) = self.fee {
+            builder = builder.fee_model(FlatFee::new(fee.maker_fee, fee.taker_fee));
+        }
+        builder = builder.data(
+            self.data
+                .iter()
+                .map(|filepath| DataSource::File(filepath.clone()))
+                .collect(),
+        );
+        if let Some(filepath) = &self.initial_snapshot {
+            builder = builder.initial_snapshot(filepath);
+        }
+        builder
+            .latency_model(latency)
+            .asset_type(asset_type)
+            .queue_model(ProbQueueModel::new(prob_func))
+            .depth(move || HashMapMarketDepth::new(tick_size, lot_size))
+            .exchange(exch_kind)
+            .bbo_feed(self.bbo_feed)
+            .build()
+    }
+}
+
+/// Returns the time in force of the discriminant.
+pub(crate) fn time_in_force(value: u8) -> Option<TimeInForce> {
+    match value {
+        0 => Some(TimeInForce::GTC),
+        1 => Some(TimeInForce::GTX),
+        2 => Some(TimeInForce::FOK),
+        3 => Some(TimeInForce::IOC),
+        _ => None,
+    }
+}
+
+/// Returns the order type of the discriminant.
+pub(crate) fn ord_type(value: u8) -> Option<OrdType> {
+    match value {
+        0 => Some(OrdType::Limit),
+        1 => Some(OrdType::Market),
+        2 => Some(OrdType::StopMarket),
+        3 => Some(OrdType::StopLimit),
+        4 => Some(OrdType::TakeProfitMarket),
+        5 => Some(OrdType::TakeProfitLimit),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Latency {
+    Constant(ConstantLatency),
+    Intp(IntpOrderLatency),
+}
+
+impl LatencyModel for Latency {
+    fn entry<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error> {
+        match self {
+            Latency::Constant(model) => model.entry(timestamp, order),
+            Latency::Intp(model) => model.entry(timestamp, order),
+        }
+    }
+
+    fn response<Q: Clone>(&mut self, timestamp: i64, order: &Order<Q>) -> Result<i64, Error> {
+        match self {
+            Latency::Constant(model) => model.response(timestamp, order),
+            Latency::Intp(model) => model.response(timestamp, order),
+        }
+    }
+
+    fn seek(&mut self, timestamp: i64) {
+        match self {
+            Latency::Constant(model) => model.seek(timestamp),
+            Latency::Intp(model) => model.seek(timestamp),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Asset {
+    Linear(LinearAsset),
+    Inverse(InverseAsset),
+}
+
+impl Asset {
+    fn get(&self) -> &dyn AssetType {
+        match self {
+            Asset::Linear(asset) => asset,
+            Asset::Inverse(asset) => asset,
+        }
+    }
+}
+
+impl AssetType for Asset {
+    fn amount(&self, exec_price: f32, qty: f32) -> f64 {
+        self.get().amount(exec_price, qty)
+    }
+
+    fn balance_change(&self, exec_price: f32, qty: f32) -> f64 {
+        self.get().balance_change(exec_price, qty)
+    }
+
+    fn equity(&self, price: f32, balance: f64, position: f64, fee: f64) -> f64 {
+        self.get().equity(price, balance, position, fee)
+    }
+
+    fn contract_size(&self) -> f64 {
+        self.get().contract_size()
+    }
+
+    fn notional(&self, price: f32, qty: f64) -> f64 {
+        self.get().notional(price, qty)
+    }
+
+    fn pnl(&self, entry_price: f32, price: f32, qty: f64) -> f64 {
+        self.get().pnl(entry_price, price, qty)
+    }
+
+    fn fee(&self, exec_price: f32, qty: f32, fee_rate: f64) -> f64 {
+        self.get().fee(exec_price, qty, fee_rate)
+    }
+}
+
+#[derive(Debug)]
+enum ProbFunc {
+    Power(PowerProbQueueFunc),
+    Power2(PowerProbQueueFunc2),
+    Power3(PowerProbQueueFunc3),
+    Log(LogProbQueueFunc),
+    Log2(LogProbQueueFunc2),
+}
+
+impl Probability for ProbFunc {
+    fn prob(&self, front: f32, back: f32) -> f32 {
+        match self {
+            ProbFunc::Power(func) => func.prob(front, back),
+            ProbFunc::Power2(func) => func.prob(front, back),
+            ProbFunc::Power3(func) => func.prob(front, back),
+            ProbFunc::Log(func) => func.prob(front, back),
+            ProbFunc::Log2(func) => func.prob(front, back),
+        }
+    }
+}
@@ -1,16 +1,18 @@
-use crate::{backtest::assettype::AssetType, ty::Order};
+use std::fmt::{Debug, Formatter};
 
-#[derive(Debug)]
-pub struct StateValues {
-    pub position: f64,
-    pub balance: f64,
-    pub fee: f64,
-    pub trade_num: i32,
-    pub trade_qty: f64,
-    pub trade_amount: f64,
-}
+pub use crate::ty::StateValues;
+use crate::{
+    backtest::{
+        assettype::AssetType,
+        models::{FeeModel, FlatFee},
+    },
+    ty::Order,
+};
+
+/// A hook invoked on every fill with the filled order and the state values after the fill is
+/// applied.
+pub type FillHook<Q> = Box<dyn FnMut(&Order<Q>, &StateValues)>;
 
-#[derive(Debug)]
 pub struct State<AT>
 where
     AT: AssetType,
@@ -21,11 +23,28 @@ where
     pub trade_num: i32,
     pub trade_qty: f64,
     pub trade_amount: f64,
-    pub maker_fee: f64,
-    pub taker_fee: f64,
+    /// Determines the fee rate of each fill. The default charges no fees.
+    pub fee_model: Box<dyn FeeModel>,
     pub asset_type: AT,
 }
 
+impl<AT> Debug for State<AT>
+where
+    AT: AssetType + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("State")
+            .field("position", &self.position)
+            .field("balance", &self.balance)
+            .field("fee", &self.fee)
+            .field("trade_num", &self.trade_num)
+            .field("trade_qty", &self.trade_qty)
+            .field("trade_amount", &self.trade_amount)
+            .field("asset_type", &self.asset_type)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<AT> State<AT>
 where
     AT: AssetType,
@@ -38,27 +57,36 @@ where
             trade_num: 0,
             trade_qty: 0.0,
             trade_amount: 0.0,
-            maker_fee: 0.0,
-            taker_fee: 0.0,
+            fee_model: Box::new(FlatFee::default()),
             asset_type,
         }
     }
 
     pub fn apply_fill<Q: Clone + Default>(&mut self, order: &Order<Q>) {
-        let fee = if order.maker {
-            self.maker_fee
-        } else {
-            self.taker_fee
-        };
         let amount = self.asset_type.amount(order.exec_price(), order.exec_qty);
+        let fee_rate = self
+            .fee_model
+            .fee_rate(order.exch_timestamp, order.maker, amount);
         self.position += order.exec_qty as f64 * order.side.as_f64();
-        self.balance -= amount * order.side.as_f64();
-        self.fee += amount * fee;
+        self.balance += self
+            .asset_type
+            .balance_change(order.exec_price(), order.exec_qty)
+            * order.side.as_f64();
+        self.fee += self
+            .asset_type
+            .fee(order.exec_price(), order.exec_qty, fee_rate);
         self.trade_num += 1;
         self.trade_qty += order.exec_qty as f64;
         self.trade_amount += amount;
     }
 
+    /// Applies a manual adjustment of the position and the balance, which is not counted as a
+    /// trade.
+    pub fn apply_adjustment(&mut self, position: f64, balance: f64) {
+        self.position += position;
+        self.balance += balance;
+    }
+
     pub fn equity(&self, mid: f32) -> f64 {
         self.asset_type
             .equity(mid, self.balance, self.position, self.fee)
@@ -0,0 +1,1268 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Error as IoError, Write},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::{
+    backtest::{assettype::AssetType, fingerprint::Fingerprint, stream::RecordStream},
+    depth::{MarketDepth, Tick},
+    ty::{Adjustment, MarkSource, Side, Status},
+    Interface,
+};
+
+const YEAR_NS: f64 = 365.0 * 24.0 * 60.0 * 60.0 * 1_000_000_000.0;
+
+/// A snapshot of an asset's state, recorded by [`Recorder`].
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Record {
+    pub timestamp: i64,
+    /// The price used to mark the position, by the [`MarkSource`] of the [`Recorder`].
+    pub price: f32,
+    pub position: f64,
+    pub balance: f64,
+    pub fee: f64,
+    pub trade_num: i32,
+    pub trade_qty: f64,
+    pub trade_amount: f64,
+    pub equity: f64,
+}
+
+/// Records snapshots of an asset's state to produce a [`BacktestReport`].
+///
+/// With a sampling interval set by [`interval`](Self::interval), the snapshots are taken at most
+/// once per interval, and [`elapse`](Self::elapse) elapses the backtest while taking them at
+/// every interval in between, so that the records are evenly spaced regardless of how the
+/// strategy elapses.
+pub struct Recorder<AT>
+where
+    AT: AssetType,
+{
+    asset_type: AT,
+    records: Vec<Record>,
+    stream: Option<RecordStream>,
+    interval: Option<i64>,
+    fingerprint: Option<Fingerprint>,
+    mark_source: MarkSource,
+    last_trade: Option<f32>,
+    mark_price: Option<f32>,
+}
+
+impl<AT> Recorder<AT>
+where
+    AT: AssetType,
+{
+    pub fn new(asset_type: AT) -> Self {
+        Self {
+            asset_type,
+            records: Vec::new(),
+            stream: None,
+            interval: None,
+            fingerprint: None,
+            mark_source: MarkSource::Mid,
+            last_trade: None,
+            mark_price: None,
+        }
+    }
+
+    /// Sets the sampling interval. Once set, [`record`](Self::record) takes a snapshot only if
+    /// at least `interval` has passed since the last one.
+    pub fn interval(self, interval: i64) -> Self {
+        Self {
+            interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Streams the records as they are recorded. See [`RecordStream`].
+    pub fn stream(self, stream: RecordStream) -> Self {
+        Self {
+            stream: Some(stream),
+            ..self
+        }
+    }
+
+    /// Embeds the [`Fingerprint`] of the backtest's configuration into the outputs,
+    /// [`report`](Self::report), [`write_csv`](Self::write_csv), and
+    /// [`write_npz`](Self::write_npz), so that the results can be traced back to what produced
+    /// them.
+    pub fn fingerprint(self, fingerprint: Fingerprint) -> Self {
+        Self {
+            fingerprint: Some(fingerprint),
+            ..self
+        }
+    }
+
+    /// Sets the price at which the position is marked, which is the mid price by default.
+    ///
+    /// The last trade is picked up from [`Interface::trade`] when recording, so the trades should
+    /// be recorded before they're cleared by
+    /// [`Interface::clear_last_trades`](crate::Interface::clear_last_trades). Since the backtest
+    /// data doesn't carry the venue's mark price, it should be provided by
+    /// [`set_mark_price`](Self::set_mark_price) to use [`MarkSource::MarkPrice`].
+    pub fn mark_source(self, mark_source: MarkSource) -> Self {
+        Self {
+            mark_source,
+            ..self
+        }
+    }
+
+    /// Sets the venue's mark price, at which the position is marked from then on under
+    /// [`MarkSource::MarkPrice`].
+    pub fn set_mark_price(&mut self, price: f32) {
+        self.mark_price = Some(price);
+    }
+
+    /// Returns `true` if a client of the record stream has requested to abort the run.
+    pub fn abort_requested(&self) -> bool {
+        self.stream
+            .as_ref()
+            .map(|stream| stream.abort_requested())
+            .unwrap_or(false)
+    }
+
+    /// Records the current state of the given asset. The position is marked at the price of the
+    /// [`MarkSource`], or at the mid price if the price of the source isn't available yet, or at
+    /// the last recorded price if either side of the market depth is empty as well.
+    pub fn record<I, Q, MD>(&mut self, hbt: &I, asset_no: usize)
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        if let (Some(interval), Some(last)) = (self.interval, self.records.last()) {
+            if hbt.current_timestamp() < last.timestamp + interval {
+                return;
+            }
+        }
+        if let Some(trade) = hbt.trade(asset_no).last() {
+            self.last_trade = Some(trade.px);
+        }
+        let depth = hbt.depth(asset_no);
+        let mid = (depth.best_bid_tick() != MD::Tick::INVALID_MIN
+            && depth.best_ask_tick() != MD::Tick::INVALID_MAX)
+            .then(|| ((depth.best_bid() + depth.best_ask()) / 2.0) as f64);
+        let mark = self.mark_source.select(
+            mid,
+            self.last_trade.map(f64::from),
+            self.mark_price.map(f64::from),
+        );
+        let price = match (mark, self.records.last()) {
+            (Some(mark), _) => mark as f32,
+            (None, Some(record)) => record.price,
+            (None, None) => return,
+        };
+        let state_values = hbt.state_values(asset_no);
+        let record = Record {
+            timestamp: hbt.current_timestamp(),
+            price,
+            position: state_values.position,
+            balance: state_values.balance,
+            fee: state_values.fee,
+            trade_num: state_values.trade_num,
+            trade_qty: state_values.trade_qty,
+            trade_amount: state_values.trade_amount,
+            equity: self.asset_type.equity(
+                price,
+                state_values.balance,
+                state_values.position,
+                state_values.fee,
+            ),
+        };
+        if let Some(stream) = self.stream.as_mut() {
+            stream.publish(&record);
+        }
+        self.records.push(record);
+    }
+
+    /// Elapses the backtest by `duration`, recording the asset at every sampling interval in
+    /// between and at the end. Without a sampling interval, it records only at the end. Returns
+    /// the result of the last elapse.
+    pub fn elapse<I, Q, MD>(
+        &mut self,
+        hbt: &mut I,
+        asset_no: usize,
+        duration: i64,
+    ) -> Result<bool, I::Error>
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        let end = hbt.current_timestamp() + duration;
+        if let Some(interval) = self.interval.filter(|interval| *interval > 0) {
+            loop {
+                let next = match self.records.last() {
+                    Some(record) => record.timestamp + interval,
+                    None => hbt.current_timestamp(),
+                };
+                if next >= end {
+                    break;
+                }
+                let step = next - hbt.current_timestamp();
+                if step > 0 && !hbt.elapse(step)? {
+                    return Ok(false);
+                }
+                let num_records = self.records.len();
+                self.record(hbt, asset_no);
+                if self.records.len() == num_records && step <= 0 {
+                    // Nothing to record yet, such as before the depth is built.
+                    break;
+                }
+            }
+        }
+        let remaining = end - hbt.current_timestamp();
+        let result = if remaining > 0 {
+            hbt.elapse(remaining)?
+        } else {
+            true
+        };
+        self.record(hbt, asset_no);
+        Ok(result)
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    pub fn into_records(self) -> Vec<Record> {
+        self.records
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        let report = BacktestReport::new(&self.records);
+        match &self.fingerprint {
+            Some(fingerprint) => report.with_fingerprint(fingerprint.clone()),
+            None => report,
+        }
+    }
+
+    /// Writes the records into a CSV file as [`write_csv`] does, preceded by the comment lines
+    /// of the fingerprint, if any, starting with `#`.
+    pub fn write_csv(&self, path: &str) -> Result<(), IoError> {
+        write_csv_with_fingerprint(path, &self.records, self.fingerprint.as_ref())
+    }
+
+    /// Writes the records into an `.npz` archive as [`write_npz`] does, with the fingerprint, if
+    /// any, as the JSON entry [`NPZ_FINGERPRINT_ENTRY`].
+    pub fn write_npz(&self, path: &str) -> Result<(), IoError> {
+        write_npz_with_fingerprint(path, &self.records, self.fingerprint.as_ref())
+    }
+}
+
+/// A fill recorded by [`FillJournal`].
+#[derive(Clone, Copy, Debug)]
+pub struct Fill {
+    /// The local timestamp at which the fill is observed.
+    pub timestamp: i64,
+    /// The exchange timestamp of the fill.
+    pub exch_timestamp: i64,
+    pub order_id: i64,
+    pub side: Side,
+    pub price: f32,
+    pub qty: f32,
+    pub maker: bool,
+}
+
+/// Records the fills of an asset's orders, and separately, the manual adjustments of its position
+/// and balance. See [`Interface::adjustments`].
+///
+/// Call [`FillJournal::update`] after every elapse, before clearing inactive orders, so that no
+/// fill is missed.
+#[derive(Default)]
+pub struct FillJournal {
+    leaves_qty: HashMap<i64, f32>,
+    fills: Vec<Fill>,
+    adjustments: Vec<Adjustment>,
+    // The number of the adjustments of all assets that have been seen.
+    num_adjustments: usize,
+}
+
+impl FillJournal {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn update<I, Q, MD>(&mut self, hbt: &I, asset_no: usize)
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        let timestamp = hbt.current_timestamp();
+        let orders = hbt.orders(asset_no);
+        for (order_id, order) in orders.iter() {
+            let leaves_qty = self.leaves_qty.entry(*order_id).or_insert(order.qty);
+            if (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+                && order.leaves_qty < *leaves_qty
+            {
+                self.fills.push(Fill {
+                    timestamp,
+                    exch_timestamp: order.exch_timestamp,
+                    order_id: *order_id,
+                    side: order.side,
+                    price: order.exec_price(),
+                    qty: *leaves_qty - order.leaves_qty,
+                    maker: order.maker,
+                });
+                *leaves_qty = order.leaves_qty;
+            }
+        }
+        self.leaves_qty.retain(|order_id, _| {
+            orders
+                .get(order_id)
+                .map(|order| !order.terminal())
+                .unwrap_or(false)
+        });
+
+        let adjustments = hbt.adjustments();
+        self.adjustments.extend(
+            adjustments[self.num_adjustments.min(adjustments.len())..]
+                .iter()
+                .filter(|adjustment| adjustment.asset_no == asset_no)
+                .cloned(),
+        );
+        self.num_adjustments = adjustments.len();
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    pub fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+}
+
+/// How the backtest terminated.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Termination {
+    #[default]
+    Completed,
+    /// Stopped early by the early-stop predicate at the given timestamp.
+    EarlyStopped(i64),
+}
+
+/// Summary statistics of recorded backtest results.
+#[derive(Clone, Debug)]
+pub struct BacktestReport {
+    pub termination: Termination,
+    pub start: i64,
+    pub end: i64,
+    pub pnl: f64,
+    pub fee: f64,
+    pub trade_num: i32,
+    pub trade_amount: f64,
+    /// The annualized Sharpe ratio of the equity changes between records, assuming nanosecond
+    /// timestamps. It is `NaN` if there are not enough records.
+    pub sharpe: f64,
+    /// The annualized Sortino ratio of the equity changes between records, which only penalizes
+    /// the losses. It is `NaN` if there are not enough records or no losses.
+    pub sortino: f64,
+    pub max_drawdown: f64,
+    /// The trading amount divided by the average absolute value of the position marked at the
+    /// price, that is, how many times the position has been turned over. It is `NaN` if the
+    /// position has never been held.
+    pub turnover: f64,
+    /// The PnL per trade. It is `NaN` if there is no trade.
+    pub pnl_per_trade: f64,
+    /// The average quantity of a trade. It is `NaN` if there is no trade.
+    pub avg_trade_qty: f64,
+    /// The average amount of a trade. It is `NaN` if there is no trade.
+    pub avg_trade_amount: f64,
+    /// The bootstrapped confidence interval of the Sharpe ratio. See
+    /// [`BacktestReport::with_bootstrap`].
+    pub sharpe_ci: Option<ConfidenceInterval>,
+    /// The bootstrapped confidence interval of the maximum drawdown. See
+    /// [`BacktestReport::with_bootstrap`].
+    pub max_drawdown_ci: Option<ConfidenceInterval>,
+    /// The execution slippage. See [`BacktestReport::with_slippage`].
+    pub slippage: Option<SlippageReport>,
+    /// The fingerprint of the configuration. See [`BacktestReport::with_fingerprint`].
+    pub fingerprint: Option<Fingerprint>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ConfidenceInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Configures the moving block bootstrap of the equity changes between records. Blocks of
+/// consecutive equity changes are resampled, rather than individual changes, to preserve their
+/// serial dependence.
+#[derive(Clone, Debug)]
+pub struct Bootstrap {
+    /// The number of consecutive equity changes in a block.
+    pub block_len: usize,
+    pub num_samples: usize,
+    /// The confidence level of the intervals, such as `0.95`.
+    pub confidence: f64,
+    /// The seed of the random number generator, to make the results reproducible.
+    pub seed: u64,
+}
+
+impl Default for Bootstrap {
+    fn default() -> Self {
+        Self {
+            block_len: 100,
+            num_samples: 1000,
+            confidence: 0.95,
+            seed: 0,
+        }
+    }
+}
+
+impl BacktestReport {
+    pub fn new(records: &[Record]) -> Self {
+        let (first, last) = match (records.first(), records.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => {
+                return Self {
+                    termination: Termination::Completed,
+                    start: 0,
+                    end: 0,
+                    pnl: 0.0,
+                    fee: 0.0,
+                    trade_num: 0,
+                    trade_amount: 0.0,
+                    sharpe: f64::NAN,
+                    sortino: f64::NAN,
+                    max_drawdown: 0.0,
+                    turnover: f64::NAN,
+                    pnl_per_trade: f64::NAN,
+                    avg_trade_qty: f64::NAN,
+                    avg_trade_amount: f64::NAN,
+                    sharpe_ci: None,
+                    max_drawdown_ci: None,
+                    slippage: None,
+                    fingerprint: None,
+                };
+            }
+        };
+        let pnl = last.equity - first.equity;
+        let trade_num = last.trade_num - first.trade_num;
+        let trade_qty = last.trade_qty - first.trade_qty;
+        let trade_amount = last.trade_amount - first.trade_amount;
+        let per_trade = |value: f64| {
+            if trade_num > 0 {
+                value / trade_num as f64
+            } else {
+                f64::NAN
+            }
+        };
+        Self {
+            termination: Termination::Completed,
+            start: first.timestamp,
+            end: last.timestamp,
+            pnl,
+            fee: last.fee - first.fee,
+            trade_num,
+            trade_amount,
+            sharpe: sharpe(records),
+            sortino: sortino(records),
+            max_drawdown: max_drawdown(records),
+            turnover: turnover(records),
+            pnl_per_trade: per_trade(pnl),
+            avg_trade_qty: per_trade(trade_qty),
+            avg_trade_amount: per_trade(trade_amount),
+            sharpe_ci: None,
+            max_drawdown_ci: None,
+            slippage: None,
+            fingerprint: None,
+        }
+    }
+
+    /// Sets how the backtest terminated, which is [`Termination::Completed`] by default.
+    pub fn with_termination(self, termination: Termination) -> Self {
+        Self {
+            termination,
+            ..self
+        }
+    }
+
+    /// Adds the execution slippage measured by [`SlippageAnalysis`].
+    pub fn with_slippage(self, slippage: SlippageReport) -> Self {
+        Self {
+            slippage: Some(slippage),
+            ..self
+        }
+    }
+
+    /// Adds the fingerprint of the configuration that produced the results. See
+    /// [`Recorder::fingerprint`].
+    pub fn with_fingerprint(self, fingerprint: Fingerprint) -> Self {
+        Self {
+            fingerprint: Some(fingerprint),
+            ..self
+        }
+    }
+
+    /// Adds the confidence intervals of the Sharpe ratio and the maximum drawdown, obtained by
+    /// the block bootstrap of the given records from which this report is produced.
+    pub fn with_bootstrap(self, records: &[Record], bootstrap: &Bootstrap) -> Self {
+        if records.len() < 3 || bootstrap.block_len == 0 || bootstrap.num_samples == 0 {
+            return self;
+        }
+        let returns: Vec<f64> = records
+            .windows(2)
+            .map(|w| w[1].equity - w[0].equity)
+            .collect();
+        let interval = (records[records.len() - 1].timestamp - records[0].timestamp) as f64
+            / returns.len() as f64;
+        let block_len = bootstrap.block_len.min(returns.len());
+
+        let mut rng = StdRng::seed_from_u64(bootstrap.seed);
+        let mut sample = Vec::with_capacity(returns.len());
+        let mut sharpes = Vec::with_capacity(bootstrap.num_samples);
+        let mut mdds = Vec::with_capacity(bootstrap.num_samples);
+        for _ in 0..bootstrap.num_samples {
+            sample.clear();
+            while sample.len() < returns.len() {
+                let start = rng.gen_range(0..=(returns.len() - block_len));
+                let len = block_len.min(returns.len() - sample.len());
+                sample.extend_from_slice(&returns[start..(start + len)]);
+            }
+            let sr = sharpe_of_returns(&sample, interval);
+            if !sr.is_nan() {
+                sharpes.push(sr);
+            }
+            mdds.push(max_drawdown_of_returns(&sample));
+        }
+
+        let alpha = (1.0 - bootstrap.confidence) / 2.0;
+        Self {
+            sharpe_ci: confidence_interval(sharpes, alpha),
+            max_drawdown_ci: confidence_interval(mdds, alpha),
+            ..self
+        }
+    }
+}
+
+fn confidence_interval(mut values: Vec<f64>, alpha: f64) -> Option<ConfidenceInterval> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let quantile = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    Some(ConfidenceInterval {
+        lower: quantile(alpha),
+        upper: quantile(1.0 - alpha),
+    })
+}
+
+/// Computes the annualized Sharpe ratio of the equity changes between records, assuming
+/// nanosecond timestamps.
+pub fn sharpe(records: &[Record]) -> f64 {
+    if records.len() < 3 {
+        return f64::NAN;
+    }
+    let returns: Vec<f64> = records
+        .windows(2)
+        .map(|w| w[1].equity - w[0].equity)
+        .collect();
+    let interval =
+        (records[records.len() - 1].timestamp - records[0].timestamp) as f64 / returns.len() as f64;
+    sharpe_of_returns(&returns, interval)
+}
+
+fn sharpe_of_returns(returns: &[f64], interval: f64) -> f64 {
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let var = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    if var == 0.0 || interval <= 0.0 {
+        return f64::NAN;
+    }
+    mean / var.sqrt() * (YEAR_NS / interval).sqrt()
+}
+
+/// Computes the annualized Sortino ratio of the equity changes between records, assuming
+/// nanosecond timestamps. The downside deviation is the root mean square of the losses, with the
+/// gains counted as zero.
+pub fn sortino(records: &[Record]) -> f64 {
+    if records.len() < 3 {
+        return f64::NAN;
+    }
+    let returns: Vec<f64> = records
+        .windows(2)
+        .map(|w| w[1].equity - w[0].equity)
+        .collect();
+    let n = returns.len() as f64;
+    let interval = (records[records.len() - 1].timestamp - records[0].timestamp) as f64 / n;
+    let mean = returns.iter().sum::<f64>() / n;
+    let downside = returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / n;
+    if downside == 0.0 || interval <= 0.0 {
+        return f64::NAN;
+    }
+    mean / downside.sqrt() * (YEAR_NS / interval).sqrt()
+}
+
+/// Computes the trading amount divided by the average absolute value of the position marked at
+/// the price.
+pub fn turnover(records: &[Record]) -> f64 {
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return f64::NAN;
+    };
+    let avg_value = records
+        .iter()
+        .map(|record| (record.position * record.price as f64).abs())
+        .sum::<f64>()
+        / records.len() as f64;
+    if avg_value == 0.0 {
+        return f64::NAN;
+    }
+    (last.trade_amount - first.trade_amount) / avg_value
+}
+
+/// The name of the entry that holds the [`Fingerprint`] in an `.npz` archive written by
+/// [`Recorder::write_npz`]. Since it is not an array, NumPy still loads the archive.
+pub const NPZ_FINGERPRINT_ENTRY: &str = "fingerprint.json";
+
+/// Writes the records into a CSV file with a header row.
+pub fn write_csv(path: &str, records: &[Record]) -> Result<(), IoError> {
+    write_csv_with_fingerprint(path, records, None)
+}
+
+fn write_csv_with_fingerprint(
+    path: &str,
+    records: &[Record],
+    fingerprint: Option<&Fingerprint>,
+) -> Result<(), IoError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    if let Some(fingerprint) = fingerprint {
+        writeln!(writer, "# fingerprint: {}", fingerprint.digest)?;
+        for (key, value) in &fingerprint.entries {
+            writeln!(writer, "# {key}: {value}")?;
+        }
+    }
+    writeln!(
+        writer,
+        "timestamp,price,position,balance,fee,trade_num,trade_qty,trade_amount,equity"
+    )?;
+    for r in records {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            r.timestamp,
+            r.price,
+            r.position,
+            r.balance,
+            r.fee,
+            r.trade_num,
+            r.trade_qty,
+            r.trade_amount,
+            r.equity
+        )?;
+    }
+    writer.flush()
+}
+
+/// Writes the records into an `.npz` archive as a structured array named `records`, which
+/// `numpy.load` reads with the fields named after those of [`Record`].
+pub fn write_npz(path: &str, records: &[Record]) -> Result<(), IoError> {
+    write_npz_with_fingerprint(path, records, None)
+}
+
+fn write_npz_with_fingerprint(
+    path: &str,
+    records: &[Record],
+    fingerprint: Option<&Fingerprint>,
+) -> Result<(), IoError> {
+    let mut header = format!(
+        "{{'descr': [('timestamp', '<i8'), ('price', '<f4'), ('position', '<f8'), \
+         ('balance', '<f8'), ('fee', '<f8'), ('trade_num', '<i4'), ('trade_qty', '<f8'), \
+         ('trade_amount', '<f8'), ('equity', '<f8')], 'fortran_order': False, 'shape': ({},), }}",
+        records.len()
+    );
+    // The total header length is padded to a multiple of 64 bytes and ends with a newline.
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut archive = zip::ZipWriter::new(BufWriter::new(File::create(path)?));
+    archive.start_file("records.npy", Default::default())?;
+    archive.write_all(b"\x93NUMPY\x01\x00")?;
+    archive.write_all(&(header.len() as u16).to_le_bytes())?;
+    archive.write_all(header.as_bytes())?;
+    for r in records {
+        archive.write_all(&r.timestamp.to_le_bytes())?;
+        archive.write_all(&r.price.to_le_bytes())?;
+        archive.write_all(&r.position.to_le_bytes())?;
+        archive.write_all(&r.balance.to_le_bytes())?;
+        archive.write_all(&r.fee.to_le_bytes())?;
+        archive.write_all(&r.trade_num.to_le_bytes())?;
+        archive.write_all(&r.trade_qty.to_le_bytes())?;
+        archive.write_all(&r.trade_amount.to_le_bytes())?;
+        archive.write_all(&r.equity.to_le_bytes())?;
+    }
+    if let Some(fingerprint) = fingerprint {
+        archive.start_file(NPZ_FINGERPRINT_ENTRY, Default::default())?;
+        archive.write_all(fingerprint.to_json().as_bytes())?;
+    }
+    archive.finish()?.flush()
+}
+
+/// Computes the maximum peak-to-trough decline of the equity.
+pub fn max_drawdown(records: &[Record]) -> f64 {
+    let mut peak = f64::MIN;
+    let mut mdd = 0.0;
+    for record in records {
+        peak = peak.max(record.equity);
+        mdd = f64::max(mdd, peak - record.equity);
+    }
+    mdd
+}
+
+fn max_drawdown_of_returns(returns: &[f64]) -> f64 {
+    let mut equity = 0.0;
+    let mut peak = 0.0;
+    let mut mdd = 0.0;
+    for r in returns {
+        equity += r;
+        peak = f64::max(peak, equity);
+        mdd = f64::max(mdd, peak - equity);
+    }
+    mdd
+}
+
+/// Per-tag statistics produced by [`Attribution`].
+#[derive(Clone, Debug)]
+pub struct TagReport {
+    pub tag: String,
+    pub num_orders: usize,
+    /// The number of orders filled at least partially.
+    pub num_filled_orders: usize,
+    pub fill_rate: f64,
+    pub filled_qty: f64,
+    /// The PnL of the fills attributed to the tag, including fees, with the resulting position
+    /// marked at the last mid price.
+    pub pnl: f64,
+    /// The average of the mid price change over the markout horizon after each fill, signed by
+    /// the fill's side. A negative value indicates adverse selection.
+    pub markout: f64,
+}
+
+#[derive(Default)]
+struct TagAcc {
+    num_orders: usize,
+    num_filled_orders: usize,
+    filled_qty: f64,
+    position: f64,
+    balance: f64,
+    fee: f64,
+    markout_sum: f64,
+    markout_num: usize,
+}
+
+struct TaggedOrder {
+    tag: usize,
+    leaves_qty: Option<f32>,
+    filled: bool,
+}
+
+struct PendingMarkout {
+    tag: usize,
+    timestamp: i64,
+    side: f64,
+    price: f32,
+}
+
+/// Attributes the fills to the user-supplied tags of the orders, such as a quote layer or a
+/// signal regime, so that strategy components can be evaluated individually from one backtest.
+///
+/// Tag an order by [`Attribution::tag`] after submitting it, and call [`Attribution::update`]
+/// after every elapse, before clearing inactive orders, so that no fill is missed.
+pub struct Attribution<AT>
+where
+    AT: AssetType,
+{
+    asset_type: AT,
+    markout_horizon: i64,
+    maker_fee: f64,
+    taker_fee: f64,
+    tags: Vec<String>,
+    tag_index: HashMap<String, usize>,
+    acc: Vec<TagAcc>,
+    orders: HashMap<i64, TaggedOrder>,
+    markouts: VecDeque<PendingMarkout>,
+    mid: f32,
+}
+
+impl<AT> Attribution<AT>
+where
+    AT: AssetType,
+{
+    /// `markout_horizon` is the time after a fill at which the mid price change is measured to
+    /// quantify adverse selection.
+    pub fn new(asset_type: AT, markout_horizon: i64) -> Self {
+        Self {
+            asset_type,
+            markout_horizon,
+            maker_fee: 0.0,
+            taker_fee: 0.0,
+            tags: Vec::new(),
+            tag_index: HashMap::new(),
+            acc: Vec::new(),
+            orders: HashMap::new(),
+            markouts: VecDeque::new(),
+            mid: f32::NAN,
+        }
+    }
+
+    /// Sets the maker and taker fee rates used to compute the PnL.
+    pub fn fee(self, maker_fee: f64, taker_fee: f64) -> Self {
+        Self {
+            maker_fee,
+            taker_fee,
+            ..self
+        }
+    }
+
+    pub fn tag(&mut self, order_id: i64, tag: &str) {
+        let tag = match self.tag_index.get(tag) {
+            Some(index) => *index,
+            None => {
+                self.tags.push(tag.to_string());
+                self.acc.push(TagAcc::default());
+                self.tag_index.insert(tag.to_string(), self.tags.len() - 1);
+                self.tags.len() - 1
+            }
+        };
+        self.acc[tag].num_orders += 1;
+        self.orders.insert(
+            order_id,
+            TaggedOrder {
+                tag,
+                leaves_qty: None,
+                filled: false,
+            },
+        );
+    }
+
+    pub fn update<I, Q, MD>(&mut self, hbt: &I, asset_no: usize)
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        let timestamp = hbt.current_timestamp();
+        let depth = hbt.depth(asset_no);
+        if depth.best_bid_tick() != MD::Tick::INVALID_MIN
+            && depth.best_ask_tick() != MD::Tick::INVALID_MAX
+        {
+            self.mid = (depth.best_bid() + depth.best_ask()) / 2.0;
+        }
+
+        while let Some(markout) = self.markouts.front() {
+            if markout.timestamp + self.markout_horizon > timestamp || self.mid.is_nan() {
+                break;
+            }
+            let acc = &mut self.acc[markout.tag];
+            acc.markout_sum += markout.side * (self.mid - markout.price) as f64;
+            acc.markout_num += 1;
+            self.markouts.pop_front();
+        }
+
+        let orders = hbt.orders(asset_no);
+        self.orders.retain(|order_id, tagged| {
+            let order = match orders.get(order_id) {
+                Some(order) => order,
+                None => return false,
+            };
+            let prev_leaves_qty = *tagged.leaves_qty.get_or_insert(order.qty);
+            if (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+                && order.leaves_qty < prev_leaves_qty
+            {
+                let qty = prev_leaves_qty - order.leaves_qty;
+                let price = order.exec_price();
+                let acc = &mut self.acc[tagged.tag];
+                if !tagged.filled {
+                    tagged.filled = true;
+                    acc.num_filled_orders += 1;
+                }
+                acc.filled_qty += qty as f64;
+                acc.position += qty as f64 * order.side.as_f64();
+                acc.balance += self.asset_type.balance_change(price, qty) * order.side.as_f64();
+                let fee_rate = if order.maker {
+                    self.maker_fee
+                } else {
+                    self.taker_fee
+                };
+                acc.fee += self.asset_type.fee(price, qty, fee_rate);
+                self.markouts.push_back(PendingMarkout {
+                    tag: tagged.tag,
+                    timestamp,
+                    side: order.side.as_f64(),
+                    price,
+                });
+                tagged.leaves_qty = Some(order.leaves_qty);
+            }
+            !order.terminal()
+        });
+    }
+
+    pub fn report(&self) -> Vec<TagReport> {
+        self.tags
+            .iter()
+            .zip(self.acc.iter())
+            .map(|(tag, acc)| TagReport {
+                tag: tag.clone(),
+                num_orders: acc.num_orders,
+                num_filled_orders: acc.num_filled_orders,
+                fill_rate: acc.num_filled_orders as f64 / acc.num_orders as f64,
+                filled_qty: acc.filled_qty,
+                pnl: self
+                    .asset_type
+                    .equity(self.mid, acc.balance, acc.position, acc.fee),
+                markout: acc.markout_sum / acc.markout_num as f64,
+            })
+            .collect()
+    }
+}
+
+/// Per-bucket statistics produced by [`LatencyAnalysis`].
+#[derive(Clone, Debug)]
+pub struct LatencyBucketReport {
+    /// The inclusive lower bound of the entry latency, or `None` if unbounded.
+    pub lower: Option<i64>,
+    /// The exclusive upper bound of the entry latency, or `None` if unbounded.
+    pub upper: Option<i64>,
+    /// The number of orders resolved into one of the outcomes.
+    pub num_orders: usize,
+    pub num_filled: usize,
+    pub num_expired: usize,
+    pub num_canceled: usize,
+    pub fill_rate: f64,
+    /// The fraction of the filled orders whose markout is negative.
+    pub adverse_rate: f64,
+    /// The average markout of the filled orders. See [`TagReport::markout`].
+    pub markout: f64,
+}
+
+#[derive(Default)]
+struct LatencyBucketAcc {
+    num_orders: usize,
+    num_filled: usize,
+    num_expired: usize,
+    num_canceled: usize,
+    num_adverse: usize,
+    markout_sum: f64,
+    markout_num: usize,
+}
+
+/// Correlates each order's modeled entry latency with its outcome, such as filled, expired, or
+/// adversely selected, to quantify how sensitive the strategy is to latency.
+///
+/// Call [`LatencyAnalysis::update`] after every elapse, before clearing inactive orders, so that
+/// no outcome is missed.
+pub struct LatencyAnalysis {
+    edges: Vec<i64>,
+    markout_horizon: i64,
+    acc: Vec<LatencyBucketAcc>,
+    // order_id -> (entry latency, filled)
+    orders: HashMap<i64, (Option<i64>, bool)>,
+    markouts: VecDeque<(usize, i64, f64, f32)>,
+    mid: f32,
+}
+
+impl LatencyAnalysis {
+    /// `edges` are the ascending boundaries of the entry latency buckets. `markout_horizon` is
+    /// the time after a fill at which the mid price change is measured.
+    pub fn new(edges: Vec<i64>, markout_horizon: i64) -> Self {
+        let acc = (0..=edges.len())
+            .map(|_| LatencyBucketAcc::default())
+            .collect();
+        Self {
+            edges,
+            markout_horizon,
+            acc,
+            orders: HashMap::new(),
+            markouts: VecDeque::new(),
+            mid: f32::NAN,
+        }
+    }
+
+    pub fn update<I, Q, MD>(&mut self, hbt: &I, asset_no: usize)
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        let timestamp = hbt.current_timestamp();
+        let depth = hbt.depth(asset_no);
+        if depth.best_bid_tick() != MD::Tick::INVALID_MIN
+            && depth.best_ask_tick() != MD::Tick::INVALID_MAX
+        {
+            self.mid = (depth.best_bid() + depth.best_ask()) / 2.0;
+        }
+
+        while let Some((bucket, fill_timestamp, side, price)) = self.markouts.front() {
+            if fill_timestamp + self.markout_horizon > timestamp || self.mid.is_nan() {
+                break;
+            }
+            let markout = side * (self.mid - price) as f64;
+            let acc = &mut self.acc[*bucket];
+            acc.markout_sum += markout;
+            acc.markout_num += 1;
+            if markout < 0.0 {
+                acc.num_adverse += 1;
+            }
+            self.markouts.pop_front();
+        }
+
+        for (order_id, order) in hbt.orders(asset_no).iter() {
+            let (latency, resolved) = self.orders.entry(*order_id).or_insert((None, false));
+            if *resolved {
+                continue;
+            }
+            if latency.is_none() {
+                *latency = hbt.order_entry_latency(asset_no, *order_id);
+            }
+            let latency = match latency {
+                Some(latency) => *latency,
+                None => continue,
+            };
+            let bucket = self.edges.partition_point(|edge| *edge <= latency);
+            let acc = &mut self.acc[bucket];
+            match order.status {
+                Status::Filled | Status::PartiallyFilled => {
+                    acc.num_orders += 1;
+                    acc.num_filled += 1;
+                    self.markouts.push_back((
+                        bucket,
+                        timestamp,
+                        order.side.as_f64(),
+                        order.exec_price(),
+                    ));
+                }
+                Status::Expired | Status::Rejected | Status::TooManyOpenOrders => {
+                    acc.num_orders += 1;
+                    acc.num_expired += 1;
+                }
+                Status::Canceled => {
+                    acc.num_orders += 1;
+                    acc.num_canceled += 1;
+                }
+                _ => continue,
+            }
+            *resolved = true;
+        }
+        let orders = hbt.orders(asset_no);
+        self.orders
+            .retain(|order_id, _| orders.contains_key(order_id));
+    }
+
+    pub fn report(&self) -> Vec<LatencyBucketReport> {
+        self.acc
+            .iter()
+            .enumerate()
+            .map(|(i, acc)| LatencyBucketReport {
+                lower: if i == 0 {
+                    None
+                } else {
+                    Some(self.edges[i - 1])
+                },
+                upper: self.edges.get(i).copied(),
+                num_orders: acc.num_orders,
+                num_filled: acc.num_filled,
+                num_expired: acc.num_expired,
+                num_canceled: acc.num_canceled,
+                fill_rate: acc.num_filled as f64 / acc.num_orders as f64,
+                adverse_rate: acc.num_adverse as f64 / acc.markout_num as f64,
+                markout: acc.markout_sum / acc.markout_num as f64,
+            })
+            .collect()
+    }
+}
+
+/// Measures the slippage of a fill price against a reference price, for reporting only; it
+/// doesn't affect the matching.
+pub trait SlippageModel {
+    /// Returns the slippage of the fill at `price` against `reference`, signed so that a positive
+    /// value is a cost, that is, a buy above or a sell below the reference.
+    fn slippage(&self, side: Side, price: f32, reference: f32) -> f64;
+}
+
+/// Measures the slippage in price units.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PriceSlippage;
+
+impl SlippageModel for PriceSlippage {
+    fn slippage(&self, side: Side, price: f32, reference: f32) -> f64 {
+        side.as_f64() * (price - reference) as f64
+    }
+}
+
+/// Measures the slippage in basis points of the reference price.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BpsSlippage;
+
+impl SlippageModel for BpsSlippage {
+    fn slippage(&self, side: Side, price: f32, reference: f32) -> f64 {
+        side.as_f64() * (price - reference) as f64 / reference as f64 * 10_000.0
+    }
+}
+
+/// A fill tagged with its slippage by [`SlippageAnalysis`].
+#[derive(Clone, Copy, Debug)]
+pub struct SlippageFill {
+    pub fill: Fill,
+    /// The mid price when the order was submitted.
+    pub arrival_mid: f32,
+    /// The price at which the strategy decided to trade, if it is given by
+    /// [`SlippageAnalysis::decision`].
+    pub decision_price: Option<f32>,
+    pub vs_arrival: f64,
+    pub vs_decision: Option<f64>,
+}
+
+/// The aggregated slippage produced by [`SlippageAnalysis`]. The slippages are averaged weighted
+/// by the fill quantity.
+#[derive(Clone, Copy, Debug)]
+pub struct SlippageReport {
+    pub num_fills: usize,
+    pub filled_qty: f64,
+    /// The average slippage versus the arrival mid. It is `NaN` if there is no fill.
+    pub vs_arrival: f64,
+    /// The average slippage versus the decision price, over the fills of the orders with a
+    /// decision price. It is `NaN` if there is no such fill.
+    pub vs_decision: f64,
+    /// The average slippage of the maker fills versus the arrival mid.
+    pub maker_vs_arrival: f64,
+    /// The average slippage of the taker fills versus the arrival mid.
+    pub taker_vs_arrival: f64,
+}
+
+struct SlippageOrder {
+    arrival_mid: f32,
+    decision_price: Option<f32>,
+    leaves_qty: f32,
+}
+
+/// Tags each fill with its slippage versus the arrival mid, which is the mid price when the order
+/// was submitted, and versus the decision price given by the strategy, as measured by the
+/// [`SlippageModel`], to standardize the execution-quality metrics across strategies.
+///
+/// Call [`SlippageAnalysis::update`] after every elapse, before clearing inactive orders, so that
+/// no fill is missed. Since the orders are picked up by the next update, the arrival mid is the
+/// mid price at the update preceding the submission.
+pub struct SlippageAnalysis<SM>
+where
+    SM: SlippageModel,
+{
+    model: SM,
+    decisions: HashMap<i64, f32>,
+    orders: HashMap<i64, SlippageOrder>,
+    fills: Vec<SlippageFill>,
+    mid: f32,
+}
+
+impl<SM> SlippageAnalysis<SM>
+where
+    SM: SlippageModel,
+{
+    pub fn new(model: SM) -> Self {
+        Self {
+            model,
+            decisions: HashMap::new(),
+            orders: HashMap::new(),
+            fills: Vec::new(),
+            mid: f32::NAN,
+        }
+    }
+
+    /// Sets the decision price of the order, such as the signal price at which the strategy
+    /// decided to trade. This should be called before the next update after the submission.
+    pub fn decision(&mut self, order_id: i64, price: f32) {
+        self.decisions.insert(order_id, price);
+    }
+
+    pub fn update<I, Q, MD>(&mut self, hbt: &I, asset_no: usize)
+    where
+        I: Interface<Q, MD>,
+        Q: Clone,
+        MD: MarketDepth,
+    {
+        let timestamp = hbt.current_timestamp();
+        let depth = hbt.depth(asset_no);
+        let mid = if depth.best_bid_tick() != MD::Tick::INVALID_MIN
+            && depth.best_ask_tick() != MD::Tick::INVALID_MAX
+        {
+            (depth.best_bid() + depth.best_ask()) / 2.0
+        } else {
+            self.mid
+        };
+        // The orders submitted since the previous update arrived at the previous mid.
+        let arrival_mid = if self.mid.is_nan() { mid } else { self.mid };
+
+        let orders = hbt.orders(asset_no);
+        for (order_id, order) in orders.iter() {
+            let tracked = match self.orders.entry(*order_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(SlippageOrder {
+                    arrival_mid,
+                    decision_price: self.decisions.remove(order_id),
+                    leaves_qty: order.qty,
+                }),
+            };
+            if (order.status == Status::Filled || order.status == Status::PartiallyFilled)
+                && order.leaves_qty < tracked.leaves_qty
+            {
+                let price = order.exec_price();
+                self.fills.push(SlippageFill {
+                    fill: Fill {
+                        timestamp,
+                        exch_timestamp: order.exch_timestamp,
+                        order_id: *order_id,
+                        side: order.side,
+                        price,
+                        qty: tracked.leaves_qty - order.leaves_qty,
+                        maker: order.maker,
+                    },
+                    arrival_mid: tracked.arrival_mid,
+                    decision_price: tracked.decision_price,
+                    vs_arrival: self.model.slippage(order.side, price, tracked.arrival_mid),
+                    vs_decision: tracked.decision_price.map(|decision_price| {
+                        self.model.slippage(order.side, price, decision_price)
+                    }),
+                });
+                tracked.leaves_qty = order.leaves_qty;
+            }
+        }
+        // The orders are kept until they are cleared so that the terminal orders aren't picked up
+        // again as new orders.
+        self.orders
+            .retain(|order_id, _| orders.contains_key(order_id));
+        self.decisions
+            .retain(|order_id, _| !orders.contains_key(order_id));
+        self.mid = mid;
+    }
+
+    pub fn fills(&self) -> &[SlippageFill] {
+        &self.fills
+    }
+
+    pub fn report(&self) -> SlippageReport {
+        let average = |filter: &dyn Fn(&SlippageFill) -> Option<f64>| {
+            let (sum, qty) = self
+                .fills
+                .iter()
+                .filter_map(|fill| filter(fill).map(|value| (value, fill.fill.qty as f64)))
+                .fold((0.0, 0.0), |(sum, total), (value, qty)| {
+                    (sum + value * qty, total + qty)
+                });
+            sum / qty
+        };
+        SlippageReport {
+            num_fills: self.fills.len(),
+            filled_qty: self.fills.iter().map(|fill| fill.fill.qty as f64).sum(),
+            vs_arrival: average(&|fill| Some(fill.vs_arrival)),
+            vs_decision: average(&|fill| fill.vs_decision),
+            maker_vs_arrival: average(&|fill| fill.fill.maker.then_some(fill.vs_arrival)),
+            taker_vs_arrival: average(&|fill| (!fill.fill.maker).then_some(fill.vs_arrival)),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use tracing::{debug, error};
+
+use crate::backtest::stats::Record;
+
+/// Streams the records to the connected TCP clients as JSON lines while the backtest runs, so
+/// that a dashboard can visualize a long run in progress. A client can request to abort the run
+/// by sending a line of `abort`, which the strategy can check by
+/// [`RecordStream::abort_requested`] or [`Recorder::abort_requested`].
+///
+/// A client that cannot keep up with the stream is disconnected, so as not to block the
+/// backtest.
+///
+/// [`Recorder::abort_requested`]: crate::backtest::stats::Recorder::abort_requested
+pub struct RecordStream {
+    listener: TcpListener,
+    clients: Vec<(TcpStream, Vec<u8>)>,
+    abort: bool,
+}
+
+impl RecordStream {
+    pub fn bind(addr: &str) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            abort: false,
+        })
+    }
+
+    pub fn publish(&mut self, record: &Record) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if let Err(error) = stream.set_nonblocking(true) {
+                        error!(?error, %addr, "Couldn't set the client stream to non-blocking.");
+                        continue;
+                    }
+                    debug!(%addr, "A record stream client is connected.");
+                    self.clients.push((stream, Vec::new()));
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    error!(?error, "Couldn't accept a record stream client.");
+                    break;
+                }
+            }
+        }
+
+        let mut line = match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(error) => {
+                error!(?error, "Couldn't serialize the record.");
+                return;
+            }
+        };
+        line.push('\n');
+
+        let mut abort = false;
+        self.clients.retain_mut(|(stream, buf)| {
+            let mut chunk = [0u8; 256];
+            loop {
+                match stream.read(&mut chunk) {
+                    Ok(0) => return false,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => return false,
+                }
+            }
+            while let Some(pos) = buf.iter().position(|b| *b == b'\n') {
+                let cmd: Vec<u8> = buf.drain(..=pos).collect();
+                if cmd.trim_ascii() == b"abort" {
+                    abort = true;
+                }
+            }
+            stream.write_all(line.as_bytes()).is_ok()
+        });
+        self.abort |= abort;
+    }
+
+    pub fn abort_requested(&self) -> bool {
+        self.abort
+    }
+}
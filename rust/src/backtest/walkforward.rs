@@ -0,0 +1,151 @@
+use std::io::Error as IoError;
+
+use crate::{
+    backtest::{
+        reader::read_data,
+        stats::{BacktestReport, Record},
+    },
+    ty::Event,
+};
+
+/// A train/test split of the data timeline. The train period is `start..split` and the test
+/// period is `split..end`, in exchange timestamps.
+#[derive(Clone, Debug)]
+pub struct Window {
+    pub start: i64,
+    pub split: i64,
+    pub end: i64,
+    /// The files that have the data of the train period, which can also have the data outside
+    /// it.
+    pub train: Vec<String>,
+    /// The files that have the data of the test period, which can also have the data outside it.
+    pub test: Vec<String>,
+}
+
+/// The result of a walk-forward analysis.
+#[derive(Clone, Debug)]
+pub struct WalkForwardResult<P> {
+    /// The calibrated parameters for each window.
+    pub params: Vec<P>,
+    /// The out-of-sample records of all test windows stitched into one series.
+    pub records: Vec<Record>,
+    pub report: BacktestReport,
+}
+
+/// Runs a walk-forward analysis over the data files. The timeline of the data, from its first to
+/// its last exchange timestamp, is split into windows of a `train` period followed by a `test`
+/// period, and the windows advance by `test` so that the test periods don't overlap. The
+/// periods are durations in the unit of the timestamps, and don't depend on how the data is
+/// split into files.
+pub struct WalkForward {
+    data: Vec<String>,
+    train: i64,
+    test: i64,
+}
+
+impl WalkForward {
+    pub fn new(data: Vec<String>, train: i64, test: i64) -> Self {
+        if train <= 0 || test <= 0 {
+            panic!();
+        }
+        Self { data, train, test }
+    }
+
+    /// Returns the windows, reading the data files to find the range of their exchange
+    /// timestamps.
+    pub fn windows(&self) -> Result<Vec<Window>, IoError> {
+        let mut spans = Vec::new();
+        for filepath in self.data.iter() {
+            let data = read_data::<Event>(filepath)?;
+            let span = (0..data.len()).map(|rn| data[rn].exch_ts).fold(
+                None,
+                |span: Option<(i64, i64)>, ts| match span {
+                    Some((first, last)) => Some((first.min(ts), last.max(ts))),
+                    None => Some((ts, ts)),
+                },
+            );
+            if let Some((first, last)) = span {
+                spans.push((filepath, first, last));
+            }
+        }
+        // The files that have any data in `start..end`.
+        let files = |start: i64, end: i64| -> Vec<String> {
+            spans
+                .iter()
+                .filter(|(_, first, last)| *first < end && *last >= start)
+                .map(|(filepath, _, _)| filepath.to_string())
+                .collect()
+        };
+
+        let mut windows = Vec::new();
+        let (Some(data_start), Some(data_end)) = (
+            spans.iter().map(|(_, first, _)| *first).min(),
+            spans.iter().map(|(_, _, last)| *last + 1).max(),
+        ) else {
+            return Ok(windows);
+        };
+        let mut start = data_start;
+        while start + self.train + self.test <= data_end {
+            let split = start + self.train;
+            let end = split + self.test;
+            windows.push(Window {
+                start,
+                split,
+                end,
+                train: files(start, split),
+                test: files(split, end),
+            });
+            start += self.test;
+        }
+        Ok(windows)
+    }
+
+    /// Invokes `calibrate` on the train period of each window, then runs the backtest by `run` on
+    /// the test period with the calibrated parameters. `run` returns the records of the
+    /// out-of-sample backtest, typically from [`Recorder`](crate::backtest::stats::Recorder).
+    ///
+    /// Since the files of a period can have the data outside it, `calibrate` and `run` should
+    /// use only the data within the period, such as by elapsing the backtest to the start of the
+    /// period before trading and stopping at its end. The records outside the test period are
+    /// dropped.
+    ///
+    /// Each test period is assumed to start flat, so the cumulative values of each window's
+    /// records are offset by the last values of the preceding window to stitch them.
+    pub fn run<P, E, C, R>(&self, mut calibrate: C, mut run: R) -> Result<WalkForwardResult<P>, E>
+    where
+        E: From<IoError>,
+        C: FnMut(&Window) -> Result<P, E>,
+        R: FnMut(&Window, &P) -> Result<Vec<Record>, E>,
+    {
+        let mut params = Vec::new();
+        let mut records: Vec<Record> = Vec::new();
+        for window in self.windows()? {
+            let p = calibrate(&window)?;
+            let window_records: Vec<Record> = run(&window, &p)?
+                .into_iter()
+                .filter(|record| (window.split..window.end).contains(&record.timestamp))
+                .collect();
+            params.push(p);
+
+            let offset = records.last().copied();
+            let base = window_records.first().copied();
+            for mut record in window_records {
+                if let (Some(offset), Some(base)) = (offset, base) {
+                    record.equity += offset.equity - base.equity;
+                    record.balance += offset.balance - base.balance;
+                    record.fee += offset.fee - base.fee;
+                    record.trade_num += offset.trade_num - base.trade_num;
+                    record.trade_qty += offset.trade_qty - base.trade_qty;
+                    record.trade_amount += offset.trade_amount - base.trade_amount;
+                }
+                records.push(record);
+            }
+        }
+        let report = BacktestReport::new(&records);
+        Ok(WalkForwardResult {
+            params,
+            records,
+            report,
+        })
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+type Levels = Vec<(f32, f32)>;
+
+/// The price levels of a symbol's book as forwarded to the bot. They are kept across the
+/// connections since the bot applies a REST depth snapshot as a diff, so the snapshot taken at a
+/// reconnection should also clear the levels that have vanished in the meantime.
+pub struct Book {
+    tick_size: f32,
+    // key: price in ticks, value: price
+    bids: HashMap<i64, f32>,
+    asks: HashMap<i64, f32>,
+}
+
+impl Book {
+    pub fn new(tick_size: f32) -> Self {
+        Self {
+            tick_size,
+            bids: Default::default(),
+            asks: Default::default(),
+        }
+    }
+
+    /// Applies a diff, in which a level with zero quantity is deleted.
+    pub fn apply_diff(&mut self, bids: &[(f32, f32)], asks: &[(f32, f32)]) {
+        update(&mut self.bids, bids, self.tick_size);
+        update(&mut self.asks, asks, self.tick_size);
+    }
+
+    /// Replaces the levels with the snapshot and returns the levels to be forwarded, which are
+    /// the snapshot's levels followed by the vanished levels with zero quantity, so that the
+    /// bot's depth ends up the same as the snapshot.
+    pub fn apply_snapshot(&mut self, mut bids: Levels, mut asks: Levels) -> (Levels, Levels) {
+        replace(&mut self.bids, &mut bids, self.tick_size);
+        replace(&mut self.asks, &mut asks, self.tick_size);
+        (bids, asks)
+    }
+}
+
+fn update(levels: &mut HashMap<i64, f32>, diff: &[(f32, f32)], tick_size: f32) {
+    for (px, qty) in diff {
+        let tick = (px / tick_size).round() as i64;
+        if *qty == 0.0 {
+            levels.remove(&tick);
+        } else {
+            levels.insert(tick, *px);
+        }
+    }
+}
+
+fn replace(levels: &mut HashMap<i64, f32>, snapshot: &mut Levels, tick_size: f32) {
+    let mut new_levels = HashMap::with_capacity(snapshot.len());
+    for (px, qty) in snapshot.iter() {
+        if *qty != 0.0 {
+            new_levels.insert((px / tick_size).round() as i64, *px);
+        }
+    }
+    for (tick, px) in levels.iter() {
+        if !new_levels.contains_key(tick) {
+            snapshot.push((*px, 0.0));
+        }
+    }
+    *levels = new_levels;
+}
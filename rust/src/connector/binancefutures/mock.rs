@@ -0,0 +1,882 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+    io::Error as IoError,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use hmac::{Hmac, KeyInit, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+use tokio_tungstenite::{
+    accept_hdr_async,
+    tungstenite::{
+        handshake::server::{Callback, ErrorResponse, Request, Response},
+        Message,
+    },
+};
+use tracing::{debug, error};
+
+#[derive(Clone, Debug)]
+pub struct MockOrder {
+    pub order_id: i64,
+    pub client_order_id: String,
+    pub symbol: String,
+    pub side: String,
+    pub order_type: String,
+    pub time_in_force: String,
+    pub price: f64,
+    pub qty: f64,
+    pub executed_qty: f64,
+    pub last_filled_qty: f64,
+    pub last_filled_price: f64,
+    pub status: String,
+    pub update_time: i64,
+}
+
+impl MockOrder {
+    fn active(&self) -> bool {
+        self.status == "NEW" || self.status == "PARTIALLY_FILLED"
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "clientOrderId": self.client_order_id,
+            "cumQty": self.executed_qty.to_string(),
+            "cumQuote": (self.executed_qty * self.price).to_string(),
+            "executedQty": self.executed_qty.to_string(),
+            "orderId": self.order_id,
+            "avgPrice": self.last_filled_price.to_string(),
+            "origQty": self.qty.to_string(),
+            "price": self.price.to_string(),
+            "reduceOnly": false,
+            "side": self.side,
+            "positionSide": "BOTH",
+            "status": self.status,
+            "stopPrice": "0",
+            "closePosition": false,
+            "symbol": self.symbol,
+            "timeInForce": self.time_in_force,
+            "type": self.order_type,
+            "origType": self.order_type,
+            "updateTime": self.update_time,
+            "workingType": "CONTRACT_PRICE",
+            "priceProtect": false,
+            "priceMatch": "NONE",
+            "selfTradePreventionMode": "NONE",
+            "goodTillDate": 0
+        })
+    }
+
+    fn to_stream_json(&self, execution_type: &str) -> Value {
+        json!({
+            "e": "ORDER_TRADE_UPDATE",
+            "E": self.update_time,
+            "T": self.update_time,
+            "o": {
+                "s": self.symbol,
+                "c": self.client_order_id,
+                "S": self.side,
+                "o": self.order_type,
+                "f": self.time_in_force,
+                "q": self.qty.to_string(),
+                "p": self.price.to_string(),
+                "ap": self.last_filled_price.to_string(),
+                "sp": "0",
+                "x": execution_type,
+                "X": self.status,
+                "i": self.order_id,
+                "l": self.last_filled_qty.to_string(),
+                "z": self.executed_qty.to_string(),
+                "L": self.last_filled_price.to_string(),
+                "T": self.update_time,
+                "t": 0
+            }
+        })
+    }
+}
+
+struct StreamClient {
+    tx: UnboundedSender<String>,
+    listen_key: String,
+    streams: HashSet<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    api_key: String,
+    secret: String,
+    listen_key: String,
+    next_order_id: i64,
+    update_id: i64,
+    orders: HashMap<String, MockOrder>,
+    positions: BTreeMap<String, f64>,
+    balances: BTreeMap<String, f64>,
+    bids: HashMap<String, BTreeMap<String, String>>,
+    asks: HashMap<String, BTreeMap<String, String>>,
+    reject_next: Option<(i64, String)>,
+    lose_next: bool,
+    // The counts reported by the rate limit headers: the request weight and the order count.
+    rate_limit_counts: (u32, u32),
+    // The `Retry-After` seconds of the HTTP 429 with which the next REST request is rejected.
+    too_many_requests_next: Option<u64>,
+    clients: Vec<StreamClient>,
+    requests: Vec<String>,
+}
+
+impl MockState {
+    fn publish(&mut self, stream: &str, data: Value) {
+        let text = json!({ "stream": stream, "data": data }).to_string();
+        self.clients.retain(|client| {
+            if client.streams.contains(stream) || client.listen_key == stream {
+                client.tx.send(text.clone()).is_ok()
+            } else {
+                !client.tx.is_closed()
+            }
+        });
+    }
+
+    fn publish_order(&mut self, order: MockOrder, execution_type: &str) {
+        let listen_key = self.listen_key.clone();
+        self.publish(&listen_key, order.to_stream_json(execution_type));
+    }
+
+    fn error(code: i64, msg: &str) -> (u16, Value) {
+        (400, json!({ "code": code, "msg": msg }))
+    }
+
+    fn handle(
+        &mut self,
+        method: &str,
+        path: &str,
+        params: &HashMap<String, String>,
+    ) -> (u16, Value) {
+        self.requests.push(format!("{} {}", method, path));
+        self.respond(method, path, params)
+    }
+
+    /// Handles a WebSocket API request by the REST endpoint of the same operation and returns the
+    /// response.
+    fn handle_ws_api(&mut self, text: &str) -> Value {
+        let req: Value = serde_json::from_str(text).unwrap_or_default();
+        let id = req["id"].clone();
+        let method = req["method"].as_str().unwrap_or_default().to_string();
+        let params: HashMap<String, String> = req["params"]
+            .as_object()
+            .map(|params| {
+                params
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = value.as_str().map(str::to_string);
+                        (key.clone(), value.unwrap_or_default())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.requests.push(format!("WS {}", method));
+        let (status, resp) = if !verify_ws_api(self, &params) {
+            (
+                401,
+                json!({ "code": -1022, "msg": "Signature for this request is not valid." }),
+            )
+        } else {
+            match method.as_str() {
+                "order.place" => self.respond("POST", "/fapi/v1/order", &params),
+                "order.cancel" => self.respond("DELETE", "/fapi/v1/order", &params),
+                "order.modify" => self.respond("PUT", "/fapi/v1/order", &params),
+                _ => Self::error(-1000, "Unknown method."),
+            }
+        };
+        let (weight, orders) = self.rate_limit_counts;
+        let rate_limit = |rate_limit_type: &str, interval: &str, interval_num: i64, count: u32| {
+            json!({
+                "rateLimitType": rate_limit_type,
+                "interval": interval,
+                "intervalNum": interval_num,
+                "count": count
+            })
+        };
+        let rate_limits = json!([
+            rate_limit("REQUEST_WEIGHT", "MINUTE", 1, weight),
+            rate_limit("ORDERS", "SECOND", 10, orders),
+            rate_limit("ORDERS", "MINUTE", 1, orders),
+        ]);
+        if status == 200 {
+            json!({ "id": id, "status": status, "result": resp, "rateLimits": rate_limits })
+        } else {
+            json!({ "id": id, "status": status, "error": resp, "rateLimits": rate_limits })
+        }
+    }
+
+    fn respond(
+        &mut self,
+        method: &str,
+        path: &str,
+        params: &HashMap<String, String>,
+    ) -> (u16, Value) {
+        // COIN-M futures serve the same endpoints under `/dapi/v1`.
+        let path = match path {
+            "/dapi/v1/positionRisk" => "/fapi/v2/positionRisk".to_string(),
+            "/dapi/v1/balance" => "/fapi/v2/balance".to_string(),
+            path => path.replacen("/dapi/", "/fapi/", 1),
+        };
+        let now = Utc::now().timestamp_millis();
+        let param = |name: &str| params.get(name).cloned().unwrap_or_default();
+        match (method, path.as_str()) {
+            ("POST", "/fapi/v1/listenKey") | ("PUT", "/fapi/v1/listenKey") => {
+                if self.listen_key.is_empty() {
+                    self.listen_key = format!("mocklistenkey{}", now);
+                }
+                (200, json!({ "listenKey": self.listen_key }))
+            }
+            ("POST", "/fapi/v1/order") => {
+                if let Some((code, msg)) = self.reject_next.take() {
+                    return Self::error(code, &msg);
+                }
+                let lose = std::mem::take(&mut self.lose_next);
+                let client_order_id = param("newClientOrderId");
+                if self.orders.contains_key(&client_order_id) {
+                    return Self::error(-4116, "ClientOrderId is duplicated.");
+                }
+                self.next_order_id += 1;
+                let order = MockOrder {
+                    order_id: self.next_order_id,
+                    client_order_id: client_order_id.clone(),
+                    symbol: param("symbol"),
+                    side: param("side"),
+                    order_type: param("type"),
+                    time_in_force: param("timeInForce"),
+                    price: param("price").parse().unwrap_or(0.0),
+                    qty: param("quantity").parse().unwrap_or(0.0),
+                    executed_qty: 0.0,
+                    last_filled_qty: 0.0,
+                    last_filled_price: 0.0,
+                    status: "NEW".to_string(),
+                    update_time: now,
+                };
+                self.orders.insert(client_order_id, order.clone());
+                self.publish_order(order.clone(), "NEW");
+                if lose {
+                    return Self::error(
+                        -1007,
+                        "Timeout waiting for response from backend server. Send status unknown; \
+                        execution status unknown.",
+                    );
+                }
+                (200, order.to_json())
+            }
+            ("PUT", "/fapi/v1/order") => {
+                if let Some((code, msg)) = self.reject_next.take() {
+                    return Self::error(code, &msg);
+                }
+                let order = match self.orders.get_mut(&param("origClientOrderId")) {
+                    Some(order) if order.active() => order,
+                    _ => return Self::error(-2013, "Order does not exist."),
+                };
+                order.price = param("price").parse().unwrap_or(order.price);
+                order.qty = param("quantity").parse().unwrap_or(order.qty);
+                order.update_time = now;
+                let order = order.clone();
+                self.publish_order(order.clone(), "AMENDMENT");
+                (200, order.to_json())
+            }
+            ("DELETE", "/fapi/v1/order") => {
+                if let Some((code, msg)) = self.reject_next.take() {
+                    return Self::error(code, &msg);
+                }
+                let order = match self.orders.get_mut(&param("origClientOrderId")) {
+                    Some(order) if order.active() => order,
+                    _ => return Self::error(-2011, "Unknown order sent."),
+                };
+                order.status = "CANCELED".to_string();
+                order.last_filled_qty = 0.0;
+                order.update_time = now;
+                let order = order.clone();
+                self.publish_order(order.clone(), "CANCELED");
+                (200, order.to_json())
+            }
+            ("GET", "/fapi/v1/order") => match self.orders.get(&param("origClientOrderId")) {
+                Some(order) => (200, order.to_json()),
+                None => Self::error(-2013, "Order does not exist."),
+            },
+            ("DELETE", "/fapi/v1/allOpenOrders") => {
+                let symbol = param("symbol");
+                let canceled: Vec<MockOrder> = self
+                    .orders
+                    .values_mut()
+                    .filter(|order| order.symbol == symbol && order.active())
+                    .map(|order| {
+                        order.status = "CANCELED".to_string();
+                        order.last_filled_qty = 0.0;
+                        order.update_time = now;
+                        order.clone()
+                    })
+                    .collect();
+                for order in canceled {
+                    self.publish_order(order, "CANCELED");
+                }
+                (
+                    200,
+                    json!({ "code": 200, "msg": "The operation of cancel all open order is done." }),
+                )
+            }
+            ("GET", "/fapi/v1/openOrders") => (
+                200,
+                Value::Array(
+                    self.orders
+                        .values()
+                        .filter(|order| order.active())
+                        .map(|order| order.to_json())
+                        .collect(),
+                ),
+            ),
+            ("GET", "/fapi/v2/positionRisk") => (
+                200,
+                Value::Array(
+                    self.positions
+                        .iter()
+                        .map(|(symbol, qty)| {
+                            json!({
+                                "entryPrice": "0",
+                                "breakEvenPrice": "0",
+                                "marginType": "cross",
+                                "isAutoAddMargin": "false",
+                                "leverage": "1",
+                                "liquidationPrice": "0",
+                                "markPrice": "0",
+                                "maxNotionalValue": "0",
+                                "positionAmt": qty.to_string(),
+                                "notional": "0",
+                                "isolatedWallet": "0",
+                                "symbol": symbol,
+                                "unRealizedProfit": "0",
+                                "positionSide": "BOTH",
+                                "updateTime": now
+                            })
+                        })
+                        .collect(),
+                ),
+            ),
+            ("GET", "/fapi/v2/balance") => (
+                200,
+                Value::Array(
+                    self.balances
+                        .iter()
+                        .map(|(asset, balance)| {
+                            json!({
+                                "accountAlias": "mock",
+                                "asset": asset,
+                                "balance": balance.to_string(),
+                                "crossWalletBalance": balance.to_string(),
+                                "crossUnPnl": "0",
+                                "availableBalance": balance.to_string(),
+                                "maxWithdrawAmount": balance.to_string(),
+                                "marginAvailable": true,
+                                "updateTime": now
+                            })
+                        })
+                        .collect(),
+                ),
+            ),
+            ("GET", "/fapi/v1/depth") => {
+                let symbol = param("symbol");
+                let levels = |book: Option<&BTreeMap<String, String>>, rev: bool| -> Vec<Value> {
+                    let mut levels: Vec<(f64, &String, &String)> = book
+                        .map(|book| {
+                            book.iter()
+                                .map(|(px, qty)| (px.parse().unwrap_or(0.0), px, qty))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    levels.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    if rev {
+                        levels.reverse();
+                    }
+                    levels
+                        .into_iter()
+                        .map(|(_, px, qty)| json!([px, qty]))
+                        .collect()
+                };
+                (
+                    200,
+                    json!({
+                        "lastUpdateId": self.update_id,
+                        "E": now,
+                        "T": now,
+                        "bids": levels(self.bids.get(&symbol), true),
+                        "asks": levels(self.asks.get(&symbol), false),
+                    }),
+                )
+            }
+            _ => Self::error(-1000, "Unsupported request."),
+        }
+    }
+}
+
+/// A local mock of the Binance Futures API subset used by the
+/// [`BinanceFutures`](crate::connector::binancefutures::BinanceFutures) connector, so that the
+/// connector logic, such as signing, reconnection, and order management, can be tested without
+/// reaching the testnet. It serves the REST API and the websocket streams on separate local
+/// ports.
+///
+/// Requests must carry the API key and a valid signature by the secret. Orders rest until the
+/// test fills them by [`fill`](Self::fill); market data is published by
+/// [`publish_depth`](Self::publish_depth) and [`publish_trade`](Self::publish_trade).
+pub struct MockServer {
+    api_addr: String,
+    stream_addr: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    /// Starts the mock server on local ports. This must be called within a Tokio runtime.
+    pub async fn start(api_key: &str, secret: &str) -> Result<Self, IoError> {
+        let state = Arc::new(Mutex::new(MockState {
+            api_key: api_key.to_string(),
+            secret: secret.to_string(),
+            ..Default::default()
+        }));
+
+        let api_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let stream_listener = TcpListener::bind("127.0.0.1:0").await?;
+        let api_addr = api_listener.local_addr()?.to_string();
+        let stream_addr = stream_listener.local_addr()?.to_string();
+
+        let state_ = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = api_listener.accept().await {
+                let state = state_.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_http(stream, state).await {
+                        debug!(?error, "The mock REST connection is closed.");
+                    }
+                });
+            }
+        });
+        let state_ = state.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = stream_listener.accept().await {
+                let state = state_.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_ws(stream, state).await {
+                        debug!(?error, "The mock stream connection is closed.");
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            api_addr,
+            stream_addr,
+            state,
+        })
+    }
+
+    /// Returns the REST API URL to pass to the connector.
+    pub fn api_url(&self) -> String {
+        format!("http://{}", self.api_addr)
+    }
+
+    /// Returns the stream URL to pass to the connector.
+    pub fn stream_url(&self) -> String {
+        format!("ws://{}/stream?streams=", self.stream_addr)
+    }
+
+    /// Returns the WebSocket API URL to pass to
+    /// [`BinanceFutures::ws_api`](crate::connector::binancefutures::BinanceFutures::ws_api), which
+    /// is served on the stream port.
+    pub fn ws_api_url(&self) -> String {
+        format!("ws://{}/ws-fapi/v1", self.stream_addr)
+    }
+
+    /// Publishes a depth update and applies it to the book served by the REST depth endpoint. A
+    /// level with zero quantity is removed.
+    pub fn publish_depth(&self, symbol: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now().timestamp_millis();
+        apply_levels(state.bids.entry(symbol.to_string()).or_default(), bids);
+        apply_levels(state.asks.entry(symbol.to_string()).or_default(), asks);
+        let prev_update_id = state.update_id;
+        state.update_id += 1;
+        let data = json!({
+            "e": "depthUpdate",
+            "E": now,
+            "T": now,
+            "s": symbol,
+            "U": state.update_id,
+            "u": state.update_id,
+            "pu": prev_update_id,
+            "b": bids,
+            "a": asks,
+        });
+        state.publish(&format!("{}@depth@0ms", symbol.to_lowercase()), data);
+    }
+
+    /// Applies a depth update to the book served by the REST depth endpoint without publishing
+    /// it, as if it's lost, so that the next update leaves a gap.
+    pub fn drop_depth(&self, symbol: &str, bids: &[(&str, &str)], asks: &[(&str, &str)]) {
+        let mut state = self.state.lock().unwrap();
+        apply_levels(state.bids.entry(symbol.to_string()).or_default(), bids);
+        apply_levels(state.asks.entry(symbol.to_string()).or_default(), asks);
+        state.update_id += 1;
+    }
+
+    pub fn publish_trade(&self, symbol: &str, price: &str, qty: &str, buyer_is_maker: bool) {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now().timestamp_millis();
+        let data = json!({
+            "e": "trade",
+            "E": now,
+            "T": now,
+            "s": symbol,
+            "t": 0,
+            "p": price,
+            "q": qty,
+            "X": "MARKET",
+            "m": buyer_is_maker,
+        });
+        state.publish(&format!("{}@trade", symbol.to_lowercase()), data);
+        // COIN-M futures subscribe to the aggregate trade stream.
+        let data = json!({
+            "e": "aggTrade",
+            "E": now,
+            "T": now,
+            "s": symbol,
+            "a": 0,
+            "p": price,
+            "q": qty,
+            "f": 0,
+            "l": 0,
+            "m": buyer_is_maker,
+        });
+        state.publish(&format!("{}@aggTrade", symbol.to_lowercase()), data);
+    }
+
+    /// Fills the order by the given quantity at the given price, and publishes the order update
+    /// and the account update through the user data stream. Returns `false` if the order is not
+    /// open.
+    pub fn fill(&self, client_order_id: &str, qty: f64, price: f64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Utc::now().timestamp_millis();
+        let order = match state.orders.get_mut(client_order_id) {
+            Some(order) if order.active() => order,
+            _ => return false,
+        };
+        let qty = qty.min(order.qty - order.executed_qty);
+        order.executed_qty += qty;
+        order.last_filled_qty = qty;
+        order.last_filled_price = price;
+        order.status = if order.executed_qty >= order.qty {
+            "FILLED".to_string()
+        } else {
+            "PARTIALLY_FILLED".to_string()
+        };
+        order.update_time = now;
+        let order = order.clone();
+
+        let position = state.positions.entry(order.symbol.clone()).or_insert(0.0);
+        if order.side == "BUY" {
+            *position += qty;
+        } else {
+            *position -= qty;
+        }
+        let position = *position;
+        state.publish_order(order.clone(), "TRADE");
+
+        let listen_key = state.listen_key.clone();
+        let data = json!({
+            "e": "ACCOUNT_UPDATE",
+            "E": now,
+            "T": now,
+            "a": {
+                "m": "ORDER",
+                "B": [],
+                "P": [{
+                    "s": order.symbol,
+                    "pa": position.to_string(),
+                    "ep": price.to_string(),
+                    "bep": price.to_string(),
+                    "cr": "0",
+                    "up": "0",
+                    "mt": "cross",
+                    "iw": "0",
+                    "ps": "BOTH"
+                }]
+            }
+        });
+        state.publish(&listen_key, data);
+        true
+    }
+
+    /// Sets the position reported by the position information endpoint.
+    pub fn set_position(&self, symbol: &str, qty: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .positions
+            .insert(symbol.to_string(), qty);
+    }
+
+    /// Sets the wallet balance of the margin asset reported by the balance endpoint.
+    pub fn set_balance(&self, asset: &str, balance: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .balances
+            .insert(asset.to_string(), balance);
+    }
+
+    /// Makes the next order request fail with the given error code and message, such as `-2019`
+    /// for insufficient margin.
+    pub fn reject_next(&self, code: i64, msg: &str) {
+        self.state.lock().unwrap().reject_next = Some((code, msg.to_string()));
+    }
+
+    /// Makes the mock process the next order submission but respond with `-1007`, the timeout
+    /// whose execution status is unknown, to simulate a lost response.
+    pub fn lose_next_response(&self) {
+        self.state.lock().unwrap().lose_next = true;
+    }
+
+    /// Sets the request weight and the order count that the mock reports by the
+    /// `X-MBX-USED-WEIGHT-1M`, `X-MBX-ORDER-COUNT-10S`, and `X-MBX-ORDER-COUNT-1M` headers of the
+    /// REST responses and the `rateLimits` of the WebSocket API responses.
+    pub fn set_rate_limit_counts(&self, weight: u32, orders: u32) {
+        self.state.lock().unwrap().rate_limit_counts = (weight, orders);
+    }
+
+    /// Makes the next REST request fail by HTTP 429 with the `Retry-After` header of the given
+    /// seconds, as Binance does once the rate limits are exceeded.
+    pub fn too_many_requests_next(&self, retry_after: u64) {
+        self.state.lock().unwrap().too_many_requests_next = Some(retry_after);
+    }
+
+    /// Closes all stream and WebSocket API connections to simulate a disconnection.
+    pub fn disconnect_streams(&self) {
+        self.state.lock().unwrap().clients.clear();
+    }
+
+    /// Returns the orders that the mock has received, including the inactive ones.
+    pub fn orders(&self) -> Vec<MockOrder> {
+        self.state
+            .lock()
+            .unwrap()
+            .orders
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the requests that the mock has served, as `{method} {path}`, or `WS {method}` for
+    /// the WebSocket API.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+fn apply_levels(book: &mut BTreeMap<String, String>, levels: &[(&str, &str)]) {
+    for (px, qty) in levels {
+        if qty.parse::<f64>().unwrap_or(0.0) == 0.0 {
+            book.remove(*px);
+        } else {
+            book.insert(px.to_string(), qty.to_string());
+        }
+    }
+}
+
+fn parse_params(s: &str, params: &mut HashMap<String, String>) {
+    for (key, value) in form_urlencoded::parse(s.as_bytes()) {
+        params.insert(key.into_owned(), value.into_owned());
+    }
+}
+
+fn verify(state: &MockState, api_key: Option<&str>, query: &str, body: &str) -> bool {
+    if api_key != Some(state.api_key.as_str()) {
+        return false;
+    }
+    let (signed, signature) = match query.rsplit_once("&signature=") {
+        Some(split) => split,
+        None => return false,
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(state.secret.as_bytes()).unwrap();
+    mac.update(signed.as_bytes());
+    mac.update(body.as_bytes());
+    let hash = mac.finalize().into_bytes();
+    let mut expected = String::with_capacity(hash.len() * 2);
+    for c in hash {
+        write!(&mut expected, "{:02x}", c).unwrap();
+    }
+    expected == signature
+}
+
+/// Verifies the signature of the WebSocket API request, which is computed over the parameters
+/// other than the signature sorted by their names.
+fn verify_ws_api(state: &MockState, params: &HashMap<String, String>) -> bool {
+    if params.get("apiKey") != Some(&state.api_key) {
+        return false;
+    }
+    let Some(signature) = params.get("signature") else {
+        return false;
+    };
+    let mut signed: Vec<(&String, &String)> = params
+        .iter()
+        .filter(|(key, _)| *key != "signature")
+        .collect();
+    signed.sort();
+    let signed = signed
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    let mut mac = Hmac::<Sha256>::new_from_slice(state.secret.as_bytes()).unwrap();
+    mac.update(signed.as_bytes());
+    let hash = mac.finalize().into_bytes();
+    let mut expected = String::with_capacity(hash.len() * 2);
+    for c in hash {
+        write!(&mut expected, "{:02x}", c).unwrap();
+    }
+    expected == *signature
+}
+
+async fn serve_http(stream: TcpStream, state: Arc<Mutex<MockState>>) -> Result<(), IoError> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await? == 0 {
+            return Ok(());
+        }
+        let mut tokens = request_line.split_whitespace();
+        let method = tokens.next().unwrap_or("").to_string();
+        let target = tokens.next().unwrap_or("").to_string();
+
+        let mut content_length = 0;
+        let mut api_key = None;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).await?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.parse().unwrap_or(0);
+                } else if name.eq_ignore_ascii_case("x-mbx-apikey") {
+                    api_key = Some(value.to_string());
+                }
+            }
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).await?;
+        let body = String::from_utf8_lossy(&body).to_string();
+
+        let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+        let mut headers = String::new();
+        let (status, resp) = {
+            let mut state = state.lock().unwrap();
+            let (weight, orders) = state.rate_limit_counts;
+            write!(
+                &mut headers,
+                "X-MBX-USED-WEIGHT-1M: {weight}\r\nX-MBX-ORDER-COUNT-10S: {orders}\r\n\
+                X-MBX-ORDER-COUNT-1M: {orders}\r\n"
+            )
+            .unwrap();
+            if let Some(retry_after) = state.too_many_requests_next.take() {
+                state.requests.push(format!("{} {}", method, path));
+                write!(&mut headers, "Retry-After: {retry_after}\r\n").unwrap();
+                (429, json!({ "code": -1003, "msg": "Too many requests." }))
+            } else if !verify(&state, api_key.as_deref(), query, &body) {
+                (
+                    401,
+                    json!({ "code": -1022, "msg": "Signature for this request is not valid." }),
+                )
+            } else {
+                let mut params = HashMap::new();
+                parse_params(query, &mut params);
+                parse_params(&body, &mut params);
+                state.handle(&method, path, &params)
+            }
+        };
+
+        let resp = resp.to_string();
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}\r\n",
+            status,
+            if status == 200 { "OK" } else { "Error" },
+            resp.len(),
+            headers
+        );
+        write.write_all(head.as_bytes()).await?;
+        write.write_all(resp.as_bytes()).await?;
+    }
+}
+
+struct UriCallback<'a>(&'a mut String);
+
+impl Callback for UriCallback<'_> {
+    fn on_request(self, req: &Request, resp: Response) -> Result<Response, ErrorResponse> {
+        *self.0 = req.uri().to_string();
+        Ok(resp)
+    }
+}
+
+async fn serve_ws(stream: TcpStream, state: Arc<Mutex<MockState>>) -> Result<(), anyhow::Error> {
+    let mut uri = String::new();
+    let ws = accept_hdr_async(stream, UriCallback(&mut uri)).await?;
+
+    // The WebSocket API is served on the same port under `/ws-fapi` or `/ws-dapi`, whose
+    // connections don't subscribe to any stream.
+    let ws_api = uri.starts_with("/ws-");
+    // The connector connects to `{stream_url}{listen_key}/{stream}/{stream}...`.
+    let streams = uri.split_once("streams=").map(|(_, s)| s).unwrap_or("");
+    let mut tokens = streams.split('/');
+    let listen_key = tokens.next().unwrap_or("").to_string();
+    let streams: HashSet<String> = tokens.map(|s| s.to_string()).collect();
+
+    let (tx, mut rx) = unbounded_channel();
+    state.lock().unwrap().clients.push(StreamClient {
+        tx,
+        listen_key,
+        streams,
+    });
+
+    let (mut write, mut read) = ws.split();
+    loop {
+        select! {
+            text = rx.recv() => {
+                match text {
+                    Some(text) => write.send(Message::Text(text)).await?,
+                    None => {
+                        write.send(Message::Close(None)).await?;
+                        return Ok(());
+                    }
+                }
+            }
+            message = read.next() => {
+                match message {
+                    Some(Ok(Message::Text(text))) if ws_api => {
+                        let resp = state.lock().unwrap().handle_ws_api(&text);
+                        write.send(Message::Text(resp.to_string())).await?;
+                    }
+                    Some(Ok(Message::Ping(data))) => write.send(Message::Pong(data)).await?,
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    Some(Ok(_)) => {}
+                    Some(Err(error)) => {
+                        error!(?error, "The mock stream connection failed.");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,30 +1,38 @@
+mod book;
+pub mod mock;
 mod msg;
 mod ordermanager;
 mod rest;
 mod ws;
+mod wsapi;
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{mpsc::Sender, Arc, Mutex},
-    time::Duration,
 };
 
 use reqwest::StatusCode;
 use thiserror::Error;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     connector::{
         binancefutures::{
-            ordermanager::{OrderManager, OrderMgr},
+            msg::rest::OrderResponse as RestOrderResponse,
+            ordermanager::{Method, OrderManager, OrderMgr, Request},
             rest::{BinanceFuturesClient, RequestError},
-            ws::connect,
+            ws::ReconnectManager,
+            wsapi::WsApi,
         },
+        ratelimit::{Cost, RateLimiter},
+        record::{SharedWsRecorder, WsRecorder},
+        retry::RetryPolicy,
         Connector,
+        OpenOrderPolicy,
     },
     get_precision,
     live::AssetInfo,
-    ty::{Error, ErrorType, LiveEvent, Order, OrderResponse, Position, Status},
+    ty::{AsStr, Error, ErrorType, LiveEvent, Order, OrderResponse, Status},
 };
 
 pub enum Endpoint {
@@ -35,10 +43,68 @@ pub enum Endpoint {
     Custom(String),
 }
 
+/// The Binance futures market, which determines the REST API paths, the streams, the quantity
+/// convention, and the margin asset of the symbols.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Market {
+    /// USDⓈ-M futures, served under `/fapi` by `https://fapi.binance.com` and
+    /// `wss://fstream.binance.com/stream?streams=`. The quantities are in the base asset, such as
+    /// BTC of `BTCUSDT`, and the symbols are settled in the quote asset.
+    #[default]
+    UsdMargined,
+    /// COIN-M futures, served under `/dapi` by `https://dapi.binance.com` and
+    /// `wss://dstream.binance.com/stream?streams=`. They are inverse contracts whose quantities
+    /// are in whole contracts of a fixed USD value, such as 100 USD for `BTCUSD_PERP` and 10 USD
+    /// for the others, and which are settled in the base asset, such as BTC. Backtest them with
+    /// [`InverseAsset`](crate::backtest::assettype::InverseAsset) of the contract value.
+    CoinMargined,
+}
+
+impl Market {
+    /// Returns the path of the REST endpoint, such as `/fapi/v1/order` or `/dapi/v1/order` for
+    /// `/v1/order`.
+    fn path(&self, path: &str) -> String {
+        match self {
+            Market::UsdMargined => format!("/fapi{path}"),
+            Market::CoinMargined => format!("/dapi{path}"),
+        }
+    }
+
+    /// Returns the path of the account endpoint, such as `positionRisk` and `balance`, which is
+    /// of v2 in USDⓈ-M futures and of v1 in COIN-M futures.
+    fn account_path(&self, endpoint: &str) -> String {
+        match self {
+            Market::UsdMargined => format!("/fapi/v2/{endpoint}"),
+            Market::CoinMargined => format!("/dapi/v1/{endpoint}"),
+        }
+    }
+
+    /// Formats the order quantity, which is a whole number of contracts in COIN-M futures.
+    fn format_qty(&self, qty: f32) -> String {
+        match self {
+            Market::UsdMargined => format!("{:.5}", qty),
+            Market::CoinMargined => format!("{:.0}", qty),
+        }
+    }
+
+    /// Returns the trade stream, which is the aggregate trade stream in COIN-M futures since
+    /// they don't provide the raw trade stream.
+    fn trade_stream(&self) -> &'static str {
+        match self {
+            Market::UsdMargined => "trade",
+            Market::CoinMargined => "aggTrade",
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum BinanceFuturesError {
     #[error("asset not found")]
     AssetNotFound,
+    #[error("{0} open orders found at startup")]
+    OpenOrdersFound(usize),
+    #[error("the book of {0} is out of sync")]
+    BookOutOfSync(String),
 }
 
 pub struct BinanceFutures {
@@ -50,6 +116,10 @@ pub struct BinanceFutures {
     inv_assets: HashMap<usize, AssetInfo>,
     orders: OrderMgr,
     client: BinanceFuturesClient,
+    recorder: Option<SharedWsRecorder>,
+    open_order_policy: OpenOrderPolicy,
+    market: Market,
+    ws_api: Option<WsApi>,
 }
 
 impl BinanceFutures {
@@ -64,8 +134,342 @@ impl BinanceFutures {
             inv_assets: Default::default(),
             orders: orders.clone(),
             client: BinanceFuturesClient::new(api_url, api_key, secret, orders),
+            recorder: None,
+            open_order_policy: Default::default(),
+            market: Default::default(),
+            ws_api: None,
+        }
+    }
+
+    /// Sets the market, which is [`Market::UsdMargined`] by default. The stream URL and the API
+    /// URL should be those of the market.
+    pub fn market(self, market: Market) -> Self {
+        Self {
+            client: self.client.market(market),
+            market,
+            ..self
         }
     }
+
+    /// Journals the order ids in use to the given file so that a restarted bot cannot reuse an
+    /// order id that may still exist on the exchange. The orders left by the previous run are
+    /// reconciled with the exchange at the first connection, and the bot receives how they have
+    /// ended, or their current states if they're still open and not canceled by the
+    /// [`OpenOrderPolicy`].
+    pub fn order_id_store(self, path: &str) -> Result<Self, std::io::Error> {
+        self.orders.lock().unwrap().open_store(path)?;
+        Ok(self)
+    }
+
+    /// Sets how the open orders found on the exchange at startup are handled. The default is
+    /// [`OpenOrderPolicy::Cancel`]. The policy also applies at every reconnection, except that
+    /// [`OpenOrderPolicy::Abort`] applies only to the first connection and the open orders found
+    /// at a reconnection are adopted instead. The orders that the connector already manages aren't
+    /// adopted but reconciled with those open on the exchange.
+    pub fn open_order_policy(self, open_order_policy: OpenOrderPolicy) -> Self {
+        Self {
+            open_order_policy,
+            ..self
+        }
+    }
+
+    /// Sets the policy of retrying the failed REST requests. By default, no request is retried.
+    /// See [`RetryPolicy`].
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            client: self.client.retry_policy(retry_policy),
+            ..self
+        }
+    }
+
+    /// Places, cancels, and modifies the orders over the WebSocket API at the given URL, such as
+    /// `wss://ws-fapi.binance.com/ws-fapi/v1`, or `wss://ws-dapi.binance.com/ws-dapi/v1` for
+    /// COIN-M futures, instead of REST. While the connection is down, the orders go through REST,
+    /// and the requests in flight when it's lost are resolved by querying their orders.
+    pub fn ws_api(self, url: &str) -> Self {
+        Self {
+            ws_api: Some(WsApi::new(url, &self.api_key, &self.secret)),
+            ..self
+        }
+    }
+
+    /// Returns the WebSocket API if it's connected and the request of the cost is within the rate
+    /// limits right away, otherwise the request goes through REST, which waits for the rate
+    /// limits or fails by them.
+    fn connected_ws_api(&self, cost: Cost) -> Option<&WsApi> {
+        self.ws_api
+            .as_ref()
+            .filter(|ws_api| ws_api.is_connected())
+            .filter(|_| self.client.get_rate_limiter().try_acquire(cost))
+    }
+
+    /// Limits the requests within the venue's API rate limits by the rate limiter, such as
+    /// [`RateLimiter::binance_futures`], and reports their usage to the bot. By default, the
+    /// requests aren't limited.
+    pub fn rate_limiter(self, rate_limiter: RateLimiter) -> Self {
+        Self {
+            client: self.client.rate_limiter(rate_limiter),
+            ..self
+        }
+    }
+
+    /// Records the raw websocket frames with the local timestamps to the given file, which can be
+    /// replayed later by [`ReplayServer`](crate::connector::record::ReplayServer).
+    pub fn record_stream(self, path: &str) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            recorder: Some(Arc::new(Mutex::new(WsRecorder::open(path)?))),
+            ..self
+        })
+    }
+}
+
+/// Returns the assets that are settled in the margin asset, which are those whose symbols end
+/// with it in USDⓈ-M futures, such as `BTCUSDT` for `USDT`, and those whose symbols start with it
+/// followed by `USD` in COIN-M futures, such as `BTCUSD_PERP` and `BTCUSD_250627` for `BTC`.
+fn settled_in<'a>(
+    assets: &'a HashMap<String, AssetInfo>,
+    margin_asset: &'a str,
+    market: Market,
+) -> impl Iterator<Item = &'a AssetInfo> + 'a {
+    assets
+        .iter()
+        .filter(move |(symbol, _)| match market {
+            Market::UsdMargined => symbol.ends_with(margin_asset),
+            Market::CoinMargined => symbol
+                .strip_prefix(margin_asset)
+                .is_some_and(|rest| rest.starts_with("USD_")),
+        })
+        .map(|(_, asset_info)| asset_info)
+}
+
+/// Applies the result of the order request, whether it's sent over REST or the WebSocket API, and
+/// sends the updated order and the error, if any.
+async fn handle_response(
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    request: Request,
+    result: Result<RestOrderResponse, RequestError>,
+    tx: &Sender<LiveEvent>,
+) {
+    let asset_no = request.asset_no;
+    let (order, error) = match (request.method, result) {
+        (Method::Place, Ok(resp)) => (
+            orders
+                .lock()
+                .unwrap()
+                .update_submit_success(request.order, resp),
+            None,
+        ),
+        (Method::Place, Err(error)) => (
+            orders.lock().unwrap().update_submit_fail(
+                request.order,
+                &error,
+                request.client_order_id,
+            ),
+            Some(error),
+        ),
+        (Method::Cancel, Ok(resp)) => (
+            orders
+                .lock()
+                .unwrap()
+                .update_cancel_success(request.order, resp),
+            None,
+        ),
+        (Method::Cancel, Err(RequestError::OrderError(-2011, _))) => {
+            // The order is unknown to the exchange; it could have already been filled or
+            // canceled, or it may never have existed. Queries the order to resolve its terminal
+            // status.
+            (
+                query_order(
+                    client,
+                    orders,
+                    &request.symbol,
+                    request.client_order_id,
+                    request.order,
+                )
+                .await,
+                None,
+            )
+        }
+        (Method::Cancel, Err(error)) => (
+            orders.lock().unwrap().update_cancel_fail(
+                request.order,
+                &error,
+                request.client_order_id,
+            ),
+            Some(error),
+        ),
+        (Method::Modify, Ok(resp)) => (
+            orders
+                .lock()
+                .unwrap()
+                .update_modify_success(request.order, resp),
+            None,
+        ),
+        (Method::Modify, Err(error)) => (
+            orders.lock().unwrap().update_modify_fail(
+                request.order,
+                &error,
+                request.client_order_id,
+            ),
+            Some(error),
+        ),
+    };
+    if let Some(order) = order {
+        tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+            .unwrap();
+    }
+    if let Some(error) = error {
+        tx.send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
+            .unwrap();
+    }
+}
+
+/// Queries the order and returns the order updated by its status.
+async fn query_order(
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    symbol: &str,
+    client_order_id: String,
+    order: Order<()>,
+) -> Option<Order<()>> {
+    match client.query_order(&client_order_id, symbol).await {
+        Ok(resp) => orders.lock().unwrap().update_query_success(order, resp),
+        Err(error) => orders
+            .lock()
+            .unwrap()
+            .update_query_fail(order, &error, client_order_id),
+    }
+}
+
+/// Reconciles the orders that the connector manages with those open on the exchange, since they
+/// may have been filled or canceled while the user data stream was disconnected. The open orders
+/// are applied as the stream updates are, and the others are queried for how they ended.
+async fn reconcile_orders(
+    client: &BinanceFuturesClient,
+    assets: &HashMap<String, AssetInfo>,
+    prefix: &str,
+    orders: &OrderMgr,
+    ev_tx: &Sender<LiveEvent>,
+) -> Result<(), RequestError> {
+    let mut open = HashSet::new();
+    for open_order in client.get_current_all_open_orders(assets, prefix).await? {
+        open.insert(open_order.client_order_id.clone());
+        let Some(order) = open_order.order else {
+            continue;
+        };
+        if !orders.lock().unwrap().is_open(&open_order.client_order_id) {
+            continue;
+        }
+        let order = orders
+            .lock()
+            .unwrap()
+            .update_from_ws(open_order.client_order_id, order);
+        if let Some(order) = order {
+            ev_tx
+                .send(LiveEvent::Order(OrderResponse {
+                    asset_no: open_order.asset_no,
+                    order,
+                }))
+                .unwrap();
+        }
+    }
+
+    let closed: Vec<_> = orders
+        .lock()
+        .unwrap()
+        .open_orders()
+        .into_iter()
+        .filter(|(_, client_order_id, _)| !open.contains(client_order_id))
+        .collect();
+    for (symbol, client_order_id, mut order) in closed {
+        let Some(asset_info) = assets.get(&symbol) else {
+            continue;
+        };
+        // The tick size of the order restored from the order id store is unknown.
+        order.tick_size = asset_info.tick_size;
+        info!(%client_order_id, "The order is no longer open. Queries how it ended.");
+        if let Some(order) = query_order(client, orders, &symbol, client_order_id, order).await {
+            ev_tx
+                .send(LiveEvent::Order(OrderResponse {
+                    asset_no: asset_info.asset_no,
+                    order,
+                }))
+                .unwrap();
+        }
+    }
+    Ok(())
+}
+
+/// Handles the open orders found on the exchange by the policy. Returns `false` if the startup
+/// should be aborted.
+async fn handle_open_orders(
+    client: &BinanceFuturesClient,
+    assets: &HashMap<String, AssetInfo>,
+    prefix: &str,
+    orders: &OrderMgr,
+    policy: OpenOrderPolicy,
+    ev_tx: &Sender<LiveEvent>,
+) -> Result<bool, RequestError> {
+    match policy {
+        OpenOrderPolicy::Cancel => {
+            for symbol in assets.keys() {
+                client.cancel_all_orders(symbol).await?;
+            }
+        }
+        OpenOrderPolicy::Adopt => {
+            for open_order in client.get_current_all_open_orders(assets, prefix).await? {
+                let Some(order) = open_order.order else {
+                    warn!(
+                        symbol = %open_order.symbol,
+                        client_order_id = %open_order.client_order_id,
+                        "Leaves the open order that isn't created by the bot."
+                    );
+                    continue;
+                };
+                if orders.lock().unwrap().is_open(&open_order.client_order_id) {
+                    // The order is already managed, which is reconciled instead.
+                    continue;
+                }
+                let adopted = orders.lock().unwrap().adopt(
+                    &open_order.symbol,
+                    open_order.client_order_id.clone(),
+                    order.clone(),
+                );
+                if adopted {
+                    info!(?order, "Adopted the open order.");
+                    ev_tx
+                        .send(LiveEvent::Order(OrderResponse {
+                            asset_no: open_order.asset_no,
+                            order,
+                        }))
+                        .unwrap();
+                } else {
+                    warn!(
+                        client_order_id = %open_order.client_order_id,
+                        "Couldn't adopt the open order since its order id is already in use."
+                    );
+                }
+            }
+        }
+        OpenOrderPolicy::Abort => {
+            let open_orders = client.get_current_all_open_orders(assets, prefix).await?;
+            if !open_orders.is_empty() {
+                error!(
+                    ?open_orders,
+                    "Aborts the startup since there are open orders."
+                );
+                ev_tx
+                    .send(LiveEvent::Error(Error::with(
+                        ErrorType::CriticalConnectionError,
+                        BinanceFuturesError::OpenOrdersFound(open_orders.len()),
+                    )))
+                    .unwrap();
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
 }
 
 impl Connector for BinanceFutures {
@@ -88,104 +492,19 @@ impl Connector for BinanceFutures {
     }
 
     fn run(&mut self, ev_tx: Sender<LiveEvent>) -> Result<(), anyhow::Error> {
-        let assets = self.assets.clone();
-        let base_url = self.url.clone();
-        let prefix = self.prefix.clone();
-        let client = self.client.clone();
-        let orders = self.orders.clone();
-        let mut error_count = 0;
-
-        let _ = tokio::spawn(async move {
-            'connection: loop {
-                if error_count > 0 {
-                    tokio::time::sleep(Duration::from_secs(5)).await;
-                }
-
-                // Cancel all orders before connecting to the stream in order to start with the
-                // clean state.
-                for symbol in assets.keys() {
-                    if let Err(error) = client.cancel_all_orders(symbol).await {
-                        error!(?error, %symbol, "Couldn't cancel all open orders.");
-                        ev_tx
-                            .send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
-                            .unwrap();
-                        error_count += 1;
-                        continue 'connection;
-                    }
-                }
-
-                // Fetches the initial states such as positions and open orders.
-                match client.get_position_information().await {
-                    Ok(positions) => {
-                        positions.into_iter().for_each(|position| {
-                            assets.get(&position.symbol).map(|asset_info| {
-                                ev_tx
-                                    .send(LiveEvent::Position(Position {
-                                        asset_no: asset_info.asset_no,
-                                        symbol: position.symbol,
-                                        qty: position.position_amount,
-                                    }))
-                                    .unwrap();
-                            });
-                        });
-                    }
-                    Err(error) => {
-                        error!(?error, "Couldn't get position information.");
-                        error_count += 1;
-                        continue 'connection;
-                    }
-                }
-
-                let listen_key = match client.start_user_data_stream().await {
-                    Ok(listen_key) => listen_key,
-                    Err(error) => {
-                        error!(?error, "Couldn't start user data stream.");
-                        // 1000 indicates user data stream starting error.
-                        ev_tx
-                            .send(LiveEvent::Error(Error::with(ErrorType::Custom(1000), error)))
-                            .unwrap();
-                        continue 'connection;
-                    }
-                };
+        self.client.get_rate_limiter().set_listener(
+            ev_tx.clone(),
+            self.assets
+                .values()
+                .map(|asset_info| asset_info.asset_no)
+                .collect(),
+        );
+        if let Some(ws_api) = self.ws_api.as_mut() {
+            ws_api.run(self.client.clone(), self.orders.clone(), ev_tx.clone());
+        }
 
-                // Prepares a URL that connects streams
-                let streams: Vec<String> = assets
-                    .keys()
-                    .map(|symbol| {
-                        format!(
-                            "{}@depth@0ms/{}@trade",
-                            symbol.to_lowercase(),
-                            symbol.to_lowercase()
-                        )
-                    })
-                    .collect();
-                let url = format!("{}{}/{}", &base_url, listen_key, streams.join("/"));
-
-                if let Err(error) = connect(
-                    &url,
-                    ev_tx.clone(),
-                    assets.clone(),
-                    &prefix,
-                    orders.clone(),
-                    client.clone(),
-                )
-                .await
-                {
-                    error!(?error, "A connection error occurred.");
-                    ev_tx
-                        .send(LiveEvent::Error(Error::with(
-                            ErrorType::ConnectionInterrupted,
-                            error,
-                        )))
-                        .unwrap();
-                } else {
-                    ev_tx
-                        .send(LiveEvent::Error(Error::new(ErrorType::ConnectionInterrupted)))
-                        .unwrap();
-                }
-                error_count += 1;
-            }
-        });
+        let manager = ReconnectManager::new(self, ev_tx);
+        let _ = tokio::spawn(manager.run());
         Ok(())
     }
 
@@ -199,65 +518,62 @@ impl Connector for BinanceFutures {
             .inv_assets
             .get(&asset_no)
             .ok_or(BinanceFuturesError::AssetNotFound)?;
-        let symbol = asset_info.symbol.clone();
+        let client_order_id = self
+            .orders
+            .lock()
+            .unwrap()
+            .prepare_client_order_id(&asset_info.symbol, order.clone());
+        let Some(client_order_id) = client_order_id else {
+            warn!(
+                ?order,
+                "The order id is already in use, or coincidentally, creates a duplicated \
+                client order id. This order request will be expired."
+            );
+            order.req = Status::None;
+            order.status = Status::Expired;
+            tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                .unwrap();
+            return Ok(());
+        };
+        let price = order.price_tick as f32 * order.tick_size;
+        let price_prec = get_precision(order.tick_size);
+        let request = Request {
+            method: Method::Place,
+            asset_no,
+            symbol: asset_info.symbol.clone(),
+            client_order_id,
+            order,
+        };
+        if let Some(ws_api) = self.connected_ws_api(Cost::new(0, 1)) {
+            let order = &request.order;
+            let params = vec![
+                ("newClientOrderId", request.client_order_id.clone()),
+                ("symbol", request.symbol.clone()),
+                ("side", order.side.as_str().to_string()),
+                ("price", format!("{:.prec$}", price, prec = price_prec)),
+                ("quantity", self.market.format_qty(order.qty)),
+                ("type", order.order_type.as_str().to_string()),
+                ("timeInForce", order.time_in_force.as_str().to_string()),
+            ];
+            return ws_api.send(&self.orders, request, params);
+        }
         let client = self.client.clone();
         let orders = self.orders.clone();
         tokio::spawn(async move {
-            let client_order_id = orders
-                .lock()
-                .unwrap()
-                .prepare_client_order_id(order.clone());
-
-            match client_order_id {
-                Some(client_order_id) => {
-                    match client
-                        .submit_order(
-                            &client_order_id,
-                            &symbol,
-                            order.side,
-                            order.price_tick as f32 * order.tick_size,
-                            get_precision(order.tick_size),
-                            order.qty,
-                            order.order_type,
-                            order.time_in_force,
-                        )
-                        .await
-                    {
-                        Ok(resp) => {
-                            let order = orders.lock().unwrap().update_submit_success(order, resp);
-                            if let Some(order) = order {
-                                tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
-                                    .unwrap();
-                            }
-                        }
-                        Err(error) => {
-                            let order = orders.lock().unwrap().update_submit_fail(
-                                order,
-                                &error,
-                                client_order_id,
-                            );
-                            if let Some(order) = order {
-                                tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
-                                    .unwrap();
-                            }
-
-                            tx.send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
-                                .unwrap();
-                        }
-                    }
-                }
-                None => {
-                    warn!(
-                        ?order,
-                        "Coincidentally, creates a duplicated client order id. \
-                        This order request will be expired."
-                    );
-                    order.req = Status::None;
-                    order.status = Status::Expired;
-                    tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
-                        .unwrap();
-                }
-            }
+            let order = &request.order;
+            let result = client
+                .submit_order(
+                    &request.client_order_id,
+                    &request.symbol,
+                    order.side,
+                    price,
+                    price_prec,
+                    order.qty,
+                    order.order_type,
+                    order.time_in_force,
+                )
+                .await;
+            handle_response(&client, &orders, request, result, &tx).await;
         });
         Ok(())
     }
@@ -265,57 +581,112 @@ impl Connector for BinanceFutures {
     fn cancel(
         &self,
         asset_no: usize,
-        mut order: Order<()>,
+        order: Order<()>,
         tx: Sender<LiveEvent>,
     ) -> Result<(), anyhow::Error> {
         let asset_info = self
             .inv_assets
             .get(&asset_no)
             .ok_or(BinanceFuturesError::AssetNotFound)?;
-        let symbol = asset_info.symbol.clone();
+        let Some(client_order_id) = self
+            .orders
+            .lock()
+            .unwrap()
+            .get_client_order_id(order.order_id)
+        else {
+            debug!(
+                order_id = order.order_id,
+                "client_order_id corresponding to order_id is not found; \
+                this may be due to the order already being canceled or filled."
+            );
+            // order.req = Status::None;
+            // order.status = Status::Expired;
+            // tx.send(Event::Order(OrderResponse { asset_no, order }))
+            //     .unwrap();
+            return Ok(());
+        };
+        let request = Request {
+            method: Method::Cancel,
+            asset_no,
+            symbol: asset_info.symbol.clone(),
+            client_order_id,
+            order,
+        };
+        if let Some(ws_api) = self.connected_ws_api(Cost::new(1, 0)) {
+            let params = vec![
+                ("symbol", request.symbol.clone()),
+                ("origClientOrderId", request.client_order_id.clone()),
+            ];
+            return ws_api.send(&self.orders, request, params);
+        }
         let client = self.client.clone();
         let orders = self.orders.clone();
         tokio::spawn(async move {
-            let client_order_id = orders.lock().unwrap().get_client_order_id(order.order_id);
-
-            match client_order_id {
-                Some(client_order_id) => {
-                    match client.cancel_order(&client_order_id, &symbol).await {
-                        Ok(resp) => {
-                            let order = orders.lock().unwrap().update_cancel_success(order, resp);
-                            if let Some(order) = order {
-                                tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
-                                    .unwrap();
-                            }
-                        }
-                        Err(error) => {
-                            let order = orders.lock().unwrap().update_cancel_fail(
-                                order,
-                                &error,
-                                client_order_id,
-                            );
-                            if let Some(order) = order {
-                                tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
-                                    .unwrap();
-                            }
-
-                            tx.send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
-                                .unwrap();
-                        }
-                    }
-                }
-                None => {
-                    debug!(
-                        order_id = order.order_id,
-                        "client_order_id corresponding to order_id is not found; \
-                        this may be due to the order already being canceled or filled."
-                    );
-                    // order.req = Status::None;
-                    // order.status = Status::Expired;
-                    // tx.send(Event::Order(OrderResponse { asset_no, order }))
-                    //     .unwrap();
-                }
-            }
+            let result = client
+                .cancel_order(&request.client_order_id, &request.symbol)
+                .await;
+            handle_response(&client, &orders, request, result, &tx).await;
+        });
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self
+            .inv_assets
+            .get(&asset_no)
+            .ok_or(BinanceFuturesError::AssetNotFound)?;
+        let Some(client_order_id) = self
+            .orders
+            .lock()
+            .unwrap()
+            .get_client_order_id(order.order_id)
+        else {
+            debug!(
+                order_id = order.order_id,
+                "client_order_id corresponding to order_id is not found; \
+                this may be due to the order already being canceled or filled."
+            );
+            return Ok(());
+        };
+        let price = order.price_tick as f32 * order.tick_size;
+        let qty = order.qty;
+        let price_prec = get_precision(order.tick_size);
+        let request = Request {
+            method: Method::Modify,
+            asset_no,
+            symbol: asset_info.symbol.clone(),
+            client_order_id,
+            order,
+        };
+        if let Some(ws_api) = self.connected_ws_api(Cost::new(1, 1)) {
+            let params = vec![
+                ("symbol", request.symbol.clone()),
+                ("origClientOrderId", request.client_order_id.clone()),
+                ("side", request.order.side.as_str().to_string()),
+                ("price", format!("{:.prec$}", price, prec = price_prec)),
+                ("quantity", self.market.format_qty(qty)),
+            ];
+            return ws_api.send(&self.orders, request, params);
+        }
+        let client = self.client.clone();
+        let orders = self.orders.clone();
+        tokio::spawn(async move {
+            let result = client
+                .modify_order(
+                    &request.client_order_id,
+                    &request.symbol,
+                    request.order.side,
+                    price,
+                    price_prec,
+                    qty,
+                )
+                .await;
+            handle_response(&client, &orders, request, result, &tx).await;
         });
         Ok(())
     }
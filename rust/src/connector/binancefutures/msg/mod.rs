@@ -8,6 +8,7 @@ use crate::ty::{OrdType, Side, Status, TimeInForce};
 
 pub mod rest;
 pub mod stream;
+pub mod wsapi;
 
 fn from_str_to_f32<'de, D>(deserializer: D) -> Result<f32, D::Error>
 where
@@ -58,12 +59,12 @@ where
         "PARTIALLY_FILLED" => Ok(Status::PartiallyFilled),
         "FILLED" => Ok(Status::Filled),
         "CANCELED" => Ok(Status::Canceled),
-        // "REJECTED" => Ok(Status::Rejected),
+        "REJECTED" => Ok(Status::Rejected),
         "EXPIRED" => Ok(Status::Expired),
         // "EXPIRED_IN_MATCH" => Ok(Status::ExpiredInMatch),
         s => Err(Error::invalid_value(
             Unexpected::Other(s),
-            &"NEW,PARTIALLY_FILLED,FILLED,CANCELED,EXPIRED",
+            &"NEW,PARTIALLY_FILLED,FILLED,CANCELED,REJECTED,EXPIRED",
         )),
     }
 }
@@ -76,12 +77,15 @@ where
     match s {
         "LIMIT" => Ok(OrdType::Limit),
         "MARKET" => Ok(OrdType::Market),
-        // "STOP" => Ok(OrdType::StopLimit),
-        // "TAKE_PROFIT" => Ok(OrdType::TakeProfitLimit),
-        // "STOP_MARKET" => Ok(OrdType::StopMarket),
-        // "TAKE_PROFIT_MARKET" => Ok(OrdType::TakeProfitMarket),
+        "STOP" => Ok(OrdType::StopLimit),
+        "TAKE_PROFIT" => Ok(OrdType::TakeProfitLimit),
+        "STOP_MARKET" => Ok(OrdType::StopMarket),
+        "TAKE_PROFIT_MARKET" => Ok(OrdType::TakeProfitMarket),
         // "TRAILING_STOP_MARKET" => Ok(OrdType::TrailingStopMarket),
-        s => Err(Error::invalid_value(Unexpected::Other(s), &"LIMIT,MARKET")),
+        s => Err(Error::invalid_value(
+            Unexpected::Other(s),
+            &"LIMIT,MARKET,STOP,TAKE_PROFIT,STOP_MARKET,TAKE_PROFIT_MARKET",
+        )),
     }
 }
 
@@ -22,7 +22,9 @@ pub enum OrderResponseResult {
 pub struct OrderResponse {
     #[serde(rename = "clientOrderId")]
     pub client_order_id: String,
+    /// New Order, Cancel Order, and Modify Order responses only field
     #[serde(rename = "cumQty")]
+    #[serde(default)]
     #[serde(deserialize_with = "from_str_to_f32")]
     pub cum_qty: f32,
     /// New Order and Cancel Order responses only field
@@ -92,11 +94,17 @@ pub struct OrderResponse {
     pub working_type: String,
     #[serde(rename = "priceProtect")]
     pub price_protect: bool,
+    /// Absent in COIN-M futures.
     #[serde(rename = "priceMatch")]
+    #[serde(default)]
     pub price_match: String,
+    /// Absent in COIN-M futures.
     #[serde(rename = "selfTradePreventionMode")]
+    #[serde(default)]
     pub self_trade_prevention_mode: String,
+    /// Absent in COIN-M futures.
     #[serde(rename = "goodTillDate")]
+    #[serde(default)]
     pub good_till_date: i64,
 }
 
@@ -126,12 +134,15 @@ pub struct PositionInformationV2 {
     #[serde(rename = "markPrice")]
     #[serde(deserialize_with = "from_str_to_f32")]
     pub mark_price: f32,
+    /// Absent in COIN-M futures, which have `maxQty` instead.
     #[serde(rename = "maxNotionalValue")]
+    #[serde(default)]
     #[serde(deserialize_with = "from_str_to_f64")]
     pub max_notional_value: f64,
     #[serde(rename = "positionAmt")]
     #[serde(deserialize_with = "from_str_to_f64")]
     pub position_amount: f64,
+    #[serde(alias = "notionalValue")]
     #[serde(deserialize_with = "from_str_to_f64")]
     pub notional: f64,
     #[serde(rename = "isolatedWallet")]
@@ -145,6 +156,13 @@ pub struct PositionInformationV2 {
     pub update_time: i64,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct AccountBalance {
+    pub asset: String,
+    #[serde(deserialize_with = "from_str_to_f64")]
+    pub balance: f64,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Depth {
     #[serde(rename = "lastUpdateId")]
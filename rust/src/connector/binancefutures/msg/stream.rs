@@ -23,6 +23,9 @@ pub enum Data {
     DepthUpdate(Depth),
     #[serde(rename = "trade")]
     Trade(Trade),
+    /// The aggregate trade, which COIN-M futures provide instead of the raw trade.
+    #[serde(rename = "aggTrade")]
+    AggTrade(Trade),
     #[serde(rename = "ORDER_TRADE_UPDATE")]
     OrderTradeUpdate(OrderTradeUpdate),
     #[serde(rename = "ACCOUNT_UPDATE")]
@@ -59,13 +62,15 @@ pub struct Trade {
     pub event_time: i64,
     #[serde(rename = "s")]
     pub symbol: String,
-    #[serde(rename = "t")]
+    /// The trade id, or the aggregate trade id of an aggregate trade.
+    #[serde(rename = "t", alias = "a")]
     pub id: i64,
     #[serde(rename = "p")]
     pub price: String,
     #[serde(rename = "q")]
     pub qty: String,
-    #[serde(rename = "X")]
+    /// Absent in an aggregate trade.
+    #[serde(rename = "X", default)]
     pub type_: String,
     #[serde(rename = "m")]
     pub is_the_buyer_the_market_maker: bool,
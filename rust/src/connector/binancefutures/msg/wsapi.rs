@@ -0,0 +1,31 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::rest::ErrorResponse;
+
+/// The response to a request of the WebSocket API, which has either the result, in the same form
+/// as the REST response, or the error.
+#[derive(Deserialize, Debug)]
+pub struct Response {
+    /// The id of the request, which is null if the request couldn't be parsed.
+    pub id: Option<String>,
+    #[serde(default)]
+    pub result: Option<Value>,
+    #[serde(default)]
+    pub error: Option<ErrorResponse>,
+    #[serde(default, rename = "rateLimits")]
+    pub rate_limits: Vec<RateLimit>,
+}
+
+/// The count of a rate limit after the request.
+#[derive(Deserialize, Debug)]
+pub struct RateLimit {
+    /// `REQUEST_WEIGHT` or `ORDERS`.
+    #[serde(rename = "rateLimitType")]
+    pub rate_limit_type: String,
+    /// `SECOND`, `MINUTE`, `HOUR`, or `DAY`.
+    pub interval: String,
+    #[serde(rename = "intervalNum")]
+    pub interval_num: i64,
+    pub count: u32,
+}
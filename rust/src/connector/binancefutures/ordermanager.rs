@@ -1,5 +1,7 @@
 use std::{
     collections::{hash_map::Entry, HashMap},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Error as IoError, Write},
     sync::{Arc, Mutex},
 };
 
@@ -9,15 +11,112 @@ use tracing::{debug, error, info, warn};
 
 use crate::{
     connector::binancefutures::{msg::rest::OrderResponse, rest::RequestError},
-    ty::{Order, Status},
+    ty::{OrdType, Order, Side, Status, TimeInForce},
 };
 
 #[derive(Debug)]
 struct OrderWrapper {
     order: Order<()>,
     client_order_id: String,
+    // The symbol of the order, which is empty if it's unknown, such as that of the order restored
+    // from an order id store journaled without the symbols.
+    symbol: String,
     removed_by_ws: bool,
     removed_by_rest: bool,
+    // The cumulative filled quantity of the last update forwarded to the bot.
+    filled_qty: f32,
+}
+
+impl OrderWrapper {
+    fn new(order: Order<()>, client_order_id: String) -> Self {
+        Self {
+            order,
+            client_order_id,
+            symbol: String::new(),
+            removed_by_ws: false,
+            removed_by_rest: false,
+            filled_qty: 0.0,
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        // The order restored from the order id store may be open until the exchange reports how
+        // it ended.
+        (self.order.active() || self.order.status == Status::Unsupported)
+            && !self.removed_by_ws
+            && !self.removed_by_rest
+    }
+
+    /// Merges the update into the order and returns the update to forward to the bot, so that the
+    /// bot sees a single monotonic sequence of the order's states, even though the same update
+    /// can arrive through both the REST response and the user data stream, in either order.
+    ///
+    /// Since Binance doesn't provide an update id common to both, the updates are sequenced by
+    /// the cumulative filled quantity and then by the order lifecycle, [`Status::transition`]. A
+    /// fill never moves a terminal order out of its status. An update that doesn't advance the
+    /// order is a duplicate or stale and is dropped, unless it's the response to a request, in
+    /// which case the latest state is forwarded instead so that the request is settled. The
+    /// forwarded `exec_qty` is the quantity filled since the last forwarded update.
+    fn merge(
+        &mut self,
+        mut order: Order<()>,
+        filled_qty: Option<f32>,
+        response: bool,
+    ) -> Option<Order<()>> {
+        let filled_qty = filled_qty.unwrap_or(self.filled_qty);
+        // The status of the order restored from the order id store is unknown until the exchange
+        // reports it.
+        let status = match self.order.status {
+            Status::Unsupported => Status::None,
+            status => status,
+        };
+        let transition = status.transition(order.status);
+        if filled_qty > self.filled_qty
+            || (filled_qty == self.filled_qty && order.status != status && transition.is_ok())
+        {
+            order.status = transition.unwrap_or(self.order.status);
+            order.exec_qty = filled_qty - self.filled_qty;
+            order.exch_timestamp = order.exch_timestamp.max(self.order.exch_timestamp);
+            self.order.update(&order);
+            self.filled_qty = filled_qty;
+            Some(order)
+        } else if response {
+            let mut order = self.order.clone();
+            order.req = Status::None;
+            order.exec_qty = 0.0;
+            Some(order)
+        } else {
+            None
+        }
+    }
+}
+
+/// The order request methods, which are named after those of the WebSocket API.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Place,
+    Cancel,
+    Modify,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Place => "order.place",
+            Method::Cancel => "order.cancel",
+            Method::Modify => "order.modify",
+        }
+    }
+}
+
+/// An order request in flight, by which its response is applied.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub asset_no: usize,
+    pub symbol: String,
+    pub client_order_id: String,
+    pub order: Order<()>,
 }
 
 pub type OrderMgr = Arc<Mutex<OrderManager>>;
@@ -27,6 +126,10 @@ pub struct OrderManager {
     prefix: String,
     orders: HashMap<String, OrderWrapper>,
     order_id_map: HashMap<i64, String>,
+    store: Option<File>,
+    // The requests sent over the WebSocket API, keyed by the request id.
+    requests: HashMap<String, Request>,
+    next_req_id: u64,
 }
 
 impl OrderManager {
@@ -35,26 +138,126 @@ impl OrderManager {
             prefix: prefix.to_string(),
             orders: Default::default(),
             order_id_map: Default::default(),
+            store: None,
+            requests: Default::default(),
+            next_req_id: 0,
+        }
+    }
+
+    /// Opens the order id store, which journals the client order ids in use along with their
+    /// symbols, and restores the order ids that may still exist on the exchange from the previous
+    /// run. The restored order ids cannot be reused until the exchange reports that the
+    /// corresponding orders are no longer active. The restored orders whose symbols are known are
+    /// reconciled with those open on the exchange like the other managed orders, so that the bot
+    /// learns how the orders left by the previous run have ended.
+    pub fn open_store(&mut self, path: &str) -> Result<(), IoError> {
+        // value: the order id and the symbol, which is empty if it's not journaled.
+        let mut in_use: HashMap<String, (i64, String)> = HashMap::new();
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let mut tokens = line.split(' ');
+                match (tokens.next(), tokens.next()) {
+                    (Some("+"), Some(client_order_id)) => {
+                        if let Some(order_id) =
+                            Self::parse_client_order_id(client_order_id, &self.prefix)
+                        {
+                            let symbol = tokens.next().unwrap_or_default().to_string();
+                            in_use.insert(client_order_id.to_string(), (order_id, symbol));
+                        }
+                    }
+                    (Some("-"), Some(client_order_id)) => {
+                        in_use.remove(client_order_id);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Compacts the journal so that it contains only the order ids in use.
+        let mut file = File::create(path)?;
+        for (client_order_id, (order_id, symbol)) in in_use {
+            writeln!(
+                file,
+                "{}",
+                Self::journal_line("+", &client_order_id, &symbol)
+            )?;
+
+            // The order's details are unknown until the exchange reports them.
+            let mut order = Order::new(
+                order_id,
+                0,
+                0.0,
+                0.0,
+                Side::Unsupported,
+                OrdType::Unsupported,
+                TimeInForce::Unsupported,
+            );
+            order.status = Status::Unsupported;
+            self.order_id_map.insert(order_id, client_order_id.clone());
+            self.orders.insert(
+                client_order_id.clone(),
+                OrderWrapper {
+                    symbol,
+                    ..OrderWrapper::new(order, client_order_id)
+                },
+            );
+        }
+        drop(file);
+
+        self.store = Some(OpenOptions::new().append(true).open(path)?);
+        Ok(())
+    }
+
+    fn journal_line(op: &str, client_order_id: &str, symbol: &str) -> String {
+        if symbol.is_empty() {
+            format!("{} {}", op, client_order_id)
+        } else {
+            format!("{} {} {}", op, client_order_id, symbol)
         }
     }
 
+    fn journal(store: &mut Option<File>, op: &str, client_order_id: &str, symbol: &str) {
+        if let Some(file) = store.as_mut() {
+            let line = Self::journal_line(op, client_order_id, symbol);
+            if let Err(error) = writeln!(file, "{}", line) {
+                error!(?error, %client_order_id, "Couldn't write to the order id store.");
+            }
+        }
+    }
+
+    fn release_order_id(
+        order_id_map: &mut HashMap<i64, String>,
+        store: &mut Option<File>,
+        order_id: i64,
+    ) {
+        if let Some(client_order_id) = order_id_map.remove(&order_id) {
+            Self::journal(store, "-", &client_order_id, "");
+        }
+    }
+
+    /// Applies an order update from the user data stream, whose `leaves_qty` reflects the
+    /// cumulative filled quantity. Returns the update to forward to the bot, if any.
     pub fn update_from_ws(
         &mut self,
         client_order_id: String,
         order: Order<()>,
     ) -> Option<Order<()>> {
+        let filled_qty = order.qty - order.leaves_qty;
         match self.orders.entry(client_order_id.clone()) {
             Entry::Occupied(mut entry) => {
                 let wrapper = entry.get_mut();
                 let already_removed = wrapper.removed_by_ws || wrapper.removed_by_rest;
-                if order.exch_timestamp >= wrapper.order.exch_timestamp {
-                    wrapper.order.update(&order);
-                }
+                let forwarded = wrapper.merge(order.clone(), Some(filled_qty), false);
 
-                if order.status != Status::New && order.status != Status::PartiallyFilled {
+                if !order.active() {
                     wrapper.removed_by_ws = true;
                     if !already_removed {
-                        self.order_id_map.remove(&order.order_id);
+                        Self::release_order_id(
+                            &mut self.order_id_map,
+                            &mut self.store,
+                            order.order_id,
+                        );
                     }
 
                     if wrapper.removed_by_ws && wrapper.removed_by_rest {
@@ -62,11 +265,7 @@ impl OrderManager {
                     }
                 }
 
-                if already_removed {
-                    None
-                } else {
-                    Some(order)
-                }
+                forwarded
             }
             Entry::Vacant(entry) => {
                 if !order.active() {
@@ -75,14 +274,12 @@ impl OrderManager {
 
                 debug!(%client_order_id, ?order, "Received an unmanaged order from WS.");
                 let wrapper = entry.insert(OrderWrapper {
-                    order: order.clone(),
-                    removed_by_ws: order.status != Status::New
-                        && order.status != Status::PartiallyFilled,
-                    removed_by_rest: false,
-                    client_order_id,
+                    removed_by_ws: !order.active(),
+                    filled_qty,
+                    ..OrderWrapper::new(order.clone(), client_order_id)
                 });
                 if wrapper.removed_by_ws || wrapper.removed_by_rest {
-                    self.order_id_map.remove(&order.order_id);
+                    Self::release_order_id(&mut self.order_id_map, &mut self.store, order.order_id);
                 }
                 Some(order)
             }
@@ -97,15 +294,15 @@ impl OrderManager {
         let order = Order {
             qty: resp.orig_qty,
             leaves_qty: resp.orig_qty - resp.cum_qty,
-            price_tick: (resp.price / order.tick_size).round() as i32,
+            price_tick: (resp.price / order.tick_size).round() as i64,
             tick_size: order.tick_size,
             side: order.side,
             time_in_force: resp.time_in_force,
             exch_timestamp: resp.update_time * 1_000_000,
-            status: Status::New,
+            status: resp.status,
             local_timestamp: 0,
             req: Status::None,
-            exec_price_tick: 0,
+            exec_price_tick: (resp.avg_price.unwrap_or(0.0) / order.tick_size).round() as i64,
             exec_qty: resp.executed_qty,
             order_id: order.order_id,
             order_type: resp.type_,
@@ -113,8 +310,10 @@ impl OrderManager {
             q: (),
             // Invalid information
             maker: false,
+            oco_group: None,
+            trigger_price_tick: 0,
         };
-        self.update_from_rest(resp.client_order_id, order)
+        self.update_from_rest(resp.client_order_id, order, Some(resp.executed_qty))
     }
 
     pub fn update_submit_fail(
@@ -145,8 +344,8 @@ impl OrderManager {
         }
 
         order.req = Status::None;
-        order.status = Status::Expired;
-        self.update_from_rest(client_order_id, order)
+        order.status = Status::Rejected;
+        self.update_from_rest(client_order_id, order, None)
     }
 
     pub fn update_cancel_success(
@@ -157,7 +356,7 @@ impl OrderManager {
         let order = Order {
             qty: resp.orig_qty,
             leaves_qty: resp.orig_qty - resp.cum_qty,
-            price_tick: (resp.price / order.tick_size).round() as i32,
+            price_tick: (resp.price / order.tick_size).round() as i64,
             tick_size: order.tick_size,
             side: resp.side,
             time_in_force: resp.time_in_force,
@@ -173,8 +372,10 @@ impl OrderManager {
             q: (),
             // Invalid information
             maker: false,
+            oco_group: None,
+            trigger_price_tick: 0,
         };
-        self.update_from_rest(resp.client_order_id, order)
+        self.update_from_rest(resp.client_order_id, order, Some(resp.executed_qty))
     }
 
     pub fn update_cancel_fail(
@@ -187,31 +388,153 @@ impl OrderManager {
             RequestError::OrderError(-2011, _) => {
                 // The given order may no longer exist; it could have already been filled or
                 // canceled. But, it cannot determine the order status because it lacks the
-                // necessary information.
-                order.leaves_qty = 0.0;
-                order.status = Status::None;
+                // necessary information. Keeps the current status so that the cancel can be
+                // resent.
             }
             error => {
                 error!(?error, "cancel error");
             }
         }
         order.req = Status::None;
-        self.update_from_rest(client_order_id, order)
+        self.update_from_rest(client_order_id, order, None)
+    }
+
+    pub fn update_modify_success(
+        &mut self,
+        order: Order<()>,
+        resp: OrderResponse,
+    ) -> Option<Order<()>> {
+        let order = Order {
+            qty: resp.orig_qty,
+            leaves_qty: resp.orig_qty - resp.executed_qty,
+            price_tick: (resp.price / order.tick_size).round() as i64,
+            tick_size: order.tick_size,
+            side: resp.side,
+            time_in_force: resp.time_in_force,
+            exch_timestamp: resp.update_time * 1_000_000,
+            status: resp.status,
+            local_timestamp: 0,
+            req: Status::None,
+            exec_price_tick: 0,
+            exec_qty: resp.executed_qty,
+            order_id: order.order_id,
+            order_type: resp.type_,
+            // Invalid information
+            q: (),
+            // Invalid information
+            maker: false,
+            oco_group: None,
+            trigger_price_tick: 0,
+        };
+        // The modification changes neither the filled quantity nor the status, by which the
+        // updates are sequenced, so the new price and quantity are applied here.
+        if let Some(wrapper) = self.orders.get_mut(&resp.client_order_id) {
+            wrapper.order.price_tick = order.price_tick;
+            wrapper.order.qty = order.qty;
+            wrapper.order.leaves_qty = order.leaves_qty;
+        }
+        self.update_from_rest(resp.client_order_id, order, Some(resp.executed_qty))
+    }
+
+    pub fn update_modify_fail(
+        &mut self,
+        mut order: Order<()>,
+        error: &RequestError,
+        client_order_id: String,
+    ) -> Option<Order<()>> {
+        match error {
+            RequestError::OrderError(-5027, _) => {
+                // No need to modify the order.
+            }
+            error => {
+                error!(?error, "modify error");
+            }
+        }
+        order.req = Status::None;
+        self.update_from_rest(client_order_id, order, None)
+    }
+
+    pub fn update_query_success(
+        &mut self,
+        order: Order<()>,
+        resp: OrderResponse,
+    ) -> Option<Order<()>> {
+        // The price of a market order is zero, so the average price is used if it has been filled,
+        // and the price the order was submitted at otherwise.
+        let price_tick = match resp.avg_price {
+            _ if resp.price > 0.0 => (resp.price / order.tick_size).round() as i64,
+            Some(avg_price) if avg_price > 0.0 => (avg_price / order.tick_size).round() as i64,
+            _ => order.price_tick,
+        };
+        let order = Order {
+            qty: resp.orig_qty,
+            leaves_qty: resp.orig_qty - resp.executed_qty,
+            price_tick,
+            tick_size: order.tick_size,
+            side: resp.side,
+            time_in_force: resp.time_in_force,
+            exch_timestamp: resp.update_time * 1_000_000,
+            status: resp.status,
+            local_timestamp: 0,
+            req: Status::None,
+            exec_price_tick: 0,
+            exec_qty: resp.executed_qty,
+            order_id: order.order_id,
+            order_type: resp.type_,
+            // Invalid information
+            q: (),
+            // Invalid information
+            maker: false,
+            oco_group: None,
+            trigger_price_tick: 0,
+        };
+        self.update_from_rest(resp.client_order_id, order, Some(resp.executed_qty))
+    }
+
+    pub fn update_query_fail(
+        &mut self,
+        mut order: Order<()>,
+        error: &RequestError,
+        client_order_id: String,
+    ) -> Option<Order<()>> {
+        match error {
+            RequestError::OrderError(-2013, _) => {
+                // Order does not exist; the order never reached the exchange.
+                order.leaves_qty = 0.0;
+                order.status = Status::Expired;
+            }
+            error => {
+                error!(?error, "query order error");
+                // It still cannot determine the order status. Keeps the current status so that
+                // the cancel can be resent.
+            }
+        }
+        order.req = Status::None;
+        self.update_from_rest(client_order_id, order, None)
     }
 
-    fn update_from_rest(&mut self, client_order_id: String, order: Order<()>) -> Option<Order<()>> {
+    /// Applies the response to a request. `filled_qty` is the cumulative filled quantity if the
+    /// response reports it. Returns the update to forward to the bot, if any.
+    fn update_from_rest(
+        &mut self,
+        client_order_id: String,
+        order: Order<()>,
+        filled_qty: Option<f32>,
+    ) -> Option<Order<()>> {
         match self.orders.entry(client_order_id.clone()) {
             Entry::Occupied(mut entry) => {
                 let wrapper = entry.get_mut();
                 let already_removed = wrapper.removed_by_ws || wrapper.removed_by_rest;
-                if order.exch_timestamp >= wrapper.order.exch_timestamp {
-                    wrapper.order.update(&order);
-                }
+                let forwarded = wrapper.merge(order.clone(), filled_qty, !already_removed);
 
-                if order.status != Status::New && order.status != Status::PartiallyFilled {
+                if !order.active() {
                     wrapper.removed_by_rest = true;
                     if !already_removed {
-                        self.order_id_map.remove(&order.order_id);
+                        Self::release_order_id(
+                            &mut self.order_id_map,
+                            &mut self.store,
+                            order.order_id,
+                        );
                     }
 
                     if wrapper.removed_by_ws && wrapper.removed_by_rest {
@@ -219,11 +542,7 @@ impl OrderManager {
                     }
                 }
 
-                if already_removed {
-                    None
-                } else {
-                    Some(order)
-                }
+                forwarded
             }
             Entry::Vacant(entry) => {
                 if !order.active() {
@@ -232,21 +551,19 @@ impl OrderManager {
 
                 debug!(%client_order_id, ?order, "Received an unmanaged order from REST.");
                 let wrapper = entry.insert(OrderWrapper {
-                    order: order.clone(),
-                    removed_by_ws: false,
-                    removed_by_rest: order.status != Status::New
-                        && order.status != Status::PartiallyFilled,
-                    client_order_id,
+                    removed_by_rest: !order.active(),
+                    filled_qty: filled_qty.unwrap_or(0.0),
+                    ..OrderWrapper::new(order.clone(), client_order_id)
                 });
                 if wrapper.removed_by_ws || wrapper.removed_by_rest {
-                    self.order_id_map.remove(&order.order_id);
+                    Self::release_order_id(&mut self.order_id_map, &mut self.store, order.order_id);
                 }
                 Some(order)
             }
         }
     }
 
-    pub fn prepare_client_order_id(&mut self, order: Order<()>) -> Option<String> {
+    pub fn prepare_client_order_id(&mut self, symbol: &str, order: Order<()>) -> Option<String> {
         if self.order_id_map.contains_key(&order.order_id) {
             return None;
         }
@@ -264,22 +581,96 @@ impl OrderManager {
 
         self.order_id_map
             .insert(order.order_id, client_order_id.clone());
+        Self::journal(&mut self.store, "+", &client_order_id, symbol);
         self.orders.insert(
             client_order_id.clone(),
             OrderWrapper {
-                order,
-                client_order_id: client_order_id.clone(),
-                removed_by_ws: false,
-                removed_by_rest: false,
+                symbol: symbol.to_string(),
+                ..OrderWrapper::new(order, client_order_id.clone())
             },
         );
         Some(client_order_id)
     }
 
+    /// Adopts an open order found on the exchange, which was created by a previous run, so that
+    /// it can be managed like the orders submitted by this run. Returns `false` if the order id
+    /// is already used by another client order id.
+    pub fn adopt(&mut self, symbol: &str, client_order_id: String, order: Order<()>) -> bool {
+        match self.order_id_map.get(&order.order_id) {
+            Some(existing) if *existing != client_order_id => return false,
+            Some(_) => {}
+            None => {
+                self.order_id_map
+                    .insert(order.order_id, client_order_id.clone());
+                Self::journal(&mut self.store, "+", &client_order_id, symbol);
+            }
+        }
+        match self.orders.entry(client_order_id.clone()) {
+            Entry::Occupied(mut entry) => {
+                let wrapper = entry.get_mut();
+                wrapper.filled_qty = order.qty - order.leaves_qty;
+                wrapper.order = order;
+                wrapper.symbol = symbol.to_string();
+                wrapper.removed_by_ws = false;
+                wrapper.removed_by_rest = false;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(OrderWrapper {
+                    symbol: symbol.to_string(),
+                    filled_qty: order.qty - order.leaves_qty,
+                    ..OrderWrapper::new(order, client_order_id)
+                });
+            }
+        }
+        true
+    }
+
     pub fn get_client_order_id(&self, order_id: i64) -> Option<String> {
         self.order_id_map.get(&order_id).cloned()
     }
 
+    /// Returns `true` if the order is open as far as the manager knows.
+    pub fn is_open(&self, client_order_id: &str) -> bool {
+        self.orders
+            .get(client_order_id)
+            .is_some_and(|wrapper| wrapper.is_open())
+    }
+
+    /// Returns the orders that are open as far as the manager knows, as their symbols, client
+    /// order ids, and orders. The orders whose symbols are unknown are excluded.
+    pub fn open_orders(&self) -> Vec<(String, String, Order<()>)> {
+        self.orders
+            .values()
+            .filter(|wrapper| wrapper.is_open() && !wrapper.symbol.is_empty())
+            .map(|wrapper| {
+                (
+                    wrapper.symbol.clone(),
+                    wrapper.client_order_id.clone(),
+                    wrapper.order.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Registers a request sent over the WebSocket API and returns its request id, by which the
+    /// response is matched.
+    pub fn prepare_request(&mut self, request: Request) -> String {
+        self.next_req_id += 1;
+        let req_id = self.next_req_id.to_string();
+        self.requests.insert(req_id.clone(), request);
+        req_id
+    }
+
+    /// Returns the request of the response.
+    pub fn take_request(&mut self, req_id: &str) -> Option<Request> {
+        self.requests.remove(req_id)
+    }
+
+    /// Returns all the requests in flight, whose responses are lost along with the connection.
+    pub fn take_requests(&mut self) -> Vec<Request> {
+        self.requests.drain().map(|(_, request)| request).collect()
+    }
+
     pub fn gc(&mut self) {
         let now = Utc::now().timestamp_nanos_opt().unwrap();
         let stale_ts = now - 300_000_000_000;
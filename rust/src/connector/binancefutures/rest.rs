@@ -10,16 +10,28 @@ use sha2::Sha256;
 use thiserror::Error;
 
 /// https://binance-docs.github.io/apidocs/futures/en/
-use super::msg::{rest, rest::PositionInformationV2};
+use super::msg::{
+    rest,
+    rest::{AccountBalance, PositionInformationV2},
+};
 use crate::{
-    connector::binancefutures::{
-        msg::{
-            rest::{OrderResponse, OrderResponseResult},
-            stream::ListenKey,
+    connector::{
+        binancefutures::{
+            msg::{
+                rest::{OrderResponse, OrderResponseResult},
+                stream::ListenKey,
+            },
+            ordermanager::{OrderManager, OrderMgr},
+            Market,
         },
-        ordermanager::{OrderManager, OrderMgr},
+        ratelimit::{Cost, Counter, RateLimitError, RateLimiter},
+        retry::{Operation, Outcome, RetryPolicy, Retryable},
+    },
+    live::{
+        clock,
+        latency::{self, Stage},
+        AssetInfo,
     },
-    live::AssetInfo,
     ty::{AsStr, Error, ErrorType, OrdType, Order, Side, Status, TimeInForce},
 };
 
@@ -31,6 +43,38 @@ pub enum RequestError {
     ReqError(#[from] reqwest::Error),
     #[error("order error")]
     OrderError(i64, String),
+    #[error("rate limited")]
+    RateLimited(#[from] RateLimitError),
+}
+
+impl Retryable for RequestError {
+    fn outcome(&self) -> Outcome {
+        match self {
+            RequestError::InvalidRequest => Outcome::Rejected,
+            RequestError::ReqError(error) => error.outcome(),
+            // -1001: Internal error; unable to process your request.
+            // -1007: Timeout waiting for response from backend server; the execution status is
+            // unknown.
+            RequestError::OrderError(-1001 | -1007, _) => Outcome::Unknown,
+            // -1008: The server is currently overloaded with other requests.
+            RequestError::OrderError(-1008, _) => Outcome::NotProcessed,
+            // -1003: Too many requests, which the rate limiter holds back until the back-off
+            // ends.
+            RequestError::OrderError(-1003, _) => Outcome::NotProcessed,
+            RequestError::RateLimited(_) => Outcome::NotProcessed,
+            RequestError::OrderError(..) => Outcome::Rejected,
+        }
+    }
+}
+
+/// An open order found on the exchange.
+#[derive(Debug)]
+pub struct OpenOrder {
+    pub asset_no: usize,
+    pub symbol: String,
+    pub client_order_id: String,
+    /// The order, if it is created by this bot, that is, its client order id has the prefix.
+    pub order: Option<Order<()>>,
 }
 
 #[derive(Clone)]
@@ -40,6 +84,9 @@ pub struct BinanceFuturesClient {
     api_key: String,
     secret: String,
     orders: OrderMgr,
+    retry: RetryPolicy,
+    market: Market,
+    rate_limiter: RateLimiter,
 }
 
 impl BinanceFuturesClient {
@@ -50,10 +97,75 @@ impl BinanceFuturesClient {
             api_key: api_key.to_string(),
             secret: secret.to_string(),
             orders,
+            retry: Default::default(),
+            market: Default::default(),
+            rate_limiter: Default::default(),
+        }
+    }
+
+    /// Sets the policy of retrying the failed requests.
+    pub fn retry_policy(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+
+    /// Sets the market, which is [`Market::UsdMargined`] by default. The URL should be that of the
+    /// market.
+    pub fn market(self, market: Market) -> Self {
+        Self { market, ..self }
+    }
+
+    /// Sets the rate limiter, which doesn't limit the requests by default.
+    pub fn rate_limiter(self, rate_limiter: RateLimiter) -> Self {
+        Self {
+            rate_limiter,
+            ..self
         }
     }
 
-    fn sign(secret: &str, s: &str) -> String {
+    pub(super) fn get_rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Applies the rate limit counts that the response headers report, such as
+    /// `X-MBX-USED-WEIGHT-1M` and `X-MBX-ORDER-COUNT-10S`, and holds the requests back by the
+    /// `Retry-After` header if the request is rejected for exceeding the rate limits, by HTTP 429,
+    /// or the IP address is banned, by HTTP 418.
+    fn update_rate_limits(&self, resp: &reqwest::Response) {
+        let mut counts = Vec::new();
+        for (name, value) in resp.headers() {
+            let name = name.as_str();
+            let (counter, interval) =
+                if let Some(interval) = name.strip_prefix("x-mbx-used-weight-") {
+                    (Counter::Weight, interval)
+                } else if let Some(interval) = name.strip_prefix("x-mbx-order-count-") {
+                    (Counter::Orders, interval)
+                } else {
+                    continue;
+                };
+            let count = value.to_str().ok().and_then(|value| value.parse().ok());
+            if let (Some(interval), Some(count)) = (parse_interval(interval), count) {
+                counts.push((counter, interval, count));
+            }
+        }
+        self.rate_limiter.update(&counts);
+        let status = resp.status().as_u16();
+        if status == 429 || status == 418 {
+            let retry_after: i64 = resp
+                .headers()
+                .get("retry-after")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(60);
+            self.rate_limiter
+                .block(clock::now() + retry_after * 1_000_000_000);
+        }
+    }
+
+    pub(super) fn get_market(&self) -> Market {
+        self.market
+    }
+
+    pub(super) fn sign(secret: &str, s: &str) -> String {
         let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
         mac.update(s.as_bytes());
         let hash = mac.finalize().into_bytes();
@@ -66,11 +178,13 @@ impl BinanceFuturesClient {
 
     async fn get<T: for<'a> Deserialize<'a>>(
         &self,
+        cost: Cost,
         path: &str,
         mut query: String,
         api_key: &str,
         secret: &str,
-    ) -> Result<T, reqwest::Error> {
+    ) -> Result<T, RequestError> {
+        self.rate_limiter.acquire(cost).await?;
         let time = Utc::now().timestamp_millis() - 1000;
         if !query.is_empty() {
             query.push_str("&");
@@ -87,22 +201,25 @@ impl BinanceFuturesClient {
             .header("Accept", "application/json")
             .header("X-MBX-APIKEY", api_key)
             .send()
-            .await?
-            .json()
             .await?;
-        Ok(resp)
+        self.update_rate_limits(&resp);
+        Ok(resp.json().await?)
     }
 
     async fn put<T: for<'a> Deserialize<'a>>(
         &self,
+        cost: Cost,
         path: &str,
         body: String,
         api_key: &str,
         secret: &str,
-    ) -> Result<T, reqwest::Error> {
+    ) -> Result<T, RequestError> {
+        self.rate_limiter.acquire(cost).await?;
+        let encode_ts = clock::now();
         let time = Utc::now().timestamp_millis() - 1000;
         let sign_body = format!("recvWindow=5000&timestamp={}{}", time, body);
         let signature = Self::sign(secret, &sign_body);
+        latency::record(Stage::OrderEncode, clock::now() - encode_ts);
         let resp = self
             .client
             .put(&format!(
@@ -113,22 +230,25 @@ impl BinanceFuturesClient {
             .header("X-MBX-APIKEY", api_key)
             .body(body)
             .send()
-            .await?
-            .json()
             .await?;
-        Ok(resp)
+        self.update_rate_limits(&resp);
+        Ok(resp.json().await?)
     }
 
     async fn post<T: for<'a> Deserialize<'a>>(
         &self,
+        cost: Cost,
         path: &str,
         body: String,
         api_key: &str,
         secret: &str,
-    ) -> Result<T, reqwest::Error> {
+    ) -> Result<T, RequestError> {
+        self.rate_limiter.acquire(cost).await?;
+        let encode_ts = clock::now();
         let time = Utc::now().timestamp_millis() - 1000;
         let sign_body = format!("recvWindow=5000&timestamp={}{}", time, body);
         let signature = Self::sign(secret, &sign_body);
+        latency::record(Stage::OrderEncode, clock::now() - encode_ts);
         let resp = self
             .client
             .post(&format!(
@@ -139,22 +259,25 @@ impl BinanceFuturesClient {
             .header("X-MBX-APIKEY", api_key)
             .body(body)
             .send()
-            .await?
-            .json()
             .await?;
-        Ok(resp)
+        self.update_rate_limits(&resp);
+        Ok(resp.json().await?)
     }
 
     async fn delete<T: for<'a> Deserialize<'a>>(
         &self,
+        cost: Cost,
         path: &str,
         body: String,
         api_key: &str,
         secret: &str,
-    ) -> Result<T, reqwest::Error> {
+    ) -> Result<T, RequestError> {
+        self.rate_limiter.acquire(cost).await?;
+        let encode_ts = clock::now();
         let time = Utc::now().timestamp_millis() - 1000;
         let sign_body = format!("recvWindow=5000&timestamp={}{}", time, body);
         let signature = Self::sign(secret, &sign_body);
+        latency::record(Stage::OrderEncode, clock::now() - encode_ts);
         let resp = self
             .client
             .delete(&format!(
@@ -165,32 +288,41 @@ impl BinanceFuturesClient {
             .header("X-MBX-APIKEY", api_key)
             .body(body)
             .send()
-            .await?
-            .json()
             .await?;
-        Ok(resp)
+        self.update_rate_limits(&resp);
+        Ok(resp.json().await?)
     }
 
-    pub async fn start_user_data_stream(&self) -> Result<String, reqwest::Error> {
+    pub async fn start_user_data_stream(&self) -> Result<String, RequestError> {
+        let path = self.market.path("/v1/listenKey");
         let resp: Result<ListenKey, _> = self
-            .post(
-                "/fapi/v1/listenKey",
-                String::new(),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Session, |_| {
+                self.post(
+                    Cost::new(1, 0),
+                    &path,
+                    String::new(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await;
         resp.map(|v| v.listen_key)
     }
 
-    pub async fn keepalive_user_data_stream(&self) -> Result<(), reqwest::Error> {
+    pub async fn keepalive_user_data_stream(&self) -> Result<(), RequestError> {
+        let path = self.market.path("/v1/listenKey");
         let _: serde_json::Value = self
-            .put(
-                "/fapi/v1/listenKey",
-                String::new(),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Session, |_| {
+                self.put(
+                    Cost::new(1, 0),
+                    &path,
+                    String::new(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(())
     }
@@ -216,19 +348,36 @@ impl BinanceFuturesClient {
         body.push_str("&price=");
         body.push_str(&format!("{:.prec$}", price, prec = price_prec));
         body.push_str("&quantity=");
-        body.push_str(&format!("{:.5}", qty));
+        body.push_str(&self.market.format_qty(qty));
         body.push_str("&type=");
         body.push_str(order_type.as_str());
         body.push_str("&timeInForce=");
         body.push_str(time_in_force.as_str());
 
-        let resp: OrderResponseResult = self
-            .post("/fapi/v1/order", body, &self.api_key, &self.secret)
-            .await?;
-        match resp {
-            OrderResponseResult::Ok(resp) => Ok(resp),
-            OrderResponseResult::Err(resp) => Err(RequestError::OrderError(resp.code, resp.msg)),
-        }
+        let path = self.market.path("/v1/order");
+        self.retry
+            .run(Operation::Submit, |attempt| {
+                let body = body.clone();
+                let path = &path;
+                async move {
+                    let resp: OrderResponseResult = self
+                        .post(Cost::new(0, 1), path, body, &self.api_key, &self.secret)
+                        .await?;
+                    match resp {
+                        OrderResponseResult::Ok(resp) => Ok(resp),
+                        // -4116: ClientOrderId is duplicated. Since a retry reuses the client
+                        // order id, this means that a previous attempt has been processed, so the
+                        // order is resolved by querying it.
+                        OrderResponseResult::Err(resp) if attempt > 1 && resp.code == -4116 => {
+                            self.query_order(client_order_id, symbol).await
+                        }
+                        OrderResponseResult::Err(resp) => {
+                            Err(RequestError::OrderError(resp.code, resp.msg))
+                        }
+                    }
+                }
+            })
+            .await
     }
 
     pub async fn submit_orders(
@@ -253,7 +402,7 @@ impl BinanceFuturesClient {
             body.push_str("\",\"price\":\"");
             body.push_str(&format!("{:.prec$}", order.3, prec = order.4));
             body.push_str("\",\"quantity\":\"");
-            body.push_str(&format!("{:.5}", order.5));
+            body.push_str(&self.market.format_qty(order.5));
             body.push_str("\",\"type\":\"");
             body.push_str(order.6.as_str());
             body.push_str("\",\"timeInForce\":\"");
@@ -262,8 +411,18 @@ impl BinanceFuturesClient {
         }
         body.push_str("]}");
 
+        let path = self.market.path("/v1/batchOrders");
         let resp: Vec<OrderResponseResult> = self
-            .post("/fapi/v1/batchOrders", body, &self.api_key, &self.secret)
+            .retry
+            .run(Operation::Submit, |_| {
+                self.post(
+                    Cost::new(5, orders.len() as u32),
+                    &path,
+                    body.clone(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(resp
             .into_iter()
@@ -295,10 +454,20 @@ impl BinanceFuturesClient {
         body.push_str("&price=");
         body.push_str(&format!("{:.prec$}", price, prec = price_prec));
         body.push_str("&quantity=");
-        body.push_str(&format!("{:.5}", qty));
+        body.push_str(&self.market.format_qty(qty));
 
+        let path = self.market.path("/v1/order");
         let resp: OrderResponseResult = self
-            .put("/fapi/v1/order", body, &self.api_key, &self.secret)
+            .retry
+            .run(Operation::Modify, |_| {
+                self.put(
+                    Cost::new(1, 1),
+                    &path,
+                    body.clone(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         match resp {
             OrderResponseResult::Ok(resp) => Ok(resp),
@@ -317,8 +486,48 @@ impl BinanceFuturesClient {
         body.push_str("&origClientOrderId=");
         body.push_str(client_order_id);
 
+        let path = self.market.path("/v1/order");
+        let resp: OrderResponseResult = self
+            .retry
+            .run(Operation::Cancel, |_| {
+                self.delete(
+                    Cost::new(1, 0),
+                    &path,
+                    body.clone(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
+            .await?;
+        match resp {
+            OrderResponseResult::Ok(resp) => Ok(resp),
+            OrderResponseResult::Err(resp) => Err(RequestError::OrderError(resp.code, resp.msg)),
+        }
+    }
+
+    pub async fn query_order(
+        &self,
+        client_order_id: &str,
+        symbol: &str,
+    ) -> Result<OrderResponse, RequestError> {
+        let mut query = String::with_capacity(100);
+        query.push_str("symbol=");
+        query.push_str(symbol);
+        query.push_str("&origClientOrderId=");
+        query.push_str(client_order_id);
+
+        let path = self.market.path("/v1/order");
         let resp: OrderResponseResult = self
-            .delete("/fapi/v1/order", body, &self.api_key, &self.secret)
+            .retry
+            .run(Operation::Query, |_| {
+                self.get(
+                    Cost::new(1, 0),
+                    &path,
+                    query.clone(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         match resp {
             OrderResponseResult::Ok(resp) => Ok(resp),
@@ -347,8 +556,18 @@ impl BinanceFuturesClient {
             body.push_str("\"");
         }
         body.push_str("]}");
+        let path = self.market.path("/v1/batchOrders");
         let resp: Vec<OrderResponseResult> = self
-            .post("/fapi/v1/batchOrders", body, &self.api_key, &self.secret)
+            .retry
+            .run(Operation::Cancel, |_| {
+                self.post(
+                    Cost::new(1, 0),
+                    &path,
+                    body.clone(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(resp
             .into_iter()
@@ -361,87 +580,145 @@ impl BinanceFuturesClient {
             .collect())
     }
 
-    pub async fn cancel_all_orders(&self, symbol: &str) -> Result<(), reqwest::Error> {
+    pub async fn cancel_all_orders(&self, symbol: &str) -> Result<(), RequestError> {
+        let path = self.market.path("/v1/allOpenOrders");
         let _: serde_json::Value = self
-            .delete(
-                "/fapi/v1/allOpenOrders",
-                format!("symbol={}", symbol),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Cancel, |_| {
+                self.delete(
+                    Cost::new(1, 0),
+                    &path,
+                    format!("symbol={}", symbol),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(())
     }
 
     pub async fn get_position_information(
         &self,
-    ) -> Result<Vec<PositionInformationV2>, reqwest::Error> {
+    ) -> Result<Vec<PositionInformationV2>, RequestError> {
+        let path = self.market.account_path("positionRisk");
         let resp: Vec<PositionInformationV2> = self
-            .get(
-                "/fapi/v2/positionRisk",
-                String::new(),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Query, |_| {
+                self.get(
+                    Cost::new(5, 0),
+                    &path,
+                    String::new(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(resp)
     }
 
+    pub async fn get_balance(&self) -> Result<Vec<AccountBalance>, RequestError> {
+        let path = self.market.account_path("balance");
+        let resp: Vec<AccountBalance> = self
+            .retry
+            .run(Operation::Query, |_| {
+                self.get(
+                    Cost::new(5, 0),
+                    &path,
+                    String::new(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
+            .await?;
+        Ok(resp)
+    }
+
+    /// Returns the open orders of the registered symbols, which are parsed into [`Order`] only if
+    /// their client order ids are created with the prefix.
     pub async fn get_current_all_open_orders(
         &self,
         assets: &HashMap<String, AssetInfo>,
-    ) -> Result<Vec<Order<()>>, reqwest::Error> {
+        prefix: &str,
+    ) -> Result<Vec<OpenOrder>, RequestError> {
+        let path = self.market.path("/v1/openOrders");
         let resp: Vec<OrderResponse> = self
-            .get(
-                "/fapi/v1/openOrders",
-                String::new(),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Query, |_| {
+                self.get(
+                    Cost::new(40, 0),
+                    &path,
+                    String::new(),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(resp
-            .iter()
-            .map(|data| {
-                assets.get(&data.symbol).and_then(|asset_info| {
-                    // fixme
-                    OrderManager::parse_client_order_id(&data.client_order_id, "prefix").map(
-                        |order_id| Order {
-                            qty: data.orig_qty,
-                            leaves_qty: data.orig_qty - data.cum_qty,
-                            price_tick: (data.price / asset_info.tick_size).round() as i32,
-                            tick_size: asset_info.tick_size,
-                            side: data.side,
-                            time_in_force: data.time_in_force,
-                            exch_timestamp: data.update_time * 1_000_000,
-                            status: data.status,
-                            local_timestamp: 0,
-                            req: Status::None,
-                            exec_price_tick: 0,
-                            exec_qty: data.executed_qty,
-                            order_id,
-                            order_type: data.type_,
-                            // Invalid information
-                            q: (),
-                            // Invalid information
-                            maker: false,
-                        },
-                    )
+            .into_iter()
+            .filter_map(|data| {
+                let asset_info = assets.get(&data.symbol)?;
+                let order = OrderManager::parse_client_order_id(&data.client_order_id, prefix).map(
+                    |order_id| Order {
+                        qty: data.orig_qty,
+                        leaves_qty: data.orig_qty - data.cum_qty,
+                        price_tick: (data.price / asset_info.tick_size).round() as i64,
+                        tick_size: asset_info.tick_size,
+                        side: data.side,
+                        time_in_force: data.time_in_force,
+                        exch_timestamp: data.update_time * 1_000_000,
+                        status: data.status,
+                        local_timestamp: 0,
+                        req: Status::None,
+                        exec_price_tick: 0,
+                        exec_qty: data.executed_qty,
+                        order_id,
+                        order_type: data.type_,
+                        // Invalid information
+                        q: (),
+                        // Invalid information
+                        maker: false,
+                        oco_group: None,
+                        trigger_price_tick: 0,
+                    },
+                );
+                Some(OpenOrder {
+                    asset_no: asset_info.asset_no,
+                    symbol: data.symbol,
+                    client_order_id: data.client_order_id,
+                    order,
                 })
             })
-            .filter(|order| order.is_some())
-            .map(|order| order.unwrap())
             .collect())
     }
 
-    pub async fn get_depth(&self, symbol: &str) -> Result<rest::Depth, reqwest::Error> {
+    pub async fn get_depth(&self, symbol: &str) -> Result<rest::Depth, RequestError> {
+        let path = self.market.path("/v1/depth");
         let resp: rest::Depth = self
-            .get(
-                "/fapi/v1/depth",
-                format!("symbol={}&limit=1000", symbol),
-                &self.api_key,
-                &self.secret,
-            )
+            .retry
+            .run(Operation::Query, |_| {
+                self.get(
+                    Cost::new(20, 0),
+                    &path,
+                    format!("symbol={}&limit=1000", symbol),
+                    &self.api_key,
+                    &self.secret,
+                )
+            })
             .await?;
         Ok(resp)
     }
 }
+
+/// Parses the interval of a rate limit header, such as `1m` of `X-MBX-USED-WEIGHT-1M`, into
+/// nanoseconds.
+fn parse_interval(s: &str) -> Option<i64> {
+    let (num, unit) = s.split_at(s.len().checked_sub(1)?);
+    let unit = match unit {
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        "d" => 86_400_000_000_000,
+        _ => return None,
+    };
+    Some(num.parse::<i64>().ok()? * unit)
+}
@@ -5,7 +5,6 @@ use std::{
 };
 
 use anyhow::Error;
-use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
 use tokio::{select, sync::mpsc::unbounded_channel, time};
 use tokio_tungstenite::{
@@ -15,18 +14,46 @@ use tokio_tungstenite::{
 use tracing::{error, info, warn};
 
 use super::{
+    book::Book,
+    handle_open_orders,
     msg::stream::{Data, Stream},
-    rest::BinanceFuturesClient,
+    reconcile_orders,
+    rest::{BinanceFuturesClient, RequestError},
+    BinanceFutures,
     BinanceFuturesError,
     OrderMgr,
 };
 use crate::{
-    connector::binancefutures::{
-        msg::{rest, stream},
-        ordermanager::OrderManager,
+    connector::{
+        binancefutures::{
+            msg::{rest, stream},
+            ordermanager::OrderManager,
+            settled_in,
+        },
+        record::SharedWsRecorder,
+        OpenOrderPolicy,
+    },
+    live::{
+        clock,
+        latency::{self, Stage},
+        AssetInfo,
+    },
+    ty::{
+        self,
+        Balance,
+        Depth,
+        Error as ErrorEvent,
+        ErrorType,
+        LiveEvent,
+        Order,
+        OrderResponse,
+        Position,
+        Status,
+        SyncKind,
+        Synced,
+        BUY,
+        SELL,
     },
-    live::AssetInfo,
-    ty::{self, Depth, LiveEvent, Order, OrderResponse, Position, Status, BUY, SELL},
 };
 
 fn parse_depth(
@@ -48,112 +75,388 @@ fn parse_px_qty_tup(px: String, qty: String) -> Result<(f32, f32), anyhow::Error
     Ok((px.parse()?, qty.parse()?))
 }
 
+/// Forwards the diff of the depth. The diffs older than the snapshot, whose lastUpdateId is in
+/// `snapshot_ids`, are dropped, and the first diff to follow the snapshot, without a gap between
+/// them, reports the book synced. Each of the later diffs should follow the previous one, whose
+/// last update id is in `prev_u`, by its `pu`. Returns `false` if there's a gap.
+fn forward_depth_update(
+    data: stream::Depth,
+    recv_ts: i64,
+    snapshot_ids: &mut HashMap<String, i64>,
+    prev_u: &mut HashMap<String, i64>,
+    book: &mut Book,
+    ev_tx: &Sender<LiveEvent>,
+    asset_info: &AssetInfo,
+) -> bool {
+    let mut synced = false;
+    if let Some(snapshot_id) = snapshot_ids.get(&data.symbol).copied() {
+        if data.last_update_id < snapshot_id {
+            return true;
+        }
+        snapshot_ids.remove(&data.symbol);
+        if data.first_update_id > snapshot_id + 1 {
+            return false;
+        }
+        synced = true;
+    } else if prev_u.get(&data.symbol) != Some(&data.prev_update_id) {
+        return false;
+    }
+    if let Some(u) = prev_u.get_mut(&data.symbol) {
+        *u = data.last_update_id;
+    }
+    match parse_depth(data.bids, data.asks) {
+        Ok((bids, asks)) => {
+            let local_ts = clock::now();
+            latency::record(Stage::FrameParse, local_ts - recv_ts);
+            book.apply_diff(&bids, &asks);
+            ev_tx
+                .send(LiveEvent::Depth(Depth {
+                    asset_no: asset_info.asset_no,
+                    exch_ts: data.transaction_time * 1_000_000,
+                    local_ts,
+                    bids,
+                    asks,
+                }))
+                .unwrap();
+        }
+        Err(error) => {
+            error!(?error, "Couldn't parse DepthUpdate stream.");
+        }
+    }
+    if synced {
+        ev_tx
+            .send(LiveEvent::Synced(Synced {
+                asset_no: asset_info.asset_no,
+                kind: SyncKind::Book,
+            }))
+            .unwrap();
+    }
+    true
+}
+
+/// Marks the book out of sync so that the snapshot is fetched again on the next diff.
+fn book_out_of_sync(symbol: &str, prev_u: &mut HashMap<String, i64>, ev_tx: &Sender<LiveEvent>) {
+    warn!(%symbol, "The diffs have a gap. Fetches the snapshot again.");
+    prev_u.remove(symbol);
+    // 1001 indicates the book is out of sync and being resynchronized.
+    ev_tx
+        .send(LiveEvent::Error(ErrorEvent::with(
+            ErrorType::Custom(1001),
+            BinanceFuturesError::BookOutOfSync(symbol.to_string()),
+        )))
+        .unwrap();
+}
+
 pub enum DepthManageMode {
     WaitUntilGapFill,
     GapFillOnTheFly,
     NaturalRefresh,
 }
 
-pub async fn connect(
-    url: &str,
-    ev_tx: Sender<LiveEvent>,
+/// Keeps the market and user data streams of the assets connected, reconnecting 5 seconds after
+/// the connection is lost. Since the updates sent meanwhile are lost, the state is resynchronized
+/// at every connection before the streams are resumed:
+///
+/// * The open orders found on the exchange are handled by the [`OpenOrderPolicy`], and the orders
+///   that the connector manages are reconciled with them. The managed orders that are no longer
+///   open are queried for how they ended, so that the bot learns of the fills and the
+///   cancellations that it missed.
+/// * The balances and the positions are fetched again.
+/// * The streams are resubscribed, and each book is rebuilt from a REST depth snapshot, which the
+///   diffs are checked to follow by their update ids. A gap between the diffs also has the
+///   snapshot fetched again, reported by [`ErrorType::Custom(1001)`](ErrorType).
+///
+/// The orders, the balances, and the positions are fetched once the streams are connected, while
+/// their messages are held, so that no update falls between the two.
+pub struct ReconnectManager {
+    base_url: String,
+    prefix: String,
     assets: HashMap<String, AssetInfo>,
-    prefix: &str,
-    orders: OrderMgr,
     client: BinanceFuturesClient,
-) -> Result<(), anyhow::Error> {
-    let mut request = url.into_client_request()?;
-    let _ = request.headers_mut();
+    orders: OrderMgr,
+    recorder: Option<SharedWsRecorder>,
+    open_order_policy: OpenOrderPolicy,
+    ev_tx: Sender<LiveEvent>,
+    // The books as forwarded to the bot, which are kept across the connections.
+    books: HashMap<String, Book>,
+}
 
-    let depth_mode = DepthManageMode::NaturalRefresh;
-    let mut pending_depth_messages: HashMap<String, Vec<stream::Depth>> = HashMap::new();
-    let mut prev_u: HashMap<String, i64> = HashMap::new();
+impl ReconnectManager {
+    pub fn new(conn: &BinanceFutures, ev_tx: Sender<LiveEvent>) -> Self {
+        Self {
+            base_url: conn.url.clone(),
+            prefix: conn.prefix.clone(),
+            assets: conn.assets.clone(),
+            client: conn.client.clone(),
+            orders: conn.orders.clone(),
+            recorder: conn.recorder.clone(),
+            open_order_policy: conn.open_order_policy,
+            ev_tx,
+            books: conn
+                .assets
+                .iter()
+                .map(|(symbol, asset_info)| (symbol.clone(), Book::new(asset_info.tick_size)))
+                .collect(),
+        }
+    }
 
-    let (ws_stream, _) = connect_async(request).await?;
-    let (mut write, mut read) = ws_stream.split();
-    let mut interval = time::interval(Duration::from_secs(60 * 30));
-    let (rest_tx, mut rest_rx) = unbounded_channel::<(String, rest::Depth)>();
-    loop {
-        select! {
-            _ = interval.tick() => {
-                let client_ = client.clone();
-                tokio::spawn(async move {
-                    if let Err(error) = client_.keepalive_user_data_stream().await {
-                        error!(?error, "Failed keepalive user data stream.");
-                    }
-                });
+    pub async fn run(mut self) {
+        let mut error_count = 0;
+        loop {
+            if error_count > 0 {
+                time::sleep(Duration::from_secs(5)).await;
             }
-            Some((symbol, data)) = rest_rx.recv() => {
-                // Processes the REST depth.
-                match parse_depth(data.bids, data.asks) {
-                    Ok((bids, asks)) => {
-                        let ai = assets
-                            .get(&symbol)
-                            .ok_or(BinanceFuturesError::AssetNotFound)?;
-                        ev_tx.send(
-                            LiveEvent::Depth(
-                                Depth {
-                                    asset_no: ai.asset_no,
-                                    exch_ts: data.transaction_time * 1_000_000,
-                                    local_ts: Utc::now().timestamp_nanos_opt().unwrap(),
-                                    bids,
-                                    asks,
-                                }
-                            )
-                        ).unwrap();
-                    }
-                    Err(error) => {
-                        error!(?error, "Couldn't parse Depth response.");
-                    }
+
+            // Handles the open orders before connecting to the stream in order to start with the
+            // known state.
+            match handle_open_orders(
+                &self.client,
+                &self.assets,
+                &self.prefix,
+                &self.orders,
+                self.open_order_policy,
+                &self.ev_tx,
+            )
+            .await
+            {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(error) => {
+                    error!(?error, "Couldn't handle the open orders.");
+                    self.ev_tx
+                        .send(LiveEvent::Error(ErrorEvent::with(
+                            ErrorType::OrderError,
+                            error,
+                        )))
+                        .unwrap();
+                    error_count += 1;
+                    continue;
+                }
+            }
+            // The open orders found at a reconnection are the bot's own, which shouldn't abort it.
+            if self.open_order_policy == OpenOrderPolicy::Abort {
+                self.open_order_policy = OpenOrderPolicy::Adopt;
+            }
+
+            let listen_key = match self.client.start_user_data_stream().await {
+                Ok(listen_key) => listen_key,
+                Err(error) => {
+                    error!(?error, "Couldn't start user data stream.");
+                    // 1000 indicates user data stream starting error.
+                    self.ev_tx
+                        .send(LiveEvent::Error(ErrorEvent::with(
+                            ErrorType::Custom(1000),
+                            error,
+                        )))
+                        .unwrap();
+                    error_count += 1;
+                    continue;
                 }
+            };
+
+            let url = self.stream_url(&listen_key);
+            if let Err(error) = self.connect(&url).await {
+                error!(?error, "A connection error occurred.");
+                self.ev_tx
+                    .send(LiveEvent::Error(ErrorEvent::with(
+                        ErrorType::ConnectionInterrupted,
+                        error,
+                    )))
+                    .unwrap();
+            } else {
+                self.ev_tx
+                    .send(LiveEvent::Error(ErrorEvent::new(
+                        ErrorType::ConnectionInterrupted,
+                    )))
+                    .unwrap();
+            }
+            error_count += 1;
+        }
+    }
 
-                // fixme: waits for pending messages without blocking.
-                // prev_u.remove(&symbol);
-                // let mut new_prev_u: Option<i64> = None;
-                // while new_prev_u.is_none() {
-                //     if let Some(msg) = pending_depth_messages.get_mut(&symbol) {
-                //         for pending_depth in msg.into_iter() {
-                //             // https://binance-docs.github.io/apidocs/futures/en/#how-to-manage-a-local-order-book-correctly
-                //             // The first processed event should have U <= lastUpdateId AND u >= lastUpdateId
-                //             if (
-                //                 pending_depth.last_update_id < resp.last_update_id
-                //                 || pending_depth.first_update_id > resp.last_update_id
-                //             ) && new_prev_u.is_none() {
-                //                 continue;
-                //             }
-                //             if new_prev_u.is_some() && pending_depth.prev_update_id != *new_prev_u.as_ref().unwrap() {
-                //                 warn!(%symbol, ?pending_depth, "UpdateId does not match.");
-                //             }
-                //
-                //             // Processes a pending depth message
-                //             new_prev_u = Some(pending_depth.last_update_id);
-                //             *prev_u.entry(symbol.clone())
-                //                 .or_insert(pending_depth.last_update_id) = pending_depth.last_update_id;
-                //         }
-                //     }
-                //     if new_prev_u.is_none() {
-                //         // Waits for depth messages.
-                //         todo!()
-                //     }
-                // }
+    /// Returns the URL that connects the user data stream and the depth and trade streams of the
+    /// assets.
+    fn stream_url(&self, listen_key: &str) -> String {
+        let market = self.client.get_market();
+        let streams: Vec<String> = self
+            .assets
+            .keys()
+            .map(|symbol| {
+                format!(
+                    "{}@depth@0ms/{}@{}",
+                    symbol.to_lowercase(),
+                    symbol.to_lowercase(),
+                    market.trade_stream()
+                )
+            })
+            .collect();
+        format!("{}{}/{}", &self.base_url, listen_key, streams.join("/"))
+    }
+
+    /// Fetches the state that the streams update: the orders, the balances, and the positions.
+    /// The positions go last since the bot regards the initial state as received once it has
+    /// them.
+    async fn resync(&self) -> Result<(), RequestError> {
+        reconcile_orders(
+            &self.client,
+            &self.assets,
+            &self.prefix,
+            &self.orders,
+            &self.ev_tx,
+        )
+        .await?;
+
+        let market = self.client.get_market();
+        for balance in self.client.get_balance().await? {
+            for asset_info in settled_in(&self.assets, &balance.asset, market) {
+                self.ev_tx
+                    .send(LiveEvent::Balance(Balance {
+                        asset_no: asset_info.asset_no,
+                        asset: balance.asset.clone(),
+                        balance: balance.balance,
+                    }))
+                    .unwrap();
             }
-            message = read.next() => {
-                match message {
-                    Some(Ok(Message::Text(text))) => {
-                        let stream = match serde_json::from_str::<Stream>(&text) {
-                            Ok(stream) => stream,
-                            Err(error) => {
-                                error!(?error, %text, "Couldn't parse Stream.");
-                                continue;
+        }
+
+        for position in self.client.get_position_information().await? {
+            if let Some(asset_info) = self.assets.get(&position.symbol) {
+                self.ev_tx
+                    .send(LiveEvent::Position(Position {
+                        asset_no: asset_info.asset_no,
+                        symbol: position.symbol,
+                        qty: position.position_amount,
+                        entry_price: Some(position.entry_price as f64),
+                        mark_price: Some(position.mark_price as f64),
+                    }))
+                    .unwrap();
+            }
+        }
+        Ok(())
+    }
+
+    async fn connect(&mut self, url: &str) -> Result<(), anyhow::Error> {
+        let mut request = url.into_client_request()?;
+        let _ = request.headers_mut();
+
+        let (ws_stream, _) = connect_async(request).await?;
+        let (mut write, mut read) = ws_stream.split();
+        // The stream messages are held while the state is fetched.
+        self.resync().await?;
+        // The user data stream is confirmed once the connection with the listen key is
+        // established and the state is resynchronized.
+        for asset_info in self.assets.values() {
+            self.ev_tx
+                .send(LiveEvent::Synced(Synced {
+                    asset_no: asset_info.asset_no,
+                    kind: SyncKind::UserData,
+                }))
+                .unwrap();
+        }
+
+        let Self {
+            ref prefix,
+            ref assets,
+            ref client,
+            ref orders,
+            ref recorder,
+            ref ev_tx,
+            ref mut books,
+            ..
+        } = *self;
+        let market = client.get_market();
+        let depth_mode = DepthManageMode::NaturalRefresh;
+        // The diffs received while the snapshot is being fetched.
+        let mut pending_depth_messages: HashMap<String, Vec<stream::Depth>> = HashMap::new();
+        let mut prev_u: HashMap<String, i64> = HashMap::new();
+        // The lastUpdateId of the snapshots that no diff has followed yet.
+        let mut snapshot_ids: HashMap<String, i64> = HashMap::new();
+
+        let mut interval = time::interval(Duration::from_secs(60 * 30));
+        let (rest_tx, mut rest_rx) = unbounded_channel::<(String, Option<rest::Depth>)>();
+        loop {
+            select! {
+                _ = interval.tick() => {
+                    let client_ = client.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = client_.keepalive_user_data_stream().await {
+                            error!(?error, "Failed keepalive user data stream.");
+                        }
+                    });
+                }
+                Some((symbol, data)) = rest_rx.recv() => {
+                    // Processes the REST depth.
+                    let Some(data) = data else {
+                        // Fetches the snapshot again on the next diff.
+                        pending_depth_messages.remove(&symbol);
+                        prev_u.remove(&symbol);
+                        continue;
+                    };
+                    let asset_info = assets
+                        .get(&symbol)
+                        .ok_or(BinanceFuturesError::AssetNotFound)?;
+                    let book = books
+                        .get_mut(&symbol)
+                        .ok_or(BinanceFuturesError::AssetNotFound)?;
+                    match parse_depth(data.bids, data.asks) {
+                        Ok((bids, asks)) => {
+                            // The levels that have vanished since the last snapshot, such as
+                            // during a disconnection, are cleared along with it.
+                            let (bids, asks) = book.apply_snapshot(bids, asks);
+                            ev_tx.send(
+                                LiveEvent::Depth(
+                                    Depth {
+                                        asset_no: asset_info.asset_no,
+                                        exch_ts: data.transaction_time * 1_000_000,
+                                        local_ts: clock::now(),
+                                        bids,
+                                        asks,
+                                    }
+                                )
+                            ).unwrap();
+                        }
+                        Err(error) => {
+                            error!(?error, "Couldn't parse Depth response.");
+                        }
+                    }
+
+                    // https://binance-docs.github.io/apidocs/futures/en/#how-to-manage-a-local-order-book-correctly
+                    // Applies the diffs buffered while the snapshot was being fetched.
+                    snapshot_ids.insert(symbol.clone(), data.last_update_id);
+                    prev_u.insert(symbol.clone(), data.last_update_id);
+                    let recv_ts = clock::now();
+                    for pending_depth in pending_depth_messages.remove(&symbol).unwrap_or_default() {
+                        if !forward_depth_update(pending_depth, recv_ts, &mut snapshot_ids, &mut prev_u, book, ev_tx, asset_info) {
+                            book_out_of_sync(&symbol, &mut prev_u, ev_tx);
+                            break;
+                        }
+                    }
+                }
+                message = read.next() => {
+                    match message {
+                        Some(Ok(Message::Text(text))) => {
+                            let recv_ts = clock::now();
+                            if let Some(recorder) = recorder.as_ref() {
+                                recorder
+                                    .lock()
+                                    .unwrap()
+                                    .record(recv_ts, &text);
                             }
-                        };
-                        match stream.data {
-                            Data::DepthUpdate(data) => {
-                                let mut prev_u_val = prev_u.get_mut(&data.symbol);
-                                if prev_u_val.is_none()
-                                    /* fixme: || data.prev_update_id != **prev_u_val.as_ref().unwrap()*/
-                                {
-                                    // if !pending_depth_messages.contains_key(&data.symbol) {
+                            let stream = match serde_json::from_str::<Stream>(&text) {
+                                Ok(stream) => stream,
+                                Err(error) => {
+                                    error!(?error, %text, "Couldn't parse Stream.");
+                                    continue;
+                                }
+                            };
+                            match stream.data {
+                                Data::DepthUpdate(data) => {
+                                    if let Some(pending) = pending_depth_messages.get_mut(&data.symbol) {
+                                        // Buffers the diffs until the snapshot is received.
+                                        pending.push(data);
+                                        continue;
+                                    }
+                                    if !prev_u.contains_key(&data.symbol) {
                                         let client_ = client.clone();
                                         let symbol = data.symbol.clone();
                                         let rest_tx_ = rest_tx.clone();
@@ -163,163 +466,165 @@ pub async fn connect(
                                                 .await;
                                             match resp {
                                                 Ok(depth) => {
-                                                    rest_tx_.send((symbol, depth)).unwrap();
+                                                    rest_tx_.send((symbol, Some(depth))).unwrap();
                                                 }
                                                 Err(error) => {
                                                     error!(
                                                         ?error,
                                                         %symbol,
                                                         "Failed to get depth through rest."
-                                                    )
+                                                    );
+                                                    rest_tx_.send((symbol, None)).unwrap();
                                                 }
                                             }
                                         });
-                                    // }
-                                    // pending_depth_messages
-                                    //     .entry(data.symbol.clone())
-                                    //     .or_insert(Vec::new())
-                                    //     .push(data);
-                                    // continue;
-                                }
-                                // *prev_u_val.unwrap() = data.last_update_id;
-                                // fixme: currently supports natural refresh only.
-                                *prev_u.entry(data.symbol.clone()).or_insert(data.last_update_id) = data.last_update_id;
-
-                                match parse_depth(data.bids, data.asks) {
-                                    Ok((bids, asks)) => {
-                                        let asset_info = assets
-                                            .get(&data.symbol)
-                                            .ok_or(BinanceFuturesError::AssetNotFound)?;
-                                        ev_tx.send(
-                                            LiveEvent::Depth(
-                                                Depth {
-                                                    asset_no: asset_info.asset_no,
-                                                    exch_ts: data.transaction_time * 1_000_000,
-                                                    local_ts: Utc::now().timestamp_nanos_opt().unwrap(),
-                                                    bids,
-                                                    asks,
-                                                }
-                                            )
-                                        ).unwrap();
-                                    }
-                                    Err(error) => {
-                                        error!(?error, "Couldn't parse DepthUpdate stream.");
+                                        pending_depth_messages.insert(data.symbol.clone(), vec![data]);
+                                        continue;
                                     }
-                                }
-                            }
-                            Data::Trade(data) => {
-                                match parse_px_qty_tup(data.price, data.qty) {
-                                    Ok((price, qty)) => {
-                                        let asset_info = assets
-                                            .get(&data.symbol)
+
+                                    let asset_info = assets
+                                        .get(&data.symbol)
                                         .ok_or(BinanceFuturesError::AssetNotFound)?;
-                                        ev_tx.send(
-                                            LiveEvent::Trade(
-                                                ty::Trade {
-                                                    asset_no: asset_info.asset_no,
-                                                    exch_ts: data.transaction_time * 1_000_000,
-                                                    local_ts: Utc::now().timestamp_nanos_opt().unwrap(),
-                                                    side: {
-                                                        if data.is_the_buyer_the_market_maker {
-                                                            SELL as i8
-                                                        } else {
-                                                            BUY as i8
-                                                        }
-                                                    },
-                                                    price,
-                                                    qty,
-                                                }
-                                            )
-                                        ).unwrap();
-                                    }
-                                    Err(e) => {
-                                        error!(error = ?e, "Couldn't parse trade stream.");
+                                    let book = books
+                                        .get_mut(&data.symbol)
+                                        .ok_or(BinanceFuturesError::AssetNotFound)?;
+                                    if !forward_depth_update(data, recv_ts, &mut snapshot_ids, &mut prev_u, book, ev_tx, asset_info) {
+                                        book_out_of_sync(&asset_info.symbol, &mut prev_u, ev_tx);
                                     }
                                 }
-                            }
-                            Data::ListenKeyExpired(_) => {
-                                error!("Listen key is expired.");
-                                // fixme: it should return an error.
-                                break;
-                            }
-                            Data::AccountUpdate(data) => {
-                                for position in data.account.position {
-                                    if let Some(asset_info) = assets.get(&position.symbol) {
-                                        ev_tx.send(
-                                            LiveEvent::Position(
-                                                Position {
-                                                    asset_no: asset_info.asset_no,
-                                                    symbol: position.symbol,
-                                                    qty: position.position_amount
-                                                }
-                                            )
-                                        ).unwrap();
+                                Data::Trade(data) | Data::AggTrade(data) => {
+                                    match parse_px_qty_tup(data.price, data.qty) {
+                                        Ok((price, qty)) => {
+                                            let local_ts = clock::now();
+                                            latency::record(Stage::FrameParse, local_ts - recv_ts);
+                                            let asset_info = assets
+                                                .get(&data.symbol)
+                                            .ok_or(BinanceFuturesError::AssetNotFound)?;
+                                            ev_tx.send(
+                                                LiveEvent::Trade(
+                                                    ty::Trade {
+                                                        asset_no: asset_info.asset_no,
+                                                        exch_ts: data.transaction_time * 1_000_000,
+                                                        local_ts,
+                                                        side: {
+                                                            if data.is_the_buyer_the_market_maker {
+                                                                SELL as i8
+                                                            } else {
+                                                                BUY as i8
+                                                            }
+                                                        },
+                                                        price,
+                                                        qty,
+                                                    }
+                                                )
+                                            ).unwrap();
+                                        }
+                                        Err(e) => {
+                                            error!(error = ?e, "Couldn't parse trade stream.");
+                                        }
                                     }
                                 }
-                            }
-                            Data::OrderTradeUpdate(data) => {
-                                if let Some(asset_info) = assets.get(&data.order.symbol) {
-                                    if let Some(order_id) = OrderManager::parse_client_order_id(&data.order.client_order_id, &prefix) {
-                                        let order = Order {
-                                            qty: data.order.original_qty,
-                                            leaves_qty: data.order.original_qty - data.order.order_filled_accumulated_qty,
-                                            price_tick: (data.order.original_price / asset_info.tick_size).round() as i32,
-                                            tick_size: asset_info.tick_size,
-                                            side: data.order.side,
-                                            time_in_force: data.order.time_in_force,
-                                            exch_timestamp: data.transaction_time * 1_000_000,
-                                            status: data.order.order_status,
-                                            local_timestamp: 0,
-                                            req: Status::None,
-                                            exec_price_tick: (data.order.last_filled_price / asset_info.tick_size).round() as i32,
-                                            exec_qty: data.order.order_last_filled_qty,
-                                            order_id,
-                                            q: (),
-                                            maker: false,
-                                            order_type: data.order.order_type
-                                        };
-
-                                        let order = orders
-                                            .lock()
-                                            .unwrap()
-                                            .update_from_ws(data.order.client_order_id, order);
-                                        if let Some(order) = order {
+                                Data::ListenKeyExpired(_) => {
+                                    error!("Listen key is expired.");
+                                    // fixme: it should return an error.
+                                    break;
+                                }
+                                Data::AccountUpdate(data) => {
+                                    for position in data.account.position {
+                                        if let Some(asset_info) = assets.get(&position.symbol) {
+                                            ev_tx.send(
+                                                LiveEvent::Position(
+                                                    Position {
+                                                        asset_no: asset_info.asset_no,
+                                                        symbol: position.symbol,
+                                                        qty: position.position_amount,
+                                                        entry_price: Some(position.entry_price as f64),
+                                                        mark_price: None,
+                                                    }
+                                                )
+                                            ).unwrap();
+                                        }
+                                    }
+                                    for balance in data.account.balance {
+                                        for asset_info in settled_in(assets, &balance.asset, market) {
                                             ev_tx.send(
-                                                LiveEvent::Order(
-                                                    OrderResponse {
+                                                LiveEvent::Balance(
+                                                    Balance {
                                                         asset_no: asset_info.asset_no,
-                                                        order
+                                                        asset: balance.asset.clone(),
+                                                        balance: balance.wallet_balance,
                                                     }
                                                 )
                                             ).unwrap();
                                         }
                                     }
                                 }
+                                Data::OrderTradeUpdate(data) => {
+                                    if let Some(asset_info) = assets.get(&data.order.symbol) {
+                                        if let Some(order_id) = OrderManager::parse_client_order_id(&data.order.client_order_id, prefix) {
+                                            let order = Order {
+                                                qty: data.order.original_qty,
+                                                leaves_qty: data.order.original_qty - data.order.order_filled_accumulated_qty,
+                                                price_tick: (data.order.original_price / asset_info.tick_size).round() as i64,
+                                                tick_size: asset_info.tick_size,
+                                                side: data.order.side,
+                                                time_in_force: data.order.time_in_force,
+                                                exch_timestamp: data.transaction_time * 1_000_000,
+                                                status: data.order.order_status,
+                                                local_timestamp: 0,
+                                                req: Status::None,
+                                                exec_price_tick: (data.order.last_filled_price / asset_info.tick_size).round() as i64,
+                                                exec_qty: data.order.order_last_filled_qty,
+                                                order_id,
+                                                q: (),
+                                                maker: false,
+                                                oco_group: None,
+                                                trigger_price_tick: 0,
+                                                order_type: data.order.order_type
+                                            };
+
+                                            let order = orders
+                                                .lock()
+                                                .unwrap()
+                                                .update_from_ws(data.order.client_order_id, order);
+                                            if let Some(order) = order {
+                                                ev_tx.send(
+                                                    LiveEvent::Order(
+                                                        OrderResponse {
+                                                            asset_no: asset_info.asset_no,
+                                                            order
+                                                        }
+                                                    )
+                                                ).unwrap();
+                                            }
+                                        }
+                                    }
+                                }
                             }
                         }
-                    }
-                    Some(Ok(Message::Binary(_))) => {}
-                    Some(Ok(Message::Ping(_))) => {
-                        orders.lock()
-                            .unwrap()
-                            .gc();
-                        write.send(Message::Pong(Vec::new())).await?;
-                    }
-                    Some(Ok(Message::Pong(_))) => {}
-                    Some(Ok(Message::Close(close_frame))) => {
-                        info!(?close_frame, "close");
-                        break;
-                    }
-                    Some(Ok(Message::Frame(_))) => {}
-                    Some(Err(e)) => {
-                        return Err(Error::from(e));
-                    }
-                    None => {
-                        break;
+                        Some(Ok(Message::Binary(_))) => {}
+                        Some(Ok(Message::Ping(_))) => {
+                            orders.lock()
+                                .unwrap()
+                                .gc();
+                            write.send(Message::Pong(Vec::new())).await?;
+                        }
+                        Some(Ok(Message::Pong(_))) => {}
+                        Some(Ok(Message::Close(close_frame))) => {
+                            info!(?close_frame, "close");
+                            break;
+                        }
+                        Some(Ok(Message::Frame(_))) => {}
+                        Some(Err(e)) => {
+                            return Err(Error::from(e));
+                        }
+                        None => {
+                            break;
+                        }
                     }
                 }
             }
         }
+        Ok(())
     }
-    Ok(())
 }
@@ -0,0 +1,283 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::{
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info, warn};
+
+use super::{handle_response, query_order, OrderMgr};
+use crate::{
+    connector::{
+        binancefutures::{
+            msg::{rest::OrderResponse, wsapi::Response},
+            ordermanager::Request,
+            rest::{BinanceFuturesClient, RequestError},
+        },
+        ratelimit::Counter,
+    },
+    live::{
+        clock,
+        latency::{self, Stage},
+    },
+    ty::{self, LiveEvent},
+};
+
+/// The order entry over the WebSocket API, which saves the round trip of establishing a REST
+/// request per order. The responses are matched with the requests by the request ids.
+///
+/// https://developers.binance.com/docs/derivatives/usds-margined-futures/websocket-api-general-info
+pub struct WsApi {
+    url: String,
+    api_key: String,
+    secret: String,
+    connected: Arc<AtomicBool>,
+    req_tx: UnboundedSender<String>,
+    req_rx: Option<UnboundedReceiver<String>>,
+}
+
+impl WsApi {
+    pub fn new(url: &str, api_key: &str, secret: &str) -> Self {
+        let (req_tx, req_rx) = unbounded_channel();
+        Self {
+            url: url.to_string(),
+            api_key: api_key.to_string(),
+            secret: secret.to_string(),
+            connected: Arc::new(AtomicBool::new(false)),
+            req_tx,
+            req_rx: Some(req_rx),
+        }
+    }
+
+    /// Returns `true` if the requests can be sent, otherwise they should go through REST.
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Acquire)
+    }
+
+    /// Signs the request with the parameters and sends it, registering the request so that its
+    /// response is applied.
+    pub fn send(
+        &self,
+        orders: &OrderMgr,
+        request: Request,
+        mut params: Vec<(&'static str, String)>,
+    ) -> Result<(), anyhow::Error> {
+        let encode_ts = clock::now();
+        let method = request.method;
+        let req_id = orders.lock().unwrap().prepare_request(request);
+        let time = Utc::now().timestamp_millis() - 1000;
+        params.push(("apiKey", self.api_key.clone()));
+        params.push(("recvWindow", "5000".to_string()));
+        params.push(("timestamp", time.to_string()));
+        // The signature is computed over the parameters sorted by their names.
+        params.sort_by_key(|(key, _)| *key);
+        let payload = params
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        let signature = BinanceFuturesClient::sign(&self.secret, &payload);
+        let mut args: Map<String, Value> = params
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), Value::String(value)))
+            .collect();
+        args.insert("signature".to_string(), Value::String(signature));
+        let req = json!({
+            "id": req_id,
+            "method": method.as_str(),
+            "params": args,
+        });
+        latency::record(Stage::OrderEncode, clock::now() - encode_ts);
+        self.req_tx.send(req.to_string())?;
+        Ok(())
+    }
+
+    /// Keeps connecting to the WebSocket API. Once the connection is lost, the requests in flight
+    /// are resolved by querying their orders since their responses are lost along with it.
+    pub fn run(
+        &mut self,
+        client: BinanceFuturesClient,
+        orders: OrderMgr,
+        ev_tx: Sender<LiveEvent>,
+    ) {
+        let Some(mut req_rx) = self.req_rx.take() else {
+            return;
+        };
+        let url = self.url.clone();
+        let connected = self.connected.clone();
+        tokio::spawn(async move {
+            loop {
+                let result = connect(&url, &mut req_rx, &client, &orders, &ev_tx, &connected).await;
+                connected.store(false, Ordering::Release);
+                // The requests sent after the disconnection but before noticing it are resolved
+                // along with those in flight.
+                while req_rx.try_recv().is_ok() {}
+                resolve_lost_requests(&client, &orders, &ev_tx);
+                match result {
+                    Ok(()) => warn!("The WebSocket API connection is closed."),
+                    Err(error) => error!(?error, "A WebSocket API connection error occurred."),
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+}
+
+async fn connect(
+    url: &str,
+    req_rx: &mut UnboundedReceiver<String>,
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    ev_tx: &Sender<LiveEvent>,
+    connected: &AtomicBool,
+) -> Result<(), anyhow::Error> {
+    let (ws_stream, _) = connect_async(url).await?;
+    let (mut write, mut read) = ws_stream.split();
+    connected.store(true, Ordering::Release);
+    info!(%url, "Connected to the WebSocket API.");
+
+    loop {
+        select! {
+            req = req_rx.recv() => {
+                let Some(req) = req else {
+                    return Ok(());
+                };
+                let write_ts = clock::now();
+                write.send(Message::Text(req)).await?;
+                latency::record(Stage::SocketWrite, clock::now() - write_ts);
+            }
+            message = read.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    handle_message(&text, client, orders, ev_tx);
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    write.send(Message::Pong(data)).await?;
+                }
+                Some(Ok(Message::Close(close_frame))) => {
+                    info!(?close_frame, "close");
+                    return Ok(());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(error)) => return Err(error.into()),
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+fn handle_message(
+    text: &str,
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    ev_tx: &Sender<LiveEvent>,
+) {
+    let resp: Response = match serde_json::from_str(text) {
+        Ok(resp) => resp,
+        Err(error) => {
+            error!(?error, %text, "Couldn't parse the WebSocket API response.");
+            return;
+        }
+    };
+    let mut counts = Vec::new();
+    for rate_limit in &resp.rate_limits {
+        let counter = match rate_limit.rate_limit_type.as_str() {
+            "REQUEST_WEIGHT" => Counter::Weight,
+            "ORDERS" => Counter::Orders,
+            _ => continue,
+        };
+        let unit = match rate_limit.interval.as_str() {
+            "SECOND" => 1_000_000_000,
+            "MINUTE" => 60_000_000_000,
+            "HOUR" => 3_600_000_000_000,
+            "DAY" => 86_400_000_000_000,
+            _ => continue,
+        };
+        counts.push((counter, rate_limit.interval_num * unit, rate_limit.count));
+    }
+    client.get_rate_limiter().update(&counts);
+    let request = resp
+        .id
+        .as_deref()
+        .and_then(|req_id| orders.lock().unwrap().take_request(req_id));
+    let Some(request) = request else {
+        warn!(%text, "Received a response to an unknown request.");
+        return;
+    };
+    let result = match (resp.result, resp.error) {
+        (_, Some(error)) => Err(RequestError::OrderError(error.code, error.msg)),
+        (Some(result), None) => match OrderResponse::deserialize(&result) {
+            Ok(resp) => Ok(resp),
+            Err(error) => {
+                error!(?error, %text, "Couldn't parse the result. Queries the order instead.");
+                resolve(client, orders, ev_tx, request);
+                return;
+            }
+        },
+        (None, None) => {
+            error!(%text, "Received neither a result nor an error. Queries the order instead.");
+            resolve(client, orders, ev_tx, request);
+            return;
+        }
+    };
+    let client = client.clone();
+    let orders = orders.clone();
+    let ev_tx = ev_tx.clone();
+    tokio::spawn(async move {
+        handle_response(&client, &orders, request, result, &ev_tx).await;
+    });
+}
+
+fn resolve_lost_requests(
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    ev_tx: &Sender<LiveEvent>,
+) {
+    let requests = orders.lock().unwrap().take_requests();
+    for request in requests {
+        warn!(
+            ?request,
+            "The response to the request is lost. Queries the order."
+        );
+        resolve(client, orders, ev_tx, request);
+    }
+}
+
+/// Resolves the status of the request's order by querying it.
+fn resolve(
+    client: &BinanceFuturesClient,
+    orders: &OrderMgr,
+    ev_tx: &Sender<LiveEvent>,
+    request: Request,
+) {
+    let client = client.clone();
+    let orders = orders.clone();
+    let ev_tx = ev_tx.clone();
+    tokio::spawn(async move {
+        let asset_no = request.asset_no;
+        let order = query_order(
+            &client,
+            &orders,
+            &request.symbol,
+            request.client_order_id,
+            request.order,
+        )
+        .await;
+        if let Some(order) = order {
+            ev_tx
+                .send(LiveEvent::Order(ty::OrderResponse { asset_no, order }))
+                .unwrap();
+        }
+    });
+}
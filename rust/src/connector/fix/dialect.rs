@@ -0,0 +1,85 @@
+use crate::{
+    connector::fix::{
+        msg::{tag, Message},
+        FixError,
+    },
+    ty::{Status, TimeInForce},
+};
+
+/// Adapts the standard FIX 4.4 messages to a venue's or a gateway's specification. The default
+/// methods follow the standard, so a dialect only needs to override the parts in which the venue
+/// deviates, such as authentication fields, custom tags, or non-standard enumerations.
+pub trait Dialect: Send + Sync {
+    /// Adds venue-specific fields to the Logon message, such as credentials.
+    fn logon(&self, _msg: &mut Message) {}
+
+    /// Adds or rewrites venue-specific fields of an outgoing application message, such as the
+    /// account or the security exchange.
+    fn outgoing(&self, _msg: &mut Message) {}
+
+    /// Sets the fields representing the time in force of an order.
+    fn time_in_force(&self, msg: &mut Message, time_in_force: TimeInForce) -> Result<(), FixError> {
+        match time_in_force {
+            TimeInForce::GTC => {
+                msg.set(tag::TIME_IN_FORCE, "1");
+            }
+            TimeInForce::IOC => {
+                msg.set(tag::TIME_IN_FORCE, "3");
+            }
+            TimeInForce::FOK => {
+                msg.set(tag::TIME_IN_FORCE, "4");
+            }
+            TimeInForce::GTX => {
+                // Post-only is expressed as ExecInst(18) Participate don't initiate.
+                msg.set(tag::TIME_IN_FORCE, "1");
+                msg.set(tag::EXEC_INST, "6");
+            }
+            TimeInForce::Unsupported => {
+                return Err(FixError::Unsupported("time in force".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps OrdStatus(39) of an execution report to the order status. Returns `None` for the
+    /// pending statuses, which don't change the order's state.
+    fn order_status(&self, msg: &Message) -> Option<Status> {
+        match msg.get(tag::ORD_STATUS)? {
+            "0" | "5" => Some(Status::New),
+            "1" => Some(Status::PartiallyFilled),
+            "2" => Some(Status::Filled),
+            "4" => Some(Status::Canceled),
+            "8" => Some(Status::Rejected),
+            "C" => Some(Status::Expired),
+            _ => None,
+        }
+    }
+}
+
+/// Follows the standard FIX 4.4 specification, optionally with Username(553) and Password(554) in
+/// the Logon message.
+#[derive(Clone, Debug, Default)]
+pub struct StandardDialect {
+    credentials: Option<(String, String)>,
+}
+
+impl StandardDialect {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn credentials(self, username: &str, password: &str) -> Self {
+        Self {
+            credentials: Some((username.to_string(), password.to_string())),
+        }
+    }
+}
+
+impl Dialect for StandardDialect {
+    fn logon(&self, msg: &mut Message) {
+        if let Some((username, password)) = self.credentials.as_ref() {
+            msg.set(tag::USERNAME, username);
+            msg.set(tag::PASSWORD, password);
+        }
+    }
+}
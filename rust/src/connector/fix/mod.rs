@@ -0,0 +1,591 @@
+mod dialect;
+mod msg;
+mod ordermanager;
+mod session;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+pub use dialect::{Dialect, StandardDialect};
+pub use msg::{msg_type, tag, Message};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    select,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    connector::{
+        fix::{
+            msg::utc_timestamp,
+            ordermanager::{OrderManager, OrderMgr},
+            session::{Inbound, Session},
+        },
+        Connector,
+    },
+    get_precision,
+    live::{
+        clock,
+        latency::{self, Stage},
+        AssetInfo,
+    },
+    ty::{
+        Error,
+        ErrorType,
+        LiveEvent,
+        OrdType,
+        Order,
+        OrderResponse,
+        Position,
+        Side,
+        Status,
+        SyncKind,
+        Synced,
+    },
+};
+
+#[derive(Error, Debug)]
+pub enum FixError {
+    #[error("asset not found")]
+    AssetNotFound,
+    #[error("the session is not logged on")]
+    NotLoggedOn,
+    #[error("the connector is already running")]
+    AlreadyRunning,
+    #[error("{0} is not supported")]
+    Unsupported(String),
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("invalid checksum")]
+    InvalidChecksum,
+    #[error("MsgSeqNum {0} is lower than expected {1}")]
+    SequenceTooLow(u64, u64),
+    #[error("the counterparty is not responding to heartbeats")]
+    HeartbeatTimeout,
+    #[error("the counterparty logged out: {0}")]
+    LoggedOut(String),
+    #[error("rejected: {0}")]
+    Rejected(String),
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A FIX 4.4 initiator for order entry, which handles the logon, heartbeats, and sequence number
+/// recovery, and translates the orders into NewOrderSingle, OrderCancelRequest, and
+/// OrderCancelReplaceRequest, and the execution reports back into the order updates.
+///
+/// Market data is not handled, so the depth and trades should be fed by another connector. The
+/// positions are tracked from the fills since the connector starts. Venue-specific deviations
+/// from the standard are handled by a [`Dialect`].
+///
+/// The connection is plain TCP; a TLS-wrapped gateway can be reached through a local TLS tunnel.
+pub struct Fix {
+    addr: String,
+    assets: HashMap<usize, AssetInfo>,
+    orders: OrderMgr,
+    dialect: Arc<dyn Dialect>,
+    session: Option<Session>,
+    logged_on: Arc<AtomicBool>,
+    req_tx: UnboundedSender<Message>,
+    req_rx: Option<UnboundedReceiver<Message>>,
+}
+
+impl Fix {
+    pub fn new(addr: &str, sender_comp_id: &str, target_comp_id: &str, prefix: &str) -> Self {
+        let dialect: Arc<dyn Dialect> = Arc::new(StandardDialect::new());
+        let (req_tx, req_rx) = unbounded_channel();
+        Self {
+            addr: addr.to_string(),
+            assets: Default::default(),
+            orders: Arc::new(Mutex::new(OrderManager::new(prefix))),
+            dialect: dialect.clone(),
+            session: Some(Session::new(sender_comp_id, target_comp_id, 30, dialect)),
+            logged_on: Default::default(),
+            req_tx,
+            req_rx: Some(req_rx),
+        }
+    }
+
+    /// Sets the dialect of the venue. The default is [`StandardDialect`] without credentials.
+    pub fn dialect<D: Dialect + 'static>(mut self, dialect: D) -> Self {
+        let dialect: Arc<dyn Dialect> = Arc::new(dialect);
+        if let Some(session) = self.session.as_mut() {
+            session.set_dialect(dialect.clone());
+        }
+        Self { dialect, ..self }
+    }
+
+    /// Sets the heartbeat interval in seconds. The default is 30 seconds.
+    pub fn heartbeat_interval(mut self, heartbeat_interval: u64) -> Self {
+        if let Some(session) = self.session.as_mut() {
+            session.set_heartbeat_interval(heartbeat_interval);
+        }
+        self
+    }
+
+    /// Persists the sequence numbers to the given file so that the session continues across
+    /// restarts. Without the store, the sequence numbers are reset on each logon.
+    pub fn seq_store(mut self, path: &str) -> Result<Self, std::io::Error> {
+        if let Some(session) = self.session.as_mut() {
+            session.open_store(path)?;
+        }
+        Ok(self)
+    }
+
+    /// Reports that the request for the order failed. A failed new order is expired, while the
+    /// order of a failed cancel or replace request remains in its current state.
+    fn fail(
+        &self,
+        asset_no: usize,
+        mut order: Order<()>,
+        tx: &Sender<LiveEvent>,
+        error: FixError,
+        new_order: bool,
+    ) {
+        order.req = Status::None;
+        if new_order {
+            self.orders.lock().unwrap().remove(order.order_id);
+            order.status = Status::Expired;
+        }
+        tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+            .unwrap();
+        tx.send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
+            .unwrap();
+    }
+
+    /// Replaces the price and the quantity of the order by OrderCancelReplaceRequest.
+    pub fn replace(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        price: f32,
+        qty: f32,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self.assets.get(&asset_no).ok_or(FixError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, FixError::NotLoggedOn, false);
+            return Ok(());
+        }
+        let cl_ord_ids = self
+            .orders
+            .lock()
+            .unwrap()
+            .prepare_amend_cl_ord_id(order.order_id);
+        let (orig_cl_ord_id, cl_ord_id) = match cl_ord_ids {
+            Some(cl_ord_ids) => cl_ord_ids,
+            None => {
+                debug!(
+                    order_id = order.order_id,
+                    "ClOrdID corresponding to order_id is not found."
+                );
+                return Ok(());
+            }
+        };
+        let mut msg = Message::new(msg_type::ORDER_CANCEL_REPLACE_REQUEST);
+        msg.set(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+            .set(tag::CL_ORD_ID, cl_ord_id)
+            .set(tag::HANDL_INST, 1)
+            .set(tag::SYMBOL, &asset_info.symbol)
+            .set(tag::SIDE, side_code(order.side)?)
+            .set(tag::TRANSACT_TIME, utc_timestamp(Utc::now()))
+            .set(tag::ORDER_QTY, qty)
+            .set(tag::ORD_TYPE, ord_type_code(order.order_type)?)
+            .set(
+                tag::PRICE,
+                format!("{:.prec$}", price, prec = get_precision(order.tick_size)),
+            );
+        if let Err(error) = self.dialect.time_in_force(&mut msg, order.time_in_force) {
+            self.fail(asset_no, order, &tx, error, false);
+            return Ok(());
+        }
+        self.req_tx.send(msg)?;
+        Ok(())
+    }
+}
+
+fn side_code(side: Side) -> Result<&'static str, FixError> {
+    match side {
+        Side::Buy => Ok("1"),
+        Side::Sell => Ok("2"),
+        _ => Err(FixError::Unsupported("side".to_string())),
+    }
+}
+
+fn ord_type_code(order_type: OrdType) -> Result<&'static str, FixError> {
+    match order_type {
+        OrdType::Market => Ok("1"),
+        OrdType::Limit => Ok("2"),
+        _ => Err(FixError::Unsupported("order type".to_string())),
+    }
+}
+
+impl Connector for Fix {
+    fn add(
+        &mut self,
+        asset_no: usize,
+        symbol: String,
+        tick_size: f32,
+        lot_size: f32,
+    ) -> Result<(), anyhow::Error> {
+        self.assets.insert(
+            asset_no,
+            AssetInfo {
+                asset_no,
+                symbol,
+                tick_size,
+                lot_size,
+            },
+        );
+        Ok(())
+    }
+
+    fn run(&mut self, ev_tx: Sender<LiveEvent>) -> Result<(), anyhow::Error> {
+        let mut session = self.session.take().ok_or(FixError::AlreadyRunning)?;
+        let mut req_rx = self.req_rx.take().ok_or(FixError::AlreadyRunning)?;
+        let addr = self.addr.clone();
+        let orders = self.orders.clone();
+        let logged_on = self.logged_on.clone();
+        let asset_nos: Vec<usize> = self.assets.keys().copied().collect();
+        let mut error_count = 0;
+
+        tokio::spawn(async move {
+            loop {
+                if error_count > 0 {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+
+                let result = connect(
+                    &addr,
+                    &mut session,
+                    &mut req_rx,
+                    &ev_tx,
+                    &orders,
+                    &logged_on,
+                    &asset_nos,
+                )
+                .await;
+                logged_on.store(false, Ordering::Release);
+                match result {
+                    Ok(()) => {
+                        ev_tx
+                            .send(LiveEvent::Error(Error::new(
+                                ErrorType::ConnectionInterrupted,
+                            )))
+                            .unwrap();
+                    }
+                    Err(error) => {
+                        error!(?error, "A FIX session error occurred.");
+                        ev_tx
+                            .send(LiveEvent::Error(Error::with(
+                                ErrorType::ConnectionInterrupted,
+                                error,
+                            )))
+                            .unwrap();
+                    }
+                }
+                error_count += 1;
+            }
+        });
+        Ok(())
+    }
+
+    fn submit(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self.assets.get(&asset_no).ok_or(FixError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, FixError::NotLoggedOn, true);
+            return Ok(());
+        }
+        let cl_ord_id = self
+            .orders
+            .lock()
+            .unwrap()
+            .prepare_cl_ord_id(asset_no, order.clone());
+        let cl_ord_id = match cl_ord_id {
+            Some(cl_ord_id) => cl_ord_id,
+            None => {
+                warn!(
+                    ?order,
+                    "The order id is already in use. This order request will be expired."
+                );
+                let mut order = order;
+                order.req = Status::None;
+                order.status = Status::Expired;
+                tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                    .unwrap();
+                return Ok(());
+            }
+        };
+
+        let mut msg = Message::new(msg_type::NEW_ORDER_SINGLE);
+        let codes = side_code(order.side)
+            .and_then(|side| ord_type_code(order.order_type).map(|ord_type| (side, ord_type)));
+        let (side, ord_type) = match codes {
+            Ok(codes) => codes,
+            Err(error) => {
+                self.fail(asset_no, order, &tx, error, true);
+                return Ok(());
+            }
+        };
+        msg.set(tag::CL_ORD_ID, cl_ord_id)
+            .set(tag::HANDL_INST, 1)
+            .set(tag::SYMBOL, &asset_info.symbol)
+            .set(tag::SIDE, side)
+            .set(tag::TRANSACT_TIME, utc_timestamp(Utc::now()))
+            .set(tag::ORDER_QTY, order.qty)
+            .set(tag::ORD_TYPE, ord_type);
+        if order.order_type == OrdType::Limit {
+            msg.set(
+                tag::PRICE,
+                format!(
+                    "{:.prec$}",
+                    order.price_tick as f32 * order.tick_size,
+                    prec = get_precision(order.tick_size)
+                ),
+            );
+        }
+        if let Err(error) = self.dialect.time_in_force(&mut msg, order.time_in_force) {
+            self.fail(asset_no, order, &tx, error, true);
+            return Ok(());
+        }
+        self.req_tx.send(msg)?;
+        Ok(())
+    }
+
+    fn cancel(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self.assets.get(&asset_no).ok_or(FixError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, FixError::NotLoggedOn, false);
+            return Ok(());
+        }
+        let cl_ord_ids = self
+            .orders
+            .lock()
+            .unwrap()
+            .prepare_amend_cl_ord_id(order.order_id);
+        match cl_ord_ids {
+            Some((orig_cl_ord_id, cl_ord_id)) => {
+                let mut msg = Message::new(msg_type::ORDER_CANCEL_REQUEST);
+                msg.set(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+                    .set(tag::CL_ORD_ID, cl_ord_id)
+                    .set(tag::SYMBOL, &asset_info.symbol)
+                    .set(tag::SIDE, side_code(order.side)?)
+                    .set(tag::TRANSACT_TIME, utc_timestamp(Utc::now()))
+                    .set(tag::ORDER_QTY, order.qty);
+                self.req_tx.send(msg)?;
+            }
+            None => {
+                debug!(
+                    order_id = order.order_id,
+                    "ClOrdID corresponding to order_id is not found; \
+                    this may be due to the order already being canceled or filled."
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let (price, qty) = (order.price(), order.qty);
+        self.replace(asset_no, order, price, qty, tx)
+    }
+
+    /// The session carries the order entry only.
+    fn streams_depth(&self) -> bool {
+        false
+    }
+}
+
+fn handle_app(msg: Message, ev_tx: &Sender<LiveEvent>, orders: &OrderMgr, dialect: &dyn Dialect) {
+    match msg.msg_type() {
+        msg_type::EXECUTION_REPORT => {
+            let result = orders
+                .lock()
+                .unwrap()
+                .update_execution_report(&msg, dialect);
+            if let Some((asset_no, order, position)) = result {
+                if order.status == Status::Rejected {
+                    let text = msg.get(tag::TEXT).unwrap_or("").to_string();
+                    ev_tx
+                        .send(LiveEvent::Error(Error::with(
+                            ErrorType::OrderError,
+                            FixError::Rejected(text),
+                        )))
+                        .unwrap();
+                }
+                ev_tx
+                    .send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                    .unwrap();
+                if let Some(qty) = position {
+                    ev_tx
+                        .send(LiveEvent::Position(Position {
+                            asset_no,
+                            symbol: msg.get(tag::SYMBOL).unwrap_or("").to_string(),
+                            qty,
+                            entry_price: None,
+                            mark_price: None,
+                        }))
+                        .unwrap();
+                }
+            }
+        }
+        msg_type::ORDER_CANCEL_REJECT => {
+            let result = orders.lock().unwrap().update_cancel_reject(&msg);
+            if let Some((asset_no, order)) = result {
+                ev_tx
+                    .send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                    .unwrap();
+            }
+            let text = msg.get(tag::TEXT).unwrap_or("").to_string();
+            ev_tx
+                .send(LiveEvent::Error(Error::with(
+                    ErrorType::OrderError,
+                    FixError::Rejected(text),
+                )))
+                .unwrap();
+        }
+        msg_type::REJECT => {
+            let text = msg.get(tag::TEXT).unwrap_or("").to_string();
+            error!(ref_seq_num = msg.get(tag::REF_SEQ_NUM), %text, "Session-level reject.");
+            ev_tx
+                .send(LiveEvent::Error(Error::with(
+                    ErrorType::OrderError,
+                    FixError::Rejected(text),
+                )))
+                .unwrap();
+        }
+        _ => {
+            debug!(?msg, "Unhandled FIX message.");
+        }
+    }
+}
+
+/// Connects and runs the session. Once logged on, the user data of the assets is reported synced,
+/// since the execution reports are delivered through the session.
+async fn connect(
+    addr: &str,
+    session: &mut Session,
+    req_rx: &mut UnboundedReceiver<Message>,
+    ev_tx: &Sender<LiveEvent>,
+    orders: &OrderMgr,
+    logged_on: &AtomicBool,
+    asset_nos: &[usize],
+) -> Result<(), FixError> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+    stream.write_all(&session.logon()).await?;
+
+    let heartbeat_interval = Duration::from_secs(session.heartbeat_interval());
+    let mut ticker = time::interval(Duration::from_secs(1));
+    let mut last_sent = Instant::now();
+    let mut last_recv = Instant::now();
+    let mut test_req_sent = false;
+    let mut buf = Vec::with_capacity(8192);
+    let mut chunk = [0u8; 4096];
+    loop {
+        select! {
+            n = stream.read(&mut chunk) => {
+                let n = n?;
+                if n == 0 {
+                    return Ok(());
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                last_recv = Instant::now();
+                test_req_sent = false;
+                while let Some((msg, len)) = Message::decode(&buf)? {
+                    buf.drain(..len);
+                    let mut out = Vec::new();
+                    let inbound = session.on_message(msg, &mut out);
+                    for data in out {
+                        stream.write_all(&data).await?;
+                        last_sent = Instant::now();
+                    }
+                    match inbound? {
+                        Inbound::Admin => {}
+                        Inbound::LoggedOn => {
+                            info!(%addr, "The FIX session is logged on.");
+                            logged_on.store(true, Ordering::Release);
+                            for asset_no in asset_nos {
+                                ev_tx
+                                    .send(LiveEvent::Synced(Synced {
+                                        asset_no: *asset_no,
+                                        kind: SyncKind::UserData,
+                                    }))
+                                    .unwrap();
+                            }
+                        }
+                        Inbound::LoggedOut(text) => {
+                            if logged_on.load(Ordering::Acquire) {
+                                stream.write_all(&session.logout(None)).await?;
+                            }
+                            return Err(FixError::LoggedOut(text.unwrap_or_default()));
+                        }
+                        Inbound::App(msg) => {
+                            handle_app(msg, ev_tx, orders, session.dialect());
+                        }
+                    }
+                }
+            }
+            msg = req_rx.recv() => {
+                match msg {
+                    Some(msg) => {
+                        let encode_ts = clock::now();
+                        let data = session.send(msg);
+                        let write_ts = clock::now();
+                        latency::record(Stage::OrderEncode, write_ts - encode_ts);
+                        stream.write_all(&data).await?;
+                        latency::record(Stage::SocketWrite, clock::now() - write_ts);
+                        last_sent = Instant::now();
+                    }
+                    None => {
+                        stream.write_all(&session.logout(None)).await?;
+                        return Ok(());
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                let since_recv = last_recv.elapsed();
+                if since_recv > heartbeat_interval * 2 {
+                    return Err(FixError::HeartbeatTimeout);
+                }
+                if !test_req_sent && since_recv > heartbeat_interval + heartbeat_interval / 5 {
+                    stream.write_all(&session.test_request()).await?;
+                    last_sent = Instant::now();
+                    test_req_sent = true;
+                }
+                if last_sent.elapsed() >= heartbeat_interval {
+                    stream.write_all(&session.heartbeat(None)).await?;
+                    last_sent = Instant::now();
+                }
+            }
+        }
+    }
+}
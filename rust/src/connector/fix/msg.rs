@@ -0,0 +1,233 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::connector::fix::FixError;
+
+pub const SOH: u8 = 0x01;
+
+pub mod tag {
+    pub const BEGIN_SEQ_NO: u32 = 7;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECK_SUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const END_SEQ_NO: u32 = 16;
+    pub const EXEC_INST: u32 = 18;
+    pub const HANDL_INST: u32 = 21;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const NEW_SEQ_NO: u32 = 36;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TIME_IN_FORCE: u32 = 59;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const GAP_FILL_FLAG: u32 = 123;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const USERNAME: u32 = 553;
+    pub const PASSWORD: u32 = 554;
+}
+
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const RESEND_REQUEST: &str = "2";
+    pub const REJECT: &str = "3";
+    pub const SEQUENCE_RESET: &str = "4";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+    pub const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+}
+
+const HEADER_TAGS: [u32; 6] = [
+    tag::MSG_TYPE,
+    tag::SENDER_COMP_ID,
+    tag::TARGET_COMP_ID,
+    tag::MSG_SEQ_NUM,
+    tag::POSS_DUP_FLAG,
+    tag::SENDING_TIME,
+];
+
+/// A FIX message, which keeps the fields in order except for the standard header fields that
+/// are computed on encoding: BeginString(8), BodyLength(9), and CheckSum(10).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Message {
+    fields: Vec<(u32, String)>,
+}
+
+impl Message {
+    pub fn new(msg_type: &str) -> Self {
+        Self {
+            fields: vec![(tag::MSG_TYPE, msg_type.to_string())],
+        }
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or("")
+    }
+
+    /// Sets the field, replacing the existing value if the tag is already set.
+    pub fn set<T: ToString>(&mut self, tag: u32, value: T) -> &mut Self {
+        let value = value.to_string();
+        match self.fields.iter_mut().find(|(t, _)| *t == tag) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((tag, value)),
+        }
+        self
+    }
+
+    pub fn remove(&mut self, tag: u32) -> Option<String> {
+        let pos = self.fields.iter().position(|(t, _)| *t == tag)?;
+        Some(self.fields.remove(pos).1)
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_parsed<T: FromStr>(&self, tag: u32) -> Option<T> {
+        self.get(tag).and_then(|value| value.parse().ok())
+    }
+
+    /// Returns the timestamp field in nanoseconds since the Unix epoch.
+    pub fn get_timestamp(&self, tag: u32) -> Option<i64> {
+        let value = self.get(tag)?;
+        NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f")
+            .ok()
+            .and_then(|dt| dt.and_utc().timestamp_nanos_opt())
+    }
+
+    pub fn fields(&self) -> &[(u32, String)] {
+        &self.fields
+    }
+
+    pub fn encode(&self, begin_string: &str) -> Vec<u8> {
+        let mut body = Vec::with_capacity(256);
+        // The standard header fields must precede the body fields, starting with MsgType(35).
+        let mut header: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|(t, _)| HEADER_TAGS.contains(t))
+            .collect();
+        header.sort_by_key(|(t, _)| HEADER_TAGS.iter().position(|tag| tag == t));
+        let body_fields = self.fields.iter().filter(|(t, _)| {
+            !HEADER_TAGS.contains(t)
+                && *t != tag::BEGIN_STRING
+                && *t != tag::BODY_LENGTH
+                && *t != tag::CHECK_SUM
+        });
+        for (tag, value) in header.into_iter().chain(body_fields) {
+            body.extend_from_slice(format!("{}={}", tag, value).as_bytes());
+            body.push(SOH);
+        }
+
+        let mut buf = Vec::with_capacity(body.len() + 32);
+        buf.extend_from_slice(format!("8={}", begin_string).as_bytes());
+        buf.push(SOH);
+        buf.extend_from_slice(format!("9={}", body.len()).as_bytes());
+        buf.push(SOH);
+        buf.extend_from_slice(&body);
+        let checksum = checksum(&buf);
+        buf.extend_from_slice(format!("10={:03}", checksum).as_bytes());
+        buf.push(SOH);
+        buf
+    }
+
+    /// Decodes a message from the front of the buffer. Returns the message and the number of
+    /// bytes consumed, or `None` if the buffer doesn't contain a complete message yet.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Message, usize)>, FixError> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        if !buf.starts_with(b"8=") {
+            return Err(FixError::InvalidMessage(
+                "the message doesn't start with BeginString".to_string(),
+            ));
+        }
+        let begin_end = match buf.iter().position(|b| *b == SOH) {
+            Some(pos) => pos,
+            None => return Ok(None),
+        };
+        let len_start = begin_end + 1;
+        let len_end = match buf[len_start..].iter().position(|b| *b == SOH) {
+            Some(pos) => len_start + pos,
+            None => return Ok(None),
+        };
+        let body_len: usize = std::str::from_utf8(&buf[len_start..len_end])
+            .ok()
+            .and_then(|field| field.strip_prefix("9="))
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| FixError::InvalidMessage("invalid BodyLength".to_string()))?;
+        let body_end = len_end + 1 + body_len;
+        // CheckSum(10) is always three digits.
+        let total = body_end + 7;
+        if buf.len() < total {
+            return Ok(None);
+        }
+        if &buf[body_end..body_end + 3] != b"10=" || buf[total - 1] != SOH {
+            return Err(FixError::InvalidMessage(
+                "CheckSum doesn't follow the body".to_string(),
+            ));
+        }
+        let expected: u8 = std::str::from_utf8(&buf[body_end + 3..total - 1])
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| FixError::InvalidMessage("invalid CheckSum".to_string()))?;
+        if checksum(&buf[..body_end]) != expected {
+            return Err(FixError::InvalidChecksum);
+        }
+
+        let mut fields = Vec::new();
+        for field in buf[len_end + 1..body_end].split(|b| *b == SOH) {
+            if field.is_empty() {
+                continue;
+            }
+            let field = std::str::from_utf8(field)
+                .map_err(|_| FixError::InvalidMessage("invalid UTF-8".to_string()))?;
+            let (tag, value) = field
+                .split_once('=')
+                .ok_or_else(|| FixError::InvalidMessage(format!("invalid field: {}", field)))?;
+            let tag = tag
+                .parse()
+                .map_err(|_| FixError::InvalidMessage(format!("invalid tag: {}", tag)))?;
+            fields.push((tag, value.to_string()));
+        }
+        Ok(Some((Message { fields }, total)))
+    }
+}
+
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Formats the timestamp in the UTCTimestamp format with milliseconds.
+pub fn utc_timestamp(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tracing::debug;
+
+use crate::{
+    connector::fix::{
+        dialect::Dialect,
+        msg::{tag, Message},
+    },
+    ty::{Order, Side, Status},
+};
+
+#[derive(Debug)]
+struct OrderWrapper {
+    asset_no: usize,
+    order: Order<()>,
+    cl_ord_id: String,
+    // Incremented for each cancel or replace request, which requires a new ClOrdID.
+    rev: u32,
+}
+
+pub type OrderMgr = Arc<Mutex<OrderManager>>;
+
+/// Maps the order ids to the ClOrdIDs, which are `{prefix}{order_id}` for new orders and
+/// `{prefix}{order_id}-{rev}` for cancel and replace requests, and tracks the positions from the
+/// fills.
+#[derive(Default, Debug)]
+pub struct OrderManager {
+    prefix: String,
+    orders: HashMap<i64, OrderWrapper>,
+    cl_ord_ids: HashMap<String, i64>,
+    position: HashMap<usize, f64>,
+}
+
+impl OrderManager {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Registers a new order and returns its ClOrdID, or `None` if the order id is in use.
+    pub fn prepare_cl_ord_id(&mut self, asset_no: usize, order: Order<()>) -> Option<String> {
+        if self.orders.contains_key(&order.order_id) {
+            return None;
+        }
+        let cl_ord_id = format!("{}{}", self.prefix, order.order_id);
+        self.cl_ord_ids.insert(cl_ord_id.clone(), order.order_id);
+        self.orders.insert(
+            order.order_id,
+            OrderWrapper {
+                asset_no,
+                order,
+                cl_ord_id: cl_ord_id.clone(),
+                rev: 0,
+            },
+        );
+        Some(cl_ord_id)
+    }
+
+    /// Returns the current ClOrdID of the order and a new ClOrdID for a cancel or replace
+    /// request.
+    pub fn prepare_amend_cl_ord_id(&mut self, order_id: i64) -> Option<(String, String)> {
+        let wrapper = self.orders.get_mut(&order_id)?;
+        wrapper.rev += 1;
+        let cl_ord_id = format!("{}{}-{}", self.prefix, order_id, wrapper.rev);
+        self.cl_ord_ids.insert(cl_ord_id.clone(), order_id);
+        Some((wrapper.cl_ord_id.clone(), cl_ord_id))
+    }
+
+    /// Removes the order whose request couldn't be sent.
+    pub fn remove(&mut self, order_id: i64) {
+        if self.orders.remove(&order_id).is_some() {
+            self.cl_ord_ids.retain(|_, id| *id != order_id);
+        }
+    }
+
+    fn find(&self, msg: &Message) -> Option<i64> {
+        msg.get(tag::CL_ORD_ID)
+            .and_then(|cl_ord_id| self.cl_ord_ids.get(cl_ord_id))
+            .or_else(|| {
+                msg.get(tag::ORIG_CL_ORD_ID)
+                    .and_then(|cl_ord_id| self.cl_ord_ids.get(cl_ord_id))
+            })
+            .copied()
+    }
+
+    /// Updates the order from an execution report. Returns the asset number, the updated order,
+    /// and the updated position if the report contains a fill.
+    pub fn update_execution_report(
+        &mut self,
+        msg: &Message,
+        dialect: &dyn Dialect,
+    ) -> Option<(usize, Order<()>, Option<f64>)> {
+        let order_id = match self.find(msg) {
+            Some(order_id) => order_id,
+            None => {
+                debug!(?msg, "Received an execution report of an unmanaged order.");
+                return None;
+            }
+        };
+        let status = dialect.order_status(msg)?;
+        let wrapper = self.orders.get_mut(&order_id)?;
+        let order = &mut wrapper.order;
+
+        let exch_timestamp = msg.get_timestamp(tag::TRANSACT_TIME).unwrap_or(0);
+        if exch_timestamp < order.exch_timestamp {
+            return None;
+        }
+        order.exch_timestamp = exch_timestamp;
+        order.status = status;
+        order.req = Status::None;
+        if let Some(qty) = msg.get_parsed(tag::ORDER_QTY) {
+            order.qty = qty;
+        }
+        if let Some(price) = msg.get_parsed::<f32>(tag::PRICE) {
+            order.price_tick = (price / order.tick_size).round() as i64;
+        }
+        if let Some(leaves_qty) = msg.get_parsed(tag::LEAVES_QTY) {
+            order.leaves_qty = leaves_qty;
+        }
+        // The replaced order continues with the ClOrdID of the replace request.
+        if let Some(cl_ord_id) = msg.get(tag::CL_ORD_ID) {
+            if self.cl_ord_ids.contains_key(cl_ord_id) {
+                wrapper.cl_ord_id = cl_ord_id.to_string();
+            }
+        }
+
+        let last_qty: f32 = msg.get_parsed(tag::LAST_QTY).unwrap_or(0.0);
+        let position = if last_qty > 0.0 {
+            let last_px: f32 = msg.get_parsed(tag::LAST_PX).unwrap_or(0.0);
+            order.exec_qty = last_qty;
+            order.exec_price_tick = (last_px / order.tick_size).round() as i64;
+            let position = self.position.entry(wrapper.asset_no).or_insert(0.0);
+            match order.side {
+                Side::Buy => *position += last_qty as f64,
+                Side::Sell => *position -= last_qty as f64,
+                _ => {}
+            }
+            Some(*position)
+        } else {
+            order.exec_qty = 0.0;
+            None
+        };
+
+        let asset_no = wrapper.asset_no;
+        let order = order.clone();
+        if !order.active() {
+            self.remove(order_id);
+        }
+        Some((asset_no, order, position))
+    }
+
+    /// Resolves the pending cancel or replace request that is rejected. The order remains in its
+    /// current state.
+    pub fn update_cancel_reject(&mut self, msg: &Message) -> Option<(usize, Order<()>)> {
+        let order_id = self.find(msg)?;
+        let wrapper = self.orders.get_mut(&order_id)?;
+        wrapper.order.req = Status::None;
+        Some((wrapper.asset_no, wrapper.order.clone()))
+    }
+}
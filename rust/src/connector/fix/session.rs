@@ -0,0 +1,252 @@
+use std::{fs, io::Error as IoError, ops::Range, sync::Arc};
+
+use chrono::Utc;
+use tracing::{error, warn};
+
+use crate::connector::fix::{
+    dialect::Dialect,
+    msg::{msg_type, tag, utc_timestamp, Message},
+    FixError,
+};
+
+/// The result of processing an inbound message by the session layer.
+pub enum Inbound {
+    /// The message is a session-level message, or a duplicate that was already processed.
+    Admin,
+    LoggedOn,
+    LoggedOut(Option<String>),
+    App(Message),
+}
+
+/// Maintains the FIX session state, such as the sequence numbers, independently of the
+/// transport. The sequence numbers can be persisted so that the session survives a restart;
+/// otherwise, the sequence numbers are reset on each logon.
+pub struct Session {
+    begin_string: String,
+    sender_comp_id: String,
+    target_comp_id: String,
+    heartbeat_interval: u64,
+    next_sender_seq: u64,
+    next_target_seq: u64,
+    // Ranges of inbound sequence numbers that were skipped and requested to be resent.
+    gaps: Vec<Range<u64>>,
+    store: Option<String>,
+    dialect: Arc<dyn Dialect>,
+}
+
+impl Session {
+    pub fn new(
+        sender_comp_id: &str,
+        target_comp_id: &str,
+        heartbeat_interval: u64,
+        dialect: Arc<dyn Dialect>,
+    ) -> Self {
+        Self {
+            begin_string: "FIX.4.4".to_string(),
+            sender_comp_id: sender_comp_id.to_string(),
+            target_comp_id: target_comp_id.to_string(),
+            heartbeat_interval,
+            next_sender_seq: 1,
+            next_target_seq: 1,
+            gaps: Vec::new(),
+            store: None,
+            dialect,
+        }
+    }
+
+    pub fn heartbeat_interval(&self) -> u64 {
+        self.heartbeat_interval
+    }
+
+    pub fn set_heartbeat_interval(&mut self, heartbeat_interval: u64) {
+        self.heartbeat_interval = heartbeat_interval;
+    }
+
+    pub fn dialect(&self) -> &dyn Dialect {
+        self.dialect.as_ref()
+    }
+
+    pub fn set_dialect(&mut self, dialect: Arc<dyn Dialect>) {
+        self.dialect = dialect;
+    }
+
+    /// Opens the sequence number store, which contains the next sender and target sequence
+    /// numbers separated by a space, and restores the sequence numbers from it if it exists.
+    pub fn open_store(&mut self, path: &str) -> Result<(), IoError> {
+        if let Ok(content) = fs::read_to_string(path) {
+            let mut tokens = content.split_whitespace();
+            if let (Some(Ok(sender)), Some(Ok(target))) = (
+                tokens.next().map(|s| s.parse()),
+                tokens.next().map(|s| s.parse()),
+            ) {
+                self.next_sender_seq = sender;
+                self.next_target_seq = target;
+            }
+        }
+        self.store = Some(path.to_string());
+        self.persist();
+        Ok(())
+    }
+
+    fn persist(&self) {
+        if let Some(path) = self.store.as_ref() {
+            let content = format!("{} {}", self.next_sender_seq, self.next_target_seq);
+            if let Err(error) = fs::write(path, content) {
+                error!(?error, %path, "Couldn't write the sequence number store.");
+            }
+        }
+    }
+
+    fn encode(&self, msg: &mut Message, seq: u64) -> Vec<u8> {
+        msg.set(tag::SENDER_COMP_ID, &self.sender_comp_id)
+            .set(tag::TARGET_COMP_ID, &self.target_comp_id)
+            .set(tag::MSG_SEQ_NUM, seq)
+            .set(tag::SENDING_TIME, utc_timestamp(Utc::now()));
+        msg.encode(&self.begin_string)
+    }
+
+    fn send_admin(&mut self, mut msg: Message) -> Vec<u8> {
+        let seq = self.next_sender_seq;
+        self.next_sender_seq += 1;
+        self.persist();
+        self.encode(&mut msg, seq)
+    }
+
+    /// Encodes an application message, applying the dialect.
+    pub fn send(&mut self, mut msg: Message) -> Vec<u8> {
+        self.dialect.outgoing(&mut msg);
+        self.send_admin(msg)
+    }
+
+    pub fn logon(&mut self) -> Vec<u8> {
+        let mut msg = Message::new(msg_type::LOGON);
+        msg.set(tag::ENCRYPT_METHOD, 0)
+            .set(tag::HEART_BT_INT, self.heartbeat_interval);
+        if self.store.is_none() {
+            self.next_sender_seq = 1;
+            self.next_target_seq = 1;
+            msg.set(tag::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        self.gaps.clear();
+        self.dialect.logon(&mut msg);
+        self.send_admin(msg)
+    }
+
+    pub fn logout(&mut self, text: Option<&str>) -> Vec<u8> {
+        let mut msg = Message::new(msg_type::LOGOUT);
+        if let Some(text) = text {
+            msg.set(tag::TEXT, text);
+        }
+        self.send_admin(msg)
+    }
+
+    pub fn heartbeat(&mut self, test_req_id: Option<&str>) -> Vec<u8> {
+        let mut msg = Message::new(msg_type::HEARTBEAT);
+        if let Some(test_req_id) = test_req_id {
+            msg.set(tag::TEST_REQ_ID, test_req_id);
+        }
+        self.send_admin(msg)
+    }
+
+    pub fn test_request(&mut self) -> Vec<u8> {
+        let mut msg = Message::new(msg_type::TEST_REQUEST);
+        msg.set(tag::TEST_REQ_ID, Utc::now().timestamp_millis());
+        self.send_admin(msg)
+    }
+
+    fn take_gap(&mut self, seq: u64) -> bool {
+        let pos = match self.gaps.iter().position(|gap| gap.contains(&seq)) {
+            Some(pos) => pos,
+            None => return false,
+        };
+        let gap = self.gaps.remove(pos);
+        if gap.start < seq {
+            self.gaps.push(gap.start..seq);
+        }
+        if seq + 1 < gap.end {
+            self.gaps.push((seq + 1)..gap.end);
+        }
+        true
+    }
+
+    /// Processes an inbound message. The session-level responses, such as a heartbeat in reply to
+    /// a test request or a resend request for a sequence gap, are appended to `out` to be sent.
+    pub fn on_message(
+        &mut self,
+        msg: Message,
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<Inbound, FixError> {
+        let seq: u64 = msg
+            .get_parsed(tag::MSG_SEQ_NUM)
+            .ok_or_else(|| FixError::InvalidMessage("missing MsgSeqNum".to_string()))?;
+
+        if msg.msg_type() == msg_type::SEQUENCE_RESET {
+            if let Some(new_seq) = msg.get_parsed::<u64>(tag::NEW_SEQ_NO) {
+                // The skipped messages are not going to be resent.
+                self.gaps.retain(|gap| gap.start >= new_seq);
+                if new_seq > self.next_target_seq {
+                    self.next_target_seq = new_seq;
+                    self.persist();
+                }
+            }
+            return Ok(Inbound::Admin);
+        }
+
+        if msg.msg_type() == msg_type::LOGON && msg.get(tag::RESET_SEQ_NUM_FLAG) == Some("Y") {
+            self.next_target_seq = seq;
+            self.gaps.clear();
+        }
+
+        if seq > self.next_target_seq {
+            warn!(
+                expected = self.next_target_seq,
+                received = seq,
+                "A sequence gap is detected. Requests the resend."
+            );
+            let mut resend = Message::new(msg_type::RESEND_REQUEST);
+            resend
+                .set(tag::BEGIN_SEQ_NO, self.next_target_seq)
+                .set(tag::END_SEQ_NO, 0);
+            out.push(self.send_admin(resend));
+            self.gaps.push(self.next_target_seq..seq);
+            self.next_target_seq = seq + 1;
+        } else if seq < self.next_target_seq {
+            if msg.get(tag::POSS_DUP_FLAG) != Some("Y") {
+                return Err(FixError::SequenceTooLow(seq, self.next_target_seq));
+            }
+            if !self.take_gap(seq) {
+                // The message was already processed.
+                return Ok(Inbound::Admin);
+            }
+        } else {
+            self.next_target_seq += 1;
+        }
+        self.persist();
+
+        match msg.msg_type() {
+            msg_type::LOGON => Ok(Inbound::LoggedOn),
+            msg_type::HEARTBEAT => Ok(Inbound::Admin),
+            msg_type::TEST_REQUEST => {
+                let test_req_id = msg.get(tag::TEST_REQ_ID).map(|id| id.to_string());
+                out.push(self.heartbeat(test_req_id.as_deref()));
+                Ok(Inbound::Admin)
+            }
+            msg_type::RESEND_REQUEST => {
+                // The application messages are never resent since stale orders must not reach
+                // the venue, so the requested range is gap-filled.
+                let begin_seq: u64 = msg.get_parsed(tag::BEGIN_SEQ_NO).unwrap_or(1);
+                let mut gap_fill = Message::new(msg_type::SEQUENCE_RESET);
+                gap_fill
+                    .set(tag::POSS_DUP_FLAG, "Y")
+                    .set(tag::GAP_FILL_FLAG, "Y")
+                    .set(tag::NEW_SEQ_NO, self.next_sender_seq);
+                out.push(self.encode(&mut gap_fill, begin_seq));
+                Ok(Inbound::Admin)
+            }
+            msg_type::LOGOUT => Ok(Inbound::LoggedOut(
+                msg.get(tag::TEXT).map(|text| text.to_string()),
+            )),
+            _ => Ok(Inbound::App(msg)),
+        }
+    }
+}
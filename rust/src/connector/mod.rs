@@ -3,6 +3,28 @@ use std::sync::mpsc::Sender;
 use crate::ty::{LiveEvent, Order};
 
 pub mod binancefutures;
+pub mod fix;
+pub mod okx;
+#[cfg(feature = "backtest")]
+pub mod paper;
+pub mod ratelimit;
+pub mod record;
+pub mod retry;
+
+/// How a connector handles the open orders that it finds on the exchange at startup, such as
+/// those left by a previous run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenOrderPolicy {
+    /// Cancels all the open orders of the registered symbols.
+    #[default]
+    Cancel,
+    /// Adopts the open orders created by the bot, those whose client order ids can be parsed into
+    /// order ids, into the bot's orders so that the strategy can manage them. The other open
+    /// orders are left as they are.
+    Adopt,
+    /// Aborts the startup if there is any open order of the registered symbols.
+    Abort,
+}
 
 pub trait Connector {
     fn add(
@@ -28,4 +50,20 @@ pub trait Connector {
         order: Order<()>,
         ev_tx: Sender<LiveEvent>,
     ) -> Result<(), anyhow::Error>;
+
+    /// Modifies the open order to the price and the quantity of `order`, which carries the new
+    /// ones.
+    fn modify(
+        &self,
+        an: usize,
+        order: Order<()>,
+        ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Returns `true` if the connector streams the books of its assets, which is `true` by
+    /// default. [`Bot::with_warm_sync`](crate::live::bot::Bot::with_warm_sync) waits for the books
+    /// only of such connectors.
+    fn streams_depth(&self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+
+use crate::connector::okx::msg::{self, Level};
+
+/// The number of the best levels on each side covered by the checksum of the `books` channel.
+const CHECKSUM_DEPTH: usize = 25;
+
+/// The local copy of the order book of the `books` channel, which keeps the original strings of
+/// the prices and the quantities since the checksum is calculated over them.
+pub struct Book {
+    tick_size: f64,
+    // key: price in ticks, value: (price, quantity)
+    bids: BTreeMap<i64, (String, String)>,
+    asks: BTreeMap<i64, (String, String)>,
+    // The sequence id of the last message applied, or `None` until a snapshot is received.
+    seq_id: Option<i64>,
+}
+
+type Changes = (Vec<(f32, f32)>, Vec<(f32, f32)>);
+
+impl Book {
+    pub fn new(tick_size: f32) -> Self {
+        Self {
+            tick_size: tick_size as f64,
+            bids: Default::default(),
+            asks: Default::default(),
+            seq_id: None,
+        }
+    }
+
+    /// Returns `true` if a snapshot has been received and no gap has been detected since.
+    pub fn is_synced(&self) -> bool {
+        self.seq_id.is_some()
+    }
+
+    /// Marks the book as out of sync so that the updates are ignored until the next snapshot.
+    /// The levels are kept so that the next snapshot can clear those that have vanished.
+    pub fn invalidate(&mut self) {
+        self.seq_id = None;
+    }
+
+    /// Applies a snapshot and returns the levels to be forwarded, which include the levels that
+    /// aren't in the snapshot with zero quantity so that the receiver's depth ends up the same as
+    /// the snapshot.
+    pub fn apply_snapshot(
+        &mut self,
+        data: &msg::Book,
+    ) -> Result<Changes, std::num::ParseFloatError> {
+        let bids = levels(&data.bids, self.tick_size)?;
+        let asks = levels(&data.asks, self.tick_size)?;
+        let changes = (
+            diff(&self.bids, &bids, self.tick_size),
+            diff(&self.asks, &asks, self.tick_size),
+        );
+        self.bids = bids;
+        self.asks = asks;
+        self.seq_id = Some(data.seq_id);
+        Ok(changes)
+    }
+
+    /// Applies an incremental update, in which a level with zero quantity is deleted, and returns
+    /// the levels to be forwarded. Returns `Ok(None)` if the update doesn't follow the last
+    /// message applied, in which case the book is out of sync.
+    pub fn apply_update(
+        &mut self,
+        data: &msg::Book,
+    ) -> Result<Option<Changes>, std::num::ParseFloatError> {
+        if self.seq_id != Some(data.prev_seq_id) {
+            return Ok(None);
+        }
+        let bids = update(&mut self.bids, &data.bids, self.tick_size)?;
+        let asks = update(&mut self.asks, &data.asks, self.tick_size)?;
+        self.seq_id = Some(data.seq_id);
+        Ok(Some((bids, asks)))
+    }
+
+    /// Calculates the checksum, which is the CRC-32 of the best 25 bids and asks interleaved as
+    /// `bid1_px:bid1_sz:ask1_px:ask1_sz:bid2_px:...`, as a signed 32-bit integer. If one side has
+    /// fewer levels, the rest of the other side follows.
+    pub fn checksum(&self) -> i32 {
+        let mut bids = self.bids.values().rev().take(CHECKSUM_DEPTH);
+        let mut asks = self.asks.values().take(CHECKSUM_DEPTH);
+        let mut fields: Vec<&str> = Vec::with_capacity(CHECKSUM_DEPTH * 4);
+        loop {
+            let bid = bids.next();
+            let ask = asks.next();
+            if bid.is_none() && ask.is_none() {
+                break;
+            }
+            for (px, sz) in bid.into_iter().chain(ask) {
+                fields.push(px);
+                fields.push(sz);
+            }
+        }
+        crc32fast::hash(fields.join(":").as_bytes()) as i32
+    }
+}
+
+fn price_tick(px: &str, tick_size: f64) -> Result<i64, std::num::ParseFloatError> {
+    Ok((px.parse::<f64>()? / tick_size).round() as i64)
+}
+
+fn levels(
+    data: &[Level],
+    tick_size: f64,
+) -> Result<BTreeMap<i64, (String, String)>, std::num::ParseFloatError> {
+    let mut levels = BTreeMap::new();
+    for level in data {
+        level.1.parse::<f32>()?;
+        levels.insert(
+            price_tick(&level.0, tick_size)?,
+            (level.0.clone(), level.1.clone()),
+        );
+    }
+    Ok(levels)
+}
+
+fn diff(
+    old: &BTreeMap<i64, (String, String)>,
+    new: &BTreeMap<i64, (String, String)>,
+    tick_size: f64,
+) -> Vec<(f32, f32)> {
+    let vanished = old
+        .keys()
+        .filter(|tick| !new.contains_key(tick))
+        .map(|tick| ((*tick as f64 * tick_size) as f32, 0.0));
+    let current = new
+        .values()
+        .map(|(px, sz)| (px.parse().unwrap(), sz.parse().unwrap()));
+    vanished.chain(current).collect()
+}
+
+fn update(
+    book: &mut BTreeMap<i64, (String, String)>,
+    data: &[Level],
+    tick_size: f64,
+) -> Result<Vec<(f32, f32)>, std::num::ParseFloatError> {
+    let mut changes = Vec::with_capacity(data.len());
+    for level in data {
+        let tick = price_tick(&level.0, tick_size)?;
+        let qty: f32 = level.1.parse()?;
+        if qty == 0.0 {
+            book.remove(&tick);
+        } else {
+            book.insert(tick, (level.0.clone(), level.1.clone()));
+        }
+        changes.push((level.0.parse()?, qty));
+    }
+    Ok(changes)
+}
@@ -0,0 +1,429 @@
+mod book;
+mod msg;
+mod ordermanager;
+mod ws;
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use serde_json::json;
+use thiserror::Error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite;
+use tracing::{debug, error, warn};
+
+use crate::{
+    connector::{
+        okx::{
+            ordermanager::{Op, OrderManager, OrderMgr},
+            ws::{connect_private, connect_public, Credentials},
+        },
+        Connector,
+    },
+    get_precision,
+    live::AssetInfo,
+    ty::{Error, ErrorType, LiveEvent, OrdType, Order, OrderResponse, Side, Status, TimeInForce},
+};
+
+#[derive(Error, Debug)]
+pub enum OkxError {
+    #[error("asset not found")]
+    AssetNotFound,
+    #[error("the private websocket is not logged in")]
+    NotLoggedIn,
+    #[error("the connector is already running")]
+    AlreadyRunning,
+    #[error("{0} is not supported")]
+    Unsupported(String),
+    #[error("login failed: {0} {1}")]
+    LoginFailed(String, String),
+    #[error("{0} failed: {1} {2}")]
+    OrderError(String, String, String),
+    #[error("the book of {0} is out of sync")]
+    BookOutOfSync(String),
+    #[error("{0}")]
+    Websocket(Box<tungstenite::Error>),
+}
+
+impl From<tungstenite::Error> for OkxError {
+    fn from(error: tungstenite::Error) -> Self {
+        OkxError::Websocket(Box::new(error))
+    }
+}
+
+/// An OKX v5 connector, which streams the `books` and `trades` channels from the public
+/// websocket, and places, cancels, and amends the orders and receives the `orders`, `positions`,
+/// and `account` channels over the private websocket after logging in with the API key.
+///
+/// The order book is validated by the checksum and the sequence ids of the `books` channel, and
+/// is resubscribed if it's out of sync, in which case [`ErrorType::Custom(1001)`](ErrorType) is
+/// reported with [`OkxError::BookOutOfSync`]. The positions are expected to be in the net mode.
+///
+/// The URLs are `wss://ws.okx.com:8443/ws/v5/public` and `wss://ws.okx.com:8443/ws/v5/private`,
+/// or `wss://wspap.okx.com:8443/ws/v5/public` and `wss://wspap.okx.com:8443/ws/v5/private` for
+/// the demo trading. The symbols are the instrument ids, such as `BTC-USDT-SWAP`, and the order
+/// quantities are in the instrument's units, which are the contracts for the derivatives.
+///
+/// Only the orders submitted since the connector starts are managed; the open orders left by a
+/// previous run are left as they are.
+pub struct Okx {
+    public_url: String,
+    private_url: String,
+    credentials: Credentials,
+    td_mode: String,
+    assets: HashMap<String, AssetInfo>,
+    inv_assets: HashMap<usize, AssetInfo>,
+    orders: OrderMgr,
+    logged_on: Arc<AtomicBool>,
+    req_tx: UnboundedSender<String>,
+    req_rx: Option<UnboundedReceiver<String>>,
+}
+
+impl Okx {
+    /// The `prefix` of the client order ids should be alphanumeric and at most 12 characters,
+    /// since OKX allows only up to 32 alphanumeric characters.
+    pub fn new(
+        public_url: &str,
+        private_url: &str,
+        prefix: &str,
+        api_key: &str,
+        secret: &str,
+        passphrase: &str,
+    ) -> Self {
+        let (req_tx, req_rx) = unbounded_channel();
+        Self {
+            public_url: public_url.to_string(),
+            private_url: private_url.to_string(),
+            credentials: Credentials {
+                api_key: api_key.to_string(),
+                secret: secret.to_string(),
+                passphrase: passphrase.to_string(),
+            },
+            td_mode: "cross".to_string(),
+            assets: Default::default(),
+            inv_assets: Default::default(),
+            orders: Arc::new(Mutex::new(OrderManager::new(prefix))),
+            logged_on: Default::default(),
+            req_tx,
+            req_rx: Some(req_rx),
+        }
+    }
+
+    /// Sets the trade mode of the orders, which is `cross` or `isolated` for the margin trading
+    /// and the derivatives, or `cash` for the spot trading. The default is `cross`.
+    pub fn td_mode(self, td_mode: &str) -> Self {
+        Self {
+            td_mode: td_mode.to_string(),
+            ..self
+        }
+    }
+
+    /// Reports that the request for the order failed. A failed new order is expired, while the
+    /// order of a failed cancel or amend request remains in its current state.
+    fn fail(
+        &self,
+        asset_no: usize,
+        mut order: Order<()>,
+        tx: &Sender<LiveEvent>,
+        error: OkxError,
+        new_order: bool,
+    ) {
+        order.req = Status::None;
+        if new_order {
+            self.orders.lock().unwrap().remove(order.order_id);
+            order.status = Status::Expired;
+        }
+        tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+            .unwrap();
+        tx.send(LiveEvent::Error(Error::with(ErrorType::OrderError, error)))
+            .unwrap();
+    }
+
+    /// Amends the price and the quantity of the order by the `amend-order` operation.
+    pub fn amend(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        price: f32,
+        qty: f32,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self
+            .inv_assets
+            .get(&asset_no)
+            .ok_or(OkxError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, OkxError::NotLoggedIn, false);
+            return Ok(());
+        }
+        let mut orders = self.orders.lock().unwrap();
+        let Some(cl_ord_id) = orders.get_cl_ord_id(order.order_id) else {
+            debug!(
+                order_id = order.order_id,
+                "clOrdId corresponding to order_id is not found."
+            );
+            return Ok(());
+        };
+        let req_id = orders.prepare_request(order.order_id, Op::Amend);
+        drop(orders);
+        let req = json!({
+            "id": req_id,
+            "op": Op::Amend.as_str(),
+            "args": [{
+                "instId": asset_info.symbol,
+                "clOrdId": cl_ord_id,
+                "newPx": format!("{:.prec$}", price, prec = get_precision(order.tick_size)),
+                "newSz": format!("{:.prec$}", qty, prec = get_precision(asset_info.lot_size)),
+            }],
+        });
+        self.req_tx.send(req.to_string())?;
+        Ok(())
+    }
+}
+
+/// Returns the OKX order type of the order, which encodes the time in force of a limit order.
+fn ord_type(order_type: OrdType, time_in_force: TimeInForce) -> Result<&'static str, OkxError> {
+    match (order_type, time_in_force) {
+        (OrdType::Market, _) => Ok("market"),
+        (OrdType::Limit, TimeInForce::GTC) => Ok("limit"),
+        (OrdType::Limit, TimeInForce::GTX) => Ok("post_only"),
+        (OrdType::Limit, TimeInForce::IOC) => Ok("ioc"),
+        (OrdType::Limit, TimeInForce::FOK) => Ok("fok"),
+        _ => Err(OkxError::Unsupported("order type".to_string())),
+    }
+}
+
+fn side(side: Side) -> Result<&'static str, OkxError> {
+    match side {
+        Side::Buy => Ok("buy"),
+        Side::Sell => Ok("sell"),
+        _ => Err(OkxError::Unsupported("side".to_string())),
+    }
+}
+
+/// Returns the assets that are settled in the currency, which is the quote currency of the
+/// instrument id, such as `USDT` for `BTC-USDT-SWAP`, or the base currency for the inverse
+/// contracts quoted in `USD`, such as `BTC` for `BTC-USD-SWAP`.
+fn settled_in<'a>(
+    assets: &'a HashMap<String, AssetInfo>,
+    ccy: &'a str,
+) -> impl Iterator<Item = &'a AssetInfo> + 'a {
+    assets
+        .iter()
+        .filter(move |(inst_id, _)| {
+            let mut parts = inst_id.split('-');
+            match (parts.next(), parts.next()) {
+                (Some(base), Some("USD")) => base == ccy,
+                (_, Some(quote)) => quote == ccy,
+                _ => false,
+            }
+        })
+        .map(|(_, asset_info)| asset_info)
+}
+
+impl Connector for Okx {
+    fn add(
+        &mut self,
+        asset_no: usize,
+        symbol: String,
+        tick_size: f32,
+        lot_size: f32,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = AssetInfo {
+            asset_no,
+            symbol: symbol.clone(),
+            tick_size,
+            lot_size,
+        };
+        self.assets.insert(symbol, asset_info.clone());
+        self.inv_assets.insert(asset_no, asset_info);
+        Ok(())
+    }
+
+    fn run(&mut self, ev_tx: Sender<LiveEvent>) -> Result<(), anyhow::Error> {
+        let mut req_rx = self.req_rx.take().ok_or(OkxError::AlreadyRunning)?;
+
+        let url = self.public_url.clone();
+        let assets = self.assets.clone();
+        let tx = ev_tx.clone();
+        tokio::spawn(async move {
+            let mut books = HashMap::new();
+            let mut error_count = 0;
+            loop {
+                if error_count > 0 {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+                if let Err(error) = connect_public(&url, &tx, &assets, &mut books).await {
+                    error!(?error, "A public websocket error occurred.");
+                    tx.send(LiveEvent::Error(Error::with(
+                        ErrorType::ConnectionInterrupted,
+                        error,
+                    )))
+                    .unwrap();
+                } else {
+                    tx.send(LiveEvent::Error(Error::new(
+                        ErrorType::ConnectionInterrupted,
+                    )))
+                    .unwrap();
+                }
+                error_count += 1;
+            }
+        });
+
+        let url = self.private_url.clone();
+        let credentials = self.credentials.clone();
+        let assets = self.assets.clone();
+        let orders = self.orders.clone();
+        let logged_on = self.logged_on.clone();
+        tokio::spawn(async move {
+            let mut error_count = 0;
+            loop {
+                if error_count > 0 {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+                let result = connect_private(
+                    &url,
+                    &credentials,
+                    &mut req_rx,
+                    &ev_tx,
+                    &assets,
+                    &orders,
+                    &logged_on,
+                )
+                .await;
+                logged_on.store(false, Ordering::Release);
+                if let Err(error) = result {
+                    error!(?error, "A private websocket error occurred.");
+                    ev_tx
+                        .send(LiveEvent::Error(Error::with(
+                            ErrorType::ConnectionInterrupted,
+                            error,
+                        )))
+                        .unwrap();
+                } else {
+                    ev_tx
+                        .send(LiveEvent::Error(Error::new(
+                            ErrorType::ConnectionInterrupted,
+                        )))
+                        .unwrap();
+                }
+                error_count += 1;
+            }
+        });
+        Ok(())
+    }
+
+    fn submit(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self
+            .inv_assets
+            .get(&asset_no)
+            .ok_or(OkxError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, OkxError::NotLoggedIn, true);
+            return Ok(());
+        }
+        let mut orders = self.orders.lock().unwrap();
+        let Some(cl_ord_id) = orders.prepare_cl_ord_id(asset_no, order.clone()) else {
+            drop(orders);
+            warn!(
+                ?order,
+                "The order id is already in use. This order request will be expired."
+            );
+            let mut order = order;
+            order.req = Status::None;
+            order.status = Status::Expired;
+            tx.send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                .unwrap();
+            return Ok(());
+        };
+        let codes = side(order.side).and_then(|side| {
+            ord_type(order.order_type, order.time_in_force).map(|ord_type| (side, ord_type))
+        });
+        let (side, ord_type) = match codes {
+            Ok(codes) => codes,
+            Err(error) => {
+                drop(orders);
+                self.fail(asset_no, order, &tx, error, true);
+                return Ok(());
+            }
+        };
+        let req_id = orders.prepare_request(order.order_id, Op::Order);
+        drop(orders);
+
+        let mut args = json!({
+            "instId": asset_info.symbol,
+            "tdMode": self.td_mode,
+            "clOrdId": cl_ord_id,
+            "side": side,
+            "ordType": ord_type,
+            "sz": format!("{:.prec$}", order.qty, prec = get_precision(asset_info.lot_size)),
+        });
+        if order.order_type == OrdType::Limit {
+            args["px"] = json!(format!(
+                "{:.prec$}",
+                order.price_tick as f32 * order.tick_size,
+                prec = get_precision(order.tick_size)
+            ));
+        }
+        let req = json!({ "id": req_id, "op": Op::Order.as_str(), "args": [args] });
+        self.req_tx.send(req.to_string())?;
+        Ok(())
+    }
+
+    fn cancel(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let asset_info = self
+            .inv_assets
+            .get(&asset_no)
+            .ok_or(OkxError::AssetNotFound)?;
+        if !self.logged_on.load(Ordering::Acquire) {
+            self.fail(asset_no, order, &tx, OkxError::NotLoggedIn, false);
+            return Ok(());
+        }
+        let mut orders = self.orders.lock().unwrap();
+        let Some(cl_ord_id) = orders.get_cl_ord_id(order.order_id) else {
+            debug!(
+                order_id = order.order_id,
+                "clOrdId corresponding to order_id is not found; \
+                this may be due to the order already being canceled or filled."
+            );
+            return Ok(());
+        };
+        let req_id = orders.prepare_request(order.order_id, Op::Cancel);
+        drop(orders);
+        let req = json!({
+            "id": req_id,
+            "op": Op::Cancel.as_str(),
+            "args": [{ "instId": asset_info.symbol, "clOrdId": cl_ord_id }],
+        });
+        self.req_tx.send(req.to_string())?;
+        Ok(())
+    }
+
+    fn modify(
+        &self,
+        asset_no: usize,
+        order: Order<()>,
+        tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let (price, qty) = (order.price(), order.qty);
+        self.amend(asset_no, order, price, qty, tx)
+    }
+}
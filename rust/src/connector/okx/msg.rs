@@ -0,0 +1,171 @@
+use serde::{
+    de::{Error, IgnoredAny},
+    Deserialize,
+    Deserializer,
+};
+
+fn from_str_to_i64<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    s.parse::<i64>().map_err(Error::custom)
+}
+
+fn from_str_to_f32<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    s.parse::<f32>().map_err(Error::custom)
+}
+
+/// OKX sends an empty string for a number that doesn't apply, such as the price of a market order
+/// or the fill price of an order without a fill, which is regarded as zero.
+fn from_str_to_f64_or_zero<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: &str = Deserialize::deserialize(deserializer)?;
+    if s.is_empty() {
+        return Ok(0.0);
+    }
+    s.parse::<f64>().map_err(Error::custom)
+}
+
+/// The subscription of a push message.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Arg {
+    pub channel: String,
+    #[serde(rename = "instId")]
+    pub inst_id: Option<String>,
+}
+
+/// The fields by which an incoming message is classified, which is a push message of a channel
+/// if it has `arg` without `event`, an event such as a login or a subscription result if it has
+/// `event`, or a response to an order operation if it has `op`.
+#[derive(Deserialize, Debug)]
+pub struct Header {
+    pub arg: Option<Arg>,
+    pub event: Option<String>,
+    pub op: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Push<T> {
+    pub arg: Arg,
+    pub action: Option<String>,
+    pub data: Vec<T>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Event {
+    pub event: String,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+}
+
+/// A price level of the `books` channel, which is the price, the quantity, the deprecated
+/// liquidated order count, and the order count.
+#[derive(Deserialize, Debug)]
+pub struct Level(pub String, pub String, IgnoredAny, IgnoredAny);
+
+#[derive(Deserialize, Debug)]
+pub struct Book {
+    pub asks: Vec<Level>,
+    pub bids: Vec<Level>,
+    #[serde(deserialize_with = "from_str_to_i64")]
+    pub ts: i64,
+    pub checksum: i32,
+    #[serde(rename = "prevSeqId")]
+    pub prev_seq_id: i64,
+    #[serde(rename = "seqId")]
+    pub seq_id: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Trade {
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+    #[serde(deserialize_with = "from_str_to_f32")]
+    pub px: f32,
+    #[serde(deserialize_with = "from_str_to_f32")]
+    pub sz: f32,
+    /// The side of the taker.
+    pub side: String,
+    #[serde(deserialize_with = "from_str_to_i64")]
+    pub ts: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Order {
+    #[serde(rename = "clOrdId")]
+    pub cl_ord_id: String,
+    #[serde(deserialize_with = "from_str_to_f64_or_zero")]
+    pub px: f64,
+    #[serde(deserialize_with = "from_str_to_f64_or_zero")]
+    pub sz: f64,
+    #[serde(rename = "fillPx", deserialize_with = "from_str_to_f64_or_zero")]
+    pub fill_px: f64,
+    #[serde(rename = "fillSz", deserialize_with = "from_str_to_f64_or_zero")]
+    pub fill_sz: f64,
+    #[serde(rename = "accFillSz", deserialize_with = "from_str_to_f64_or_zero")]
+    pub acc_fill_sz: f64,
+    pub state: String,
+    #[serde(rename = "uTime", deserialize_with = "from_str_to_i64")]
+    pub u_time: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Position {
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+    #[serde(deserialize_with = "from_str_to_f64_or_zero")]
+    pub pos: f64,
+    #[serde(rename = "avgPx", deserialize_with = "from_str_to_f64_or_zero")]
+    pub avg_px: f64,
+    #[serde(
+        rename = "markPx",
+        default,
+        deserialize_with = "from_str_to_f64_or_zero"
+    )]
+    pub mark_px: f64,
+    /// `net` in the net mode, or `long` or `short` in the long/short mode.
+    #[serde(rename = "posSide")]
+    pub pos_side: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Account {
+    pub details: Vec<BalanceDetail>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BalanceDetail {
+    pub ccy: String,
+    #[serde(rename = "cashBal", deserialize_with = "from_str_to_f64_or_zero")]
+    pub cash_bal: f64,
+}
+
+/// The response to an order operation, `order`, `cancel-order`, or `amend-order`, which has the
+/// id of the request.
+#[derive(Deserialize, Debug)]
+pub struct OpResponse {
+    pub id: String,
+    pub op: String,
+    pub code: String,
+    #[serde(default)]
+    pub msg: String,
+    #[serde(default)]
+    pub data: Vec<OpResult>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpResult {
+    #[serde(rename = "sCode")]
+    pub s_code: String,
+    #[serde(rename = "sMsg")]
+    pub s_msg: String,
+}
@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tracing::debug;
+
+use crate::{
+    connector::okx::msg,
+    ty::{Order, Status},
+};
+
+/// The order operations of the private websocket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Order,
+    Cancel,
+    Amend,
+}
+
+impl Op {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Order => "order",
+            Op::Cancel => "cancel-order",
+            Op::Amend => "amend-order",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct OrderWrapper {
+    asset_no: usize,
+    order: Order<()>,
+}
+
+pub type OrderMgr = Arc<Mutex<OrderManager>>;
+
+/// Maps the order ids to the client order ids, which are `{prefix}{order_id}` with the order id
+/// as an unsigned integer since OKX allows only alphanumeric characters, and the ids of the
+/// requests in flight to the orders.
+#[derive(Default, Debug)]
+pub struct OrderManager {
+    prefix: String,
+    orders: HashMap<i64, OrderWrapper>,
+    // key: request id
+    requests: HashMap<String, (i64, Op)>,
+    next_req_id: u64,
+}
+
+impl OrderManager {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn cl_ord_id(&self, order_id: i64) -> String {
+        format!("{}{}", self.prefix, order_id as u64)
+    }
+
+    fn parse_cl_ord_id(&self, cl_ord_id: &str) -> Option<i64> {
+        let order_id: u64 = cl_ord_id.strip_prefix(&self.prefix)?.parse().ok()?;
+        Some(order_id as i64)
+    }
+
+    /// Registers a new order and returns its client order id, or `None` if the order id is in
+    /// use.
+    pub fn prepare_cl_ord_id(&mut self, asset_no: usize, order: Order<()>) -> Option<String> {
+        if self.orders.contains_key(&order.order_id) {
+            return None;
+        }
+        let cl_ord_id = self.cl_ord_id(order.order_id);
+        self.orders
+            .insert(order.order_id, OrderWrapper { asset_no, order });
+        Some(cl_ord_id)
+    }
+
+    /// Returns the client order id of the order if it's managed.
+    pub fn get_cl_ord_id(&self, order_id: i64) -> Option<String> {
+        self.orders
+            .contains_key(&order_id)
+            .then(|| self.cl_ord_id(order_id))
+    }
+
+    /// Registers a request for the order and returns its request id, by which the response is
+    /// matched.
+    pub fn prepare_request(&mut self, order_id: i64, op: Op) -> String {
+        self.next_req_id += 1;
+        let req_id = self.next_req_id.to_string();
+        self.requests.insert(req_id.clone(), (order_id, op));
+        req_id
+    }
+
+    /// Removes the order whose request couldn't be sent.
+    pub fn remove(&mut self, order_id: i64) {
+        self.orders.remove(&order_id);
+    }
+
+    /// Forgets the requests in flight, whose responses are lost along with the connection.
+    pub fn clear_requests(&mut self) {
+        self.requests.clear();
+    }
+
+    /// Resolves the request by its response. The successful requests are reflected by the
+    /// `orders` channel, so only a failed request returns the order, which is expired if it's a
+    /// new order and otherwise remains in its current state.
+    pub fn update_op_response(&mut self, req_id: &str, failed: bool) -> Option<(usize, Order<()>)> {
+        let (order_id, op) = self.requests.remove(req_id)?;
+        if !failed {
+            return None;
+        }
+        let wrapper = self.orders.get_mut(&order_id)?;
+        let asset_no = wrapper.asset_no;
+        let mut order = wrapper.order.clone();
+        order.req = Status::None;
+        if op == Op::Order {
+            order.status = Status::Expired;
+            self.remove(order_id);
+        } else {
+            wrapper.order.req = Status::None;
+        }
+        Some((asset_no, order))
+    }
+
+    /// Updates the order from the `orders` channel. Returns the asset number and the updated
+    /// order.
+    pub fn update_from_ws(&mut self, data: &msg::Order) -> Option<(usize, Order<()>)> {
+        let order_id = match self.parse_cl_ord_id(&data.cl_ord_id) {
+            Some(order_id) if self.orders.contains_key(&order_id) => order_id,
+            _ => {
+                debug!(?data, "Received an order update of an unmanaged order.");
+                return None;
+            }
+        };
+        let status = match data.state.as_str() {
+            "live" => Status::New,
+            "partially_filled" => Status::PartiallyFilled,
+            "filled" => Status::Filled,
+            "canceled" | "mmp_canceled" => Status::Canceled,
+            state => {
+                debug!(%state, "Unsupported order state.");
+                return None;
+            }
+        };
+        let wrapper = self.orders.get_mut(&order_id)?;
+        let order = &mut wrapper.order;
+
+        let exch_timestamp = data.u_time * 1_000_000;
+        if exch_timestamp < order.exch_timestamp {
+            return None;
+        }
+        order.exch_timestamp = exch_timestamp;
+        order.status = status;
+        order.req = Status::None;
+        order.qty = data.sz as f32;
+        order.leaves_qty = (data.sz - data.acc_fill_sz) as f32;
+        if data.px > 0.0 {
+            order.price_tick = (data.px / order.tick_size as f64).round() as i64;
+        }
+        if data.fill_sz > 0.0 {
+            order.exec_qty = data.fill_sz as f32;
+            order.exec_price_tick = (data.fill_px / order.tick_size as f64).round() as i64;
+        } else {
+            order.exec_qty = 0.0;
+        }
+
+        let asset_no = wrapper.asset_no;
+        let order = order.clone();
+        if !order.active() {
+            self.remove(order_id);
+        }
+        Some((asset_no, order))
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use futures_util::{SinkExt, StreamExt};
+use hmac::{Hmac, KeyInit, Mac};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use sha2::Sha256;
+use tokio::{select, sync::mpsc::UnboundedReceiver, time};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    connector::okx::{
+        book::Book,
+        msg::{self, Header, Push},
+        ordermanager::OrderMgr,
+        settled_in,
+        OkxError,
+    },
+    live::{
+        clock,
+        latency::{self, Stage},
+        AssetInfo,
+    },
+    ty::{
+        Balance,
+        Depth,
+        Error,
+        ErrorType,
+        LiveEvent,
+        OrderResponse,
+        Position,
+        SyncKind,
+        Synced,
+        Trade,
+        BUY,
+        SELL,
+    },
+};
+
+/// OKX closes the connection if nothing is received for 30 seconds.
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+/// The credentials of the API key, which log in to the private websocket.
+#[derive(Clone)]
+pub struct Credentials {
+    pub api_key: String,
+    pub secret: String,
+    pub passphrase: String,
+}
+
+impl Credentials {
+    /// Builds the login request, whose signature is the Base64-encoded HMAC SHA256 of the
+    /// timestamp in seconds, the method `GET`, and the path `/users/self/verify`.
+    fn login(&self) -> String {
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).unwrap();
+        mac.update(format!("{timestamp}GET/users/self/verify").as_bytes());
+        let sign = STANDARD.encode(mac.finalize().into_bytes());
+        json!({
+            "op": "login",
+            "args": [{
+                "apiKey": self.api_key,
+                "passphrase": self.passphrase,
+                "timestamp": timestamp,
+                "sign": sign,
+            }],
+        })
+        .to_string()
+    }
+}
+
+fn parse<T: DeserializeOwned>(text: &str) -> Option<T> {
+    match serde_json::from_str::<T>(text) {
+        Ok(data) => Some(data),
+        Err(error) => {
+            error!(?error, %text, "Couldn't parse the message.");
+            None
+        }
+    }
+}
+
+fn books_arg(inst_id: &str) -> serde_json::Value {
+    json!({ "channel": "books", "instId": inst_id })
+}
+
+/// Connects to the public websocket and forwards the `books` and `trades` channels of the assets.
+/// The books are kept across the connections so that the snapshot at a reconnection clears the
+/// levels that have vanished in the meantime.
+pub async fn connect_public(
+    url: &str,
+    ev_tx: &Sender<LiveEvent>,
+    assets: &HashMap<String, AssetInfo>,
+    books: &mut HashMap<String, Book>,
+) -> Result<(), OkxError> {
+    let (ws_stream, _) = connect_async(url).await?;
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut args = Vec::with_capacity(assets.len() * 2);
+    for inst_id in assets.keys() {
+        args.push(books_arg(inst_id));
+        args.push(json!({ "channel": "trades", "instId": inst_id }));
+    }
+    write
+        .send(Message::Text(
+            json!({ "op": "subscribe", "args": args }).to_string(),
+        ))
+        .await?;
+    for book in books.values_mut() {
+        book.invalidate();
+    }
+    // The books that have received a snapshot but no update yet, which are reported synced on
+    // the first update in sync.
+    let mut awaiting_update = HashSet::new();
+
+    let mut ping = time::interval(PING_INTERVAL);
+    loop {
+        select! {
+            _ = ping.tick() => {
+                write.send(Message::Text("ping".to_string())).await?;
+            }
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Ping(data))) => {
+                        write.send(Message::Pong(data)).await?;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(close_frame))) => {
+                        info!(?close_frame, "close");
+                        return Ok(());
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => return Err(error.into()),
+                    None => return Ok(()),
+                };
+                if text == "pong" {
+                    continue;
+                }
+                let recv_ts = clock::now();
+                let Some(header) = parse::<Header>(&text) else {
+                    continue;
+                };
+                if let Some(event) = header.event {
+                    if event == "error" {
+                        let event = parse::<msg::Event>(&text);
+                        error!(?event, "The public websocket returned an error.");
+                    }
+                    continue;
+                }
+                let Some(arg) = header.arg else {
+                    continue;
+                };
+                match arg.channel.as_str() {
+                    "books" => {
+                        let Some(push) = parse::<Push<msg::Book>>(&text) else {
+                            continue;
+                        };
+                        let inst_id = push.arg.inst_id.unwrap_or_default();
+                        let Some(asset_info) = assets.get(&inst_id) else {
+                            continue;
+                        };
+                        let book = books
+                            .entry(inst_id.clone())
+                            .or_insert_with(|| Book::new(asset_info.tick_size));
+                        let snapshot = push.action.as_deref() == Some("snapshot");
+                        for data in push.data {
+                            let changes = if snapshot {
+                                book.apply_snapshot(&data).map(Some)
+                            } else if book.is_synced() {
+                                book.apply_update(&data)
+                            } else {
+                                // Waits for the snapshot of the resubscription.
+                                continue;
+                            };
+                            let changes = match changes {
+                                Ok(changes) => changes,
+                                Err(error) => {
+                                    error!(?error, "Couldn't parse the books channel.");
+                                    None
+                                }
+                            };
+                            let in_sync = match changes {
+                                Some((bids, asks)) => {
+                                    let local_ts = clock::now();
+                                    latency::record(Stage::FrameParse, local_ts - recv_ts);
+                                    // The changes are forwarded even if the checksum doesn't
+                                    // match so that the bot's depth stays the same as the local
+                                    // book, which the snapshot of the resubscription corrects.
+                                    ev_tx
+                                        .send(LiveEvent::Depth(Depth {
+                                            asset_no: asset_info.asset_no,
+                                            exch_ts: data.ts * 1_000_000,
+                                            local_ts,
+                                            bids,
+                                            asks,
+                                        }))
+                                        .unwrap();
+                                    book.checksum() == data.checksum
+                                }
+                                None => false,
+                            };
+                            if in_sync && snapshot {
+                                awaiting_update.insert(inst_id.clone());
+                            } else if in_sync && awaiting_update.remove(&inst_id) {
+                                ev_tx
+                                    .send(LiveEvent::Synced(Synced {
+                                        asset_no: asset_info.asset_no,
+                                        kind: SyncKind::Book,
+                                    }))
+                                    .unwrap();
+                            }
+                            if !in_sync {
+                                warn!(%inst_id, "The book is out of sync. Resubscribes.");
+                                book.invalidate();
+                                awaiting_update.remove(&inst_id);
+                                // 1001 indicates the book is out of sync and being resubscribed.
+                                ev_tx
+                                    .send(LiveEvent::Error(Error::with(
+                                        ErrorType::Custom(1001),
+                                        OkxError::BookOutOfSync(inst_id.clone()),
+                                    )))
+                                    .unwrap();
+                                for op in ["unsubscribe", "subscribe"] {
+                                    write
+                                        .send(Message::Text(
+                                            json!({ "op": op, "args": [books_arg(&inst_id)] })
+                                                .to_string(),
+                                        ))
+                                        .await?;
+                                }
+                                break;
+                            }
+                        }
+                    }
+                    "trades" => {
+                        let Some(push) = parse::<Push<msg::Trade>>(&text) else {
+                            continue;
+                        };
+                        let local_ts = clock::now();
+                        latency::record(Stage::FrameParse, local_ts - recv_ts);
+                        for data in push.data {
+                            let Some(asset_info) = assets.get(&data.inst_id) else {
+                                continue;
+                            };
+                            ev_tx
+                                .send(LiveEvent::Trade(Trade {
+                                    asset_no: asset_info.asset_no,
+                                    exch_ts: data.ts * 1_000_000,
+                                    local_ts,
+                                    side: if data.side == "buy" {
+                                        BUY as i8
+                                    } else {
+                                        SELL as i8
+                                    },
+                                    price: data.px,
+                                    qty: data.sz,
+                                }))
+                                .unwrap();
+                        }
+                    }
+                    channel => {
+                        debug!(%channel, "Unhandled channel.");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Connects to the private websocket, logs in, subscribes to the `orders`, `positions`, and
+/// `account` channels, and sends the order requests once logged in.
+pub async fn connect_private(
+    url: &str,
+    credentials: &Credentials,
+    req_rx: &mut UnboundedReceiver<String>,
+    ev_tx: &Sender<LiveEvent>,
+    assets: &HashMap<String, AssetInfo>,
+    orders: &OrderMgr,
+    logged_on: &AtomicBool,
+) -> Result<(), OkxError> {
+    let (ws_stream, _) = connect_async(url).await?;
+    let (mut write, mut read) = ws_stream.split();
+    write.send(Message::Text(credentials.login())).await?;
+    orders.lock().unwrap().clear_requests();
+
+    // The first push of the positions channel is the snapshot of the open positions, in which
+    // the assets without a position are missing.
+    let mut positions_synced = false;
+    let mut ping = time::interval(PING_INTERVAL);
+    loop {
+        select! {
+            _ = ping.tick() => {
+                write.send(Message::Text("ping".to_string())).await?;
+            }
+            req = req_rx.recv(), if logged_on.load(Ordering::Acquire) => {
+                let Some(req) = req else {
+                    return Ok(());
+                };
+                let write_ts = clock::now();
+                write.send(Message::Text(req)).await?;
+                latency::record(Stage::SocketWrite, clock::now() - write_ts);
+            }
+            message = read.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Ping(data))) => {
+                        write.send(Message::Pong(data)).await?;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(close_frame))) => {
+                        info!(?close_frame, "close");
+                        return Ok(());
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => return Err(error.into()),
+                    None => return Ok(()),
+                };
+                if text == "pong" {
+                    continue;
+                }
+                let Some(header) = parse::<Header>(&text) else {
+                    continue;
+                };
+                if header.event.is_some() {
+                    let Some(event) = parse::<msg::Event>(&text) else {
+                        continue;
+                    };
+                    let channel = header.arg.as_ref().map(|arg| arg.channel.as_str());
+                    match event.event.as_str() {
+                        "login" if event.code == "0" => {
+                            info!("Logged in to the private websocket.");
+                            let args = json!([
+                                { "channel": "orders", "instType": "ANY" },
+                                { "channel": "positions", "instType": "ANY" },
+                                { "channel": "account" },
+                            ]);
+                            write
+                                .send(Message::Text(
+                                    json!({ "op": "subscribe", "args": args }).to_string(),
+                                ))
+                                .await?;
+                            logged_on.store(true, Ordering::Release);
+                        }
+                        // The order updates are delivered once the orders channel is subscribed.
+                        "subscribe" if channel == Some("orders") => {
+                            for asset_info in assets.values() {
+                                ev_tx
+                                    .send(LiveEvent::Synced(Synced {
+                                        asset_no: asset_info.asset_no,
+                                        kind: SyncKind::UserData,
+                                    }))
+                                    .unwrap();
+                            }
+                        }
+                        "error" if !logged_on.load(Ordering::Acquire) => {
+                            return Err(OkxError::LoginFailed(event.code, event.msg));
+                        }
+                        "error" => {
+                            error!(?event, "The private websocket returned an error.");
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                if header.op.is_some() {
+                    let Some(resp) = parse::<msg::OpResponse>(&text) else {
+                        continue;
+                    };
+                    let result = resp.data.first();
+                    let failed = resp.code != "0" || result.is_some_and(|r| r.s_code != "0");
+                    let order = orders.lock().unwrap().update_op_response(&resp.id, failed);
+                    if failed {
+                        if let Some((asset_no, order)) = order {
+                            ev_tx
+                                .send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                                .unwrap();
+                        }
+                        let (code, msg) = match result {
+                            Some(result) if result.s_code != "0" => {
+                                (result.s_code.clone(), result.s_msg.clone())
+                            }
+                            _ => (resp.code, resp.msg),
+                        };
+                        ev_tx
+                            .send(LiveEvent::Error(Error::with(
+                                ErrorType::OrderError,
+                                OkxError::OrderError(resp.op, code, msg),
+                            )))
+                            .unwrap();
+                    }
+                    continue;
+                }
+                let Some(arg) = header.arg else {
+                    continue;
+                };
+                match arg.channel.as_str() {
+                    "orders" => {
+                        let Some(push) = parse::<Push<msg::Order>>(&text) else {
+                            continue;
+                        };
+                        for data in push.data {
+                            let result = orders.lock().unwrap().update_from_ws(&data);
+                            if let Some((asset_no, order)) = result {
+                                ev_tx
+                                    .send(LiveEvent::Order(OrderResponse { asset_no, order }))
+                                    .unwrap();
+                            }
+                        }
+                    }
+                    "positions" => {
+                        let Some(push) = parse::<Push<msg::Position>>(&text) else {
+                            continue;
+                        };
+                        let mut updated: HashMap<&str, f64> = HashMap::new();
+                        let mut entry_prices: HashMap<&str, f64> = HashMap::new();
+                        let mut mark_prices: HashMap<&str, f64> = HashMap::new();
+                        for data in push.data.iter() {
+                            if !assets.contains_key(&data.inst_id) {
+                                continue;
+                            }
+                            let qty = match data.pos_side.as_str() {
+                                "short" => -data.pos.abs(),
+                                _ => data.pos,
+                            };
+                            *updated.entry(&data.inst_id).or_insert(0.0) += qty;
+                            if data.avg_px > 0.0 {
+                                entry_prices.insert(&data.inst_id, data.avg_px);
+                            }
+                            if data.mark_px > 0.0 {
+                                mark_prices.insert(&data.inst_id, data.mark_px);
+                            }
+                        }
+                        if !positions_synced {
+                            for inst_id in assets.keys() {
+                                updated.entry(inst_id).or_insert(0.0);
+                            }
+                            positions_synced = true;
+                        }
+                        for (inst_id, qty) in updated {
+                            let asset_info = assets.get(inst_id).unwrap();
+                            ev_tx
+                                .send(LiveEvent::Position(Position {
+                                    asset_no: asset_info.asset_no,
+                                    symbol: inst_id.to_string(),
+                                    qty,
+                                    entry_price: entry_prices.get(inst_id).copied(),
+                                    mark_price: mark_prices.get(inst_id).copied(),
+                                }))
+                                .unwrap();
+                        }
+                    }
+                    "account" => {
+                        let Some(push) = parse::<Push<msg::Account>>(&text) else {
+                            continue;
+                        };
+                        for data in push.data {
+                            for detail in data.details {
+                                for asset_info in settled_in(assets, &detail.ccy) {
+                                    ev_tx
+                                        .send(LiveEvent::Balance(Balance {
+                                            asset_no: asset_info.asset_no,
+                                            asset: detail.ccy.clone(),
+                                            balance: detail.cash_bal,
+                                        }))
+                                        .unwrap();
+                                }
+                            }
+                        }
+                    }
+                    channel => {
+                        debug!(%channel, "Unhandled channel.");
+                    }
+                }
+            }
+        }
+    }
+}
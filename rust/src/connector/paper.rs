@@ -0,0 +1,522 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use tracing::error;
+
+use crate::{
+    backtest::{
+        assettype::LinearAsset,
+        models::{LatencyModel, QueueModel},
+        order::OrderBus,
+        proc::{NoPartialFillExchange, Processor},
+        profile::{GtxPolicy, ModifyPolicy},
+        reader::{Cache, Reader, EXCH_EVENT},
+        state::State,
+    },
+    connector::Connector,
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    live::clock,
+    ty::{
+        Depth,
+        Error as ErrorEvent,
+        ErrorType,
+        Event,
+        LiveEvent,
+        Order,
+        OrderResponse,
+        Position,
+        Status,
+        Trade,
+        BUY,
+        DEPTH_EVENT,
+        SELL,
+        TRADE_EVENT,
+    },
+};
+
+/// An input of the emulated exchange.
+enum Input {
+    /// A market event from the wrapped connector.
+    Feed(LiveEvent),
+    /// An order request from the bot.
+    Request(usize, Order<()>),
+}
+
+struct PaperAsset {
+    asset_no: usize,
+    symbol: String,
+    tick_size: f32,
+    lot_size: f32,
+}
+
+/// A connector for paper trading, which takes the market data from the wrapped connector and
+/// emulates the exchange locally by [`NoPartialFillExchange`] with the latency and queue models
+/// of the backtest, so that a strategy can be validated against the real-time data without
+/// risking capital. The wrapped connector receives no order requests; its order, position, and
+/// balance events are dropped, and the others are passed through.
+///
+/// The emulated exchange runs on its own thread against the local clock. Since the market data
+/// reaches it only when it's received, the exchange lags the venue by the feed latency, and an
+/// order reaches it after the entry latency of the latency model from when the connector
+/// receives the request. The responses reach the bot after the response latency. The position of
+/// each asset starts from zero, and is reported by a position event after each fill.
+pub struct PaperConnector<C, Q, LM, QM> {
+    inner: C,
+    assets: Vec<PaperAsset>,
+    latency_model: LM,
+    queue_model: Option<Box<dyn Fn() -> QM + Send>>,
+    gtx_policy: GtxPolicy,
+    modify_policy: ModifyPolicy,
+    req_tx: Option<Sender<Input>>,
+    _q_marker: PhantomData<Q>,
+}
+
+impl<C, Q, LM, QM> PaperConnector<C, Q, LM, QM>
+where
+    C: Connector,
+    Q: Clone + Default + Send + 'static,
+    LM: LatencyModel + Clone + Send + 'static,
+    QM: QueueModel<Q, HashMapMarketDepth> + 'static,
+{
+    /// Wraps the connector that provides the market data. `queue_model` creates the queue model
+    /// of each asset.
+    pub fn new<F>(inner: C, latency_model: LM, queue_model: F) -> Self
+    where
+        F: Fn() -> QM + Send + 'static,
+    {
+        Self {
+            inner,
+            assets: Vec::new(),
+            latency_model,
+            queue_model: Some(Box::new(queue_model)),
+            gtx_policy: Default::default(),
+            modify_policy: Default::default(),
+            req_tx: None,
+            _q_marker: Default::default(),
+        }
+    }
+
+    /// Sets what the emulated exchange does with a post-only order that would take liquidity.
+    /// See [`GtxPolicy`].
+    pub fn gtx_policy(self, gtx_policy: GtxPolicy) -> Self {
+        Self { gtx_policy, ..self }
+    }
+
+    /// Sets whether a modified order keeps its queue position at the emulated exchange. See
+    /// [`ModifyPolicy`].
+    pub fn modify_policy(self, modify_policy: ModifyPolicy) -> Self {
+        Self {
+            modify_policy,
+            ..self
+        }
+    }
+
+    fn request(&self, an: usize, order: Order<()>) -> Result<(), anyhow::Error> {
+        let req_tx = self
+            .req_tx
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("the paper connector isn't running"))?;
+        req_tx
+            .send(Input::Request(an, order))
+            .map_err(|_| anyhow::anyhow!("the emulated exchange has stopped"))
+    }
+}
+
+impl<C, Q, LM, QM> Connector for PaperConnector<C, Q, LM, QM>
+where
+    C: Connector,
+    Q: Clone + Default + Send + 'static,
+    LM: LatencyModel + Clone + Send + 'static,
+    QM: QueueModel<Q, HashMapMarketDepth> + 'static,
+{
+    fn add(
+        &mut self,
+        an: usize,
+        symbol: String,
+        tick_size: f32,
+        lot_size: f32,
+    ) -> Result<(), anyhow::Error> {
+        self.inner.add(an, symbol.clone(), tick_size, lot_size)?;
+        self.assets.push(PaperAsset {
+            asset_no: an,
+            symbol,
+            tick_size,
+            lot_size,
+        });
+        Ok(())
+    }
+
+    fn run(&mut self, tx: Sender<LiveEvent>) -> Result<(), anyhow::Error> {
+        let queue_model = self
+            .queue_model
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("the paper connector is already running"))?;
+        let (feed_tx, feed_rx) = channel();
+        self.inner.run(feed_tx)?;
+
+        let (input_tx, input_rx) = channel();
+        let feed_input_tx = input_tx.clone();
+        thread::spawn(move || {
+            for ev in feed_rx {
+                if feed_input_tx.send(Input::Feed(ev)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let assets: Vec<_> = self
+            .assets
+            .iter()
+            .map(|asset| {
+                (
+                    asset.asset_no,
+                    asset.symbol.clone(),
+                    asset.tick_size,
+                    asset.lot_size,
+                )
+            })
+            .collect();
+        let latency_model = self.latency_model.clone();
+        let (gtx_policy, modify_policy) = (self.gtx_policy, self.modify_policy);
+        thread::spawn(move || {
+            let mut venue = PaperVenue::new(latency_model, gtx_policy, modify_policy);
+            for (asset_no, symbol, tick_size, lot_size) in assets {
+                venue.add(asset_no, symbol, tick_size, lot_size, queue_model());
+            }
+            venue.run(input_rx, tx);
+        });
+        self.req_tx = Some(input_tx);
+        Ok(())
+    }
+
+    fn submit(
+        &self,
+        an: usize,
+        order: Order<()>,
+        _ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        self.request(an, order)
+    }
+
+    fn cancel(
+        &self,
+        an: usize,
+        order: Order<()>,
+        _ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        self.request(an, order)
+    }
+
+    fn modify(
+        &self,
+        an: usize,
+        order: Order<()>,
+        _ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        self.request(an, order)
+    }
+
+    fn streams_depth(&self) -> bool {
+        self.inner.streams_depth()
+    }
+}
+
+type PaperExchange<Q, LM, QM> = NoPartialFillExchange<LinearAsset, Q, LM, QM, HashMapMarketDepth>;
+
+struct VenueAsset<Q, LM, QM>
+where
+    Q: Clone + Default,
+    LM: LatencyModel,
+    QM: QueueModel<Q, HashMapMarketDepth>,
+{
+    symbol: String,
+    exch: PaperExchange<Q, LM, QM>,
+    // The order requests to the exchange and the responses from it.
+    orders_to_exch: OrderBus<Q>,
+    orders_from_exch: OrderBus<Q>,
+    position: f64,
+}
+
+/// The exchange emulated on the live feed, which runs on its own thread.
+struct PaperVenue<Q, LM, QM>
+where
+    Q: Clone + Default,
+    LM: LatencyModel,
+    QM: QueueModel<Q, HashMapMarketDepth>,
+{
+    // key: asset_no
+    assets: HashMap<usize, VenueAsset<Q, LM, QM>>,
+    // The latency model of the order entry, while the exchanges apply the response latency.
+    latency_model: LM,
+    gtx_policy: GtxPolicy,
+    modify_policy: ModifyPolicy,
+}
+
+impl<Q, LM, QM> PaperVenue<Q, LM, QM>
+where
+    Q: Clone + Default,
+    LM: LatencyModel + Clone,
+    QM: QueueModel<Q, HashMapMarketDepth>,
+{
+    fn new(latency_model: LM, gtx_policy: GtxPolicy, modify_policy: ModifyPolicy) -> Self {
+        Self {
+            assets: HashMap::new(),
+            latency_model,
+            gtx_policy,
+            modify_policy,
+        }
+    }
+
+    fn add(&mut self, asset_no: usize, symbol: String, tick_size: f32, lot_size: f32, qm: QM) {
+        let orders_to_exch = OrderBus::new();
+        let orders_from_exch = OrderBus::new();
+        let mut exch = NoPartialFillExchange::new(
+            Reader::new(Cache::new()),
+            HashMapMarketDepth::new(tick_size, lot_size),
+            State::new(LinearAsset::new(1.0)),
+            self.latency_model.clone(),
+            qm,
+            orders_from_exch.clone(),
+            orders_to_exch.clone(),
+        );
+        exch.gtx_policy = self.gtx_policy;
+        exch.modify_policy = self.modify_policy;
+        self.assets.insert(
+            asset_no,
+            VenueAsset {
+                symbol,
+                exch,
+                orders_to_exch,
+                orders_from_exch,
+                position: 0.0,
+            },
+        );
+    }
+
+    fn run(mut self, rx: Receiver<Input>, tx: Sender<LiveEvent>) {
+        // The positions start from zero, which also completes the bot's startup sync.
+        for (asset_no, asset) in self.assets.iter() {
+            let position = Position {
+                asset_no: *asset_no,
+                symbol: asset.symbol.clone(),
+                qty: 0.0,
+                entry_price: None,
+                mark_price: None,
+            };
+            if tx.send(LiveEvent::Position(position)).is_err() {
+                return;
+            }
+        }
+        loop {
+            let input = match self.next_timestamp() {
+                Some(timestamp) => {
+                    let timeout = Duration::from_nanos((timestamp - clock::now()).max(0) as u64);
+                    match rx.recv_timeout(timeout) {
+                        Ok(input) => Some(input),
+                        Err(RecvTimeoutError::Timeout) => None,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                None => match rx.recv() {
+                    Ok(input) => Some(input),
+                    Err(_) => return,
+                },
+            };
+            let now = clock::now();
+            // The orders and the responses due by now go before the input.
+            if self.process_orders(now, &tx).is_err() {
+                return;
+            }
+            match input {
+                Some(Input::Feed(ev)) => match ev {
+                    LiveEvent::Depth(_) | LiveEvent::Trade(_) => {
+                        self.on_market(&ev);
+                        if tx.send(ev).is_err() {
+                            return;
+                        }
+                    }
+                    LiveEvent::Order(_) | LiveEvent::Position(_) | LiveEvent::Balance(_) => {}
+                    ev => {
+                        if tx.send(ev).is_err() {
+                            return;
+                        }
+                    }
+                },
+                Some(Input::Request(asset_no, order)) => self.on_request(asset_no, order, now),
+                None => {}
+            }
+            if self.process_orders(now, &tx).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Returns the earliest timestamp at which an order reaches the exchange or a response
+    /// reaches the bot.
+    fn next_timestamp(&self) -> Option<i64> {
+        self.assets
+            .values()
+            .map(|asset| {
+                asset
+                    .exch
+                    .frontmost_recv_order_timestamp()
+                    .min(asset.orders_from_exch.frontmost_timestamp())
+            })
+            .min()
+            .filter(|timestamp| *timestamp != i64::MAX)
+    }
+
+    fn on_market(&mut self, ev: &LiveEvent) {
+        let (asset_no, rows) = match ev {
+            LiveEvent::Depth(Depth {
+                asset_no,
+                local_ts,
+                bids,
+                asks,
+                ..
+            }) => {
+                let row = |side: i64, (px, qty): &(f32, f32)| Event {
+                    ev: EXCH_EVENT | DEPTH_EVENT | side,
+                    exch_ts: *local_ts,
+                    local_ts: *local_ts,
+                    px: *px,
+                    qty: *qty,
+                };
+                let rows: Vec<_> = bids
+                    .iter()
+                    .map(|level| row(BUY, level))
+                    .chain(asks.iter().map(|level| row(SELL, level)))
+                    .collect();
+                (*asset_no, rows)
+            }
+            LiveEvent::Trade(Trade {
+                asset_no,
+                local_ts,
+                side,
+                price,
+                qty,
+                ..
+            }) => {
+                let side = match side {
+                    1 => BUY,
+                    -1 => SELL,
+                    _ => return,
+                };
+                let row = Event {
+                    ev: EXCH_EVENT | TRADE_EVENT | side,
+                    exch_ts: *local_ts,
+                    local_ts: *local_ts,
+                    px: *price,
+                    qty: *qty,
+                };
+                (*asset_no, vec![row])
+            }
+            _ => return,
+        };
+        let Some(asset) = self.assets.get_mut(&asset_no) else {
+            return;
+        };
+        for row in rows {
+            if let Err(error) = asset.exch.apply_row(row) {
+                error!(?error, %asset_no, "The emulated exchange couldn't apply the market event.");
+            }
+        }
+    }
+
+    fn on_request(&mut self, asset_no: usize, order: Order<()>, now: i64) {
+        let Some(asset) = self.assets.get_mut(&asset_no) else {
+            error!(%asset_no, "The asset isn't added to the paper connector.");
+            return;
+        };
+        let mut order = with_q(order, Q::default());
+        if order.req == Status::New {
+            // The bot leaves it to the venue, while the exchange expects it to be set by the local.
+            order.leaves_qty = order.qty;
+        }
+        match self.latency_model.entry(now, &order) {
+            Ok(latency) => asset.orders_to_exch.append(order, now + latency),
+            Err(error) => error!(?error, "Couldn't compute the entry latency."),
+        }
+    }
+
+    /// Processes the orders that reach the exchange by `now` and delivers the responses that
+    /// reach the bot by then. Returns `Err` if the bot has stopped.
+    fn process_orders(&mut self, now: i64, tx: &Sender<LiveEvent>) -> Result<(), ()> {
+        for (asset_no, asset) in self.assets.iter_mut() {
+            loop {
+                let recv_timestamp = asset.exch.frontmost_recv_order_timestamp();
+                if recv_timestamp > now {
+                    break;
+                }
+                if let Err(error) = asset.exch.process_recv_order(recv_timestamp, -1) {
+                    error!(?error, %asset_no, "The emulated exchange couldn't process the order.");
+                }
+            }
+            while asset
+                .orders_from_exch
+                .get_head_timestamp()
+                .is_some_and(|timestamp| timestamp <= now)
+            {
+                let mut order = with_q(asset.orders_from_exch.remove(0), ());
+                if order.req == Status::Rejected {
+                    // The request has been rejected since the order is already gone, as a venue
+                    // would reject it.
+                    let error = ErrorEvent::with(
+                        ErrorType::OrderError,
+                        format!("order {}: the request is rejected", order.order_id),
+                    );
+                    tx.send(LiveEvent::Error(error)).map_err(|_| ())?;
+                    order.req = Status::None;
+                    order.exec_qty = 0.0;
+                }
+                let exec_qty = order.exec_qty as f64 * order.side.as_f64();
+                tx.send(LiveEvent::Order(OrderResponse {
+                    asset_no: *asset_no,
+                    order,
+                }))
+                .map_err(|_| ())?;
+                if exec_qty != 0.0 {
+                    asset.position += exec_qty;
+                    let position = Position {
+                        asset_no: *asset_no,
+                        symbol: asset.symbol.clone(),
+                        qty: asset.position,
+                        entry_price: None,
+                        mark_price: None,
+                    };
+                    tx.send(LiveEvent::Position(position)).map_err(|_| ())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts the order into that of the queue position type `R`.
+fn with_q<Q: Clone, R: Clone>(order: Order<Q>, q: R) -> Order<R> {
+    Order {
+        qty: order.qty,
+        leaves_qty: order.leaves_qty,
+        price_tick: order.price_tick,
+        tick_size: order.tick_size,
+        side: order.side,
+        time_in_force: order.time_in_force,
+        exch_timestamp: order.exch_timestamp,
+        status: order.status,
+        local_timestamp: order.local_timestamp,
+        req: order.req,
+        exec_price_tick: order.exec_price_tick,
+        exec_qty: order.exec_qty,
+        order_id: order.order_id,
+        q,
+        maker: order.maker,
+        order_type: order.order_type,
+        oco_group: order.oco_group,
+        trigger_price_tick: order.trigger_price_tick,
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::Duration,
+};
+
+use thiserror::Error;
+use tracing::warn;
+
+use crate::{
+    live::clock,
+    ty::{LiveEvent, RateLimitStatus},
+};
+
+/// What a rate limit counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    /// The weights of the requests, such as Binance's `REQUEST_WEIGHT`.
+    Weight,
+    /// The orders placed, including the modifications, such as Binance's `ORDERS`.
+    Orders,
+}
+
+/// A rate limit of the venue's API, which allows up to `limit` counts within the fixed windows of
+/// `interval` nanoseconds aligned to the epoch, as Binance resets its counters at the start of
+/// every interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ApiRateLimit {
+    pub counter: Counter,
+    pub interval: i64,
+    pub limit: u32,
+}
+
+/// What a request counts towards the rate limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cost {
+    pub weight: u32,
+    pub orders: u32,
+}
+
+impl Cost {
+    pub const fn new(weight: u32, orders: u32) -> Self {
+        Self { weight, orders }
+    }
+}
+
+/// How the requests beyond the rate limits are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitAction {
+    /// Holds the requests until the windows reset, or until the venue's back-off ends.
+    #[default]
+    Queue,
+    /// Fails the requests with [`RateLimitError`].
+    Reject,
+}
+
+/// The request is rejected by the [`RateLimiter`] without being sent, since it would exceed the
+/// rate limits until the timestamp.
+#[derive(Error, Clone, Copy, Debug)]
+#[error("rate limited until {until}")]
+pub struct RateLimitError {
+    pub until: i64,
+}
+
+#[derive(Debug)]
+struct Window {
+    limit: ApiRateLimit,
+    start: i64,
+    count: u32,
+}
+
+impl Window {
+    fn roll(&mut self, now: i64) {
+        let start = now - now.rem_euclid(self.limit.interval);
+        if start != self.start {
+            self.start = start;
+            self.count = 0;
+        }
+    }
+
+    fn end(&self) -> i64 {
+        self.start + self.limit.interval
+    }
+
+    fn cost(&self, cost: Cost) -> u32 {
+        match self.limit.counter {
+            Counter::Weight => cost.weight,
+            Counter::Orders => cost.orders,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    windows: Vec<Window>,
+    // The end of the back-off imposed by the venue, such as by HTTP 429 or 418.
+    blocked_until: i64,
+    // The sender of the status and the assets of the connector, to which the status is reported.
+    listener: Option<(Sender<LiveEvent>, Vec<usize>)>,
+    last_status: Option<RateLimitStatus>,
+}
+
+impl State {
+    /// Counts the request if it's within the limits, otherwise returns the timestamp until which
+    /// it should wait.
+    fn acquire(&mut self, now: i64, cost: Cost) -> Result<(), i64> {
+        if now < self.blocked_until {
+            return Err(self.blocked_until);
+        }
+        let mut until = None;
+        for window in self.windows.iter_mut() {
+            window.roll(now);
+            let cost = window.cost(cost);
+            // A request that costs more than the limit itself is let through an empty window
+            // rather than held forever.
+            if cost > 0 && window.count > 0 && window.count + cost > window.limit.limit {
+                until = until.max(Some(window.end()));
+            }
+        }
+        if let Some(until) = until {
+            return Err(until);
+        }
+        for window in self.windows.iter_mut() {
+            window.count += window.cost(cost);
+        }
+        Ok(())
+    }
+
+    fn status(&mut self, now: i64) -> RateLimitStatus {
+        let mut usage = 0.0f64;
+        let mut order_budget: Option<u32> = None;
+        let mut reset_at = i64::MAX;
+        for window in self.windows.iter_mut() {
+            window.roll(now);
+            usage = usage.max(window.count as f64 / window.limit.limit as f64);
+            reset_at = reset_at.min(window.end());
+            if window.limit.counter == Counter::Orders {
+                let budget = window.limit.limit.saturating_sub(window.count);
+                order_budget = Some(order_budget.map_or(budget, |b| b.min(budget)));
+            }
+        }
+        RateLimitStatus {
+            asset_no: 0,
+            usage,
+            order_budget,
+            reset_at,
+            blocked_until: (self.blocked_until > now).then_some(self.blocked_until),
+        }
+    }
+
+    /// Reports the status to the bot if it has changed since the last report.
+    fn notify(&mut self, now: i64) {
+        if self.listener.is_none() {
+            return;
+        }
+        let status = self.status(now);
+        if self.last_status.as_ref() == Some(&status) {
+            return;
+        }
+        if let Some((ev_tx, asset_nos)) = self.listener.as_ref() {
+            for asset_no in asset_nos {
+                let _ = ev_tx.send(LiveEvent::RateLimit(RateLimitStatus {
+                    asset_no: *asset_no,
+                    ..status.clone()
+                }));
+            }
+        }
+        self.last_status = Some(status);
+    }
+}
+
+/// Keeps the requests of a connector within the venue's API rate limits, instead of having the
+/// excess rejected by the venue, which may also ban the IP address for a while. It's shared by
+/// the clones of the connector's client.
+///
+/// The counts are estimated locally by the [`Cost`]s of the requests and corrected by those that
+/// the venue reports, such as by Binance's `X-MBX-USED-WEIGHT-1M` and `X-MBX-ORDER-COUNT-10S`
+/// response headers, so that the requests of the other processes sharing the same IP address or
+/// account are also taken into account. Once the venue rejects a request for exceeding its
+/// limits, all requests are held back until the back-off it tells ends.
+///
+/// The usage is reported to the bot by [`LiveEvent::RateLimit`], by which
+/// [`Interface::order_budget`](crate::Interface::order_budget) is also limited, so that the
+/// strategy can throttle itself.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimiter {
+    state: Arc<Mutex<State>>,
+    action: RateLimitAction,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Vec<ApiRateLimit>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                windows: limits
+                    .into_iter()
+                    .map(|limit| Window {
+                        limit,
+                        start: 0,
+                        count: 0,
+                    })
+                    .collect(),
+                ..Default::default()
+            })),
+            action: Default::default(),
+        }
+    }
+
+    /// Constructs a rate limiter with the default rate limits of Binance USDⓈ-M Futures, which
+    /// are the request weight of 2400 per minute and 300 orders per 10 seconds and 1200 orders
+    /// per minute. The limits of your account are listed in the `rateLimits` of
+    /// `GET /fapi/v1/exchangeInfo`.
+    pub fn binance_futures() -> Self {
+        Self::new(vec![
+            ApiRateLimit {
+                counter: Counter::Weight,
+                interval: 60_000_000_000,
+                limit: 2400,
+            },
+            ApiRateLimit {
+                counter: Counter::Orders,
+                interval: 10_000_000_000,
+                limit: 300,
+            },
+            ApiRateLimit {
+                counter: Counter::Orders,
+                interval: 60_000_000_000,
+                limit: 1200,
+            },
+        ])
+    }
+
+    /// Sets how the requests beyond the rate limits are handled. The default is
+    /// [`RateLimitAction::Queue`].
+    pub fn action(self, action: RateLimitAction) -> Self {
+        Self { action, ..self }
+    }
+
+    /// Reports the status of the rate limits to the bot as that of the assets.
+    pub(crate) fn set_listener(&self, ev_tx: Sender<LiveEvent>, asset_nos: Vec<usize>) {
+        self.state.lock().unwrap().listener = Some((ev_tx, asset_nos));
+    }
+
+    /// Counts the request if it's within the rate limits right now, without waiting.
+    pub fn try_acquire(&self, cost: Cost) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .acquire(clock::now(), cost)
+            .is_ok()
+    }
+
+    /// Counts the request, waiting until it's within the rate limits, or failing by
+    /// [`RateLimitAction::Reject`].
+    pub async fn acquire(&self, cost: Cost) -> Result<(), RateLimitError> {
+        loop {
+            let until = {
+                let mut state = self.state.lock().unwrap();
+                let now = clock::now();
+                match state.acquire(now, cost) {
+                    Ok(()) => return Ok(()),
+                    Err(until) => {
+                        state.notify(now);
+                        until
+                    }
+                }
+            };
+            if self.action == RateLimitAction::Reject {
+                return Err(RateLimitError { until });
+            }
+            let wait = (until - clock::now()).max(0) as u64;
+            warn!(?cost, until, "Holds the request back by the rate limits.");
+            tokio::time::sleep(Duration::from_nanos(wait)).await;
+        }
+    }
+
+    /// Applies the counts that the venue reports for the rate limits, each as the counter, the
+    /// interval in nanoseconds, and the count.
+    pub fn update(&self, counts: &[(Counter, i64, u32)]) {
+        let mut state = self.state.lock().unwrap();
+        let now = clock::now();
+        for (counter, interval, count) in counts {
+            for window in state.windows.iter_mut() {
+                if window.limit.counter == *counter && window.limit.interval == *interval {
+                    window.roll(now);
+                    window.count = *count;
+                }
+            }
+        }
+        state.notify(now);
+    }
+
+    /// Holds all requests back until the timestamp, as the venue tells to back off.
+    pub fn block(&self, until: i64) {
+        let mut state = self.state.lock().unwrap();
+        warn!(until, "The venue tells to back off.");
+        state.blocked_until = state.blocked_until.max(until);
+        state.notify(clock::now());
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Error as IoError, ErrorKind, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use tracing::{debug, error};
+
+/// A websocket frame recorded with the local timestamp at which it was received.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    /// Local timestamp in nanoseconds.
+    pub local_ts: i64,
+    pub text: String,
+}
+
+/// Records the raw websocket text frames received by a connector, one frame per line as
+/// `{local_ts} {text}`, so that the session can be replayed by [`ReplayServer`]. The file is
+/// flushed on each frame so that the recording survives a crash.
+pub struct WsRecorder {
+    writer: BufWriter<File>,
+}
+
+pub type SharedWsRecorder = Arc<Mutex<WsRecorder>>;
+
+impl WsRecorder {
+    /// Opens the recording file, appending to it if it exists.
+    pub fn open(path: &str) -> Result<Self, IoError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, local_ts: i64, text: &str) {
+        // A frame occupies a line. Line breaks in a JSON payload can only be whitespace between
+        // tokens, since they must be escaped within strings, so they are replaced by spaces.
+        let result = if text.contains('\n') {
+            writeln!(self.writer, "{} {}", local_ts, text.replace('\n', " "))
+        } else {
+            writeln!(self.writer, "{} {}", local_ts, text)
+        }
+        .and_then(|_| self.writer.flush());
+        if let Err(error) = result {
+            error!(?error, "Couldn't record the websocket frame.");
+        }
+    }
+}
+
+/// Reads the frames recorded by [`WsRecorder`].
+pub fn read_recording(path: &str) -> Result<Vec<RecordedFrame>, IoError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut frames = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let (local_ts, text) = line.split_once(' ').ok_or_else(|| {
+            IoError::new(ErrorKind::InvalidData, format!("invalid line: {}", line))
+        })?;
+        let local_ts = local_ts.parse().map_err(|_| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("invalid timestamp: {}", local_ts),
+            )
+        })?;
+        frames.push(RecordedFrame {
+            local_ts,
+            text: text.to_string(),
+        });
+    }
+    Ok(frames)
+}
+
+/// A local websocket server that replays the recorded frames to each connection in the recorded
+/// order, so that a connector can be fed the same stream again by pointing its stream URL to
+/// [`url`](Self::url). The connection remains open after the last frame until the client closes
+/// it.
+pub struct ReplayServer {
+    addr: String,
+}
+
+impl ReplayServer {
+    /// Starts the replay server on a local port. The frames are sent at the recorded intervals
+    /// divided by `speed`; if `speed` is zero or negative, they are sent as fast as possible.
+    /// This must be called within a Tokio runtime.
+    pub async fn start(frames: Vec<RecordedFrame>, speed: f64) -> Result<Self, IoError> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?.to_string();
+        let frames = Arc::new(frames);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let frames = frames.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = replay(stream, frames, speed).await {
+                        debug!(?error, "The replay connection is closed.");
+                    }
+                });
+            }
+        });
+        Ok(Self { addr })
+    }
+
+    /// Returns the stream URL to pass to the connector. The path that the connector appends is
+    /// ignored.
+    pub fn url(&self) -> String {
+        format!("ws://{}/stream?streams=", self.addr)
+    }
+}
+
+async fn replay(
+    stream: TcpStream,
+    frames: Arc<Vec<RecordedFrame>>,
+    speed: f64,
+) -> Result<(), anyhow::Error> {
+    let ws = accept_async(stream).await?;
+    let (mut write, mut read) = ws.split();
+    let mut prev_ts = frames.first().map(|frame| frame.local_ts).unwrap_or(0);
+    for frame in frames.iter() {
+        if speed > 0.0 && frame.local_ts > prev_ts {
+            let wait = ((frame.local_ts - prev_ts) as f64 / speed) as u64;
+            tokio::time::sleep(Duration::from_nanos(wait)).await;
+        }
+        prev_ts = frame.local_ts;
+        write.send(Message::Text(frame.text.clone())).await?;
+    }
+    while let Some(message) = read.next().await {
+        match message? {
+            Message::Ping(data) => write.send(Message::Pong(data)).await?,
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
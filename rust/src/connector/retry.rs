@@ -0,0 +1,179 @@
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use tracing::warn;
+
+/// The type of a request to the exchange, by which a [`RetryPolicy`] decides whether and how
+/// many times the request is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Submits an order. A retry reuses the client order id of the original request, so the
+    /// exchange rejects it as a duplicate if the original request has been processed, and the
+    /// connector then resolves the order by querying it instead of creating another one.
+    Submit,
+    /// Modifies an order.
+    Modify,
+    /// Cancels an order. A retry of a cancel that has already been processed fails as an unknown
+    /// order, which the connector resolves by querying the order.
+    Cancel,
+    /// Queries orders, positions, balances, or market data, which has no side effect.
+    Query,
+    /// Manages the session, such as starting and keeping alive the user data stream.
+    Session,
+}
+
+/// The outcome of a failed request, in terms of whether it's safe to retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The request surely hasn't been processed, such as when the connection couldn't be
+    /// established.
+    NotProcessed,
+    /// The request may or may not have been processed, such as when the response timed out.
+    Unknown,
+    /// The request has been rejected for a reason that a retry cannot fix.
+    Rejected,
+}
+
+/// Classifies a failed request by its [`Outcome`].
+pub trait Retryable {
+    fn outcome(&self) -> Outcome;
+}
+
+impl Retryable for reqwest::Error {
+    fn outcome(&self) -> Outcome {
+        if self.is_connect() {
+            Outcome::NotProcessed
+        } else if self.is_timeout()
+            || self.is_request()
+            || self.status().is_some_and(|status| status.is_server_error())
+        {
+            Outcome::Unknown
+        } else {
+            Outcome::Rejected
+        }
+    }
+}
+
+/// The rule of retrying an [`Operation`].
+#[derive(Clone, Copy, Debug)]
+pub struct RetryRule {
+    /// The maximum number of attempts including the first one.
+    pub max_attempts: u32,
+    /// Whether the request is retried even if it may have been processed. This should be set
+    /// only for idempotent requests, whose repetition has no further effect.
+    pub retry_unknown: bool,
+}
+
+/// Decides whether and how a failed request to the exchange is retried, by the rule of its
+/// [`Operation`], with an exponential backoff between the attempts.
+///
+/// By default, no request is retried, which is the same as the connectors without a retry
+/// policy. [`RetryPolicy::new`] retries every operation up to the given number of attempts,
+/// including the order submissions, whose retries are idempotent since they reuse the client
+/// order ids.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    rules: HashMap<Operation, RetryRule>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            rules: HashMap::new(),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs a policy that retries every operation up to `max_attempts` attempts in total,
+    /// including the requests that may have been processed.
+    pub fn new(max_attempts: u32) -> Self {
+        let rule = RetryRule {
+            max_attempts,
+            retry_unknown: true,
+        };
+        Self {
+            rules: [
+                Operation::Submit,
+                Operation::Modify,
+                Operation::Cancel,
+                Operation::Query,
+                Operation::Session,
+            ]
+            .into_iter()
+            .map(|op| (op, rule))
+            .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the rule of the operation.
+    pub fn rule(mut self, op: Operation, rule: RetryRule) -> Self {
+        self.rules.insert(op, rule);
+        self
+    }
+
+    /// Sets the backoff before the first retry, which doubles at every retry up to `max`. The
+    /// defaults are 100 milliseconds and 2 seconds.
+    pub fn backoff(self, initial: Duration, max: Duration) -> Self {
+        Self {
+            initial_backoff: initial,
+            max_backoff: max,
+            ..self
+        }
+    }
+
+    /// Returns the backoff before the retry of the given number, starting at 1, or `None` if the
+    /// request that failed with the outcome shouldn't be retried.
+    pub fn backoff_for(&self, op: Operation, retry: u32, outcome: Outcome) -> Option<Duration> {
+        let rule = self.rules.get(&op)?;
+        let retryable = match outcome {
+            Outcome::NotProcessed => true,
+            Outcome::Unknown => rule.retry_unknown,
+            Outcome::Rejected => false,
+        };
+        if !retryable || retry >= rule.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(retry - 1);
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+
+    /// Runs the request built by `request` for the operation, retrying it by the policy. The
+    /// request is called with the number of the attempt, starting at 1, so that it can tell a
+    /// retry from the original request.
+    pub async fn run<T, E, F, Fut>(&self, op: Operation, mut request: F) -> Result<T, E>
+    where
+        E: Retryable + std::fmt::Debug,
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request(attempt).await {
+                Ok(resp) => return Ok(resp),
+                Err(error) => {
+                    let Some(backoff) = self.backoff_for(op, attempt, error.outcome()) else {
+                        return Err(error);
+                    };
+                    warn!(
+                        ?op,
+                        attempt,
+                        ?backoff,
+                        ?error,
+                        "Retries the failed request."
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
@@ -1,41 +1,62 @@
 use std::collections::BTreeMap;
 
-use super::{ApplySnapshot, MarketDepth, INVALID_MAX, INVALID_MIN};
-use crate::{
-    backtest::reader::Data,
-    ty::{Event, BUY, SELL},
-};
-
+#[cfg(feature = "backtest")]
+use super::{snapshot_rows, ApplySnapshot};
+use super::{MarketDepth, Tick};
+#[cfg(feature = "backtest")]
+use crate::{backtest::reader::Data, ty::Event};
+use crate::ty::{BUY, SELL};
+
+/// BTreeMap-based Market Depth
+///
+/// The exchange timestamps of the last updates are kept per side, but not per level.
+///
+/// The price ticks are of the integer type `T`, which is `i64` by default. See [`Tick`].
 #[derive(Debug)]
-pub struct BTreeMarketDepth {
+pub struct BTreeMarketDepth<T: Tick = i64> {
     pub tick_size: f32,
     pub lot_size: f32,
     pub timestamp: i64,
-    pub bid_depth: BTreeMap<i32, f32>,
-    pub ask_depth: BTreeMap<i32, f32>,
+    pub bid_timestamp: i64,
+    pub ask_timestamp: i64,
+    pub bid_depth: BTreeMap<T, f32>,
+    pub ask_depth: BTreeMap<T, f32>,
 }
 
 impl BTreeMarketDepth {
+    /// Constructs a depth with the price ticks of `i64`.
     pub fn new(tick_size: f32, lot_size: f32) -> Self {
+        Self::new_generic(tick_size, lot_size)
+    }
+}
+
+impl<T: Tick> BTreeMarketDepth<T> {
+    /// Constructs a depth with the price ticks of `T`, such as
+    /// `BTreeMarketDepth::<i32>::new_generic(..)`.
+    pub fn new_generic(tick_size: f32, lot_size: f32) -> Self {
         Self {
             tick_size,
             lot_size,
             timestamp: 0,
+            bid_timestamp: 0,
+            ask_timestamp: 0,
             bid_depth: Default::default(),
             ask_depth: Default::default(),
         }
     }
 }
 
-impl MarketDepth for BTreeMarketDepth {
+impl<T: Tick> MarketDepth for BTreeMarketDepth<T> {
+    type Tick = T;
+
     fn update_bid_depth(
         &mut self,
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64) {
-        let price_tick = (price / self.tick_size).round() as i32;
-        let prev_best_bid_tick = *self.bid_depth.keys().last().unwrap_or(&INVALID_MIN);
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
+        let prev_best_bid_tick = *self.bid_depth.keys().last().unwrap_or(&T::INVALID_MIN);
         let prev_qty = *self.bid_depth.get(&prev_best_bid_tick).unwrap_or(&0.0);
 
         if (qty / self.lot_size).round() as i32 == 0 {
@@ -43,7 +64,8 @@ impl MarketDepth for BTreeMarketDepth {
         } else {
             *self.bid_depth.entry(price_tick).or_insert(qty) = qty;
         }
-        let best_bid_tick = *self.bid_depth.keys().last().unwrap_or(&INVALID_MIN);
+        self.bid_timestamp = timestamp;
+        let best_bid_tick = *self.bid_depth.keys().last().unwrap_or(&T::INVALID_MIN);
         (
             price_tick,
             prev_best_bid_tick,
@@ -59,9 +81,9 @@ impl MarketDepth for BTreeMarketDepth {
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64) {
-        let price_tick = (price / self.tick_size).round() as i32;
-        let prev_best_ask_tick = *self.bid_depth.keys().next().unwrap_or(&INVALID_MAX);
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
+        let prev_best_ask_tick = *self.bid_depth.keys().next().unwrap_or(&T::INVALID_MAX);
         let prev_qty = *self.ask_depth.get(&prev_best_ask_tick).unwrap_or(&0.0);
 
         if (qty / self.lot_size).round() as i32 == 0 {
@@ -69,7 +91,8 @@ impl MarketDepth for BTreeMarketDepth {
         } else {
             *self.ask_depth.entry(price_tick).or_insert(qty) = qty;
         }
-        let best_ask_tick = *self.ask_depth.keys().next().unwrap_or(&INVALID_MAX);
+        self.ask_timestamp = timestamp;
+        let best_ask_tick = *self.ask_depth.keys().next().unwrap_or(&T::INVALID_MAX);
         (
             price_tick,
             prev_best_ask_tick,
@@ -81,24 +104,18 @@ impl MarketDepth for BTreeMarketDepth {
     }
 
     fn clear_depth(&mut self, side: i64, clear_upto_price: f32) {
-        let clear_upto = (clear_upto_price / self.tick_size).round() as i32;
+        let clear_upto = T::from_price(clear_upto_price, self.tick_size);
         if side == BUY {
             let best_bid_tick = self.best_bid_tick();
-            if best_bid_tick != INVALID_MIN {
-                for t in clear_upto..(best_bid_tick + 1) {
-                    if self.bid_depth.contains_key(&t) {
-                        self.bid_depth.remove(&t);
-                    }
-                }
+            if best_bid_tick != T::INVALID_MIN {
+                self.bid_depth
+                    .retain(|t, _| *t < clear_upto || *t > best_bid_tick);
             }
         } else if side == SELL {
             let best_ask_tick = self.best_ask_tick();
-            if best_ask_tick != INVALID_MAX {
-                for t in best_ask_tick..(clear_upto + 1) {
-                    if self.ask_depth.contains_key(&t) {
-                        self.ask_depth.remove(&t);
-                    }
-                }
+            if best_ask_tick != T::INVALID_MAX {
+                self.ask_depth
+                    .retain(|t, _| *t < best_ask_tick || *t > clear_upto);
             }
         } else {
             self.bid_depth.clear();
@@ -107,19 +124,27 @@ impl MarketDepth for BTreeMarketDepth {
     }
 
     fn best_bid(&self) -> f32 {
-        self.best_bid_tick() as f32 * self.tick_size
+        self.best_bid_tick().to_price(self.tick_size)
     }
 
     fn best_ask(&self) -> f32 {
-        self.best_ask_tick() as f32 * self.tick_size
+        self.best_ask_tick().to_price(self.tick_size)
+    }
+
+    fn best_bid_tick(&self) -> T {
+        *self.bid_depth.keys().last().unwrap_or(&T::INVALID_MIN)
+    }
+
+    fn best_ask_tick(&self) -> T {
+        *self.ask_depth.keys().next().unwrap_or(&T::INVALID_MAX)
     }
 
-    fn best_bid_tick(&self) -> i32 {
-        *self.bid_depth.keys().last().unwrap_or(&INVALID_MIN)
+    fn bid_timestamp(&self) -> i64 {
+        self.bid_timestamp
     }
 
-    fn best_ask_tick(&self) -> i32 {
-        *self.ask_depth.keys().next().unwrap_or(&INVALID_MAX)
+    fn ask_timestamp(&self) -> i64 {
+        self.ask_timestamp
     }
 
     fn tick_size(&self) -> f32 {
@@ -129,9 +154,18 @@ impl MarketDepth for BTreeMarketDepth {
     fn lot_size(&self) -> f32 {
         self.lot_size
     }
+
+    fn bid_qty_at_tick(&self, price_tick: T) -> f32 {
+        *self.bid_depth.get(&price_tick).unwrap_or(&0.0)
+    }
+
+    fn ask_qty_at_tick(&self, price_tick: T) -> f32 {
+        *self.ask_depth.get(&price_tick).unwrap_or(&0.0)
+    }
 }
 
-impl ApplySnapshot for BTreeMarketDepth {
+#[cfg(feature = "backtest")]
+impl<T: Tick> ApplySnapshot for BTreeMarketDepth<T> {
     fn apply_snapshot(&mut self, data: &Data<Event>) {
         self.bid_depth.clear();
         self.ask_depth.clear();
@@ -139,12 +173,23 @@ impl ApplySnapshot for BTreeMarketDepth {
             let price = data[row_num].px;
             let qty = data[row_num].qty;
 
-            let price_tick = (price / self.tick_size).round() as i32;
+            let price_tick = T::from_price(price, self.tick_size);
             if data[row_num].ev & BUY == BUY {
                 *self.bid_depth.entry(price_tick).or_insert(0f32) = qty;
+                self.bid_timestamp = data[row_num].exch_ts;
             } else if data[row_num].ev & SELL == SELL {
                 *self.ask_depth.entry(price_tick).or_insert(0f32) = qty;
+                self.ask_timestamp = data[row_num].exch_ts;
             }
         }
     }
+
+    fn snapshot(&self, timestamp: i64) -> Vec<Event> {
+        snapshot_rows(
+            self.bid_depth.iter().rev().map(|(tick, qty)| (*tick, *qty)),
+            self.ask_depth.iter().map(|(tick, qty)| (*tick, *qty)),
+            self.tick_size,
+            timestamp,
+        )
+    }
 }
@@ -1,11 +1,15 @@
-use std::collections::{hash_map::Entry, HashMap};
-
-use super::{ApplySnapshot, MarketDepth, INVALID_MAX, INVALID_MIN};
-use crate::{
-    backtest::reader::Data,
-    ty::{Event, BUY, SELL},
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::Entry, HashMap},
 };
 
+#[cfg(feature = "backtest")]
+use super::{snapshot_rows, ApplySnapshot};
+use super::{MarketDepth, Tick};
+#[cfg(feature = "backtest")]
+use crate::{backtest::reader::Data, ty::Event};
+use crate::ty::{BUY, SELL};
+
 /// HashMap-based Market Depth
 ///
 /// This is considered more robust than a BTreeMap-based Market Depth. This is because in the
@@ -16,62 +20,207 @@ use crate::{
 /// In contrast, a HashMap-based Market Depth tracks the latest best bid and ask prices, updating
 /// them accordingly. This allows for natural refresh of market depth, even in cases where there are
 /// missing feeds.
-pub struct HashMapMarketDepth {
+///
+/// The depth can be capped to a number of ticks around the mid price by
+/// [`with_cap`](Self::with_cap), in which case the levels beyond the cap are discarded.
+///
+/// The exchange timestamps of the last updates are kept per side, and also per level if enabled
+/// by [`with_level_timestamps`](Self::with_level_timestamps).
+///
+/// The price ticks are of the integer type `T`, which is `i64` by default. See [`Tick`].
+pub struct HashMapMarketDepth<T: Tick = i64> {
     pub tick_size: f32,
     pub lot_size: f32,
     pub timestamp: i64,
-    pub ask_depth: HashMap<i32, f32>,
-    pub bid_depth: HashMap<i32, f32>,
-    pub best_bid_tick: i32,
-    pub best_ask_tick: i32,
-    pub low_bid_tick: i32,
-    pub high_ask_tick: i32,
+    pub bid_timestamp: i64,
+    pub ask_timestamp: i64,
+    pub ask_depth: HashMap<T, f32>,
+    pub bid_depth: HashMap<T, f32>,
+    pub best_bid_tick: T,
+    pub best_ask_tick: T,
+    pub low_bid_tick: T,
+    pub high_ask_tick: T,
+    pub cap: Option<T>,
+    // key: price tick, value: the exchange timestamp of the last update of the level, which are
+    // kept only if enabled.
+    bid_level_timestamps: Option<HashMap<T, i64>>,
+    ask_level_timestamps: Option<HashMap<T, i64>>,
 }
 
-fn depth_below(depth: &HashMap<i32, f32>, start: i32, end: i32) -> i32 {
-    for t in (end..start).rev() {
+fn depth_below<T: Tick>(depth: &HashMap<T, f32>, start: T, end: T) -> T {
+    let mut t = start;
+    while t > end {
+        t = t - T::ONE;
         if *depth.get(&t).unwrap_or(&0f32) > 0f32 {
             return t;
         }
     }
-    return INVALID_MIN;
+    return T::INVALID_MIN;
 }
 
-fn depth_above(depth: &HashMap<i32, f32>, start: i32, end: i32) -> i32 {
-    for t in (start + 1)..(end + 1) {
+fn depth_above<T: Tick>(depth: &HashMap<T, f32>, start: T, end: T) -> T {
+    let mut t = start;
+    while t < end {
+        t = t + T::ONE;
         if *depth.get(&t).unwrap_or(&0f32) > 0f32 {
             return t;
         }
     }
-    return INVALID_MAX;
+    return T::INVALID_MAX;
 }
 
 impl HashMapMarketDepth {
+    /// Constructs a depth with the price ticks of `i64`.
     pub fn new(tick_size: f32, lot_size: f32) -> Self {
+        Self::new_generic(tick_size, lot_size)
+    }
+}
+
+impl<T: Tick> HashMapMarketDepth<T> {
+    /// Constructs a depth with the price ticks of `T`, such as
+    /// `HashMapMarketDepth::<i32>::new_generic(..)`.
+    pub fn new_generic(tick_size: f32, lot_size: f32) -> Self {
         Self {
             tick_size,
             lot_size,
             timestamp: 0,
+            bid_timestamp: 0,
+            ask_timestamp: 0,
             ask_depth: HashMap::new(),
             bid_depth: HashMap::new(),
-            best_bid_tick: INVALID_MIN,
-            best_ask_tick: INVALID_MAX,
-            low_bid_tick: INVALID_MAX,
-            high_ask_tick: INVALID_MIN,
+            best_bid_tick: T::INVALID_MIN,
+            best_ask_tick: T::INVALID_MAX,
+            low_bid_tick: T::INVALID_MAX,
+            high_ask_tick: T::INVALID_MIN,
+            cap: None,
+            bid_level_timestamps: None,
+            ask_level_timestamps: None,
+        }
+    }
+
+    /// Caps the depth to `ticks` ticks on each side of the mid price, or of the best price of the
+    /// other side if a side is empty. The levels beyond the cap are discarded, and the remaining
+    /// levels are pruned as the mid price moves, which bounds the memory and the update cost for
+    /// a huge book. The best bid and ask are always kept, even if the spread is wider than the
+    /// cap. See [`MarketDepth::tick_range`] for the range of the maintained levels.
+    pub fn with_cap(self, ticks: T) -> Self {
+        Self {
+            cap: Some(ticks),
+            ..self
+        }
+    }
+
+    /// Keeps the exchange timestamp of the last update of each level, which is returned by
+    /// [`MarketDepth::bid_timestamp_at_tick`] and [`MarketDepth::ask_timestamp_at_tick`], at the
+    /// cost of another map per side.
+    pub fn with_level_timestamps(self) -> Self {
+        Self {
+            bid_level_timestamps: Some(HashMap::new()),
+            ask_level_timestamps: Some(HashMap::new()),
+            ..self
+        }
+    }
+
+    fn bounds(&self) -> Option<(T, T)> {
+        let cap = self.cap?;
+        let center = match (
+            self.best_bid_tick == T::INVALID_MIN,
+            self.best_ask_tick == T::INVALID_MAX,
+        ) {
+            (true, true) => return None,
+            (false, true) => self.best_bid_tick,
+            (true, false) => self.best_ask_tick,
+            (false, false) => self.best_bid_tick.midpoint(self.best_ask_tick),
+        };
+        let mut lower = center.saturating_sub(cap);
+        if self.best_bid_tick != T::INVALID_MIN {
+            lower = lower.min(self.best_bid_tick);
+        }
+        let mut upper = center.saturating_add(cap);
+        if self.best_ask_tick != T::INVALID_MAX {
+            upper = upper.max(self.best_ask_tick);
+        }
+        Some((lower, upper))
+    }
+
+    fn prune(&mut self) {
+        let Some((lower, upper)) = self.bounds() else {
+            return;
+        };
+        let in_range = |t: &T, _: &mut f32| *t >= lower && *t <= upper;
+        self.bid_depth.retain(in_range);
+        self.ask_depth.retain(in_range);
+        self.retain_level_timestamps();
+        if self.best_bid_tick != T::INVALID_MIN {
+            self.low_bid_tick = self.low_bid_tick.max(lower);
+        }
+        if self.best_ask_tick != T::INVALID_MAX {
+            self.high_ask_tick = self.high_ask_tick.min(upper);
+        }
+    }
+
+    /// Drops the timestamps of the levels that no longer exist.
+    fn retain_level_timestamps(&mut self) {
+        if let Some(timestamps) = self.bid_level_timestamps.as_mut() {
+            let depth = &self.bid_depth;
+            timestamps.retain(|t, _| depth.contains_key(t));
+        }
+        if let Some(timestamps) = self.ask_level_timestamps.as_mut() {
+            let depth = &self.ask_depth;
+            timestamps.retain(|t, _| depth.contains_key(t));
+        }
+    }
+}
+
+/// Records the exchange timestamp of the level's update, removing it if the level is deleted.
+fn update_level_timestamp<T: Tick>(
+    timestamps: &mut Option<HashMap<T, i64>>,
+    price_tick: T,
+    qty_lot: i32,
+    timestamp: i64,
+) {
+    if let Some(timestamps) = timestamps.as_mut() {
+        if qty_lot > 0 {
+            timestamps.insert(price_tick, timestamp);
+        } else {
+            timestamps.remove(&price_tick);
         }
     }
 }
 
-impl MarketDepth for HashMapMarketDepth {
+impl<T: Tick> MarketDepth for HashMapMarketDepth<T> {
+    type Tick = T;
+
     fn update_bid_depth(
         &mut self,
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64) {
-        let price_tick = (price / self.tick_size).round() as i32;
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
         let qty_lot = (qty / self.lot_size).round() as i32;
         let prev_best_bid_tick = self.best_bid_tick;
+        if qty_lot > 0 && self.best_bid_tick != T::INVALID_MIN {
+            if let Some((lower, _)) = self.bounds() {
+                if price_tick < lower {
+                    return (
+                        price_tick,
+                        prev_best_bid_tick,
+                        self.best_bid_tick,
+                        0.0,
+                        0.0,
+                        timestamp,
+                    );
+                }
+            }
+        }
+        self.bid_timestamp = timestamp;
+        update_level_timestamp(
+            &mut self.bid_level_timestamps,
+            price_tick,
+            qty_lot,
+            timestamp,
+        );
         let prev_qty;
         match self.bid_depth.entry(price_tick) {
             Entry::Occupied(mut entry) => {
@@ -94,8 +243,8 @@ impl MarketDepth for HashMapMarketDepth {
             if price_tick == self.best_bid_tick {
                 self.best_bid_tick =
                     depth_below(&self.bid_depth, self.best_bid_tick, self.low_bid_tick);
-                if self.best_bid_tick == INVALID_MIN {
-                    self.low_bid_tick = INVALID_MAX
+                if self.best_bid_tick == T::INVALID_MIN {
+                    self.low_bid_tick = T::INVALID_MAX
                 }
             }
         } else {
@@ -108,6 +257,9 @@ impl MarketDepth for HashMapMarketDepth {
             }
             self.low_bid_tick = self.low_bid_tick.min(price_tick);
         }
+        if self.cap.is_some() && self.best_bid_tick != prev_best_bid_tick {
+            self.prune();
+        }
         (
             price_tick,
             prev_best_bid_tick,
@@ -123,10 +275,31 @@ impl MarketDepth for HashMapMarketDepth {
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64) {
-        let price_tick = (price / self.tick_size).round() as i32;
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
         let qty_lot = (qty / self.lot_size).round() as i32;
         let prev_best_ask_tick = self.best_ask_tick;
+        if qty_lot > 0 && self.best_ask_tick != T::INVALID_MAX {
+            if let Some((_, upper)) = self.bounds() {
+                if price_tick > upper {
+                    return (
+                        price_tick,
+                        prev_best_ask_tick,
+                        self.best_ask_tick,
+                        0.0,
+                        0.0,
+                        timestamp,
+                    );
+                }
+            }
+        }
+        self.ask_timestamp = timestamp;
+        update_level_timestamp(
+            &mut self.ask_level_timestamps,
+            price_tick,
+            qty_lot,
+            timestamp,
+        );
         let prev_qty;
         match self.ask_depth.entry(price_tick) {
             Entry::Occupied(mut entry) => {
@@ -149,8 +322,8 @@ impl MarketDepth for HashMapMarketDepth {
             if price_tick == self.best_ask_tick {
                 self.best_ask_tick =
                     depth_above(&self.ask_depth, self.best_ask_tick, self.high_ask_tick);
-                if self.best_ask_tick == INVALID_MAX {
-                    self.high_ask_tick = INVALID_MIN
+                if self.best_ask_tick == T::INVALID_MAX {
+                    self.high_ask_tick = T::INVALID_MIN
                 }
             }
         } else {
@@ -163,6 +336,9 @@ impl MarketDepth for HashMapMarketDepth {
             }
             self.high_ask_tick = self.high_ask_tick.max(price_tick);
         }
+        if self.cap.is_some() && self.best_ask_tick != prev_best_ask_tick {
+            self.prune();
+        }
         (
             price_tick,
             prev_best_ask_tick,
@@ -174,54 +350,51 @@ impl MarketDepth for HashMapMarketDepth {
     }
 
     fn clear_depth(&mut self, side: i64, clear_upto_price: f32) {
-        let clear_upto = (clear_upto_price / self.tick_size).round() as i32;
+        let clear_upto = T::from_price(clear_upto_price, self.tick_size);
         if side == BUY {
-            if self.best_bid_tick != INVALID_MIN {
-                for t in clear_upto..(self.best_bid_tick + 1) {
-                    if self.bid_depth.contains_key(&t) {
-                        self.bid_depth.remove(&t);
-                    }
-                }
+            if self.best_bid_tick != T::INVALID_MIN {
+                self.bid_depth
+                    .retain(|t, _| *t < clear_upto || *t > self.best_bid_tick);
             }
-            self.best_bid_tick = depth_below(&self.bid_depth, clear_upto - 1, self.low_bid_tick);
-            if self.best_bid_tick == INVALID_MIN {
-                self.low_bid_tick = INVALID_MAX;
+            self.best_bid_tick =
+                depth_below(&self.bid_depth, clear_upto - T::ONE, self.low_bid_tick);
+            if self.best_bid_tick == T::INVALID_MIN {
+                self.low_bid_tick = T::INVALID_MAX;
             }
         } else if side == SELL {
-            if self.best_ask_tick != INVALID_MAX {
-                for t in self.best_ask_tick..(clear_upto + 1) {
-                    if self.ask_depth.contains_key(&t) {
-                        self.ask_depth.remove(&t);
-                    }
-                }
+            if self.best_ask_tick != T::INVALID_MAX {
+                self.ask_depth
+                    .retain(|t, _| *t < self.best_ask_tick || *t > clear_upto);
             }
-            self.best_ask_tick = depth_above(&self.ask_depth, clear_upto + 1, self.high_ask_tick);
-            if self.best_ask_tick == INVALID_MAX {
-                self.high_ask_tick = INVALID_MIN;
+            self.best_ask_tick =
+                depth_above(&self.ask_depth, clear_upto + T::ONE, self.high_ask_tick);
+            if self.best_ask_tick == T::INVALID_MAX {
+                self.high_ask_tick = T::INVALID_MIN;
             }
         } else {
             self.bid_depth.clear();
             self.ask_depth.clear();
-            self.best_bid_tick = INVALID_MIN;
-            self.best_ask_tick = INVALID_MAX;
-            self.low_bid_tick = INVALID_MAX;
-            self.high_ask_tick = INVALID_MIN;
+            self.best_bid_tick = T::INVALID_MIN;
+            self.best_ask_tick = T::INVALID_MAX;
+            self.low_bid_tick = T::INVALID_MAX;
+            self.high_ask_tick = T::INVALID_MIN;
         }
+        self.retain_level_timestamps();
     }
 
     fn best_bid(&self) -> f32 {
-        self.best_bid_tick as f32 * self.tick_size
+        self.best_bid_tick.to_price(self.tick_size)
     }
 
     fn best_ask(&self) -> f32 {
-        self.best_ask_tick as f32 * self.tick_size
+        self.best_ask_tick.to_price(self.tick_size)
     }
 
-    fn best_bid_tick(&self) -> i32 {
+    fn best_bid_tick(&self) -> T {
         self.best_bid_tick
     }
 
-    fn best_ask_tick(&self) -> i32 {
+    fn best_ask_tick(&self) -> T {
         self.best_ask_tick
     }
 
@@ -232,30 +405,101 @@ impl MarketDepth for HashMapMarketDepth {
     fn lot_size(&self) -> f32 {
         self.lot_size
     }
+
+    fn bid_qty_at_tick(&self, price_tick: T) -> f32 {
+        *self.bid_depth.get(&price_tick).unwrap_or(&0.0)
+    }
+
+    fn ask_qty_at_tick(&self, price_tick: T) -> f32 {
+        *self.ask_depth.get(&price_tick).unwrap_or(&0.0)
+    }
+
+    fn bid_timestamp(&self) -> i64 {
+        self.bid_timestamp
+    }
+
+    fn ask_timestamp(&self) -> i64 {
+        self.ask_timestamp
+    }
+
+    fn bid_timestamp_at_tick(&self, price_tick: T) -> Option<i64> {
+        self.bid_level_timestamps
+            .as_ref()?
+            .get(&price_tick)
+            .copied()
+    }
+
+    fn ask_timestamp_at_tick(&self, price_tick: T) -> Option<i64> {
+        self.ask_level_timestamps
+            .as_ref()?
+            .get(&price_tick)
+            .copied()
+    }
+
+    fn tick_range(&self) -> Option<(T, T)> {
+        self.bounds()
+    }
 }
 
-impl ApplySnapshot for HashMapMarketDepth {
+#[cfg(feature = "backtest")]
+impl<T: Tick> ApplySnapshot for HashMapMarketDepth<T> {
     fn apply_snapshot(&mut self, data: &Data<Event>) {
-        self.best_bid_tick = INVALID_MIN;
-        self.best_ask_tick = INVALID_MAX;
-        self.low_bid_tick = INVALID_MAX;
-        self.high_ask_tick = INVALID_MIN;
+        self.best_bid_tick = T::INVALID_MIN;
+        self.best_ask_tick = T::INVALID_MAX;
+        self.low_bid_tick = T::INVALID_MAX;
+        self.high_ask_tick = T::INVALID_MIN;
         self.bid_depth.clear();
         self.ask_depth.clear();
         for row_num in 0..data.len() {
             let price = data[row_num].px;
             let qty = data[row_num].qty;
+            let timestamp = data[row_num].exch_ts;
 
-            let price_tick = (price / self.tick_size).round() as i32;
+            let price_tick = T::from_price(price, self.tick_size);
             if data[row_num].ev & BUY == BUY {
                 self.best_bid_tick = self.best_bid_tick.max(price_tick);
                 self.low_bid_tick = self.low_bid_tick.min(price_tick);
                 *self.bid_depth.entry(price_tick).or_insert(0f32) = qty;
+                self.bid_timestamp = timestamp;
+                if let Some(timestamps) = self.bid_level_timestamps.as_mut() {
+                    timestamps.insert(price_tick, timestamp);
+                }
             } else if data[row_num].ev & SELL == SELL {
                 self.best_ask_tick = self.best_ask_tick.min(price_tick);
                 self.high_ask_tick = self.high_ask_tick.max(price_tick);
                 *self.ask_depth.entry(price_tick).or_insert(0f32) = qty;
+                self.ask_timestamp = timestamp;
+                if let Some(timestamps) = self.ask_level_timestamps.as_mut() {
+                    timestamps.insert(price_tick, timestamp);
+                }
             }
         }
+        self.prune();
+        self.retain_level_timestamps();
+    }
+
+    fn snapshot(&self, timestamp: i64) -> Vec<Event> {
+        // The levels beyond the best, which are left by the crossing updates, are not part of the
+        // book.
+        let mut bids: Vec<(T, f32)> = self
+            .bid_depth
+            .iter()
+            .filter(|(tick, qty)| **tick <= self.best_bid_tick && **qty > 0.0)
+            .map(|(tick, qty)| (*tick, *qty))
+            .collect();
+        bids.sort_unstable_by_key(|(tick, _)| Reverse(*tick));
+        let mut asks: Vec<(T, f32)> = self
+            .ask_depth
+            .iter()
+            .filter(|(tick, qty)| **tick >= self.best_ask_tick && **qty > 0.0)
+            .map(|(tick, qty)| (*tick, *qty))
+            .collect();
+        asks.sort_unstable_by_key(|(tick, _)| *tick);
+        snapshot_rows(
+            bids.into_iter(),
+            asks.into_iter(),
+            self.tick_size,
+            timestamp,
+        )
     }
 }
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth, Tick};
+use crate::ty::Side;
+
+/// An order in the book of an [`L3MarketDepth`].
+#[derive(Clone, Debug)]
+pub struct L3Order<T: Tick = i64> {
+    pub order_id: i64,
+    pub side: Side,
+    pub price_tick: T,
+    pub qty: f32,
+    /// The time priority of the order. The orders at the same price are queued in ascending
+    /// order of their priorities, and an order gets a new priority behind all the others when it
+    /// joins a queue.
+    pub priority: u64,
+    pub timestamp: i64,
+}
+
+/// Market-by-order market depth, which tracks the individual orders in the book and their queues
+/// at each price in order of time priority, built from market-by-order data such as
+/// [`L3Event`](crate::ty::L3Event). The aggregated depth by price is maintained alongside and is
+/// available by [`depth`](Self::depth).
+///
+/// An order loses its time priority when its price is changed or its quantity is increased, and
+/// keeps it when its quantity is decreased, which is the rule of most venues.
+///
+/// The price ticks are of the integer type `T`, which is `i64` by default. See [`Tick`].
+pub struct L3MarketDepth<T: Tick = i64> {
+    depth: HashMapMarketDepth<T>,
+    // key: order_id, value: the order
+    orders: HashMap<i64, L3Order<T>>,
+    // key: price tick, value: the ids of the orders at the price in order of priority
+    bid_queues: HashMap<T, VecDeque<i64>>,
+    ask_queues: HashMap<T, VecDeque<i64>>,
+    next_priority: u64,
+}
+
+impl L3MarketDepth {
+    /// Constructs a depth with the price ticks of `i64`.
+    pub fn new(tick_size: f32, lot_size: f32) -> Self {
+        Self::new_generic(tick_size, lot_size)
+    }
+}
+
+impl<T: Tick> L3MarketDepth<T> {
+    /// Constructs a depth with the price ticks of `T`, such as
+    /// `L3MarketDepth::<i32>::new_generic(..)`.
+    pub fn new_generic(tick_size: f32, lot_size: f32) -> Self {
+        Self {
+            depth: HashMapMarketDepth::new_generic(tick_size, lot_size),
+            orders: HashMap::new(),
+            bid_queues: HashMap::new(),
+            ask_queues: HashMap::new(),
+            next_priority: 0,
+        }
+    }
+
+    /// Returns the aggregated depth by price.
+    pub fn depth(&self) -> &HashMapMarketDepth<T> {
+        &self.depth
+    }
+
+    pub fn orders(&self) -> &HashMap<i64, L3Order<T>> {
+        &self.orders
+    }
+
+    /// Returns the orders at the price in order of priority.
+    pub fn queue(&self, side: Side, price_tick: T) -> impl Iterator<Item = &L3Order<T>> {
+        let queues = if side == Side::Buy {
+            &self.bid_queues
+        } else {
+            &self.ask_queues
+        };
+        queues
+            .get(&price_tick)
+            .into_iter()
+            .flatten()
+            .map(|order_id| &self.orders[order_id])
+    }
+
+    /// Returns the quantity of the orders at the price that are ahead of the given priority.
+    pub fn qty_ahead(&self, side: Side, price_tick: T, priority: u64) -> f32 {
+        self.queue(side, price_tick)
+            .take_while(|order| order.priority < priority)
+            .map(|order| order.qty)
+            .sum()
+    }
+
+    /// Takes the next priority, as if an order outside the book, such as the strategy's own
+    /// order, joined the queue, so that the orders joining afterward are queued behind it.
+    pub fn take_priority(&mut self) -> u64 {
+        self.next_priority += 1;
+        self.next_priority
+    }
+
+    /// Adds an order to the end of the queue at its price. Returns the price tick, and the
+    /// previous and the new best price ticks of the side, or `None` if an order with the same id
+    /// already exists.
+    pub fn add_order(
+        &mut self,
+        order_id: i64,
+        side: Side,
+        price: f32,
+        qty: f32,
+        timestamp: i64,
+    ) -> Option<(T, T, T)> {
+        if self.orders.contains_key(&order_id) {
+            return None;
+        }
+        let price_tick = T::from_price(price, self.depth.tick_size);
+        let priority = self.take_priority();
+        self.orders.insert(
+            order_id,
+            L3Order {
+                order_id,
+                side,
+                price_tick,
+                qty,
+                priority,
+                timestamp,
+            },
+        );
+        self.queues(side)
+            .entry(price_tick)
+            .or_default()
+            .push_back(order_id);
+        let (prev_best_tick, best_tick) = self.update_level(side, price_tick, qty, timestamp);
+        Some((price_tick, prev_best_tick, best_tick))
+    }
+
+    /// Deletes the order from the book. Returns the deleted order, or `None` if it doesn't exist.
+    pub fn delete_order(&mut self, order_id: i64, timestamp: i64) -> Option<L3Order<T>> {
+        let order = self.orders.remove(&order_id)?;
+        let queues = self.queues(order.side);
+        if let Some(queue) = queues.get_mut(&order.price_tick) {
+            queue.retain(|id| *id != order_id);
+            if queue.is_empty() {
+                queues.remove(&order.price_tick);
+            }
+        }
+        self.update_level(order.side, order.price_tick, -order.qty, timestamp);
+        Some(order)
+    }
+
+    /// Changes the price and the quantity of the order. Returns the order before the change, and
+    /// the previous and the new best price ticks of the side, or `None` if it doesn't exist.
+    pub fn modify_order(
+        &mut self,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        timestamp: i64,
+    ) -> Option<(L3Order<T>, T, T)> {
+        let prev = self.orders.get(&order_id)?.clone();
+        let price_tick = T::from_price(price, self.depth.tick_size);
+        if price_tick == prev.price_tick && qty <= prev.qty {
+            // Keeps the priority.
+            let order = self.orders.get_mut(&order_id).unwrap();
+            order.qty = qty;
+            order.timestamp = timestamp;
+            let (prev_best_tick, best_tick) =
+                self.update_level(prev.side, price_tick, qty - prev.qty, timestamp);
+            return Some((prev, prev_best_tick, best_tick));
+        }
+        self.delete_order(order_id, timestamp);
+        let (_, prev_best_tick, best_tick) =
+            self.add_order(order_id, prev.side, price, qty, timestamp)?;
+        Some((prev, prev_best_tick, best_tick))
+    }
+
+    /// Executes the order by the quantity, deleting it once it's fully executed. Returns the order
+    /// before the execution, or `None` if it doesn't exist.
+    pub fn fill_order(&mut self, order_id: i64, qty: f32, timestamp: i64) -> Option<L3Order<T>> {
+        let order = self.orders.get_mut(&order_id)?;
+        let prev = order.clone();
+        if ((prev.qty - qty) / self.depth.lot_size).round() <= 0.0 {
+            self.delete_order(order_id, timestamp);
+        } else {
+            order.qty -= qty;
+            order.timestamp = timestamp;
+            self.update_level(prev.side, prev.price_tick, -qty, timestamp);
+        }
+        Some(prev)
+    }
+
+    /// Deletes all the orders.
+    pub fn clear(&mut self) {
+        let (tick_size, lot_size) = (self.depth.tick_size, self.depth.lot_size);
+        self.depth = HashMapMarketDepth::new_generic(tick_size, lot_size);
+        self.orders.clear();
+        self.bid_queues.clear();
+        self.ask_queues.clear();
+    }
+
+    fn queues(&mut self, side: Side) -> &mut HashMap<T, VecDeque<i64>> {
+        if side == Side::Buy {
+            &mut self.bid_queues
+        } else {
+            &mut self.ask_queues
+        }
+    }
+
+    /// Changes the aggregated quantity at the price by `delta`, returning the previous and the
+    /// new best price ticks of the side.
+    fn update_level(&mut self, side: Side, price_tick: T, delta: f32, timestamp: i64) -> (T, T) {
+        let price = price_tick.to_price(self.depth.tick_size);
+        let levels = if side == Side::Buy {
+            &self.bid_queues
+        } else {
+            &self.ask_queues
+        };
+        // Prevents the accumulation of the rounding errors once the level is empty.
+        let qty = if levels.contains_key(&price_tick) {
+            let depth = if side == Side::Buy {
+                &self.depth.bid_depth
+            } else {
+                &self.depth.ask_depth
+            };
+            depth.get(&price_tick).copied().unwrap_or(0.0) + delta
+        } else {
+            0.0
+        };
+        let (_, prev_best_tick, best_tick, _, _, _) = if side == Side::Buy {
+            self.depth.update_bid_depth(price, qty, timestamp)
+        } else {
+            self.depth.update_ask_depth(price, qty, timestamp)
+        };
+        (prev_best_tick, best_tick)
+    }
+}
@@ -1,25 +1,162 @@
-use crate::{backtest::reader::Data, ty::Event};
+use core::{
+    fmt::Debug,
+    hash::Hash,
+    ops::{Add, Sub},
+};
 
+#[cfg(feature = "backtest")]
+use crate::{
+    backtest::reader::{Data, EXCH_EVENT, LOCAL_EVENT},
+    ty::{Event, BUY, DEPTH_SNAPSHOT_EVENT, SELL},
+};
+
+#[cfg(feature = "std")]
 pub mod btreemarketdepth;
+#[cfg(feature = "std")]
 pub mod hashmapmarketdepth;
+#[cfg(feature = "std")]
+pub mod l3marketdepth;
+#[cfg(feature = "std")]
+pub mod roivectormarketdepth;
+
+/// The price tick of the best bid if the bid side is empty, in the ticks of the orders.
+pub const INVALID_MIN: i64 = i64::MIN;
+/// The price tick of the best ask if the ask side is empty, in the ticks of the orders.
+pub const INVALID_MAX: i64 = i64::MAX;
+
+/// An integer type of the price ticks of a market depth.
+///
+/// A narrower type reduces the memory of the depth, but it overflows for instruments with a tiny
+/// tick size relative to the price, such as an asset priced in the hundreds of thousands with a
+/// tick size of `0.00001`. The depths use `i64` by default. The orders always use `i64`, to which
+/// the ticks of any depth are widened by [`to_i64`](Tick::to_i64).
+pub trait Tick:
+    Copy
+    + Ord
+    + Hash
+    + Debug
+    + Default
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+{
+    /// The price tick of the best bid if the bid side is empty.
+    const INVALID_MIN: Self;
+    /// The price tick of the best ask if the ask side is empty.
+    const INVALID_MAX: Self;
+    const ONE: Self;
+
+    /// Converts the price into the ticks, saturating at the bounds of the type.
+    fn from_price(price: f32, tick_size: f32) -> Self;
+
+    fn to_price(self, tick_size: f32) -> f32;
+
+    /// Converts the ticks of the orders, saturating at the bounds of the type, which are the
+    /// invalid ticks.
+    fn from_i64(tick: i64) -> Self;
+
+    /// Widens the ticks into the ticks of the orders, with the invalid ticks to
+    /// [`INVALID_MIN`] and [`INVALID_MAX`].
+    fn to_i64(self) -> i64;
+
+    /// Returns the number of the ticks from `base`, which must not be greater, as an index.
+    fn index_from(self, base: Self) -> usize;
+
+    fn saturating_add(self, rhs: Self) -> Self;
+
+    fn saturating_sub(self, rhs: Self) -> Self;
+
+    /// Returns the tick in the middle of the ticks, rounded toward zero.
+    fn midpoint(self, other: Self) -> Self;
+}
+
+macro_rules! impl_tick {
+    ($ty:ty) => {
+        impl Tick for $ty {
+            const INVALID_MIN: Self = <$ty>::MIN;
+            const INVALID_MAX: Self = <$ty>::MAX;
+            const ONE: Self = 1;
+
+            #[inline]
+            fn from_price(price: f32, tick_size: f32) -> Self {
+                // Rounds half away from zero as `f64::round` does, which isn't available without
+                // `std`. The cast truncates toward zero and saturates, and the fraction is exact.
+                let ticks = price as f64 / tick_size as f64;
+                let truncated = ticks as $ty;
+                let fraction = ticks - truncated as f64;
+                if fraction >= 0.5 {
+                    truncated.saturating_add(1)
+                } else if fraction <= -0.5 {
+                    truncated.saturating_sub(1)
+                } else {
+                    truncated
+                }
+            }
+
+            #[inline]
+            fn to_price(self, tick_size: f32) -> f32 {
+                (self as f64 * tick_size as f64) as f32
+            }
+
+            #[inline]
+            fn from_i64(tick: i64) -> Self {
+                tick.clamp(<$ty>::MIN as i64, <$ty>::MAX as i64) as $ty
+            }
+
+            #[inline]
+            fn to_i64(self) -> i64 {
+                match self {
+                    <$ty>::MIN => INVALID_MIN,
+                    <$ty>::MAX => INVALID_MAX,
+                    tick => tick as i64,
+                }
+            }
 
-pub const INVALID_MIN: i32 = i32::MIN;
-pub const INVALID_MAX: i32 = i32::MAX;
+            #[inline]
+            fn index_from(self, base: Self) -> usize {
+                <$ty>::saturating_sub(self, base) as usize
+            }
+
+            #[inline]
+            fn saturating_add(self, rhs: Self) -> Self {
+                <$ty>::saturating_add(self, rhs)
+            }
+
+            #[inline]
+            fn saturating_sub(self, rhs: Self) -> Self {
+                <$ty>::saturating_sub(self, rhs)
+            }
+
+            #[inline]
+            fn midpoint(self, other: Self) -> Self {
+                ((self as i128 + other as i128) / 2) as $ty
+            }
+        }
+    };
+}
+
+impl_tick!(i32);
+impl_tick!(i64);
 
 pub trait MarketDepth {
+    /// The integer type of the price ticks.
+    type Tick: Tick;
+
     fn update_bid_depth(
         &mut self,
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64);
+    ) -> (Self::Tick, Self::Tick, Self::Tick, f32, f32, i64);
 
     fn update_ask_depth(
         &mut self,
         price: f32,
         qty: f32,
         timestamp: i64,
-    ) -> (i32, i32, i32, f32, f32, i64);
+    ) -> (Self::Tick, Self::Tick, Self::Tick, f32, f32, i64);
 
     fn clear_depth(&mut self, side: i64, clear_upto_price: f32);
 
@@ -27,15 +164,80 @@ pub trait MarketDepth {
 
     fn best_ask(&self) -> f32;
 
-    fn best_bid_tick(&self) -> i32;
+    fn best_bid_tick(&self) -> Self::Tick;
 
-    fn best_ask_tick(&self) -> i32;
+    fn best_ask_tick(&self) -> Self::Tick;
 
     fn tick_size(&self) -> f32;
 
     fn lot_size(&self) -> f32;
+
+    /// Returns the quantity at the bid price tick, which is zero if there is no level at the
+    /// tick.
+    fn bid_qty_at_tick(&self, price_tick: Self::Tick) -> f32;
+
+    /// Returns the quantity at the ask price tick, which is zero if there is no level at the
+    /// tick.
+    fn ask_qty_at_tick(&self, price_tick: Self::Tick) -> f32;
+
+    /// Returns the exchange timestamp of the last update applied to the bid side, or zero if none
+    /// has been applied. Compared with the current time, it tells how stale the side's feed is.
+    fn bid_timestamp(&self) -> i64;
+
+    /// Returns the exchange timestamp of the last update applied to the ask side, or zero if none
+    /// has been applied.
+    fn ask_timestamp(&self) -> i64;
+
+    /// Returns the exchange timestamp of the last update of the bid level at the price tick, or
+    /// `None` if there is no level at the tick or the depth doesn't track the levels' timestamps.
+    /// They are tracked only if enabled, such as by `with_level_timestamps` of
+    /// [`HashMapMarketDepth`](hashmapmarketdepth::HashMapMarketDepth).
+    fn bid_timestamp_at_tick(&self, _price_tick: Self::Tick) -> Option<i64> {
+        None
+    }
+
+    /// Returns the exchange timestamp of the last update of the ask level at the price tick, or
+    /// `None` if there is no level at the tick or the depth doesn't track the levels' timestamps.
+    fn ask_timestamp_at_tick(&self, _price_tick: Self::Tick) -> Option<i64> {
+        None
+    }
+
+    /// Returns the range of the price ticks, inclusive, within which the depth is maintained if
+    /// the depth is capped, such as by [`HashMapMarketDepth::with_cap`]. The levels outside the
+    /// range are unknown rather than empty. Returns `None` if the depth isn't capped or is empty.
+    ///
+    /// [`HashMapMarketDepth::with_cap`]: hashmapmarketdepth::HashMapMarketDepth::with_cap
+    fn tick_range(&self) -> Option<(Self::Tick, Self::Tick)> {
+        None
+    }
 }
 
+#[cfg(feature = "backtest")]
 pub trait ApplySnapshot {
     fn apply_snapshot(&mut self, data: &Data<Event>);
+
+    /// Returns the levels of the depth as the depth snapshot rows of both the exchange and the
+    /// local, stamped with `timestamp`, the bids from the best and then the asks from the best.
+    /// See [`write_depth_snapshot`](crate::backtest::reader::write_depth_snapshot).
+    fn snapshot(&self, timestamp: i64) -> Vec<Event>;
+}
+
+/// Builds the depth snapshot rows from the levels of each side, ordered from the best.
+#[cfg(feature = "backtest")]
+fn snapshot_rows<T: Tick>(
+    bids: impl Iterator<Item = (T, f32)>,
+    asks: impl Iterator<Item = (T, f32)>,
+    tick_size: f32,
+    timestamp: i64,
+) -> Vec<Event> {
+    let row = |side: i64, (tick, qty): (T, f32)| Event {
+        ev: DEPTH_SNAPSHOT_EVENT | side | EXCH_EVENT | LOCAL_EVENT,
+        exch_ts: timestamp,
+        local_ts: timestamp,
+        px: tick.to_price(tick_size),
+        qty,
+    };
+    bids.map(|level| row(BUY, level))
+        .chain(asks.map(|level| row(SELL, level)))
+        .collect()
 }
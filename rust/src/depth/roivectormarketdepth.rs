@@ -0,0 +1,457 @@
+#[cfg(feature = "backtest")]
+use super::{snapshot_rows, ApplySnapshot};
+use super::{MarketDepth, Tick};
+use crate::ty::{BUY, SELL};
+#[cfg(feature = "backtest")]
+use crate::{backtest::reader::Data, ty::Event};
+
+/// Vector-based Market Depth within a range of interest(ROI)
+///
+/// The quantities are stored in contiguous vectors indexed by the price tick from the lower bound
+/// of the range of interest, so that an update is a plain index instead of a hash, and the next
+/// best price is found by scanning the adjacent elements. This makes it considerably faster than
+/// [`HashMapMarketDepth`](super::hashmapmarketdepth::HashMapMarketDepth) for liquid symbols,
+/// whose books are updated most frequently.
+///
+/// The levels outside the range of interest are discarded, so the range should be set wide enough
+/// to cover the prices at which the asset trades during the period, with a margin. Like
+/// [`HashMapMarketDepth`](super::hashmapmarketdepth::HashMapMarketDepth), it tracks the latest
+/// best bid and ask prices, which keeps it robust against missing feeds.
+///
+/// The exchange timestamps of the last updates are kept per side, and also per level if enabled
+/// by [`with_level_timestamps`](Self::with_level_timestamps).
+///
+/// The price ticks are of the integer type `T`, which is `i64` by default. See [`Tick`].
+pub struct RoiVectorMarketDepth<T: Tick = i64> {
+    pub tick_size: f32,
+    pub lot_size: f32,
+    pub timestamp: i64,
+    pub bid_timestamp: i64,
+    pub ask_timestamp: i64,
+    pub ask_depth: Vec<f32>,
+    pub bid_depth: Vec<f32>,
+    pub best_bid_tick: T,
+    pub best_ask_tick: T,
+    pub low_bid_tick: T,
+    pub high_ask_tick: T,
+    pub roi_lb: T,
+    pub roi_ub: T,
+    // The exchange timestamps of the last updates of the levels, indexed as the quantities, which
+    // are kept only if enabled.
+    bid_level_timestamps: Option<Vec<i64>>,
+    ask_level_timestamps: Option<Vec<i64>>,
+}
+
+fn depth_below<T: Tick>(depth: &[f32], roi_lb: T, start: T, end: T) -> T {
+    // The indices are clamped to the range of interest.
+    let start = if start <= roi_lb {
+        0
+    } else {
+        start.index_from(roi_lb).min(depth.len())
+    };
+    let end = if end <= roi_lb {
+        0
+    } else {
+        end.index_from(roi_lb)
+    };
+    if end >= start {
+        return T::INVALID_MIN;
+    }
+    match depth[end..start].iter().rposition(|qty| *qty > 0f32) {
+        Some(t) => roi_lb + T::from_i64((end + t) as i64),
+        None => T::INVALID_MIN,
+    }
+}
+
+fn depth_above<T: Tick>(depth: &[f32], roi_lb: T, start: T, end: T) -> T {
+    // The indices are clamped to the range of interest.
+    let start = if start < roi_lb {
+        0
+    } else {
+        start.index_from(roi_lb).saturating_add(1)
+    };
+    let end = if end < roi_lb {
+        0
+    } else {
+        end.index_from(roi_lb).saturating_add(1).min(depth.len())
+    };
+    if start >= end {
+        return T::INVALID_MAX;
+    }
+    match depth[start..end].iter().position(|qty| *qty > 0f32) {
+        Some(t) => roi_lb + T::from_i64((start + t) as i64),
+        None => T::INVALID_MAX,
+    }
+}
+
+impl RoiVectorMarketDepth {
+    /// Constructs a depth that maintains the levels from `roi_lb` to `roi_ub` in price,
+    /// inclusive, with the price ticks of `i64`.
+    pub fn new(tick_size: f32, lot_size: f32, roi_lb: f32, roi_ub: f32) -> Self {
+        Self::new_generic(tick_size, lot_size, roi_lb, roi_ub)
+    }
+}
+
+impl<T: Tick> RoiVectorMarketDepth<T> {
+    /// Constructs a depth that maintains the levels from `roi_lb` to `roi_ub` in price,
+    /// inclusive, with the price ticks of `T`, such as
+    /// `RoiVectorMarketDepth::<i32>::new_generic(..)`.
+    pub fn new_generic(tick_size: f32, lot_size: f32, roi_lb: f32, roi_ub: f32) -> Self {
+        let roi_lb = T::from_price(roi_lb, tick_size);
+        let roi_ub = T::from_price(roi_ub, tick_size);
+        let len = if roi_ub < roi_lb {
+            0
+        } else {
+            roi_ub.index_from(roi_lb) + 1
+        };
+        Self {
+            tick_size,
+            lot_size,
+            timestamp: 0,
+            bid_timestamp: 0,
+            ask_timestamp: 0,
+            ask_depth: vec![0.0; len],
+            bid_depth: vec![0.0; len],
+            best_bid_tick: T::INVALID_MIN,
+            best_ask_tick: T::INVALID_MAX,
+            low_bid_tick: T::INVALID_MAX,
+            high_ask_tick: T::INVALID_MIN,
+            roi_lb,
+            roi_ub,
+            bid_level_timestamps: None,
+            ask_level_timestamps: None,
+        }
+    }
+
+    /// Keeps the exchange timestamp of the last update of each level, which is returned by
+    /// [`MarketDepth::bid_timestamp_at_tick`] and [`MarketDepth::ask_timestamp_at_tick`], at the
+    /// cost of another vector per side.
+    pub fn with_level_timestamps(self) -> Self {
+        let len = self.bid_depth.len();
+        Self {
+            bid_level_timestamps: Some(vec![0; len]),
+            ask_level_timestamps: Some(vec![0; len]),
+            ..self
+        }
+    }
+
+    fn index(&self, price_tick: T) -> Option<usize> {
+        if price_tick < self.roi_lb || price_tick > self.roi_ub {
+            None
+        } else {
+            Some(price_tick.index_from(self.roi_lb))
+        }
+    }
+}
+
+impl<T: Tick> MarketDepth for RoiVectorMarketDepth<T> {
+    type Tick = T;
+
+    fn update_bid_depth(
+        &mut self,
+        price: f32,
+        qty: f32,
+        timestamp: i64,
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
+        let qty_lot = (qty / self.lot_size).round() as i32;
+        let prev_best_bid_tick = self.best_bid_tick;
+        let Some(index) = self.index(price_tick) else {
+            return (
+                price_tick,
+                prev_best_bid_tick,
+                self.best_bid_tick,
+                0.0,
+                0.0,
+                timestamp,
+            );
+        };
+        let prev_qty = self.bid_depth[index];
+        self.bid_depth[index] = if qty_lot > 0 { qty } else { 0.0 };
+        self.bid_timestamp = timestamp;
+        if let Some(timestamps) = self.bid_level_timestamps.as_mut() {
+            timestamps[index] = timestamp;
+        }
+
+        if qty_lot == 0 {
+            if price_tick == self.best_bid_tick {
+                self.best_bid_tick = depth_below(
+                    &self.bid_depth,
+                    self.roi_lb,
+                    self.best_bid_tick,
+                    self.low_bid_tick,
+                );
+                if self.best_bid_tick == T::INVALID_MIN {
+                    self.low_bid_tick = T::INVALID_MAX
+                }
+            }
+        } else {
+            if price_tick > self.best_bid_tick {
+                self.best_bid_tick = price_tick;
+                if self.best_bid_tick >= self.best_ask_tick {
+                    self.best_ask_tick = depth_above(
+                        &self.ask_depth,
+                        self.roi_lb,
+                        self.best_bid_tick,
+                        self.high_ask_tick,
+                    );
+                }
+            }
+            self.low_bid_tick = self.low_bid_tick.min(price_tick);
+        }
+        (
+            price_tick,
+            prev_best_bid_tick,
+            self.best_bid_tick,
+            prev_qty,
+            qty,
+            timestamp,
+        )
+    }
+
+    fn update_ask_depth(
+        &mut self,
+        price: f32,
+        qty: f32,
+        timestamp: i64,
+    ) -> (T, T, T, f32, f32, i64) {
+        let price_tick = T::from_price(price, self.tick_size);
+        let qty_lot = (qty / self.lot_size).round() as i32;
+        let prev_best_ask_tick = self.best_ask_tick;
+        let Some(index) = self.index(price_tick) else {
+            return (
+                price_tick,
+                prev_best_ask_tick,
+                self.best_ask_tick,
+                0.0,
+                0.0,
+                timestamp,
+            );
+        };
+        let prev_qty = self.ask_depth[index];
+        self.ask_depth[index] = if qty_lot > 0 { qty } else { 0.0 };
+        self.ask_timestamp = timestamp;
+        if let Some(timestamps) = self.ask_level_timestamps.as_mut() {
+            timestamps[index] = timestamp;
+        }
+
+        if qty_lot == 0 {
+            if price_tick == self.best_ask_tick {
+                self.best_ask_tick = depth_above(
+                    &self.ask_depth,
+                    self.roi_lb,
+                    self.best_ask_tick,
+                    self.high_ask_tick,
+                );
+                if self.best_ask_tick == T::INVALID_MAX {
+                    self.high_ask_tick = T::INVALID_MIN
+                }
+            }
+        } else {
+            if price_tick < self.best_ask_tick {
+                self.best_ask_tick = price_tick;
+                if self.best_bid_tick >= self.best_ask_tick {
+                    self.best_bid_tick = depth_below(
+                        &self.bid_depth,
+                        self.roi_lb,
+                        self.best_ask_tick,
+                        self.low_bid_tick,
+                    );
+                }
+            }
+            self.high_ask_tick = self.high_ask_tick.max(price_tick);
+        }
+        (
+            price_tick,
+            prev_best_ask_tick,
+            self.best_ask_tick,
+            prev_qty,
+            qty,
+            timestamp,
+        )
+    }
+
+    fn clear_depth(&mut self, side: i64, clear_upto_price: f32) {
+        let clear_upto = T::from_price(clear_upto_price, self.tick_size);
+        if side == BUY {
+            if self.best_bid_tick != T::INVALID_MIN {
+                let from = self
+                    .index(clear_upto.max(self.roi_lb))
+                    .unwrap_or(usize::MAX);
+                let to = self
+                    .index(self.best_bid_tick.min(self.roi_ub))
+                    .map_or(0, |t| t + 1);
+                for t in from..to {
+                    self.bid_depth[t] = 0.0;
+                }
+            }
+            self.best_bid_tick = depth_below(
+                &self.bid_depth,
+                self.roi_lb,
+                clear_upto.saturating_sub(T::ONE),
+                self.low_bid_tick,
+            );
+            if self.best_bid_tick == T::INVALID_MIN {
+                self.low_bid_tick = T::INVALID_MAX;
+            }
+        } else if side == SELL {
+            if self.best_ask_tick != T::INVALID_MAX {
+                let from = self
+                    .index(self.best_ask_tick.max(self.roi_lb))
+                    .unwrap_or(usize::MAX);
+                let to = self.index(clear_upto.min(self.roi_ub)).map_or(0, |t| t + 1);
+                for t in from..to {
+                    self.ask_depth[t] = 0.0;
+                }
+            }
+            self.best_ask_tick = depth_above(
+                &self.ask_depth,
+                self.roi_lb,
+                clear_upto.saturating_add(T::ONE),
+                self.high_ask_tick,
+            );
+            if self.best_ask_tick == T::INVALID_MAX {
+                self.high_ask_tick = T::INVALID_MIN;
+            }
+        } else {
+            self.bid_depth.fill(0.0);
+            self.ask_depth.fill(0.0);
+            self.best_bid_tick = T::INVALID_MIN;
+            self.best_ask_tick = T::INVALID_MAX;
+            self.low_bid_tick = T::INVALID_MAX;
+            self.high_ask_tick = T::INVALID_MIN;
+        }
+    }
+
+    fn best_bid(&self) -> f32 {
+        self.best_bid_tick.to_price(self.tick_size)
+    }
+
+    fn best_ask(&self) -> f32 {
+        self.best_ask_tick.to_price(self.tick_size)
+    }
+
+    fn best_bid_tick(&self) -> T {
+        self.best_bid_tick
+    }
+
+    fn best_ask_tick(&self) -> T {
+        self.best_ask_tick
+    }
+
+    fn tick_size(&self) -> f32 {
+        self.tick_size
+    }
+
+    fn lot_size(&self) -> f32 {
+        self.lot_size
+    }
+
+    /// Returns the quantity at the bid price tick, which is zero outside the range of interest.
+    fn bid_qty_at_tick(&self, price_tick: T) -> f32 {
+        self.index(price_tick)
+            .map_or(0.0, |index| self.bid_depth[index])
+    }
+
+    /// Returns the quantity at the ask price tick, which is zero outside the range of interest.
+    fn ask_qty_at_tick(&self, price_tick: T) -> f32 {
+        self.index(price_tick)
+            .map_or(0.0, |index| self.ask_depth[index])
+    }
+
+    fn bid_timestamp(&self) -> i64 {
+        self.bid_timestamp
+    }
+
+    fn ask_timestamp(&self) -> i64 {
+        self.ask_timestamp
+    }
+
+    fn bid_timestamp_at_tick(&self, price_tick: T) -> Option<i64> {
+        let timestamps = self.bid_level_timestamps.as_ref()?;
+        let index = self.index(price_tick)?;
+        (self.bid_depth[index] > 0.0).then(|| timestamps[index])
+    }
+
+    fn ask_timestamp_at_tick(&self, price_tick: T) -> Option<i64> {
+        let timestamps = self.ask_level_timestamps.as_ref()?;
+        let index = self.index(price_tick)?;
+        (self.ask_depth[index] > 0.0).then(|| timestamps[index])
+    }
+
+    fn tick_range(&self) -> Option<(T, T)> {
+        Some((self.roi_lb, self.roi_ub))
+    }
+}
+
+#[cfg(feature = "backtest")]
+impl<T: Tick> ApplySnapshot for RoiVectorMarketDepth<T> {
+    fn apply_snapshot(&mut self, data: &Data<Event>) {
+        self.best_bid_tick = T::INVALID_MIN;
+        self.best_ask_tick = T::INVALID_MAX;
+        self.low_bid_tick = T::INVALID_MAX;
+        self.high_ask_tick = T::INVALID_MIN;
+        self.bid_depth.fill(0.0);
+        self.ask_depth.fill(0.0);
+        for row_num in 0..data.len() {
+            let price = data[row_num].px;
+            let qty = data[row_num].qty;
+
+            let price_tick = T::from_price(price, self.tick_size);
+            let Some(index) = self.index(price_tick) else {
+                continue;
+            };
+            let timestamp = data[row_num].exch_ts;
+            if data[row_num].ev & BUY == BUY {
+                self.best_bid_tick = self.best_bid_tick.max(price_tick);
+                self.low_bid_tick = self.low_bid_tick.min(price_tick);
+                self.bid_depth[index] = qty;
+                self.bid_timestamp = timestamp;
+                if let Some(timestamps) = self.bid_level_timestamps.as_mut() {
+                    timestamps[index] = timestamp;
+                }
+            } else if data[row_num].ev & SELL == SELL {
+                self.best_ask_tick = self.best_ask_tick.min(price_tick);
+                self.high_ask_tick = self.high_ask_tick.max(price_tick);
+                self.ask_depth[index] = qty;
+                self.ask_timestamp = timestamp;
+                if let Some(timestamps) = self.ask_level_timestamps.as_mut() {
+                    timestamps[index] = timestamp;
+                }
+            }
+        }
+    }
+
+    fn snapshot(&self, timestamp: i64) -> Vec<Event> {
+        let level = |(i, qty): (usize, &f32)| (self.roi_lb + T::from_i64(i as i64), *qty);
+        // The levels beyond the best, which are left by the crossing updates, are not part of the
+        // book.
+        let bid_end = if self.best_bid_tick < self.roi_lb {
+            0
+        } else {
+            (self.best_bid_tick.index_from(self.roi_lb) + 1).min(self.bid_depth.len())
+        };
+        let ask_start = if self.best_ask_tick < self.roi_lb {
+            0
+        } else {
+            self.best_ask_tick
+                .index_from(self.roi_lb)
+                .min(self.ask_depth.len())
+        };
+        snapshot_rows(
+            self.bid_depth[..bid_end]
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, qty)| **qty > 0.0)
+                .map(level),
+            self.ask_depth[ask_start..]
+                .iter()
+                .enumerate()
+                .filter(|(_, qty)| **qty > 0.0)
+                .map(|(i, qty)| level((ask_start + i, qty))),
+            self.tick_size,
+            timestamp,
+        )
+    }
+}
@@ -1,23 +1,49 @@
-use std::fmt::{Display, Formatter};
+use core::fmt::{Display, Formatter};
 
+use crate::ty::Status;
+
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub enum BuildError {
     BuilderIncomplete(&'static str),
     Duplicate(String, String),
     ConnectorNotFound(String),
+    ProfileNotFound(String),
     Error(anyhow::Error),
 }
 
+#[cfg(feature = "std")]
 impl Display for BuildError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for BuildError {}
 
+#[cfg(feature = "std")]
 impl From<anyhow::Error> for BuildError {
     fn from(value: anyhow::Error) -> Self {
         BuildError::Error(value)
     }
 }
+
+/// Error returned when an order status update violates the order lifecycle.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct InvalidTransition {
+    pub from: Status,
+    pub to: Status,
+}
+
+impl Display for InvalidTransition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "invalid order status transition from {:?} to {:?}",
+            self.from, self.to
+        )
+    }
+}
+
+impl core::error::Error for InvalidTransition {}
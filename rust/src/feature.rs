@@ -0,0 +1,118 @@
+use crate::{
+    depth::{MarketDepth, Tick},
+    signal::DecayingSum,
+    ty::{Event, BUY, SELL},
+};
+
+/// A feature of an asset's market, which [`FeatureSet`] maintains.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    /// The imbalance of the quantities on the book, `(bid - ask) / (bid + ask)`, from `-1` to
+    /// `1`, where each side's quantity is the sum of its `levels` price levels from the best
+    /// price, counted in ticks including the empty levels.
+    BookImbalance { levels: usize },
+    /// The best ask price minus the best bid price.
+    Spread,
+    /// The imbalance of the traded quantities by the aggressor side, `(buy - sell) / (buy +
+    /// sell)`, from `-1` to `1`, where the quantities decay by event time with the half-life in
+    /// nanoseconds. See [`DecayingSum`].
+    TradeImbalance { half_life: i64 },
+}
+
+/// The features of an asset, which the engine updates on every depth and trade event of the
+/// asset, so that the strategy reads the latest values by
+/// [`Interface::features`](crate::Interface::features) instead of recomputing them at every
+/// wakeup, with the same definitions in backtesting and live trading.
+///
+/// The values are in order of addition by [`feature`](Self::feature). A value is `NaN` until it's
+/// defined, such as the book imbalance while either side of the book is empty, or the trade
+/// imbalance before the first trade.
+#[derive(Clone, Debug, Default)]
+pub struct FeatureSet {
+    features: Vec<Feature>,
+    values: Vec<f64>,
+    // The decayed buy and sell quantities of each trade imbalance feature, in order of the
+    // features.
+    flows: Vec<Option<(DecayingSum, DecayingSum)>>,
+}
+
+impl FeatureSet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds the feature, of which the value follows those of the features added before.
+    pub fn feature(mut self, feature: Feature) -> Self {
+        self.flows.push(match feature {
+            Feature::TradeImbalance { half_life } => {
+                Some((DecayingSum::new(half_life), DecayingSum::new(half_life)))
+            }
+            _ => None,
+        });
+        self.features.push(feature);
+        self.values.push(f64::NAN);
+        self
+    }
+
+    pub fn features(&self) -> &[Feature] {
+        &self.features
+    }
+
+    /// Returns the latest values of the features.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Updates the features of the book after the depth is updated.
+    pub fn on_depth<MD>(&mut self, depth: &MD)
+    where
+        MD: MarketDepth,
+    {
+        let best_bid_tick = depth.best_bid_tick();
+        let best_ask_tick = depth.best_ask_tick();
+        let empty =
+            best_bid_tick == MD::Tick::INVALID_MIN || best_ask_tick == MD::Tick::INVALID_MAX;
+        for (feature, value) in self.features.iter().zip(self.values.iter_mut()) {
+            *value = match feature {
+                Feature::BookImbalance { levels } if !empty => {
+                    let (mut bid_tick, mut ask_tick) = (best_bid_tick, best_ask_tick);
+                    let mut bid_qty = 0.0;
+                    let mut ask_qty = 0.0;
+                    for _ in 0..*levels {
+                        bid_qty += depth.bid_qty_at_tick(bid_tick) as f64;
+                        ask_qty += depth.ask_qty_at_tick(ask_tick) as f64;
+                        bid_tick = bid_tick.saturating_sub(MD::Tick::ONE);
+                        ask_tick = ask_tick.saturating_add(MD::Tick::ONE);
+                    }
+                    (bid_qty - ask_qty) / (bid_qty + ask_qty)
+                }
+                Feature::Spread if !empty => (depth.best_ask() - depth.best_bid()) as f64,
+                Feature::TradeImbalance { .. } => continue,
+                _ => f64::NAN,
+            };
+        }
+    }
+
+    /// Updates the features of the trades with the trade, of which the side is the aggressor's.
+    pub fn on_trade(&mut self, trade: &Event) {
+        let buy = trade.ev & BUY == BUY;
+        if !buy && trade.ev & SELL != SELL {
+            // The aggressor side is unknown.
+            return;
+        }
+        for (flow, value) in self.flows.iter_mut().zip(self.values.iter_mut()) {
+            let Some((buy_qty, sell_qty)) = flow else {
+                continue;
+            };
+            if buy {
+                buy_qty.add(trade.exch_ts, trade.qty as f64);
+            } else {
+                sell_qty.add(trade.exch_ts, trade.qty as f64);
+            }
+            // Both sides decay at the same rate, so the imbalance holds until the next trade.
+            let buy_qty = buy_qty.value_at(trade.exch_ts);
+            let sell_qty = sell_qty.value_at(trade.exch_ts);
+            *value = (buy_qty - sell_qty) / (buy_qty + sell_qty);
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! The C ABI of the backtester, so that it can be linked from the other languages, such as C++ and
+//! Julia. A backtest is created from the JSON configuration of its assets by [`hbt_create`] and
+//! driven through an opaque pointer, in the same manner as [`Interface`]. The values are read into
+//! the buffers provided by the caller, so the library doesn't hand out any memory other than the
+//! backtest itself and the last error message.
+//!
+//! The configuration is an object of the list of the assets, in order of their asset numbers:
+//!
+//! ```json
+//! {
+//!     "assets": [
+//!         {
+//!             "data": ["SOLUSDT_20240215.npz"],
+//!             "latency": {"intp": {"filepath": "latency_20240215.npz"}},
+//!             "asset_type": {"linear": {"contract_size": 1.0}},
+//!             "queue_model": {"power_prob3": {"n": 3.0}},
+//!             "tick_size": 0.001,
+//!             "lot_size": 1.0,
+//!             "exchange": "no_partial_fill",
+//!             "fee": {"maker_fee": -0.00005, "taker_fee": 0.0007}
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! The latency is either `constant` with `entry_latency` and `response_latency`, or `intp` with
+//! `filepath` and `out_of_range`, one of `clamp`, the default, `extrapolate`, and `error`. The
+//! queue model is one of `power_prob`, `power_prob2`, and `power_prob3` with `n`, `log_prob`, and
+//! `log_prob2`. The exchange is one of `no_partial_fill`, `partial_fill`, and `reference_only`.
+//! `initial_snapshot`, `bbo_feed`, and `venue_profile` can be set as well. The market depth is
+//! [`HashMapMarketDepth`].
+//!
+//! The functions that can fail return `-1` on failure, whose reason is then returned by
+//! [`hbt_last_error`]. Those that return a flag return `1` for `true` and `0` for `false`, and the
+//! others return `0` on success. The time in force, the order type, the side, and the status are
+//! the integers of the discriminants of [`TimeInForce`](crate::ty::TimeInForce),
+//! [`OrdType`](crate::ty::OrdType), [`Side`], and [`Status`](crate::ty::Status).
+//!
+//! A panic is caught at the boundary and fails the function in the same way, after which the
+//! backtest may be inconsistent and should only be freed. This requires the library to be built to
+//! unwind, such as by the `release-ffi` profile, since the `release` profile aborts on a panic.
+//!
+//! A backtest isn't thread-safe; it must not be used by more than one thread at a time.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    slice,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    backtest::{
+        backtest::MultiAssetMultiExchangeBacktest,
+        models::QueuePos,
+        spec::{ord_type, time_in_force, AssetSpec},
+        BtBuilder,
+    },
+    depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth, INVALID_MAX, INVALID_MIN},
+    ty::{Order, Side, StateValues},
+    Interface,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // A nul byte, which can be quoted from the configuration, would end the C string early.
+    let message = CString::new(message.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Returns the flag, or `-1` setting the last error if the result is an error.
+fn flag<E: ToString>(result: Result<bool, E>) -> i32 {
+    match result {
+        Ok(flag) => flag as i32,
+        Err(error) => {
+            set_last_error(error.to_string());
+            -1
+        }
+    }
+}
+
+/// Runs the body of an entry point, returning `on_panic` and setting the last error if it
+/// panics, as a panic must not unwind across the boundary.
+fn guard<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            set_last_error(format!("panicked: {message}"));
+            on_panic
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BacktestSpec {
+    assets: Vec<AssetSpec>,
+}
+
+/// The backtest that the functions drive, which is opaque to the caller.
+pub struct HftBacktest {
+    hbt: MultiAssetMultiExchangeBacktest<QueuePos, HashMapMarketDepth>,
+    num_assets: usize,
+}
+
+impl HftBacktest {
+    /// Checks the asset number, as an invalid one would panic across the boundary otherwise.
+    fn check_asset_no(&self, asset_no: usize) -> bool {
+        if asset_no < self.num_assets {
+            true
+        } else {
+            set_last_error(format!("invalid asset number {asset_no}"));
+            false
+        }
+    }
+}
+
+/// The order to submit by [`hbt_submit_order`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HbtOrderRequest {
+    pub order_id: i64,
+    pub price: f32,
+    pub qty: f32,
+    /// The trigger price of a stop or take-profit order, which is ignored for the other order
+    /// types.
+    pub trigger_price: f32,
+    /// `1` for a buy order and `-1` for a sell order.
+    pub side: i8,
+    pub time_in_force: u8,
+    pub order_type: u8,
+}
+
+/// The top of the market depth of an asset, read by [`hbt_depth`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HbtDepth {
+    pub best_bid_tick: i64,
+    pub best_ask_tick: i64,
+    pub best_bid: f32,
+    pub best_ask: f32,
+    pub best_bid_qty: f32,
+    pub best_ask_qty: f32,
+    pub tick_size: f32,
+    pub lot_size: f32,
+}
+
+/// An order of an asset, read by [`hbt_orders`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HbtOrder {
+    pub order_id: i64,
+    pub exch_timestamp: i64,
+    pub local_timestamp: i64,
+    pub price: f32,
+    pub qty: f32,
+    pub leaves_qty: f32,
+    pub exec_price: f32,
+    pub exec_qty: f32,
+    pub side: i8,
+    pub order_type: u8,
+    pub time_in_force: u8,
+    pub status: u8,
+    pub req: u8,
+    pub maker: bool,
+}
+
+impl From<&Order<QueuePos>> for HbtOrder {
+    fn from(order: &Order<QueuePos>) -> Self {
+        Self {
+            order_id: order.order_id,
+            exch_timestamp: order.exch_timestamp,
+            local_timestamp: order.local_timestamp,
+            price: order.price(),
+            qty: order.qty,
+            leaves_qty: order.leaves_qty,
+            exec_price: order.exec_price(),
+            exec_qty: order.exec_qty,
+            side: order.side as i8,
+            order_type: order.order_type as u8,
+            time_in_force: order.time_in_force as u8,
+            status: order.status as u8,
+            req: order.req as u8,
+            maker: order.maker,
+        }
+    }
+}
+
+/// Returns the message of the last error on the calling thread, or null if no error has occurred.
+/// The message is valid until the next error on the thread.
+#[no_mangle]
+pub extern "C" fn hbt_last_error() -> *const c_char {
+    guard(ptr::null(), || {
+        LAST_ERROR.with(|last_error| {
+            last_error
+                .borrow()
+                .as_ref()
+                .map_or(ptr::null(), |message| message.as_ptr())
+        })
+    })
+}
+
+/// Builds the backtest from the JSON configuration, reading the data of the assets. Returns null
+/// if the configuration is null or invalid, or the data can't be read. The backtest must be freed
+/// by [`hbt_free`].
+///
+/// # Safety
+/// `config` must be null or a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hbt_create(config: *const c_char) -> *mut HftBacktest {
+    guard(ptr::null_mut(), || {
+        if config.is_null() {
+            set_last_error("null configuration".to_string());
+            return ptr::null_mut();
+        }
+        let config = match CStr::from_ptr(config).to_str() {
+            Ok(config) => config,
+            Err(error) => {
+                set_last_error(error.to_string());
+                return ptr::null_mut();
+            }
+        };
+        let spec: BacktestSpec = match serde_json::from_str(config) {
+            Ok(spec) => spec,
+            Err(error) => {
+                set_last_error(error.to_string());
+                return ptr::null_mut();
+            }
+        };
+        let mut builder = BtBuilder::new();
+        for asset in spec.assets.iter() {
+            match asset.build() {
+                Ok(asset) => builder = builder.add(asset),
+                Err(error) => {
+                    set_last_error(error.to_string());
+                    return ptr::null_mut();
+                }
+            }
+        }
+        match builder.build() {
+            Ok(hbt) => Box::into_raw(Box::new(HftBacktest {
+                hbt,
+                num_assets: spec.assets.len(),
+            })),
+            Err(error) => {
+                set_last_error(error.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Closes and frees the backtest.
+///
+/// # Safety
+/// `hbt` must be null or a backtest returned by [`hbt_create`] that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn hbt_free(hbt: *mut HftBacktest) {
+    guard((), || {
+        if !hbt.is_null() {
+            let mut hbt = Box::from_raw(hbt);
+            let _ = hbt.hbt.close();
+        }
+    })
+}
+
+/// Returns the current timestamp of the backtest, or `-1` if it fails.
+///
+/// # Safety
+/// `hbt` must be a live backtest returned by [`hbt_create`], as for all the functions below.
+#[no_mangle]
+pub unsafe extern "C" fn hbt_current_timestamp(hbt: *const HftBacktest) -> i64 {
+    guard(-1, || (*hbt).hbt.current_timestamp())
+}
+
+/// Elapses the backtest by `duration`. Returns `0` if the data has ended.
+///
+/// # Safety
+/// See [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_elapse(hbt: *mut HftBacktest, duration: i64) -> i32 {
+    guard(-1, || flag((*hbt).hbt.elapse(duration)))
+}
+
+/// Elapses the backtest by `duration` to account for the processing time of the strategy.
+///
+/// # Safety
+/// See [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_elapse_bt(hbt: *mut HftBacktest, duration: i64) -> i32 {
+    guard(-1, || flag((*hbt).hbt.elapse_bt(duration)))
+}
+
+/// Waits until the next feed, or the next order response too if `include_order_resp` is `true`,
+/// up to `timeout`.
+///
+/// # Safety
+/// See [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_wait_next_feed(
+    hbt: *mut HftBacktest,
+    include_order_resp: bool,
+    timeout: i64,
+) -> i32 {
+    guard(-1, || {
+        flag((*hbt).hbt.wait_next_feed(include_order_resp, timeout))
+    })
+}
+
+/// Submits the order and, if `wait` is `true`, waits for its response.
+///
+/// # Safety
+/// `req` must point to a valid request, and see [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_submit_order(
+    hbt: *mut HftBacktest,
+    asset_no: usize,
+    req: *const HbtOrderRequest,
+    wait: bool,
+) -> i32 {
+    guard(-1, || {
+        let hbt = &mut *hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        let req = &*req;
+        let Some(tif) = time_in_force(req.time_in_force) else {
+            set_last_error(format!("invalid time in force {}", req.time_in_force));
+            return -1;
+        };
+        let Some(order_type) = ord_type(req.order_type) else {
+            set_last_error(format!("invalid order type {}", req.order_type));
+            return -1;
+        };
+        // The backtest takes the trigger price of the orders submitted while it's set.
+        let trigger_price = order_type.is_trigger().then_some(req.trigger_price);
+        hbt.hbt.set_trigger_price(asset_no, trigger_price);
+        let result = if req.side == Side::Buy as i8 {
+            hbt.hbt.submit_buy_order(
+                asset_no,
+                req.order_id,
+                req.price,
+                req.qty,
+                tif,
+                order_type,
+                wait,
+            )
+        } else if req.side == Side::Sell as i8 {
+            hbt.hbt.submit_sell_order(
+                asset_no,
+                req.order_id,
+                req.price,
+                req.qty,
+                tif,
+                order_type,
+                wait,
+            )
+        } else {
+            set_last_error(format!("invalid side {}", req.side));
+            return -1;
+        };
+        flag(result)
+    })
+}
+
+/// Cancels the order and, if `wait` is `true`, waits for its response.
+///
+/// # Safety
+/// See [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_cancel(
+    hbt: *mut HftBacktest,
+    asset_no: usize,
+    order_id: i64,
+    wait: bool,
+) -> i32 {
+    guard(-1, || {
+        let hbt = &mut *hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        flag(hbt.hbt.cancel(asset_no, order_id, wait))
+    })
+}
+
+/// Clears the orders that have ended of the asset, or of all the assets if `asset_no` is
+/// negative.
+///
+/// # Safety
+/// See [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_clear_inactive_orders(hbt: *mut HftBacktest, asset_no: i64) -> i32 {
+    guard(-1, || {
+        let hbt = &mut *hbt;
+        let asset_no = match usize::try_from(asset_no) {
+            Ok(asset_no) if !hbt.check_asset_no(asset_no) => return -1,
+            Ok(asset_no) => Some(asset_no),
+            Err(_) => None,
+        };
+        hbt.hbt.clear_inactive_orders(asset_no);
+        0
+    })
+}
+
+/// Reads the top of the market depth of the asset into `out`.
+///
+/// # Safety
+/// `out` must point to a writable [`HbtDepth`], and see [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_depth(
+    hbt: *const HftBacktest,
+    asset_no: usize,
+    out: *mut HbtDepth,
+) -> i32 {
+    guard(-1, || {
+        let hbt = &*hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        let depth = hbt.hbt.depth(asset_no);
+        *out = HbtDepth {
+            best_bid_tick: depth.best_bid_tick(),
+            best_ask_tick: depth.best_ask_tick(),
+            best_bid: depth.best_bid(),
+            best_ask: depth.best_ask(),
+            best_bid_qty: depth.bid_qty_at_tick(depth.best_bid_tick()),
+            best_ask_qty: depth.ask_qty_at_tick(depth.best_ask_tick()),
+            tick_size: depth.tick_size(),
+            lot_size: depth.lot_size(),
+        };
+        0
+    })
+}
+
+/// Reads the quantities of `levels` ticks of a side of the market depth, from the best price
+/// away from the mid, into `qty_out`, where the ticks without a quantity are zero. The side is
+/// `1` for the bid and `-1` for the ask. If the side is empty, all the quantities are zero.
+///
+/// # Safety
+/// `qty_out` must point to `levels` writable `float`s, and see [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_depth_levels(
+    hbt: *const HftBacktest,
+    asset_no: usize,
+    side: i8,
+    qty_out: *mut f32,
+    levels: usize,
+) -> i32 {
+    guard(-1, || {
+        let hbt = &*hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        let depth = hbt.hbt.depth(asset_no);
+        let qty_out = slice::from_raw_parts_mut(qty_out, levels);
+        if side == Side::Buy as i8 {
+            let best_bid_tick = depth.best_bid_tick();
+            for (i, qty) in qty_out.iter_mut().enumerate() {
+                *qty = if best_bid_tick == INVALID_MIN {
+                    0.0
+                } else {
+                    depth.bid_qty_at_tick(best_bid_tick - i as i64)
+                };
+            }
+        } else if side == Side::Sell as i8 {
+            let best_ask_tick = depth.best_ask_tick();
+            for (i, qty) in qty_out.iter_mut().enumerate() {
+                *qty = if best_ask_tick == INVALID_MAX {
+                    0.0
+                } else {
+                    depth.ask_qty_at_tick(best_ask_tick + i as i64)
+                };
+            }
+        } else {
+            set_last_error(format!("invalid side {side}"));
+            return -1;
+        }
+        0
+    })
+}
+
+/// Reads the state values of the asset, such as the position and the balance, into `out`.
+///
+/// # Safety
+/// `out` must point to a writable [`StateValues`], and see [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_state_values(
+    hbt: *const HftBacktest,
+    asset_no: usize,
+    out: *mut StateValues,
+) -> i32 {
+    guard(-1, || {
+        let hbt = &*hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        *out = hbt.hbt.state_values(asset_no);
+        0
+    })
+}
+
+/// Reads the orders of the asset, in order of their order ids, into `out` up to `capacity`.
+/// Returns the number of the orders, which can exceed `capacity`, in which case the caller can
+/// read them all again with a larger buffer.
+///
+/// # Safety
+/// `out` must point to `capacity` writable [`HbtOrder`]s, and see [`hbt_current_timestamp`].
+#[no_mangle]
+pub unsafe extern "C" fn hbt_orders(
+    hbt: *const HftBacktest,
+    asset_no: usize,
+    out: *mut HbtOrder,
+    capacity: usize,
+) -> isize {
+    guard(-1, || {
+        let hbt = &*hbt;
+        if !hbt.check_asset_no(asset_no) {
+            return -1;
+        }
+        let mut orders: Vec<_> = hbt.hbt.orders(asset_no).values().collect();
+        orders.sort_by_key(|order| order.order_id);
+        for (i, order) in orders.iter().take(capacity).enumerate() {
+            out.add(i).write(HbtOrder::from(*order));
+        }
+        orders.len() as isize
+    })
+}
@@ -0,0 +1,142 @@
+use crate::{
+    depth::{MarketDepth, Tick},
+    ty::{OrdType, Status, TimeInForce},
+    Interface,
+};
+
+/// Maintains a delta-neutral hedge of the position in one asset with another asset, such as
+/// hedging perpetual futures inventory with spot. Since it only relies on [`Interface`], the same
+/// hedger works in both backtesting and live trading.
+///
+/// The target position of the hedge asset is `-ratio * position`. When the hedge position
+/// deviates from the target by more than the rebalance band, the hedger submits an aggressive
+/// limit order for the difference, priced `slippage` ticks through the best price on the other
+/// side. In backtesting, the hedge asset is an ordinary asset, so the hedge orders go through its
+/// own latency model, queue model, and fees.
+///
+/// Only one hedge order is outstanding at a time. If the previous hedge order is still resting on
+/// the book, it is canceled first and the rebalancing happens on the next update.
+pub struct DeltaHedger {
+    asset_no: usize,
+    hedge_asset_no: usize,
+    ratio: f64,
+    band: f64,
+    slippage: i64,
+    next_order_id: i64,
+    order_id: Option<i64>,
+}
+
+impl DeltaHedger {
+    /// Hedges the position of `asset_no` with `hedge_asset_no` by the given hedge ratio. The
+    /// hedge is rebalanced when the deviation from the target exceeds `band`, in the quantity of
+    /// the hedge asset.
+    pub fn new(asset_no: usize, hedge_asset_no: usize, ratio: f64, band: f64) -> Self {
+        Self {
+            asset_no,
+            hedge_asset_no,
+            ratio,
+            band,
+            slippage: 0,
+            next_order_id: i64::MAX / 2,
+            order_id: None,
+        }
+    }
+
+    /// Sets the number of ticks through the best price at which the hedge orders are priced. The
+    /// default is `0`, which crosses the spread at the best price.
+    pub fn slippage(self, slippage: i64) -> Self {
+        Self { slippage, ..self }
+    }
+
+    /// Sets the first order id of the hedge orders, which increases by one for each hedge order.
+    /// It must not overlap with the order ids that the strategy uses for the hedge asset. The
+    /// default is `i64::MAX / 2`.
+    pub fn order_id_base(self, order_id_base: i64) -> Self {
+        Self {
+            next_order_id: order_id_base,
+            ..self
+        }
+    }
+
+    /// Returns the target position of the hedge asset.
+    pub fn target_position<Q, MD, I>(&self, hbt: &I) -> f64
+    where
+        Q: Sized + Clone,
+        I: Interface<Q, MD>,
+    {
+        -self.ratio * hbt.position(self.asset_no)
+    }
+
+    /// Rebalances the hedge if needed. This should be called after each elapse. Returns `true` if
+    /// a new hedge order is submitted.
+    pub fn update<Q, MD, I>(&mut self, hbt: &mut I) -> Result<bool, I::Error>
+    where
+        Q: Sized + Clone,
+        MD: MarketDepth,
+        I: Interface<Q, MD>,
+    {
+        if let Some(order_id) = self.order_id {
+            match hbt.orders(self.hedge_asset_no).get(&order_id) {
+                Some(order)
+                    if order.status == Status::New || order.status == Status::PartiallyFilled =>
+                {
+                    if order.req == Status::None {
+                        hbt.cancel(self.hedge_asset_no, order_id, false)?;
+                    }
+                    return Ok(false);
+                }
+                Some(order) if order.req != Status::None => {
+                    // The hedge order is not acknowledged yet.
+                    return Ok(false);
+                }
+                _ => {
+                    self.order_id = None;
+                }
+            }
+        }
+
+        let diff = self.target_position(hbt) - hbt.position(self.hedge_asset_no);
+        if diff.abs() <= self.band {
+            return Ok(false);
+        }
+
+        let depth = hbt.depth(self.hedge_asset_no);
+        if depth.best_bid_tick() == MD::Tick::INVALID_MIN
+            || depth.best_ask_tick() == MD::Tick::INVALID_MAX
+        {
+            return Ok(false);
+        }
+        let lot_size = depth.lot_size() as f64;
+        let qty = (diff.abs() / lot_size).round() * lot_size;
+        if qty < lot_size {
+            return Ok(false);
+        }
+        let tick_size = depth.tick_size();
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        self.order_id = Some(order_id);
+        if diff > 0.0 {
+            let price = (depth.best_ask_tick().to_i64() + self.slippage).to_price(tick_size);
+            hbt.submit_buy_order(
+                self.hedge_asset_no,
+                order_id,
+                price,
+                qty as f32,
+                TimeInForce::GTC,
+                OrdType::Limit,
+                false,
+            )
+        } else {
+            let price = (depth.best_bid_tick().to_i64() - self.slippage).to_price(tick_size);
+            hbt.submit_sell_order(
+                self.hedge_asset_no,
+                order_id,
+                price,
+                qty as f32,
+                TimeInForce::GTC,
+                OrdType::Limit,
+                false,
+            )
+        }
+    }
+}
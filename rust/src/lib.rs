@@ -14,17 +14,31 @@
 //! - Backtesting of multi-asset and multi-exchange models
 //! - Deployment of a live trading bot using the same algo code
 //!
+//! ## Features
+//! - `backtest` (default): The backtesting features in `backtest`.
+//! - `live` (default): The live bot and the exchange connectors in `live` and `connector`, which
+//!   depend on the Tokio and HTTP stack.
+//! - `std`: The common features such as [`Interface`](crate::Interface) and the market depth
+//!   implementations, enabled by both of the above. Without it, only the core types in [`ty`] and
+//!   the market depth traits in [`depth`] are available, which compile without the standard
+//!   library.
+//!
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
 use std::collections::HashMap;
 
-use crate::{
-    backtest::state::StateValues,
-    ty::{OrdType, Order, Event, TimeInForce},
-};
+#[cfg(feature = "std")]
+use crate::ty::{Adjustment, OrdType, Order, Event, ExternalEvent, StateValues, TimeInForce};
 
 /// Defines backtesting features.
+#[cfg(feature = "backtest")]
 pub mod backtest;
 
 /// Defines exchange connectors
+#[cfg(feature = "live")]
 pub mod connector;
 
 /// Defines a market depth to build the order book from the feed data.
@@ -33,13 +47,43 @@ pub mod depth;
 /// Defines errors.
 pub mod error;
 
+/// Provides the market features maintained by the engine for the strategy.
+#[cfg(feature = "std")]
+pub mod feature;
+
+/// Provides the C ABI of the backtester.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// Provides a delta hedging helper for both backtesting and live trading.
+#[cfg(feature = "std")]
+pub mod hedge;
+
 /// Defines live bot features.
+#[cfg(feature = "live")]
 pub mod live;
 
+/// Provides the Python bindings of the backtester.
+#[cfg(feature = "hftbacktest-py")]
+pub mod python;
+
+/// Defines a trading schedule to gate order submission by time windows.
+#[cfg(feature = "std")]
+pub mod schedule;
+
+/// Provides moving averages and statistics that decay by event time for signals.
+#[cfg(feature = "std")]
+pub mod signal;
+
+/// Provides a harness to unit-test strategies with scripted market scenarios.
+#[cfg(feature = "backtest")]
+pub mod testing;
+
 /// Defines types.
 pub mod ty;
 
 /// Provides an interface for a backtester or a bot.
+#[cfg(feature = "std")]
 pub trait Interface<Q, MD>
 where
     Q: Sized + Clone,
@@ -54,12 +98,39 @@ where
 
     fn depth(&self, asset_no: usize) -> &MD;
 
-    fn trade(&self, asset_no: usize) -> &Vec<Event>;
+    fn trade(&self, asset_no: usize) -> &[Event];
 
     fn clear_last_trades(&mut self, asset_no: Option<usize>);
 
+    /// Returns the external events delivered so far, such as news releases or scheduled
+    /// announcements, in order of their timestamps. It is only available in backtesting.
+    fn external_events(&self) -> &Vec<ExternalEvent>;
+
+    fn clear_external_events(&mut self);
+
+    /// Returns the position and balance adjustments applied so far, in order of their
+    /// timestamps. It is only available in backtesting.
+    fn adjustments(&self) -> &[Adjustment];
+
     fn orders(&self, asset_no: usize) -> &HashMap<i64, Order<Q>>;
 
+    /// Returns the entry latency of the order, from the submission to the receipt by the
+    /// exchange, once the response to the order has been received. It is only available in
+    /// backtesting.
+    fn order_entry_latency(&self, asset_no: usize, order_id: i64) -> Option<i64>;
+
+    /// Returns the number of the orders of the asset that can be submitted now without being
+    /// throttled, or `None` if the order submissions aren't throttled. It is only available in
+    /// live trading with a [`Throttle`](crate::live::throttle::Throttle), or with a connector
+    /// that limits its requests by a [`RateLimiter`](crate::connector::ratelimit::RateLimiter)
+    /// with order rate limits, in which case the venue's remaining order counts also apply.
+    fn order_budget(&self, asset_no: usize) -> Option<u32>;
+
+    /// Returns the latest values of the features of the asset, which the engine updates on every
+    /// event, in order of the features of the [`FeatureSet`](crate::feature::FeatureSet) set for
+    /// the asset, or an empty slice if it isn't set.
+    fn features(&self, asset_no: usize) -> &[f64];
+
     fn submit_buy_order(
         &mut self,
         asset_no: usize,
@@ -84,10 +155,47 @@ where
 
     fn cancel(&mut self, asset_no: usize, order_id: i64, wait: bool) -> Result<bool, Self::Error>;
 
+    /// Reduces the quantity of the order to `qty` without canceling it, which is the new total
+    /// quantity including the executed quantity, so it should be less than the order's quantity
+    /// and greater than the executed quantity. Whether the order keeps its queue position depends
+    /// on the venue; in backtesting, it's set by
+    /// [`ModifyPolicy`](crate::backtest::profile::ModifyPolicy).
+    fn reduce_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error>;
+
+    /// Modifies the price and the quantity of the order without canceling it, where `qty` is the
+    /// new total quantity including the executed quantity, so it should be greater than the
+    /// executed quantity. A modification that makes the order marketable takes the market, or
+    /// cancels a post-only order. Whether the order keeps its queue position depends on the venue;
+    /// in backtesting, it's set by [`ModifyPolicy`](crate::backtest::profile::ModifyPolicy).
+    fn modify_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        wait: bool,
+    ) -> Result<bool, Self::Error>;
+
     fn clear_inactive_orders(&mut self, asset_no: Option<usize>);
 
     fn elapse(&mut self, duration: i64) -> Result<bool, Self::Error>;
 
+    /// Waits until the next feed, a depth or trade event, is received, or the next order response
+    /// too if `include_order_resp` is `true`, up to `timeout`. If a wakeup interval is set, the
+    /// wakeups are coalesced to at most once per interval, and the strategy wakes with all the
+    /// events received in between applied.
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, Self::Error>;
+
     /// Elapses time only in backtesting. In live mode, it is ignored.
     ///
     /// The [`elapse`] method exclusively manages time during backtesting, meaning that factors such
@@ -101,6 +209,7 @@ where
 /// Gets price precision.
 ///
 /// [`tick_size`] should not be a computed value.
+#[cfg(feature = "std")]
 pub fn get_precision(tick_size: f32) -> usize {
     let s = tick_size.to_string();
     let mut prec = 0;
@@ -0,0 +1,97 @@
+use std::io::Error as IoError;
+
+use tracing::warn;
+
+/// Scheduling settings of a live thread: the cores that the thread is pinned to and the
+/// real-time priority. Since the OS scheduler's jitter adds to the order latency, pinning the
+/// strategy loop and the connector I/O threads to isolated cores with a real-time priority can
+/// reduce it.
+///
+/// Both are supported on Linux only. Setting a real-time priority typically requires the
+/// `CAP_SYS_NICE` capability or an `rtprio` limit; if a setting is not permitted, a warning is
+/// logged and the thread runs with the default.
+#[derive(Clone, Debug, Default)]
+pub struct ThreadSettings {
+    cores: Vec<usize>,
+    priority: Option<i32>,
+}
+
+impl ThreadSettings {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Pins the thread to the given core. Can be called multiple times to allow multiple cores.
+    pub fn core(mut self, core: usize) -> Self {
+        self.cores.push(core);
+        self
+    }
+
+    /// Sets the `SCHED_FIFO` real-time priority, from 1 (lowest) to 99 (highest).
+    pub fn priority(self, priority: i32) -> Self {
+        Self {
+            priority: Some(priority),
+            ..self
+        }
+    }
+
+    /// Applies the settings to the calling thread.
+    pub fn apply(&self) -> Result<(), IoError> {
+        if !self.cores.is_empty() {
+            set_affinity(&self.cores)?;
+        }
+        if let Some(priority) = self.priority {
+            set_priority(priority)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the settings to the calling thread, logging a warning on failure.
+    pub(crate) fn apply_or_warn(&self, thread: &str) {
+        if let Err(error) = self.apply() {
+            warn!(?error, %thread, settings = ?self, "Couldn't apply the thread settings.");
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cores: &[usize]) -> Result<(), IoError> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &core in cores {
+            libc::CPU_SET(core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(IoError::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_priority(priority: i32) -> Result<(), IoError> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } != 0 {
+        return Err(IoError::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cores: &[usize]) -> Result<(), IoError> {
+    Err(IoError::new(
+        std::io::ErrorKind::Unsupported,
+        "CPU affinity is supported on Linux only",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_priority(_priority: i32) -> Result<(), IoError> {
+    Err(IoError::new(
+        std::io::ErrorKind::Unsupported,
+        "real-time priority is supported on Linux only",
+    ))
+}
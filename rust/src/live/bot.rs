@@ -1,11 +1,15 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    hint,
+    mem,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
-use chrono::Utc;
 use tokio::{
     select,
     sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
@@ -13,18 +17,29 @@ use tokio::{
 use tracing::{debug, error, info, warn};
 
 use crate::{
-    backtest::{
-        state::{State, StateValues},
-        Error,
-    },
     connector::Connector,
     depth::{
         btreemarketdepth::BTreeMarketDepth,
         hashmapmarketdepth::HashMapMarketDepth,
         MarketDepth,
     },
-    live::{AssetInfo, LiveBuilder},
-    ty::{Error as ErrorEvent, LiveEvent, OrdType, Order, Request, Event, Side, Status, TimeInForce, BUY, SELL},
+    feature::FeatureSet,
+    live::{
+        affinity::ThreadSettings,
+        clock::{self, set_clock_source, ClockSource},
+        conflation::ConflationQueue,
+        journal::{AssetState, StateJournal},
+        latency::{self, Stage},
+        risk::{HaltReason, RiskManager},
+        sim::SimVenue,
+        throttle::{Throttle, ThrottleAction},
+        wait::{Timer, WaitStrategy, WakeupStats},
+        AssetInfo,
+        LiveBuilder,
+    },
+    schedule::Schedule,
+    ty::{Depth, Error as ErrorEvent, ErrorType, ExternalEvent, LiveEvent, OrdType, Order, RateLimitStatus, Request, ResponsePriority, Event, Side, StateValues, Status, SyncKind, TimeInForce, Trade, BUY, SELL},
+        Adjustment,
     Interface,
 };
 
@@ -34,11 +49,58 @@ pub enum BotError {
     OrderNotFound,
     DuplicateOrderId,
     InvalidOrderStatus,
+    /// The quantity of the order reduction isn't between the executed quantity and the order's
+    /// quantity.
+    InvalidOrderQty,
+    OutsideSchedule,
+    /// The initial state wasn't received, or the books and the user data streams weren't synced,
+    /// within the startup timeout.
+    StartupTimeout,
+    /// The initial position of the asset doesn't match the expected position.
+    PositionMismatch(usize),
+    /// A connector reported a critical error before the initial state was received.
+    StartupAborted,
+    /// The order id is outside the range available to the strategy of a
+    /// [`Portfolio`](crate::live::portfolio::Portfolio).
+    InvalidOrderId,
+    /// The order is rejected by the [`RiskManager`].
+    RiskLimitExceeded,
+    /// The bot has been halted by the kill switch of the [`RiskManager`].
+    Halted,
+    /// The order submission exceeds the limits of the [`Throttle`] with
+    /// [`ThrottleAction::Reject`].
+    Throttled,
     Custom(String),
 }
 
-#[tokio::main(worker_threads = 2)]
-async fn thread_main(
+/// The default time that [`Bot::run`] waits for the initial state if an expected position is set
+/// without [`Bot::with_startup_sync`].
+const DEFAULT_STARTUP_TIMEOUT: i64 = 30_000_000_000;
+
+fn thread_main(
+    ev_tx: Sender<LiveEvent>,
+    req_rx: UnboundedReceiver<Request>,
+    conns: HashMap<String, Box<dyn Connector + Send + 'static>>,
+    mapping: Vec<(String, AssetInfo)>,
+    io_thread: Option<ThreadSettings>,
+) {
+    if let Some(settings) = io_thread.as_ref() {
+        settings.apply_or_warn("io");
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .on_thread_start(move || {
+            if let Some(settings) = io_thread.as_ref() {
+                settings.apply_or_warn("io");
+            }
+        })
+        .build()
+        .unwrap();
+    runtime.block_on(run_connectors(ev_tx, req_rx, conns, mapping));
+}
+
+async fn run_connectors(
     ev_tx: Sender<LiveEvent>,
     mut req_rx: UnboundedReceiver<Request>,
     mut conns: HashMap<String, Box<dyn Connector + Send + 'static>>,
@@ -52,6 +114,7 @@ async fn thread_main(
             req = req_rx.recv() => {
                 match req {
                     Some(Request::Order((an, order))) => {
+                        latency::record(Stage::OrderDispatch, clock::now() - order.local_timestamp);
                         if let Some((connector_name, _)) = mapping.get(an) {
                             let conn_ = conns.get_mut(connector_name).unwrap();
                             let ev_tx_ = ev_tx.clone();
@@ -66,6 +129,11 @@ async fn thread_main(
                                         error!(?error, "cancel error");
                                     }
                                 }
+                                Status::Replaced => {
+                                    if let Err(error) = conn_.modify(an, order, ev_tx_) {
+                                        error!(?error, "modify error");
+                                    }
+                                }
                                 req => {
                                     error!(?req, "invalid request.");
                                 }
@@ -81,6 +149,15 @@ async fn thread_main(
     }
 }
 
+/// What [`Bot::process_next`] has received.
+enum Received {
+    Feed,
+    OrderResponse,
+    Other,
+    Timeout,
+    Disconnected,
+}
+
 pub struct Bot {
     req_tx: UnboundedSender<Request>,
     req_rx: Option<UnboundedReceiver<Request>>,
@@ -89,10 +166,53 @@ pub struct Bot {
     pub depth: Vec<HashMapMarketDepth>,
     pub orders: Vec<HashMap<i64, Order<()>>>,
     pub position: Vec<f64>,
+    pub entry_price: Vec<f64>,
+    pub mark_price: Vec<f64>,
+    pub balance: Vec<f64>,
     trade: Vec<Vec<Event>>,
     conns: Option<HashMap<String, Box<dyn Connector + Send + 'static>>>,
     assets: Vec<(String, AssetInfo)>,
     error_handler: Option<Box<dyn FnMut(ErrorEvent) -> Result<(), BotError>>>,
+    fill_hook: Option<Box<dyn FnMut(usize, &Order<()>, &StateValues)>>,
+    schedule: Option<Schedule>,
+    external_events: Vec<ExternalEvent>,
+    conflation: Option<Arc<ConflationQueue>>,
+    strategy_thread: Option<ThreadSettings>,
+    io_thread: Option<ThreadSettings>,
+    wait_strategy: WaitStrategy,
+    timer: Timer,
+    timer_wakeup: WakeupStats,
+    event_wakeup: WakeupStats,
+    wakeup_interval: Option<i64>,
+    last_wakeup: i64,
+    response_priority: Option<ResponsePriority>,
+    // The received events reordered by the response priority, which are processed before
+    // receiving more.
+    reordered: VecDeque<LiveEvent>,
+    sim: Option<SimVenue>,
+    startup_timeout: Option<i64>,
+    expected_position: HashMap<usize, f64>,
+    warm_sync_timeout: Option<i64>,
+    // Whether the connector of the asset streams its book, which the warm sync waits for.
+    streams_depth: Vec<bool>,
+    book_synced: Vec<bool>,
+    user_data_synced: Vec<bool>,
+    // key: asset_no, value: the one-cancels-other group that the submitted orders join.
+    oco_group: HashMap<usize, i64>,
+    throttle: Option<Throttle>,
+    // The order submissions held by the throttle, in order of submission.
+    throttled: VecDeque<(usize, Order<()>)>,
+    // key: asset_no, value: the last status of the venue's API rate limits reported.
+    rate_limits: HashMap<usize, RateLimitStatus>,
+    risk: Option<RiskManager>,
+    features: Vec<FeatureSet>,
+    journal: Option<StateJournal>,
+    // key: asset_no, value: the position restored from the journal, which is compared with the
+    // initial position.
+    restored_position: HashMap<usize, f64>,
+    // The orders restored from the journal that the exchange hasn't confirmed yet, as (asset_no,
+    // order_id).
+    unconfirmed: HashSet<(usize, i64)>,
 }
 
 impl Bot {
@@ -112,7 +232,17 @@ impl Bot {
 
         let orders = assets.iter().map(|_| HashMap::new()).collect();
         let position = assets.iter().map(|_| 0.0).collect();
+        let entry_price = assets.iter().map(|_| 0.0).collect();
+        let mark_price = assets.iter().map(|_| 0.0).collect();
+        let balance = assets.iter().map(|_| 0.0).collect();
         let trade = assets.iter().map(|_| Vec::new()).collect();
+        let streams_depth = assets
+            .iter()
+            .map(|(name, _)| conns.get(name).is_some_and(|conn| conn.streams_depth()))
+            .collect();
+        let book_synced = vec![false; assets.len()];
+        let user_data_synced = vec![false; assets.len()];
+        let features = assets.iter().map(|_| FeatureSet::new()).collect();
 
         Self {
             ev_tx: Some(ev_tx),
@@ -122,97 +252,1044 @@ impl Bot {
             depth,
             orders,
             position,
+            entry_price,
+            mark_price,
+            balance,
             conns: Some(conns),
             assets,
             trade,
             error_handler: None,
+            fill_hook: None,
+            schedule: None,
+            external_events: Vec::new(),
+            conflation: None,
+            strategy_thread: None,
+            io_thread: None,
+            wait_strategy: Default::default(),
+            timer: Timer::new(),
+            timer_wakeup: Default::default(),
+            event_wakeup: Default::default(),
+            wakeup_interval: None,
+            last_wakeup: i64::MIN,
+            response_priority: None,
+            reordered: VecDeque::new(),
+            sim: None,
+            startup_timeout: None,
+            expected_position: HashMap::new(),
+            warm_sync_timeout: None,
+            streams_depth,
+            book_synced,
+            user_data_synced,
+            oco_group: HashMap::new(),
+            throttle: None,
+            throttled: VecDeque::new(),
+            rate_limits: HashMap::new(),
+            risk: None,
+            features,
+            journal: None,
+            restored_position: HashMap::new(),
+            unconfirmed: HashSet::new(),
+        }
+    }
+
+    /// Validates the order submissions by the [`RiskManager`] before they reach the connectors,
+    /// and halts the bot by its kill switch once the loss or the errors reach their thresholds.
+    pub fn with_risk_manager(self, risk: RiskManager) -> Self {
+        Self {
+            risk: Some(risk),
+            ..self
+        }
+    }
+
+    /// Returns the [`RiskManager`], if it's set.
+    pub fn risk_manager(&self) -> Option<&RiskManager> {
+        self.risk.as_ref()
+    }
+
+    /// Returns why the bot has been halted by the kill switch, or `None` if it hasn't.
+    pub fn halt_reason(&self) -> Option<&HaltReason> {
+        self.risk.as_ref().and_then(|risk| risk.halted())
+    }
+
+    /// Triggers the kill switch manually, which cancels all the open orders and rejects any
+    /// further submission with [`BotError::Halted`].
+    pub fn halt(&mut self) {
+        self.halt_(HaltReason::Manual);
+    }
+
+    fn halt_(&mut self, reason: HaltReason) {
+        let risk = self.risk.get_or_insert_with(Default::default);
+        if risk.halted().is_some() {
+            return;
+        }
+        error!(
+            ?reason,
+            "The kill switch is triggered. Cancels all the open orders and halts."
+        );
+        risk.halt(reason);
+        for asset_no in 0..self.orders.len() {
+            self.cancel_all(asset_no);
+        }
+    }
+
+    /// Cancels all the open orders of the asset, including those held by the throttle. The
+    /// orders waiting for the responses to their submissions are canceled once they're accepted.
+    fn cancel_all(&mut self, asset_no: usize) {
+        let throttled: HashSet<i64> = self
+            .throttled
+            .iter()
+            .filter(|(an, _)| *an == asset_no)
+            .map(|(_, order)| order.order_id)
+            .collect();
+        let order_ids: Vec<i64> = self.orders[asset_no]
+            .values()
+            .filter(|order| order.cancellable() || throttled.contains(&order.order_id))
+            .map(|order| order.order_id)
+            .collect();
+        for order_id in order_ids {
+            if let Err(error) = self.cancel(asset_no, order_id, false) {
+                warn!(?error, order_id, "Couldn't cancel the order.");
+            }
+        }
+    }
+
+    /// Halts the bot if the loss has reached the maximum loss of the [`RiskManager`].
+    fn check_loss(&mut self) {
+        let reason = self
+            .risk
+            .as_ref()
+            .and_then(|risk| risk.check_loss(&self.depth));
+        if let Some(reason) = reason {
+            self.halt_(reason);
+        }
+    }
+
+    /// Journals the state of the assets, which is the position, the average entry price, and the
+    /// balance, and the open orders to the file at `path`, restoring them as the previous run has
+    /// left them, so that a restarted bot keeps track of its position and of the orders that may
+    /// still be open on the exchange. The restored orders keep their order ids in use until they
+    /// end and are cleared by [`Interface::clear_inactive_orders`].
+    ///
+    /// The restored state is merged with that fetched from the exchange at startup. The state of
+    /// the assets is replaced by that reported by the connectors, and the restored orders are
+    /// updated by the order responses of the connectors that reconcile the orders of the previous
+    /// run, such as [`BinanceFutures`](crate::connector::binancefutures::BinanceFutures) with
+    /// its order id store. If the startup sync is set, [`Bot::run`] warns of the positions that
+    /// have changed since they were journaled, such as by the fills missed while the bot was down,
+    /// and of the restored orders that the exchange hasn't confirmed.
+    pub fn with_state_journal(mut self, path: &str) -> Result<Self, std::io::Error> {
+        let (journal, restored) = StateJournal::open(path)?;
+        for (asset_no, state) in restored.states {
+            if asset_no >= self.assets.len() {
+                warn!(asset_no, "The asset of the journaled state is not found.");
+                continue;
+            }
+            self.position[asset_no] = state.position;
+            self.entry_price[asset_no] = state.entry_price;
+            self.balance[asset_no] = state.balance;
+            self.restored_position.insert(asset_no, state.position);
+        }
+        for ((asset_no, order_id), mut order) in restored.orders {
+            let Some(orders) = self.orders.get_mut(asset_no) else {
+                warn!(asset_no, order_id, "The asset of the journaled order is not found.");
+                continue;
+            };
+            if order.active() {
+                // The request in flight is lost along with the previous run.
+                order.req = Status::None;
+            }
+            orders.insert(order_id, order);
+            self.unconfirmed.insert((asset_no, order_id));
+        }
+        info!(
+            position = ?self.position,
+            orders = self.unconfirmed.len(),
+            "Restored the state from the journal."
+        );
+        self.journal = Some(journal);
+        Ok(self)
+    }
+
+    fn journal_state(&mut self, asset_no: usize) {
+        if let Some(journal) = self.journal.as_mut() {
+            journal.write_state(
+                asset_no,
+                AssetState {
+                    position: self.position[asset_no],
+                    entry_price: self.entry_price[asset_no],
+                    balance: self.balance[asset_no],
+                },
+            );
+        }
+    }
+
+    fn journal_order(&mut self, asset_no: usize, order_id: i64) {
+        let order = self.orders[asset_no].get(&order_id);
+        if let (Some(journal), Some(order)) = (self.journal.as_mut(), order) {
+            journal.write_order(asset_no, order);
+        }
+    }
+
+    /// Throttles the outbound order submissions by the limits of the [`Throttle`]. The
+    /// submissions beyond the limits are queued or rejected by its [`ThrottleAction`], and the
+    /// remaining budget is available by [`Interface::order_budget`].
+    pub fn with_throttle(self, throttle: Throttle) -> Self {
+        Self {
+            throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Returns the number of the order submissions of the asset held by the throttle.
+    pub fn throttled_orders(&self, asset_no: usize) -> usize {
+        self.throttled
+            .iter()
+            .filter(|(an, _)| *an == asset_no)
+            .count()
+    }
+
+    /// Returns the last status of the venue's API rate limits reported by the connector of the
+    /// asset, if it limits its requests by a
+    /// [`RateLimiter`](crate::connector::ratelimit::RateLimiter).
+    pub fn rate_limit_status(&self, asset_no: usize) -> Option<&RateLimitStatus> {
+        self.rate_limits.get(&asset_no)
+    }
+
+    /// Sends the order submissions held by the throttle that the limits allow now, keeping the
+    /// order of the submissions of each asset.
+    fn release_throttled(&mut self) {
+        let Some(throttle) = self.throttle.as_mut() else {
+            return;
+        };
+        if self.throttled.is_empty() {
+            return;
+        }
+        let now = clock::now();
+        let mut blocked = HashSet::new();
+        let mut released = Vec::new();
+        let mut i = 0;
+        while i < self.throttled.len() {
+            let asset_no = self.throttled[i].0;
+            if !blocked.contains(&asset_no) && throttle.acquire(asset_no, now) {
+                released.push(self.throttled.remove(i).unwrap());
+            } else {
+                blocked.insert(asset_no);
+                i += 1;
+            }
+        }
+        for (asset_no, mut order) in released {
+            order.local_timestamp = now;
+            if let Some(local_order) = self.orders[asset_no].get_mut(&order.order_id) {
+                local_order.local_timestamp = now;
+            }
+            debug!(
+                asset_no,
+                order_id = order.order_id,
+                "Releases the throttled order."
+            );
+            self.send(asset_no, order);
+        }
+    }
+
+    /// Returns the earliest time at which the throttle may release a held order submission.
+    fn next_throttle_release(&self) -> Option<i64> {
+        let throttle = self.throttle.as_ref()?;
+        let now = clock::now();
+        self.throttled
+            .iter()
+            .filter_map(|(asset_no, _)| throttle.next_release(*asset_no, now))
+            .min()
+    }
+
+    /// Constructs a bot that is driven by the simulated venue instead of the connectors, for
+    /// testing the live path deterministically against recorded events. The assets are those of
+    /// the venue. This selects [`ClockSource::Simulated`] for the whole process, which starts at
+    /// the venue's first event, so it shouldn't be used in a process that trades live.
+    ///
+    /// [`Bot::run`] does nothing for a simulated bot, and [`Bot::elapse`] replays the events up
+    /// to the simulated time plus the duration without waiting, returning `false` once all the
+    /// events have been replayed.
+    pub fn simulated(venue: SimVenue) -> Self {
+        let assets = venue
+            .assets()
+            .into_iter()
+            .map(|asset_info| ("sim".to_string(), asset_info))
+            .collect();
+        set_clock_source(ClockSource::Simulated).unwrap();
+        if let Some(start_time) = venue.start_time() {
+            clock::set_simulated_time(start_time);
+        }
+        Self {
+            sim: Some(venue),
+            ..Self::new(HashMap::new(), assets)
+        }
+    }
+
+    /// Sets the schedule that gates order submission. See [`Schedule`].
+    pub fn with_schedule(self, schedule: Schedule) -> Self {
+        Self {
+            schedule: Some(schedule),
+            ..self
+        }
+    }
+
+    /// Enables the depth conflation, which merges the depth updates by price level instead of
+    /// queueing them while the strategy falls behind, to bound the memory and the staleness
+    /// under load. See [`Bot::conflated`] for the number of merged updates.
+    pub fn with_depth_conflation(self) -> Self {
+        let num_assets = self.assets.len();
+        Self {
+            conflation: Some(Arc::new(ConflationQueue::new(num_assets))),
+            ..self
+        }
+    }
+
+    /// Caps the depth of the asset to `ticks` ticks on each side of the mid price, discarding the
+    /// levels beyond it, to reduce the memory and the update cost for a symbol with a huge book.
+    /// The cap is advertised to the strategy by [`MarketDepth::tick_range`]. See
+    /// [`HashMapMarketDepth::with_cap`].
+    pub fn with_depth_cap(mut self, asset_no: usize, ticks: i64) -> Self {
+        if let Some(depth) = self.depth.get_mut(asset_no) {
+            depth.cap = Some(ticks);
+        }
+        self
+    }
+
+    /// Sets the features of the asset that the bot maintains on every depth and trade event of
+    /// the asset, which the strategy reads by [`Interface::features`]. See [`FeatureSet`].
+    pub fn with_features(mut self, asset_no: usize, features: FeatureSet) -> Self {
+        if let Some(slot) = self.features.get_mut(asset_no) {
+            *slot = features;
+        }
+        self
+    }
+
+    /// Keeps the exchange timestamp of the last update of each level of the depths, which is
+    /// returned by [`MarketDepth::bid_timestamp_at_tick`] and
+    /// [`MarketDepth::ask_timestamp_at_tick`]. See [`HashMapMarketDepth::with_level_timestamps`].
+    pub fn with_level_timestamps(self) -> Self {
+        let depth = self
+            .depth
+            .into_iter()
+            .map(HashMapMarketDepth::with_level_timestamps)
+            .collect();
+        Self { depth, ..self }
+    }
+
+    /// Returns the number of depth updates of the asset that have been merged into a queued
+    /// update by the depth conflation.
+    pub fn conflated(&self, asset_no: usize) -> u64 {
+        self.conflation
+            .as_ref()
+            .map(|queue| queue.conflated(asset_no))
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of events that are queued but not processed yet, if the depth
+    /// conflation is enabled.
+    pub fn queued_events(&self) -> Option<usize> {
+        self.conflation.as_ref().map(|queue| queue.len())
+    }
+
+    /// Sets the scheduling settings of the strategy thread, which are applied to the thread that
+    /// calls [`Bot::run`]. Therefore, `run` should be called from the thread that runs the
+    /// strategy loop.
+    pub fn with_strategy_thread(self, settings: ThreadSettings) -> Self {
+        Self {
+            strategy_thread: Some(settings),
+            ..self
+        }
+    }
+
+    /// Sets the scheduling settings of the connector I/O threads.
+    pub fn with_io_thread(self, settings: ThreadSettings) -> Self {
+        Self {
+            io_thread: Some(settings),
+            ..self
+        }
+    }
+
+    /// Sets how [`Bot::elapse`] waits for the next event. See [`WaitStrategy`].
+    pub fn with_wait_strategy(self, wait_strategy: WaitStrategy) -> Self {
+        Self {
+            wait_strategy,
+            ..self
+        }
+    }
+
+    /// Coalesces the wakeups of [`Bot::wait_next_feed`] to at most once per `interval`. A feed
+    /// that arrives sooner than `interval` after the previous wakeup doesn't wake the strategy
+    /// immediately; instead, the strategy wakes once the interval has passed, with all the events
+    /// received in between applied.
+    pub fn with_wakeup_interval(self, interval: i64) -> Self {
+        Self {
+            wakeup_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sets whether the order responses or the market data updates are processed first among
+    /// the events that have been received together, that is, those that are already queued when
+    /// the bot takes the next event, or those with the same timestamp in a simulation. By
+    /// default, the events are processed in the order they are received. See
+    /// [`ResponsePriority`].
+    pub fn with_response_priority(self, response_priority: ResponsePriority) -> Self {
+        Self {
+            response_priority: Some(response_priority),
+            ..self
+        }
+    }
+
+    /// Makes [`Bot::run`] wait up to `timeout` until the connectors deliver the initial state
+    /// fetched from the exchange, which is the position of every asset and, if the connector
+    /// provides them, the average entry price and the balance, so that the strategy loop begins
+    /// with the actual state. `run` fails with [`BotError::StartupTimeout`] if the position of any
+    /// asset isn't received in time, or with [`BotError::StartupAborted`] if a connector reports a
+    /// critical error meanwhile, such as when it aborts the startup due to
+    /// [`OpenOrderPolicy::Abort`](crate::connector::OpenOrderPolicy::Abort).
+    pub fn with_startup_sync(self, timeout: i64) -> Self {
+        Self {
+            startup_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Requires the initial position of the asset to be `qty`; otherwise, [`Bot::run`] refuses to
+    /// start and fails with [`BotError::PositionMismatch`]. The positions are compared within half
+    /// the lot size. This implies [`Bot::with_startup_sync`], with a timeout of 30 seconds if it
+    /// isn't set.
+    pub fn with_expected_position(mut self, asset_no: usize, qty: f64) -> Self {
+        self.expected_position.insert(asset_no, qty);
+        self
+    }
+
+    /// Makes [`Bot::run`] wait up to `timeout`, after the initial state if the startup sync is
+    /// set, until the bot is ready: the book of every asset has received a snapshot and at least
+    /// one diff consistent with it, and the user data stream of every asset is confirmed, so that
+    /// the strategy never quotes off an empty or half-built book. The books of the connectors that
+    /// don't stream them, such as [`Fix`](crate::connector::fix::Fix), aren't waited for. `run`
+    /// fails with [`BotError::StartupTimeout`] if the bot isn't ready in time. See
+    /// [`Bot::wait_ready`].
+    pub fn with_warm_sync(self, timeout: i64) -> Self {
+        Self {
+            warm_sync_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Sets the hook invoked on every fill reported by the exchange, with the asset number, the
+    /// updated order, whose `exec_qty` and `exec_price_tick` are those of the fill, and the asset's
+    /// state values. Since the position and the balance are updated separately by the exchange,
+    /// the state values may not reflect the fill yet.
+    pub fn with_fill_hook<F>(self, hook: F) -> Self
+    where
+        F: FnMut(usize, &Order<()>, &StateValues) + 'static,
+    {
+        Self {
+            fill_hook: Some(Box::new(hook)),
+            ..self
+        }
+    }
+
+    /// Returns the statistics of how late [`Bot::elapse`] returns after the requested duration
+    /// has passed.
+    pub fn timer_wakeup_stats(&self) -> &WakeupStats {
+        &self.timer_wakeup
+    }
+
+    /// Returns the statistics of the delay from when a connector receives a depth or trade event
+    /// to when the bot processes it.
+    pub fn event_wakeup_stats(&self) -> &WakeupStats {
+        &self.event_wakeup
+    }
+
+    /// Resets the wakeup latency statistics.
+    pub fn reset_wakeup_stats(&mut self) {
+        self.timer_wakeup.reset();
+        self.event_wakeup.reset();
+    }
+
+    /// Sets the one-cancels-other group that the orders of the asset submitted afterward join,
+    /// until it is reset with `None`. Since the Binance Futures connector has no native OCO
+    /// orders, the bot emulates it by canceling the other orders of the group once a fill of an
+    /// order of the group is reported, so unlike in backtesting, the other orders stay open for
+    /// the round trip and can be filled in the meantime.
+    pub fn set_oco_group(&mut self, asset_no: usize, group: Option<i64>) {
+        match group {
+            Some(group) => self.oco_group.insert(asset_no, group),
+            None => self.oco_group.remove(&asset_no),
+        };
+    }
+
+    fn cancel_oco_orders(&mut self, asset_no: usize, filled: &Order<()>) {
+        let Some(group) = filled.oco_group else {
+            return;
+        };
+        let order_ids: Vec<i64> = self.orders[asset_no]
+            .values()
+            .filter(|order| {
+                order.oco_group == Some(group)
+                    && order.order_id != filled.order_id
+                    && order.cancellable()
+            })
+            .map(|order| order.order_id)
+            .collect();
+        for order_id in order_ids {
+            if let Err(error) = self.cancel(asset_no, order_id, false) {
+                warn!(
+                    ?error,
+                    order_id, "Couldn't cancel the one-cancels-other order."
+                );
+            }
         }
     }
 
-    pub fn run(&mut self) {
+    /// Starts the connectors. If the startup sync is set, this blocks until the initial state is
+    /// received and checked against the expected positions. See [`Bot::with_startup_sync`].
+    pub fn run(&mut self) -> Result<(), BotError> {
+        if self.sim.is_some() {
+            return Ok(());
+        }
+        if let Some(asset_no) = self
+            .expected_position
+            .keys()
+            .find(|asset_no| **asset_no >= self.assets.len())
+        {
+            error!(%asset_no, "The asset of the expected position is not found.");
+            return Err(BotError::AssetNotFound);
+        }
+        if let Some(settings) = self.strategy_thread.as_ref() {
+            settings.apply_or_warn("strategy");
+        }
+        if let Some(queue) = self.conflation.clone() {
+            // The connectors' events are drained into the conflation queue as they arrive.
+            let (_, ev_rx) = channel();
+            let ev_rx = mem::replace(&mut self.ev_rx, ev_rx);
+            let io_thread = self.io_thread.clone();
+            thread::spawn(move || {
+                if let Some(settings) = io_thread {
+                    settings.apply_or_warn("io");
+                }
+                while let Ok(ev) = ev_rx.recv() {
+                    queue.push(ev);
+                }
+                queue.close();
+            });
+        }
         let ev_tx = self.ev_tx.take().unwrap();
         let req_rx = self.req_rx.take().unwrap();
         let conns = self.conns.take().unwrap();
         let assets = self.assets.clone();
+        let io_thread = self.io_thread.clone();
         let _ = thread::spawn(move || {
-            thread_main(ev_tx, req_rx, conns, assets);
+            thread_main(ev_tx, req_rx, conns, assets, io_thread);
         });
+        self.sync_initial_state()?;
+        if let Some(timeout) = self.warm_sync_timeout {
+            self.wait_ready(timeout)?;
+        }
+        Ok(())
     }
 
-    fn elapse_(&mut self, duration: i64) -> Result<bool, BotError> {
-        let now = Instant::now();
-        let mut remaining_duration = duration;
-        loop {
-            let timeout = Duration::from_nanos(remaining_duration as u64);
-            match self.ev_rx.recv_timeout(timeout) {
-                Ok(LiveEvent::Depth(data)) => {
-                    // fixme: updates the depth only if exch_ts is greater than that of the existing
-                    //        level.
-                    let depth = unsafe { self.depth.get_unchecked_mut(data.asset_no) };
-                    depth.timestamp = data.exch_ts;
-                    for (px, qty) in data.bids {
-                        depth.update_bid_depth(px, qty, 0);
+    /// Returns `true` if the books and the user data streams of all assets are synced. See
+    /// [`Bot::with_warm_sync`].
+    pub fn is_ready(&self) -> bool {
+        (0..self.assets.len()).all(|asset_no| {
+            (self.book_synced[asset_no] || !self.streams_depth[asset_no])
+                && self.user_data_synced[asset_no]
+        })
+    }
+
+    /// Blocks, processing the events, until the bot is ready or `timeout` passes, which is the
+    /// readiness barrier that [`Bot::run`] passes through if [`Bot::with_warm_sync`] is set. Fails
+    /// with [`BotError::StartupTimeout`] if the bot isn't ready in time, or with
+    /// [`BotError::StartupAborted`] if a connector reports a critical error meanwhile.
+    pub fn wait_ready(&mut self, timeout: i64) -> Result<(), BotError> {
+        let deadline = clock::now() + timeout;
+        while !self.is_ready() {
+            let remaining = deadline - clock::now();
+            let ev = if remaining > 0 {
+                self.wait(Duration::from_nanos(remaining as u64)).ok()
+            } else {
+                None
+            };
+            let Some(ev) = ev else {
+                error!(
+                    book_synced = ?self.book_synced,
+                    user_data_synced = ?self.user_data_synced,
+                    "The books and the user data streams aren't synced."
+                );
+                return Err(BotError::StartupTimeout);
+            };
+            let aborted = matches!(
+                &ev,
+                LiveEvent::Error(error) if error.ty == ErrorType::CriticalConnectionError
+            );
+            self.process(ev)?;
+            if aborted {
+                error!("A connector aborted the startup.");
+                return Err(BotError::StartupAborted);
+            }
+        }
+        info!("The books and the user data streams are synced.");
+        Ok(())
+    }
+
+    fn sync_initial_state(&mut self) -> Result<(), BotError> {
+        let timeout = match self.startup_timeout {
+            Some(timeout) => timeout,
+            None if !self.expected_position.is_empty() => DEFAULT_STARTUP_TIMEOUT,
+            None => return Ok(()),
+        };
+        let deadline = clock::now() + timeout;
+        let mut pending: HashSet<usize> = (0..self.assets.len()).collect();
+        while !pending.is_empty() {
+            let remaining = deadline - clock::now();
+            if remaining <= 0 {
+                error!(?pending, "Couldn't receive the initial positions.");
+                return Err(BotError::StartupTimeout);
+            }
+            match self.wait(Duration::from_nanos(remaining as u64)) {
+                Ok(ev) => {
+                    let aborted = match &ev {
+                        LiveEvent::Position(data) => {
+                            pending.remove(&data.asset_no);
+                            false
+                        }
+                        LiveEvent::Error(error) => error.ty == ErrorType::CriticalConnectionError,
+                        _ => false,
+                    };
+                    self.process(ev)?;
+                    if aborted {
+                        error!(?pending, "A connector aborted the startup.");
+                        return Err(BotError::StartupAborted);
                     }
-                    for (px, qty) in data.asks {
-                        depth.update_ask_depth(px, qty, 0);
+                }
+                Err(_) => {
+                    error!(?pending, "Couldn't receive the initial positions.");
+                    return Err(BotError::StartupTimeout);
+                }
+            }
+        }
+        for (asset_no, qty) in self.expected_position.iter() {
+            let position = self.position[*asset_no];
+            let lot_size = self.assets[*asset_no].1.lot_size as f64;
+            if (position - qty).abs() >= lot_size / 2.0 {
+                error!(
+                    %asset_no,
+                    %position,
+                    expected = %qty,
+                    "The initial position doesn't match the expected position."
+                );
+                return Err(BotError::PositionMismatch(*asset_no));
+            }
+        }
+        for (asset_no, restored) in self.restored_position.iter() {
+            let position = self.position[*asset_no];
+            let lot_size = self.assets[*asset_no].1.lot_size as f64;
+            if (position - restored).abs() >= lot_size / 2.0 {
+                warn!(
+                    %asset_no,
+                    %position,
+                    %restored,
+                    "The position has changed since it was journaled."
+                );
+            }
+        }
+        if !self.unconfirmed.is_empty() {
+            warn!(
+                orders = ?self.unconfirmed,
+                "The orders restored from the journal aren't confirmed by the exchange."
+            );
+        }
+        info!(position = ?self.position, balance = ?self.balance, "Initial state is synced.");
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<LiveEvent, RecvTimeoutError> {
+        let ev = match self.conflation.as_ref() {
+            Some(queue) => queue.try_recv(),
+            None => self.ev_rx.try_recv(),
+        };
+        ev.map_err(|error| match error {
+            TryRecvError::Empty => RecvTimeoutError::Timeout,
+            TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+        })
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<LiveEvent, RecvTimeoutError> {
+        match self.conflation.as_ref() {
+            Some(queue) => queue.recv_timeout(timeout),
+            None => self.ev_rx.recv_timeout(timeout),
+        }
+    }
+
+    /// Reorders the events received together by the response priority and returns the first.
+    fn reorder(&mut self, first: LiveEvent, rest: Vec<LiveEvent>) -> LiveEvent {
+        let Some(response_priority) = self.response_priority else {
+            self.reordered.extend(rest);
+            return first;
+        };
+        let mut batch = rest;
+        batch.insert(0, first);
+        // The sort is stable, so the events of the same kind keep the received order.
+        batch.sort_by_key(|ev| {
+            let is_response = matches!(
+                ev,
+                LiveEvent::Order(_) | LiveEvent::Position(_) | LiveEvent::Balance(_)
+            );
+            let is_market_data = matches!(ev, LiveEvent::Depth(_) | LiveEvent::Trade(_));
+            match response_priority {
+                ResponsePriority::OrderResponseFirst => !is_response,
+                ResponsePriority::MarketDataFirst => !is_market_data,
+            }
+        });
+        self.reordered.extend(batch);
+        self.reordered.pop_front().unwrap()
+    }
+
+    /// Waits for the next event according to the wait strategy, reordering the events that are
+    /// already queued by the response priority.
+    fn wait(&mut self, timeout: Duration) -> Result<LiveEvent, RecvTimeoutError> {
+        if let Some(ev) = self.reordered.pop_front() {
+            return Ok(ev);
+        }
+        let ev = self.wait_(timeout)?;
+        if self.response_priority.is_none() {
+            return Ok(ev);
+        }
+        let mut rest = Vec::new();
+        while let Ok(ev) = self.try_recv() {
+            rest.push(ev);
+        }
+        Ok(self.reorder(ev, rest))
+    }
+
+    fn wait_(&self, timeout: Duration) -> Result<LiveEvent, RecvTimeoutError> {
+        let spin = match self.wait_strategy {
+            WaitStrategy::Park => return self.recv_timeout(timeout),
+            WaitStrategy::BusySpin => timeout,
+            WaitStrategy::SpinThenPark(spin) => timeout.min(Duration::from_nanos(spin as u64)),
+            WaitStrategy::Sleep(interval) => {
+                let start = Instant::now();
+                let interval = Duration::from_nanos(interval as u64);
+                loop {
+                    match self.try_recv() {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        result => return result,
+                    }
+                    let elapsed = start.elapsed();
+                    if elapsed >= timeout {
+                        return Err(RecvTimeoutError::Timeout);
                     }
+                    self.timer.sleep(interval.min(timeout - elapsed));
                 }
-                Ok(LiveEvent::Trade(data)) => {
-                    let trade = unsafe { self.trade.get_unchecked_mut(data.asset_no) };
-                    trade.push(Event {
-                        exch_ts: data.exch_ts,
-                        local_ts: data.local_ts,
-                        ev: {
-                            if data.side == 1 {
-                                BUY
-                            } else if data.side == -1 {
-                                SELL
-                            } else {
-                                0
-                            }
-                        },
-                        px: data.price,
-                        qty: data.qty,
-                    });
+            }
+        };
+        let start = Instant::now();
+        loop {
+            match self.try_recv() {
+                Err(RecvTimeoutError::Timeout) => {}
+                result => return result,
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= spin {
+                return if spin < timeout {
+                    self.recv_timeout(timeout - elapsed)
+                } else {
+                    Err(RecvTimeoutError::Timeout)
+                };
+            }
+            hint::spin_loop();
+        }
+    }
+
+    fn process(&mut self, ev: LiveEvent) -> Result<(), BotError> {
+        match ev {
+            LiveEvent::Depth(data) => {
+                let update_ts = latency::enabled().then(clock::now);
+                // fixme: updates the depth only if exch_ts is greater than that of the existing
+                //        level.
+                let depth = unsafe { self.depth.get_unchecked_mut(data.asset_no) };
+                depth.timestamp = data.exch_ts;
+                for (px, qty) in data.bids {
+                    depth.update_bid_depth(px, qty, data.exch_ts);
                 }
-                Ok(LiveEvent::Order(data)) => {
-                    debug!(?data, "Event::Order");
-                    match self
-                        .orders
-                        .get_mut(data.asset_no)
-                        .ok_or(BotError::AssetNotFound)?
-                        .entry(data.order.order_id)
-                    {
-                        Entry::Occupied(mut entry) => {
-                            let ex_order = entry.get_mut();
-                            if data.order.exch_timestamp >= ex_order.exch_timestamp {
-                                if ex_order.status == Status::Canceled
-                                    || ex_order.status == Status::Expired
-                                    || ex_order.status == Status::Filled
+                for (px, qty) in data.asks {
+                    depth.update_ask_depth(px, qty, data.exch_ts);
+                }
+                unsafe { self.features.get_unchecked_mut(data.asset_no) }.on_depth(depth);
+                if let Some(update_ts) = update_ts {
+                    latency::record(Stage::BookUpdate, clock::now() - update_ts);
+                }
+                self.check_loss();
+            }
+            LiveEvent::Trade(data) => {
+                let trade = Event {
+                    exch_ts: data.exch_ts,
+                    local_ts: data.local_ts,
+                    ev: {
+                        if data.side == 1 {
+                            BUY
+                        } else if data.side == -1 {
+                            SELL
+                        } else {
+                            0
+                        }
+                    },
+                    px: data.price,
+                    qty: data.qty,
+                };
+                unsafe { self.features.get_unchecked_mut(data.asset_no) }.on_trade(&trade);
+                unsafe { self.trade.get_unchecked_mut(data.asset_no) }.push(trade);
+                if let Some(risk) = self.risk.as_mut() {
+                    risk.on_trade(data.asset_no, data.price as f64);
+                    self.check_loss();
+                }
+            }
+            LiveEvent::Order(data) => {
+                debug!(?data, "Event::Order");
+                let asset_no = data.asset_no;
+                let order_id = data.order.order_id;
+                let mut filled = None;
+                match self
+                    .orders
+                    .get_mut(asset_no)
+                    .ok_or(BotError::AssetNotFound)?
+                    .entry(data.order.order_id)
+                {
+                    Entry::Occupied(mut entry) => {
+                        let ex_order = entry.get_mut();
+                        if data.order.exch_timestamp >= ex_order.exch_timestamp {
+                            if ex_order.terminal() {
+                                // Ignores the update since the current status is the final status.
+                            } else if !ex_order.status.can_transition_to(data.order.status) {
+                                warn!(?data, "Received an invalid order status transition.");
+                            } else {
+                                ex_order.update(&data.order);
+                                if matches!(
+                                    ex_order.status,
+                                    Status::Filled | Status::PartiallyFilled
+                                ) && ex_order.exec_qty > 0.0
                                 {
-                                    // Ignores the update since the current status is the final status.
-                                } else {
-                                    ex_order.update(&data.order);
+                                    filled = Some(ex_order.clone());
                                 }
                             }
                         }
-                        Entry::Vacant(entry) => {
-                            warn!(?data, "Received an unmanaged order.");
-                            entry.insert(data.order);
-                        }
                     }
+                    Entry::Vacant(entry) => {
+                        warn!(?data, "Received an unmanaged order.");
+                        entry.insert(data.order);
+                    }
+                }
+                self.unconfirmed.remove(&(asset_no, order_id));
+                self.journal_order(asset_no, order_id);
+                if self.halt_reason().is_some() {
+                    // The order accepted after the halt is canceled as well.
+                    let cancellable = self.orders[asset_no]
+                        .get(&order_id)
+                        .is_some_and(|order| order.cancellable());
+                    if cancellable {
+                        self.cancel(asset_no, order_id, false)?;
+                    }
+                }
+                if let Some(order) = filled {
+                    if let Some(risk) = self.risk.as_mut() {
+                        risk.on_fill(asset_no, &order);
+                    }
+                    self.check_loss();
+                    self.cancel_oco_orders(asset_no, &order);
+                    let state_values = self.state_values(asset_no);
+                    if let Some(hook) = self.fill_hook.as_mut() {
+                        hook(asset_no, &order, &state_values);
+                    }
+                }
+            }
+            LiveEvent::Position(data) => {
+                *(unsafe { self.position.get_unchecked_mut(data.asset_no) }) = data.qty;
+                if let Some(entry_price) = data.entry_price {
+                    *(unsafe { self.entry_price.get_unchecked_mut(data.asset_no) }) = entry_price;
+                }
+                if let Some(mark_price) = data.mark_price {
+                    *(unsafe { self.mark_price.get_unchecked_mut(data.asset_no) }) = mark_price;
+                    if let Some(risk) = self.risk.as_mut() {
+                        risk.on_mark_price(data.asset_no, mark_price);
+                        self.check_loss();
+                    }
+                }
+                self.journal_state(data.asset_no);
+            }
+            LiveEvent::Balance(data) => {
+                *(unsafe { self.balance.get_unchecked_mut(data.asset_no) }) = data.balance;
+                self.journal_state(data.asset_no);
+            }
+            LiveEvent::Error(error) => {
+                let reason = self
+                    .risk
+                    .as_mut()
+                    .and_then(|risk| risk.on_error(clock::now()));
+                if let Some(reason) = reason {
+                    self.halt_(reason);
+                }
+                if let Some(handler) = self.error_handler.as_mut() {
+                    handler(error)?;
                 }
-                Ok(LiveEvent::Position(data)) => {
-                    *(unsafe { self.position.get_unchecked_mut(data.asset_no) }) = data.qty;
+            }
+            LiveEvent::Synced(data) => {
+                let synced = match data.kind {
+                    SyncKind::Book => self.book_synced.get_mut(data.asset_no),
+                    SyncKind::UserData => self.user_data_synced.get_mut(data.asset_no),
+                };
+                if let Some(synced) = synced {
+                    debug!(?data, "Event::Synced");
+                    *synced = true;
                 }
-                Ok(LiveEvent::Error(error)) => {
-                    if let Some(handler) = self.error_handler.as_mut() {
-                        handler(error)?;
+            }
+            LiveEvent::RateLimit(data) => {
+                self.rate_limits.insert(data.asset_no, data);
+            }
+        }
+        Ok(())
+    }
+
+    /// Processes the next event that arrives by `until`, releasing the throttled order
+    /// submissions as the limits allow in the meantime.
+    fn process_next(&mut self, until: i64) -> Result<Received, BotError> {
+        loop {
+            self.release_throttled();
+            let deadline = self
+                .next_throttle_release()
+                .map_or(until, |release| release.min(until));
+            match self.receive_next(deadline)? {
+                Received::Timeout if deadline < until => {}
+                received => return Ok(received),
+            }
+        }
+    }
+
+    fn receive_next(&mut self, until: i64) -> Result<Received, BotError> {
+        let ev = match self.sim.as_mut() {
+            Some(_) if !self.reordered.is_empty() => self.reordered.pop_front().unwrap(),
+            Some(sim) => match sim.next_until(until) {
+                Some((ts, ev)) => {
+                    clock::set_simulated_time(ts);
+                    let mut rest = Vec::new();
+                    if self.response_priority.is_some() {
+                        while let Some((_, ev)) = sim.next_until(ts) {
+                            rest.push(ev);
+                        }
                     }
+                    self.reorder(ev, rest)
                 }
-                Err(RecvTimeoutError::Timeout) => {
+                None => {
+                    clock::set_simulated_time(until);
+                    return Ok(if sim.is_finished() {
+                        Received::Disconnected
+                    } else {
+                        Received::Timeout
+                    });
+                }
+            },
+            None => {
+                let timeout = until - clock::now();
+                if timeout <= 0 {
+                    return Ok(Received::Timeout);
+                }
+                match self.wait(Duration::from_nanos(timeout as u64)) {
+                    Ok(ev) => ev,
+                    Err(RecvTimeoutError::Timeout) => return Ok(Received::Timeout),
+                    Err(RecvTimeoutError::Disconnected) => return Ok(Received::Disconnected),
+                }
+            }
+        };
+        let received = match &ev {
+            LiveEvent::Depth(_) | LiveEvent::Trade(_) => Received::Feed,
+            LiveEvent::Order(_) => Received::OrderResponse,
+            _ => Received::Other,
+        };
+        self.process(ev)?;
+        Ok(received)
+    }
+
+    fn elapse_sim(&mut self, duration: i64) -> Result<bool, BotError> {
+        let until = clock::now() + duration;
+        loop {
+            match self.process_next(until)? {
+                Received::Timeout => return Ok(true),
+                Received::Disconnected => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+
+    fn wait_next_feed_(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, BotError> {
+        let deadline = clock::now() + timeout;
+        loop {
+            match self.process_next(deadline)? {
+                Received::Feed => break,
+                Received::OrderResponse if include_order_resp => break,
+                Received::Timeout => {
+                    self.last_wakeup = clock::now();
+                    return Ok(true);
+                }
+                Received::Disconnected => return Ok(false),
+                _ => {}
+            }
+        }
+        if let Some(interval) = self.wakeup_interval {
+            let next_wakeup = self.last_wakeup.saturating_add(interval).min(deadline);
+            while clock::now() < next_wakeup {
+                match self.process_next(next_wakeup)? {
+                    Received::Timeout => break,
+                    Received::Disconnected => return Ok(false),
+                    _ => {}
+                }
+            }
+        }
+        self.last_wakeup = clock::now();
+        Ok(true)
+    }
+
+    fn elapse_(&mut self, duration: i64) -> Result<bool, BotError> {
+        if self.sim.is_some() {
+            return self.elapse_sim(duration);
+        }
+        let now = Instant::now();
+        let mut remaining_duration = duration;
+        loop {
+            self.release_throttled();
+            // Wakes up early to release the throttled order submissions.
+            let (timeout, throttled) = match self.next_throttle_release() {
+                Some(release) if release - clock::now() < remaining_duration => {
+                    ((release - clock::now()).max(0), true)
+                }
+                _ => (remaining_duration, false),
+            };
+            let ev = self.wait(Duration::from_nanos(timeout as u64));
+            match &ev {
+                Ok(LiveEvent::Depth(Depth { local_ts, .. }))
+                | Ok(LiveEvent::Trade(Trade { local_ts, .. })) => {
+                    let latency = clock::now() - local_ts;
+                    self.event_wakeup.record(latency);
+                    latency::record(Stage::Delivery, latency);
+                }
+                Err(RecvTimeoutError::Timeout) if !throttled => {
+                    let overshoot = now.elapsed().as_nanos() as i64 - duration;
+                    self.timer_wakeup.record(overshoot);
+                    latency::record(Stage::StrategyWakeup, overshoot);
+                }
+                _ => {}
+            }
+            match ev {
+                Ok(ev) => self.process(ev)?,
+                Err(RecvTimeoutError::Timeout) if !throttled => {
                     return Ok(true);
                 }
+                Err(RecvTimeoutError::Timeout) => {}
                 Err(RecvTimeoutError::Disconnected) => {
                     return Ok(false);
                 }
@@ -236,6 +1313,11 @@ impl Bot {
         wait: bool,
         side: Side,
     ) -> Result<bool, BotError> {
+        if let Some(schedule) = self.schedule.as_ref() {
+            if !schedule.is_active(clock::now()) {
+                return Err(BotError::OutsideSchedule);
+            }
+        }
         let orders = self
             .orders
             .get_mut(asset_no)
@@ -247,32 +1329,137 @@ impl Bot {
         let order = Order {
             order_id,
             q: (),
-            price_tick: (price / tick_size).round() as i32,
+            price_tick: (price / tick_size).round() as i64,
             qty,
             leaves_qty: 0.0,
             tick_size,
             side,
             time_in_force,
             order_type,
-            status: Status::New,
-            local_timestamp: Utc::now().timestamp_nanos_opt().unwrap(),
+            status: Status::None,
+            local_timestamp: clock::now(),
             req: Status::New,
             exec_price_tick: 0,
             exch_timestamp: 0,
             exec_qty: 0.0,
             maker: false,
+            oco_group: self.oco_group.get(&asset_no).copied(),
+            trigger_price_tick: 0,
         };
+        if let Some(risk) = self.risk.as_ref() {
+            risk.check(
+                asset_no,
+                &order,
+                self.position[asset_no],
+                orders,
+                &self.depth[asset_no],
+            )?;
+        }
+        if let Some(throttle) = self.throttle.as_mut() {
+            // Holds the submission behind the ones already held for the asset to keep the order.
+            let held = self.throttled.iter().any(|(an, _)| *an == asset_no);
+            if held || !throttle.acquire(asset_no, order.local_timestamp) {
+                if throttle.action == ThrottleAction::Reject {
+                    return Err(BotError::Throttled);
+                }
+                debug!(asset_no, order_id, "Throttles the order.");
+                orders.insert(order.order_id, order.clone());
+                self.throttled.push_back((asset_no, order));
+                return Ok(true);
+            }
+        }
         orders.insert(order.order_id, order.clone());
-        self.req_tx.send(Request::Order((asset_no, order))).unwrap();
+        self.send(asset_no, order);
         Ok(true)
     }
+
+    /// Modifies the order to the price and the quantity, or reduces its quantity if the price is
+    /// `None`. A reduction only lowers the exposure, so it isn't checked by the risk manager.
+    fn replace(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price_tick: Option<i64>,
+        qty: f32,
+    ) -> Result<bool, BotError> {
+        let reduction = price_tick.is_none();
+        let orders = self
+            .orders
+            .get_mut(asset_no)
+            .ok_or(BotError::AssetNotFound)?;
+        let order = orders.get(&order_id).ok_or(BotError::OrderNotFound)?;
+        let price_tick = price_tick.unwrap_or(order.price_tick);
+        let held = self
+            .throttled
+            .iter()
+            .position(|(an, order)| *an == asset_no && order.order_id == order_id);
+        // The quantity of the order that remains to be filled once modified.
+        let open_qty = match held {
+            Some(_) => qty,
+            None => {
+                if !order.cancellable() {
+                    return Err(BotError::InvalidOrderStatus);
+                }
+                qty - (order.qty - order.leaves_qty)
+            }
+        };
+        if open_qty <= 0.0 || (reduction && qty >= order.qty) {
+            return Err(BotError::InvalidOrderQty);
+        }
+        if let (Some(risk), false) = (self.risk.as_ref(), reduction) {
+            let mut others = orders.clone();
+            let mut modified = others.remove(&order_id).unwrap();
+            modified.price_tick = price_tick;
+            modified.qty = open_qty;
+            risk.check(
+                asset_no,
+                &modified,
+                self.position[asset_no],
+                &others,
+                &self.depth[asset_no],
+            )?;
+        }
+        let order = orders.get_mut(&order_id).unwrap();
+        if let Some(i) = held {
+            // The order hasn't been sent yet, so it will be sent as modified.
+            let (_, held) = &mut self.throttled[i];
+            held.price_tick = price_tick;
+            held.qty = qty;
+            order.price_tick = price_tick;
+            order.qty = qty;
+            return Ok(true);
+        }
+        // The modification counts towards the order rate limits. It isn't held by the throttle
+        // since it would be stale by the time it's sent.
+        if let Some(throttle) = self.throttle.as_mut() {
+            if !throttle.acquire(asset_no, clock::now()) {
+                return Err(BotError::Throttled);
+            }
+        }
+        // The order keeps its price and quantity until the venue accepts the modification.
+        order.req = Status::Replaced;
+        order.local_timestamp = clock::now();
+        let mut req_order = order.clone();
+        req_order.price_tick = price_tick;
+        req_order.qty = qty;
+        self.send(asset_no, req_order);
+        Ok(true)
+    }
+
+    fn send(&mut self, asset_no: usize, order: Order<()>) {
+        self.journal_order(asset_no, order.order_id);
+        match self.sim.as_mut() {
+            Some(sim) => sim.request(asset_no, order, clock::now()),
+            None => self.req_tx.send(Request::Order((asset_no, order))).unwrap(),
+        }
+    }
 }
 
 impl Interface<(), HashMapMarketDepth> for Bot {
     type Error = BotError;
 
     fn current_timestamp(&self) -> i64 {
-        Utc::now().timestamp_nanos_opt().unwrap()
+        clock::now()
     }
 
     fn position(&self, asset_no: usize) -> f64 {
@@ -282,7 +1469,7 @@ impl Interface<(), HashMapMarketDepth> for Bot {
     fn state_values(&self, asset_no: usize) -> StateValues {
         StateValues {
             position: *self.position.get(asset_no).unwrap_or(&0.0),
-            balance: 0.0,
+            balance: *self.balance.get(asset_no).unwrap_or(&0.0),
             fee: 0.0,
             trade_num: 0,
             trade_qty: 0.0,
@@ -294,7 +1481,7 @@ impl Interface<(), HashMapMarketDepth> for Bot {
         self.depth.get(asset_no).unwrap()
     }
 
-    fn trade(&self, asset_no: usize) -> &Vec<Event> {
+    fn trade(&self, asset_no: usize) -> &[Event] {
         self.trade.get(asset_no).unwrap()
     }
 
@@ -311,10 +1498,52 @@ impl Interface<(), HashMapMarketDepth> for Bot {
         }
     }
 
+    fn external_events(&self) -> &Vec<ExternalEvent> {
+        &self.external_events
+    }
+
+    fn clear_external_events(&mut self) {}
+
+    fn adjustments(&self) -> &[Adjustment] {
+        &[]
+    }
+
     fn orders(&self, asset_no: usize) -> &HashMap<i64, Order<()>> {
         self.orders.get(asset_no).unwrap()
     }
 
+    fn order_entry_latency(&self, _asset_no: usize, _order_id: i64) -> Option<i64> {
+        None
+    }
+
+    fn features(&self, asset_no: usize) -> &[f64] {
+        self.features.get(asset_no).unwrap().values()
+    }
+
+    fn order_budget(&self, asset_no: usize) -> Option<u32> {
+        let now = clock::now();
+        // The venue's order budget applies until the window that binds it resets.
+        let venue_budget =
+            self.rate_limits
+                .get(&asset_no)
+                .and_then(|status| match status.blocked_until {
+                    Some(until) if until > now => Some(0),
+                    _ if status.reset_at > now => status.order_budget,
+                    _ => None,
+                });
+        let throttle_budget = self.throttle.as_ref().and_then(|throttle| {
+            if self.throttled.iter().any(|(an, _)| *an == asset_no) {
+                Some(0)
+            } else {
+                throttle.budget(asset_no, now)
+            }
+        });
+        match (throttle_budget, venue_budget) {
+            (Some(throttle_budget), Some(venue_budget)) => Some(throttle_budget.min(venue_budget)),
+            (throttle_budget, venue_budget) => throttle_budget.or(venue_budget),
+        }
+    }
+
     fn submit_buy_order(
         &mut self,
         asset_no: usize,
@@ -365,27 +1594,66 @@ impl Interface<(), HashMapMarketDepth> for Bot {
             .get_mut(asset_no)
             .ok_or(BotError::AssetNotFound)?;
         let order = orders.get_mut(&order_id).ok_or(BotError::OrderNotFound)?;
+        if let Some(i) = self
+            .throttled
+            .iter()
+            .position(|(an, order)| *an == asset_no && order.order_id == order_id)
+        {
+            // The order hasn't been sent yet.
+            self.throttled.remove(i);
+            order.status = Status::Canceled;
+            order.req = Status::None;
+            order.local_timestamp = clock::now();
+            return Ok(true);
+        }
         if !order.cancellable() {
             return Err(BotError::InvalidOrderStatus);
         }
         order.req = Status::Canceled;
-        order.local_timestamp = Utc::now().timestamp_nanos_opt().unwrap();
-        self.req_tx
-            .send(Request::Order((asset_no, order.clone())))
-            .unwrap();
+        order.local_timestamp = clock::now();
+        let order = order.clone();
+        self.send(asset_no, order);
         Ok(true)
     }
 
+    fn reduce_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        qty: f32,
+        _wait: bool,
+    ) -> Result<bool, Self::Error> {
+        self.replace(asset_no, order_id, None, qty)
+    }
+
+    fn modify_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        _wait: bool,
+    ) -> Result<bool, Self::Error> {
+        let tick_size = self
+            .assets
+            .get(asset_no)
+            .ok_or(BotError::AssetNotFound)?
+            .1
+            .tick_size;
+        let price_tick = (price / tick_size).round() as i64;
+        self.replace(asset_no, order_id, Some(price_tick), qty)
+    }
+
     fn clear_inactive_orders(&mut self, an: Option<usize>) {
         match an {
             Some(an) => {
                 if let Some(orders) = self.orders.get_mut(an) {
-                    orders.retain(|order_id, order| order.active());
+                    orders.retain(|order_id, order| !order.terminal());
                 }
             }
             None => {
                 for orders in self.orders.iter_mut() {
-                    orders.retain(|order_id, order| order.active());
+                    orders.retain(|order_id, order| !order.terminal());
                 }
             }
         }
@@ -395,6 +1663,14 @@ impl Interface<(), HashMapMarketDepth> for Bot {
         self.elapse_(duration)
     }
 
+    fn wait_next_feed(
+        &mut self,
+        include_order_resp: bool,
+        timeout: i64,
+    ) -> Result<bool, Self::Error> {
+        self.wait_next_feed_(include_order_resp, timeout)
+    }
+
     fn elapse_bt(&mut self, _duration: i64) -> Result<bool, Self::Error> {
         Ok(true)
     }
@@ -0,0 +1,138 @@
+use std::{
+    io::Error as IoError,
+    sync::{
+        atomic::{AtomicI64, AtomicU8, Ordering},
+        OnceLock,
+    },
+    time::Instant,
+};
+
+use chrono::Utc;
+
+/// The source of the local timestamps in live trading, such as the `local_ts` of the market data
+/// events and the local timestamps of the orders. Since the local timestamps recorded in live
+/// trading are used to build the latency files, a consistent source matters when comparing them
+/// across machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ClockSource {
+    /// The system wall clock, `CLOCK_REALTIME`. It can jump when the clock is adjusted. This is
+    /// the default.
+    Realtime,
+    /// The monotonic clock with an offset to the wall clock measured when the source is selected.
+    /// It never jumps but drifts from the wall clock over time.
+    MonotonicWithOffset,
+    /// A PTP hardware clock, such as `/dev/ptp0`, of a NIC synchronized by PTP. The clock is
+    /// read as it is, so it should be configured to run in UTC rather than TAI. Linux only.
+    Phc(String),
+    /// The simulated time of [`SimVenue`](crate::live::sim::SimVenue), which only moves when
+    /// the simulated bot elapses. It is selected by [`Bot::simulated`](crate::live::bot::Bot::simulated).
+    Simulated,
+}
+
+const REALTIME: u8 = 0;
+const MONOTONIC: u8 = 1;
+const PHC: u8 = 2;
+const SIMULATED: u8 = 3;
+
+static SOURCE: AtomicU8 = AtomicU8::new(REALTIME);
+static MONOTONIC_OFFSET: AtomicI64 = AtomicI64::new(0);
+static PHC_CLOCK_ID: AtomicI64 = AtomicI64::new(0);
+static SIMULATED_NOW: AtomicI64 = AtomicI64::new(0);
+static ANCHOR: OnceLock<Instant> = OnceLock::new();
+
+fn realtime() -> i64 {
+    Utc::now().timestamp_nanos_opt().unwrap()
+}
+
+fn monotonic() -> i64 {
+    ANCHOR.get_or_init(Instant::now).elapsed().as_nanos() as i64
+}
+
+#[cfg(target_os = "linux")]
+fn open_phc(device: &str) -> Result<i64, IoError> {
+    use std::{fs::OpenOptions, os::fd::IntoRawFd};
+
+    // The descriptor stays open for the lifetime of the process since the clock id refers to it.
+    let fd = OpenOptions::new().read(true).open(device)?.into_raw_fd();
+    // FD_TO_CLOCKID
+    let clock_id = ((!fd) << 3) | 3;
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(clock_id, &mut ts) } != 0 {
+        let error = IoError::last_os_error();
+        unsafe {
+            libc::close(fd);
+        }
+        return Err(error);
+    }
+    Ok(clock_id as i64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_phc(_device: &str) -> Result<i64, IoError> {
+    Err(IoError::new(
+        std::io::ErrorKind::Unsupported,
+        "PTP hardware clock is supported on Linux only",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn phc() -> i64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(
+            PHC_CLOCK_ID.load(Ordering::Relaxed) as libc::clockid_t,
+            &mut ts,
+        );
+    }
+    ts.tv_sec * 1_000_000_000 + ts.tv_nsec
+}
+
+#[cfg(not(target_os = "linux"))]
+fn phc() -> i64 {
+    realtime()
+}
+
+/// Selects the clock source for the whole process. This should be called before the bot and
+/// the connectors start.
+pub fn set_clock_source(source: ClockSource) -> Result<(), IoError> {
+    match source {
+        ClockSource::Realtime => {
+            SOURCE.store(REALTIME, Ordering::Relaxed);
+        }
+        ClockSource::MonotonicWithOffset => {
+            MONOTONIC_OFFSET.store(realtime() - monotonic(), Ordering::Relaxed);
+            SOURCE.store(MONOTONIC, Ordering::Relaxed);
+        }
+        ClockSource::Phc(device) => {
+            PHC_CLOCK_ID.store(open_phc(&device)?, Ordering::Relaxed);
+            SOURCE.store(PHC, Ordering::Relaxed);
+        }
+        ClockSource::Simulated => {
+            SOURCE.store(SIMULATED, Ordering::Relaxed);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the current local timestamp in nanoseconds since the Unix epoch from the selected
+/// clock source.
+#[inline]
+pub fn now() -> i64 {
+    match SOURCE.load(Ordering::Relaxed) {
+        MONOTONIC => monotonic() + MONOTONIC_OFFSET.load(Ordering::Relaxed),
+        PHC => phc(),
+        SIMULATED => SIMULATED_NOW.load(Ordering::Relaxed),
+        _ => realtime(),
+    }
+}
+
+/// Sets the time of [`ClockSource::Simulated`].
+pub fn set_simulated_time(timestamp: i64) {
+    SIMULATED_NOW.store(timestamp, Ordering::Relaxed);
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        mpsc::{RecvTimeoutError, TryRecvError},
+        Condvar,
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::ty::{Depth, LiveEvent};
+
+struct Queue {
+    events: VecDeque<LiveEvent>,
+    // The sequence number of the front event.
+    head_seq: u64,
+    // The sequence number of the queued depth update of each asset that has not been consumed
+    // yet.
+    pending_depth: HashMap<usize, u64>,
+    conflated: Vec<u64>,
+    disconnected: bool,
+}
+
+impl Queue {
+    fn pop(&mut self) -> Option<LiveEvent> {
+        let ev = self.events.pop_front()?;
+        let seq = self.head_seq;
+        self.head_seq += 1;
+        if let LiveEvent::Depth(data) = &ev {
+            if self.pending_depth.get(&data.asset_no) == Some(&seq) {
+                self.pending_depth.remove(&data.asset_no);
+            }
+        }
+        Some(ev)
+    }
+}
+
+/// An event queue between the connectors and the bot that bounds the queued depth updates. If a
+/// depth update arrives while a depth update of the same asset is still queued, meaning the
+/// strategy is falling behind, it is merged into the queued one by price level, keeping the
+/// latest quantity, instead of being appended. The other events are queued as they are.
+///
+/// As a result, the merged update is delivered at the position of the queued one and can be
+/// ahead of the events that arrived between the two.
+pub(crate) struct ConflationQueue {
+    queue: Mutex<Queue>,
+    cond: Condvar,
+}
+
+impl ConflationQueue {
+    pub fn new(num_assets: usize) -> Self {
+        Self {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                head_seq: 0,
+                pending_depth: HashMap::new(),
+                conflated: vec![0; num_assets],
+                disconnected: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    pub fn push(&self, ev: LiveEvent) {
+        let mut queue = self.queue.lock().unwrap();
+        match ev {
+            LiveEvent::Depth(data) => {
+                let pending = queue
+                    .pending_depth
+                    .get(&data.asset_no)
+                    .map(|seq| (seq - queue.head_seq) as usize);
+                match pending {
+                    Some(index) => {
+                        let asset_no = data.asset_no;
+                        if let Some(LiveEvent::Depth(queued)) = queue.events.get_mut(index) {
+                            merge_depth(queued, data);
+                        }
+                        if let Some(count) = queue.conflated.get_mut(asset_no) {
+                            *count += 1;
+                        }
+                    }
+                    None => {
+                        let seq = queue.head_seq + queue.events.len() as u64;
+                        queue.pending_depth.insert(data.asset_no, seq);
+                        queue.events.push_back(LiveEvent::Depth(data));
+                    }
+                }
+            }
+            ev => queue.events.push_back(ev),
+        }
+        self.cond.notify_one();
+    }
+
+    /// Marks that no more events will be pushed.
+    pub fn close(&self) {
+        self.queue.lock().unwrap().disconnected = true;
+        self.cond.notify_all();
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<LiveEvent, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            if let Some(ev) = queue.pop() {
+                return Ok(ev);
+            }
+            if queue.disconnected {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            queue = self.cond.wait_timeout(queue, deadline - now).unwrap().0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<LiveEvent, TryRecvError> {
+        let mut queue = self.queue.lock().unwrap();
+        match queue.pop() {
+            Some(ev) => Ok(ev),
+            None if queue.disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Returns the number of depth updates of the asset that have been merged into a queued one.
+    pub fn conflated(&self, asset_no: usize) -> u64 {
+        self.queue
+            .lock()
+            .unwrap()
+            .conflated
+            .get(asset_no)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().events.len()
+    }
+}
+
+fn merge_depth(queued: &mut Depth, data: Depth) {
+    queued.exch_ts = queued.exch_ts.max(data.exch_ts);
+    queued.local_ts = queued.local_ts.max(data.local_ts);
+    merge_levels(&mut queued.bids, data.bids);
+    merge_levels(&mut queued.asks, data.asks);
+}
+
+fn merge_levels(levels: &mut Vec<(f32, f32)>, updates: Vec<(f32, f32)>) {
+    for (px, qty) in updates {
+        match levels.iter_mut().find(|(px_, _)| *px_ == px) {
+            Some(level) => level.1 = qty,
+            None => levels.push((px, qty)),
+        }
+    }
+}
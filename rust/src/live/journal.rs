@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Error as IoError, Write},
+};
+
+use tracing::{error, warn};
+
+use crate::ty::{OrdType, Order, Side, Status, TimeInForce};
+
+/// The state of an asset that the bot holds, as the connectors have last reported.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct AssetState {
+    pub position: f64,
+    pub entry_price: f64,
+    pub balance: f64,
+}
+
+/// The state and the open orders of the bot restored from the journal.
+#[derive(Debug, Default)]
+pub(crate) struct Restored {
+    pub states: HashMap<usize, AssetState>,
+    // key: (asset_no, order_id)
+    pub orders: HashMap<(usize, i64), Order<()>>,
+}
+
+/// Journals the state of the bot's assets and its open orders to an append-only file, from which
+/// a restarted bot restores them. Each line is one of the following records, which are applied in
+/// order:
+///
+/// * `S <asset_no> <position> <entry_price> <balance>`: the state of the asset.
+/// * `O <asset_no> <order_id> <side> <order_type> <time_in_force> <status> <req> <price_tick>
+///   <tick_size> <qty> <leaves_qty> <exch_timestamp> <local_timestamp> <oco_group>
+///   <trigger_price_tick>`: the latest state of the order, of which the enums are written as
+///   their discriminants and the one-cancels-other group as `-` if the order has none.
+/// * `X <asset_no> <order_id>`: the order has ended.
+///
+/// The journal is compacted when it's opened, so that it contains only the last states and the
+/// orders that haven't ended. A line that can't be parsed, such as the last line written partially
+/// at a crash, is skipped.
+pub(crate) struct StateJournal {
+    file: File,
+    // key: asset_no, value: the last state journaled, which isn't journaled again until changed.
+    states: HashMap<usize, AssetState>,
+}
+
+impl StateJournal {
+    /// Opens the journal, creating it if it doesn't exist, and returns it along with the state
+    /// and the open orders restored from it.
+    pub fn open(path: &str) -> Result<(Self, Restored), IoError> {
+        let mut restored = Restored::default();
+        if let Ok(file) = File::open(path) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if apply(&mut restored, &line).is_none() {
+                    warn!(%line, "Skips the invalid line of the state journal.");
+                }
+            }
+        }
+
+        // Compacts the journal into a temporary file, which then replaces the journal, so that a
+        // crash during the compaction doesn't lose the journal.
+        let tmp_path = format!("{path}.tmp");
+        let mut file = File::create(&tmp_path)?;
+        for (asset_no, state) in restored.states.iter() {
+            writeln!(file, "{}", state_line(*asset_no, state))?;
+        }
+        for ((asset_no, _), order) in restored.orders.iter() {
+            writeln!(file, "{}", order_line(*asset_no, order))?;
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp_path, path)?;
+
+        let journal = Self {
+            file: OpenOptions::new().append(true).open(path)?,
+            states: restored.states.clone(),
+        };
+        Ok((journal, restored))
+    }
+
+    fn write(&mut self, line: &str) {
+        if let Err(error) = writeln!(self.file, "{}", line) {
+            error!(?error, %line, "Couldn't write to the state journal.");
+        }
+    }
+
+    /// Journals the state of the asset if it has changed.
+    pub fn write_state(&mut self, asset_no: usize, state: AssetState) {
+        if self.states.get(&asset_no) == Some(&state) {
+            return;
+        }
+        self.states.insert(asset_no, state);
+        self.write(&state_line(asset_no, &state));
+    }
+
+    /// Journals the order, or its end if it's terminal.
+    pub fn write_order(&mut self, asset_no: usize, order: &Order<()>) {
+        if order.terminal() {
+            self.write(&format!("X {} {}", asset_no, order.order_id));
+        } else {
+            self.write(&order_line(asset_no, order));
+        }
+    }
+}
+
+fn state_line(asset_no: usize, state: &AssetState) -> String {
+    format!(
+        "S {} {} {} {}",
+        asset_no, state.position, state.entry_price, state.balance
+    )
+}
+
+fn order_line(asset_no: usize, order: &Order<()>) -> String {
+    let oco_group = match order.oco_group {
+        Some(group) => group.to_string(),
+        None => "-".to_string(),
+    };
+    format!(
+        "O {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+        asset_no,
+        order.order_id,
+        order.side as i8,
+        order.order_type as u8,
+        order.time_in_force as u8,
+        order.status as u8,
+        order.req as u8,
+        order.price_tick,
+        order.tick_size,
+        order.qty,
+        order.leaves_qty,
+        order.exch_timestamp,
+        order.local_timestamp,
+        oco_group,
+        order.trigger_price_tick
+    )
+}
+
+/// Applies the record of the line to the restored state, or returns `None` if the line is invalid.
+fn apply(restored: &mut Restored, line: &str) -> Option<()> {
+    let mut tokens = line.split(' ');
+    let kind = tokens.next()?;
+    let asset_no: usize = tokens.next()?.parse().ok()?;
+    match kind {
+        "S" => {
+            let state = AssetState {
+                position: tokens.next()?.parse().ok()?,
+                entry_price: tokens.next()?.parse().ok()?,
+                balance: tokens.next()?.parse().ok()?,
+            };
+            restored.states.insert(asset_no, state);
+        }
+        "O" => {
+            let order_id: i64 = tokens.next()?.parse().ok()?;
+            let side = side(tokens.next()?.parse().ok()?)?;
+            let order_type = ord_type(tokens.next()?.parse().ok()?)?;
+            let time_in_force = time_in_force(tokens.next()?.parse().ok()?)?;
+            let status = status(tokens.next()?.parse().ok()?)?;
+            let req = req(tokens.next()?.parse().ok()?)?;
+            let price_tick = tokens.next()?.parse().ok()?;
+            let tick_size = tokens.next()?.parse().ok()?;
+            let qty = tokens.next()?.parse().ok()?;
+            let mut order = Order::new(
+                order_id,
+                price_tick,
+                tick_size,
+                qty,
+                side,
+                order_type,
+                time_in_force,
+            );
+            order.status = status;
+            order.req = req;
+            order.leaves_qty = tokens.next()?.parse().ok()?;
+            order.exch_timestamp = tokens.next()?.parse().ok()?;
+            order.local_timestamp = tokens.next()?.parse().ok()?;
+            order.oco_group = match tokens.next()? {
+                "-" => None,
+                group => Some(group.parse().ok()?),
+            };
+            order.trigger_price_tick = tokens.next()?.parse().ok()?;
+            restored.orders.insert((asset_no, order_id), order);
+        }
+        "X" => {
+            let order_id: i64 = tokens.next()?.parse().ok()?;
+            restored.orders.remove(&(asset_no, order_id));
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+fn side(value: i8) -> Option<Side> {
+    match value {
+        1 => Some(Side::Buy),
+        -1 => Some(Side::Sell),
+        _ => None,
+    }
+}
+
+fn ord_type(value: u8) -> Option<OrdType> {
+    match value {
+        0 => Some(OrdType::Limit),
+        1 => Some(OrdType::Market),
+        2 => Some(OrdType::StopMarket),
+        3 => Some(OrdType::StopLimit),
+        4 => Some(OrdType::TakeProfitMarket),
+        5 => Some(OrdType::TakeProfitLimit),
+        _ => None,
+    }
+}
+
+fn time_in_force(value: u8) -> Option<TimeInForce> {
+    match value {
+        0 => Some(TimeInForce::GTC),
+        1 => Some(TimeInForce::GTX),
+        2 => Some(TimeInForce::FOK),
+        3 => Some(TimeInForce::IOC),
+        _ => None,
+    }
+}
+
+/// Returns the status of the order, which is one of those that an order that hasn't ended has.
+fn status(value: u8) -> Option<Status> {
+    match value {
+        0 => Some(Status::None),
+        1 => Some(Status::New),
+        5 => Some(Status::PartiallyFilled),
+        _ => None,
+    }
+}
+
+/// Returns the request of the order, which is `None` if there's no request in flight.
+fn req(value: u8) -> Option<Status> {
+    match value {
+        0 => Some(Status::None),
+        1 => Some(Status::New),
+        4 => Some(Status::Canceled),
+        8 => Some(Status::Replaced),
+        _ => None,
+    }
+}
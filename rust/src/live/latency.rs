@@ -0,0 +1,168 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+/// The stages of the live path whose latencies are measured once the instrumentation is enabled
+/// by [`set_enabled`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// From the receipt of a websocket frame to the parsed market data event, in the connector.
+    FrameParse,
+    /// From the parsed market data event to when the bot dequeues it.
+    Delivery,
+    /// From when the bot dequeues a depth event to when the market depth is updated.
+    BookUpdate,
+    /// From the end of the requested duration to when [`Bot::elapse`](crate::live::bot::Bot)
+    /// returns control to the strategy.
+    StrategyWakeup,
+    /// From when the strategy submits or cancels an order to when the connector thread picks up
+    /// the request.
+    OrderDispatch,
+    /// The time taken by the connector to encode and sign the request.
+    OrderEncode,
+    /// The time taken by the connector to write the encoded request to the socket. Only the
+    /// connectors that own the socket, such as the FIX connector, measure this.
+    SocketWrite,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 7] = [
+        Stage::FrameParse,
+        Stage::Delivery,
+        Stage::BookUpdate,
+        Stage::StrategyWakeup,
+        Stage::OrderDispatch,
+        Stage::OrderEncode,
+        Stage::SocketWrite,
+    ];
+}
+
+// Each power of two is divided into this many sub-buckets, which bounds the relative error of
+// the percentiles to about 1/8.
+const SUB_BUCKETS: usize = 8;
+const BUCKETS: usize = 64 * SUB_BUCKETS;
+
+/// A latency histogram in nanoseconds with log-linear buckets.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    pub count: u64,
+    pub sum: i64,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            count: 0,
+            sum: 0,
+            min: i64::MAX,
+            max: 0,
+        }
+    }
+
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            return value as usize;
+        }
+        let exp = 63 - value.leading_zeros() as usize;
+        let sub = (value >> (exp - 3)) as usize & (SUB_BUCKETS - 1);
+        (exp - 2) * SUB_BUCKETS + sub
+    }
+
+    fn bucket_value(bucket: usize) -> i64 {
+        if bucket < SUB_BUCKETS {
+            return bucket as i64;
+        }
+        let exp = bucket / SUB_BUCKETS + 2;
+        let sub = (bucket % SUB_BUCKETS) as i64;
+        (SUB_BUCKETS as i64 + sub) << (exp - 3)
+    }
+
+    /// Records a latency. A negative latency, which can occur if the clock is adjusted, is
+    /// recorded as zero.
+    pub fn record(&mut self, latency: i64) {
+        let latency = latency.max(0);
+        self.counts[Self::bucket(latency as u64)] += 1;
+        self.count += 1;
+        self.sum += latency;
+        self.min = self.min.min(latency);
+        self.max = self.max.max(latency);
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// Returns the lower bound of the bucket that contains the given quantile, from 0 to 1.
+    pub fn percentile(&self, quantile: f64) -> i64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut cum = 0;
+        for (bucket, count) in self.counts.iter().enumerate() {
+            cum += count;
+            if cum >= rank {
+                return Self::bucket_value(bucket).clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static HISTOGRAMS: [Mutex<Histogram>; 7] = [
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+    Mutex::new(Histogram::new()),
+];
+
+/// Enables or disables the latency instrumentation for the whole process. It is disabled by
+/// default.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records the latency of the stage if the instrumentation is enabled.
+#[inline]
+pub fn record(stage: Stage, latency: i64) {
+    if enabled() {
+        HISTOGRAMS[stage as usize].lock().unwrap().record(latency);
+    }
+}
+
+/// Returns a copy of the histogram of the stage.
+pub fn histogram(stage: Stage) -> Histogram {
+    HISTOGRAMS[stage as usize].lock().unwrap().clone()
+}
+
+/// Clears the histograms of all stages.
+pub fn reset() {
+    for histogram in HISTOGRAMS.iter() {
+        *histogram.lock().unwrap() = Histogram::new();
+    }
+}
@@ -1,8 +1,37 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+};
 
-use crate::{connector::Connector, error::BuildError, live::bot::Bot};
+use crate::{
+    connector::Connector,
+    error::BuildError,
+    feature::FeatureSet,
+    live::{
+        affinity::ThreadSettings,
+        bot::Bot,
+        clock::{set_clock_source, ClockSource},
+        risk::RiskManager,
+        shared::SharedConnectors,
+        throttle::Throttle,
+        wait::WaitStrategy,
+    },
+    schedule::Schedule,
+    ty::ResponsePriority,
+};
 
+pub mod affinity;
 pub mod bot;
+pub mod clock;
+mod conflation;
+mod journal;
+pub mod latency;
+pub mod portfolio;
+pub mod risk;
+mod shared;
+pub mod sim;
+pub mod throttle;
+pub mod wait;
 
 #[derive(Clone)]
 pub struct AssetInfo {
@@ -15,6 +44,23 @@ pub struct AssetInfo {
 pub struct LiveBuilder {
     conns: HashMap<String, Box<dyn Connector + Send + 'static>>,
     assets: Vec<(String, AssetInfo)>,
+    schedule: Option<Schedule>,
+    depth_conflation: bool,
+    strategy_thread: Option<ThreadSettings>,
+    io_thread: Option<ThreadSettings>,
+    wait_strategy: WaitStrategy,
+    clock_source: Option<ClockSource>,
+    wakeup_interval: Option<i64>,
+    response_priority: Option<ResponsePriority>,
+    startup_timeout: Option<i64>,
+    expected_position: Vec<(usize, f64)>,
+    warm_sync_timeout: Option<i64>,
+    depth_cap: Vec<(usize, i64)>,
+    features: Vec<(usize, FeatureSet)>,
+    level_timestamps: bool,
+    throttle: Option<Throttle>,
+    risk: Option<RiskManager>,
+    state_journal: Option<String>,
 }
 
 impl LiveBuilder {
@@ -22,6 +68,23 @@ impl LiveBuilder {
         Self {
             conns: HashMap::new(),
             assets: Vec::new(),
+            schedule: None,
+            depth_conflation: false,
+            strategy_thread: None,
+            io_thread: None,
+            wait_strategy: Default::default(),
+            clock_source: None,
+            wakeup_interval: None,
+            response_priority: None,
+            startup_timeout: None,
+            expected_position: Vec::new(),
+            warm_sync_timeout: None,
+            depth_cap: Vec::new(),
+            features: Vec::new(),
+            level_timestamps: false,
+            throttle: None,
+            risk: None,
+            state_journal: None,
         }
     }
 
@@ -47,9 +110,200 @@ impl LiveBuilder {
         self
     }
 
-    pub fn build(self) -> Result<Bot, BuildError> {
+    /// Sets the schedule that gates order submission. See [`Schedule`].
+    pub fn schedule(self, schedule: Schedule) -> Self {
+        Self {
+            schedule: Some(schedule),
+            ..self
+        }
+    }
+
+    /// Enables the depth conflation. See [`Bot::with_depth_conflation`].
+    pub fn depth_conflation(self, depth_conflation: bool) -> Self {
+        Self {
+            depth_conflation,
+            ..self
+        }
+    }
+
+    /// Caps the depth of the asset to `ticks` ticks on each side of the mid price. See
+    /// [`Bot::with_depth_cap`].
+    pub fn depth_cap(mut self, asset_no: usize, ticks: i64) -> Self {
+        self.depth_cap.push((asset_no, ticks));
+        self
+    }
+
+    /// Sets the features of the asset that the bot maintains. See [`Bot::with_features`].
+    pub fn features(mut self, asset_no: usize, features: FeatureSet) -> Self {
+        self.features.push((asset_no, features));
+        self
+    }
+
+    /// Keeps the exchange timestamp of the last update of each level of the depths. See
+    /// [`Bot::with_level_timestamps`].
+    pub fn level_timestamps(self, level_timestamps: bool) -> Self {
+        Self {
+            level_timestamps,
+            ..self
+        }
+    }
+
+    /// Sets the scheduling settings of the strategy thread. See [`Bot::with_strategy_thread`].
+    pub fn strategy_thread(self, settings: ThreadSettings) -> Self {
+        Self {
+            strategy_thread: Some(settings),
+            ..self
+        }
+    }
+
+    /// Sets the scheduling settings of the connector I/O threads.
+    pub fn io_thread(self, settings: ThreadSettings) -> Self {
+        Self {
+            io_thread: Some(settings),
+            ..self
+        }
+    }
+
+    /// Sets how [`Bot::elapse`] waits for the next event. See [`WaitStrategy`].
+    pub fn wait_strategy(self, wait_strategy: WaitStrategy) -> Self {
+        Self {
+            wait_strategy,
+            ..self
+        }
+    }
+
+    /// Selects the clock source of the local timestamps. See [`ClockSource`].
+    pub fn clock_source(self, clock_source: ClockSource) -> Self {
+        Self {
+            clock_source: Some(clock_source),
+            ..self
+        }
+    }
+
+    /// Coalesces the strategy wakeups to at most once per `interval`. See
+    /// [`Bot::with_wakeup_interval`].
+    pub fn wakeup_interval(self, interval: i64) -> Self {
+        Self {
+            wakeup_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Sets whether the order responses or the market data updates are processed first among
+    /// the events received together. See [`Bot::with_response_priority`].
+    pub fn response_priority(self, response_priority: ResponsePriority) -> Self {
+        Self {
+            response_priority: Some(response_priority),
+            ..self
+        }
+    }
+
+    /// Makes [`Bot::run`] wait up to `timeout` for the initial state fetched from the exchange.
+    /// See [`Bot::with_startup_sync`].
+    pub fn startup_sync(self, timeout: i64) -> Self {
+        Self {
+            startup_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Requires the initial position of the asset to be `qty`, or the bot refuses to start. See
+    /// [`Bot::with_expected_position`].
+    pub fn expected_position(mut self, asset_no: usize, qty: f64) -> Self {
+        self.expected_position.push((asset_no, qty));
+        self
+    }
+
+    /// Makes [`Bot::run`] wait up to `timeout` until the books and the user data streams of all
+    /// assets are synced. See [`Bot::with_warm_sync`].
+    pub fn warm_sync(self, timeout: i64) -> Self {
+        Self {
+            warm_sync_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Throttles the outbound order submissions. See [`Bot::with_throttle`].
+    pub fn throttle(self, throttle: Throttle) -> Self {
+        Self {
+            throttle: Some(throttle),
+            ..self
+        }
+    }
+
+    /// Validates the order submissions and halts the bot by the kill switch of the
+    /// [`RiskManager`]. See [`Bot::with_risk_manager`].
+    pub fn risk_manager(self, risk: RiskManager) -> Self {
+        Self {
+            risk: Some(risk),
+            ..self
+        }
+    }
+
+    /// Journals the state and the open orders of the bot to the file at `path`, from which they're
+    /// restored at the next build. The bots built by [`LiveBuilder::build_shared`] journal to
+    /// their own files, `path` suffixed with `.` and the bot number. See
+    /// [`Bot::with_state_journal`].
+    pub fn state_journal(self, path: &str) -> Self {
+        Self {
+            state_journal: Some(path.to_string()),
+            ..self
+        }
+    }
+
+    pub fn build(mut self) -> Result<Bot, BuildError> {
+        self.add_assets()?;
+        let conns = mem::take(&mut self.conns);
+        let con = Bot::new(conns, self.assets.clone());
+        let con = self.configure(con);
+        match self.state_journal.as_ref() {
+            Some(path) => Self::open_state_journal(con, path),
+            None => Ok(con),
+        }
+    }
+
+    /// Builds `num_bots` bots that share the connectors, so that the strategies, each running on
+    /// its own bot, such as on its own thread, trade the registered assets over a single
+    /// connection per connector. Every bot has all the assets with the same asset numbers and
+    /// the same settings, and receives all the market data and the position updates, while each
+    /// bot sees only its own orders, whose order ids are in its own namespace and must be within
+    /// `0..(1 << ORDER_ID_BITS)`. See [`ORDER_ID_BITS`](portfolio::ORDER_ID_BITS).
+    ///
+    /// The connectors are started when the first bot runs, and the events are held for each of
+    /// the other bots until it runs, so all the bots should be run soon after.
+    pub fn build_shared(mut self, num_bots: usize) -> Result<Vec<Bot>, BuildError> {
+        self.add_assets()?;
+        let names: Vec<String> = self.conns.keys().cloned().collect();
+        let shared = SharedConnectors::new(mem::take(&mut self.conns), num_bots);
+        (0..num_bots)
+            .map(|bot_no| {
+                let conns = names
+                    .iter()
+                    .map(|name| {
+                        let conn: Box<dyn Connector + Send + 'static> =
+                            Box::new(shared.connector(name, bot_no));
+                        (name.clone(), conn)
+                    })
+                    .collect();
+                let con = self.configure(Bot::new(conns, self.assets.clone()));
+                match self.state_journal.as_ref() {
+                    Some(path) => Self::open_state_journal(con, &format!("{path}.{bot_no}")),
+                    None => Ok(con),
+                }
+            })
+            .collect()
+    }
+
+    fn open_state_journal(con: Bot, path: &str) -> Result<Bot, BuildError> {
+        con.with_state_journal(path)
+            .map_err(|error| BuildError::Error(error.into()))
+    }
+
+    fn add_assets(&mut self) -> Result<(), BuildError> {
+        if let Some(clock_source) = self.clock_source.take() {
+            set_clock_source(clock_source).map_err(|error| BuildError::Error(error.into()))?;
+        }
         let mut dup = HashSet::new();
-        let mut conns = self.conns;
         for (an, (name, asset_info)) in self.assets.iter().enumerate() {
             if !dup.insert(format!("{}/{}", name, asset_info.symbol)) {
                 Err(BuildError::Duplicate(
@@ -57,7 +311,8 @@ impl LiveBuilder {
                     asset_info.symbol.clone(),
                 ))?;
             }
-            let conn = conns
+            let conn = self
+                .conns
                 .get_mut(name)
                 .ok_or(BuildError::ConnectorNotFound(name.to_string()))?;
             conn.add(
@@ -67,8 +322,53 @@ impl LiveBuilder {
                 asset_info.lot_size,
             )?;
         }
+        Ok(())
+    }
 
-        let con = Bot::new(conns, self.assets);
-        Ok(con)
+    fn configure(&self, mut con: Bot) -> Bot {
+        con = con.with_wait_strategy(self.wait_strategy);
+        if let Some(schedule) = self.schedule.clone() {
+            con = con.with_schedule(schedule);
+        }
+        if self.depth_conflation {
+            con = con.with_depth_conflation();
+        }
+        for (asset_no, ticks) in self.depth_cap.iter() {
+            con = con.with_depth_cap(*asset_no, *ticks);
+        }
+        for (asset_no, features) in self.features.iter() {
+            con = con.with_features(*asset_no, features.clone());
+        }
+        if self.level_timestamps {
+            con = con.with_level_timestamps();
+        }
+        if let Some(settings) = self.strategy_thread.clone() {
+            con = con.with_strategy_thread(settings);
+        }
+        if let Some(settings) = self.io_thread.clone() {
+            con = con.with_io_thread(settings);
+        }
+        if let Some(interval) = self.wakeup_interval {
+            con = con.with_wakeup_interval(interval);
+        }
+        if let Some(response_priority) = self.response_priority {
+            con = con.with_response_priority(response_priority);
+        }
+        if let Some(timeout) = self.startup_timeout {
+            con = con.with_startup_sync(timeout);
+        }
+        for (asset_no, qty) in self.expected_position.iter() {
+            con = con.with_expected_position(*asset_no, *qty);
+        }
+        if let Some(timeout) = self.warm_sync_timeout {
+            con = con.with_warm_sync(timeout);
+        }
+        if let Some(throttle) = self.throttle.clone() {
+            con = con.with_throttle(throttle);
+        }
+        if let Some(risk) = self.risk.clone() {
+            con = con.with_risk_manager(risk);
+        }
+        con
     }
 }
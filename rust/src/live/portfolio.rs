@@ -0,0 +1,371 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Instant};
+
+use crate::{
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    live::{
+        bot::{Bot, BotError},
+        risk::RiskManager,
+    },
+    ty::{Event, OrdType, Order, StateValues, TimeInForce},
+    Interface,
+};
+
+/// The number of the low bits of an order id in the bot that hold the strategy's own order id.
+/// The high bits hold the strategy number and the portfolio's tag, so the strategy's order ids
+/// must be within `0..(1 << ORDER_ID_BITS)`.
+pub const ORDER_ID_BITS: u32 = 48;
+
+// The bit that marks the order ids in the bot issued by the portfolio, so that the orders
+// submitted directly through the bot aren't taken as a strategy's. The strategy number is held
+// in the bits between it and the strategy's own order id.
+const PORTFOLIO_TAG: i64 = 1 << 62;
+
+/// A strategy hosted by a [`Portfolio`].
+pub trait Strategy {
+    /// Called on every wakeup of the portfolio, in order of the strategies.
+    fn on_wakeup(&mut self, ctx: &mut StrategyContext) -> Result<(), BotError>;
+
+    /// Called on every fill of the strategy's orders before the wakeup, with the filled order in
+    /// the strategy's order id namespace.
+    fn on_fill(&mut self, _asset_no: usize, _order: &Order<()>) {}
+}
+
+/// The metrics of a strategy of a [`Portfolio`].
+#[derive(Clone, Debug, Default)]
+pub struct StrategyMetrics {
+    /// The number of the orders submitted.
+    pub submitted: u64,
+    /// The number of the orders rejected by the [`RiskManager`].
+    pub risk_rejected: u64,
+    /// The number of the fills.
+    pub fills: u64,
+    /// The time spent in the strategy's wakeups, in nanoseconds.
+    pub busy_time: i64,
+}
+
+/// The position and the trading of a strategy in an asset, accounted from its fills.
+#[derive(Clone, Debug, Default)]
+struct Book {
+    position: f64,
+    balance: f64,
+    trade_num: i32,
+    trade_qty: f64,
+    trade_amount: f64,
+}
+
+/// The state of a strategy of a [`Portfolio`].
+struct Slot {
+    strategy: Box<dyn Strategy>,
+    // The strategy's orders in its own order id namespace, per asset.
+    orders: Vec<HashMap<i64, Order<()>>>,
+    books: Vec<Book>,
+    metrics: StrategyMetrics,
+}
+
+/// The view of a [`Portfolio`] given to a strategy on its wakeup, through which it sees the
+/// shared market data and its own orders, position, and state, and submits the orders in its own
+/// order id namespace.
+pub struct StrategyContext<'a> {
+    bot: &'a mut Bot,
+    strategy_no: usize,
+    slot: &'a mut Slot,
+}
+
+impl StrategyContext<'_> {
+    pub fn strategy_no(&self) -> usize {
+        self.strategy_no
+    }
+
+    pub fn current_timestamp(&self) -> i64 {
+        self.bot.current_timestamp()
+    }
+
+    pub fn depth(&self, asset_no: usize) -> &HashMapMarketDepth {
+        self.bot.depth(asset_no)
+    }
+
+    /// Returns the market trades received since the previous wakeup.
+    pub fn trade(&self, asset_no: usize) -> &[Event] {
+        self.bot.trade(asset_no)
+    }
+
+    /// Returns the strategy's own position in the asset, accounted from its fills.
+    pub fn position(&self, asset_no: usize) -> f64 {
+        self.slot
+            .books
+            .get(asset_no)
+            .map_or(0.0, |book| book.position)
+    }
+
+    /// Returns the strategy's own state values of the asset, accounted from its fills. The fees
+    /// aren't accounted.
+    pub fn state_values(&self, asset_no: usize) -> StateValues {
+        let book = self.slot.books.get(asset_no).cloned().unwrap_or_default();
+        StateValues {
+            position: book.position,
+            balance: book.balance,
+            fee: 0.0,
+            trade_num: book.trade_num,
+            trade_qty: book.trade_qty,
+            trade_amount: book.trade_amount,
+        }
+    }
+
+    /// Returns the strategy's own orders of the asset.
+    pub fn orders(&self, asset_no: usize) -> &HashMap<i64, Order<()>> {
+        &self.slot.orders[asset_no]
+    }
+
+    pub fn submit_buy_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+    ) -> Result<bool, BotError> {
+        let bot_order_id = to_bot_order_id(self.strategy_no, order_id)?;
+        let result = self.bot.submit_buy_order(
+            asset_no,
+            bot_order_id,
+            price,
+            qty,
+            time_in_force,
+            order_type,
+            false,
+        );
+        let result = self.count_submission(result)?;
+        self.sync_order(asset_no, bot_order_id);
+        Ok(result)
+    }
+
+    pub fn submit_sell_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        time_in_force: TimeInForce,
+        order_type: OrdType,
+    ) -> Result<bool, BotError> {
+        let bot_order_id = to_bot_order_id(self.strategy_no, order_id)?;
+        let result = self.bot.submit_sell_order(
+            asset_no,
+            bot_order_id,
+            price,
+            qty,
+            time_in_force,
+            order_type,
+            false,
+        );
+        let result = self.count_submission(result)?;
+        self.sync_order(asset_no, bot_order_id);
+        Ok(result)
+    }
+
+    /// Counts the submission in the metrics, including the rejection by the bot's
+    /// [`RiskManager`].
+    fn count_submission(&mut self, result: Result<bool, BotError>) -> Result<bool, BotError> {
+        match result {
+            Ok(_) => self.slot.metrics.submitted += 1,
+            Err(BotError::RiskLimitExceeded) => self.slot.metrics.risk_rejected += 1,
+            Err(_) => {}
+        }
+        result
+    }
+
+    pub fn cancel(&mut self, asset_no: usize, order_id: i64) -> Result<bool, BotError> {
+        let bot_order_id = to_bot_order_id(self.strategy_no, order_id)?;
+        let result = self.bot.cancel(asset_no, bot_order_id, false)?;
+        self.sync_order(asset_no, bot_order_id);
+        Ok(result)
+    }
+
+    /// Clears the strategy's own inactive orders of the asset, or of all the assets if `None`.
+    pub fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
+        let strategy_no = self.strategy_no;
+        for (an, orders) in self.bot.orders.iter_mut().enumerate() {
+            if asset_no.is_none_or(|asset_no| asset_no == an) {
+                orders.retain(|order_id, order| {
+                    from_bot_order_id(*order_id).map(|(no, _)| no) != Some(strategy_no)
+                        || !order.terminal()
+                });
+                self.slot.orders[an].retain(|_, order| !order.terminal());
+            }
+        }
+    }
+
+    fn sync_order(&mut self, asset_no: usize, bot_order_id: i64) {
+        let order = self.bot.orders[asset_no].get(&bot_order_id);
+        if let (Some(order), Some((_, order_id))) = (order, from_bot_order_id(bot_order_id)) {
+            let mut order = order.clone();
+            order.order_id = order_id;
+            self.slot.orders[asset_no].insert(order_id, order);
+        }
+    }
+}
+
+// The fills of the orders reported by the bot's fill hook, with their asset numbers.
+type Fills = Rc<RefCell<Vec<(usize, Order<()>)>>>;
+
+fn to_bot_order_id(strategy_no: usize, order_id: i64) -> Result<i64, BotError> {
+    if !(0..(1 << ORDER_ID_BITS)).contains(&order_id)
+        || strategy_no as i64 >= PORTFOLIO_TAG >> ORDER_ID_BITS
+    {
+        return Err(BotError::InvalidOrderId);
+    }
+    Ok(PORTFOLIO_TAG | ((strategy_no as i64) << ORDER_ID_BITS) | order_id)
+}
+
+/// Returns the strategy number and the strategy's own order id of the order id in the bot, or
+/// `None` if the portfolio didn't issue it, such as a negative order id or the order id of an
+/// order submitted directly through the bot.
+fn from_bot_order_id(bot_order_id: i64) -> Option<(usize, i64)> {
+    if bot_order_id < 0 || bot_order_id & PORTFOLIO_TAG == 0 {
+        return None;
+    }
+    Some((
+        ((bot_order_id & !PORTFOLIO_TAG) >> ORDER_ID_BITS) as usize,
+        bot_order_id & ((1 << ORDER_ID_BITS) - 1),
+    ))
+}
+
+/// Runs several strategies in one process on a shared [`Bot`], so that they share its connectors
+/// and market data and are held together to the limits of a shared [`RiskManager`]. Each strategy
+/// has its own order id namespace, and its own position and state accounted from its fills, as
+/// well as its own [`StrategyMetrics`].
+///
+/// The portfolio takes over the bot's fill hook to attribute the fills to the strategies, and
+/// clears the market trades after every wakeup, once all the strategies have seen them.
+pub struct Portfolio {
+    bot: Bot,
+    slots: Vec<Slot>,
+    // The fills reported by the bot's fill hook since the last wakeup.
+    fills: Fills,
+}
+
+impl Portfolio {
+    /// Constructs a portfolio on the bot, installing the risk manager on the bot in place of the
+    /// one set by [`Bot::with_risk_manager`], if any, so that it checks the submissions of all the
+    /// strategies against the account's position and open orders.
+    pub fn new(bot: Bot, risk: RiskManager) -> Self {
+        let fills: Fills = Default::default();
+        let fills_ = fills.clone();
+        let bot = bot
+            .with_risk_manager(risk)
+            .with_fill_hook(move |asset_no, order, _| {
+                fills_.borrow_mut().push((asset_no, order.clone()));
+            });
+        Self {
+            bot,
+            slots: Vec::new(),
+            fills,
+        }
+    }
+
+    /// Adds a strategy, returning its strategy number, which is its index in order of addition.
+    pub fn add<S>(&mut self, strategy: S) -> usize
+    where
+        S: Strategy + 'static,
+    {
+        let num_assets = self.bot.orders.len();
+        self.slots.push(Slot {
+            strategy: Box::new(strategy),
+            orders: vec![HashMap::new(); num_assets],
+            books: vec![Book::default(); num_assets],
+            metrics: Default::default(),
+        });
+        self.slots.len() - 1
+    }
+
+    pub fn bot(&self) -> &Bot {
+        &self.bot
+    }
+
+    pub fn bot_mut(&mut self) -> &mut Bot {
+        &mut self.bot
+    }
+
+    pub fn metrics(&self, strategy_no: usize) -> &StrategyMetrics {
+        &self.slots[strategy_no].metrics
+    }
+
+    /// Returns the position of the strategy in the asset, accounted from its fills.
+    pub fn position(&self, strategy_no: usize, asset_no: usize) -> f64 {
+        self.slots[strategy_no].books[asset_no].position
+    }
+
+    /// Elapses the bot by `duration`, and then wakes the strategies in order, after applying the
+    /// fills received in the meantime. Returns `false` once the bot has been disconnected.
+    pub fn elapse(&mut self, duration: i64) -> Result<bool, BotError> {
+        if !self.bot.elapse(duration)? {
+            return Ok(false);
+        }
+        self.apply_fills();
+        self.sync_orders();
+        for (strategy_no, slot) in self.slots.iter_mut().enumerate() {
+            let start = Instant::now();
+            let mut strategy = std::mem::replace(&mut slot.strategy, Box::new(Idle));
+            let result = strategy.on_wakeup(&mut StrategyContext {
+                bot: &mut self.bot,
+                strategy_no,
+                slot,
+            });
+            slot.strategy = strategy;
+            slot.metrics.busy_time += start.elapsed().as_nanos() as i64;
+            result?;
+        }
+        self.bot.clear_last_trades(None);
+        Ok(true)
+    }
+
+    fn apply_fills(&mut self) {
+        let fills = std::mem::take(&mut *self.fills.borrow_mut());
+        for (asset_no, mut order) in fills {
+            // The order isn't a strategy's, such as the one submitted through the bot.
+            let Some((strategy_no, order_id)) = from_bot_order_id(order.order_id) else {
+                continue;
+            };
+            let Some(slot) = self.slots.get_mut(strategy_no) else {
+                continue;
+            };
+            order.order_id = order_id;
+            let book = &mut slot.books[asset_no];
+            let qty = order.exec_qty as f64;
+            let amount = qty * order.exec_price_tick as f64 * order.tick_size as f64;
+            book.position += order.side.as_f64() * qty;
+            book.balance -= order.side.as_f64() * amount;
+            book.trade_num += 1;
+            book.trade_qty += qty;
+            book.trade_amount += amount;
+            slot.metrics.fills += 1;
+            slot.strategy.on_fill(asset_no, &order);
+        }
+    }
+
+    /// Updates the strategies' orders from the bot's.
+    fn sync_orders(&mut self) {
+        for (asset_no, orders) in self.bot.orders.iter().enumerate() {
+            for (bot_order_id, order) in orders.iter() {
+                let Some((strategy_no, order_id)) = from_bot_order_id(*bot_order_id) else {
+                    continue;
+                };
+                if let Some(slot) = self.slots.get_mut(strategy_no) {
+                    let mut order = order.clone();
+                    order.order_id = order_id;
+                    slot.orders[asset_no].insert(order_id, order);
+                }
+            }
+        }
+    }
+}
+
+/// Stands in for a strategy while it's being woken.
+struct Idle;
+
+impl Strategy for Idle {
+    fn on_wakeup(&mut self, _ctx: &mut StrategyContext) -> Result<(), BotError> {
+        Ok(())
+    }
+}
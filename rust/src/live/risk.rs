@@ -0,0 +1,450 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth, Tick, INVALID_MAX, INVALID_MIN},
+    live::bot::BotError,
+    ty::{MarkSource, OrdType, Order, Side},
+};
+
+/// The limits of an asset.
+#[derive(Clone, Copy, Debug, Default)]
+struct AssetLimits {
+    max_position: Option<f64>,
+    max_order_qty: Option<f64>,
+    max_open_orders: Option<usize>,
+    max_open_notional: Option<f64>,
+    price_collar: Option<f64>,
+}
+
+/// Why the kill switch of the [`RiskManager`] has halted the bot.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HaltReason {
+    /// The loss of the trades made since the bot started has reached the maximum loss, with the
+    /// loss at the time.
+    MaxLoss(f64),
+    /// The number of the errors reported by the connectors within the window has reached the
+    /// maximum.
+    MaxErrors,
+    /// Halted by [`Bot::halt`](crate::live::bot::Bot::halt).
+    Manual,
+}
+
+/// Validates the order submissions of the bot before they reach the connectors, and halts the
+/// bot by the kill switch, which cancels all the open orders and rejects any further submission
+/// with [`BotError::Halted`], once the loss or the errors reach their thresholds. The bot stays
+/// halted until it's restarted.
+///
+/// A submission is rejected with [`BotError::RiskLimitExceeded`] if it would exceed any limit of
+/// the asset:
+///
+/// * The maximum quantity of an order.
+/// * The maximum position, which the position would exceed in the worst case that all the open
+///   orders of the side are filled along with the order. An order that reduces the absolute
+///   position in that case is allowed.
+/// * The maximum number of the open orders, including those waiting for the responses.
+/// * The maximum notional value of the open orders, including the order.
+/// * The price collar, within which a limit order's price should be relative to the opposite
+///   best price, so that a buy order's price is at most the best ask times `1 + collar` and a
+///   sell order's price is at least the best bid times `1 - collar`. A limit order is rejected if
+///   the opposite side of the book is empty.
+///
+/// The loss is that of the trades made since the bot started, which is computed from the fills
+/// marked to the prices of the assets' [`MarkSource`]s, the mid prices by default, excluding the
+/// fees and the positions held before the start.
+#[derive(Clone, Debug, Default)]
+pub struct RiskManager {
+    // key: asset_no
+    limits: HashMap<usize, AssetLimits>,
+    max_loss: Option<f64>,
+    // The maximum number of the errors and the window in nanoseconds.
+    max_errors: Option<(usize, i64)>,
+    // The timestamps of the errors within the window, in ascending order.
+    errors: VecDeque<i64>,
+    // key: asset_no, value: the cash flow and the position of the fills since the start.
+    fills: HashMap<usize, (f64, f64)>,
+    // key: asset_no
+    mark_sources: HashMap<usize, MarkSource>,
+    // key: asset_no
+    last_trades: HashMap<usize, f64>,
+    // key: asset_no
+    mark_prices: HashMap<usize, f64>,
+    halted: Option<HaltReason>,
+}
+
+impl RiskManager {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the maximum absolute position of the asset.
+    pub fn max_position(mut self, asset_no: usize, qty: f64) -> Self {
+        self.limits.entry(asset_no).or_default().max_position = Some(qty);
+        self
+    }
+
+    /// Sets the maximum quantity of an order of the asset.
+    pub fn max_order_qty(mut self, asset_no: usize, qty: f64) -> Self {
+        self.limits.entry(asset_no).or_default().max_order_qty = Some(qty);
+        self
+    }
+
+    /// Sets the maximum number of the open orders of the asset.
+    pub fn max_open_orders(mut self, asset_no: usize, n: usize) -> Self {
+        self.limits.entry(asset_no).or_default().max_open_orders = Some(n);
+        self
+    }
+
+    /// Sets the maximum notional value of the open orders of the asset, which is the sum of their
+    /// prices times their remaining quantities.
+    pub fn max_open_notional(mut self, asset_no: usize, notional: f64) -> Self {
+        self.limits.entry(asset_no).or_default().max_open_notional = Some(notional);
+        self
+    }
+
+    /// Sets the price collar of the asset as a fraction of the opposite best price, such as
+    /// `0.01` for 1%.
+    pub fn price_collar(mut self, asset_no: usize, collar: f64) -> Self {
+        self.limits.entry(asset_no).or_default().price_collar = Some(collar);
+        self
+    }
+
+    /// Sets the price at which the position of the asset is marked for the loss, which is the
+    /// mid price by default.
+    pub fn mark_source(mut self, asset_no: usize, source: MarkSource) -> Self {
+        self.mark_sources.insert(asset_no, source);
+        self
+    }
+
+    /// Halts the bot once the loss of all assets reaches `loss`, which is a positive amount in
+    /// the quote currency.
+    pub fn max_loss(self, loss: f64) -> Self {
+        Self {
+            max_loss: Some(loss),
+            ..self
+        }
+    }
+
+    /// Halts the bot once `max` errors are reported by the connectors within `window`
+    /// nanoseconds.
+    pub fn max_errors(self, max: usize, window: i64) -> Self {
+        Self {
+            max_errors: Some((max, window)),
+            ..self
+        }
+    }
+
+    /// Returns why the bot has been halted, or `None` if it hasn't.
+    pub fn halted(&self) -> Option<&HaltReason> {
+        self.halted.as_ref()
+    }
+
+    pub(crate) fn halt(&mut self, reason: HaltReason) {
+        if self.halted.is_none() {
+            self.halted = Some(reason);
+        }
+    }
+
+    /// Returns the price at which the position of the asset is marked by its [`MarkSource`], or
+    /// `None` if neither the price of the source nor the mid price is available.
+    pub fn mark(&self, asset_no: usize, depth: &HashMapMarketDepth) -> Option<f64> {
+        let best_bid_tick = depth.best_bid_tick().to_i64();
+        let best_ask_tick = depth.best_ask_tick().to_i64();
+        let mid = (best_bid_tick != INVALID_MIN && best_ask_tick != INVALID_MAX)
+            .then(|| (best_bid_tick + best_ask_tick) as f64 / 2.0 * depth.tick_size() as f64);
+        self.mark_sources
+            .get(&asset_no)
+            .copied()
+            .unwrap_or_default()
+            .select(
+                mid,
+                self.last_trades.get(&asset_no).copied(),
+                self.mark_prices.get(&asset_no).copied(),
+            )
+    }
+
+    /// Returns the loss of the trades made since the bot started, marked by the assets'
+    /// [`MarkSource`]s, which is negative if they have made a profit.
+    pub fn loss(&self, depth: &[HashMapMarketDepth]) -> f64 {
+        -self
+            .fills
+            .iter()
+            .map(|(asset_no, (cash, position))| {
+                if *position == 0.0 {
+                    return *cash;
+                }
+                let Some(depth) = depth.get(*asset_no) else {
+                    return 0.0;
+                };
+                match self.mark(*asset_no, depth) {
+                    Some(mark) => cash + position * mark,
+                    // The trades can't be marked without the price, so they're left out.
+                    None => 0.0,
+                }
+            })
+            .sum::<f64>()
+    }
+
+    /// Checks the order submission of the asset against the limits, given the position and the
+    /// orders of the asset, which don't include the order yet.
+    pub(crate) fn check(
+        &self,
+        asset_no: usize,
+        order: &Order<()>,
+        position: f64,
+        orders: &HashMap<i64, Order<()>>,
+        depth: &HashMapMarketDepth,
+    ) -> Result<(), BotError> {
+        if self.halted.is_some() {
+            return Err(BotError::Halted);
+        }
+        let Some(limits) = self.limits.get(&asset_no) else {
+            return Ok(());
+        };
+        let open_orders = || orders.values().filter(|order| !order.terminal());
+        // The leaves quantity is known only once the order is accepted.
+        let open_qty = |order: &Order<()>| {
+            if order.leaves_qty > 0.0 {
+                order.leaves_qty as f64
+            } else {
+                order.qty as f64
+            }
+        };
+        if let Some(max_order_qty) = limits.max_order_qty {
+            if order.qty as f64 > max_order_qty {
+                return Err(BotError::RiskLimitExceeded);
+            }
+        }
+        if let Some(max_open_orders) = limits.max_open_orders {
+            if open_orders().count() >= max_open_orders {
+                return Err(BotError::RiskLimitExceeded);
+            }
+        }
+        if let Some(max_position) = limits.max_position {
+            let open_qty: f64 = open_orders()
+                .filter(|open| open.side == order.side)
+                .map(open_qty)
+                .sum();
+            let worst_position = position + order.side.as_f64() * (open_qty + order.qty as f64);
+            if worst_position.abs() > max_position && worst_position.abs() > position.abs() {
+                return Err(BotError::RiskLimitExceeded);
+            }
+        }
+        if let Some(max_open_notional) = limits.max_open_notional {
+            let notional = |order: &Order<()>| open_qty(order) * order.price() as f64;
+            let open_notional: f64 = open_orders().map(notional).sum();
+            if open_notional + order.qty as f64 * order.price() as f64 > max_open_notional {
+                return Err(BotError::RiskLimitExceeded);
+            }
+        }
+        if let (Some(collar), OrdType::Limit) = (limits.price_collar, order.order_type) {
+            let price = order.price() as f64;
+            let tick_size = depth.tick_size() as f64;
+            let within = match order.side {
+                Side::Buy => {
+                    let best_ask_tick = depth.best_ask_tick().to_i64();
+                    best_ask_tick != INVALID_MAX
+                        && price <= best_ask_tick as f64 * tick_size * (1.0 + collar)
+                }
+                _ => {
+                    let best_bid_tick = depth.best_bid_tick().to_i64();
+                    best_bid_tick != INVALID_MIN
+                        && price >= best_bid_tick as f64 * tick_size * (1.0 - collar)
+                }
+            };
+            if !within {
+                return Err(BotError::RiskLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the fill of the order, of which `exec_qty` is the quantity of the fill.
+    pub(crate) fn on_fill(&mut self, asset_no: usize, order: &Order<()>) {
+        let (cash, position) = self.fills.entry(asset_no).or_default();
+        let qty = order.exec_qty as f64;
+        *cash -= order.side.as_f64() * qty * order.exec_price_tick as f64 * order.tick_size as f64;
+        *position += order.side.as_f64() * qty;
+    }
+
+    /// Records the price of the asset's last trade in the market.
+    pub(crate) fn on_trade(&mut self, asset_no: usize, price: f64) {
+        self.last_trades.insert(asset_no, price);
+    }
+
+    /// Records the venue's mark price of the asset.
+    pub(crate) fn on_mark_price(&mut self, asset_no: usize, price: f64) {
+        self.mark_prices.insert(asset_no, price);
+    }
+
+    /// Returns the reason to halt if the loss has reached the maximum loss.
+    pub(crate) fn check_loss(&self, depth: &[HashMapMarketDepth]) -> Option<HaltReason> {
+        let max_loss = self.max_loss?;
+        if self.halted.is_some() || self.fills.is_empty() {
+            return None;
+        }
+        let loss = self.loss(depth);
+        (loss >= max_loss).then_some(HaltReason::MaxLoss(loss))
+    }
+
+    /// Records the error reported at `now`, returning the reason to halt if the errors have
+    /// reached the maximum.
+    pub(crate) fn on_error(&mut self, now: i64) -> Option<HaltReason> {
+        let (max, window) = self.max_errors?;
+        while self.errors.front().is_some_and(|ts| *ts <= now - window) {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(now);
+        (self.halted.is_none() && self.errors.len() >= max).then_some(HaltReason::MaxErrors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{HaltReason, RiskManager};
+    use crate::{
+        depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth},
+        live::bot::BotError,
+        ty::{OrdType, Order, Side, TimeInForce},
+    };
+
+    fn depth(bid: Option<f32>, ask: Option<f32>) -> HashMapMarketDepth {
+        let mut depth = HashMapMarketDepth::new(0.1, 1.0);
+        if let Some(bid) = bid {
+            depth.update_bid_depth(bid, 10.0, 0);
+        }
+        if let Some(ask) = ask {
+            depth.update_ask_depth(ask, 10.0, 0);
+        }
+        depth
+    }
+
+    fn order(side: Side, price: f32, ord_type: OrdType) -> Order<()> {
+        Order::new(
+            1,
+            (price / 0.1).round() as i64,
+            0.1,
+            1.0,
+            side,
+            ord_type,
+            TimeInForce::GTC,
+        )
+    }
+
+    fn fill(side: Side, price: f32, qty: f32) -> Order<()> {
+        let mut order = order(side, price, OrdType::Limit);
+        order.exec_price_tick = order.price_tick;
+        order.exec_qty = qty;
+        order
+    }
+
+    #[test]
+    fn max_loss_trips() {
+        let mut risk = RiskManager::new().max_loss(5.0);
+        risk.on_fill(0, &fill(Side::Buy, 100.0, 2.0));
+
+        // Marked to the mid of 99.0, the loss is 2.0.
+        let depths = [depth(Some(98.9), Some(99.1))];
+        assert!(risk.check_loss(&depths).is_none());
+
+        // Marked to the mid of 97.0, the loss is 6.0.
+        let depths = [depth(Some(96.9), Some(97.1))];
+        let Some(HaltReason::MaxLoss(loss)) = risk.check_loss(&depths) else {
+            panic!("the max loss doesn't trip");
+        };
+        assert!((loss - 6.0).abs() < 1e-6);
+
+        // Once halted, the loss doesn't trip again.
+        risk.halt(HaltReason::MaxLoss(loss));
+        assert!(risk.check_loss(&depths).is_none());
+    }
+
+    #[test]
+    fn max_errors_trips_within_window() {
+        let mut risk = RiskManager::new().max_errors(3, 1_000);
+        assert_eq!(risk.on_error(0), None);
+        assert_eq!(risk.on_error(500), None);
+        assert_eq!(risk.on_error(900), Some(HaltReason::MaxErrors));
+    }
+
+    #[test]
+    fn error_count_resets_outside_window() {
+        let mut risk = RiskManager::new().max_errors(3, 1_000);
+        assert_eq!(risk.on_error(0), None);
+        assert_eq!(risk.on_error(500), None);
+        // The first error has aged out of the window.
+        assert_eq!(risk.on_error(1_000), None);
+        // And then the second.
+        assert_eq!(risk.on_error(1_600), None);
+        assert_eq!(risk.on_error(1_700), Some(HaltReason::MaxErrors));
+    }
+
+    #[test]
+    fn halted_rejects_submissions() {
+        let mut risk = RiskManager::new();
+        let depth = depth(Some(99.9), Some(100.0));
+        let order = order(Side::Buy, 99.9, OrdType::Limit);
+        assert_eq!(risk.check(0, &order, 0.0, &HashMap::new(), &depth), Ok(()));
+
+        risk.halt(HaltReason::MaxErrors);
+        // The first reason is kept.
+        risk.halt(HaltReason::Manual);
+        assert_eq!(risk.halted(), Some(&HaltReason::MaxErrors));
+        assert_eq!(
+            risk.check(0, &order, 0.0, &HashMap::new(), &depth),
+            Err(BotError::Halted)
+        );
+        assert_eq!(risk.on_error(0), None);
+    }
+
+    #[test]
+    fn price_collar_rejects_outside() {
+        let risk = RiskManager::new().price_collar(0, 0.01);
+        let depth = depth(Some(99.9), Some(100.0));
+        let orders = HashMap::new();
+        let check = |side, price, ord_type| {
+            risk.check(0, &order(side, price, ord_type), 0.0, &orders, &depth)
+        };
+
+        assert_eq!(check(Side::Buy, 100.9, OrdType::Limit), Ok(()));
+        assert_eq!(
+            check(Side::Buy, 101.1, OrdType::Limit),
+            Err(BotError::RiskLimitExceeded)
+        );
+        assert_eq!(check(Side::Sell, 99.0, OrdType::Limit), Ok(()));
+        assert_eq!(
+            check(Side::Sell, 98.8, OrdType::Limit),
+            Err(BotError::RiskLimitExceeded)
+        );
+        // Only the limit orders are collared.
+        assert_eq!(check(Side::Buy, 101.1, OrdType::Market), Ok(()));
+    }
+
+    #[test]
+    fn price_collar_rejects_empty_opposite_side() {
+        let risk = RiskManager::new().price_collar(0, 0.01);
+        let depth = depth(Some(99.9), None);
+        let orders = HashMap::new();
+        assert_eq!(
+            risk.check(
+                0,
+                &order(Side::Buy, 99.9, OrdType::Limit),
+                0.0,
+                &orders,
+                &depth
+            ),
+            Err(BotError::RiskLimitExceeded)
+        );
+        assert_eq!(
+            risk.check(
+                0,
+                &order(Side::Sell, 100.0, OrdType::Limit),
+                0.0,
+                &orders,
+                &depth
+            ),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+        Mutex,
+    },
+    thread,
+};
+
+use tracing::warn;
+
+use crate::{
+    connector::Connector,
+    live::portfolio::ORDER_ID_BITS,
+    ty::{LiveEvent, Order, OrderResponse, Status},
+};
+
+type Conns = HashMap<String, Box<dyn Connector + Send + 'static>>;
+
+/// A bot's end of the shared event stream.
+#[derive(Default)]
+struct Subscriber {
+    tx: Option<Sender<LiveEvent>>,
+    // The events received before the bot runs, which are delivered once it does.
+    backlog: Vec<LiveEvent>,
+    closed: bool,
+}
+
+impl Subscriber {
+    fn send(&mut self, ev: LiveEvent) {
+        if self.closed {
+            return;
+        }
+        match self.tx.as_ref() {
+            Some(tx) => {
+                if tx.send(ev).is_err() {
+                    // The bot has been dropped.
+                    self.closed = true;
+                }
+            }
+            None => self.backlog.push(ev),
+        }
+    }
+}
+
+struct Hub {
+    conns: Conns,
+    started: bool,
+}
+
+/// The connectors shared by the bots built by
+/// [`LiveBuilder::build_shared`](crate::live::LiveBuilder::build_shared), which are started once
+/// the first bot runs. The events of the connectors are routed to the bots: the order responses
+/// to the bot that owns the order by its order id namespace, and the other events, such as the
+/// market data, the positions, and the errors, to all of them.
+///
+/// The order id of a bot is sent to the connectors with the bot number in its high bits, in the
+/// same way as [`Portfolio`](crate::live::portfolio::Portfolio), so that the order ids, and the
+/// client order ids that the connectors' order managers derive from them, don't collide across
+/// the bots. A bot's order ids must be within `0..(1 << ORDER_ID_BITS)`.
+#[derive(Clone)]
+pub(crate) struct SharedConnectors {
+    hub: Arc<Mutex<Hub>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    ev_tx: Sender<LiveEvent>,
+}
+
+impl SharedConnectors {
+    pub fn new(conns: Conns, num_bots: usize) -> Self {
+        let (ev_tx, ev_rx) = channel();
+        let subscribers: Arc<Mutex<Vec<Subscriber>>> = Arc::new(Mutex::new(
+            (0..num_bots).map(|_| Subscriber::default()).collect(),
+        ));
+        let subscribers_ = subscribers.clone();
+        thread::spawn(move || {
+            for ev in ev_rx {
+                let mut subscribers = subscribers_.lock().unwrap();
+                match ev {
+                    LiveEvent::Order(mut data) => {
+                        let bot_no = (data.order.order_id >> ORDER_ID_BITS) as usize;
+                        data.order.order_id &= (1 << ORDER_ID_BITS) - 1;
+                        match subscribers.get_mut(bot_no) {
+                            Some(subscriber) => subscriber.send(LiveEvent::Order(data)),
+                            None => warn!(?data, "The order doesn't belong to any bot."),
+                        }
+                    }
+                    ev => {
+                        for subscriber in subscribers.iter_mut() {
+                            subscriber.send(ev.clone());
+                        }
+                    }
+                }
+            }
+        });
+        Self {
+            hub: Arc::new(Mutex::new(Hub {
+                conns,
+                started: false,
+            })),
+            subscribers,
+            ev_tx,
+        }
+    }
+
+    /// Returns the connector of the name for the bot, which is to be registered to the bot in
+    /// place of the shared connector.
+    pub fn connector(&self, name: &str, bot_no: usize) -> SharedConnector {
+        SharedConnector {
+            shared: self.clone(),
+            name: name.to_string(),
+            bot_no,
+        }
+    }
+}
+
+/// A bot's handle to a connector of [`SharedConnectors`].
+pub(crate) struct SharedConnector {
+    shared: SharedConnectors,
+    name: String,
+    bot_no: usize,
+}
+
+impl SharedConnector {
+    /// Puts the bot number into the high bits of the order id, or returns `None` if the order id
+    /// is out of the bot's namespace.
+    fn namespaced(&self, mut order: Order<()>) -> Option<Order<()>> {
+        if !(0..(1 << ORDER_ID_BITS)).contains(&order.order_id) {
+            return None;
+        }
+        order.order_id |= (self.bot_no as i64) << ORDER_ID_BITS;
+        Some(order)
+    }
+
+    fn with_connector<F>(&self, f: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&dyn Connector) -> Result<(), anyhow::Error>,
+    {
+        let hub = self.shared.hub.lock().unwrap();
+        let conn = hub
+            .conns
+            .get(&self.name)
+            .ok_or_else(|| anyhow::anyhow!("connector {} not found", self.name))?;
+        f(conn.as_ref())
+    }
+}
+
+impl Connector for SharedConnector {
+    fn add(
+        &mut self,
+        _an: usize,
+        _symbol: String,
+        _tick_size: f32,
+        _lot_size: f32,
+    ) -> Result<(), anyhow::Error> {
+        // The assets have been added to the shared connector by the builder.
+        Ok(())
+    }
+
+    fn run(&mut self, tx: Sender<LiveEvent>) -> Result<(), anyhow::Error> {
+        {
+            let mut subscribers = self.shared.subscribers.lock().unwrap();
+            let subscriber = &mut subscribers[self.bot_no];
+            if subscriber.tx.is_none() {
+                for ev in subscriber.backlog.drain(..) {
+                    let _ = tx.send(ev);
+                }
+                subscriber.tx = Some(tx);
+            }
+        }
+        let mut hub = self.shared.hub.lock().unwrap();
+        if !hub.started {
+            hub.started = true;
+            for conn in hub.conns.values_mut() {
+                conn.run(self.shared.ev_tx.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn submit(
+        &self,
+        an: usize,
+        mut order: Order<()>,
+        ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let Some(namespaced) = self.namespaced(order.clone()) else {
+            order.req = Status::None;
+            order.status = Status::Rejected;
+            ev_tx.send(LiveEvent::Order(OrderResponse {
+                asset_no: an,
+                order,
+            }))?;
+            return Err(anyhow::anyhow!(
+                "the order id is out of the bot's namespace"
+            ));
+        };
+        self.with_connector(|conn| conn.submit(an, namespaced, self.shared.ev_tx.clone()))
+    }
+
+    fn cancel(
+        &self,
+        an: usize,
+        order: Order<()>,
+        _ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let order = self
+            .namespaced(order)
+            .ok_or_else(|| anyhow::anyhow!("the order id is out of the bot's namespace"))?;
+        self.with_connector(|conn| conn.cancel(an, order, self.shared.ev_tx.clone()))
+    }
+
+    fn modify(
+        &self,
+        an: usize,
+        order: Order<()>,
+        _ev_tx: Sender<LiveEvent>,
+    ) -> Result<(), anyhow::Error> {
+        let order = self
+            .namespaced(order)
+            .ok_or_else(|| anyhow::anyhow!("the order id is out of the bot's namespace"))?;
+        self.with_connector(|conn| conn.modify(an, order, self.shared.ev_tx.clone()))
+    }
+
+    fn streams_depth(&self) -> bool {
+        let hub = self.shared.hub.lock().unwrap();
+        hub.conns
+            .get(&self.name)
+            .is_some_and(|conn| conn.streams_depth())
+    }
+}
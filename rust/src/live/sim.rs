@@ -0,0 +1,619 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[cfg(feature = "backtest")]
+use crate::{
+    backtest::reader::{EXCH_EVENT, LOCAL_EVENT},
+    ty::{Event, BUY, DEPTH_EVENT, DEPTH_SNAPSHOT_EVENT, SELL, TRADE_EVENT},
+};
+use crate::{
+    depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth, INVALID_MAX, INVALID_MIN},
+    live::AssetInfo,
+    ty::{
+        Depth,
+        Error as ErrorEvent,
+        ErrorType,
+        LiveEvent,
+        OrdType,
+        Order,
+        OrderResponse,
+        Position,
+        Side,
+        Status,
+        TimeInForce,
+        Trade,
+    },
+};
+
+enum Action {
+    /// A recorded market event reaches the venue at its exchange timestamp.
+    Market(LiveEvent),
+    /// An order request, stamped with the bot's host clock when it was sent, reaches the venue.
+    Request(usize, Order<()>, i64),
+    /// An event reaches the bot.
+    Deliver(LiveEvent),
+}
+
+struct Scheduled {
+    ts: i64,
+    seq: u64,
+    action: Action,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        (self.ts, self.seq) == (other.ts, other.seq)
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.ts, self.seq).cmp(&(other.ts, other.seq))
+    }
+}
+
+/// The error of the bot's host clock against the venue's clock, which [`SimVenue`] injects to
+/// test that the bot's latency measurements, order reconciliation and request timestamp handling
+/// hold up under a skewed or stepping clock, such as NTP steps.
+///
+/// At time `t`, the host clock reads `t + offset + steps + jitter`, where `steps` is the sum of
+/// the deltas of the steps that have occurred by `t` and `jitter` is drawn uniformly from
+/// `[-jitter, jitter]` each time the clock is read. The jitter is drawn from a generator seeded
+/// with [`ClockSkew::seed`], so the simulation remains deterministic.
+#[derive(Clone, Debug, Default)]
+pub struct ClockSkew {
+    offset: i64,
+    jitter: i64,
+    steps: Vec<(i64, i64)>,
+    seed: u64,
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the constant offset of the host clock. A positive offset means the host clock is
+    /// ahead of the venue's.
+    pub fn offset(self, offset: i64) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Sets the maximum jitter of each clock reading.
+    pub fn jitter(self, jitter: i64) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// Adds a step of `delta` to the host clock at `timestamp`, as an NTP step would.
+    pub fn step(mut self, timestamp: i64, delta: i64) -> Self {
+        self.steps.push((timestamp, delta));
+        self
+    }
+
+    /// Sets the seed of the jitter. The default is zero.
+    pub fn seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    fn at(&self, timestamp: i64, rng: &mut StdRng) -> i64 {
+        let steps: i64 = self
+            .steps
+            .iter()
+            .filter(|(ts, _)| *ts <= timestamp)
+            .map(|(_, delta)| delta)
+            .sum();
+        let jitter = if self.jitter > 0 {
+            rng.gen_range(-self.jitter..=self.jitter)
+        } else {
+            0
+        };
+        self.offset + steps + jitter
+    }
+}
+
+/// How far ahead of the venue's clock a request timestamp can be, as on Binance.
+const MAX_REQUEST_AHEAD: i64 = 1_000_000_000;
+
+struct SimAsset {
+    info: AssetInfo,
+    depth: HashMapMarketDepth,
+    orders: HashMap<i64, Order<()>>,
+    position: f64,
+}
+
+/// A simulated venue that drives the live [`Bot`](crate::live::bot::Bot) with recorded
+/// normalized connector events instead of the connectors, so that the live-path code, the order
+/// state handling of the bot and the strategy running on it, can be tested with realistic
+/// sequences without a venue. See [`Bot::simulated`](crate::live::bot::Bot::simulated).
+///
+/// The simulation is deterministic and runs as fast as the events are processed: the bot's clock
+/// is switched to [`ClockSource::Simulated`](crate::live::clock::ClockSource::Simulated), which
+/// only moves forward as the bot elapses, and everything happens on the calling thread.
+///
+/// Each recorded depth or trade event updates the venue's book at its exchange timestamp and is
+/// delivered to the bot at its local timestamp. The orders reach the venue after the entry
+/// latency and the responses reach the bot after the response latency. The matching is
+/// deliberately simple:
+/// - An order that crosses the book when it arrives is filled in full at the best price as a
+///   taker, except a post-only(GTX) order, which expires.
+/// - A resting order is filled in full at its price when the opposite best crosses it, and
+///   partially or fully by the trades printed at or through its price.
+/// - Market orders and IOC or FOK orders that don't cross expire.
+/// - A modification changes the resting order in place, and the new price takes effect from the
+///   next book update.
+///
+/// With [`SimVenue::clock_skew`], the bot's host clock is skewed against the venue's: the local
+/// timestamps of the delivered depth and trade events, stamped by the connector, and the
+/// timestamps of the order requests are read from the skewed clock. With
+/// [`SimVenue::recv_window`], the venue rejects the requests whose timestamps are out of the
+/// window, as Binance does with `recvWindow`.
+#[derive(Default)]
+pub struct SimVenue {
+    assets: Vec<SimAsset>,
+    queue: BinaryHeap<Reverse<Scheduled>>,
+    seq: u64,
+    entry_latency: i64,
+    response_latency: i64,
+    clock_skew: Option<(ClockSkew, StdRng)>,
+    recv_window: Option<i64>,
+}
+
+impl SimVenue {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the constant order entry and response latencies. The defaults are zero.
+    pub fn order_latency(self, entry_latency: i64, response_latency: i64) -> Self {
+        Self {
+            entry_latency,
+            response_latency,
+            ..self
+        }
+    }
+
+    /// Sets the skew of the bot's host clock against the venue's. See [`ClockSkew`].
+    pub fn clock_skew(self, clock_skew: ClockSkew) -> Self {
+        let rng = StdRng::seed_from_u64(clock_skew.seed);
+        Self {
+            clock_skew: Some((clock_skew, rng)),
+            ..self
+        }
+    }
+
+    /// Sets the window within which the venue accepts a request. A request is rejected if its
+    /// timestamp is more than `recv_window` behind the venue's clock when it arrives, or more than
+    /// one second ahead of it. By default, the request timestamps are not checked.
+    pub fn recv_window(self, recv_window: i64) -> Self {
+        Self {
+            recv_window: Some(recv_window),
+            ..self
+        }
+    }
+
+    /// Adds an asset, whose asset number is the number of the assets added before it.
+    pub fn add(mut self, symbol: &str, tick_size: f32, lot_size: f32) -> Self {
+        let asset_no = self.assets.len();
+        self.assets.push(SimAsset {
+            info: AssetInfo {
+                asset_no,
+                symbol: symbol.to_string(),
+                tick_size,
+                lot_size,
+            },
+            depth: HashMapMarketDepth::new(tick_size, lot_size),
+            orders: HashMap::new(),
+            position: 0.0,
+        });
+        self
+    }
+
+    /// Adds the recorded depth and trade events. The events of different assets can be fed in
+    /// any order since they are replayed in order of their timestamps; the events with the same
+    /// timestamp are replayed in the fed order. The other events are ignored.
+    pub fn feed<I>(mut self, events: I) -> Self
+    where
+        I: IntoIterator<Item = LiveEvent>,
+    {
+        for ev in events {
+            let (exch_ts, local_ts) = match &ev {
+                LiveEvent::Depth(Depth {
+                    exch_ts, local_ts, ..
+                })
+                | LiveEvent::Trade(Trade {
+                    exch_ts, local_ts, ..
+                }) => (*exch_ts, *local_ts),
+                _ => continue,
+            };
+            self.schedule(exch_ts, Action::Market(ev.clone()));
+            self.schedule(local_ts, Action::Deliver(ev));
+        }
+        self
+    }
+
+    /// Adds the recorded events of the asset in the feed data format. Each depth or snapshot row
+    /// becomes a depth event of a single level and each trade row becomes a trade event. Since a
+    /// live depth event cannot clear the book, the depth clear rows are dropped, and so a
+    /// snapshot only updates the levels it contains.
+    #[cfg(feature = "backtest")]
+    pub fn feed_rows(mut self, asset_no: usize, rows: &[Event]) -> Self {
+        for row in rows {
+            let ev = match row.ev & 0xff {
+                DEPTH_EVENT | DEPTH_SNAPSHOT_EVENT => {
+                    let level = vec![(row.px, row.qty)];
+                    let (bids, asks) = if row.ev & BUY == BUY {
+                        (level, Vec::new())
+                    } else if row.ev & SELL == SELL {
+                        (Vec::new(), level)
+                    } else {
+                        continue;
+                    };
+                    LiveEvent::Depth(Depth {
+                        asset_no,
+                        exch_ts: row.exch_ts,
+                        local_ts: row.local_ts,
+                        bids,
+                        asks,
+                    })
+                }
+                TRADE_EVENT => LiveEvent::Trade(Trade {
+                    asset_no,
+                    exch_ts: row.exch_ts,
+                    local_ts: row.local_ts,
+                    side: if row.ev & BUY == BUY {
+                        1
+                    } else if row.ev & SELL == SELL {
+                        -1
+                    } else {
+                        0
+                    },
+                    price: row.px,
+                    qty: row.qty,
+                }),
+                _ => continue,
+            };
+            if row.ev & EXCH_EVENT == EXCH_EVENT {
+                self.schedule(row.exch_ts, Action::Market(ev.clone()));
+            }
+            if row.ev & LOCAL_EVENT == LOCAL_EVENT {
+                self.schedule(row.local_ts, Action::Deliver(ev));
+            }
+        }
+        self
+    }
+
+    /// Returns the assets in the order of their asset numbers.
+    pub fn assets(&self) -> Vec<AssetInfo> {
+        self.assets.iter().map(|asset| asset.info.clone()).collect()
+    }
+
+    /// Returns the timestamp of the earliest scheduled event, from which the simulation starts.
+    pub fn start_time(&self) -> Option<i64> {
+        self.queue.peek().map(|Reverse(scheduled)| scheduled.ts)
+    }
+
+    /// Returns `true` if all the events have been replayed and all the responses delivered.
+    pub fn is_finished(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    fn schedule(&mut self, ts: i64, action: Action) {
+        self.queue.push(Reverse(Scheduled {
+            ts,
+            seq: self.seq,
+            action,
+        }));
+        self.seq += 1;
+    }
+
+    /// Sends the order request, which reaches the venue after the entry latency.
+    pub(crate) fn request(&mut self, asset_no: usize, order: Order<()>, now: i64) {
+        let req_ts = now + self.skew(now);
+        self.schedule(
+            now + self.entry_latency,
+            Action::Request(asset_no, order, req_ts),
+        );
+    }
+
+    /// Returns the error of the bot's host clock at `timestamp`.
+    fn skew(&mut self, timestamp: i64) -> i64 {
+        match self.clock_skew.as_mut() {
+            Some((clock_skew, rng)) => clock_skew.at(timestamp, rng),
+            None => 0,
+        }
+    }
+
+    /// Runs the venue up to `until` and returns the next event that reaches the bot by then,
+    /// with its timestamp.
+    pub(crate) fn next_until(&mut self, until: i64) -> Option<(i64, LiveEvent)> {
+        while self
+            .queue
+            .peek()
+            .is_some_and(|Reverse(scheduled)| scheduled.ts <= until)
+        {
+            let Reverse(Scheduled { ts, action, .. }) = self.queue.pop().unwrap();
+            match action {
+                Action::Market(ev) => self.on_market(ts, ev),
+                Action::Request(asset_no, order, req_ts) => {
+                    self.on_request(ts, asset_no, order, req_ts)
+                }
+                Action::Deliver(mut ev) => {
+                    // The connector stamps the feed with the host clock on receipt.
+                    if let LiveEvent::Depth(Depth { local_ts, .. })
+                    | LiveEvent::Trade(Trade { local_ts, .. }) = &mut ev
+                    {
+                        *local_ts = ts + self.skew(ts);
+                    }
+                    return Some((ts, ev));
+                }
+            }
+        }
+        None
+    }
+
+    fn respond(&mut self, ts: i64, asset_no: usize, order: Order<()>) {
+        let ts = ts + self.response_latency;
+        let exec_qty = order.exec_qty as f64 * order.side.as_f64();
+        self.schedule(
+            ts,
+            Action::Deliver(LiveEvent::Order(OrderResponse { asset_no, order })),
+        );
+        if exec_qty != 0.0 {
+            let asset = &mut self.assets[asset_no];
+            asset.position += exec_qty;
+            let position = Position {
+                asset_no,
+                symbol: asset.info.symbol.clone(),
+                qty: asset.position,
+                entry_price: None,
+                mark_price: None,
+            };
+            self.schedule(ts, Action::Deliver(LiveEvent::Position(position)));
+        }
+    }
+
+    fn on_request(&mut self, ts: i64, asset_no: usize, mut order: Order<()>, req_ts: i64) {
+        if self.assets.get(asset_no).is_none() {
+            return;
+        }
+        order.exch_timestamp = ts;
+        order.exec_qty = 0.0;
+        if let Some(recv_window) = self.recv_window {
+            if ts - req_ts > recv_window || req_ts - ts > MAX_REQUEST_AHEAD {
+                // The venue rejects the request regardless of its kind.
+                let error = ErrorEvent::with(
+                    ErrorType::OrderError,
+                    format!(
+                        "order {}: timestamp {} is outside of the recvWindow at {}",
+                        order.order_id, req_ts, ts
+                    ),
+                );
+                self.schedule(
+                    ts + self.response_latency,
+                    Action::Deliver(LiveEvent::Error(error)),
+                );
+                if order.req == Status::New {
+                    order.req = Status::None;
+                    order.status = Status::Rejected;
+                    self.respond(ts, asset_no, order);
+                } else if let Some(resting) = self.assets[asset_no].orders.get(&order.order_id) {
+                    // The order keeps resting, and the response clears the pending request.
+                    let mut resting = resting.clone();
+                    resting.req = Status::None;
+                    resting.exch_timestamp = ts;
+                    resting.exec_qty = 0.0;
+                    self.respond(ts, asset_no, resting);
+                }
+                return;
+            }
+        }
+        let asset = &mut self.assets[asset_no];
+        match order.req {
+            Status::New => {
+                order.req = Status::None;
+                if asset.orders.contains_key(&order.order_id) {
+                    order.status = Status::Rejected;
+                    self.respond(ts, asset_no, order);
+                    return;
+                }
+                order.leaves_qty = order.qty;
+                let best_tick = match order.side {
+                    Side::Buy => asset.depth.best_ask_tick,
+                    _ => asset.depth.best_bid_tick,
+                };
+                let crossing = best_tick != INVALID_MIN
+                    && best_tick != INVALID_MAX
+                    && (order.order_type == OrdType::Market
+                        || (order.side == Side::Buy && order.price_tick >= best_tick)
+                        || (order.side == Side::Sell && order.price_tick <= best_tick));
+                if crossing && order.time_in_force != TimeInForce::GTX {
+                    order.status = Status::Filled;
+                    order.exec_price_tick = best_tick;
+                    order.exec_qty = order.qty;
+                    order.leaves_qty = 0.0;
+                    order.maker = false;
+                } else if crossing
+                    || order.order_type == OrdType::Market
+                    || order.time_in_force == TimeInForce::IOC
+                    || order.time_in_force == TimeInForce::FOK
+                {
+                    order.status = Status::Expired;
+                } else {
+                    order.status = Status::New;
+                    asset.orders.insert(order.order_id, order.clone());
+                }
+                self.respond(ts, asset_no, order);
+            }
+            Status::Canceled => match asset.orders.remove(&order.order_id) {
+                Some(mut resting) => {
+                    resting.status = Status::Canceled;
+                    resting.req = Status::None;
+                    resting.exch_timestamp = ts;
+                    resting.exec_qty = 0.0;
+                    self.respond(ts, asset_no, resting);
+                }
+                None => {
+                    // The order has already been filled or doesn't exist, as a venue would
+                    // reject the cancel.
+                    let error = ErrorEvent::with(
+                        ErrorType::OrderError,
+                        format!("order {} not found", order.order_id),
+                    );
+                    self.schedule(
+                        ts + self.response_latency,
+                        Action::Deliver(LiveEvent::Error(error)),
+                    );
+                }
+            },
+            Status::Replaced => {
+                let Some(resting) = asset.orders.get_mut(&order.order_id) else {
+                    // The order has already been filled or doesn't exist.
+                    let error = ErrorEvent::with(
+                        ErrorType::OrderError,
+                        format!("order {} not found", order.order_id),
+                    );
+                    self.schedule(
+                        ts + self.response_latency,
+                        Action::Deliver(LiveEvent::Error(error)),
+                    );
+                    return;
+                };
+                let exec_qty = resting.qty - resting.leaves_qty;
+                let accepted = order.qty > exec_qty;
+                if accepted {
+                    resting.price_tick = order.price_tick;
+                    resting.qty = order.qty;
+                    resting.leaves_qty = order.qty - exec_qty;
+                }
+                // The response clears the pending request either way.
+                let mut resting = resting.clone();
+                resting.req = Status::None;
+                resting.exch_timestamp = ts;
+                resting.exec_qty = 0.0;
+                if !accepted {
+                    let error = ErrorEvent::with(
+                        ErrorType::OrderError,
+                        format!("order {}: quantity is below the executed", order.order_id),
+                    );
+                    self.schedule(
+                        ts + self.response_latency,
+                        Action::Deliver(LiveEvent::Error(error)),
+                    );
+                }
+                self.respond(ts, asset_no, resting);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_market(&mut self, ts: i64, ev: LiveEvent) {
+        let (asset_no, fills) = match ev {
+            LiveEvent::Depth(data) => {
+                let Some(asset) = self.assets.get_mut(data.asset_no) else {
+                    return;
+                };
+                asset.depth.timestamp = data.exch_ts;
+                for (px, qty) in data.bids {
+                    asset.depth.update_bid_depth(px, qty, data.exch_ts);
+                }
+                for (px, qty) in data.asks {
+                    asset.depth.update_ask_depth(px, qty, data.exch_ts);
+                }
+                let best_bid_tick = asset.depth.best_bid_tick;
+                let best_ask_tick = asset.depth.best_ask_tick;
+                let fills = asset.fill(|order| match order.side {
+                    Side::Buy if best_ask_tick != INVALID_MAX => {
+                        (order.price_tick >= best_ask_tick).then_some(f32::INFINITY)
+                    }
+                    Side::Sell if best_bid_tick != INVALID_MIN => {
+                        (order.price_tick <= best_bid_tick).then_some(f32::INFINITY)
+                    }
+                    _ => None,
+                });
+                (data.asset_no, fills)
+            }
+            LiveEvent::Trade(data) => {
+                let Some(asset) = self.assets.get_mut(data.asset_no) else {
+                    return;
+                };
+                let trade_tick = (data.price / asset.info.tick_size).round() as i64;
+                // A sell-initiated trade takes the bids and a buy-initiated one the asks.
+                let mut remaining = data.qty;
+                let fills = asset.fill(|order| {
+                    let crossed = match order.side {
+                        Side::Buy => data.side == -1 && order.price_tick >= trade_tick,
+                        Side::Sell => data.side == 1 && order.price_tick <= trade_tick,
+                        Side::Unsupported => false,
+                    };
+                    if !crossed || remaining <= 0.0 {
+                        return None;
+                    }
+                    let exec_qty = order.leaves_qty.min(remaining);
+                    remaining -= exec_qty;
+                    Some(exec_qty)
+                });
+                (data.asset_no, fills)
+            }
+            _ => return,
+        };
+        for mut order in fills {
+            order.exch_timestamp = ts;
+            self.respond(ts, asset_no, order);
+        }
+    }
+}
+
+impl SimAsset {
+    /// Fills the resting orders by the quantity returned by `exec_qty`, in order of price
+    /// priority and then order id, and returns the updated orders.
+    fn fill<F>(&mut self, mut exec_qty: F) -> Vec<Order<()>>
+    where
+        F: FnMut(&Order<()>) -> Option<f32>,
+    {
+        let mut resting: Vec<&Order<()>> = self.orders.values().collect();
+        resting.sort_by_key(|order| {
+            let priority = match order.side {
+                Side::Buy => -order.price_tick,
+                _ => order.price_tick,
+            };
+            (priority, order.order_id)
+        });
+        let mut fills = Vec::new();
+        for order in resting {
+            if let Some(qty) = exec_qty(order) {
+                let mut order = order.clone();
+                let qty = qty.min(order.leaves_qty);
+                order.exec_qty = qty;
+                order.exec_price_tick = order.price_tick;
+                order.leaves_qty -= qty;
+                order.maker = true;
+                order.status = if order.leaves_qty > 0.0 {
+                    Status::PartiallyFilled
+                } else {
+                    Status::Filled
+                };
+                fills.push(order);
+            }
+        }
+        for order in fills.iter() {
+            if order.status == Status::Filled {
+                self.orders.remove(&order.order_id);
+            } else {
+                self.orders.insert(order.order_id, order.clone());
+            }
+        }
+        fills
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{HashMap, VecDeque};
+
+/// A limit of the number of order submissions within a sliding window, in nanoseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub max: u32,
+    pub window: i64,
+}
+
+/// How the order submissions beyond the limits of a [`Throttle`] are handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThrottleAction {
+    /// Queues the submissions and sends them in order once the limits allow. A queued order
+    /// stays with the `New` request and without a status until it's sent, and canceling it
+    /// removes it from the queue without reaching the exchange.
+    #[default]
+    Queue,
+    /// Rejects the submissions with [`BotError::Throttled`](crate::live::bot::BotError::Throttled).
+    Reject,
+}
+
+/// Throttles the outbound order submissions of the bot to stay within the venue's order rate
+/// limits, per account and per asset, instead of having the excess rejected by the venue, which
+/// may also ban the account for a while. The cancels aren't throttled since they don't count
+/// towards the order rate limits of most venues, including Binance. The order reductions count
+/// towards the limits, but those beyond the limits are rejected rather than queued.
+///
+/// The remaining budget is available to the strategy by
+/// [`Interface::order_budget`](crate::Interface::order_budget).
+#[derive(Clone, Debug, Default)]
+pub struct Throttle {
+    account_limits: Vec<RateLimit>,
+    // key: asset_no
+    asset_limits: HashMap<usize, Vec<RateLimit>>,
+    pub(crate) action: ThrottleAction,
+    // The timestamps of the submissions sent, in ascending order.
+    account_sent: VecDeque<i64>,
+    // key: asset_no
+    asset_sent: HashMap<usize, VecDeque<i64>>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Constructs a throttle with the default order rate limits of a Binance Futures account,
+    /// which are 300 orders per 10 seconds and 1200 orders per minute. Binance doesn't publish
+    /// per-symbol limits, which can be added by [`asset_limit`](Self::asset_limit).
+    pub fn binance_futures() -> Self {
+        Self::new()
+            .account_limit(300, 10_000_000_000)
+            .account_limit(1200, 60_000_000_000)
+    }
+
+    /// Adds a limit of `max` submissions per `window` for the whole account.
+    pub fn account_limit(mut self, max: u32, window: i64) -> Self {
+        self.account_limits.push(RateLimit { max, window });
+        self
+    }
+
+    /// Adds a limit of `max` submissions per `window` for the asset.
+    pub fn asset_limit(mut self, asset_no: usize, max: u32, window: i64) -> Self {
+        self.asset_limits
+            .entry(asset_no)
+            .or_default()
+            .push(RateLimit { max, window });
+        self
+    }
+
+    /// Sets how the submissions beyond the limits are handled. The default is
+    /// [`ThrottleAction::Queue`].
+    pub fn action(self, action: ThrottleAction) -> Self {
+        Self { action, ..self }
+    }
+
+    /// Returns the number of the submissions of the asset that can be sent at `now`, or `None`
+    /// if the asset isn't limited.
+    pub fn budget(&self, asset_no: usize, now: i64) -> Option<u32> {
+        let account = budget(&self.account_limits, &self.account_sent, now);
+        let asset = self.asset_limits.get(&asset_no).and_then(|limits| {
+            budget(
+                limits,
+                self.asset_sent.get(&asset_no).unwrap_or(&VecDeque::new()),
+                now,
+            )
+        });
+        match (account, asset) {
+            (Some(account), Some(asset)) => Some(account.min(asset)),
+            (account, asset) => account.or(asset),
+        }
+    }
+
+    /// Takes a unit of the budget of the asset at `now` if there is any left, returning whether
+    /// the submission can be sent.
+    pub(crate) fn acquire(&mut self, asset_no: usize, now: i64) -> bool {
+        if self.budget(asset_no, now) == Some(0) {
+            return false;
+        }
+        let max_window = |limits: &[RateLimit]| limits.iter().map(|l| l.window).max();
+        if let Some(window) = max_window(&self.account_limits) {
+            record(&mut self.account_sent, now, window);
+        }
+        if let Some(window) = self
+            .asset_limits
+            .get(&asset_no)
+            .and_then(|limits| max_window(limits))
+        {
+            record(self.asset_sent.entry(asset_no).or_default(), now, window);
+        }
+        true
+    }
+
+    /// Returns the earliest time after `now` at which the budget of the asset may increase.
+    pub(crate) fn next_release(&self, asset_no: usize, now: i64) -> Option<i64> {
+        let asset_limits = self.asset_limits.get(&asset_no);
+        let asset_sent = self.asset_sent.get(&asset_no);
+        let account = next_release(&self.account_limits, &self.account_sent, now);
+        let asset = asset_limits
+            .zip(asset_sent)
+            .and_then(|(limits, sent)| next_release(limits, sent, now));
+        match (account, asset) {
+            (Some(account), Some(asset)) => Some(account.max(asset)),
+            (account, asset) => account.or(asset),
+        }
+    }
+}
+
+fn budget(limits: &[RateLimit], sent: &VecDeque<i64>, now: i64) -> Option<u32> {
+    limits
+        .iter()
+        .map(|limit| {
+            let num_sent = sent
+                .iter()
+                .rev()
+                .take_while(|ts| **ts > now - limit.window)
+                .count();
+            limit.max.saturating_sub(num_sent as u32)
+        })
+        .min()
+}
+
+/// Returns the time at which every exhausted limit has a unit of the budget again.
+fn next_release(limits: &[RateLimit], sent: &VecDeque<i64>, now: i64) -> Option<i64> {
+    limits
+        .iter()
+        .filter_map(|limit| {
+            let in_window: Vec<i64> = sent
+                .iter()
+                .rev()
+                .take_while(|ts| **ts > now - limit.window)
+                .copied()
+                .collect();
+            if in_window.len() < limit.max as usize || limit.max == 0 {
+                return None;
+            }
+            // The `max`-th latest submission has to leave the window.
+            Some(in_window[limit.max as usize - 1] + limit.window)
+        })
+        .max()
+}
+
+fn record(sent: &mut VecDeque<i64>, now: i64, window: i64) {
+    while sent.front().is_some_and(|ts| *ts <= now - window) {
+        sent.pop_front();
+    }
+    sent.push_back(now);
+}
@@ -0,0 +1,124 @@
+use std::time::Duration;
+
+/// Determines how [`Bot::elapse`](crate::live::bot::Bot) waits for the next event, trading CPU
+/// usage for wakeup latency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WaitStrategy {
+    /// Blocks on the event channel until an event arrives or the timeout expires. This uses the
+    /// least CPU but the wakeup latency depends on the OS scheduler. This is the default.
+    #[default]
+    Park,
+    /// Polls the event channel in a busy loop, which occupies a core entirely but has the lowest
+    /// wakeup latency.
+    BusySpin,
+    /// Polls the event channel in a busy loop for the given duration in nanoseconds, then blocks
+    /// for the rest of the timeout.
+    SpinThenPark(i64),
+    /// Polls the event channel and sleeps for the given interval in nanoseconds between polls if
+    /// there is no event. On Linux, the sleep is timed by a `timerfd`.
+    Sleep(i64),
+}
+
+/// Statistics of the wakeup latency in nanoseconds.
+#[derive(Clone, Debug, Default)]
+pub struct WakeupStats {
+    pub count: u64,
+    pub total: i64,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl WakeupStats {
+    pub fn record(&mut self, latency: i64) {
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        if self.count == 0 || latency > self.max {
+            self.max = latency;
+        }
+        self.count += 1;
+        self.total += latency;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.total as f64 / self.count as f64
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+}
+
+/// A sleep timer for [`WaitStrategy::Sleep`].
+#[cfg(target_os = "linux")]
+pub(crate) struct Timer {
+    fd: i32,
+}
+
+#[cfg(target_os = "linux")]
+impl Timer {
+    pub fn new() -> Self {
+        let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_CLOEXEC) };
+        Self { fd }
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        if self.fd < 0 {
+            std::thread::sleep(duration);
+            return;
+        }
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: duration.as_secs() as libc::time_t,
+                // Zero disarms the timer.
+                tv_nsec: duration.subsec_nanos().max(1) as libc::c_long,
+            },
+        };
+        let mut expirations = 0u64;
+        unsafe {
+            if libc::timerfd_settime(self.fd, 0, &spec, std::ptr::null_mut()) != 0 {
+                std::thread::sleep(duration);
+                return;
+            }
+            // Blocks until the timer expires.
+            libc::read(
+                self.fd,
+                &mut expirations as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if self.fd >= 0 {
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct Timer;
+
+#[cfg(not(target_os = "linux"))]
+impl Timer {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
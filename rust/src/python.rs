@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use crate::{
+    backtest::{
+        backtest::MultiAssetMultiExchangeBacktest,
+        models::{OutOfRange, QueuePos},
+        spec::{
+            ord_type,
+            time_in_force,
+            AssetSpec,
+            AssetTypeSpec,
+            ExchangeSpec,
+            FeeSpec,
+            LatencySpec,
+            QueueModelSpec,
+        },
+        BtBuilder,
+        Error,
+    },
+    depth::{hashmapmarketdepth::HashMapMarketDepth, MarketDepth},
+    error::BuildError,
+    ty::{OrdType, Order, Side, StateValues, Status, TimeInForce},
+    Interface,
+};
+
+type Backtest = MultiAssetMultiExchangeBacktest<QueuePos, HashMapMarketDepth>;
+
+fn backtest_error(error: Error) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+fn build_error(error: BuildError) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+fn to_time_in_force(value: u8) -> PyResult<TimeInForce> {
+    time_in_force(value)
+        .ok_or_else(|| PyValueError::new_err(format!("invalid time in force {value}")))
+}
+
+fn to_ord_type(value: u8) -> PyResult<OrdType> {
+    ord_type(value).ok_or_else(|| PyValueError::new_err(format!("invalid order type {value}")))
+}
+
+/// Builds an asset for the backtest, as [`BtAssetBuilder`](crate::backtest::BtAssetBuilder)
+/// does. The settings are kept until the asset is built by `BtBuilder.build`, where the data is
+/// read. The market depth is [`HashMapMarketDepth`] and the queue model is
+/// [`ProbQueueModel`](crate::backtest::models::ProbQueueModel) with the probability function set.
+#[pyclass(name = "BtAssetBuilder")]
+#[derive(Clone, Debug, Default)]
+pub struct PyBtAssetBuilder {
+    spec: AssetSpec,
+}
+
+#[pymethods]
+impl PyBtAssetBuilder {
+    #[new]
+    fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the data files, such as `.npz` files, in order of time.
+    fn data(mut slf: PyRefMut<'_, Self>, data: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.spec.data = data;
+        slf
+    }
+
+    fn initial_snapshot(mut slf: PyRefMut<'_, Self>, filepath: String) -> PyRefMut<'_, Self> {
+        slf.spec.initial_snapshot = Some(filepath);
+        slf
+    }
+
+    fn constant_latency(
+        mut slf: PyRefMut<'_, Self>,
+        entry_latency: i64,
+        response_latency: i64,
+    ) -> PyRefMut<'_, Self> {
+        slf.spec.latency = Some(LatencySpec::Constant {
+            entry_latency,
+            response_latency,
+        });
+        slf
+    }
+
+    /// Sets [`IntpOrderLatency`](crate::backtest::models::IntpOrderLatency) with the latency data
+    /// file, which handles a request outside the time range of the data by `out_of_range`, one of
+    /// `"clamp"`, `"extrapolate"`, and `"error"`. See
+    /// [`OutOfRange`](crate::backtest::models::OutOfRange).
+    #[pyo3(signature = (filepath, out_of_range = "clamp"))]
+    fn intp_order_latency<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        filepath: String,
+        out_of_range: &str,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let out_of_range = match out_of_range {
+            "clamp" => OutOfRange::Clamp,
+            "extrapolate" => OutOfRange::Extrapolate,
+            "error" => OutOfRange::Error,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid out_of_range {out_of_range}"
+                )))
+            }
+        };
+        slf.spec.latency = Some(LatencySpec::Intp {
+            filepath,
+            out_of_range,
+        });
+        Ok(slf)
+    }
+
+    fn linear_asset(mut slf: PyRefMut<'_, Self>, contract_size: f64) -> PyRefMut<'_, Self> {
+        slf.spec.asset_type = Some(AssetTypeSpec::Linear { contract_size });
+        slf
+    }
+
+    fn inverse_asset(mut slf: PyRefMut<'_, Self>, contract_size: f64) -> PyRefMut<'_, Self> {
+        slf.spec.asset_type = Some(AssetTypeSpec::Inverse { contract_size });
+        slf
+    }
+
+    fn power_prob_queue_model(mut slf: PyRefMut<'_, Self>, n: f32) -> PyRefMut<'_, Self> {
+        slf.spec.queue_model = Some(QueueModelSpec::PowerProb { n });
+        slf
+    }
+
+    fn power_prob_queue_model2(mut slf: PyRefMut<'_, Self>, n: f32) -> PyRefMut<'_, Self> {
+        slf.spec.queue_model = Some(QueueModelSpec::PowerProb2 { n });
+        slf
+    }
+
+    fn power_prob_queue_model3(mut slf: PyRefMut<'_, Self>, n: f32) -> PyRefMut<'_, Self> {
+        slf.spec.queue_model = Some(QueueModelSpec::PowerProb3 { n });
+        slf
+    }
+
+    fn log_prob_queue_model(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.spec.queue_model = Some(QueueModelSpec::LogProb);
+        slf
+    }
+
+    fn log_prob_queue_model2(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.spec.queue_model = Some(QueueModelSpec::LogProb2);
+        slf
+    }
+
+    fn tick_size(mut slf: PyRefMut<'_, Self>, tick_size: f32) -> PyRefMut<'_, Self> {
+        slf.spec.tick_size = Some(tick_size);
+        slf
+    }
+
+    /// Sets the lot size. The default is `1.0`.
+    fn lot_size(mut slf: PyRefMut<'_, Self>, lot_size: f32) -> PyRefMut<'_, Self> {
+        slf.spec.lot_size = lot_size;
+        slf
+    }
+
+    /// Sets [`NoPartialFillExchange`](crate::backtest::ExchangeKind::NoPartialFillExchange),
+    /// which is the default.
+    fn no_partial_fill_exchange(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.spec.exchange = ExchangeSpec::NoPartialFill;
+        slf
+    }
+
+    /// Sets [`PartialFillExchange`](crate::backtest::ExchangeKind::PartialFillExchange).
+    fn partial_fill_exchange(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.spec.exchange = ExchangeSpec::PartialFill;
+        slf
+    }
+
+    /// Sets [`ReferenceOnly`](crate::backtest::ExchangeKind::ReferenceOnly), for an asset that the
+    /// strategy only reads signals from.
+    fn reference_only(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.spec.exchange = ExchangeSpec::ReferenceOnly;
+        slf
+    }
+
+    /// See [`BtAssetBuilder::bbo_feed`](crate::backtest::BtAssetBuilder::bbo_feed).
+    fn bbo_feed(mut slf: PyRefMut<'_, Self>, bbo_feed: bool) -> PyRefMut<'_, Self> {
+        slf.spec.bbo_feed = bbo_feed;
+        slf
+    }
+
+    /// Sets [`FlatFee`](crate::backtest::models::FlatFee) with the maker and the taker fee rates.
+    fn trading_fee(
+        mut slf: PyRefMut<'_, Self>,
+        maker_fee: f64,
+        taker_fee: f64,
+    ) -> PyRefMut<'_, Self> {
+        slf.spec.fee = Some(FeeSpec {
+            maker_fee,
+            taker_fee,
+        });
+        slf
+    }
+
+    /// See [`BtAssetBuilder::venue_profile`](crate::backtest::BtAssetBuilder::venue_profile). The
+    /// fee set by [`trading_fee`](Self::trading_fee) overrides that of the profile.
+    fn venue_profile(mut slf: PyRefMut<'_, Self>, name: String) -> PyRefMut<'_, Self> {
+        slf.spec.venue_profile = Some(name);
+        slf
+    }
+}
+
+/// Builds the backtest of the assets added, as [`BtBuilder`] does.
+#[pyclass(name = "BtBuilder")]
+#[derive(Default)]
+pub struct PyBtBuilder {
+    assets: Vec<PyBtAssetBuilder>,
+}
+
+#[pymethods]
+impl PyBtBuilder {
+    #[new]
+    fn new() -> Self {
+        Default::default()
+    }
+
+    fn add<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        asset: PyRef<'py, PyBtAssetBuilder>,
+    ) -> PyRefMut<'py, Self> {
+        slf.assets.push(asset.clone());
+        slf
+    }
+
+    /// Builds the assets, reading their data, and the backtest of them in order of addition.
+    fn build(&self) -> PyResult<PyHftBacktest> {
+        let mut builder = BtBuilder::new();
+        for asset in self.assets.iter() {
+            builder = builder.add(asset.spec.build().map_err(build_error)?);
+        }
+        Ok(PyHftBacktest {
+            hbt: builder.build().map_err(build_error)?,
+        })
+    }
+}
+
+/// The backtest, driven by the same methods as [`Interface`], whose errors are raised as
+/// `RuntimeError`. The time in force, the order type, the side, and the status are the integers
+/// of the discriminants of [`TimeInForce`], [`OrdType`], [`Side`], and [`Status`], which are
+/// defined as the module's constants, such as `GTC` and `LIMIT`.
+#[pyclass(name = "HftBacktest", unsendable)]
+pub struct PyHftBacktest {
+    hbt: Backtest,
+}
+
+#[pymethods]
+impl PyHftBacktest {
+    fn current_timestamp(&self) -> i64 {
+        self.hbt.current_timestamp()
+    }
+
+    fn position(&self, asset_no: usize) -> f64 {
+        self.hbt.position(asset_no)
+    }
+
+    fn state_values(&self, asset_no: usize) -> StateValues {
+        self.hbt.state_values(asset_no)
+    }
+
+    /// Returns the market depth of the asset, which reflects the depth as the backtest elapses.
+    fn depth(slf: Bound<'_, Self>, asset_no: usize) -> PyMarketDepth {
+        PyMarketDepth {
+            hbt: slf.unbind(),
+            asset_no,
+        }
+    }
+
+    /// Returns the trades received since they were last cleared, as the tuples of `(ev, exch_ts,
+    /// local_ts, px, qty)`.
+    fn trade(&self, asset_no: usize) -> Vec<(i64, i64, i64, f32, f32)> {
+        self.hbt
+            .trade(asset_no)
+            .iter()
+            .map(|trade| (trade.ev, trade.exch_ts, trade.local_ts, trade.px, trade.qty))
+            .collect()
+    }
+
+    #[pyo3(signature = (asset_no = None))]
+    fn clear_last_trades(&mut self, asset_no: Option<usize>) {
+        self.hbt.clear_last_trades(asset_no);
+    }
+
+    /// Returns the orders of the asset by their order ids.
+    fn orders(&self, asset_no: usize) -> HashMap<i64, PyOrder> {
+        self.hbt
+            .orders(asset_no)
+            .iter()
+            .map(|(order_id, order)| (*order_id, PyOrder::from(order)))
+            .collect()
+    }
+
+    /// Submits a buy order. See `OrderRequest`.
+    fn submit_buy_order(
+        &mut self,
+        asset_no: usize,
+        request: PyOrderRequest,
+        wait: bool,
+    ) -> PyResult<bool> {
+        self.submit_order(asset_no, Side::Buy, request, wait)
+    }
+
+    /// Submits a sell order. See `OrderRequest`.
+    fn submit_sell_order(
+        &mut self,
+        asset_no: usize,
+        request: PyOrderRequest,
+        wait: bool,
+    ) -> PyResult<bool> {
+        self.submit_order(asset_no, Side::Sell, request, wait)
+    }
+
+    fn cancel(&mut self, asset_no: usize, order_id: i64, wait: bool) -> PyResult<bool> {
+        self.hbt
+            .cancel(asset_no, order_id, wait)
+            .map_err(backtest_error)
+    }
+
+    fn reduce_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        qty: f32,
+        wait: bool,
+    ) -> PyResult<bool> {
+        self.hbt
+            .reduce_order(asset_no, order_id, qty, wait)
+            .map_err(backtest_error)
+    }
+
+    fn modify_order(
+        &mut self,
+        asset_no: usize,
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        wait: bool,
+    ) -> PyResult<bool> {
+        self.hbt
+            .modify_order(asset_no, order_id, price, qty, wait)
+            .map_err(backtest_error)
+    }
+
+    #[pyo3(signature = (asset_no = None))]
+    fn clear_inactive_orders(&mut self, asset_no: Option<usize>) {
+        self.hbt.clear_inactive_orders(asset_no);
+    }
+
+    fn elapse(&mut self, duration: i64) -> PyResult<bool> {
+        self.hbt.elapse(duration).map_err(backtest_error)
+    }
+
+    fn elapse_bt(&mut self, duration: i64) -> PyResult<bool> {
+        self.hbt.elapse_bt(duration).map_err(backtest_error)
+    }
+
+    fn wait_next_feed(&mut self, include_order_resp: bool, timeout: i64) -> PyResult<bool> {
+        self.hbt
+            .wait_next_feed(include_order_resp, timeout)
+            .map_err(backtest_error)
+    }
+
+    fn close(&mut self) -> PyResult<()> {
+        self.hbt.close().map_err(backtest_error)
+    }
+}
+
+impl PyHftBacktest {
+    fn submit_order(
+        &mut self,
+        asset_no: usize,
+        side: Side,
+        request: PyOrderRequest,
+        wait: bool,
+    ) -> PyResult<bool> {
+        let time_in_force = to_time_in_force(request.time_in_force)?;
+        let order_type = to_ord_type(request.order_type)?;
+        let trigger_price = match (order_type.is_trigger(), request.trigger_price) {
+            (true, None) => {
+                return Err(PyValueError::new_err(
+                    "a stop or take-profit order requires the trigger price",
+                ));
+            }
+            (true, trigger_price) => trigger_price,
+            (false, _) => None,
+        };
+        // The backtest takes the trigger price of the orders submitted while it's set.
+        self.hbt.set_trigger_price(asset_no, trigger_price);
+        let result = if side == Side::Buy {
+            self.hbt.submit_buy_order(
+                asset_no,
+                request.order_id,
+                request.price,
+                request.qty,
+                time_in_force,
+                order_type,
+                wait,
+            )
+        } else {
+            self.hbt.submit_sell_order(
+                asset_no,
+                request.order_id,
+                request.price,
+                request.qty,
+                time_in_force,
+                order_type,
+                wait,
+            )
+        };
+        self.hbt.set_trigger_price(asset_no, None);
+        result.map_err(backtest_error)
+    }
+}
+
+/// An order to submit through `HftBacktest.submit_buy_order` or `submit_sell_order`. The trigger
+/// price is required for a stop or take-profit order, such as `STOP_MARKET`, and ignored for the
+/// other order types.
+///
+/// ```python
+/// request = OrderRequest(1, 99.0, 1.0, GTC, STOP_MARKET, trigger_price=99.5)
+/// hbt.submit_sell_order(0, request, False)
+/// ```
+#[pyclass(name = "OrderRequest", get_all, set_all)]
+#[derive(Clone, Debug)]
+pub struct PyOrderRequest {
+    order_id: i64,
+    price: f32,
+    qty: f32,
+    time_in_force: u8,
+    order_type: u8,
+    trigger_price: Option<f32>,
+}
+
+#[pymethods]
+impl PyOrderRequest {
+    #[new]
+    #[pyo3(signature = (
+        order_id,
+        price,
+        qty,
+        time_in_force = TimeInForce::GTC as u8,
+        order_type = OrdType::Limit as u8,
+        trigger_price = None,
+    ))]
+    fn new(
+        order_id: i64,
+        price: f32,
+        qty: f32,
+        time_in_force: u8,
+        order_type: u8,
+        trigger_price: Option<f32>,
+    ) -> Self {
+        Self {
+            order_id,
+            price,
+            qty,
+            time_in_force,
+            order_type,
+            trigger_price,
+        }
+    }
+}
+
+/// The market depth of an asset of [`PyHftBacktest`].
+#[pyclass(name = "MarketDepth")]
+pub struct PyMarketDepth {
+    hbt: Py<PyHftBacktest>,
+    asset_no: usize,
+}
+
+impl PyMarketDepth {
+    fn with<T>(&self, py: Python<'_>, f: impl FnOnce(&HashMapMarketDepth) -> T) -> T {
+        f(self.hbt.borrow(py).hbt.depth(self.asset_no))
+    }
+}
+
+#[pymethods]
+impl PyMarketDepth {
+    fn best_bid(&self, py: Python<'_>) -> f32 {
+        self.with(py, |depth| depth.best_bid())
+    }
+
+    fn best_ask(&self, py: Python<'_>) -> f32 {
+        self.with(py, |depth| depth.best_ask())
+    }
+
+    fn best_bid_tick(&self, py: Python<'_>) -> i64 {
+        self.with(py, |depth| depth.best_bid_tick())
+    }
+
+    fn best_ask_tick(&self, py: Python<'_>) -> i64 {
+        self.with(py, |depth| depth.best_ask_tick())
+    }
+
+    fn tick_size(&self, py: Python<'_>) -> f32 {
+        self.with(py, |depth| depth.tick_size())
+    }
+
+    fn lot_size(&self, py: Python<'_>) -> f32 {
+        self.with(py, |depth| depth.lot_size())
+    }
+
+    fn bid_qty_at_tick(&self, py: Python<'_>, price_tick: i64) -> f32 {
+        self.with(py, |depth| depth.bid_qty_at_tick(price_tick))
+    }
+
+    fn ask_qty_at_tick(&self, py: Python<'_>, price_tick: i64) -> f32 {
+        self.with(py, |depth| depth.ask_qty_at_tick(price_tick))
+    }
+}
+
+/// An order of [`PyHftBacktest`], as of when it's returned.
+#[pyclass(name = "Order", get_all)]
+#[derive(Clone)]
+pub struct PyOrder {
+    order_id: i64,
+    price: f32,
+    qty: f32,
+    leaves_qty: f32,
+    exec_price: f32,
+    exec_qty: f32,
+    side: i8,
+    order_type: u8,
+    time_in_force: u8,
+    status: u8,
+    req: u8,
+    maker: bool,
+    exch_timestamp: i64,
+    local_timestamp: i64,
+}
+
+impl From<&Order<QueuePos>> for PyOrder {
+    fn from(order: &Order<QueuePos>) -> Self {
+        Self {
+            order_id: order.order_id,
+            price: order.price(),
+            qty: order.qty,
+            leaves_qty: order.leaves_qty,
+            exec_price: order.exec_price(),
+            exec_qty: order.exec_qty,
+            side: order.side as i8,
+            order_type: order.order_type as u8,
+            time_in_force: order.time_in_force as u8,
+            status: order.status as u8,
+            req: order.req as u8,
+            maker: order.maker,
+            exch_timestamp: order.exch_timestamp,
+            local_timestamp: order.local_timestamp,
+        }
+    }
+}
+
+/// The Python module of the backtester, built by
+/// `maturin build --features hftbacktest-py,pyo3/extension-module`.
+///
+/// ```python
+/// from hftbacktest import BtAssetBuilder, BtBuilder, GTX, LIMIT
+///
+/// asset = (
+///     BtAssetBuilder()
+///     .data(['SOLUSDT_20240215.npz'])
+///     .intp_order_latency('latency_20240215.npz')
+///     .linear_asset(1.0)
+///     .power_prob_queue_model3(3.0)
+///     .tick_size(0.001)
+/// )
+/// hbt = BtBuilder().add(asset).build()
+/// while hbt.elapse(100_000_000):
+///     depth = hbt.depth(0)
+///     ...
+/// ```
+#[pymodule]
+fn hftbacktest(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyBtAssetBuilder>()?;
+    m.add_class::<PyBtBuilder>()?;
+    m.add_class::<PyHftBacktest>()?;
+    m.add_class::<PyMarketDepth>()?;
+    m.add_class::<PyOrder>()?;
+    m.add_class::<PyOrderRequest>()?;
+    m.add_class::<StateValues>()?;
+
+    m.add("BUY", Side::Buy as i8)?;
+    m.add("SELL", Side::Sell as i8)?;
+
+    m.add("GTC", TimeInForce::GTC as u8)?;
+    m.add("GTX", TimeInForce::GTX as u8)?;
+    m.add("FOK", TimeInForce::FOK as u8)?;
+    m.add("IOC", TimeInForce::IOC as u8)?;
+
+    m.add("LIMIT", OrdType::Limit as u8)?;
+    m.add("MARKET", OrdType::Market as u8)?;
+    m.add("STOP_MARKET", OrdType::StopMarket as u8)?;
+    m.add("STOP_LIMIT", OrdType::StopLimit as u8)?;
+    m.add("TAKE_PROFIT_MARKET", OrdType::TakeProfitMarket as u8)?;
+    m.add("TAKE_PROFIT_LIMIT", OrdType::TakeProfitLimit as u8)?;
+
+    m.add("NONE", Status::None as u8)?;
+    m.add("NEW", Status::New as u8)?;
+    m.add("EXPIRED", Status::Expired as u8)?;
+    m.add("FILLED", Status::Filled as u8)?;
+    m.add("CANCELED", Status::Canceled as u8)?;
+    m.add("PARTIALLY_FILLED", Status::PartiallyFilled as u8)?;
+    m.add("REJECTED", Status::Rejected as u8)?;
+    m.add("TOO_MANY_OPEN_ORDERS", Status::TooManyOpenOrders as u8)?;
+    m.add("REPLACED", Status::Replaced as u8)?;
+    Ok(())
+}
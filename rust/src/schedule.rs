@@ -0,0 +1,128 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Error as IoError, ErrorKind},
+};
+
+use chrono::{DateTime, Datelike, NaiveTime, Timelike, Utc, Weekday};
+
+#[derive(Clone, Debug)]
+struct DailyWindow {
+    // Indexed by the number of days from Monday.
+    days: [bool; 7],
+    start: u32,
+    end: u32,
+}
+
+impl DailyWindow {
+    fn new(days: &[Weekday], start: NaiveTime, end: NaiveTime) -> Self {
+        let mut days_ = [false; 7];
+        for day in days {
+            days_[day.num_days_from_monday() as usize] = true;
+        }
+        Self {
+            days: days_,
+            start: start.num_seconds_from_midnight(),
+            end: end.num_seconds_from_midnight(),
+        }
+    }
+
+    fn contains(&self, dt: &DateTime<Utc>) -> bool {
+        let day = dt.weekday().num_days_from_monday() as usize;
+        let secs = dt.num_seconds_from_midnight();
+        if self.start <= self.end {
+            self.days[day] && secs >= self.start && secs < self.end
+        } else {
+            // The window wraps around midnight, so it belongs to the day it starts.
+            let prev_day = (day + 6) % 7;
+            (self.days[day] && secs >= self.start) || (self.days[prev_day] && secs < self.end)
+        }
+    }
+}
+
+/// Gates order submission by UTC time windows. The engine consults the schedule when the
+/// strategy submits an order, and rejects the submission if the schedule is not active.
+/// Cancellation is always allowed.
+///
+/// The schedule is active at a given time if it falls within any of the allowed windows, or if
+/// there is no allowed window, and it doesn't fall within any of the blocked windows or the
+/// blackout periods.
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    allowed: Vec<DailyWindow>,
+    blocked: Vec<DailyWindow>,
+    blackouts: Vec<(i64, i64)>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Allows trading from `start` to `end` UTC on the given days. If `start` is later than
+    /// `end`, the window wraps around midnight.
+    pub fn allow(mut self, days: &[Weekday], start: NaiveTime, end: NaiveTime) -> Self {
+        self.allowed.push(DailyWindow::new(days, start, end));
+        self
+    }
+
+    /// Blocks trading from `start` to `end` UTC on the given days, such as around the funding
+    /// times. If `start` is later than `end`, the window wraps around midnight.
+    pub fn block(mut self, days: &[Weekday], start: NaiveTime, end: NaiveTime) -> Self {
+        self.blocked.push(DailyWindow::new(days, start, end));
+        self
+    }
+
+    /// Blocks trading from `start` to `end` in nanoseconds since the Unix epoch.
+    pub fn blackout(mut self, start: i64, end: i64) -> Self {
+        self.blackouts.push((start, end));
+        self
+    }
+
+    /// Loads the blackout periods from a calendar file, such as scheduled economic releases. Each
+    /// line consists of comma-separated start and end times, either in RFC 3339 or in nanoseconds
+    /// since the Unix epoch, optionally followed by a description. Empty lines and lines starting
+    /// with `#` are ignored.
+    pub fn calendar(mut self, path: &str) -> Result<Self, IoError> {
+        let file = File::open(path)?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split(',');
+            let start = parse_time(tokens.next().unwrap_or(""))?;
+            let end = parse_time(tokens.next().unwrap_or(""))?;
+            self.blackouts.push((start, end));
+        }
+        Ok(self)
+    }
+
+    /// Returns `true` if trading is allowed at the given timestamp in nanoseconds since the Unix
+    /// epoch.
+    pub fn is_active(&self, timestamp: i64) -> bool {
+        if self
+            .blackouts
+            .iter()
+            .any(|(start, end)| timestamp >= *start && timestamp < *end)
+        {
+            return false;
+        }
+        let dt = DateTime::from_timestamp_nanos(timestamp);
+        if self.blocked.iter().any(|window| window.contains(&dt)) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|window| window.contains(&dt))
+    }
+}
+
+pub(crate) fn parse_time(s: &str) -> Result<i64, IoError> {
+    let s = s.trim();
+    if let Ok(timestamp) = s.parse::<i64>() {
+        return Ok(timestamp);
+    }
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .and_then(|dt| dt.timestamp_nanos_opt())
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, format!("invalid time: {}", s)))
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+/// Returns the weight that an average keeps over `elapsed` nanoseconds under the decay rate.
+fn decay(rate: f64, elapsed: i64) -> f64 {
+    (-rate * elapsed.max(0) as f64).exp()
+}
+
+/// An exponentially weighted moving average and variance of a series that is sampled at
+/// irregular times, such as the mid price updated by the feed, which decays by the time elapsed
+/// rather than by the number of updates.
+///
+/// A series is regarded as holding each value until the next update, so a value is weighted by
+/// how long it has held. Consequently, the updates at the same timestamp don't bias the average
+/// towards the bursts of events, and only the last of them counts. Since the latest value holds
+/// until the next update, the average is read as of a timestamp by [`value_at`](Self::value_at),
+/// such as the current timestamp, which keeps decaying towards the latest value while no update
+/// arrives.
+#[derive(Clone, Debug)]
+pub struct Ewma {
+    rate: f64,
+    timestamp: i64,
+    last: f64,
+    mean: f64,
+    mean_sq: f64,
+    initialized: bool,
+}
+
+impl Ewma {
+    /// Constructs an average of which the weight of a value halves every `half_life`
+    /// nanoseconds.
+    pub fn new(half_life: i64) -> Self {
+        Self {
+            rate: std::f64::consts::LN_2 / half_life as f64,
+            timestamp: 0,
+            last: 0.0,
+            mean: 0.0,
+            mean_sq: 0.0,
+            initialized: false,
+        }
+    }
+
+    /// Updates the series with the value at the timestamp. The first value initializes the
+    /// average. An update older than the previous one is applied as if it had arrived at the
+    /// same timestamp.
+    pub fn update(&mut self, timestamp: i64, value: f64) {
+        if !self.initialized {
+            self.mean = value;
+            self.mean_sq = value * value;
+            self.timestamp = timestamp;
+            self.initialized = true;
+        } else {
+            let (mean, mean_sq) = self.moments_at(timestamp);
+            self.mean = mean;
+            self.mean_sq = mean_sq;
+            self.timestamp = self.timestamp.max(timestamp);
+        }
+        self.last = value;
+    }
+
+    fn moments_at(&self, timestamp: i64) -> (f64, f64) {
+        let w = decay(self.rate, timestamp - self.timestamp);
+        (
+            w * self.mean + (1.0 - w) * self.last,
+            w * self.mean_sq + (1.0 - w) * self.last * self.last,
+        )
+    }
+
+    /// Returns the average as of the timestamp, or `None` if no value has been updated.
+    pub fn value_at(&self, timestamp: i64) -> Option<f64> {
+        self.initialized.then(|| self.moments_at(timestamp).0)
+    }
+
+    /// Returns the variance as of the timestamp, or `None` if no value has been updated.
+    pub fn variance_at(&self, timestamp: i64) -> Option<f64> {
+        self.initialized.then(|| {
+            let (mean, mean_sq) = self.moments_at(timestamp);
+            (mean_sq - mean * mean).max(0.0)
+        })
+    }
+
+    /// Returns the last updated value, or `None` if no value has been updated.
+    pub fn last(&self) -> Option<f64> {
+        self.initialized.then_some(self.last)
+    }
+}
+
+/// An exponentially decaying sum of the quantities of the events, such as the traded volume,
+/// which decays by the time elapsed. Divided by the mean lifetime, `half_life / ln 2`, it's the
+/// rate of the quantities per nanosecond.
+#[derive(Clone, Debug)]
+pub struct DecayingSum {
+    rate: f64,
+    timestamp: i64,
+    sum: f64,
+}
+
+impl DecayingSum {
+    /// Constructs a sum of which the weight of a quantity halves every `half_life` nanoseconds.
+    pub fn new(half_life: i64) -> Self {
+        Self {
+            rate: std::f64::consts::LN_2 / half_life as f64,
+            timestamp: 0,
+            sum: 0.0,
+        }
+    }
+
+    /// Adds the quantity of the event at the timestamp. An event older than the previous one is
+    /// added as if it had occurred at the same timestamp.
+    pub fn add(&mut self, timestamp: i64, qty: f64) {
+        self.sum = self.value_at(timestamp) + qty;
+        self.timestamp = self.timestamp.max(timestamp);
+    }
+
+    /// Returns the sum as of the timestamp.
+    pub fn value_at(&self, timestamp: i64) -> f64 {
+        self.sum * decay(self.rate, timestamp - self.timestamp)
+    }
+}
+
+/// The simple moving average and variance of the values updated within the last `window`
+/// nanoseconds, of which each value counts equally regardless of the time between the updates.
+///
+/// The values drop out of the window as the time passes, by [`update`](Self::update) and
+/// [`advance`](Self::advance), so the window should be advanced to the current timestamp before
+/// reading the statistics while no update arrives.
+#[derive(Clone, Debug)]
+pub struct RollingWindow {
+    window: i64,
+    // The timestamps and the values in the window, in ascending order of the timestamps.
+    values: VecDeque<(i64, f64)>,
+    sum: f64,
+    sum_sq: f64,
+}
+
+impl RollingWindow {
+    pub fn new(window: i64) -> Self {
+        Self {
+            window,
+            values: VecDeque::new(),
+            sum: 0.0,
+            sum_sq: 0.0,
+        }
+    }
+
+    /// Updates the window with the value at the timestamp, which should be no older than the
+    /// previous update.
+    pub fn update(&mut self, timestamp: i64, value: f64) {
+        self.advance(timestamp);
+        self.values.push_back((timestamp, value));
+        self.sum += value;
+        self.sum_sq += value * value;
+    }
+
+    /// Drops the values that are out of the window as of the timestamp.
+    pub fn advance(&mut self, timestamp: i64) {
+        while let Some((ts, value)) = self.values.front().copied() {
+            if ts > timestamp - self.window {
+                break;
+            }
+            self.values.pop_front();
+            self.sum -= value;
+            self.sum_sq -= value * value;
+        }
+        if self.values.is_empty() {
+            // Clears the rounding errors accumulated by the subtractions.
+            self.sum = 0.0;
+            self.sum_sq = 0.0;
+        }
+    }
+
+    /// Returns the number of the values in the window.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the mean of the values in the window, or `None` if the window is empty.
+    pub fn mean(&self) -> Option<f64> {
+        (!self.values.is_empty()).then(|| self.sum / self.values.len() as f64)
+    }
+
+    /// Returns the population variance of the values in the window, or `None` if the window is
+    /// empty.
+    pub fn variance(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        Some((self.sum_sq / self.values.len() as f64 - mean * mean).max(0.0))
+    }
+}
@@ -0,0 +1,418 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use serde::Deserialize;
+
+use crate::{
+    backtest::{
+        assettype::LinearAsset,
+        backtest::MultiAssetMultiExchangeBacktest,
+        models::{ConstantLatency, RiskAdverseQueueModel},
+        reader::{Data, EXCH_EVENT, LOCAL_EVENT},
+        BtAssetBuilder,
+        BtBuilder,
+        DataSource,
+    },
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    error::BuildError,
+    ty::{
+        Event,
+        Order,
+        Status,
+        BUY,
+        DEPTH_CLEAR_EVENT,
+        DEPTH_EVENT,
+        DEPTH_SNAPSHOT_EVENT,
+        SELL,
+        TRADE_EVENT,
+    },
+    Interface,
+};
+
+/// The side of the trade initiator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeSide {
+    /// A buyer takes the asks.
+    Buy,
+    /// A seller takes the bids.
+    Sell,
+}
+
+/// A step of a [`Scenario`]. Steps can be deserialized from any serde format, such as JSON or
+/// YAML, in which each step is an externally tagged enum, for example, `{"trade": {"side":
+/// "buy", "px": 100.0, "qty": 1.0}}` or `{"advance": 1000000}`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// Replaces the whole book. The levels are `(price, quantity)` pairs.
+    Book {
+        bids: Vec<(f32, f32)>,
+        asks: Vec<(f32, f32)>,
+    },
+    /// Sets the quantity of a bid level; zero removes the level.
+    Bid {
+        px: f32,
+        qty: f32,
+    },
+    /// Sets the quantity of an ask level; zero removes the level.
+    Ask {
+        px: f32,
+        qty: f32,
+    },
+    Trade {
+        side: TradeSide,
+        px: f32,
+        qty: f32,
+    },
+    /// Advances the scenario time.
+    Advance(i64),
+    /// Stuffs quotes behind the best: `updates` depth updates spread evenly over `duration`,
+    /// alternating between the sides, which place `qty` on the `levels` levels behind the best
+    /// bid `bid` and the best ask `ask` in turn, and cancel them on the next pass over the levels.
+    /// The best is untouched. The scenario time advances by `duration`.
+    Stuff {
+        bid: f32,
+        ask: f32,
+        qty: f32,
+        levels: usize,
+        updates: usize,
+        duration: i64,
+    },
+    /// Flips the best rapidly: `flips` depth updates spread evenly over `duration`, which
+    /// alternately remove and restore with `qty` the best bid `bid`, then the best ask `ask`, so
+    /// that the best moves back and forth to the next level, which should exist. An odd number of
+    /// flips leaves the last flipped level removed. The scenario time advances by `duration`.
+    Flip {
+        bid: f32,
+        ask: f32,
+        qty: f32,
+        flips: usize,
+        duration: i64,
+    },
+}
+
+/// A scripted market scenario for unit-testing strategy logic deterministically. The scenario
+/// is turned into the feed data of a single asset and run through the real backtest engine, so
+/// the strategy's orders are filled by the same exchange simulation as in a regular backtest.
+///
+/// The steps are stamped with the scenario time, which starts at `start` and only moves forward
+/// by [`Step::Advance`]. The exchange sees each step at the scenario time and the strategy at the
+/// scenario time plus the feed latency. Orders are filled by [`RiskAdverseQueueModel`], so an
+/// order at a level is filled only after the quantity ahead of it is traded.
+///
+/// ```ignore
+/// let mut hbt = Scenario::new(0.1, 1.0)
+///     .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+///     .advance(1_000_000)
+///     .trade(TradeSide::Sell, 99.9, 10.0)
+///     .advance(1_000_000)
+///     .backtest()?;
+/// hbt.elapse(500_000)?;
+/// hbt.submit_buy_order(0, 1, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, false)?;
+/// hbt.elapse(1_000_000)?;
+/// assert_eq!(filled_orders(&hbt, 0).len(), 1);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    tick_size: f32,
+    lot_size: f32,
+    feed_latency: i64,
+    entry_latency: i64,
+    response_latency: i64,
+    ts: i64,
+    rows: Vec<Event>,
+    // The lowest bid and the highest ask ever set, up to which a book replacement clears.
+    low_bid: f32,
+    high_ask: f32,
+}
+
+impl Scenario {
+    pub fn new(tick_size: f32, lot_size: f32) -> Self {
+        Self {
+            tick_size,
+            lot_size,
+            feed_latency: 0,
+            entry_latency: 0,
+            response_latency: 0,
+            ts: 1,
+            rows: Vec::new(),
+            low_bid: f32::INFINITY,
+            high_ask: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Constructs a scenario from the steps, such as those deserialized from a file.
+    pub fn from_steps(tick_size: f32, lot_size: f32, steps: Vec<Step>) -> Self {
+        steps
+            .into_iter()
+            .fold(Self::new(tick_size, lot_size), |scenario, step| {
+                scenario.step(step)
+            })
+    }
+
+    /// Constructs a scenario from the steps in JSON.
+    pub fn from_json(tick_size: f32, lot_size: f32, json: &str) -> Result<Self, IoError> {
+        let steps = serde_json::from_str(json).map_err(|error| {
+            IoError::new(
+                ErrorKind::InvalidData,
+                format!("invalid scenario: {}", error),
+            )
+        })?;
+        Ok(Self::from_steps(tick_size, lot_size, steps))
+    }
+
+    /// Sets the start time of the scenario. This should be set before adding any step.
+    pub fn start(self, start: i64) -> Self {
+        Self { ts: start, ..self }
+    }
+
+    /// Sets the feed latency from the exchange to the strategy, which applies to all the steps,
+    /// including those added before. The default is zero.
+    pub fn feed_latency(mut self, feed_latency: i64) -> Self {
+        for row in self.rows.iter_mut() {
+            row.local_ts = row.exch_ts + feed_latency;
+        }
+        Self {
+            feed_latency,
+            ..self
+        }
+    }
+
+    /// Sets the constant order entry and response latencies. The defaults are zero.
+    pub fn order_latency(self, entry_latency: i64, response_latency: i64) -> Self {
+        Self {
+            entry_latency,
+            response_latency,
+            ..self
+        }
+    }
+
+    fn push(&mut self, ev: i64, px: f32, qty: f32) {
+        self.push_at(self.ts, ev, px, qty);
+    }
+
+    fn push_at(&mut self, ts: i64, ev: i64, px: f32, qty: f32) {
+        self.rows.push(Event {
+            ev: ev | EXCH_EVENT | LOCAL_EVENT,
+            exch_ts: ts,
+            local_ts: ts + self.feed_latency,
+            px,
+            qty,
+        });
+    }
+
+    // The timestamp of the `i`-th of the `n` updates spread evenly over `duration`.
+    fn spread_ts(&self, i: usize, n: usize, duration: i64) -> i64 {
+        self.ts + duration * i as i64 / n as i64
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        match step {
+            Step::Book { bids, asks } => {
+                for (px, _) in bids.iter() {
+                    self.low_bid = self.low_bid.min(*px);
+                }
+                for (px, _) in asks.iter() {
+                    self.high_ask = self.high_ask.max(*px);
+                }
+                if self.low_bid.is_finite() {
+                    self.push(DEPTH_CLEAR_EVENT | BUY, self.low_bid, 0.0);
+                }
+                if self.high_ask.is_finite() {
+                    self.push(DEPTH_CLEAR_EVENT | SELL, self.high_ask, 0.0);
+                }
+                for (px, qty) in bids {
+                    self.push(DEPTH_SNAPSHOT_EVENT | BUY, px, qty);
+                }
+                for (px, qty) in asks {
+                    self.push(DEPTH_SNAPSHOT_EVENT | SELL, px, qty);
+                }
+            }
+            Step::Bid { px, qty } => {
+                self.low_bid = self.low_bid.min(px);
+                self.push(DEPTH_EVENT | BUY, px, qty);
+            }
+            Step::Ask { px, qty } => {
+                self.high_ask = self.high_ask.max(px);
+                self.push(DEPTH_EVENT | SELL, px, qty);
+            }
+            Step::Trade { side, px, qty } => {
+                let side = match side {
+                    TradeSide::Buy => BUY,
+                    TradeSide::Sell => SELL,
+                };
+                self.push(TRADE_EVENT | side, px, qty);
+            }
+            Step::Advance(duration) => self.ts += duration,
+            Step::Stuff {
+                bid,
+                ask,
+                qty,
+                levels,
+                updates,
+                duration,
+            } => {
+                let levels = levels.max(1);
+                self.low_bid = self.low_bid.min(bid - levels as f32 * self.tick_size);
+                self.high_ask = self.high_ask.max(ask + levels as f32 * self.tick_size);
+                for i in 0..updates {
+                    let ts = self.spread_ts(i, updates, duration);
+                    // Each pass over the levels adds the quotes and the next pass cancels them.
+                    let level = (i / 2) % levels + 1;
+                    let qty = if (i / 2 / levels) % 2 == 0 { qty } else { 0.0 };
+                    if i % 2 == 0 {
+                        self.push_at(
+                            ts,
+                            DEPTH_EVENT | BUY,
+                            bid - level as f32 * self.tick_size,
+                            qty,
+                        );
+                    } else {
+                        self.push_at(
+                            ts,
+                            DEPTH_EVENT | SELL,
+                            ask + level as f32 * self.tick_size,
+                            qty,
+                        );
+                    }
+                }
+                self.ts += duration;
+            }
+            Step::Flip {
+                bid,
+                ask,
+                qty,
+                flips,
+                duration,
+            } => {
+                self.low_bid = self.low_bid.min(bid);
+                self.high_ask = self.high_ask.max(ask);
+                for i in 0..flips {
+                    let ts = self.spread_ts(i, flips, duration);
+                    let qty = if i % 2 == 0 { 0.0 } else { qty };
+                    if (i / 2) % 2 == 0 {
+                        self.push_at(ts, DEPTH_EVENT | BUY, bid, qty);
+                    } else {
+                        self.push_at(ts, DEPTH_EVENT | SELL, ask, qty);
+                    }
+                }
+                self.ts += duration;
+            }
+        }
+        self
+    }
+
+    pub fn book(self, bids: &[(f32, f32)], asks: &[(f32, f32)]) -> Self {
+        self.step(Step::Book {
+            bids: bids.to_vec(),
+            asks: asks.to_vec(),
+        })
+    }
+
+    pub fn bid(self, px: f32, qty: f32) -> Self {
+        self.step(Step::Bid { px, qty })
+    }
+
+    pub fn ask(self, px: f32, qty: f32) -> Self {
+        self.step(Step::Ask { px, qty })
+    }
+
+    pub fn trade(self, side: TradeSide, px: f32, qty: f32) -> Self {
+        self.step(Step::Trade { side, px, qty })
+    }
+
+    pub fn advance(self, duration: i64) -> Self {
+        self.step(Step::Advance(duration))
+    }
+
+    /// Stuffs quotes behind the best. See [`Step::Stuff`].
+    pub fn stuff(
+        self,
+        bid: f32,
+        ask: f32,
+        qty: f32,
+        levels: usize,
+        updates: usize,
+        duration: i64,
+    ) -> Self {
+        self.step(Step::Stuff {
+            bid,
+            ask,
+            qty,
+            levels,
+            updates,
+            duration,
+        })
+    }
+
+    /// Flips the best rapidly. See [`Step::Flip`].
+    pub fn flip(self, bid: f32, ask: f32, qty: f32, flips: usize, duration: i64) -> Self {
+        self.step(Step::Flip {
+            bid,
+            ask,
+            qty,
+            flips,
+            duration,
+        })
+    }
+
+    /// Returns the feed data rows of the scenario.
+    pub fn rows(&self) -> &[Event] {
+        &self.rows
+    }
+
+    /// Builds a backtest of a single asset, asset number 0, that replays the scenario.
+    pub fn backtest(
+        &self,
+    ) -> Result<MultiAssetMultiExchangeBacktest<f32, HashMapMarketDepth>, BuildError> {
+        let tick_size = self.tick_size;
+        let lot_size = self.lot_size;
+        BtBuilder::new()
+            .add(
+                BtAssetBuilder::new()
+                    .data(vec![DataSource::Array(Data::from_rows(&self.rows))])
+                    .latency_model(ConstantLatency::new(
+                        self.entry_latency,
+                        self.response_latency,
+                    ))
+                    .asset_type(LinearAsset::new(1.0))
+                    .queue_model(RiskAdverseQueueModel::new())
+                    .depth(move || HashMapMarketDepth::new(tick_size, lot_size))
+                    .build()?,
+            )
+            .build()
+    }
+}
+
+fn orders_with<Q, MD, I>(hbt: &I, asset_no: usize, status: &[Status]) -> Vec<Order<Q>>
+where
+    Q: Clone,
+    I: Interface<Q, MD>,
+{
+    let mut orders: Vec<Order<Q>> = hbt
+        .orders(asset_no)
+        .values()
+        .filter(|order| status.contains(&order.status))
+        .cloned()
+        .collect();
+    orders.sort_by_key(|order| order.order_id);
+    orders
+}
+
+/// Returns the orders that are working in the market, sorted by order id.
+pub fn working_orders<Q, MD, I>(hbt: &I, asset_no: usize) -> Vec<Order<Q>>
+where
+    Q: Clone,
+    I: Interface<Q, MD>,
+{
+    orders_with(hbt, asset_no, &[Status::New, Status::PartiallyFilled])
+}
+
+/// Returns the fully filled orders, sorted by order id.
+pub fn filled_orders<Q, MD, I>(hbt: &I, asset_no: usize) -> Vec<Order<Q>>
+where
+    Q: Clone,
+    I: Interface<Q, MD>,
+{
+    orders_with(hbt, asset_no, &[Status::Filled])
+}
@@ -1,9 +1,24 @@
-use std::{
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+use core::{
     any::Any,
     fmt::{Debug, Formatter},
-    sync::Arc,
 };
 
+use crate::{depth::Tick, error::InvalidTransition};
+
+/// The state values of an asset, such as the position and the balance.
+#[derive(Debug)]
+#[repr(C)]
+#[cfg_attr(feature = "hftbacktest-py", pyo3::pyclass(get_all))]
+pub struct StateValues {
+    pub position: f64,
+    pub balance: f64,
+    pub fee: f64,
+    pub trade_num: i32,
+    pub trade_qty: f64,
+    pub trade_amount: f64,
+}
+
 /// Error type which is assigned to [`Error`].
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 #[repr(i64)]
@@ -54,7 +69,54 @@ pub enum LiveEvent {
     Trade(Trade),
     Order(OrderResponse),
     Position(Position),
+    Balance(Balance),
     Error(Error),
+    Synced(Synced),
+    RateLimit(RateLimitStatus),
+}
+
+/// Which is delivered to the strategy first when an order response and a market data update
+/// arrive at the same time: at the same local timestamp in backtesting, or together in the same
+/// batch of received events in live trading.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ResponsePriority {
+    /// Market data updates are delivered first, so the strategy sees the order response against
+    /// the updated market.
+    MarketDataFirst,
+    /// Order responses, including the position updates, are delivered first, so the strategy sees
+    /// its order state updated before reacting to the market.
+    OrderResponseFirst,
+}
+
+/// The price at which a position is marked for its unrealized PnL and the equity, by which the
+/// drawdowns can differ materially.
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub enum MarkSource {
+    /// The mid price of the best bid and the best ask.
+    #[default]
+    Mid,
+    /// The price of the last trade in the market.
+    LastTrade,
+    /// The venue's mark price, such as that of a perpetual futures contract, which is available
+    /// only if the connector provides it. See [`Position::mark_price`].
+    MarkPrice,
+}
+
+impl MarkSource {
+    /// Returns the price to mark at among the given prices, each of which is `None` if it isn't
+    /// available. If the price of the source isn't available, it falls back to the mid price.
+    pub fn select(
+        &self,
+        mid: Option<f64>,
+        last_trade: Option<f64>,
+        mark_price: Option<f64>,
+    ) -> Option<f64> {
+        match self {
+            MarkSource::Mid => mid,
+            MarkSource::LastTrade => last_trade.or(mid),
+            MarkSource::MarkPrice => mark_price.or(mid),
+        }
+    }
 }
 
 pub const BUY: i64 = 1 << 29;
@@ -64,6 +126,14 @@ pub const DEPTH_EVENT: i64 = 1;
 pub const TRADE_EVENT: i64 = 2;
 pub const DEPTH_CLEAR_EVENT: i64 = 3;
 pub const DEPTH_SNAPSHOT_EVENT: i64 = 4;
+/// Adds an order to the book in market-by-order data. See [`L3Event`].
+pub const ADD_ORDER_EVENT: i64 = 10;
+/// Deletes an order from the book in market-by-order data.
+pub const CANCEL_ORDER_EVENT: i64 = 11;
+/// Changes the price and the quantity of an order in the book in market-by-order data.
+pub const MODIFY_ORDER_EVENT: i64 = 12;
+/// Executes an order in the book by the quantity in market-by-order data.
+pub const FILL_EVENT: i64 = 13;
 pub const USER_DEFINED_EVENT: i64 = 100;
 
 pub trait AsStr {
@@ -81,6 +151,22 @@ pub struct Event {
     pub qty: f32,
 }
 
+/// Market-by-order exchange event data, in which each row is a message about an individual order
+/// in the book identified by `order_id`: [`ADD_ORDER_EVENT`], [`CANCEL_ORDER_EVENT`],
+/// [`MODIFY_ORDER_EVENT`], or [`FILL_EVENT`], combined with the side of the order in the book,
+/// [`BUY`] or [`SELL`]. For a modify, `px` and `qty` are the new price and quantity, and for a
+/// fill, `px` is the execution price and `qty` the executed quantity.
+#[derive(Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct L3Event {
+    pub ev: i64,
+    pub exch_ts: i64,
+    pub local_ts: i64,
+    pub order_id: i64,
+    pub px: f32,
+    pub qty: f32,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Depth {
     pub asset_no: usize,
@@ -90,6 +176,41 @@ pub struct Depth {
     pub asks: Vec<(f32, f32)>,
 }
 
+/// What a connector reports as synced by [`LiveEvent::Synced`].
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SyncKind {
+    /// The asset's book has received a snapshot and at least one diff consistent with it.
+    Book,
+    /// The user data stream of the asset's account is confirmed, so that the order, position,
+    /// and balance updates are delivered.
+    UserData,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Synced {
+    pub asset_no: usize,
+    pub kind: SyncKind,
+}
+
+/// The usage of the venue's API rate limits by the connector of an asset, reported by
+/// [`LiveEvent::RateLimit`] as it changes. See
+/// [`RateLimiter`](crate::connector::ratelimit::RateLimiter).
+#[derive(Clone, PartialEq, Debug)]
+pub struct RateLimitStatus {
+    pub asset_no: usize,
+    /// The highest ratio of the count to the limit among the rate limits.
+    pub usage: f64,
+    /// The number of the orders that can be placed within the order rate limits, or `None` if
+    /// there's no order rate limit.
+    pub order_budget: Option<u32>,
+    /// The timestamp at which the earliest of the windows of the rate limits resets, after which
+    /// the usage and the order budget are no longer accurate.
+    pub reset_at: i64,
+    /// The timestamp until which the requests are held back since the venue has told to back
+    /// off, if it has.
+    pub blocked_until: Option<i64>,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Trade {
     pub asset_no: usize,
@@ -100,11 +221,52 @@ pub struct Trade {
     pub qty: f32,
 }
 
+/// An external event, such as a news release or a scheduled announcement, which is injected into
+/// the backtest and delivered to the strategy once the local time reaches its timestamp.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExternalEvent {
+    /// Local timestamp at which the strategy becomes aware of the event.
+    pub timestamp: i64,
+    pub name: String,
+    pub value: f64,
+}
+
+/// A manual adjustment of an asset's position and balance, such as a starting inventory or an
+/// over-the-counter block trade, which is injected into the backtest and applied once the local
+/// time reaches its timestamp. It is accounted for separately from the fills.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Adjustment {
+    /// Local timestamp at which the adjustment is applied.
+    pub timestamp: i64,
+    pub asset_no: usize,
+    /// The change in the position.
+    pub position: f64,
+    /// The change in the balance. For an inventory transferred at a price, this is the change by
+    /// a fill of it, which is
+    /// [`AssetType::balance_change`](crate::backtest::assettype::AssetType::balance_change).
+    pub balance: f64,
+    pub note: String,
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct Position {
     pub asset_no: usize,
     pub symbol: String,
     pub qty: f64,
+    /// The average entry price, if the connector provides it.
+    pub entry_price: Option<f64>,
+    /// The venue's mark price at the time of the update, if the connector provides it.
+    pub mark_price: Option<f64>,
+}
+
+/// The balance of the margin asset that an asset is settled in, such as the wallet balance in
+/// USDT for a USDT-margined contract.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Balance {
+    pub asset_no: usize,
+    /// The margin asset.
+    pub asset: String,
+    pub balance: f64,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
@@ -152,9 +314,69 @@ pub enum Status {
     Filled = 3,
     Canceled = 4,
     PartiallyFilled = 5,
+    Rejected = 6,
+    /// Rejected since the open orders of the asset have reached the venue's cap.
+    TooManyOpenOrders = 7,
+    /// The request to modify the order, which is only used in [`Order::req`].
+    Replaced = 8,
     Unsupported = 255,
 }
 
+/// Order lifecycle state machine shared by the backtest and the live bot.
+///
+/// `None` is the initial status until the exchange acknowledges the order. `New` and
+/// `PartiallyFilled` are the active statuses, and `Filled`, `Canceled`, `Expired`, `Rejected`, and
+/// `TooManyOpenOrders` are the terminal statuses; once an order reaches a terminal status it cannot
+/// change anymore. A partially filled order cannot go back to `New`.
+/// Pending requests are tracked separately through [`Order::req`], which is set to `Rejected` in
+/// backtesting when a cancel or a modification is rejected, such as because the order has been
+/// filled or expired while the request was in flight.
+impl Status {
+    pub fn is_active(&self) -> bool {
+        *self == Status::New || *self == Status::PartiallyFilled
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        *self == Status::Filled
+            || *self == Status::Canceled
+            || *self == Status::Expired
+            || *self == Status::Rejected
+            || *self == Status::TooManyOpenOrders
+    }
+
+    /// Checks if the order can move from this status to the given status. Moving to the same
+    /// status is allowed since the same update can be delivered more than once.
+    pub fn can_transition_to(&self, next: Status) -> bool {
+        if *self == next {
+            return *self != Status::Unsupported;
+        }
+        match self {
+            Status::None => next != Status::Unsupported,
+            Status::New => {
+                next != Status::None
+                    && next != Status::Rejected
+                    && next != Status::TooManyOpenOrders
+                    && next != Status::Unsupported
+            }
+            Status::PartiallyFilled => {
+                next == Status::Filled || next == Status::Canceled || next == Status::Expired
+            }
+            _ => false,
+        }
+    }
+
+    pub fn transition(&self, next: Status) -> Result<Status, InvalidTransition> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(InvalidTransition {
+                from: *self,
+                to: next,
+            })
+        }
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 #[repr(u8)]
 pub enum TimeInForce {
@@ -182,14 +404,43 @@ impl AsStr for TimeInForce {
 pub enum OrdType {
     Limit = 0,
     Market = 1,
+    /// A stop order that becomes a market order once triggered. A buy stop order is triggered
+    /// when the price rises to its trigger price, and a sell stop order when the price falls to
+    /// it.
+    StopMarket = 2,
+    /// A stop order that becomes a limit order once triggered.
+    StopLimit = 3,
+    /// A take-profit order that becomes a market order once triggered. A buy take-profit order is
+    /// triggered when the price falls to its trigger price, and a sell take-profit order when the
+    /// price rises to it.
+    TakeProfitMarket = 4,
+    /// A take-profit order that becomes a limit order once triggered.
+    TakeProfitLimit = 5,
     Unsupported = 255,
 }
 
+impl OrdType {
+    /// Returns `true` if the order rests untriggered until the price reaches its trigger price.
+    pub fn is_trigger(&self) -> bool {
+        matches!(
+            self,
+            OrdType::StopMarket
+                | OrdType::StopLimit
+                | OrdType::TakeProfitMarket
+                | OrdType::TakeProfitLimit
+        )
+    }
+}
+
 impl AsStr for OrdType {
     fn as_str(&self) -> &'static str {
         match self {
             OrdType::Limit => "LIMIT",
             OrdType::Market => "MARKET",
+            OrdType::StopMarket => "STOP_MARKET",
+            OrdType::StopLimit => "STOP",
+            OrdType::TakeProfitMarket => "TAKE_PROFIT_MARKET",
+            OrdType::TakeProfitLimit => "TAKE_PROFIT",
             OrdType::Unsupported => panic!("OrdType::Unsupported"),
         }
     }
@@ -202,7 +453,9 @@ where
 {
     pub qty: f32,
     pub leaves_qty: f32,
-    pub price_tick: i32,
+    /// The price in ticks, which is `i64` regardless of the tick type of the market depth, so
+    /// that it can hold the ticks of any depth. See [`Tick`](crate::depth::Tick).
+    pub price_tick: i64,
     pub tick_size: f32,
     pub side: Side,
     pub time_in_force: TimeInForce,
@@ -210,12 +463,20 @@ where
     pub status: Status,
     pub local_timestamp: i64,
     pub req: Status,
-    pub exec_price_tick: i32,
+    /// The price in ticks at which the order was last executed, in `i64` as
+    /// [`price_tick`](Self::price_tick).
+    pub exec_price_tick: i64,
     pub exec_qty: f32,
     pub order_id: i64,
     pub q: Q,
     pub maker: bool,
     pub order_type: OrdType,
+    /// The one-cancels-other group the order belongs to. When an order of a group is filled, the
+    /// other orders of the group are canceled.
+    pub oco_group: Option<i64>,
+    /// The trigger price of a trigger order, such as a stop or take-profit order, in ticks. It is
+    /// zero for the other orders.
+    pub trigger_price_tick: i64,
 }
 
 impl<Q> Order<Q>
@@ -224,7 +485,7 @@ where
 {
     pub fn new(
         order_id: i64,
-        price_tick: i32,
+        price_tick: i64,
         tick_size: f32,
         qty: f32,
         side: Side,
@@ -251,30 +512,42 @@ where
             q: Q::default(),
             maker: false,
             order_type,
+            oco_group: None,
+            trigger_price_tick: 0,
         }
     }
 
     pub fn price(&self) -> f32 {
-        self.price_tick as f32 * self.tick_size
+        self.price_tick.to_price(self.tick_size)
     }
 
     pub fn exec_price(&self) -> f32 {
-        self.exec_price_tick as f32 * self.tick_size
+        self.exec_price_tick.to_price(self.tick_size)
     }
 
     pub fn cancellable(&self) -> bool {
-        (self.status == Status::New || self.status == Status::PartiallyFilled)
-            && self.req == Status::None
+        self.status.is_active() && self.req == Status::None
     }
 
     pub fn active(&self) -> bool {
-        self.status == Status::New || self.status == Status::PartiallyFilled
+        self.status.is_active()
+    }
+
+    pub fn terminal(&self) -> bool {
+        self.status.is_terminal()
     }
 
     pub fn pending(&self) -> bool {
         self.req != Status::None
     }
 
+    /// Moves the order to the given status, refusing the move that the order lifecycle doesn't
+    /// allow. See [`Status::can_transition_to`].
+    pub fn set_status(&mut self, status: Status) -> Result<(), InvalidTransition> {
+        self.status = self.status.transition(status)?;
+        Ok(())
+    }
+
     pub fn update(&mut self, order: &Order<Q>) {
         self.qty = order.qty;
         self.leaves_qty = order.leaves_qty;
@@ -305,7 +578,7 @@ impl<Q> Debug for Order<Q>
 where
     Q: Sized + Clone,
 {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Order")
             .field("qty", &self.qty)
             .field("leaves_qty", &self.leaves_qty)
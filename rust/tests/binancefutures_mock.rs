@@ -0,0 +1,128 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use hftbacktest::{
+    connector::{
+        binancefutures::{mock::MockServer, BinanceFutures},
+        Connector,
+    },
+    ty::{LiveEvent, OrdType, Order, Side, Status, SyncKind, Synced, TimeInForce},
+};
+
+const SYMBOL: &str = "BTCUSDT";
+const TICK_SIZE: f32 = 0.1;
+
+/// Waits for the next response to the order, skipping the other events.
+fn recv_order(rx: &Receiver<LiveEvent>, order_id: i64) -> Order<()> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    tokio::task::block_in_place(|| loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(timeout) {
+            Ok(LiveEvent::Order(resp)) if resp.order.order_id == order_id => return resp.order,
+            Ok(_) => {}
+            Err(error) => panic!("no response to the order {order_id}: {error}"),
+        }
+    })
+}
+
+/// Waits until the order's status is `status`, as the REST response and the user data stream
+/// both report the order.
+fn wait_status(rx: &Receiver<LiveEvent>, order_id: i64, status: Status) -> Order<()> {
+    loop {
+        let order = recv_order(rx, order_id);
+        if order.status == status {
+            return order;
+        }
+    }
+}
+
+fn buy_order(order_id: i64, price: f32, qty: f32) -> Order<()> {
+    let mut order = Order::new(
+        order_id,
+        (price / TICK_SIZE).round() as i64,
+        TICK_SIZE,
+        qty,
+        Side::Buy,
+        OrdType::Limit,
+        TimeInForce::GTC,
+    );
+    order.req = Status::New;
+    order
+}
+
+async fn connect() -> (
+    MockServer,
+    BinanceFutures,
+    Sender<LiveEvent>,
+    Receiver<LiveEvent>,
+) {
+    let mock = MockServer::start("key", "secret").await.unwrap();
+    let mut connector =
+        BinanceFutures::new(&mock.stream_url(), &mock.api_url(), "test", "key", "secret");
+    connector
+        .add(0, SYMBOL.to_string(), TICK_SIZE, 0.001)
+        .unwrap();
+    let (tx, rx) = channel();
+    connector.run(tx.clone()).unwrap();
+    // Waits until the user data stream is connected, so that the order updates are streamed.
+    tokio::task::block_in_place(|| loop {
+        match rx.recv_timeout(Duration::from_secs(10)) {
+            Ok(LiveEvent::Synced(Synced {
+                kind: SyncKind::UserData,
+                ..
+            })) => break,
+            Ok(_) => {}
+            Err(error) => panic!("the user data stream isn't connected: {error}"),
+        }
+    });
+    (mock, connector, tx, rx)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submit_fill_and_cancel() {
+    let (mock, connector, tx, rx) = connect().await;
+
+    connector
+        .submit(0, buy_order(1, 100.0, 2.0), tx.clone())
+        .unwrap();
+    let order = wait_status(&rx, 1, Status::New);
+    assert_eq!(order.price_tick, 1000);
+    assert_eq!(order.leaves_qty, 2.0);
+
+    let client_order_id = mock.orders()[0].client_order_id.clone();
+    assert!(mock.fill(&client_order_id, 0.5, 100.0));
+    let order = wait_status(&rx, 1, Status::PartiallyFilled);
+    assert_eq!(order.exec_qty, 0.5);
+
+    connector.cancel(0, order, tx.clone()).unwrap();
+    let order = wait_status(&rx, 1, Status::Canceled);
+    assert_eq!(order.req, Status::None);
+
+    let mock_order = &mock.orders()[0];
+    assert_eq!(mock_order.status, "CANCELED");
+    assert_eq!(mock_order.executed_qty, 0.5);
+    let requests = mock.requests();
+    assert!(requests.contains(&"POST /fapi/v1/order".to_string()));
+    assert!(requests.contains(&"DELETE /fapi/v1/order".to_string()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fill_the_whole_order() {
+    let (mock, connector, tx, rx) = connect().await;
+
+    connector
+        .submit(0, buy_order(7, 99.5, 1.0), tx.clone())
+        .unwrap();
+    wait_status(&rx, 7, Status::New);
+
+    let client_order_id = mock.orders()[0].client_order_id.clone();
+    assert!(mock.fill(&client_order_id, 1.0, 99.5));
+    let order = wait_status(&rx, 7, Status::Filled);
+    assert_eq!(order.leaves_qty, 0.0);
+    assert_eq!(order.exec_price_tick, 995);
+
+    // The filled order can't be filled again.
+    assert!(!mock.fill(&client_order_id, 1.0, 99.5));
+}
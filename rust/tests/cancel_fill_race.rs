@@ -0,0 +1,84 @@
+use hftbacktest::{
+    backtest::{
+        assettype::LinearAsset,
+        models::{LatencyModel, RiskAdverseQueueModel},
+        reader::Data,
+        BtAssetBuilder,
+        BtBuilder,
+        DataSource,
+        Error,
+    },
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    testing::{Scenario, TradeSide},
+    ty::{OrdType, Order, Status, TimeInForce},
+    Interface,
+};
+
+const FILL_TS: i64 = 1_000;
+const SLOW_RESPONSE: i64 = 1_000;
+const FAST_RESPONSE: i64 = 10;
+
+/// Responds slowly only to the fill, so that the cancel reject, sent later, would overtake the
+/// fill if the responses for the same order weren't kept in order.
+#[derive(Clone, Debug)]
+struct SlowFillLatency;
+
+impl LatencyModel for SlowFillLatency {
+    fn entry<Q: Clone>(&mut self, _timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        Ok(10)
+    }
+
+    fn response<Q: Clone>(&mut self, timestamp: i64, _order: &Order<Q>) -> Result<i64, Error> {
+        if timestamp == FILL_TS {
+            Ok(SLOW_RESPONSE)
+        } else {
+            Ok(FAST_RESPONSE)
+        }
+    }
+}
+
+#[test]
+fn cancel_reject_follows_fill() {
+    let scenario = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+        .advance(FILL_TS - 1)
+        .trade(TradeSide::Sell, 99.9, 10.0)
+        .advance(1_000_000)
+        // Keeps the feed running after the responses.
+        .ask(100.0, 5.0);
+    let mut hbt = BtBuilder::new()
+        .add(
+            BtAssetBuilder::new()
+                .data(vec![DataSource::Array(Data::from_rows(scenario.rows()))])
+                .latency_model(SlowFillLatency)
+                .asset_type(LinearAsset::new(1.0))
+                .queue_model(RiskAdverseQueueModel::new())
+                .depth(|| HashMapMarketDepth::new(0.1, 1.0))
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap();
+
+    hbt.elapse(100).unwrap();
+    hbt.submit_buy_order(0, 1, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+        .unwrap();
+    // The cancel reaches the exchange right after the order is filled by the trade.
+    hbt.elapse(FILL_TS - 5 - hbt.current_timestamp()).unwrap();
+    hbt.cancel(0, 1, false).unwrap();
+
+    // Neither the fill nor the reject arrives until the fill's slow response does, even though
+    // the reject is sent later with the fast response latency.
+    hbt.elapse(FILL_TS + SLOW_RESPONSE - 1 - hbt.current_timestamp())
+        .unwrap();
+    let order = hbt.orders(0).get(&1).unwrap();
+    assert_eq!(order.status, Status::New);
+    assert_eq!(order.req, Status::Canceled);
+
+    // The fill arrives first and then the reject, which only marks the request as rejected.
+    hbt.elapse(1).unwrap();
+    let order = hbt.orders(0).get(&1).unwrap();
+    assert_eq!(order.status, Status::Filled);
+    assert_eq!(order.req, Status::Rejected);
+    assert_eq!(order.exec_qty, 1.0);
+}
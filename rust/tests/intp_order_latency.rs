@@ -0,0 +1,62 @@
+use hftbacktest::{
+    backtest::{
+        models::{IntpOrderLatency, LatencyModel, OrderLatencyRow, OutOfRange},
+        reader::Data,
+    },
+    ty::{OrdType, Order, Side, TimeInForce},
+};
+
+fn row(req_timestamp: i64, exch_timestamp: i64, resp_timestamp: i64) -> OrderLatencyRow {
+    OrderLatencyRow {
+        req_timestamp,
+        exch_timestamp,
+        resp_timestamp,
+        reserved: 0,
+    }
+}
+
+fn entry(latency: &mut IntpOrderLatency, timestamp: i64) -> i64 {
+    let order = Order::<()>::new(
+        1,
+        1000,
+        0.1,
+        1.0,
+        Side::Buy,
+        OrdType::Limit,
+        TimeInForce::GTC,
+    );
+    latency.entry(timestamp, &order).unwrap()
+}
+
+/// The rejections, whose exchange timestamps are zero, at both edges of the data. The entry
+/// latency of a rejection is the negative of its round trip.
+fn rejections_at_edges() -> Data<OrderLatencyRow> {
+    Data::from_rows(&[
+        row(1_000, 0, 1_500),
+        row(2_000, 2_100, 2_200),
+        row(3_000, 3_100, 3_200),
+        row(4_000, 0, 4_700),
+    ])
+}
+
+#[test]
+fn rejection_at_first_row() {
+    let mut latency = IntpOrderLatency::new(rejections_at_edges(), OutOfRange::Clamp);
+    // Clamped to the first row before the range.
+    assert_eq!(entry(&mut latency, 500), -500);
+    assert_eq!(entry(&mut latency, 1_000), -500);
+}
+
+#[test]
+fn rejection_at_last_row() {
+    let mut latency = IntpOrderLatency::new(rejections_at_edges(), OutOfRange::Clamp);
+    // At the last row, and clamped to it after the range.
+    assert_eq!(entry(&mut latency, 4_000), -700);
+    assert_eq!(entry(&mut latency, 5_000), -700);
+}
+
+#[test]
+fn interpolates_between_accepted_rows() {
+    let mut latency = IntpOrderLatency::new(rejections_at_edges(), OutOfRange::Clamp);
+    assert_eq!(entry(&mut latency, 2_500), 100);
+}
@@ -0,0 +1,116 @@
+use hftbacktest::{
+    depth::MarketDepth,
+    testing::{filled_orders, working_orders, Scenario, TradeSide},
+    ty::{OrdType, TimeInForce},
+    Interface,
+};
+
+#[test]
+fn feed_latency_applies_to_all_steps() {
+    let scenario = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+        .advance(1_000)
+        .feed_latency(50)
+        .trade(TradeSide::Sell, 99.9, 1.0);
+    assert!(!scenario.rows().is_empty());
+    for row in scenario.rows() {
+        assert_eq!(row.local_ts, row.exch_ts + 50);
+    }
+}
+
+#[test]
+fn from_json_matches_builder() {
+    let json = r#"[
+        {"book": {"bids": [[99.9, 5.0]], "asks": [[100.0, 5.0]]}},
+        {"advance": 1000},
+        {"trade": {"side": "sell", "px": 99.9, "qty": 1.0}}
+    ]"#;
+    let from_json = Scenario::from_json(0.1, 1.0, json).unwrap();
+    let built = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+        .advance(1_000)
+        .trade(TradeSide::Sell, 99.9, 1.0);
+    assert_eq!(
+        format!("{:?}", from_json.rows()),
+        format!("{:?}", built.rows())
+    );
+    assert!(Scenario::from_json(0.1, 1.0, r#"[{"unknown": 1}]"#).is_err());
+}
+
+#[test]
+fn book_replaces_the_whole_book() {
+    let mut hbt = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0), (99.8, 5.0)], &[(100.0, 5.0), (100.1, 5.0)])
+        .advance(1_000)
+        .book(&[(99.5, 2.0)], &[(100.5, 2.0)])
+        .advance(1_000)
+        .ask(100.5, 2.0)
+        .backtest()
+        .unwrap();
+    hbt.elapse(1_500).unwrap();
+    let depth = hbt.depth(0);
+    assert_eq!(depth.best_bid(), 99.5);
+    assert_eq!(depth.best_ask(), 100.5);
+    assert_eq!(depth.bid_qty_at_tick(998), 0.0);
+}
+
+#[test]
+fn flip_moves_the_best_and_stuff_keeps_it() {
+    let mut hbt = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0), (99.8, 5.0)], &[(100.0, 5.0), (100.1, 5.0)])
+        .advance(1_000)
+        // Behind the second levels, which the flips move the best to.
+        .stuff(99.8, 100.1, 1.0, 3, 12, 1_000)
+        .flip(99.9, 100.0, 5.0, 4, 1_000)
+        .advance(1_000)
+        .ask(100.0, 5.0)
+        .backtest()
+        .unwrap();
+    hbt.elapse(1_900).unwrap();
+    let depth = hbt.depth(0);
+    assert_eq!(depth.best_bid(), 99.9);
+    assert_eq!(depth.best_ask(), 100.0);
+
+    // The best bid is removed by the first flip.
+    hbt.elapse(200).unwrap();
+    assert_eq!(hbt.depth(0).best_bid(), 99.8);
+
+    // The best ask is removed by the third flip.
+    hbt.elapse(500).unwrap();
+    let depth = hbt.depth(0);
+    assert_eq!(depth.best_bid(), 99.9);
+    assert_eq!(depth.best_ask(), 100.1);
+
+    // An even number of flips restores the best.
+    hbt.elapse(1_000).unwrap();
+    let depth = hbt.depth(0);
+    assert_eq!(depth.best_bid(), 99.9);
+    assert_eq!(depth.best_ask(), 100.0);
+}
+
+#[test]
+fn order_is_filled_after_the_queue_ahead() {
+    let mut hbt = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+        .advance(1_000)
+        .trade(TradeSide::Sell, 99.9, 3.0)
+        .advance(1_000)
+        .trade(TradeSide::Sell, 99.9, 3.0)
+        .advance(1_000)
+        .ask(100.0, 5.0)
+        .backtest()
+        .unwrap();
+    hbt.elapse(500).unwrap();
+    hbt.submit_buy_order(0, 1, 99.9, 1.0, TimeInForce::GTC, OrdType::Limit, false)
+        .unwrap();
+
+    // 3 of the 5 ahead are traded.
+    hbt.elapse(1_000).unwrap();
+    assert_eq!(working_orders(&hbt, 0).len(), 1);
+    assert!(filled_orders(&hbt, 0).is_empty());
+
+    // The rest ahead and then the order are traded.
+    hbt.elapse(1_000).unwrap();
+    assert!(working_orders(&hbt, 0).is_empty());
+    assert_eq!(filled_orders(&hbt, 0).len(), 1);
+}
@@ -0,0 +1,122 @@
+use hftbacktest::{
+    backtest::{
+        assettype::LinearAsset,
+        backtest::MultiAssetMultiExchangeBacktest,
+        models::{ConstantLatency, RiskAdverseQueueModel},
+        reader::Data,
+        BtAssetBuilder,
+        BtBuilder,
+        DataSource,
+        ExchangeKind,
+    },
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    testing::Scenario,
+    ty::{OrdType, Status, TimeInForce},
+    Interface,
+};
+
+const EXCHANGES: [ExchangeKind; 2] = [
+    ExchangeKind::NoPartialFillExchange,
+    ExchangeKind::PartialFillExchange,
+];
+
+/// 2 at 100.0 and 2 at 100.1 are offered.
+fn scenario() -> Scenario {
+    Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 2.0), (100.1, 2.0)])
+        .advance(10_000)
+        // Keeps the feed running after the responses.
+        .ask(100.0, 2.0)
+}
+
+fn backtest(
+    scenario: &Scenario,
+    exchange: ExchangeKind,
+) -> MultiAssetMultiExchangeBacktest<f32, HashMapMarketDepth> {
+    BtBuilder::new()
+        .add(
+            BtAssetBuilder::new()
+                .data(vec![DataSource::Array(Data::from_rows(scenario.rows()))])
+                .latency_model(ConstantLatency::new(10, 10))
+                .asset_type(LinearAsset::new(1.0))
+                .queue_model(RiskAdverseQueueModel::new())
+                .depth(|| HashMapMarketDepth::new(0.1, 1.0))
+                .exchange(exchange)
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap()
+}
+
+fn submit_buy(
+    hbt: &mut MultiAssetMultiExchangeBacktest<f32, HashMapMarketDepth>,
+    price: f32,
+    qty: f32,
+    time_in_force: TimeInForce,
+) {
+    hbt.elapse(100).unwrap();
+    hbt.submit_buy_order(0, 1, price, qty, time_in_force, OrdType::Limit, false)
+        .unwrap();
+    hbt.elapse(1_000).unwrap();
+}
+
+#[test]
+fn ioc_and_fok_expire_unless_marketable() {
+    for exchange in EXCHANGES {
+        for time_in_force in [TimeInForce::IOC, TimeInForce::FOK] {
+            let mut hbt = backtest(&scenario(), exchange);
+            submit_buy(&mut hbt, 99.9, 1.0, time_in_force);
+            let order = hbt.orders(0).get(&1).unwrap();
+            assert_eq!(
+                order.status,
+                Status::Expired,
+                "{exchange:?} {time_in_force:?}"
+            );
+            assert_eq!(order.exec_qty, 0.0);
+            assert_eq!(hbt.position(0), 0.0);
+        }
+    }
+}
+
+#[test]
+fn fok_expires_unless_fully_marketable() {
+    for exchange in EXCHANGES {
+        // Only 4 are offered up to the order's price.
+        let mut hbt = backtest(&scenario(), exchange);
+        submit_buy(&mut hbt, 100.1, 5.0, TimeInForce::FOK);
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Expired, "{exchange:?}");
+        assert_eq!(hbt.position(0), 0.0, "{exchange:?}");
+
+        let mut hbt = backtest(&scenario(), exchange);
+        submit_buy(&mut hbt, 100.1, 4.0, TimeInForce::FOK);
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Filled, "{exchange:?}");
+        assert_eq!(hbt.position(0), 4.0, "{exchange:?}");
+    }
+}
+
+#[test]
+fn partial_fill_ioc_cancels_the_rest() {
+    let mut hbt = backtest(&scenario(), ExchangeKind::PartialFillExchange);
+    submit_buy(&mut hbt, 100.1, 5.0, TimeInForce::IOC);
+    // Takes the 4 offered up to the order's price, and the rest expires instead of resting.
+    let order = hbt.orders(0).get(&1).unwrap();
+    assert_eq!(order.status, Status::Expired);
+    assert_eq!(order.leaves_qty, 1.0);
+    assert_eq!(hbt.position(0), 4.0);
+    // One trade at each level.
+    assert_eq!(hbt.state_values(0).trade_num, 2);
+}
+
+#[test]
+fn no_partial_fill_ioc_fills_entirely() {
+    let mut hbt = backtest(&scenario(), ExchangeKind::NoPartialFillExchange);
+    submit_buy(&mut hbt, 100.1, 5.0, TimeInForce::IOC);
+    // The order is filled entirely at the best ask, as this exchange doesn't fill partially.
+    let order = hbt.orders(0).get(&1).unwrap();
+    assert_eq!(order.status, Status::Filled);
+    assert_eq!(order.exec_price(), 100.0);
+    assert_eq!(hbt.position(0), 5.0);
+}
@@ -0,0 +1,127 @@
+use hftbacktest::{
+    backtest::{
+        assettype::LinearAsset,
+        backtest::MultiAssetMultiExchangeBacktest,
+        models::{ConstantLatency, RiskAdverseQueueModel},
+        reader::Data,
+        BtAssetBuilder,
+        BtBuilder,
+        DataSource,
+        ExchangeKind,
+    },
+    depth::hashmapmarketdepth::HashMapMarketDepth,
+    testing::{Scenario, TradeSide},
+    ty::{OrdType, Status, TimeInForce},
+    Interface,
+};
+
+const EXCHANGES: [ExchangeKind; 2] = [
+    ExchangeKind::NoPartialFillExchange,
+    ExchangeKind::PartialFillExchange,
+];
+
+fn backtest(
+    scenario: &Scenario,
+    exchange: ExchangeKind,
+) -> MultiAssetMultiExchangeBacktest<f32, HashMapMarketDepth> {
+    BtBuilder::new()
+        .add(
+            BtAssetBuilder::new()
+                .data(vec![DataSource::Array(Data::from_rows(scenario.rows()))])
+                .latency_model(ConstantLatency::new(10, 10))
+                .asset_type(LinearAsset::new(1.0))
+                .queue_model(RiskAdverseQueueModel::new())
+                .depth(|| HashMapMarketDepth::new(0.1, 1.0))
+                .exchange(exchange)
+                // Only one submission is allowed for the whole scenario.
+                .order_rate_limit(1, 1_000_000_000)
+                .build()
+                .unwrap(),
+        )
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn triggered_order_is_not_rate_limited() {
+    let scenario = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0), (99.0, 5.0)], &[(100.0, 5.0)])
+        .advance(1_000)
+        // The best bid falls to the trigger price.
+        .bid(99.9, 0.0)
+        .advance(1_000)
+        // Keeps the feed running after the responses.
+        .ask(100.0, 5.0);
+    for exchange in EXCHANGES {
+        let mut hbt = backtest(&scenario, exchange);
+        hbt.elapse(100).unwrap();
+        hbt.set_trigger_price(0, Some(99.5));
+        hbt.submit_sell_order(
+            0,
+            1,
+            99.0,
+            1.0,
+            TimeInForce::GTC,
+            OrdType::StopMarket,
+            false,
+        )
+        .unwrap();
+        hbt.set_trigger_price(0, None);
+        hbt.elapse(500).unwrap();
+        assert_eq!(hbt.orders(0).get(&1).unwrap().status, Status::New);
+
+        // The activation of the order isn't another submission, so it isn't rejected by the
+        // exhausted rate limit.
+        hbt.elapse(1_500).unwrap();
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Filled, "{exchange:?}");
+        assert_eq!(order.exec_price(), 99.0);
+    }
+}
+
+#[test]
+fn triggered_market_order_expires_on_empty_book() {
+    let scenario = Scenario::new(0.1, 1.0)
+        .book(&[(99.9, 5.0)], &[(100.0, 5.0)])
+        .advance(1_000)
+        // The bid side empties and then a trade at the trigger price triggers the order.
+        .bid(99.9, 0.0)
+        .advance(1_000)
+        .trade(TradeSide::Sell, 99.4, 1.0)
+        .advance(1_000)
+        // A bid reappears at the price the order was submitted with.
+        .bid(99.0, 5.0)
+        .advance(1_000)
+        .trade(TradeSide::Sell, 99.0, 10.0)
+        .advance(1_000)
+        // Keeps the feed running after the responses.
+        .ask(100.0, 5.0);
+    for exchange in EXCHANGES {
+        let mut hbt = backtest(&scenario, exchange);
+        hbt.elapse(100).unwrap();
+        hbt.set_trigger_price(0, Some(99.5));
+        hbt.submit_sell_order(
+            0,
+            1,
+            99.0,
+            1.0,
+            TimeInForce::GTC,
+            OrdType::StopMarket,
+            false,
+        )
+        .unwrap();
+        hbt.set_trigger_price(0, None);
+
+        // The market order has nothing to take, so it expires rather than resting at 99.0 to
+        // be filled later.
+        hbt.elapse(2_500).unwrap();
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Expired, "{exchange:?}");
+        assert_eq!(order.order_type, OrdType::Market);
+
+        hbt.elapse(2_000).unwrap();
+        let order = hbt.orders(0).get(&1).unwrap();
+        assert_eq!(order.status, Status::Expired, "{exchange:?}");
+        assert_eq!(order.exec_qty, 0.0);
+    }
+}